@@ -0,0 +1,316 @@
+//! Hot paths of [`DynRecord`] conversions, for narrow, wide and nullable-heavy schemas.
+//!
+//! Save a baseline before a refactor touching the runtime records and compare against it after:
+//!
+//! ```sh
+//! cargo bench --bench dyn_record -- --save-baseline before
+//! cargo bench --bench dyn_record -- --baseline before
+//! ```
+
+use std::{hint::black_box, io::Cursor};
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use mimalloc::MiMalloc;
+use tonbo::{
+    arrow::array::RecordBatch,
+    executor::tokio::TokioExecutor,
+    inmem::immutable::{ArrowArrays, Builder},
+    parquet::arrow::{ArrowSchemaConverter, ProjectionMask},
+    record::{
+        Cell, DataType, DynRecord, DynRecordImmutableArrays, DynRecordRef, DynSchema, Record,
+        RecordRef, Schema, Value, ValueDesc,
+    },
+    timestamp::{Timestamp, Ts},
+    DbOption, Decode, Encode, DB,
+};
+
+#[global_allocator]
+static GLOBAL: MiMalloc = MiMalloc;
+
+const ROWS: usize = 1024;
+
+/// user columns besides the primary key of the wide and nullable-heavy schemas
+const WIDE_COLUMNS: usize = 64;
+
+/// types the wide schemas cycle through
+const TYPES: [DataType; 5] = [
+    DataType::Int64,
+    DataType::UInt32,
+    DataType::String,
+    DataType::Boolean,
+    DataType::Bytes,
+];
+
+#[derive(Clone, Copy)]
+enum Shape {
+    /// a primary key and a string
+    Narrow,
+    /// [`WIDE_COLUMNS`] non-nullable columns
+    Wide,
+    /// [`WIDE_COLUMNS`] nullable columns, nine out of ten values are null
+    Nullable,
+}
+
+impl Shape {
+    const ALL: [Shape; 3] = [Shape::Narrow, Shape::Wide, Shape::Nullable];
+
+    fn name(self) -> &'static str {
+        match self {
+            Shape::Narrow => "narrow",
+            Shape::Wide => "wide",
+            Shape::Nullable => "nullable",
+        }
+    }
+
+    fn descs(self) -> Vec<ValueDesc> {
+        let id = ValueDesc::new("id".to_string(), DataType::Int64, false);
+        let columns = match self {
+            Shape::Narrow => vec![ValueDesc::new("value".to_string(), DataType::String, false)],
+            Shape::Wide | Shape::Nullable => (0..WIDE_COLUMNS)
+                .map(|i| {
+                    ValueDesc::new(
+                        format!("c{i}"),
+                        TYPES[i % TYPES.len()],
+                        matches!(self, Shape::Nullable),
+                    )
+                })
+                .collect(),
+        };
+        std::iter::once(id).chain(columns).collect()
+    }
+
+    fn schema(self) -> DynSchema {
+        DynSchema::new(self.descs(), 0)
+    }
+
+    fn records(self) -> Vec<DynRecord> {
+        let descs = self.descs();
+        (0..ROWS)
+            .map(|row| {
+                let values = descs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, desc)| {
+                        if i == 0 {
+                            return Value::with_cell(
+                                desc.name.clone(),
+                                Cell::from(row as i64),
+                                false,
+                            );
+                        }
+                        let is_null = desc.is_nullable && (row + i) % 10 != 0;
+                        value(desc, row, is_null)
+                    })
+                    .collect();
+                DynRecord::new(values, 0)
+            })
+            .collect()
+    }
+}
+
+/// the value of `desc` at `row`, null if `is_null`
+fn value(desc: &ValueDesc, row: usize, is_null: bool) -> Value {
+    macro_rules! wrap {
+        ($value:expr) => {
+            Cell::from((!is_null).then_some($value))
+        };
+    }
+
+    let value = match desc.datatype {
+        DataType::Int64 => wrap!(row as i64),
+        DataType::UInt32 => wrap!(row as u32),
+        DataType::String => wrap!(format!("value-{row:08}")),
+        DataType::Boolean => wrap!(row % 2 == 0),
+        DataType::Bytes => wrap!(row.to_le_bytes().to_vec()),
+        datatype => unreachable!("{datatype:?} is not used by the benches"),
+    };
+    Value::with_cell(desc.name.clone(), value, desc.is_nullable)
+}
+
+fn record_batch(schema: &DynSchema, records: &[DynRecord]) -> RecordBatch {
+    let mut builder = DynRecordImmutableArrays::builder(schema.arrow_schema().clone(), ROWS);
+    for record in records {
+        builder.push(
+            Ts {
+                ts: Timestamp::from(0),
+                value: record.key(),
+            },
+            Some(record.as_record_ref()),
+        );
+    }
+    builder.finish(None).as_record_batch().clone()
+}
+
+/// keeps the primary key and every fourth user column
+fn projection_mask(schema: &DynSchema) -> ProjectionMask {
+    let arrow_schema = schema.arrow_schema();
+    let descriptor = ArrowSchemaConverter::new()
+        .convert(arrow_schema)
+        .expect("the schema must convert to parquet");
+    let indices = (0..arrow_schema.fields().len())
+        .filter(|i| *i < 2 || *i == schema.primary_key_index() || i % 4 == 0)
+        .collect::<Vec<_>>();
+    ProjectionMask::roots(&descriptor, indices)
+}
+
+fn from_record_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dyn_record/from_record_batch");
+    group.throughput(Throughput::Elements(ROWS as u64));
+
+    for shape in Shape::ALL {
+        let schema = shape.schema();
+        let batch = record_batch(&schema, &shape.records());
+        let all = ProjectionMask::all();
+        let projected = projection_mask(&schema);
+
+        for (mask_name, mask) in [("all", &all), ("projected", &projected)] {
+            group.bench_with_input(
+                BenchmarkId::new(mask_name, shape.name()),
+                &batch,
+                |b, batch| {
+                    b.iter(|| {
+                        for offset in 0..batch.num_rows() {
+                            black_box(DynRecordRef::from_record_batch(
+                                batch,
+                                offset,
+                                mask,
+                                schema.arrow_schema(),
+                            ));
+                        }
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn projection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dyn_record/projection");
+    group.throughput(Throughput::Elements(ROWS as u64));
+
+    for shape in Shape::ALL {
+        let schema = shape.schema();
+        let records = shape.records();
+        let mask = projection_mask(&schema);
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(shape.name()),
+            &records,
+            |b, records| {
+                b.iter_batched(
+                    || {
+                        records
+                            .iter()
+                            .map(Record::as_record_ref)
+                            .collect::<Vec<_>>()
+                    },
+                    |mut record_refs| {
+                        for record_ref in record_refs.iter_mut() {
+                            record_ref.projection(&mask);
+                        }
+                        record_refs
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn wal_codec(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("dyn_record/wal");
+    group.throughput(Throughput::Elements(ROWS as u64));
+
+    for shape in Shape::ALL {
+        let records = shape.records();
+        let mut encoded = Vec::new();
+        runtime.block_on(async {
+            let mut cursor = Cursor::new(&mut encoded);
+            for record in records.iter() {
+                record.as_record_ref().encode(&mut cursor).await.unwrap();
+            }
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("encode", shape.name()),
+            &records,
+            |b, records| {
+                b.to_async(&runtime).iter_batched(
+                    || Vec::with_capacity(encoded.len()),
+                    |mut bytes| async move {
+                        let mut cursor = Cursor::new(&mut bytes);
+                        for record in records.iter() {
+                            record.as_record_ref().encode(&mut cursor).await.unwrap();
+                        }
+                        bytes
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("decode", shape.name()),
+            &encoded,
+            |b, encoded| {
+                b.to_async(&runtime).iter_batched(
+                    || encoded.clone(),
+                    |mut bytes| async move {
+                        let mut cursor = Cursor::new(&mut bytes);
+                        for _ in 0..ROWS {
+                            black_box(DynRecord::decode(&mut cursor).await.unwrap());
+                        }
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn memtable_insert(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("dyn_record/memtable_insert");
+    group.throughput(Throughput::Elements(ROWS as u64));
+
+    for shape in Shape::ALL {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let schema = shape.schema();
+        // the WAL is disabled to only measure the conversions into the memtable
+        let option = DbOption::new(
+            fusio::path::Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &schema,
+        )
+        .disable_wal();
+        let db: DB<DynRecord, TokioExecutor> = runtime
+            .block_on(DB::new(option, TokioExecutor::current(), schema))
+            .unwrap();
+        let db = &db;
+
+        group.bench_function(BenchmarkId::from_parameter(shape.name()), |b| {
+            b.to_async(&runtime).iter_batched(
+                || shape.records(),
+                |records| async move { db.insert_batch(records.into_iter()).await.unwrap() },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    from_record_batch,
+    projection,
+    wal_codec,
+    memtable_insert
+);
+criterion_main!(benches);
@@ -1,5 +1,5 @@
 import { userSchema } from "./schema";
-import init, { TonboDB, DbOption } from "./pkg/tonbo_js";
+import init, { TonboDB, DbOption, Bound } from "./pkg/tonbo_js";
 
 async function main() {
   // Initialize the WASM module
@@ -26,6 +26,12 @@ async function main() {
     const name = await db.get(1, (val) => val.name);
     console.log("read committed name: ", name);
   });
+
+  // scan the records as they are read
+  const iter = await db.scanIter(Bound.included(0), Bound.unbounded());
+  for await (const record of { [Symbol.asyncIterator]: () => iter }) {
+    console.log(record);
+  }
 }
 
 main().catch(console.error);
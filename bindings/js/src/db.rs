@@ -12,12 +12,13 @@ use wasm_bindgen::prelude::*;
 use crate::{
     datatype::to_datatype,
     options::DbOption,
+    scan::ScanIterator,
     transaction::Transaction,
     utils::{parse_key, parse_record, to_record},
     Bound,
 };
 
-type JsExecutor = OpfsExecutor;
+pub(crate) type JsExecutor = OpfsExecutor;
 
 #[wasm_bindgen]
 pub struct TonboDB {
@@ -75,20 +76,24 @@ impl TonboDB {
 
 #[wasm_bindgen]
 impl TonboDB {
+    /// open the DB, fails if a path of `option` is on OPFS and the browser lacks it
     #[wasm_bindgen(constructor)]
-    pub async fn new(option: DbOption, schema: Object) -> Self {
+    pub async fn new(option: DbOption, schema: Object) -> Result<TonboDB, JsValue> {
+        if option.uses_opfs() && !opfs_available() {
+            return Err("OPFS is not available in this browser".into());
+        }
         let (desc, primary_key_index) = Self::parse_schema(schema);
         let schema = DynSchema::new(desc.clone(), primary_key_index);
 
         let db = DB::new(option.into_option(&schema), JsExecutor::new(), schema)
             .await
-            .unwrap();
+            .map_err(|err| JsValue::from(err.to_string()))?;
 
-        Self {
+        Ok(Self {
             desc: Arc::new(desc),
             primary_key_index,
             db: Arc::new(db),
-        }
+        })
     }
 
     /// get the record with `key` as the primary key and process it using closure `cb`
@@ -182,6 +187,16 @@ impl TonboDB {
         Ok(wasm_streams::ReadableStream::from_stream(stream).into_raw())
     }
 
+    /// scan the records with primary keys between `lower` and `high` as an async iterator, see
+    /// [`ScanIterator`]
+    #[wasm_bindgen(js_name = "scanIter")]
+    pub async fn scan_iter(&self, lower: Bound, high: Bound) -> Result<ScanIterator, JsValue> {
+        let desc = self.desc.get(self.primary_key_index).unwrap();
+        let bounds = (lower.into_bound(desc)?, high.into_bound(desc)?);
+
+        Ok(ScanIterator::new(self.db.clone(), bounds).await)
+    }
+
     /// open an optimistic ACID transaction
     pub async fn transaction(&self, cb: Function) -> Result<(), JsValue> {
         let txn = self.db.transaction().await;
@@ -216,14 +231,22 @@ impl TonboDB {
     }
 }
 
+/// whether the browser provides the OPFS, `navigator.storage.getDirectory`
+fn opfs_available() -> bool {
+    Reflect::get(&js_sys::global(), &"navigator".into())
+        .and_then(|navigator| Reflect::get(&navigator, &"storage".into()))
+        .and_then(|storage| Reflect::get(&storage, &"getDirectory".into()))
+        .is_ok_and(|get_directory| get_directory.is_function())
+}
+
 #[cfg(test)]
 mod tests {
     use fusio::{path::Path, DynFs};
-    use js_sys::Object;
+    use js_sys::{Object, Reflect};
     use wasm_bindgen::JsValue;
     use wasm_bindgen_test::*;
 
-    use crate::{options::DbOption, AwsCredential, S3Builder, TonboDB};
+    use crate::{options::DbOption, AwsCredential, Bound, S3Builder, TonboDB};
 
     wasm_bindgen_test_configure!(run_in_browser);
 
@@ -297,7 +320,8 @@ mod tests {
                 &JsValue::from_str(i.to_string().as_str()),
             )
             .unwrap();
-            js_sys::Reflect::set(&item, &JsValue::from_str("price"), &JsValue::from(i as f64)).unwrap();
+            js_sys::Reflect::set(&item, &JsValue::from_str("price"), &JsValue::from(i as f64))
+                .unwrap();
 
             items.push(item);
         }
@@ -310,7 +334,7 @@ mod tests {
         let option = DbOption::new("open".to_string()).expect("cannot open DB");
 
         let schema = schema();
-        let db = TonboDB::new(option, schema).await;
+        let db = TonboDB::new(option, schema).await.unwrap();
 
         db.flush_wal().await.unwrap();
         drop(db);
@@ -322,7 +346,7 @@ mod tests {
         let option = DbOption::new("write".to_string()).expect("cannot open DB");
 
         let schema = schema();
-        let db = TonboDB::new(option, schema).await;
+        let db = TonboDB::new(option, schema).await.unwrap();
 
         for item in test_items() {
             db.insert(item).await.unwrap();
@@ -333,6 +357,50 @@ mod tests {
         remove("write").await;
     }
 
+    #[wasm_bindgen_test]
+    pub async fn test_scan_iter() {
+        let option = DbOption::new("scan_iter".to_string()).expect("cannot open DB");
+
+        let schema = schema();
+        let db = TonboDB::new(option, schema).await.unwrap();
+
+        for item in test_items() {
+            db.insert(item).await.unwrap();
+        }
+        db.flush().await.unwrap();
+
+        let mut iter = db
+            .scan_iter(
+                Bound::included(JsValue::from(10)),
+                Bound::excluded(JsValue::from(20)),
+            )
+            .await
+            .unwrap();
+        let mut ids = vec![];
+        loop {
+            let next = iter.next().await.unwrap();
+            if Reflect::get(&next, &"done".into())
+                .unwrap()
+                .as_bool()
+                .unwrap()
+            {
+                break;
+            }
+            let record = Reflect::get(&next, &"value".into()).unwrap();
+            ids.push(
+                Reflect::get(&record, &"id".into())
+                    .unwrap()
+                    .as_f64()
+                    .unwrap() as u8,
+            );
+        }
+        assert_eq!(ids, (10..20).collect::<Vec<u8>>());
+
+        drop(iter);
+        drop(db);
+        remove("scan_iter").await;
+    }
+
     #[ignore]
     #[wasm_bindgen_test]
     pub async fn test_write_s3() {
@@ -357,7 +425,7 @@ mod tests {
             .unwrap();
 
         let schema = schema();
-        let db = TonboDB::new(option, schema).await;
+        let db = TonboDB::new(option, schema).await.unwrap();
 
         for (i, item) in test_items().into_iter().enumerate() {
             if i % 5 == 0 {
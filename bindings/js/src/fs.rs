@@ -41,6 +41,10 @@ pub struct FsOptions {
 }
 
 impl FsOptions {
+    pub(crate) fn is_local(&self) -> bool {
+        matches!(self.inner, FsOptionsInner::Local)
+    }
+
     pub(crate) fn path(&self, path: String) -> Result<Path, JsValue> {
         match self.inner {
             FsOptionsInner::Local => {
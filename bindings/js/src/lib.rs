@@ -7,5 +7,7 @@ pub use db::*;
 pub use transaction::*;
 pub mod range;
 pub use range::*;
+pub mod scan;
+pub use scan::*;
 pub mod fs;
 pub use fs::*;
@@ -64,6 +64,16 @@ impl DbOption {
 }
 
 impl DbOption {
+    /// whether the base path or a level path is on the OPFS
+    pub(crate) fn uses_opfs(&self) -> bool {
+        self.base_fs.is_local()
+            || self
+                .level_paths
+                .iter()
+                .flatten()
+                .any(|(_, fs_options)| fs_options.is_local())
+    }
+
     pub(crate) fn into_option<S: Schema>(self, schema: &S) -> tonbo::DbOption {
         let mut opt = tonbo::DbOption::new(Path::from(self.path), schema)
             .clean_channel_buffer(self.clean_channel_buffer)
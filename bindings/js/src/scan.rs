@@ -0,0 +1,87 @@
+use std::{mem::transmute, ops::Bound, pin::Pin, sync::Arc};
+
+use futures::{Stream, StreamExt};
+use js_sys::{Object, Reflect};
+use tonbo::{record::DynRecord, record::Value, DB};
+use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
+
+use crate::{db::JsExecutor, utils::to_record};
+
+type RecordStream = Pin<Box<dyn Stream<Item = Result<JsValue, JsValue>>>>;
+
+/// An async iterator over the records of a scan, see `TonboDB.scanIter`.
+///
+/// `next()` follows the async iterator protocol, so the iterator is consumed with
+/// `for await (const record of { [Symbol.asyncIterator]: () => iter })`.
+#[wasm_bindgen]
+pub struct ScanIterator {
+    // declared first to be dropped before the DB and the bounds it borrows
+    stream: Option<RecordStream>,
+    _bounds: Box<(Bound<Value>, Bound<Value>)>,
+    _db: Arc<DB<DynRecord, JsExecutor>>,
+}
+
+impl ScanIterator {
+    pub(crate) async fn new(
+        db: Arc<DB<DynRecord, JsExecutor>>,
+        bounds: (Bound<Value>, Bound<Value>),
+    ) -> Self {
+        let bounds = Box::new(bounds);
+        // Safety: the DB and the bounds are kept alive on the heap until the stream is dropped
+        let (static_db, range) = unsafe {
+            (
+                transmute::<&DB<DynRecord, JsExecutor>, &'static DB<DynRecord, JsExecutor>>(&db),
+                transmute::<
+                    (Bound<&Value>, Bound<&Value>),
+                    (Bound<&'static Value>, Bound<&'static Value>),
+                >((bounds.0.as_ref(), bounds.1.as_ref())),
+            )
+        };
+        let stream = static_db
+            .scan(range, |entry| {
+                let record = entry.get();
+                to_record(&record.columns, record.primary_index)
+            })
+            .await
+            .map(|result| result.map_err(|err| JsValue::from(err.to_string())));
+
+        Self {
+            stream: Some(Box::pin(stream)),
+            _bounds: bounds,
+            _db: db,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl ScanIterator {
+    /// resolves to `{ value, done: false }` with the next record, or `{ done: true }` once the
+    /// scan ends
+    pub async fn next(&mut self) -> Result<JsValue, JsValue> {
+        let result = Object::new();
+        let next = match self.stream.as_mut() {
+            Some(stream) => stream.next().await.transpose()?,
+            None => None,
+        };
+        match next {
+            Some(record) => {
+                Reflect::set(&result, &"value".into(), &record)?;
+                Reflect::set(&result, &"done".into(), &JsValue::FALSE)?;
+            }
+            None => {
+                self.stream = None;
+                Reflect::set(&result, &"done".into(), &JsValue::TRUE)?;
+            }
+        }
+        Ok(result.into())
+    }
+
+    /// stop the scan early, releasing what it holds
+    #[wasm_bindgen(js_name = "return")]
+    pub fn finish(&mut self) -> JsValue {
+        self.stream = None;
+        let result = Object::new();
+        let _ = Reflect::set(&result, &"done".into(), &JsValue::TRUE);
+        result.into()
+    }
+}
@@ -1,4 +1,6 @@
+from datetime import timedelta
 from typing import Any, AsyncIterable, final
+import pyarrow
 from enum import Enum, auto
 from tonbo import error as error
 from tonbo.fs import FsOptions
@@ -64,9 +66,22 @@ class DbOption:
     major_default_oldest_table_num: int
     major_threshold_with_sst_size: int
     max_sst_file_size: int
+    max_subcompactions: int
+    max_row_group_size: int | None
+    data_page_size: int | None
     version_log_snapshot_threshold: int
     use_wal: bool
     wal_buffer_size: int
+    memtable_shards: int
+    flush_max_rows: int | None
+    flush_max_wal_bytes: int | None
+    flush_max_age: timedelta | None
+    block_cache: int | None
+    metadata_cache: int | None
+    max_open_tables: int | None
+    scan_parallelism: int
+    transaction_max_rows: int | None
+    transaction_max_bytes: int | None
     path: str
 
     def __init__(self, path: str) -> None:
@@ -122,6 +137,24 @@ class Transaction:
             projection: fields to projection
         """
         ...
+    async def scan_arrow(
+        self,
+        lower: Bound | None,
+        high: Bound | None,
+        limit: int | None = None,
+        projection: list[str] = ["*"],
+        batch_size: int = 1024,
+    ) -> pyarrow.RecordBatchReader:
+        """Scan into a :py:class:`pyarrow.RecordBatchReader`, uncommitted changes included.
+
+        Args:
+            lower: Lower bound of range. Use None represent unbounded.
+            high: High bound of range. Use None represent unbounded.
+            limit: max number records to scan
+            projection: fields to projection, the primary key is always read
+            batch_size: max number of rows in a batch
+        """
+        ...
     async def commit(self) -> None:
         """Commit :py:class:`Transaction`."""
         ...
@@ -159,6 +192,25 @@ class TonboDB:
     async def transaction(self) -> Transaction:
         """Create a new :py:class:`Transaction`."""
         ...
+    async def scan_arrow(
+        self,
+        lower: Bound | None,
+        high: Bound | None,
+        limit: int | None = None,
+        projection: list[str] = ["*"],
+        batch_size: int = 1024,
+    ) -> pyarrow.RecordBatchReader:
+        """Scan a snapshot into a :py:class:`pyarrow.RecordBatchReader`. The snapshot holds
+        back the flushes of the memtables until the reader is closed.
+
+        Args:
+            lower: Lower bound of range. Use None represent unbounded.
+            high: High bound of range. Use None represent unbounded.
+            limit: max number records to scan
+            projection: fields to projection, the primary key is always read
+            batch_size: max number of rows in a batch
+        """
+        ...
     async def flush(self) -> None:
         """Try to execute compaction."""
         ...
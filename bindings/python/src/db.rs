@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{mem::transmute, sync::Arc};
 
 use pyo3::{
     prelude::*,
@@ -8,8 +8,11 @@ use pyo3::{
 };
 use pyo3_async_runtimes::tokio::{future_into_py, get_runtime};
 use tonbo::{
+    arrow::datatypes::SchemaRef,
     executor::tokio::TokioExecutor,
-    record::{DynRecord, DynSchema, Value, ValueDesc},
+    magic,
+    record::{DynRecord, DynSchema, Schema, Value, ValueDesc},
+    snapshot::Snapshot,
     DB,
 };
 
@@ -17,16 +20,23 @@ use crate::{
     column::Column,
     error::{CommitError, DbError},
     options::DbOption,
+    range,
     record_batch::RecordBatch,
+    stream::{scan_batches, ArrowReader},
     transaction::Transaction,
-    utils::{to_col, to_dict},
+    utils::{to_bound, to_col, to_dict, to_projection, to_projection_schema},
 };
 
 type PyExecutor = TokioExecutor;
 
+/// rows of the batches read by `scan_arrow` by default
+pub(crate) const DEFAULT_BATCH_SIZE: usize = 1024;
+
 #[pyclass]
 pub struct TonboDB {
     desc: Arc<Vec<Column>>,
+    /// arrow schema of the user columns, without `_null` and `_ts`
+    arrow_schema: SchemaRef,
     primary_key_index: usize,
     db: Arc<DB<DynRecord, PyExecutor>>,
 }
@@ -56,6 +66,15 @@ impl TonboDB {
             }
         }
         let schema = DynSchema::new(desc, primary_key_index.unwrap());
+        let arrow_schema = schema.arrow_schema();
+        let user_columns = arrow_schema
+            .fields()
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| ![magic::NULL, magic::TS].contains(&field.name().as_str()))
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        let arrow_schema = Arc::new(arrow_schema.project(&user_columns).unwrap());
         let option = option.into_option(&schema);
         let db = get_runtime()
             .block_on(async { DB::new(option, TokioExecutor::current(), schema).await })
@@ -63,6 +82,7 @@ impl TonboDB {
         Ok(Self {
             db: Arc::new(db),
             desc: Arc::new(cols),
+            arrow_schema,
             primary_key_index: primary_key_index.expect("Primary key not found"),
         })
     }
@@ -143,9 +163,65 @@ impl TonboDB {
     fn transaction<'py>(&'py self, py: Python<'py>) -> PyResult<Bound<PyAny>> {
         let db = self.db.clone();
         let desc = self.desc.clone();
+        let arrow_schema = self.arrow_schema.clone();
         future_into_py(py, async move {
             let txn = db.transaction().await;
-            Ok(Transaction::new(txn, desc.clone()))
+            Ok(Transaction::new(txn, desc.clone(), arrow_schema))
+        })
+    }
+
+    /// Scan the records between `lower` and `high` of a snapshot into a
+    /// `pyarrow.RecordBatchReader`. The snapshot holds back the flushes of the memtables until
+    /// the reader is closed.
+    ///
+    /// * `lower`: - Lower bound of range. Use None represent unbounded.
+    /// * `high`: - High bound of range. Use None represent unbounded.
+    /// * `limit`: - Max number records to scan.
+    /// * `projection`: - Fields to projection in the record. Projection all by default.
+    /// * `batch_size`: - Max number of rows in a batch.
+    #[pyo3(signature= (lower, high, limit=None, projection=vec!["*".to_string()], batch_size=DEFAULT_BATCH_SIZE))]
+    fn scan_arrow<'py>(
+        &'py self,
+        py: Python<'py>,
+        lower: Option<Py<range::Bound>>,
+        high: Option<Py<range::Bound>>,
+        limit: Option<usize>,
+        projection: Vec<String>,
+        batch_size: usize,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let col_desc = self.desc.get(self.primary_key_index).unwrap();
+        let projection = to_projection(&self.desc, &projection);
+        let schema = to_projection_schema(&self.arrow_schema, self.primary_key_index, &projection);
+        let bounds = Box::new(to_bound(py, col_desc, lower, high));
+        let db = self.db.clone();
+
+        future_into_py(py, async move {
+            // the DB, the snapshot and the bounds live in the reader until the stream borrowing
+            // them is dropped
+            let snapshot = Box::new(unsafe {
+                transmute::<Snapshot<'_, DynRecord>, Snapshot<'static, DynRecord>>(
+                    db.snapshot().await,
+                )
+            });
+            let (snapshot_ref, range) = unsafe {
+                (
+                    transmute::<&Snapshot<'static, DynRecord>, &'static Snapshot<'static, DynRecord>>(
+                        &snapshot,
+                    ),
+                    transmute::<
+                        (std::ops::Bound<&Value>, std::ops::Bound<&Value>),
+                        (
+                            std::ops::Bound<&'static Value>,
+                            std::ops::Bound<&'static Value>,
+                        ),
+                    >((bounds.0.as_ref(), bounds.1.as_ref())),
+                )
+            };
+            let stream =
+                scan_batches(snapshot_ref.scan(range), limit, projection, batch_size).await?;
+            let reader = ArrowReader::new(schema, stream, Box::new((snapshot, bounds, db)));
+
+            Python::with_gil(|py| reader.into_pyarrow(py))
         })
     }
 
@@ -1,6 +1,8 @@
 use pyo3::{
     create_exception,
-    exceptions::{PyException, PyIOError, PyValueError},
+    exceptions::{
+        PyBlockingIOError, PyException, PyIOError, PyPermissionError, PyTimeoutError, PyValueError,
+    },
     pyclass, PyErr,
 };
 use tonbo::record::DynRecord;
@@ -52,6 +54,23 @@ impl From<DbError> for PyErr {
             tonbo::DbError::WalWrite(err) => PyIOError::new_err(err.to_string()),
             tonbo::DbError::ExceedsMaxLevel => ExceedsMaxLevelError::new_err("Exceeds max level"),
             tonbo::DbError::Logger(err) => PyIOError::new_err(err.to_string()),
+            tonbo::DbError::Lease(err) => PyIOError::new_err(err.to_string()),
+            err @ (tonbo::DbError::OutOfRetention(_)
+            | tonbo::DbError::UnsortedBulkLoad
+            | tonbo::DbError::BulkLoadOverlap
+            | tonbo::DbError::MissingMergeOperator
+            | tonbo::DbError::InvalidUpdate(_)
+            | tonbo::DbError::InvalidIngest(_)
+            | tonbo::DbError::InvalidExport(_)
+            | tonbo::DbError::InvalidImport(_)
+            | tonbo::DbError::InvalidTable(_)
+            | tonbo::DbError::SnapshotExists(_)
+            | tonbo::DbError::StaleTimestamp(_)) => PyValueError::new_err(err.to_string()),
+            err @ tonbo::DbError::DeadlineExceeded => PyTimeoutError::new_err(err.to_string()),
+            err @ tonbo::DbError::Busy => PyBlockingIOError::new_err(err.to_string()),
+            err @ (tonbo::DbError::ReadOnly | tonbo::DbError::AlreadyOwned(_)) => {
+                PyPermissionError::new_err(err.to_string())
+            }
         }
     }
 }
@@ -65,10 +84,16 @@ impl From<CommitError> for PyErr {
             tonbo::transaction::CommitError::WriteConflict(key) => {
                 WriteConflictError::new_err(key.name())
             }
+            err @ tonbo::transaction::CommitError::ReadConflict(_) => {
+                WriteConflictError::new_err(err.to_string())
+            }
             tonbo::transaction::CommitError::SendCompactTaskError(err) => {
                 InnerError::new_err(err.to_string())
             }
             tonbo::transaction::CommitError::ChannelClose => InnerError::new_err("channel close"),
+            err @ tonbo::transaction::CommitError::LimitExceeded(_) => {
+                PyValueError::new_err(err.to_string())
+            }
         }
     }
 }
@@ -1,5 +1,10 @@
+use std::time::Duration;
+
 use pyo3::{pyclass, pymethods, PyResult};
-use tonbo::{option::Path, record::Schema};
+use tonbo::{
+    option::{FlushPolicy, Path},
+    record::Schema,
+};
 
 use crate::{ExceedsMaxLevelError, FsOptions};
 
@@ -26,6 +31,15 @@ pub struct DbOption {
     /// Maximum size of each parquet
     #[pyo3(get, set)]
     max_sst_file_size: usize,
+    /// Maximum number of sub-ranges of a major compaction merged concurrently
+    #[pyo3(get, set)]
+    max_subcompactions: usize,
+    /// Maximum number of rows in a row group of the parquet, 1024 * 1024 if None
+    #[pyo3(get, set)]
+    max_row_group_size: Option<usize>,
+    /// Maximum size in bytes of a data page of the parquet, 1 MiB if None
+    #[pyo3(get, set)]
+    data_page_size: Option<usize>,
     #[pyo3(get, set)]
     version_log_snapshot_threshold: u32,
     #[pyo3(get, set)]
@@ -33,6 +47,36 @@ pub struct DbOption {
     /// Maximum size of WAL buffer size
     #[pyo3(get, set)]
     wal_buffer_size: usize,
+    /// number of shards of the mutable memtable, written concurrently
+    #[pyo3(get, set)]
+    memtable_shards: usize,
+    /// the memtable is frozen once this number of records is written, unlimited if None
+    #[pyo3(get, set)]
+    flush_max_rows: Option<usize>,
+    /// the memtable is frozen once this number of bytes is logged to the WAL, unlimited if None
+    #[pyo3(get, set)]
+    flush_max_wal_bytes: Option<usize>,
+    /// the memtable is frozen once this time passed since its first write, unlimited if None
+    #[pyo3(get, set)]
+    flush_max_age: Option<Duration>,
+    /// capacity in bytes of the in-memory cache of the parquet blocks, disabled if None
+    #[pyo3(get, set)]
+    block_cache: Option<u64>,
+    /// capacity in bytes of the in-memory cache of the parquet footers, disabled if None
+    #[pyo3(get, set)]
+    metadata_cache: Option<u64>,
+    /// Maximum number of parquet kept open between reads, none are kept if None
+    #[pyo3(get, set)]
+    max_open_tables: Option<usize>,
+    /// number of parquet of a level read at once by a scan
+    #[pyo3(get, set)]
+    scan_parallelism: usize,
+    /// Maximum number of keys written by a transaction, unlimited if None
+    #[pyo3(get, set)]
+    transaction_max_rows: Option<usize>,
+    /// Maximum size in bytes of the records written by a transaction, unlimited if None
+    #[pyo3(get, set)]
+    transaction_max_bytes: Option<usize>,
     /// build the `DB` storage directory based on the passed path
     #[pyo3(get, set)]
     path: String,
@@ -52,9 +96,22 @@ impl DbOption {
             major_default_oldest_table_num: 3,
             major_threshold_with_sst_size: 4,
             max_sst_file_size: 256 * 1024 * 1024,
+            max_subcompactions: 1,
+            max_row_group_size: None,
+            data_page_size: None,
             version_log_snapshot_threshold: 200,
             use_wal: true,
             wal_buffer_size: 4 * 1024,
+            memtable_shards: 1,
+            flush_max_rows: None,
+            flush_max_wal_bytes: None,
+            flush_max_age: None,
+            block_cache: None,
+            metadata_cache: None,
+            max_open_tables: None,
+            scan_parallelism: 1,
+            transaction_max_rows: None,
+            transaction_max_bytes: None,
             path,
             base_fs: FsOptions::Local {},
             level_paths: vec![None; MAX_LEVEL],
@@ -79,8 +136,44 @@ impl DbOption {
             .major_default_oldest_table_num(self.major_default_oldest_table_num)
             .major_threshold_with_sst_size(self.major_threshold_with_sst_size)
             .max_sst_file_size(self.max_sst_file_size)
+            .max_subcompactions(self.max_subcompactions)
             .version_log_snapshot_threshold(self.version_log_snapshot_threshold)
+            .wal_buffer_size(self.wal_buffer_size)
+            .memtable_shards(self.memtable_shards)
+            .scan_parallelism(self.scan_parallelism)
             .base_fs(tonbo::option::FsOptions::from(self.base_fs));
+        if self.flush_max_rows.is_some()
+            || self.flush_max_wal_bytes.is_some()
+            || self.flush_max_age.is_some()
+        {
+            opt = opt.flush_policy(FlushPolicy {
+                max_rows: self.flush_max_rows,
+                max_wal_bytes: self.flush_max_wal_bytes,
+                max_age: self.flush_max_age,
+                ..Default::default()
+            });
+        }
+        if let Some(max_row_group_size) = self.max_row_group_size {
+            opt = opt.max_row_group_size(max_row_group_size);
+        }
+        if let Some(data_page_size) = self.data_page_size {
+            opt = opt.data_page_size(data_page_size);
+        }
+        if let Some(capacity) = self.block_cache {
+            opt = opt.block_cache(capacity);
+        }
+        if let Some(capacity) = self.metadata_cache {
+            opt = opt.metadata_cache(capacity);
+        }
+        if let Some(max_open_tables) = self.max_open_tables {
+            opt = opt.max_open_tables(max_open_tables);
+        }
+        if let Some(transaction_max_rows) = self.transaction_max_rows {
+            opt = opt.transaction_max_rows(transaction_max_rows);
+        }
+        if let Some(transaction_max_bytes) = self.transaction_max_bytes {
+            opt = opt.transaction_max_bytes(transaction_max_bytes);
+        }
         for (level, path) in self.level_paths.into_iter().enumerate() {
             if let Some((path, fs_options)) = path {
                 opt = opt
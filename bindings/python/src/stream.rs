@@ -1,15 +1,21 @@
-use std::{pin::Pin, sync::Arc};
+use std::{any::Any, pin::Pin, sync::Arc};
 
-use futures::{Stream, TryStreamExt};
+use arrow::{
+    array::{RecordBatch, RecordBatchReader},
+    datatypes::SchemaRef,
+    error::ArrowError,
+    ffi_stream::FFI_ArrowArrayStream,
+};
+use futures::{Stream, StreamExt, TryStreamExt};
 use pyo3::{
     exceptions::PyStopAsyncIteration, prelude::*, pyclass, pymethods, IntoPyObjectExt, PyRef,
     PyRefMut, PyResult, Python,
 };
-use pyo3_async_runtimes::tokio::future_into_py;
+use pyo3_async_runtimes::tokio::{future_into_py, get_runtime};
 use tokio::sync::Mutex;
-use tonbo::{parquet::errors::ParquetError, record::DynRecord, stream};
+use tonbo::{parquet::errors::ParquetError, record::DynRecord, stream, Scan};
 
-use crate::utils::to_dict;
+use crate::{error::DbError, utils::to_dict};
 
 type AsyncStream =
     Pin<Box<dyn Stream<Item = Result<stream::Entry<'static, DynRecord>, ParquetError>> + Send>>;
@@ -52,3 +58,95 @@ impl ScanStream {
         Ok(Some(fut.into()))
     }
 }
+
+type BatchStream = Pin<Box<dyn Stream<Item = Result<RecordBatch, ParquetError>> + Send>>;
+
+/// Build the batches of at most `batch_size` rows of `scan`
+pub(crate) async fn scan_batches(
+    mut scan: Scan<'static, 'static, DynRecord>,
+    limit: Option<usize>,
+    projection: Vec<usize>,
+    batch_size: usize,
+) -> PyResult<impl Stream<Item = Result<RecordBatch, ParquetError>> + Send + 'static> {
+    if let Some(limit) = limit {
+        scan = scan.limit(limit);
+    }
+    Ok(scan
+        .projection_with_index(projection)
+        .scan_batches(batch_size)
+        .await
+        .map_err(DbError::from)?)
+}
+
+/// The batches of a scan, read by a `pyarrow.RecordBatchReader` through the Arrow C stream
+/// interface. Every batch blocks on the runtime of the bindings until it is read.
+pub(crate) struct ArrowReader {
+    schema: SchemaRef,
+    // declared first to be dropped before what it borrows
+    stream: BatchStream,
+    _owner: Box<dyn Any + Send>,
+}
+
+impl ArrowReader {
+    /// `owner` keeps what `stream` borrows alive
+    pub(crate) fn new(
+        schema: SchemaRef,
+        stream: impl Stream<Item = Result<RecordBatch, ParquetError>> + Send + 'static,
+        owner: Box<dyn Any + Send>,
+    ) -> Self {
+        Self {
+            schema,
+            stream: Box::pin(stream),
+            _owner: owner,
+        }
+    }
+
+    /// Export the reader as a `pyarrow.RecordBatchReader`
+    pub(crate) fn into_pyarrow(self, py: Python<'_>) -> PyResult<PyObject> {
+        let mut stream = FFI_ArrowArrayStream::new(Box::new(self));
+        // pyarrow moves the stream out, leaving a released one to be dropped
+        let reader = py
+            .import("pyarrow")?
+            .getattr("RecordBatchReader")?
+            .call_method1(
+                "_import_from_c",
+                (&mut stream as *mut FFI_ArrowArrayStream as usize,),
+            )?;
+        Ok(reader.unbind())
+    }
+}
+
+impl Iterator for ArrowReader {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = get_runtime().block_on(self.stream.next())?;
+        Some(
+            batch
+                .map_err(|err| ArrowError::ExternalError(Box::new(err)))
+                .and_then(|batch| {
+                    // leave out `_null` and `_ts`, in the order of the schema
+                    let columns = self
+                        .schema
+                        .fields()
+                        .iter()
+                        .map(|field| {
+                            batch.column_by_name(field.name()).cloned().ok_or_else(|| {
+                                ArrowError::SchemaError(format!(
+                                    "column {} not found in batch",
+                                    field.name()
+                                ))
+                            })
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    RecordBatch::try_new(self.schema.clone(), columns)
+                }),
+        )
+    }
+}
+
+impl RecordBatchReader for ArrowReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
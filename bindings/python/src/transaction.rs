@@ -7,22 +7,25 @@ use pyo3::{
 };
 use pyo3_async_runtimes::tokio::future_into_py;
 use tonbo::{
+    arrow::datatypes::SchemaRef,
     record::{DynRecord, Value},
     transaction, Projection,
 };
 
 use crate::{
     column::Column,
+    db::DEFAULT_BATCH_SIZE,
     error::{repeated_commit_err, CommitError, DbError},
     range,
-    stream::ScanStream,
-    utils::{to_bound, to_col, to_dict},
+    stream::{scan_batches, ArrowReader, ScanStream},
+    utils::{to_bound, to_col, to_dict, to_projection, to_projection_schema},
 };
 
 #[pyclass]
 pub struct Transaction {
     txn: Option<transaction::Transaction<'static, DynRecord>>,
     desc: Arc<Vec<Column>>,
+    arrow_schema: SchemaRef,
     primary_key_index: usize,
 }
 
@@ -30,6 +33,7 @@ impl Transaction {
     pub(crate) fn new<'txn>(
         txn: transaction::Transaction<'txn, DynRecord>,
         desc: Arc<Vec<Column>>,
+        arrow_schema: SchemaRef,
     ) -> Self {
         let primary_key_index = desc
             .iter()
@@ -45,21 +49,13 @@ impl Transaction {
                 >(txn)
             }),
             desc,
+            arrow_schema,
             primary_key_index,
         }
     }
 
     fn projection(&self, projection: Vec<String>) -> Vec<usize> {
-        match projection.contains(&"*".to_string()) {
-            true => (0..self.desc.len()).collect(),
-            false => self
-                .desc
-                .iter()
-                .enumerate()
-                .filter(|(_idx, col)| projection.contains(&col.name))
-                .map(|(idx, _col)| idx)
-                .collect(),
-        }
+        to_projection(&self.desc, &projection)
     }
 }
 
@@ -214,6 +210,54 @@ impl Transaction {
         })
     }
 
+    /// Scan the records between `lower` and `high` into a `pyarrow.RecordBatchReader`, see
+    /// `scan`. The uncommitted changes of the transaction are read as well.
+    ///
+    /// * `batch_size`: - Max number of rows in a batch.
+    #[pyo3(signature= (lower, high, limit=None, projection=vec!["*".to_string()], batch_size=DEFAULT_BATCH_SIZE))]
+    fn scan_arrow<'py>(
+        &'py mut self,
+        py: Python<'py>,
+        lower: Option<Py<range::Bound>>,
+        high: Option<Py<range::Bound>>,
+        limit: Option<usize>,
+        projection: Vec<String>,
+        batch_size: usize,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        if self.txn.is_none() {
+            return Err(repeated_commit_err());
+        }
+        let txn = self.txn.as_ref().unwrap();
+        let txn = unsafe {
+            transmute::<
+                &transaction::Transaction<'_, DynRecord>,
+                &'static transaction::Transaction<'_, DynRecord>,
+            >(txn)
+        };
+        let col_desc = self.desc.get(self.primary_key_index).unwrap();
+        let projection = self.projection(projection);
+        let schema = to_projection_schema(&self.arrow_schema, self.primary_key_index, &projection);
+
+        let bounds = Box::new(to_bound(py, col_desc, lower, high));
+
+        future_into_py(py, async move {
+            // the bounds live in the reader until the stream borrowing them is dropped
+            let range = unsafe {
+                transmute::<
+                    (std::ops::Bound<&Value>, std::ops::Bound<&Value>),
+                    (
+                        std::ops::Bound<&'static Value>,
+                        std::ops::Bound<&'static Value>,
+                    ),
+                >((bounds.0.as_ref(), bounds.1.as_ref()))
+            };
+            let stream = scan_batches(txn.scan(range), limit, projection, batch_size).await?;
+            let reader = ArrowReader::new(schema, stream, bounds);
+
+            Python::with_gil(|py| reader.into_pyarrow(py))
+        })
+    }
+
     /// Commit `Transaction`
     fn commit<'py>(&'py mut self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
         if self.txn.is_none() {
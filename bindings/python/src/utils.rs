@@ -5,6 +5,7 @@ use pyo3::{
     Bound, Py, PyAny, Python,
 };
 use tonbo::{
+    arrow::datatypes::{Schema as ArrowSchema, SchemaRef},
     cast_arc_value,
     record::{DataType as TonboDataType, Value, F64},
 };
@@ -199,3 +200,32 @@ pub(crate) fn to_bound(
 
     (lower, high)
 }
+
+/// indices of the columns named in `projection`, all of them for `*`
+pub(crate) fn to_projection(desc: &[Column], projection: &[String]) -> Vec<usize> {
+    match projection.contains(&"*".to_string()) {
+        true => (0..desc.len()).collect(),
+        false => desc
+            .iter()
+            .enumerate()
+            .filter(|(_idx, col)| projection.contains(&col.name))
+            .map(|(idx, _col)| idx)
+            .collect(),
+    }
+}
+
+/// arrow schema of the batches scanned with `projection`, which always contain the primary key
+///
+/// * `schema`: arrow schema of the user columns
+pub(crate) fn to_projection_schema(
+    schema: &ArrowSchema,
+    primary_key_index: usize,
+    projection: &[usize],
+) -> SchemaRef {
+    let mut indices = projection.to_vec();
+    indices.push(primary_key_index);
+    indices.sort_unstable();
+    indices.dedup();
+
+    Arc::new(schema.project(&indices).unwrap())
+}
@@ -1,6 +1,6 @@
 import pytest
 import tempfile
-from tonbo import DbOption, Column, DataType, Record, RecordBatch, TonboDB
+from tonbo import DbOption, Column, DataType, Record, RecordBatch, TonboDB, Bound
 
 
 @Record
@@ -80,3 +80,17 @@ async def test_db_recover():
     for i in range(0, 100):
         user = await db.get(i)
         assert user == {"age": i, "height": i * 10, "weight": i * 20}
+
+
+@pytest.mark.asyncio
+async def test_db_scan_arrow():
+    db = build_db()
+    for i in range(0, 100):
+        await db.insert(User(age=i, height=i * 10, weight=i * 20))
+    await db.remove(20)
+
+    reader = await db.scan_arrow(None, Bound.Excluded(50), batch_size=8)
+    batches = list(reader)
+    assert all(batch.num_rows <= 8 for batch in batches)
+    ages = [age for batch in batches for age in batch.column("age").to_pylist()]
+    assert ages == [i for i in range(0, 50) if i != 20]
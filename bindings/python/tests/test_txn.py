@@ -148,3 +148,19 @@ async def test_txn_cannot_read_future():
     for i in range(0, 10):
         user = await txn3.get(i)
         assert user == { "age": i, "height": i * 20, "weight": i * 40 }
+
+
+@pytest.mark.asyncio
+async def test_txn_scan_arrow():
+    db = build_db()
+    txn = await db.transaction()
+    for i in range(0, 100):
+        txn.insert(User(age=i, height=i * 10, weight=i * 20))
+
+    reader = await txn.scan_arrow(
+        Bound.Included(10), Bound.Excluded(75), projection=["height"], batch_size=16
+    )
+    assert reader.schema.names == ["age", "height"]
+    table = reader.read_all()
+    assert table.column("age").to_pylist() == list(range(10, 75))
+    assert table.column("height").to_pylist() == [i * 10 for i in range(10, 75)]
@@ -1,4 +1,11 @@
-use std::{hash::Hash, ops::Range, sync::Arc};
+use std::{
+    hash::Hash,
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use bytes::Bytes;
 use futures_core::future::BoxFuture;
@@ -26,6 +33,63 @@ where
 {
     meta: foyer::Cache<K, Arc<ParquetMetaData>>,
     data: foyer::HybridCache<(K, Range<u64>), Bytes>,
+    meta_hits: AtomicU64,
+    meta_misses: AtomicU64,
+    data_hits: AtomicU64,
+    data_misses: AtomicU64,
+}
+
+/// Lookups of a [`FoyerCache`] since it was built, data lookups count every byte range.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub meta_hits: u64,
+    pub meta_misses: u64,
+    pub data_hits: u64,
+    pub data_misses: u64,
+}
+
+impl CacheStats {
+    /// share of the data lookups served by the cache, 0 without lookups
+    pub fn data_hit_rate(&self) -> f64 {
+        self.data_hits as f64 / (self.data_hits + self.data_misses).max(1) as f64
+    }
+
+    /// share of the metadata lookups served by the cache, 0 without lookups
+    pub fn meta_hit_rate(&self) -> f64 {
+        self.meta_hits as f64 / (self.meta_hits + self.meta_misses).max(1) as f64
+    }
+}
+
+impl<K> FoyerCache<K>
+where
+    for<'a> K: Send + Sync + Hash + Eq + Serialize + Deserialize<'a> + 'static,
+{
+    /// cache the metadata of the files in the memory cache `meta`, and their byte ranges in
+    /// the hybrid memory and disk cache `data`
+    pub fn new(
+        meta: foyer::Cache<K, Arc<ParquetMetaData>>,
+        data: foyer::HybridCache<(K, Range<u64>), Bytes>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(FoyerCacheInner {
+                meta,
+                data,
+                meta_hits: AtomicU64::new(0),
+                meta_misses: AtomicU64::new(0),
+                data_hits: AtomicU64::new(0),
+                data_misses: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            meta_hits: self.inner.meta_hits.load(Ordering::Relaxed),
+            meta_misses: self.inner.meta_misses.load(Ordering::Relaxed),
+            data_hits: self.inner.data_hits.load(Ordering::Relaxed),
+            data_misses: self.inner.data_misses.load(Ordering::Relaxed),
+        }
+    }
 }
 
 impl<K> LruCache<K> for FoyerCache<K>
@@ -79,8 +143,10 @@ where
                 .await
                 .map_err(|e| ParquetError::External(e.into()))?
             {
+                self.cache.inner.data_hits.fetch_add(1, Ordering::Relaxed);
                 Ok(data.value().clone())
             } else {
+                self.cache.inner.data_misses.fetch_add(1, Ordering::Relaxed);
                 let data = self.reader.get_bytes(range.clone()).await?;
                 self.cache
                     .inner
@@ -98,8 +164,10 @@ where
     ) -> BoxFuture<'s, Result<Arc<ParquetMetaData>>> {
         async move {
             if let Some(meta) = self.cache.inner.meta.get(&self.key) {
+                self.cache.inner.meta_hits.fetch_add(1, Ordering::Relaxed);
                 Ok(meta.value().clone())
             } else {
+                self.cache.inner.meta_misses.fetch_add(1, Ordering::Relaxed);
                 let meta = self.reader.get_metadata(options).await?;
                 self.cache.inner.meta.insert(self.key.clone(), meta.clone());
                 Ok(meta)
@@ -126,13 +194,20 @@ where
                     missed.push((id, range));
                 }
             }
+            let inner = &self.cache.inner;
+            inner
+                .data_hits
+                .fetch_add(results.len() as u64, Ordering::Relaxed);
+            inner
+                .data_misses
+                .fetch_add(missed.len() as u64, Ordering::Relaxed);
             if !missed.is_empty() {
                 let data = self
                     .reader
                     .get_byte_ranges(missed.iter().map(|&(_, r)| r.clone()).collect())
                     .await?;
-                for (id, range) in missed {
-                    let data = data[id].clone();
+                // the fetched ranges are in the order of the missed ones
+                for ((id, range), data) in missed.into_iter().zip(data) {
                     self.cache
                         .inner
                         .data
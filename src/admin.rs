@@ -0,0 +1,139 @@
+//! The admin socket of a live [`DB`], driven by `tonbo-cli`.
+//!
+//! [`DB::serve_admin`] listens on a Unix socket for commands, one per line, and answers every
+//! command with the lines of its output followed by `ok`, or with a single `error: <reason>`
+//! line:
+//!
+//! - `flush`: freeze the mutable memtable and flush the memtables into SSTables, see [`DB::flush`]
+//! - `compact`: the same, the compactions due after the flush run along with it
+//! - `ping`: answers `ok`
+
+use std::{io, path::Path};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+use tracing::warn;
+
+use crate::{
+    executor::Executor,
+    record::{Record, Schema},
+    DB,
+};
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Serve the commands of the admin socket (see [`admin`](crate::admin)) at `path` until
+    /// accepting a connection fails. A socket file left at `path` by a crashed process is
+    /// replaced.
+    ///
+    /// Connections are served one after the other, the caller spawns the returned future on its
+    /// runtime, e.g. next to the [`DB`].
+    pub async fn serve_admin(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        match std::fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        let listener = UnixListener::bind(path)?;
+        loop {
+            let (stream, _) = listener.accept().await?;
+            if let Err(err) = self.serve_admin_connection(stream).await {
+                warn!("admin connection failed: {err}");
+            }
+        }
+    }
+
+    async fn serve_admin_connection(&self, stream: UnixStream) -> io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            let response = match self.admin_command(line.trim()).await {
+                Ok(output) => format!("{output}ok\n"),
+                Err(reason) => format!("error: {reason}\n"),
+            };
+            writer.write_all(response.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// run `command`, returns its output lines
+    async fn admin_command(&self, command: &str) -> Result<String, String> {
+        match command {
+            "flush" | "compact" => self
+                .flush()
+                .await
+                .map(|()| String::new())
+                .map_err(|err| err.to_string()),
+            "ping" => Ok(String::new()),
+            _ => Err(format!("unknown command {command:?}")),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::UnixStream,
+    };
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn admin_socket() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: Arc<DB<Test, TokioExecutor>> = Arc::new(
+            DB::new(option, TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap(),
+        );
+        db.insert(Test {
+            vstring: "key".to_string(),
+            vu32: 0,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+
+        let socket = temp_dir.path().join("admin.sock");
+        tokio::spawn({
+            let db = db.clone();
+            let socket = socket.clone();
+            async move { db.serve_admin(socket).await }
+        });
+        let stream = loop {
+            match UnixStream::connect(&socket).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        writer.write_all(b"flush\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+        assert_eq!(db.ctx.version_set.current().await.level_slice[0].len(), 1);
+        writer.write_all(b"vacuum\n").await.unwrap();
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            "error: unknown command \"vacuum\""
+        );
+    }
+}
@@ -0,0 +1,520 @@
+use std::{ops::Bound, sync::Arc};
+
+use arrow::{
+    array::{new_null_array, Array, ArrayRef, AsArray, Float64Array, Int64Array, UInt64Array},
+    compute::{
+        cast, concat,
+        kernels::{aggregate::sum, sort::sort_to_indices},
+        take, SortOptions,
+    },
+    datatypes::{DataType, Float64Type, Int64Type, Schema as ArrowSchema, UInt32Type, UInt64Type},
+    error::ArrowError,
+};
+use futures_util::StreamExt;
+use parquet::{
+    arrow::arrow_reader::statistics::StatisticsConverter, errors::ParquetError,
+    file::metadata::ParquetMetaData,
+};
+
+use crate::{
+    executor::Executor,
+    magic,
+    record::{Record, Schema},
+    scope::Scope,
+    snapshot::Snapshot,
+    timestamp::Timestamp,
+    version::MAX_LEVEL,
+    DbError, DB,
+};
+
+const SCAN_BATCH_SIZE: usize = 8192;
+
+/// Aggregation evaluated by [`DB::aggregate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggExpr<'a> {
+    /// number of live rows, returned as `UInt64`
+    Count,
+    /// smallest non-null value of the column, returned with the column's type
+    Min(&'a str),
+    /// largest non-null value of the column, returned with the column's type
+    Max(&'a str),
+    /// sum of a numeric column, returned as `Int64`, `UInt64` or `Float64`
+    Sum(&'a str),
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Evaluate `expr` over the records with primary keys in `range`, the result is a single-row
+    /// array.
+    ///
+    /// SSTables that lie entirely in `range`, have no tombstones, contain no rows newer than the
+    /// read timestamp and do not overlap any other memtable or SSTable are answered from their
+    /// Parquet row group statistics without being read. Everything else is scanned. `Sum` always
+    /// scans since Parquet does not record sums, but only the aggregated column is read.
+    pub async fn aggregate(
+        &self,
+        range: (
+            Bound<&<R::Schema as Schema>::Key>,
+            Bound<&<R::Schema as Schema>::Key>,
+        ),
+        expr: AggExpr<'_>,
+    ) -> Result<ArrayRef, DbError<R>> {
+        let snapshot = self.snapshot().await;
+        let full_schema = self.ctx.arrow_schema();
+        let column = match expr {
+            AggExpr::Count => None,
+            AggExpr::Min(column) | AggExpr::Max(column) | AggExpr::Sum(column) => {
+                if full_schema.index_of(column).is_err() {
+                    panic!("unexpected field {}", column)
+                }
+                Some(column)
+            }
+        };
+        let mut partials = Vec::new();
+
+        // tables answered from statistics, sorted by key and disjoint from every other source
+        let mut stat_scopes = Vec::new();
+        if !matches!(expr, AggExpr::Sum(_)) {
+            for (level, scope) in isolated_tables(&snapshot, range) {
+                let metadata = snapshot
+                    .version()
+                    .table_metadata(
+                        self.ctx.storage_manager(),
+                        level,
+                        scope.gen,
+                        self.ctx.cache().clone(),
+                    )
+                    .await?;
+                if let Some(partial) =
+                    statistics_partial(&metadata, full_schema, expr, snapshot.ts())?
+                {
+                    partials.push(partial);
+                    stat_scopes.push(scope);
+                }
+            }
+            stat_scopes.sort_by(|a, b| a.min.cmp(&b.min));
+        }
+
+        let mut lower = range.0;
+        let mut gaps = Vec::with_capacity(stat_scopes.len() + 1);
+        for scope in stat_scopes {
+            gaps.push((lower, Bound::Excluded(&scope.min)));
+            lower = Bound::Excluded(&scope.max);
+        }
+        gaps.push((lower, range.1));
+
+        for gap in gaps {
+            let mut scan = snapshot.scan(gap);
+            if let Some(column) = column {
+                scan = scan.projection(&[column]);
+            }
+            let mut batches = scan.scan_batches(SCAN_BATCH_SIZE).await?;
+
+            while let Some(batch) = batches.next().await.transpose()? {
+                let partial = match column {
+                    None => Arc::new(UInt64Array::from(vec![batch.num_rows() as u64])),
+                    Some(column) => {
+                        let array = batch.column_by_name(column).ok_or_else(|| {
+                            ParquetError::General(format!("column {} not found in batch", column))
+                        })?;
+                        reduce(expr, array.as_ref()).map_err(ParquetError::from)?
+                    }
+                };
+                partials.push(partial);
+            }
+        }
+
+        let data_type = match column {
+            None => DataType::UInt64,
+            Some(column) => {
+                let data_type = full_schema.field_with_name(column).unwrap().data_type();
+                match expr {
+                    AggExpr::Sum(_) => sum_type(data_type).map_err(ParquetError::from)?,
+                    _ => data_type.clone(),
+                }
+            }
+        };
+        if partials.is_empty() {
+            return Ok(match expr {
+                AggExpr::Count => Arc::new(UInt64Array::from(vec![0])),
+                _ => new_null_array(&data_type, 1),
+            });
+        }
+        let partials = partials
+            .iter()
+            .map(|array| array.as_ref())
+            .collect::<Vec<_>>();
+        let partials = cast(&concat(&partials).map_err(ParquetError::from)?, &data_type)
+            .map_err(ParquetError::from)?;
+
+        Ok(reduce(expr, &partials).map_err(ParquetError::from)?)
+    }
+}
+
+/// SSTables of level 1 and above lying entirely in `range` that no other memtable or SSTable
+/// overlaps and that are known to hold a single version of every key, level 0 tables may
+/// contain several versions of a key so they are never used
+fn isolated_tables<'s, R>(
+    snapshot: &'s Snapshot<'_, R>,
+    range: (
+        Bound<&<R::Schema as Schema>::Key>,
+        Bound<&<R::Schema as Schema>::Key>,
+    ),
+) -> Vec<(usize, &'s Scope<<R::Schema as Schema>::Key>)>
+where
+    R: Record,
+{
+    let storage = snapshot.schema();
+    let version = snapshot.version();
+
+    let mut tables = Vec::new();
+    // tables keep several versions of a key during time travel retention, only deltas of
+    // records with merge-on-read, and records that may have expired
+    if storage.option.time_travel_retention > 0
+        || storage.is_merge_on_read(snapshot.ctx())
+        || storage.record_schema.expire_at_index().is_some()
+    {
+        return tables;
+    }
+    for level in 1..MAX_LEVEL {
+        for scope in version.level_slice[level].iter() {
+            // versions kept for a named snapshot stay until the table is compacted again
+            if !contains_scope(range, scope)
+                || !scope.stats.is_some_and(|stats| stats.is_single_version)
+            {
+                continue;
+            }
+            let overlaps_mutable = storage
+                .mutable
+                .scan(
+                    (Bound::Included(&scope.min), Bound::Included(&scope.max)),
+                    snapshot.ts(),
+                )
+                .next()
+                .is_some();
+            let overlaps_immutable =
+                storage
+                    .immutables
+                    .iter()
+                    .any(|(_, immutable)| match immutable.scope() {
+                        (Some(min), Some(max)) => min <= &scope.max && &scope.min <= max,
+                        _ => false,
+                    });
+            let overlaps_table = version
+                .level_slice
+                .iter()
+                .enumerate()
+                .flat_map(|(i, scopes)| scopes.iter().map(move |other| (i, other)))
+                .any(|(i, other)| {
+                    !(i == level && other.gen == scope.gen)
+                        && other.min <= scope.max
+                        && scope.min <= other.max
+                });
+
+            if !overlaps_mutable && !overlaps_immutable && !overlaps_table {
+                tables.push((level, scope));
+            }
+        }
+    }
+    tables
+}
+
+fn contains_scope<K>(range: (Bound<&K>, Bound<&K>), scope: &Scope<K>) -> bool
+where
+    K: Ord,
+{
+    let lower = match range.0 {
+        Bound::Included(key) => key <= &scope.min,
+        Bound::Excluded(key) => key < &scope.min,
+        Bound::Unbounded => true,
+    };
+    let upper = match range.1 {
+        Bound::Included(key) => &scope.max <= key,
+        Bound::Excluded(key) => &scope.max < key,
+        Bound::Unbounded => true,
+    };
+    lower && upper
+}
+
+/// partial result of `expr` computed from row group statistics, `None` if they are insufficient
+fn statistics_partial(
+    metadata: &ParquetMetaData,
+    full_schema: &ArrowSchema,
+    expr: AggExpr<'_>,
+    ts: Timestamp,
+) -> Result<Option<ArrayRef>, ParquetError> {
+    let parquet_schema = metadata.file_metadata().schema_descr();
+    let row_groups = metadata.row_groups();
+    let converter =
+        |column: &str| StatisticsConverter::try_new(column, full_schema, parquet_schema);
+
+    // tombstones and versions newer than the read timestamp must go through the merge
+    let nulls = converter(magic::NULL)?.row_group_maxes(row_groups)?;
+    if nulls.null_count() > 0 || nulls.as_boolean().true_count() > 0 {
+        return Ok(None);
+    }
+    let tss = converter(magic::TS)?.row_group_maxes(row_groups)?;
+    let tss = tss.as_primitive_opt::<UInt32Type>();
+    if !tss.is_some_and(|tss| {
+        tss.null_count() == 0 && tss.values().iter().all(|max| *max <= u32::from(ts))
+    }) {
+        return Ok(None);
+    }
+
+    let row_counts = converter(magic::TS)?.row_group_row_counts(row_groups)?;
+    let Some(row_counts) = row_counts else {
+        return Ok(None);
+    };
+    let column = match expr {
+        AggExpr::Count => {
+            return Ok(Some(Arc::new(UInt64Array::from(vec![row_counts
+                .values()
+                .iter()
+                .sum::<u64>()]))));
+        }
+        AggExpr::Min(column) | AggExpr::Max(column) => column,
+        AggExpr::Sum(_) => return Ok(None),
+    };
+    let converter = converter(column)?;
+    let values = match expr {
+        AggExpr::Min(_) => converter.row_group_mins(row_groups)?,
+        _ => converter.row_group_maxes(row_groups)?,
+    };
+    let null_counts = converter.row_group_null_counts(row_groups)?;
+    // a missing min/max is only fine when the whole row group is null
+    for i in 0..values.len() {
+        if values.is_null(i)
+            && (null_counts.is_null(i) || null_counts.value(i) != row_counts.value(i))
+        {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(
+        reduce(expr, values.as_ref()).map_err(ParquetError::from)?,
+    ))
+}
+
+/// reduce `array` to the single-row partial result of `expr`
+fn reduce(expr: AggExpr<'_>, array: &dyn Array) -> Result<ArrayRef, ArrowError> {
+    match expr {
+        AggExpr::Count => Ok(Arc::new(UInt64Array::from(vec![sum(
+            array.as_primitive::<UInt64Type>()
+        )
+        .unwrap_or(0)]))),
+        AggExpr::Min(_) | AggExpr::Max(_) => {
+            let indices = sort_to_indices(
+                array,
+                Some(SortOptions {
+                    descending: matches!(expr, AggExpr::Max(_)),
+                    nulls_first: false,
+                }),
+                Some(1),
+            )?;
+            if indices.is_empty() {
+                return Ok(new_null_array(array.data_type(), 1));
+            }
+            take(array, &indices, None)
+        }
+        AggExpr::Sum(_) => {
+            let array = cast(array, &sum_type(array.data_type())?)?;
+            Ok(match array.data_type() {
+                DataType::Int64 => Arc::new(Int64Array::from(vec![sum(
+                    array.as_primitive::<Int64Type>()
+                )])),
+                DataType::UInt64 => Arc::new(UInt64Array::from(vec![sum(
+                    array.as_primitive::<UInt64Type>()
+                )])),
+                _ => Arc::new(Float64Array::from(vec![sum(
+                    array.as_primitive::<Float64Type>()
+                )])),
+            })
+        }
+    }
+}
+
+fn sum_type(data_type: &DataType) -> Result<DataType, ArrowError> {
+    match data_type {
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => Ok(DataType::Int64),
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => {
+            Ok(DataType::UInt64)
+        }
+        DataType::Float16 | DataType::Float32 | DataType::Float64 => Ok(DataType::Float64),
+        data_type => Err(ArrowError::InvalidArgumentError(format!(
+            "sum is not supported for {}",
+            data_type
+        ))),
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::ops::Bound;
+
+    use arrow::{
+        array::AsArray,
+        datatypes::{UInt32Type, UInt64Type},
+    };
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::AggExpr;
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test,
+        trigger::TriggerType, CompactionOption, DbOption, SizeRatioOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn aggregate() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 1;
+        option.major_threshold_with_sst_size = 3;
+        option.level_sst_magnification = 10;
+        option.major_default_oldest_table_num = 1;
+        option.trigger_type = TriggerType::Length(5);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        for i in 0..32 {
+            db.write(
+                Test {
+                    vstring: i.to_string(),
+                    vu32: i,
+                    vbool: Some(true),
+                },
+                0.into(),
+            )
+            .await
+            .unwrap();
+            if i % 5 == 0 {
+                db.flush().await.unwrap();
+            }
+        }
+        db.remove(3.to_string()).await.unwrap();
+        db.insert(Test {
+            vstring: 10.to_string(),
+            vu32: 1000,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+
+        let all = (Bound::Unbounded, Bound::Unbounded);
+        let count = db.aggregate(all, AggExpr::Count).await.unwrap();
+        assert_eq!(count.as_primitive::<UInt64Type>().value(0), 31);
+
+        let max = db.aggregate(all, AggExpr::Max("vu32")).await.unwrap();
+        assert_eq!(max.as_primitive::<UInt32Type>().value(0), 1000);
+
+        let sum = db.aggregate(all, AggExpr::Sum("vu32")).await.unwrap();
+        assert_eq!(
+            sum.as_primitive::<UInt64Type>().value(0),
+            (0..32).sum::<u64>() - 3 - 10 + 1000
+        );
+
+        let lower = 20.to_string();
+        let upper = 4.to_string();
+        let range = (Bound::Included(&lower), Bound::Excluded(&upper));
+        let min = db.aggregate(range, AggExpr::Min("vu32")).await.unwrap();
+        assert_eq!(min.as_primitive::<UInt32Type>().value(0), 20);
+        let count = db.aggregate(range, AggExpr::Count).await.unwrap();
+        // 20..=29, 30 and 31
+        assert_eq!(count.as_primitive::<UInt64Type>().value(0), 12);
+
+        let empty = 5.to_string();
+        let range = (Bound::Included(&empty), Bound::Excluded(&empty));
+        let count = db.aggregate(range, AggExpr::Count).await.unwrap();
+        assert_eq!(count.as_primitive::<UInt64Type>().value(0), 0);
+        let min = db.aggregate(range, AggExpr::Min("vu32")).await.unwrap();
+        assert!(min.is_null(0));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn aggregate_compacted_level0_table() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .compaction_option(CompactionOption::SizeRatio(SizeRatioOption {
+            level0_table_num: 1,
+            ..Default::default()
+        }));
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        for vu32 in [1, 2] {
+            db.insert(Test {
+                vstring: "a".to_string(),
+                vu32,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+
+        // the single table of level 0 holds both versions, it leaves one in level 1
+        assert_eq!(db.ctx.version_set().current().await.tables_len(1), 1);
+        let all = (Bound::Unbounded, Bound::Unbounded);
+        let count = db.aggregate(all, AggExpr::Count).await.unwrap();
+        assert_eq!(count.as_primitive::<UInt64Type>().value(0), 1);
+        let min = db.aggregate(all, AggExpr::Min("vu32")).await.unwrap();
+        assert_eq!(min.as_primitive::<UInt32Type>().value(0), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn aggregate_after_named_snapshot() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .compaction_option(CompactionOption::SizeRatio(SizeRatioOption {
+            level0_table_num: 1,
+            ..Default::default()
+        }));
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        let insert = |vu32| {
+            db.insert(Test {
+                vstring: "a".to_string(),
+                vu32,
+                vbool: None,
+            })
+        };
+        insert(1).await.unwrap();
+        db.flush().await.unwrap();
+        db.create_named_snapshot("pinned").await.unwrap();
+        insert(2).await.unwrap();
+        db.flush().await.unwrap();
+        assert!(db.release_named_snapshot("pinned"));
+
+        // the compacted table keeps the version of the released snapshot
+        let version = db.ctx.version_set().current().await;
+        assert_eq!(version.tables_len(0), 0);
+        assert_eq!(version.tables_len(1), 1);
+        assert_eq!(version.level_slice[1][0].stats.unwrap().rows, 2);
+        drop(version);
+        let all = (Bound::Unbounded, Bound::Unbounded);
+        let count = db.aggregate(all, AggExpr::Count).await.unwrap();
+        assert_eq!(count.as_primitive::<UInt64Type>().value(0), 1);
+        let min = db.aggregate(all, AggExpr::Min("vu32")).await.unwrap();
+        assert_eq!(min.as_primitive::<UInt32Type>().value(0), 2);
+    }
+}
@@ -0,0 +1,126 @@
+use std::{error::Error, fmt::Debug, sync::Arc};
+
+use fusio::{path::Path, DynFs};
+use fusio_dispatch::FsOptions;
+use futures_core::future::BoxFuture;
+
+use crate::fs::{copy, FileId, FileType};
+
+/// Receives the WAL segments of a [`DB`](crate::DB) once their memtables are flushed into
+/// SSTables, instead of the segments being removed, see
+/// [`DbOption::wal_archiver`](crate::DbOption::wal_archiver).
+///
+/// Segments are named by their [`FileId`], which orders them by creation: replaying the archived
+/// segments in order on top of a backup of the SSTables recovers the [`DB`](crate::DB) to a point
+/// in time, shipping them to another process keeps a warm standby.
+///
+/// `archive` runs on the compaction task. The segment is removed once it is archived, a segment
+/// that fails to be archived is kept and archived again with the next segments.
+pub trait WalArchiver: Debug + Send + Sync {
+    /// archive the WAL segment `gen` at `path` of `fs`
+    fn archive<'a>(
+        &'a self,
+        gen: FileId,
+        path: &'a Path,
+        fs: &'a Arc<dyn DynFs>,
+    ) -> BoxFuture<'a, Result<(), Box<dyn Error + Send + Sync>>>;
+}
+
+/// A [`WalArchiver`] copying the WAL segments to a directory, e.g. a prefix of an object store.
+pub struct CopyArchiver {
+    path: Path,
+    fs: Arc<dyn DynFs>,
+}
+
+impl CopyArchiver {
+    /// copy the segments to `path` of the fs of `fs_options`, which is created if missing
+    pub async fn new(path: Path, fs_options: FsOptions) -> Result<Self, fusio::Error> {
+        let fs = fs_options.parse()?;
+        fs.create_dir_all(&path).await?;
+
+        Ok(Self { path, fs })
+    }
+}
+
+impl Debug for CopyArchiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CopyArchiver")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl WalArchiver for CopyArchiver {
+    fn archive<'a>(
+        &'a self,
+        gen: FileId,
+        path: &'a Path,
+        fs: &'a Arc<dyn DynFs>,
+    ) -> BoxFuture<'a, Result<(), Box<dyn Error + Send + Sync>>> {
+        Box::pin(async move {
+            copy(
+                &**fs,
+                path,
+                &*self.fs,
+                &self.path.child(format!("{}.{}", gen, FileType::Wal)),
+                None,
+            )
+            .await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::fs;
+
+    use fusio::path::Path;
+    use fusio_dispatch::FsOptions;
+    use tempfile::TempDir;
+
+    use super::CopyArchiver;
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn archive_flushed_segments() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = TempDir::new().unwrap();
+        let archiver = CopyArchiver::new(
+            Path::from_filesystem_path(archive_dir.path()).unwrap(),
+            FsOptions::Local,
+        )
+        .await
+        .unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .version_log_snapshot_threshold(1)
+        .wal_archiver(std::sync::Arc::new(archiver));
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        db.insert(Test {
+            vstring: "key".to_string(),
+            vu32: 1,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+
+        // the flushed segment is moved to the archive
+        let archived = fs::read_dir(archive_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(archived.len(), 1);
+        assert!(!temp_dir.path().join("wal").join(&archived[0]).exists());
+    }
+}
@@ -0,0 +1,491 @@
+use std::{collections::HashSet, fmt::Write as _, io::ErrorKind, sync::Arc};
+
+use fusio::{path::Path, DynFs, Read, Write};
+use fusio_dispatch::FsOptions;
+use futures_util::StreamExt;
+use thiserror::Error;
+
+use crate::{
+    checkpoint::write_manifest,
+    executor::Executor,
+    fs::{copy, generate_file_id, manager::StoreManager, parse_file_id, FileId, FileType},
+    record::{Record, Schema},
+    timestamp::Timestamp,
+    DbError, DbOption, DB,
+};
+
+const METADATA_HEADER: &str = "tonbo-backup v1";
+
+/// tables shared by the backups of a location, which are never rewritten once written
+const TABLES_DIR: &str = "tables";
+/// metadata and manifest of every backup
+const BACKUPS_DIR: &str = "backups";
+/// WAL segments cut by every backup
+const WAL_DIR: &str = "wal";
+
+/// Whether a backup copies every table of the [`DB`] or only those missing in the backup
+/// location.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackupMode {
+    /// copy every table again, e.g. to replace tables damaged in the backup location
+    Full,
+    /// copy only the tables not backed up yet, SSTables are never modified once written
+    #[default]
+    Incremental,
+}
+
+/// A backup written by [`DB::backup`]: the tables of a version of the [`DB`] and the WAL
+/// segments cut at the latest commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupInfo {
+    pub id: FileId,
+    /// the latest timestamp of the [`DB`] when the backup was taken
+    pub ts: Timestamp,
+    pub tables: Vec<BackupTable>,
+    pub wal_segments: Vec<BackupSegment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupTable {
+    pub gen: FileId,
+    pub level: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupSegment {
+    pub gen: FileId,
+    /// bytes of the segment cut by the backup
+    pub size: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("backup fusio error: {0}")]
+    Fusio(#[from] fusio::Error),
+    #[error("malformed backup metadata: {0}")]
+    Metadata(String),
+    #[error("backup {0} not found")]
+    NotFound(FileId),
+    #[error("restore target {0} already holds a DB")]
+    NotEmpty(Path),
+}
+
+impl BackupInfo {
+    fn encode(&self) -> String {
+        let mut metadata = format!(
+            "{METADATA_HEADER}\nid {}\nts {}\n",
+            self.id,
+            u32::from(self.ts)
+        );
+        // SAFETY: writing to a String is infallible
+        for table in &self.tables {
+            writeln!(metadata, "table {} {}", table.gen, table.level).unwrap();
+        }
+        for segment in &self.wal_segments {
+            writeln!(metadata, "wal {} {}", segment.gen, segment.size).unwrap();
+        }
+        metadata
+    }
+
+    fn decode(metadata: &str) -> Result<Self, BackupError> {
+        let malformed = |line: &str| BackupError::Metadata(line.to_string());
+        let mut lines = metadata.lines();
+        if lines.next() != Some(METADATA_HEADER) {
+            return Err(malformed("missing header"));
+        }
+        let id = lines
+            .next()
+            .and_then(|line| line.strip_prefix("id "))
+            .and_then(|id| id.parse::<FileId>().ok())
+            .ok_or_else(|| malformed("missing id"))?;
+        let ts = lines
+            .next()
+            .and_then(|line| line.strip_prefix("ts "))
+            .and_then(|ts| ts.parse::<u32>().ok())
+            .ok_or_else(|| malformed("missing timestamp"))?;
+
+        let mut info = BackupInfo {
+            id,
+            ts: ts.into(),
+            tables: Vec::new(),
+            wal_segments: Vec::new(),
+        };
+        for line in lines {
+            let fields = line.split(' ').collect::<Vec<_>>();
+            let [kind, gen, value] = fields[..] else {
+                return Err(malformed(line));
+            };
+            let gen = gen.parse::<FileId>().map_err(|_| malformed(line))?;
+            match kind {
+                "table" => info.tables.push(BackupTable {
+                    gen,
+                    level: value.parse().map_err(|_| malformed(line))?,
+                }),
+                "wal" => info.wal_segments.push(BackupSegment {
+                    gen,
+                    size: value.parse().map_err(|_| malformed(line))?,
+                }),
+                _ => return Err(malformed(line)),
+            }
+        }
+
+        Ok(info)
+    }
+}
+
+/// A backup location of [`DB`]s, e.g. a prefix of an object store, holding the backups taken by
+/// [`DB::backup`] and restoring them with [`BackupEngine::restore`].
+///
+/// The backups of a location share its tables: a table is copied by the first backup holding it,
+/// later [`BackupMode::Incremental`] backups only copy the tables written since.
+pub struct BackupEngine {
+    path: Path,
+    fs: Arc<dyn DynFs>,
+}
+
+impl BackupEngine {
+    /// back up to `path` of the fs of `fs_options`, which is created if missing
+    pub async fn new(path: Path, fs_options: FsOptions) -> Result<Self, BackupError> {
+        let fs = fs_options.parse()?;
+        fs.create_dir_all(&path.child(TABLES_DIR)).await?;
+        fs.create_dir_all(&path.child(BACKUPS_DIR)).await?;
+
+        Ok(Self { path, fs })
+    }
+
+    fn table_path(&self, gen: FileId) -> Path {
+        self.path
+            .child(TABLES_DIR)
+            .child(format!("{}.{}", gen, FileType::Parquet))
+    }
+
+    fn segment_path(&self, id: FileId, gen: FileId) -> Path {
+        self.path
+            .child(WAL_DIR)
+            .child(id.to_string())
+            .child(format!("{}.{}", gen, FileType::Wal))
+    }
+
+    fn manifest_path(&self, id: FileId) -> Path {
+        self.path
+            .child(BACKUPS_DIR)
+            .child(format!("{}.{}", id, FileType::Log))
+    }
+
+    fn metadata_path(&self, id: FileId) -> Path {
+        self.path.child(BACKUPS_DIR).child(format!("{}.backup", id))
+    }
+
+    /// the backups of the location, oldest first
+    pub async fn backups(&self) -> Result<Vec<BackupInfo>, BackupError> {
+        let mut ids = Vec::new();
+        let mut entries = self.fs.list(&self.path.child(BACKUPS_DIR)).await?;
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if let Some(id) = entry
+                .path
+                .filename()
+                .and_then(|name| name.strip_suffix(".backup"))
+                .and_then(|id| id.parse::<FileId>().ok())
+            {
+                ids.push(id);
+            }
+        }
+        drop(entries);
+        // backups are named by their time ordered ids
+        ids.sort();
+
+        let mut backups = Vec::with_capacity(ids.len());
+        for id in ids {
+            backups.push(self.backup(id).await?);
+        }
+        Ok(backups)
+    }
+
+    /// the backup `id` of the location
+    pub async fn backup(&self, id: FileId) -> Result<BackupInfo, BackupError> {
+        let mut metadata_file = match self
+            .fs
+            .open_options(
+                &self.metadata_path(id),
+                FileType::Parquet.open_options(true),
+            )
+            .await
+        {
+            Ok(file) => file,
+            Err(fusio::Error::Io(err)) if err.kind() == ErrorKind::NotFound => {
+                return Err(BackupError::NotFound(id))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let (result, metadata) = metadata_file.read_to_end_at(Vec::new(), 0).await;
+        result?;
+        let metadata = String::from_utf8(metadata)
+            .map_err(|_| BackupError::Metadata("not valid UTF-8".to_string()))?;
+        BackupInfo::decode(&metadata)
+    }
+
+    /// Restore the backup `id` into the paths of `option`, which must not hold a [`DB`] yet, to
+    /// open a [`DB`] on with `option` afterward.
+    pub async fn restore(&self, id: FileId, option: &DbOption) -> Result<(), BackupError> {
+        let info = self.backup(id).await?;
+        let mut manager = StoreManager::new(option.base_fs.clone(), option.level_paths.clone())?;
+        if let Some((_, fs_options)) = &option.wal_path {
+            manager = manager.with_wal_fs(fs_options.clone())?;
+        }
+
+        let version_dir = option.version_log_dir_path();
+        manager.base_fs().create_dir_all(&version_dir).await?;
+        let mut logs = manager.base_fs().list(&version_dir).await?;
+        if let Some(log) = logs.next().await {
+            log?;
+            return Err(BackupError::NotEmpty(option.base_path.clone()));
+        }
+        drop(logs);
+        manager
+            .wal_fs()
+            .create_dir_all(&option.wal_dir_path())
+            .await?;
+        for (level_path, _) in option.level_paths.iter().flatten() {
+            manager
+                .get_fs(level_path)
+                .create_dir_all(level_path)
+                .await?;
+        }
+
+        for table in &info.tables {
+            let level_path = option
+                .level_fs_path(table.level)
+                .unwrap_or(&option.base_path);
+            copy(
+                &*self.fs,
+                &self.table_path(table.gen),
+                &**manager.get_fs(level_path),
+                &option.table_path(table.gen, table.level),
+                None,
+            )
+            .await?;
+        }
+        for segment in &info.wal_segments {
+            copy(
+                &*self.fs,
+                &self.segment_path(id, segment.gen),
+                &**manager.wal_fs(),
+                &option.wal_segment_path(segment.gen),
+                None,
+            )
+            .await?;
+        }
+        // the manifest goes last, a restore stopped before is restored again
+        copy(
+            &*self.fs,
+            &self.manifest_path(id),
+            &**manager.base_fs(),
+            &option.version_log_path(generate_file_id()),
+            None,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Back up the [`DB`] to `engine`: the tables of the current version, copied according to
+    /// `mode`, a manifest of them and the WAL segments cut at the latest commit. Writers are only
+    /// blocked while the WAL is flushed and cut, see [`DB::checkpoint`].
+    ///
+    /// The backup is listed by [`BackupEngine::backups`] once every file is copied.
+    pub async fn backup(
+        &self,
+        engine: &BackupEngine,
+        mode: BackupMode,
+    ) -> Result<BackupInfo, DbError<R>> {
+        let id = generate_file_id();
+        let backed_up = match mode {
+            BackupMode::Full => HashSet::new(),
+            BackupMode::Incremental => {
+                let mut backed_up = HashSet::new();
+                let mut tables = engine.fs.list(&engine.path.child(TABLES_DIR)).await?;
+                while let Some(table) = tables.next().await {
+                    let table = table?;
+                    if !table.path.as_ref().ends_with("parquet") {
+                        continue;
+                    }
+                    if let Some(gen) = parse_file_id(&table.path, FileType::Parquet)? {
+                        backed_up.insert(gen);
+                    }
+                }
+                backed_up
+            }
+        };
+
+        let option = self.schema.read().await.option.clone();
+        let cut = self.cut_wal().await?;
+        let ts = cut.ts;
+        engine
+            .fs
+            .create_dir_all(&engine.path.child(WAL_DIR).child(id.to_string()))
+            .await?;
+        let (segments, version) = self
+            .copy_wal_cut(cut, &*engine.fs, |gen| engine.segment_path(id, gen))
+            .await?;
+
+        let mut tables = Vec::new();
+        for (level, scopes) in version.level_slice.iter().enumerate() {
+            let level_path = option.level_fs_path(level).unwrap_or(&option.base_path);
+            let level_fs = self.ctx.manager.get_fs(level_path);
+            for scope in scopes {
+                if !backed_up.contains(&scope.gen) {
+                    copy(
+                        &**level_fs,
+                        &option.table_path(scope.gen, level),
+                        &*engine.fs,
+                        &engine.table_path(scope.gen),
+                        None,
+                    )
+                    .await?;
+                }
+                tables.push(BackupTable {
+                    gen: scope.gen,
+                    level,
+                });
+            }
+        }
+        write_manifest(&version, engine.fs.clone(), engine.manifest_path(id)).await?;
+
+        let info = BackupInfo {
+            id,
+            ts,
+            tables,
+            wal_segments: segments
+                .into_iter()
+                .map(|(gen, size)| BackupSegment { gen, size })
+                .collect(),
+        };
+        let mut metadata_file = engine
+            .fs
+            .open_options(
+                &engine.metadata_path(id),
+                FileType::Parquet.open_options(false),
+            )
+            .await?;
+        let (result, _) = metadata_file.write_all(info.encode().into_bytes()).await;
+        result?;
+        metadata_file.close().await?;
+
+        Ok(info)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::fs;
+
+    use fusio::path::Path;
+    use fusio_dispatch::FsOptions;
+    use tempfile::TempDir;
+
+    use super::{BackupEngine, BackupError, BackupInfo, BackupMode};
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn incremental_backup() {
+        let temp_dir = TempDir::new().unwrap();
+        let backup_dir = TempDir::new().unwrap();
+        let record = |key: &str| Test {
+            vstring: key.to_string(),
+            vu32: 0,
+            vbool: None,
+        };
+        let engine = BackupEngine::new(
+            Path::from_filesystem_path(backup_dir.path()).unwrap(),
+            FsOptions::Local,
+        )
+        .await
+        .unwrap();
+
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        db.insert(record("a")).await.unwrap();
+        db.flush().await.unwrap();
+        let first = db.backup(&engine, BackupMode::Full).await.unwrap();
+
+        db.insert(record("b")).await.unwrap();
+        db.flush().await.unwrap();
+        db.insert(record("c")).await.unwrap();
+        let second = db.backup(&engine, BackupMode::Incremental).await.unwrap();
+        // the table of the first backup is not copied again
+        assert_eq!(second.tables.len(), 2);
+        assert_eq!(
+            fs::read_dir(backup_dir.path().join("tables"))
+                .unwrap()
+                .count(),
+            2
+        );
+        assert_eq!(
+            engine.backups().await.unwrap(),
+            vec![first.clone(), second.clone()]
+        );
+
+        for (info, keys) in [(&first, vec!["a"]), (&second, vec!["a", "b", "c"])] {
+            let restore_dir = TempDir::new().unwrap();
+            let option = DbOption::new(
+                Path::from_filesystem_path(restore_dir.path()).unwrap(),
+                &TestSchema,
+            );
+            engine.restore(info.id, &option).await.unwrap();
+            assert!(matches!(
+                engine.restore(info.id, &option).await,
+                Err(BackupError::NotEmpty(_))
+            ));
+
+            let restored: DB<Test, TokioExecutor> =
+                DB::new(option, TokioExecutor::current(), TestSchema)
+                    .await
+                    .unwrap();
+            for key in ["a", "b", "c"] {
+                assert_eq!(
+                    restored
+                        .get(&key.to_string(), |entry| entry.get().vu32)
+                        .await
+                        .unwrap()
+                        .is_some(),
+                    keys.contains(&key),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decode_metadata() {
+        let info = BackupInfo::decode(
+            "tonbo-backup v1\nid 01JAAAAAAAAAAAAAAAAAAAAAAA\nts 7\ntable \
+             01JAAAAAAAAAAAAAAAAAAAAAAB 1\nwal 01JAAAAAAAAAAAAAAAAAAAAAAC 42\n",
+        )
+        .unwrap();
+        assert_eq!(BackupInfo::decode(&info.encode()).unwrap(), info);
+        assert_eq!(info.tables[0].level, 1);
+        assert_eq!(info.wal_segments[0].size, 42);
+
+        assert!(matches!(
+            BackupInfo::decode("tonbo-backup v1\nid 01JAAAAAAAAAAAAAAAAAAAAAAA\nts 7\nsst x 1\n"),
+            Err(BackupError::Metadata(_))
+        ));
+    }
+}
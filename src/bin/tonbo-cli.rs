@@ -0,0 +1,459 @@
+//! Administer a tonbo [`DB`] from the command line: inspect its manifest and SSTables, dump its
+//! records, verify and repair its files, and flush or compact a live instance through its admin
+//! socket, see [`DB::serve_admin`].
+//!
+//! The records are read as [`DynRecord`]s, their schema is taken from the SSTables of the DB.
+//! The offline commands open the DB read-only, so the commits not flushed into SSTables yet are
+//! not read.
+
+use std::{ops::Bound, path::PathBuf, process::ExitCode, sync::Arc};
+
+use clap::{Parser, Subcommand};
+use fusio::{disk::LocalFs, path::Path, DynFs};
+use futures_util::StreamExt;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
+use tonbo::{
+    arrow::{array::ArrayRef, json::LineDelimitedWriter, util::display::array_value_to_string},
+    executor::tokio::TokioExecutor,
+    magic,
+    record::{Cell, DataType, DynRecord, DynSchema, Value, ValueDesc, F32, F64},
+    DbOption, SsTable, DB,
+};
+
+#[derive(Parser)]
+#[command(name = "tonbo-cli", about = "Administer a tonbo DB", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the levels and SSTables of the manifest of the DB at PATH
+    Manifest { path: PathBuf },
+    /// List the SSTable files in the directory of the DB at PATH, whether in the manifest or not
+    Tables { path: PathBuf },
+    /// Print the schema, key range and column statistics of the SSTable FILE
+    Inspect { file: PathBuf },
+    /// Print the records of the DB at PATH as JSON lines, from FROM to TO included
+    Dump {
+        path: PathBuf,
+        #[arg(long)]
+        from: Option<String>,
+        #[arg(long)]
+        to: Option<String>,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Verify the checksums and key ranges of the SSTables and WAL segments of the DB at PATH
+    Verify { path: PathBuf },
+    /// Rebuild the manifest of the closed DB at PATH from its SSTables
+    Repair { path: PathBuf },
+    /// Flush the memtables of a live DB through its admin socket
+    Flush { socket: PathBuf },
+    /// Flush a live DB and run its compactions due through its admin socket
+    Compact { socket: PathBuf },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(reason) => {
+            eprintln!("error: {reason}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Manifest { path } => manifest(&path).await,
+        Command::Tables { path } => tables(&path).await,
+        Command::Inspect { file } => inspect(&file).await,
+        Command::Dump {
+            path,
+            from,
+            to,
+            limit,
+        } => dump(&path, from, to, limit).await,
+        Command::Verify { path } => verify(&path).await,
+        Command::Repair { path } => repair(&path).await,
+        Command::Flush { socket } => admin(&socket, "flush").await,
+        Command::Compact { socket } => admin(&socket, "compact").await,
+    }
+}
+
+fn fs_path(path: &std::path::Path) -> Result<Path, String> {
+    Path::from_filesystem_path(path).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+/// the SSTable files in the directory `path`
+async fn table_files(fs: &Arc<dyn DynFs>, path: &Path) -> Result<Vec<Path>, String> {
+    let mut files = Vec::new();
+    let mut list = fs.list(path).await.map_err(|err| err.to_string())?;
+    while let Some(meta) = list.next().await {
+        let meta = meta.map_err(|err| err.to_string())?;
+        if meta.path.as_ref().ends_with(".parquet") {
+            files.push(meta.path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// the columns of the records of the DB at `path` and the index of their primary key, taken from
+/// its first SSTable
+async fn infer_schema(fs: &Arc<dyn DynFs>, path: &Path) -> Result<(Vec<ValueDesc>, usize), String> {
+    let file = table_files(fs, path)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("{path}: no SSTable to take the schema of the records from"))?;
+    let inspection = SsTable::inspect(fs, &file)
+        .await
+        .map_err(|err| format!("{file}: {err}"))?;
+    let fields = inspection
+        .schema
+        .fields()
+        .iter()
+        .filter(|field| field.name() != magic::NULL && field.name() != magic::TS)
+        .collect::<Vec<_>>();
+    let mut descs = Vec::with_capacity(fields.len());
+    for field in &fields {
+        let datatype = match field.data_type() {
+            tonbo::arrow::datatypes::DataType::UInt8 => DataType::UInt8,
+            tonbo::arrow::datatypes::DataType::UInt16 => DataType::UInt16,
+            tonbo::arrow::datatypes::DataType::UInt32 => DataType::UInt32,
+            tonbo::arrow::datatypes::DataType::UInt64 => DataType::UInt64,
+            tonbo::arrow::datatypes::DataType::Int8 => DataType::Int8,
+            tonbo::arrow::datatypes::DataType::Int16 => DataType::Int16,
+            tonbo::arrow::datatypes::DataType::Int32 => DataType::Int32,
+            tonbo::arrow::datatypes::DataType::Int64 => DataType::Int64,
+            tonbo::arrow::datatypes::DataType::Float32 => DataType::Float32,
+            tonbo::arrow::datatypes::DataType::Float64 => DataType::Float64,
+            tonbo::arrow::datatypes::DataType::Utf8 => DataType::String,
+            tonbo::arrow::datatypes::DataType::Boolean => DataType::Boolean,
+            tonbo::arrow::datatypes::DataType::Binary => DataType::Bytes,
+            datatype => {
+                return Err(format!(
+                    "{file}: column {} of type {datatype} is not supported",
+                    field.name()
+                ))
+            }
+        };
+        descs.push(ValueDesc::new(
+            field.name().clone(),
+            datatype,
+            field.is_nullable(),
+        ));
+    }
+    let primary_key = inspection
+        .primary_key
+        .ok_or_else(|| format!("{file}: the primary key is not recorded"))?;
+    let primary_index = descs
+        .iter()
+        .position(|desc| desc.name == primary_key)
+        .ok_or_else(|| format!("{file}: no primary key column {primary_key}"))?;
+    Ok((descs, primary_index))
+}
+
+async fn open(path: &std::path::Path) -> Result<DB<DynRecord, TokioExecutor>, String> {
+    let fs: Arc<dyn DynFs> = Arc::new(LocalFs {});
+    let path = fs_path(path)?;
+    let (descs, primary_index) = infer_schema(&fs, &path).await?;
+    let schema = DynSchema::new(descs, primary_index);
+    DB::open_read_only(
+        DbOption::new(path, &schema),
+        TokioExecutor::current(),
+        schema,
+    )
+    .await
+    .map_err(|err| err.to_string())
+}
+
+/// a primary key or a column value as text
+fn show(value: &Value) -> String {
+    macro_rules! show {
+        ($($ty:ty),*) => {
+            $(
+                if let Some(value) = value.value.downcast_ref::<$ty>() {
+                    return format!("{value:?}");
+                }
+                if let Some(value) = value.value.downcast_ref::<Option<$ty>>() {
+                    return value
+                        .as_ref()
+                        .map_or("null".to_string(), |value| format!("{value:?}"));
+                }
+            )*
+        };
+    }
+    show!(
+        u8,
+        u16,
+        u32,
+        u64,
+        i8,
+        i16,
+        i32,
+        i64,
+        F32,
+        F64,
+        String,
+        bool,
+        Vec<u8>
+    );
+    format!("{value:?}")
+}
+
+fn show_array(array: &Option<ArrayRef>) -> String {
+    array
+        .as_ref()
+        .and_then(|array| array_value_to_string(array, 0).ok())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+async fn manifest(path: &std::path::Path) -> Result<(), String> {
+    let db = open(path).await?;
+    let description = db.describe().await.map_err(|err| err.to_string())?;
+    println!("timestamp {}", u32::from(description.ts));
+    for level in &description.levels {
+        if level.tables.is_empty() {
+            continue;
+        }
+        println!(
+            "level {}: {} tables, {} bytes",
+            level.level,
+            level.tables.len(),
+            level.size
+        );
+        for table in &level.tables {
+            println!(
+                "  {} [{}, {}] {} rows, {} tombstones, {} bytes",
+                table.gen,
+                show(&table.min_key),
+                show(&table.max_key),
+                table.rows,
+                table
+                    .tombstones
+                    .map_or("?".to_string(), |tombstones| tombstones.to_string()),
+                table.size
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn tables(path: &std::path::Path) -> Result<(), String> {
+    let fs: Arc<dyn DynFs> = Arc::new(LocalFs {});
+    for file in table_files(&fs, &fs_path(path)?).await? {
+        match SsTable::inspect(&fs, &file).await {
+            Ok(inspection) => println!(
+                "{file} [{}, {}] {} rows, {} tombstones, {} row groups",
+                show_array(&inspection.min_key),
+                show_array(&inspection.max_key),
+                inspection.rows,
+                inspection.tombstones,
+                inspection.row_groups
+            ),
+            Err(err) => println!("{file} unreadable: {err}"),
+        }
+    }
+    Ok(())
+}
+
+async fn inspect(file: &std::path::Path) -> Result<(), String> {
+    let fs: Arc<dyn DynFs> = Arc::new(LocalFs {});
+    let inspection = SsTable::inspect(&fs, &fs_path(file)?)
+        .await
+        .map_err(|err| err.to_string())?;
+    println!(
+        "primary key {}",
+        inspection.primary_key.as_deref().unwrap_or("-")
+    );
+    println!(
+        "key range [{}, {}]",
+        show_array(&inspection.min_key),
+        show_array(&inspection.max_key)
+    );
+    println!(
+        "{} rows, {} tombstones, {} row groups",
+        inspection.rows, inspection.tombstones, inspection.row_groups
+    );
+    if let Some(created_by) = &inspection.created_by {
+        println!("created by {created_by}");
+    }
+    for (field, column) in inspection
+        .schema
+        .fields()
+        .iter()
+        .zip(inspection.columns.iter())
+    {
+        println!(
+            "  {} {}{}: [{}, {}] {} nulls, {} bytes ({} uncompressed)",
+            column.name,
+            field.data_type(),
+            if field.is_nullable() { "?" } else { "" },
+            show_array(&column.min),
+            show_array(&column.max),
+            column
+                .null_count
+                .map_or("?".to_string(), |null_count| null_count.to_string()),
+            column.compressed_size,
+            column.uncompressed_size
+        );
+    }
+    Ok(())
+}
+
+/// the primary key of `desc` parsed from `text`
+fn parse_key(desc: &ValueDesc, text: &str) -> Result<Value, String> {
+    macro_rules! parse {
+        ($ty:ty) => {
+            text.parse::<$ty>()
+                .map_err(|err| format!("invalid key {text:?}: {err}"))?
+        };
+    }
+    let value = match desc.datatype {
+        DataType::UInt8 => Cell::from(parse!(u8)),
+        DataType::UInt16 => Cell::from(parse!(u16)),
+        DataType::UInt32 => Cell::from(parse!(u32)),
+        DataType::UInt64 => Cell::from(parse!(u64)),
+        DataType::Int8 => Cell::from(parse!(i8)),
+        DataType::Int16 => Cell::from(parse!(i16)),
+        DataType::Int32 => Cell::from(parse!(i32)),
+        DataType::Int64 => Cell::from(parse!(i64)),
+        DataType::Float32 => Cell::from(F32::from(parse!(f32))),
+        DataType::Float64 => Cell::from(F64::from(parse!(f64))),
+        DataType::String => Cell::from(text.to_string()),
+        DataType::Boolean => Cell::from(parse!(bool)),
+        DataType::Bytes => Cell::from(text.as_bytes().to_vec()),
+    };
+    Ok(Value::with_cell(desc.name.clone(), value, desc.is_nullable))
+}
+
+async fn dump(
+    path: &std::path::Path,
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<usize>,
+) -> Result<(), String> {
+    let fs: Arc<dyn DynFs> = Arc::new(LocalFs {});
+    let (descs, primary_index) = infer_schema(&fs, &fs_path(path)?).await?;
+    let from = from
+        .map(|from| parse_key(&descs[primary_index], &from))
+        .transpose()?;
+    let to = to
+        .map(|to| parse_key(&descs[primary_index], &to))
+        .transpose()?;
+    let db = open(path).await?;
+
+    let snapshot = db.snapshot().await;
+    let mut scan = snapshot.scan((
+        from.as_ref().map_or(Bound::Unbounded, Bound::Included),
+        to.as_ref().map_or(Bound::Unbounded, Bound::Included),
+    ));
+    if let Some(limit) = limit {
+        scan = scan.limit(limit);
+    }
+    let mut batches = Box::pin(
+        scan.scan_batches(1024)
+            .await
+            .map_err(|err| err.to_string())?,
+    );
+    let mut writer = LineDelimitedWriter::new(std::io::stdout().lock());
+    while let Some(batch) = batches.next().await {
+        let batch = batch.map_err(|err| err.to_string())?;
+        // the records without `_null` and `_ts`
+        let columns = (0..batch.num_columns())
+            .filter(|&index| {
+                let name = batch.schema_ref().field(index).name().clone();
+                name != magic::NULL && name != magic::TS
+            })
+            .collect::<Vec<_>>();
+        let batch = batch.project(&columns).map_err(|err| err.to_string())?;
+        writer.write(&batch).map_err(|err| err.to_string())?;
+    }
+    writer.finish().map_err(|err| err.to_string())
+}
+
+async fn verify(path: &std::path::Path) -> Result<(), String> {
+    let db = open(path).await?;
+    let report = db.verify_integrity().await.map_err(|err| err.to_string())?;
+    println!(
+        "{} tables and {} WAL segments verified",
+        report.tables, report.wal_segments
+    );
+    for corrupted in &report.corrupted {
+        let range = corrupted
+            .range
+            .as_ref()
+            .map_or(String::new(), |(min, max)| {
+                format!(" [{}, {}]", show(min), show(max))
+            });
+        println!("{}{range}: {:?}", corrupted.path, corrupted.corruption);
+    }
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(format!("{} corrupted files", report.corrupted.len()))
+    }
+}
+
+async fn repair(path: &std::path::Path) -> Result<(), String> {
+    let fs: Arc<dyn DynFs> = Arc::new(LocalFs {});
+    let path = fs_path(path)?;
+    let (descs, primary_index) = infer_schema(&fs, &path).await?;
+    let schema = DynSchema::new(descs, primary_index);
+    let option = DbOption::new(path, &schema);
+    let report = DB::<DynRecord, TokioExecutor>::repair(option, schema)
+        .await
+        .map_err(|err| err.to_string())?;
+    println!(
+        "{} tables recovered up to timestamp {}",
+        report.tables(),
+        u32::from(report.ts)
+    );
+    for (level, tables) in report.levels.iter().enumerate() {
+        if *tables > 0 {
+            println!("  level {level}: {tables} tables");
+        }
+    }
+    for (path, reason) in &report.skipped_tables {
+        println!("skipped {path}: {reason}");
+    }
+    for path in &report.flushed_wal_segments {
+        println!("removed flushed WAL segment {path}");
+    }
+    for (path, reason) in &report.corrupted_wal_segments {
+        println!("cut corrupted WAL segment {path}: {reason}");
+    }
+    Ok(())
+}
+
+/// run `command` on the admin socket at `socket` and print its output
+async fn admin(socket: &std::path::Path, command: &str) -> Result<(), String> {
+    let stream = UnixStream::connect(socket)
+        .await
+        .map_err(|err| format!("{}: {err}", socket.display()))?;
+    let (reader, mut writer) = stream.into_split();
+    writer
+        .write_all(format!("{command}\n").as_bytes())
+        .await
+        .map_err(|err| err.to_string())?;
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await.map_err(|err| err.to_string())? {
+        if line == "ok" {
+            return Ok(());
+        }
+        if let Some(reason) = line.strip_prefix("error: ") {
+            return Err(reason.to_string());
+        }
+        println!("{line}");
+    }
+    Err("the admin socket closed the connection".to_string())
+}
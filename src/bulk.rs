@@ -0,0 +1,826 @@
+use std::{mem, sync::Arc};
+
+use arrow::{
+    array::{ArrayRef, BooleanArray, RecordBatch, UInt32Array},
+    datatypes::Schema as ArrowSchema,
+};
+use fusio::{path::Path, DynFs, DynRead};
+use fusio_parquet::{reader::AsyncReader, writer::AsyncWriter};
+use futures_util::StreamExt;
+use parquet::arrow::{
+    async_reader::ParquetRecordBatchStream, ArrowSchemaConverter, AsyncArrowWriter,
+    ParquetRecordBatchStreamBuilder, ProjectionMask,
+};
+
+use crate::{
+    executor::Executor,
+    fs::{generate_file_id, FileId, FileType},
+    inmem::immutable::{ArrowArrays, Builder},
+    magic::{self, USER_COLUMN_OFFSET},
+    record::{KeyRef, Record, RecordRef, Schema},
+    scope::Scope,
+    timestamp::{Timestamp, Ts, EPOCH},
+    transaction::CommitError,
+    version::{edit::VersionEdit, MAX_LEVEL},
+    DbError, DbOption, DB,
+};
+
+/// the level bulk loaded tables are installed in
+const BULK_LOAD_LEVEL: usize = MAX_LEVEL - 1;
+
+/// Loads sorted records straight into SSTables, open with [`DB::bulk_load_session`].
+///
+/// Records bypass the WAL and the memtables, they are written to tables of the last level and
+/// become visible together when [`BulkLoadSession::finish`] installs the tables in a single
+/// version edit. Major compactions are paused until every session is finished or dropped. The
+/// tables of a session dropped before it is finished are removed.
+///
+/// The loaded key range must not overlap any data of the [`DB`] that is older than the session,
+/// writes made while the session is open are newer than the loaded records and take precedence.
+pub struct BulkLoadSession<'db, R, E>
+where
+    R: Record,
+    E: Executor,
+{
+    db: &'db DB<R, E>,
+    option: Arc<DbOption>,
+    ts: Timestamp,
+    builder: <<R::Schema as Schema>::Columns as ArrowArrays>::Builder,
+    min: Option<<R::Schema as Schema>::Key>,
+    max: Option<<R::Schema as Schema>::Key>,
+    scopes: Vec<Scope<<R::Schema as Schema>::Key>>,
+    /// every table opened and not installed or discarded yet, including the one being written
+    tables: Vec<FileId>,
+}
+
+impl<'db, R, E> BulkLoadSession<'db, R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// append `record`, its primary key must be greater than the one of the previous record
+    pub async fn write(&mut self, record: R) -> Result<(), CommitError<R>> {
+        let key = record.key().to_key();
+        self.check_order(&key)?;
+
+        if self.builder.written_size() >= self.option.max_sst_file_size {
+            self.build_table().await?;
+        }
+        self.builder
+            .push(Ts::new(record.key(), self.ts), Some(record.as_record_ref()));
+        if self.min.is_none() {
+            self.min = Some(key.clone());
+        }
+        self.max = Some(key);
+
+        Ok(())
+    }
+
+    /// write the remaining records and install every table of the session atomically.
+    ///
+    /// # Error
+    /// Returns [`DbError::BulkLoadOverlap`] and removes the written tables if the loaded range
+    /// overlaps a table that was added to the [`DB`] in the meantime.
+    pub async fn finish(mut self) -> Result<(), CommitError<R>> {
+        if self.builder.written_size() > 0 {
+            self.build_table().await?;
+        }
+        let scopes = mem::take(&mut self.scopes);
+        let (Some(first), Some(last)) = (scopes.first(), scopes.last()) else {
+            return Ok(());
+        };
+        // keep compactions from installing versions until the tables are added
+        let db = self.db;
+        let _guard = db.schema.upgradable_read().await;
+        let version = self.db.ctx.version_set.current().await;
+
+        let is_overlapped = version
+            .level_slice
+            .iter()
+            .flatten()
+            .any(|scope| scope.min <= last.max && first.min <= scope.max);
+        if is_overlapped {
+            self.discard(scopes.into_iter().map(|scope| scope.gen))
+                .await?;
+            return Err(DbError::BulkLoadOverlap.into());
+        }
+        let mut version_edits = scopes
+            .into_iter()
+            .map(|scope| VersionEdit::Add {
+                level: BULK_LOAD_LEVEL as u8,
+                scope,
+            })
+            .collect::<Vec<_>>();
+        version_edits.push(VersionEdit::LatestTimeStamp {
+            ts: self.db.ctx.load_ts(),
+        });
+        self.db
+            .ctx
+            .version_set
+            .apply_edits(version_edits, None, false)
+            .await
+            .map_err(DbError::Version)?;
+        self.db.ctx.remove_bulk_tables(&mem::take(&mut self.tables));
+
+        Ok(())
+    }
+
+    fn level_fs(&self) -> &Arc<dyn DynFs> {
+        let level_path = self
+            .option
+            .level_fs_path(BULK_LOAD_LEVEL)
+            .unwrap_or(&self.option.base_path);
+        self.db.ctx.manager.get_fs(level_path)
+    }
+
+    /// keys must be strictly greater than every key loaded before
+    fn check_order(&self, key: &<R::Schema as Schema>::Key) -> Result<(), DbError<R>> {
+        let last = self
+            .max
+            .as_ref()
+            .or_else(|| self.scopes.last().map(|scope| &scope.max));
+        if last.is_some_and(|last| last >= key) {
+            return Err(DbError::UnsortedBulkLoad);
+        }
+        Ok(())
+    }
+
+    /// remove the tables `gens`, which were not installed
+    async fn discard(&mut self, gens: impl IntoIterator<Item = FileId>) -> Result<(), DbError<R>> {
+        for gen in gens {
+            self.level_fs()
+                .remove(&self.option.table_path(gen, BULK_LOAD_LEVEL))
+                .await?;
+            self.tables.retain(|table| *table != gen);
+            self.db.ctx.remove_bulk_tables(&[gen]);
+        }
+        Ok(())
+    }
+
+    async fn table_writer(
+        &mut self,
+        gen: FileId,
+    ) -> Result<AsyncArrowWriter<AsyncWriter>, DbError<R>> {
+        // registered before the file is created, so that garbage collections keep it
+        self.tables.push(gen);
+        self.db.ctx.add_bulk_table(gen);
+        Ok(AsyncArrowWriter::try_new(
+            AsyncWriter::new(
+                self.level_fs()
+                    .open_options(
+                        &self.option.table_path(gen, BULK_LOAD_LEVEL),
+                        FileType::Parquet.open_options(false),
+                    )
+                    .await?,
+            ),
+            self.db.ctx.arrow_schema().clone(),
+            Some(self.option.table_properties(BULK_LOAD_LEVEL)?),
+        )?)
+    }
+
+    /// close the table `gen`, which holds the records from `self.min` to `self.max`
+    async fn finish_table(
+        &mut self,
+        gen: FileId,
+        writer: AsyncArrowWriter<AsyncWriter>,
+    ) -> Result<(), DbError<R>> {
+        writer.close().await?;
+
+        // SAFETY: a table is only finished after a record was written
+        self.scopes.push(Scope {
+            min: self.min.take().unwrap(),
+            max: self.max.take().unwrap(),
+            gen,
+            wal_ids: None,
+            stats: None,
+        });
+        Ok(())
+    }
+
+    async fn build_table(&mut self) -> Result<(), DbError<R>> {
+        let gen = generate_file_id();
+        let columns = self.builder.finish(None);
+        let mut writer = self.table_writer(gen).await?;
+        writer.write(columns.as_record_batch()).await?;
+        self.finish_table(gen, writer).await
+    }
+
+    /// copy the rows of the Parquet file at `path` of `fs` into tables of the session, batch by
+    /// batch
+    async fn ingest(
+        &mut self,
+        fs: &Arc<dyn DynFs>,
+        path: &Path,
+        primary_key_index: usize,
+    ) -> Result<(), DbError<R>> {
+        let file = fs
+            .open_options(path, FileType::Parquet.open_options(true))
+            .await?;
+        let size = file.size().await?;
+        let builder =
+            ParquetRecordBatchStreamBuilder::new(AsyncReader::new(file, size).await?).await?;
+        let full_schema = self.db.ctx.arrow_schema().clone();
+        let has_meta = check_ingest_schema(&full_schema, builder.schema())
+            .map_err(|reason| DbError::InvalidIngest(format!("{path}: {reason}")))?;
+        let key_mask = ProjectionMask::roots(
+            &ArrowSchemaConverter::new().convert(&full_schema)?,
+            [0, 1, primary_key_index],
+        );
+
+        let mut table = None;
+        let result = self
+            .copy(builder.build()?, path, has_meta, &key_mask, &mut table)
+            .await;
+        match (table, result) {
+            (Some((gen, writer)), Ok(())) => self.finish_table(gen, writer).await,
+            (Some((gen, writer)), Err(err)) => {
+                drop(writer);
+                self.discard([gen]).await?;
+                Err(err)
+            }
+            (None, result) => result,
+        }
+    }
+
+    /// write the batches of `stream` to tables, `table` is the table being written if any. The
+    /// `_ts` of batches holding `_null` and `_ts` is replaced as well.
+    async fn copy(
+        &mut self,
+        mut stream: ParquetRecordBatchStream<AsyncReader>,
+        path: &Path,
+        has_meta: bool,
+        key_mask: &ProjectionMask,
+        table: &mut Option<(FileId, AsyncArrowWriter<AsyncWriter>)>,
+    ) -> Result<(), DbError<R>> {
+        let full_schema = self.db.ctx.arrow_schema().clone();
+        while let Some(batch) = stream.next().await.transpose()? {
+            let num_rows = batch.num_rows();
+            if num_rows == 0 {
+                continue;
+            }
+            let (null, user_columns) = if has_meta {
+                (
+                    batch.column(0).clone(),
+                    &batch.columns()[USER_COLUMN_OFFSET..],
+                )
+            } else {
+                (
+                    Arc::new(BooleanArray::from(vec![false; num_rows])) as ArrayRef,
+                    batch.columns(),
+                )
+            };
+            let columns = [
+                null,
+                Arc::new(UInt32Array::from(vec![u32::from(self.ts); num_rows])) as ArrayRef,
+            ]
+            .into_iter()
+            .chain(user_columns.iter().cloned())
+            .collect::<Vec<_>>();
+            // also rejects nulls in the columns that are not nullable
+            let batch = RecordBatch::try_new(full_schema.clone(), columns)
+                .map_err(|err| DbError::InvalidIngest(format!("{path}: {err}")))?;
+
+            for offset in 0..num_rows {
+                let key = R::Ref::from_record_batch(&batch, offset, key_mask, &full_schema)
+                    .key()
+                    .value
+                    .to_key();
+                self.check_order(&key)?;
+                if self.min.is_none() {
+                    self.min = Some(key.clone());
+                }
+                self.max = Some(key);
+            }
+
+            if table.is_none() {
+                let gen = generate_file_id();
+                *table = Some((gen, self.table_writer(gen).await?));
+            }
+            // SAFETY: the table is opened above
+            let (_, writer) = table.as_mut().unwrap();
+            writer.write(&batch).await?;
+            if writer.bytes_written() + writer.in_progress_size() >= self.option.max_sst_file_size {
+                let (gen, writer) = table.take().unwrap();
+                self.finish_table(gen, writer).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// ingested files hold the columns of `full_schema` in the same order, with or without `_null`
+/// and `_ts`. Returns whether the file holds `_null` and `_ts`, as tables of [`SsTableWriter`].
+fn check_ingest_schema(full_schema: &ArrowSchema, schema: &ArrowSchema) -> Result<bool, String> {
+    let has_meta = schema
+        .fields()
+        .first()
+        .is_some_and(|field| field.name() == magic::NULL);
+    let expected = if has_meta {
+        &full_schema.fields()[..]
+    } else {
+        &full_schema.fields()[USER_COLUMN_OFFSET..]
+    };
+    if schema.fields().len() != expected.len() {
+        return Err(format!(
+            "expected {} columns, found {}",
+            expected.len(),
+            schema.fields().len()
+        ));
+    }
+    for (expected, field) in expected.iter().zip(schema.fields()) {
+        if expected.name() != field.name() || expected.data_type() != field.data_type() {
+            return Err(format!(
+                "expected column {} of type {}, found {} of type {}",
+                expected.name(),
+                expected.data_type(),
+                field.name(),
+                field.data_type()
+            ));
+        }
+    }
+    Ok(has_meta)
+}
+
+/// Builds a table in the format of the SSTables of a [`DB`] without opening it, for preparing
+/// bulk loads away from the serving instance.
+///
+/// Records must be written in strictly ascending primary key order. The finished file can then be
+/// loaded with [`DB::ingest_parquet`], which gives its records the timestamp of the ingestion.
+pub struct SsTableWriter<R>
+where
+    R: Record,
+{
+    writer: AsyncArrowWriter<AsyncWriter>,
+    builder: <<R::Schema as Schema>::Columns as ArrowArrays>::Builder,
+    max_batch_size: usize,
+    last: Option<<R::Schema as Schema>::Key>,
+    len: usize,
+}
+
+impl<R> SsTableWriter<R>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+{
+    /// create the table at `path` of `fs`, written with the Parquet settings of `option` for the
+    /// last level, which [`DB::ingest_parquet`] loads it into
+    pub async fn new(
+        fs: &dyn DynFs,
+        path: &Path,
+        schema: &R::Schema,
+        option: &DbOption,
+    ) -> Result<Self, DbError<R>> {
+        let writer = AsyncArrowWriter::try_new(
+            AsyncWriter::new(
+                fs.open_options(path, FileType::Parquet.open_options(false))
+                    .await?,
+            ),
+            schema.arrow_schema().clone(),
+            Some(option.parquet_properties(BULK_LOAD_LEVEL)),
+        )?;
+
+        Ok(Self {
+            writer,
+            builder: <R::Schema as Schema>::Columns::builder(schema.arrow_schema().clone(), 8192),
+            max_batch_size: 8 * 1024 * 1024,
+            last: None,
+            len: 0,
+        })
+    }
+
+    /// append `record`, its primary key must be greater than the one of the previous record
+    pub async fn write(&mut self, record: R) -> Result<(), DbError<R>> {
+        let key = record.key().to_key();
+        if self.last.as_ref().is_some_and(|last| last >= &key) {
+            return Err(DbError::UnsortedBulkLoad);
+        }
+
+        if self.builder.written_size() >= self.max_batch_size {
+            self.write_batch().await?;
+        }
+        self.builder
+            .push(Ts::new(record.key(), EPOCH), Some(record.as_record_ref()));
+        self.last = Some(key);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// number of records written
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// write the remaining records and the footer of the table
+    pub async fn finish(mut self) -> Result<(), DbError<R>> {
+        if self.builder.written_size() > 0 {
+            self.write_batch().await?;
+        }
+        self.writer.close().await?;
+
+        Ok(())
+    }
+
+    async fn write_batch(&mut self) -> Result<(), DbError<R>> {
+        let columns = self.builder.finish(None);
+        self.writer.write(columns.as_record_batch()).await?;
+
+        Ok(())
+    }
+}
+
+impl<R, E> Drop for BulkLoadSession<'_, R, E>
+where
+    R: Record,
+    E: Executor,
+{
+    fn drop(&mut self) {
+        // the session is dropped before it is finished, or the tables failed to be installed.
+        // Tables the cleaner can not take now are left to `DB::collect_garbage`
+        for gen in self.tables.iter() {
+            let _ = self.db.ctx.version_set.try_clean(*gen, BULK_LOAD_LEVEL);
+        }
+        self.db.ctx.remove_bulk_tables(&self.tables);
+        self.db.ctx.finish_bulk_load();
+    }
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Open a [`BulkLoadSession`] for loading large amounts of sorted records.
+    ///
+    /// The memtables are flushed first, so the session only needs to check the loaded range
+    /// against SSTables when it is finished.
+    pub async fn bulk_load_session(&self) -> Result<BulkLoadSession<'_, R, E>, CommitError<R>> {
+        self.flush().await?;
+        self.ctx.start_bulk_load();
+        let option = self.schema.read().await.option.clone();
+
+        Ok(BulkLoadSession {
+            db: self,
+            ts: self.ctx.increase_ts(),
+            builder: <R::Schema as Schema>::Columns::builder(self.ctx.arrow_schema().clone(), 8192),
+            option,
+            min: None,
+            max: None,
+            scopes: Vec::new(),
+            tables: Vec::new(),
+        })
+    }
+
+    /// Load the Parquet files at `paths` of the base fs into SSTables of the last level, skipping
+    /// the WAL and the memtables.
+    ///
+    /// Files hold the columns of the schema, either without `_null` and `_ts` or with them as
+    /// the tables of a [`SsTableWriter`]. Their rows are sorted by strictly ascending primary key
+    /// across all files, so files must be given in key order.
+    /// Rows are copied batch by batch into tables installed together in a single version edit,
+    /// the files themselves are left untouched. Like a [`BulkLoadSession`], the loaded range
+    /// must not overlap the data of the [`DB`].
+    ///
+    /// # Error
+    /// Returns [`DbError::InvalidIngest`] if the columns of a file do not match the schema,
+    /// [`DbError::UnsortedBulkLoad`] if keys are not ascending and
+    /// [`DbError::BulkLoadOverlap`] if the files overlap the data of the [`DB`]. No table is
+    /// installed on errors.
+    pub async fn ingest_parquet(
+        &self,
+        paths: impl IntoIterator<Item = Path>,
+    ) -> Result<(), CommitError<R>> {
+        let primary_key_index = self.schema.read().await.record_schema.primary_key_index();
+        let fs = self.ctx.manager.base_fs().clone();
+        let mut session = self.bulk_load_session().await?;
+        for path in paths {
+            if let Err(err) = session.ingest(&fs, &path, primary_key_index).await {
+                let scopes = mem::take(&mut session.scopes);
+                session
+                    .discard(scopes.into_iter().map(|scope| scope.gen))
+                    .await?;
+                return Err(err.into());
+            }
+        }
+        session.finish().await
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use arrow::{
+        array::{ArrayRef, BooleanArray, RecordBatch, StringArray, UInt32Array},
+        datatypes::Schema as ArrowSchema,
+    };
+    use fusio::{
+        disk::LocalFs,
+        path::{path_to_local, Path},
+    };
+    use parquet::arrow::ArrowWriter;
+    use tempfile::TempDir;
+
+    use super::{SsTableWriter, BULK_LOAD_LEVEL};
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, record::Schema,
+        tests::Test, transaction::CommitError, DbError, DbOption, DB,
+    };
+
+    /// write the keys `keys` to an external Parquet file holding the columns of [`Test`]
+    fn external_file(dir: &TempDir, name: &str, keys: impl Iterator<Item = u32>) -> Path {
+        let full_schema = TestSchema.arrow_schema();
+        let schema = Arc::new(ArrowSchema::new(full_schema.fields()[2..].to_vec()));
+        let keys = keys.collect::<Vec<_>>();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from_iter_values(
+                    keys.iter().map(|i| format!("k{i:03}")),
+                )) as ArrayRef,
+                Arc::new(UInt32Array::from(keys.clone())),
+                Arc::new(BooleanArray::from(vec![None; keys.len()])),
+            ],
+        )
+        .unwrap();
+
+        let path = dir.path().join(name);
+        let mut writer =
+            ArrowWriter::try_new(std::fs::File::create(&path).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        Path::from_filesystem_path(path).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ingest_parquet() {
+        let temp_dir = TempDir::new().unwrap();
+        let external_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        let first = external_file(&external_dir, "first.parquet", 0..50);
+        let second = external_file(&external_dir, "second.parquet", 50..100);
+        // files must be given in key order
+        assert!(matches!(
+            db.ingest_parquet([second.clone(), first.clone()]).await,
+            Err(CommitError::Database(DbError::UnsortedBulkLoad))
+        ));
+        let version = db.ctx.version_set.current().await;
+        assert!(version.level_slice[BULK_LOAD_LEVEL].is_empty());
+        drop(version);
+
+        db.ingest_parquet([first.clone(), second]).await.unwrap();
+        for i in [0, 49, 50, 99] {
+            let vu32 = db.get(&format!("k{i:03}"), |e| e.get().vu32).await.unwrap();
+            assert_eq!(vu32, Some(i));
+        }
+        // newer writes take precedence over ingested rows
+        db.insert(Test {
+            vstring: "k042".to_string(),
+            vu32: 0,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        let vu32 = db.get(&"k042".to_string(), |e| e.get().vu32).await.unwrap();
+        assert_eq!(vu32, Some(0));
+
+        assert!(matches!(
+            db.ingest_parquet([first]).await,
+            Err(CommitError::Database(DbError::BulkLoadOverlap))
+        ));
+
+        let path = external_dir.path().join("mismatched.parquet");
+        let schema = Arc::new(ArrowSchema::new(
+            TestSchema.arrow_schema().fields()[3..].to_vec(),
+        ));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(UInt32Array::from(vec![1])) as ArrayRef,
+                Arc::new(BooleanArray::from(vec![None])),
+            ],
+        )
+        .unwrap();
+        let mut writer =
+            ArrowWriter::try_new(std::fs::File::create(&path).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        assert!(matches!(
+            db.ingest_parquet([Path::from_filesystem_path(path).unwrap()])
+                .await,
+            Err(CommitError::Database(DbError::InvalidIngest(_)))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bulk_load_session() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .max_sst_file_size(64);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        db.insert(Test {
+            vstring: "a".to_string(),
+            vu32: 0,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+
+        let mut session = db.bulk_load_session().await.unwrap();
+        assert!(db.ctx.is_bulk_loading());
+        for i in 0..100 {
+            session
+                .write(Test {
+                    vstring: format!("k{i:03}"),
+                    vu32: i,
+                    vbool: Some(true),
+                })
+                .await
+                .unwrap();
+        }
+        assert!(matches!(
+            session
+                .write(Test {
+                    vstring: "k050".to_string(),
+                    vu32: 0,
+                    vbool: None,
+                })
+                .await,
+            Err(CommitError::Database(DbError::UnsortedBulkLoad))
+        ));
+        // nothing is visible before the session is finished
+        assert_eq!(
+            db.get(&"k000".to_string(), |e| e.get().vu32).await.unwrap(),
+            None
+        );
+        session.finish().await.unwrap();
+        assert!(!db.ctx.is_bulk_loading());
+
+        let version = db.ctx.version_set.current().await;
+        assert!(version.level_slice[BULK_LOAD_LEVEL].len() > 1);
+        drop(version);
+        for i in [0, 42, 99] {
+            let vu32 = db.get(&format!("k{i:03}"), |e| e.get().vu32).await.unwrap();
+            assert_eq!(vu32, Some(i));
+        }
+        let vu32 = db.get(&"a".to_string(), |e| e.get().vu32).await.unwrap();
+        assert_eq!(vu32, Some(0));
+
+        let mut session = db.bulk_load_session().await.unwrap();
+        session
+            .write(Test {
+                vstring: "k100".to_string(),
+                vu32: 100,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        // newer writes of other ranges do not conflict with the session
+        db.insert(Test {
+            vstring: "z".to_string(),
+            vu32: 0,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+        session.finish().await.unwrap();
+        let vu32 = db.get(&"k100".to_string(), |e| e.get().vu32).await.unwrap();
+        assert_eq!(vu32, Some(100));
+
+        let mut session = db.bulk_load_session().await.unwrap();
+        session
+            .write(Test {
+                vstring: "k042".to_string(),
+                vu32: 0,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        assert!(matches!(
+            session.finish().await,
+            Err(CommitError::Database(DbError::BulkLoadOverlap))
+        ));
+        let vu32 = db.get(&"k042".to_string(), |e| e.get().vu32).await.unwrap();
+        assert_eq!(vu32, Some(42));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ss_table_writer() {
+        let temp_dir = TempDir::new().unwrap();
+        let external_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+
+        let path = Path::from_filesystem_path(external_dir.path().join("table.parquet")).unwrap();
+        let mut writer = SsTableWriter::<Test>::new(&LocalFs {}, &path, &TestSchema, &option)
+            .await
+            .unwrap();
+        for i in 0..100 {
+            writer
+                .write(Test {
+                    vstring: format!("k{i:03}"),
+                    vu32: i,
+                    vbool: Some(i % 2 == 0),
+                })
+                .await
+                .unwrap();
+        }
+        assert!(matches!(
+            writer
+                .write(Test {
+                    vstring: "k000".to_string(),
+                    vu32: 0,
+                    vbool: None,
+                })
+                .await,
+            Err(DbError::UnsortedBulkLoad)
+        ));
+        assert_eq!(writer.len(), 100);
+        writer.finish().await.unwrap();
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        db.ingest_parquet([path]).await.unwrap();
+        for i in [0, 51, 99] {
+            let row = db
+                .get(&format!("k{i:03}"), |e| {
+                    let record = e.get();
+                    Some((record.vu32, record.vbool))
+                })
+                .await
+                .unwrap();
+            assert_eq!(row, Some((Some(i), Some(i % 2 == 0))));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn drop_bulk_load_session() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .max_sst_file_size(64)
+        .gc_grace(Duration::ZERO);
+        let db: DB<Test, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+
+        let mut session = db.bulk_load_session().await.unwrap();
+        for i in 0..100 {
+            session
+                .write(Test {
+                    vstring: format!("k{i:03}"),
+                    vu32: i,
+                    vbool: None,
+                })
+                .await
+                .unwrap();
+        }
+        let paths = session
+            .tables
+            .iter()
+            .map(|gen| path_to_local(&option.table_path(*gen, BULK_LOAD_LEVEL)).unwrap())
+            .collect::<Vec<_>>();
+        assert!(!paths.is_empty());
+        // the tables of an open session are not garbage
+        assert!(db.collect_garbage().await.unwrap().tables.is_empty());
+        assert!(paths.iter().all(|path| path.exists()));
+
+        drop(session);
+        assert!(!db.ctx.is_bulk_loading());
+        for _ in 0..100 {
+            if !paths.iter().any(|path| path.exists()) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!paths.iter().any(|path| path.exists()));
+        assert!(db.ctx.bulk_tables().is_empty());
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bytes::Bytes;
+use futures_core::future::BoxFuture;
+use parquet::{
+    arrow::{arrow_reader::ArrowReaderOptions, async_reader::AsyncFileReader},
+    errors::Result as ParquetResult,
+    file::metadata::ParquetMetaData,
+};
+use parquet_lru::{BoxedFileReader, DynLruCache};
+
+use super::Lru;
+use crate::{fs::FileId, ParquetLru};
+
+/// Statistics of the block cache of a [`DB`](crate::DB), see
+/// [`DbOption::block_cache`](crate::DbOption::block_cache).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockCacheStats {
+    /// lookups served by the cache
+    pub hits: u64,
+    /// lookups read from the tables
+    pub misses: u64,
+    /// blocks evicted to keep the cache within its capacity
+    pub evictions: u64,
+    pub evicted_bytes: u64,
+    /// number of blocks in the cache
+    pub blocks: usize,
+    /// bytes of the blocks in the cache
+    pub size: u64,
+    pub capacity: u64,
+}
+
+impl BlockCacheStats {
+    /// share of the lookups served by the cache, `0.0` before the first lookup
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+/// a cached block: the table and the byte range of the block
+type BlockKey = (FileId, Range<u64>);
+
+/// Column chunks of the SSTables shared by every scan of a [`DB`](crate::DB), the least recently
+/// read blocks are evicted once the blocks exceed the capacity in bytes. The parsed footers are
+/// cached by the [`MetadataCache`](super::metadata::MetadataCache).
+pub(crate) struct BlockCache {
+    blocks: Mutex<Lru<BlockKey, Bytes>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    evicted_bytes: AtomicU64,
+}
+
+impl BlockCache {
+    pub(crate) fn new(capacity: u64) -> Self {
+        Self {
+            blocks: Mutex::new(Lru::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            evicted_bytes: AtomicU64::new(0),
+        }
+    }
+
+    fn get(&self, key: &BlockKey) -> Option<Bytes> {
+        let block = self.blocks.lock().unwrap().get(key).cloned();
+        match block {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        block
+    }
+
+    fn insert(&self, key: BlockKey, bytes: Bytes) {
+        let size = bytes.len() as u64;
+        let evicted = {
+            let mut blocks = self.blocks.lock().unwrap();
+            if size > blocks.capacity() {
+                return;
+            }
+            blocks.insert(key, size, bytes)
+        };
+        self.evictions
+            .fetch_add(evicted.len() as u64, Ordering::Relaxed);
+        self.evicted_bytes.fetch_add(
+            evicted.iter().map(|(_, size)| size).sum(),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub(crate) fn stats(&self) -> BlockCacheStats {
+        let blocks = self.blocks.lock().unwrap();
+        BlockCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            evicted_bytes: self.evicted_bytes.load(Ordering::Relaxed),
+            blocks: blocks.len(),
+            size: blocks.size(),
+            capacity: blocks.capacity(),
+        }
+    }
+}
+
+/// the cache SSTables are opened with, which reads their blocks from `block_cache` before
+/// `parquet_lru` reads them
+pub(crate) fn cache(parquet_lru: &ParquetLru, block_cache: Arc<BlockCache>) -> ParquetLru {
+    Arc::new(BlockCachedLru {
+        inner: parquet_lru.clone(),
+        block_cache,
+    })
+}
+
+struct BlockCachedLru {
+    inner: ParquetLru,
+    block_cache: Arc<BlockCache>,
+}
+
+impl DynLruCache<FileId> for BlockCachedLru {
+    fn get_reader(&self, key: FileId, reader: BoxedFileReader) -> BoxFuture<'_, BoxedFileReader> {
+        Box::pin(async move {
+            BoxedFileReader::new(BlockCachedReader {
+                inner: self.inner.get_reader(key, reader).await,
+                gen: key,
+                block_cache: self.block_cache.clone(),
+            })
+        })
+    }
+}
+
+struct BlockCachedReader {
+    inner: BoxedFileReader,
+    gen: FileId,
+    block_cache: Arc<BlockCache>,
+}
+
+impl AsyncFileReader for BlockCachedReader {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        Box::pin(async move {
+            let key = (self.gen, range.clone());
+            if let Some(bytes) = self.block_cache.get(&key) {
+                return Ok(bytes);
+            }
+            let bytes = self.inner.get_bytes(range).await?;
+            self.block_cache.insert(key, bytes.clone());
+            Ok(bytes)
+        })
+    }
+
+    fn get_metadata<'s>(
+        &'s mut self,
+        options: Option<&'s ArrowReaderOptions>,
+    ) -> BoxFuture<'s, ParquetResult<Arc<ParquetMetaData>>> {
+        self.inner.get_metadata(options)
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        Box::pin(async move {
+            let mut blocks = Vec::with_capacity(ranges.len());
+            let mut missed = Vec::new();
+            for range in ranges {
+                let block = self.block_cache.get(&(self.gen, range.clone()));
+                if block.is_none() {
+                    missed.push(range);
+                }
+                blocks.push(block);
+            }
+            if missed.is_empty() {
+                return Ok(blocks.into_iter().flatten().collect());
+            }
+
+            let fetched = self.inner.get_byte_ranges(missed.clone()).await?;
+            for (range, bytes) in missed.into_iter().zip(fetched.iter()) {
+                self.block_cache.insert((self.gen, range), bytes.clone());
+            }
+            let mut fetched = fetched.into_iter();
+            // SAFETY: a block is fetched for every missed block
+            Ok(blocks
+                .into_iter()
+                .map(|block| block.unwrap_or_else(|| fetched.next().unwrap()))
+                .collect())
+        })
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use bytes::Bytes;
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::BlockCache;
+    use crate::{
+        executor::tokio::TokioExecutor, fs::generate_file_id, inmem::immutable::tests::TestSchema,
+        tests::Test, DbOption, DB,
+    };
+
+    #[test]
+    fn evict_least_recently_read() {
+        let cache = BlockCache::new(20);
+        let gen = generate_file_id();
+        let block = |start: u64| ((gen, start..start + 8), Bytes::from(vec![start as u8; 8]));
+        let (first, first_block) = block(0);
+        let (second, second_block) = block(8);
+        let (third, third_block) = block(16);
+        cache.insert(first.clone(), first_block);
+        cache.insert(second.clone(), second_block);
+        // the first block is read last, so the second one is evicted for the third one
+        assert!(cache.get(&first).is_some());
+        cache.insert(third.clone(), third_block);
+
+        assert!(cache.get(&first).is_some());
+        assert!(cache.get(&second).is_none());
+        assert!(cache.get(&third).is_some());
+
+        // blocks larger than the cache are not cached
+        let large = (gen, 24..45);
+        cache.insert(large.clone(), Bytes::from(vec![0; 21]));
+        assert!(cache.get(&large).is_none());
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 3);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.evicted_bytes, 8);
+        assert_eq!(stats.blocks, 2);
+        assert_eq!(stats.size, 16);
+        assert_eq!(stats.capacity, 20);
+        assert_eq!(stats.hit_rate(), 0.6);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_through() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .block_cache(1024 * 1024);
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        db.insert(Test {
+            vstring: "key".to_string(),
+            vu32: 1,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+
+        let mut stats = db.block_cache_stats().unwrap();
+        for _ in 0..2 {
+            assert_eq!(
+                db.get(&"key".to_string(), |entry| entry.get().vu32)
+                    .await
+                    .unwrap(),
+                Some(1)
+            );
+            let last = stats;
+            stats = db.block_cache_stats().unwrap();
+            assert!(stats.hits + stats.misses > last.hits + last.misses);
+        }
+        // the second read is served by the blocks the first one cached
+        assert!(stats.hits > 0);
+        assert!(stats.blocks > 0);
+        assert!(stats.size <= stats.capacity);
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use fusio::{disk::LocalFs, path::Path, DynFs, Read, Write};
+use futures_core::future::BoxFuture;
+use futures_util::StreamExt;
+use parquet::{
+    arrow::{arrow_reader::ArrowReaderOptions, async_reader::AsyncFileReader},
+    errors::Result as ParquetResult,
+    file::metadata::ParquetMetaData,
+};
+use parquet_lru::{BoxedFileReader, DynLruCache};
+
+use super::Lru;
+use crate::{
+    fs::{FileId, FileType},
+    ParquetLru,
+};
+
+/// a cached page: the table and the byte range of the page
+type PageKey = (FileId, Range<u64>);
+
+/// Pages of the SSTables cached in the files of a local directory, see
+/// [`DbOption::disk_cache`](crate::DbOption::disk_cache).
+///
+/// The index of the pages is kept in memory, the least recently read pages are evicted once the
+/// pages exceed the capacity.
+pub(crate) struct DiskCache {
+    fs: Arc<dyn DynFs>,
+    dir: Path,
+    pages: Mutex<Lru<PageKey, ()>>,
+}
+
+impl DiskCache {
+    /// open the cache in the directory `dir` of the local disk, the pages cached there before
+    /// are removed as they are not indexed. The other files of `dir` are left alone.
+    pub(crate) async fn new(dir: Path, capacity: u64) -> Result<Self, fusio::Error> {
+        let fs: Arc<dyn DynFs> = Arc::new(LocalFs {});
+        fs.create_dir_all(&dir).await?;
+        let mut stale = Vec::new();
+        let mut files = fs.list(&dir).await?;
+        while let Some(file) = files.next().await {
+            let path = file?.path;
+            if path.filename().and_then(parse_page).is_some() {
+                stale.push(path);
+            }
+        }
+        drop(files);
+        for path in stale {
+            fs.remove(&path).await?;
+        }
+
+        Ok(Self {
+            fs,
+            dir,
+            pages: Mutex::new(Lru::new(capacity)),
+        })
+    }
+
+    fn page_path(&self, (gen, range): &PageKey) -> Path {
+        self.dir
+            .child(format!("{}-{}-{}", gen, range.start, range.end))
+    }
+
+    async fn get(&self, key: &PageKey) -> Option<Bytes> {
+        if self.pages.lock().unwrap().get(key).is_none() {
+            return None;
+        }
+        let read = async {
+            let mut file = self
+                .fs
+                .open_options(&self.page_path(key), FileType::Parquet.open_options(true))
+                .await?;
+            let (result, bytes) = file.read_to_end_at(Vec::new(), 0).await;
+            result.map(|_| bytes)
+        };
+        // the page may be evicted while it is read
+        match read.await {
+            Ok(bytes) if bytes.len() as u64 == key.1.end - key.1.start => Some(Bytes::from(bytes)),
+            _ => None,
+        }
+    }
+
+    async fn insert(&self, key: PageKey, bytes: &Bytes) {
+        let size = bytes.len() as u64;
+        {
+            let pages = self.pages.lock().unwrap();
+            if size > pages.capacity() || pages.contains(&key) {
+                return;
+            }
+        }
+        let write = async {
+            let mut file = self
+                .fs
+                .open_options(&self.page_path(&key), FileType::Parquet.open_options(false))
+                .await?;
+            let (result, _) = file.write_all(bytes.to_vec()).await;
+            result?;
+            file.close().await
+        };
+        // a page that can't be written is read from its table again
+        if write.await.is_err() {
+            return;
+        }
+
+        let evicted = self.pages.lock().unwrap().insert(key, size, ());
+        for (key, _) in evicted {
+            let _ = self.fs.remove(&self.page_path(&key)).await;
+        }
+    }
+}
+
+/// the key of the page cached in the file `name`, see [`DiskCache::page_path`]
+fn parse_page(name: &str) -> Option<PageKey> {
+    let mut parts = name.rsplitn(3, '-');
+    let end = parts.next()?.parse().ok()?;
+    let start = parts.next()?.parse().ok()?;
+    let gen = parts.next()?.parse().ok()?;
+    Some((gen, start..end))
+}
+
+/// the cache SSTables are opened with, which reads their pages from `disk_cache` before
+/// `parquet_lru` reads them
+pub(crate) fn cache(parquet_lru: &ParquetLru, disk_cache: Arc<DiskCache>) -> ParquetLru {
+    Arc::new(DiskCachedLru {
+        inner: parquet_lru.clone(),
+        disk_cache,
+    })
+}
+
+struct DiskCachedLru {
+    inner: ParquetLru,
+    disk_cache: Arc<DiskCache>,
+}
+
+impl DynLruCache<FileId> for DiskCachedLru {
+    fn get_reader(&self, key: FileId, reader: BoxedFileReader) -> BoxFuture<'_, BoxedFileReader> {
+        Box::pin(async move {
+            let reader = BoxedFileReader::new(DiskCachedReader {
+                inner: reader,
+                gen: key,
+                disk_cache: self.disk_cache.clone(),
+            });
+            self.inner.get_reader(key, reader).await
+        })
+    }
+}
+
+/// serves the data pages from the disk cache, the metadata is read from the table
+struct DiskCachedReader {
+    inner: BoxedFileReader,
+    gen: FileId,
+    disk_cache: Arc<DiskCache>,
+}
+
+impl AsyncFileReader for DiskCachedReader {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        Box::pin(async move {
+            let key = (self.gen, range);
+            if let Some(bytes) = self.disk_cache.get(&key).await {
+                return Ok(bytes);
+            }
+            let bytes = self.inner.get_bytes(key.1.clone()).await?;
+            self.disk_cache.insert(key, &bytes).await;
+            Ok(bytes)
+        })
+    }
+
+    fn get_metadata<'s>(
+        &'s mut self,
+        options: Option<&'s ArrowReaderOptions>,
+    ) -> BoxFuture<'s, ParquetResult<Arc<ParquetMetaData>>> {
+        self.inner.get_metadata(options)
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        Box::pin(async move {
+            let mut pages = Vec::with_capacity(ranges.len());
+            let mut missed = Vec::new();
+            for range in ranges {
+                let page = self.disk_cache.get(&(self.gen, range.clone())).await;
+                if page.is_none() {
+                    missed.push(range);
+                }
+                pages.push(page);
+            }
+            if missed.is_empty() {
+                return Ok(pages.into_iter().flatten().collect());
+            }
+
+            let fetched = self.inner.get_byte_ranges(missed.clone()).await?;
+            for (range, bytes) in missed.into_iter().zip(fetched.iter()) {
+                self.disk_cache.insert((self.gen, range), bytes).await;
+            }
+            let mut fetched = fetched.into_iter();
+            // SAFETY: a page is fetched for every missed page
+            Ok(pages
+                .into_iter()
+                .map(|page| page.unwrap_or_else(|| fetched.next().unwrap()))
+                .collect())
+        })
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use bytes::Bytes;
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::DiskCache;
+    use crate::{
+        executor::tokio::TokioExecutor, fs::generate_file_id, inmem::immutable::tests::TestSchema,
+        tests::Test, DbOption, DB,
+    };
+
+    #[tokio::test]
+    async fn evict_least_recently_read() {
+        let temp_dir = TempDir::new().unwrap();
+        let gen = generate_file_id();
+        std::fs::write(temp_dir.path().join(format!("{gen}-0-5")), b"stale").unwrap();
+        // a file the cache did not write is kept
+        std::fs::write(temp_dir.path().join("other"), b"other").unwrap();
+        let cache = DiskCache::new(Path::from_filesystem_path(temp_dir.path()).unwrap(), 20)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+        std::fs::remove_file(temp_dir.path().join("other")).unwrap();
+
+        let page = |start: u64| ((gen, start..start + 8), Bytes::from(vec![start as u8; 8]));
+        let (first, first_bytes) = page(0);
+        let (second, second_bytes) = page(8);
+        let (third, third_bytes) = page(16);
+        cache.insert(first.clone(), &first_bytes).await;
+        cache.insert(second.clone(), &second_bytes).await;
+        // the first page is read last, so the second one is evicted for the third one
+        assert_eq!(cache.get(&first).await, Some(first_bytes.clone()));
+        cache.insert(third.clone(), &third_bytes).await;
+
+        assert_eq!(cache.get(&first).await, Some(first_bytes));
+        assert_eq!(cache.get(&second).await, None);
+        assert_eq!(cache.get(&third).await, Some(third_bytes));
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+
+        // pages larger than the cache are not cached
+        let large = (gen, 24..45);
+        cache.insert(large.clone(), &Bytes::from(vec![0; 21])).await;
+        assert_eq!(cache.get(&large).await, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_through() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .disk_cache(
+            Path::from_filesystem_path(cache_dir.path()).unwrap(),
+            1024 * 1024,
+        );
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        db.insert(Test {
+            vstring: "key".to_string(),
+            vu32: 1,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(
+                db.get(&"key".to_string(), |entry| entry.get().vu32)
+                    .await
+                    .unwrap(),
+                Some(1)
+            );
+        }
+        assert!(std::fs::read_dir(cache_dir.path()).unwrap().count() > 0);
+    }
+}
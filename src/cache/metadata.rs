@@ -0,0 +1,216 @@
+use std::{
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bytes::Bytes;
+use futures_core::future::BoxFuture;
+use parquet::{
+    arrow::{arrow_reader::ArrowReaderOptions, async_reader::AsyncFileReader},
+    errors::Result as ParquetResult,
+    file::metadata::ParquetMetaData,
+};
+use parquet_lru::{BoxedFileReader, DynLruCache};
+
+use super::Lru;
+use crate::{fs::FileId, ParquetLru};
+
+/// Statistics of the footer cache of a [`DB`](crate::DB), see
+/// [`DbOption::metadata_cache`](crate::DbOption::metadata_cache).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetadataCacheStats {
+    /// footers served by the cache
+    pub hits: u64,
+    /// footers read from the tables
+    pub misses: u64,
+    /// footers evicted to keep the cache within its capacity
+    pub evictions: u64,
+    /// number of tables whose footer is cached
+    pub tables: usize,
+    /// bytes of the parsed footers in the cache
+    pub size: u64,
+    pub capacity: u64,
+}
+
+impl MetadataCacheStats {
+    /// share of the footers served by the cache, `0.0` before the first read
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            reads => self.hits as f64 / reads as f64,
+        }
+    }
+}
+
+/// The parsed footers of the SSTables of a [`DB`](crate::DB) with their page index, weighted by
+/// their size in memory, the least recently read footers are evicted once they exceed the
+/// capacity in bytes. Unlike the [`BlockCache`](super::block::BlockCache), footers are not
+/// evicted by the pages of wide scans.
+pub(crate) struct MetadataCache {
+    footers: Mutex<Lru<FileId, Arc<ParquetMetaData>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl MetadataCache {
+    pub(crate) fn new(capacity: u64) -> Self {
+        Self {
+            footers: Mutex::new(Lru::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// the footer of `gen` if it is cached
+    fn get(&self, gen: &FileId) -> Option<Arc<ParquetMetaData>> {
+        let metadata = self.footers.lock().unwrap().get(gen).cloned();
+        match metadata {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        metadata
+    }
+
+    fn insert(&self, gen: FileId, metadata: Arc<ParquetMetaData>) {
+        let size = metadata.memory_size() as u64;
+        let evicted = {
+            let mut footers = self.footers.lock().unwrap();
+            if size > footers.capacity() {
+                return;
+            }
+            // a footer read by concurrent misses replaces the one read first
+            footers.remove(&gen);
+            footers.insert(gen, size, metadata)
+        };
+        self.evictions
+            .fetch_add(evicted.len() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> MetadataCacheStats {
+        let footers = self.footers.lock().unwrap();
+        MetadataCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            tables: footers.len(),
+            size: footers.size(),
+            capacity: footers.capacity(),
+        }
+    }
+}
+
+/// the cache SSTables are opened with, which reads their footers from `metadata_cache` before
+/// `parquet_lru` reads them
+pub(crate) fn cache(parquet_lru: &ParquetLru, metadata_cache: Arc<MetadataCache>) -> ParquetLru {
+    Arc::new(MetadataCachedLru {
+        inner: parquet_lru.clone(),
+        metadata_cache,
+    })
+}
+
+struct MetadataCachedLru {
+    inner: ParquetLru,
+    metadata_cache: Arc<MetadataCache>,
+}
+
+impl DynLruCache<FileId> for MetadataCachedLru {
+    fn get_reader(&self, key: FileId, reader: BoxedFileReader) -> BoxFuture<'_, BoxedFileReader> {
+        Box::pin(async move {
+            BoxedFileReader::new(MetadataCachedReader {
+                inner: self.inner.get_reader(key, reader).await,
+                gen: key,
+                metadata_cache: self.metadata_cache.clone(),
+            })
+        })
+    }
+}
+
+struct MetadataCachedReader {
+    inner: BoxedFileReader,
+    gen: FileId,
+    metadata_cache: Arc<MetadataCache>,
+}
+
+impl AsyncFileReader for MetadataCachedReader {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_metadata<'s>(
+        &'s mut self,
+        options: Option<&'s ArrowReaderOptions>,
+    ) -> BoxFuture<'s, ParquetResult<Arc<ParquetMetaData>>> {
+        Box::pin(async move {
+            if let Some(metadata) = self.metadata_cache.get(&self.gen) {
+                return Ok(metadata);
+            }
+            // the page index is always read, so the cached footer serves every reader
+            let options = options.cloned().unwrap_or_default().with_page_index(true);
+            let metadata = self.inner.get_metadata(Some(&options)).await?;
+            self.metadata_cache.insert(self.gen, metadata.clone());
+            Ok(metadata)
+        })
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        self.inner.get_byte_ranges(ranges)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn skip_footer_reads() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .metadata_cache(1024 * 1024);
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        db.insert(Test {
+            vstring: "key".to_string(),
+            vu32: 1,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(
+                db.get(&"key".to_string(), |entry| entry.get().vu32)
+                    .await
+                    .unwrap(),
+                Some(1)
+            );
+        }
+        // only the first get reads the footer
+        let stats = db.metadata_cache_stats().unwrap();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.tables, 1);
+        assert!(stats.size > 0 && stats.size <= stats.capacity);
+        assert_eq!(db.memory_usage().await.metadata_cache, stats.size);
+    }
+}
@@ -0,0 +1,100 @@
+pub(crate) mod block;
+pub(crate) mod disk;
+pub(crate) mod metadata;
+pub(crate) mod table;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+/// Entries sized in bytes, evicted in least recently used order once they exceed the capacity.
+pub(crate) struct Lru<K, V> {
+    /// the last use, the size and the value of every entry
+    entries: HashMap<K, (u64, u64, V)>,
+    /// the entries by last use
+    uses: BTreeMap<u64, K>,
+    clock: u64,
+    size: u64,
+    capacity: u64,
+}
+
+impl<K, V> Lru<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    pub(crate) fn new(capacity: u64) -> Self {
+        Self {
+            entries: HashMap::new(),
+            uses: BTreeMap::new(),
+            clock: 0,
+            size: 0,
+            capacity,
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// bytes of the entries
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn contains(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// the value of `key`, which becomes the most recently used entry
+    pub(crate) fn get(&mut self, key: &K) -> Option<&V> {
+        self.clock += 1;
+        let (last_use, _, value) = self.entries.get_mut(key)?;
+        self.uses.remove(last_use);
+        *last_use = self.clock;
+        self.uses.insert(self.clock, key.clone());
+        Some(value)
+    }
+
+    /// remove the entry of `key`
+    pub(crate) fn remove(&mut self, key: &K) -> Option<V> {
+        let (last_use, size, value) = self.entries.remove(key)?;
+        self.uses.remove(&last_use);
+        self.size -= size;
+        Some(value)
+    }
+
+    /// remove the least recently used entry
+    pub(crate) fn pop(&mut self) -> Option<(K, V)> {
+        let (_, key) = self.uses.pop_first()?;
+        // SAFETY: every use is the last use of an entry
+        let (_, size, value) = self.entries.remove(&key).unwrap();
+        self.size -= size;
+        Some((key, value))
+    }
+
+    /// add the entry `key` of `size` bytes unless it is there already, returns the keys and the
+    /// sizes of the entries evicted to keep the entries within the capacity
+    pub(crate) fn insert(&mut self, key: K, size: u64, value: V) -> Vec<(K, u64)> {
+        if self.get(&key).is_some() {
+            return Vec::new();
+        }
+        self.entries.insert(key.clone(), (self.clock, size, value));
+        self.uses.insert(self.clock, key);
+        self.size += size;
+
+        let mut evicted = Vec::new();
+        while self.size > self.capacity {
+            // SAFETY: the entries are not empty while their size is not zero
+            let (_, key) = self.uses.pop_first().unwrap();
+            let (_, size, _) = self.entries.remove(&key).unwrap();
+            self.size -= size;
+            evicted.push((key, size));
+        }
+        evicted
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bytes::Bytes;
+use fusio::{path::Path, DynFs};
+use fusio_parquet::reader::AsyncReader;
+use futures_core::future::BoxFuture;
+use parquet::{
+    arrow::{arrow_reader::ArrowReaderOptions, async_reader::AsyncFileReader},
+    errors::Result as ParquetResult,
+    file::metadata::ParquetMetaData,
+};
+use parquet_lru::BoxedFileReader;
+
+use super::Lru;
+use crate::fs::{FileId, FileType};
+
+/// Statistics of the SSTables a [`DB`](crate::DB) keeps open, see
+/// [`DbOption::max_open_tables`](crate::DbOption::max_open_tables).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TableCacheStats {
+    /// reads served by a table kept open
+    pub hits: u64,
+    /// reads opening their table
+    pub misses: u64,
+    /// tables closed to keep the open tables within the capacity
+    pub evictions: u64,
+    /// tables kept open between reads
+    pub idle: usize,
+    /// tables being read
+    pub in_use: usize,
+    pub capacity: usize,
+}
+
+impl TableCacheStats {
+    /// share of the reads served by a table kept open, `0.0` before the first read
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            reads => self.hits as f64 / reads as f64,
+        }
+    }
+}
+
+/// an open table and its footer once read
+struct Handle {
+    reader: AsyncReader,
+    metadata: Option<Arc<ParquetMetaData>>,
+}
+
+/// the tables kept open between reads, a table read at once by several scans is opened by each
+struct Idle {
+    handles: Lru<(FileId, u64), Handle>,
+    /// the handles of every table
+    by_gen: HashMap<FileId, Vec<u64>>,
+    next: u64,
+}
+
+impl Idle {
+    fn push(&mut self, gen: FileId, handle: Handle) {
+        self.next += 1;
+        self.handles.insert((gen, self.next), 1, handle);
+        self.by_gen.entry(gen).or_default().push(self.next);
+    }
+
+    fn take(&mut self, gen: FileId) -> Option<Handle> {
+        let seqs = self.by_gen.get_mut(&gen)?;
+        // SAFETY: tables without handles are removed
+        let seq = seqs.pop().unwrap();
+        if seqs.is_empty() {
+            self.by_gen.remove(&gen);
+        }
+        self.handles.remove(&(gen, seq))
+    }
+
+    /// remove the least recently read handle
+    fn pop(&mut self) -> Option<Handle> {
+        let ((gen, seq), handle) = self.handles.pop()?;
+        if let Some(seqs) = self.by_gen.get_mut(&gen) {
+            seqs.retain(|other| *other != seq);
+            if seqs.is_empty() {
+                self.by_gen.remove(&gen);
+            }
+        }
+        Some(handle)
+    }
+}
+
+/// The SSTables of a [`DB`](crate::DB) kept open between reads with their footers, up to a
+/// number of open tables. The tables being read count into it and are never closed early, the
+/// least recently read idle tables are closed first.
+pub(crate) struct TableCache {
+    idle: Mutex<Idle>,
+    in_use: AtomicUsize,
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl TableCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            idle: Mutex::new(Idle {
+                handles: Lru::new(u64::MAX),
+                by_gen: HashMap::new(),
+                next: 0,
+            }),
+            in_use: AtomicUsize::new(0),
+            capacity,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// a reader of the table `gen` at `path` of `fs`, opened unless it is kept open. The table is
+    /// kept open again once the reader is dropped.
+    pub(crate) async fn open(
+        self: &Arc<Self>,
+        fs: &Arc<dyn DynFs>,
+        path: &Path,
+        gen: FileId,
+    ) -> Result<BoxedFileReader, fusio::Error> {
+        let handle = self.idle.lock().unwrap().take(gen);
+        let handle = match handle {
+            Some(handle) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                handle
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                Handle {
+                    reader: open_reader(fs, path).await?,
+                    metadata: None,
+                }
+            }
+        };
+        self.in_use.fetch_add(1, Ordering::Relaxed);
+
+        Ok(BoxedFileReader::new(CachedReader {
+            handle: Some(handle),
+            gen,
+            failed: false,
+            cache: self.clone(),
+        }))
+    }
+
+    /// close the idle handles of the table `gen`, e.g. before it is removed
+    pub(crate) fn evict(&self, gen: FileId) {
+        let mut evicted = Vec::new();
+        {
+            let mut idle = self.idle.lock().unwrap();
+            while let Some(handle) = idle.take(gen) {
+                evicted.push(handle);
+            }
+        }
+        self.evictions
+            .fetch_add(evicted.len() as u64, Ordering::Relaxed);
+    }
+
+    /// keep `handle` of the table `gen` open unless the read failed, closing the least recently
+    /// read handles when the open tables exceed the capacity
+    fn put(&self, gen: FileId, handle: Option<Handle>) {
+        let in_use = self.in_use.fetch_sub(1, Ordering::Relaxed) - 1;
+        let Some(handle) = handle else {
+            return;
+        };
+        let room = self.capacity.saturating_sub(in_use);
+        let mut evicted = Vec::new();
+        {
+            let mut idle = self.idle.lock().unwrap();
+            while idle.handles.len() >= room {
+                match idle.pop() {
+                    Some(handle) => evicted.push(handle),
+                    None => break,
+                }
+            }
+            if room > 0 {
+                idle.push(gen, handle);
+            } else {
+                evicted.push(handle);
+            }
+        }
+        // the tables are closed once the lock is released
+        self.evictions
+            .fetch_add(evicted.len() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> TableCacheStats {
+        let idle = self.idle.lock().unwrap().handles.len();
+        TableCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            idle,
+            in_use: self.in_use.load(Ordering::Relaxed),
+            capacity: self.capacity,
+        }
+    }
+}
+
+/// a reader of the table `gen` at `path` of `fs`, kept open by `table_cache` if there is one
+pub(crate) async fn open(
+    table_cache: Option<&Arc<TableCache>>,
+    fs: &Arc<dyn DynFs>,
+    path: &Path,
+    gen: FileId,
+) -> Result<BoxedFileReader, fusio::Error> {
+    match table_cache {
+        Some(table_cache) => table_cache.open(fs, path, gen).await,
+        None => Ok(BoxedFileReader::new(open_reader(fs, path).await?)),
+    }
+}
+
+async fn open_reader(fs: &Arc<dyn DynFs>, path: &Path) -> Result<AsyncReader, fusio::Error> {
+    let file = fs
+        .open_options(path, FileType::Parquet.open_options(true))
+        .await?;
+    let size = file.size().await?;
+    AsyncReader::new(file, size).await
+}
+
+struct CachedReader {
+    /// `None` once dropped
+    handle: Option<Handle>,
+    gen: FileId,
+    /// the table is closed once dropped if reading it failed
+    failed: bool,
+    cache: Arc<TableCache>,
+}
+
+impl CachedReader {
+    fn handle(&mut self) -> &mut Handle {
+        // SAFETY: the handle is taken on drop
+        self.handle.as_mut().unwrap()
+    }
+}
+
+impl AsyncFileReader for CachedReader {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        Box::pin(async move {
+            let result = self.handle().reader.get_bytes(range).await;
+            self.failed |= result.is_err();
+            result
+        })
+    }
+
+    fn get_metadata<'s>(
+        &'s mut self,
+        options: Option<&'s ArrowReaderOptions>,
+    ) -> BoxFuture<'s, ParquetResult<Arc<ParquetMetaData>>> {
+        Box::pin(async move {
+            let handle = self.handle();
+            // the footer is read again when it was read without the page index, which may be
+            // asked for now
+            if let Some(metadata) = &handle.metadata {
+                if options.is_none() || metadata.offset_index().is_some() {
+                    return Ok(metadata.clone());
+                }
+            }
+            let result = handle.reader.get_metadata(options).await;
+            match &result {
+                Ok(metadata) => handle.metadata = Some(metadata.clone()),
+                Err(_) => self.failed = true,
+            }
+            result
+        })
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        Box::pin(async move {
+            let result = self.handle().reader.get_byte_ranges(ranges).await;
+            self.failed |= result.is_err();
+            result
+        })
+    }
+}
+
+impl Drop for CachedReader {
+    fn drop(&mut self) {
+        let handle = self.handle.take().filter(|_| !self.failed);
+        self.cache.put(self.gen, handle);
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use fusio::{disk::LocalFs, path::Path, DynFs};
+    use tempfile::TempDir;
+
+    use super::TableCache;
+    use crate::{
+        executor::tokio::TokioExecutor, fs::generate_file_id, inmem::immutable::tests::TestSchema,
+        tests::Test, DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn close_least_recently_read() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs: Arc<dyn DynFs> = Arc::new(LocalFs {});
+        let tables = (0..3)
+            .map(|_| {
+                let gen = generate_file_id();
+                let path = temp_dir.path().join(format!("{gen}.parquet"));
+                std::fs::write(&path, b"table").unwrap();
+                (gen, Path::from_filesystem_path(path).unwrap())
+            })
+            .collect::<Vec<_>>();
+        let cache = Arc::new(TableCache::new(2));
+
+        let first = cache.open(&fs, &tables[0].1, tables[0].0).await.unwrap();
+        let second = cache.open(&fs, &tables[1].1, tables[1].0).await.unwrap();
+        // tables being read are not closed, even beyond the capacity
+        let third = cache.open(&fs, &tables[2].1, tables[2].0).await.unwrap();
+        assert_eq!(cache.stats().in_use, 3);
+        // the first table is closed as the two others are still read
+        drop(first);
+        drop(second);
+        drop(third);
+        let stats = cache.stats();
+        assert_eq!((stats.idle, stats.in_use, stats.evictions), (2, 0, 1));
+
+        drop(cache.open(&fs, &tables[2].1, tables[2].0).await.unwrap());
+        drop(cache.open(&fs, &tables[0].1, tables[0].0).await.unwrap());
+        // the second table is the least recently read, it is closed for the first one
+        cache.evict(tables[2].0);
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 4);
+        assert_eq!(stats.evictions, 3);
+        assert_eq!(stats.idle, 1);
+        assert_eq!(stats.capacity, 2);
+        assert_eq!(stats.hit_rate(), 0.2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reuse_open_tables() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .max_open_tables(4);
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        db.insert(Test {
+            vstring: "key".to_string(),
+            vu32: 1,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(
+                db.get(&"key".to_string(), |entry| entry.get().vu32)
+                    .await
+                    .unwrap(),
+                Some(1)
+            );
+        }
+        let stats = db.table_cache_stats().unwrap();
+        // the second get reads the table the first one opened
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.hits, 1);
+        assert_eq!((stats.idle, stats.in_use), (1, 0));
+    }
+}
@@ -0,0 +1,265 @@
+use std::fmt::Debug;
+
+use arrow::{
+    array::{make_array, ArrayRef},
+    datatypes::Schema as ArrowSchema,
+};
+use fusio::path::Path;
+
+use crate::{
+    context::Context,
+    record::{Key, Record},
+    scope::TableStats,
+    timestamp::Timestamp,
+    version::{TransactionTs, VersionError},
+};
+
+/// Table-level statistics published to a [`CatalogSink`] after every flush and compaction, taken
+/// from the statistics recorded with the tables when they were written.
+#[derive(Debug, Clone)]
+pub struct TableStatistics {
+    /// rows stored in SSTables, including overwritten versions and tombstones that are not
+    /// compacted yet
+    pub row_count: u64,
+    /// compressed size of all SSTables in bytes
+    pub size: u64,
+    /// number of SSTables
+    pub table_count: usize,
+    /// smallest primary key in SSTables as a single-row array
+    pub min_key: Option<ArrayRef>,
+    /// largest primary key in SSTables as a single-row array
+    pub max_key: Option<ArrayRef>,
+    /// fingerprint of the Arrow schema, it changes whenever the schema does and stays the same
+    /// across processes and builds of tonbo
+    pub schema_version: u64,
+    /// latest timestamp of the [`DB`](crate::DB) when the statistics were taken
+    pub ts: Timestamp,
+}
+
+/// Receives [`TableStatistics`] of a [`DB`](crate::DB) (Hive Metastore / Glue style catalogs).
+///
+/// `publish` is called on the compaction task, implementations should hand the statistics off
+/// instead of blocking on remote calls.
+pub trait CatalogSink: Debug + Send + Sync {
+    /// `base_path` is [`DbOption::path`](crate::DbOption::path) of the table
+    fn publish(&self, base_path: &Path, statistics: TableStatistics);
+}
+
+pub(crate) async fn table_statistics<R>(
+    ctx: &Context<R>,
+) -> Result<TableStatistics, VersionError<R>>
+where
+    R: Record,
+{
+    let version = ctx.version_set.current().await;
+    let mut statistics = TableStatistics {
+        row_count: 0,
+        size: 0,
+        table_count: 0,
+        min_key: None,
+        max_key: None,
+        schema_version: schema_version(ctx.arrow_schema()),
+        ts: version.load_ts(),
+    };
+    let mut min = None;
+    let mut max = None;
+
+    for (level, scopes) in version.level_slice.iter().enumerate() {
+        for scope in scopes {
+            match scope.stats {
+                Some(TableStats {
+                    rows,
+                    size: Some(size),
+                    ..
+                }) => {
+                    statistics.row_count += rows;
+                    statistics.size += size;
+                }
+                // the footers of the tables written without their statistics
+                _ => {
+                    let metadata = version
+                        .table_metadata(
+                            ctx.storage_manager(),
+                            level,
+                            scope.gen,
+                            ctx.cache().clone(),
+                        )
+                        .await?;
+                    statistics.row_count += metadata.file_metadata().num_rows() as u64;
+                    statistics.size += metadata
+                        .row_groups()
+                        .iter()
+                        .map(|row_group| row_group.compressed_size() as u64)
+                        .sum::<u64>();
+                }
+            }
+            statistics.table_count += 1;
+
+            if !matches!(min, Some(min) if min <= &scope.min) {
+                min = Some(&scope.min);
+            }
+            if !matches!(max, Some(max) if max >= &scope.max) {
+                max = Some(&scope.max);
+            }
+        }
+    }
+    statistics.min_key = min.map(key_array);
+    statistics.max_key = max.map(key_array);
+
+    Ok(statistics)
+}
+
+fn key_array<K>(key: &K) -> ArrayRef
+where
+    K: Key,
+{
+    make_array(key.to_arrow_datum().get().0.to_data())
+}
+
+/// the 64-bit FNV-1a hash of the names, data types, nullability and metadata of the fields of
+/// `schema`, which unlike [`DefaultHasher`](std::hash::DefaultHasher) does not change between
+/// runs or Rust releases
+fn schema_version(schema: &ArrowSchema) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    fn write(hash: &mut u64, bytes: &[u8]) {
+        for byte in bytes {
+            *hash = (*hash ^ *byte as u64).wrapping_mul(PRIME);
+        }
+    }
+    // strings are prefixed by their lengths, so that the fields can't run into each other
+    fn write_str(hash: &mut u64, string: &str) {
+        write(hash, &(string.len() as u64).to_le_bytes());
+        write(hash, string.as_bytes());
+    }
+
+    let mut hash = OFFSET_BASIS;
+    for field in schema.fields() {
+        write_str(&mut hash, field.name());
+        write_str(&mut hash, &format!("{:?}", field.data_type()));
+        write(&mut hash, &[field.is_nullable() as u8]);
+        let mut metadata = field.metadata().iter().collect::<Vec<_>>();
+        metadata.sort();
+        write(&mut hash, &(metadata.len() as u64).to_le_bytes());
+        for (key, value) in metadata {
+            write_str(&mut hash, key);
+            write_str(&mut hash, value);
+        }
+    }
+    hash
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use arrow::{
+        array::AsArray,
+        datatypes::{DataType, Field, Schema as ArrowSchema},
+    };
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::{schema_version, CatalogSink, TableStatistics};
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[derive(Debug, Default)]
+    struct TestSink {
+        published: Mutex<Vec<(Path, TableStatistics)>>,
+    }
+
+    impl CatalogSink for TestSink {
+        fn publish(&self, base_path: &Path, statistics: TableStatistics) {
+            self.published
+                .lock()
+                .unwrap()
+                .push((base_path.clone(), statistics));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn publish_after_flush() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = Path::from_filesystem_path(temp_dir.path()).unwrap();
+        let sink = Arc::new(TestSink::default());
+        let option = DbOption::new(path.clone(), &TestSchema).catalog_sink(sink.clone());
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        for i in 1..4 {
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+
+        {
+            let published = sink.published.lock().unwrap();
+            assert_eq!(published.len(), 1);
+            let (base_path, statistics) = &published[0];
+            assert_eq!(base_path, &path);
+            assert_eq!(statistics.row_count, 3);
+            assert_eq!(statistics.table_count, 1);
+            assert!(statistics.size > 0);
+            let min_key = statistics.min_key.as_ref().unwrap();
+            let max_key = statistics.max_key.as_ref().unwrap();
+            assert_eq!(min_key.as_string::<i32>().value(0), "1");
+            assert_eq!(max_key.as_string::<i32>().value(0), "3");
+        }
+
+        db.insert(Test {
+            vstring: 0.to_string(),
+            vu32: 0,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+
+        let published = sink.published.lock().unwrap();
+        assert_eq!(published.len(), 2);
+        let (_, statistics) = &published[1];
+        assert_eq!(statistics.row_count, 4);
+        assert_eq!(statistics.table_count, 2);
+        assert_eq!(statistics.schema_version, published[0].1.schema_version);
+        let min_key = statistics.min_key.as_ref().unwrap();
+        assert_eq!(min_key.as_string::<i32>().value(0), "0");
+    }
+
+    #[test]
+    fn stable_schema_version() {
+        let schema = |metadata: [(&str, &str); 2]| {
+            ArrowSchema::new(vec![
+                Field::new("name", DataType::Utf8, false),
+                Field::new("age", DataType::UInt32, true).with_metadata(
+                    metadata
+                        .into_iter()
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .collect(),
+                ),
+            ])
+        };
+        // pinned, so that a catalog sees the same version after an upgrade
+        assert_eq!(
+            schema_version(&schema([("a", "1"), ("b", "2")])),
+            0xef1dbe54863637db
+        );
+        assert_eq!(
+            schema_version(&schema([("b", "2"), ("a", "1")])),
+            0xef1dbe54863637db
+        );
+        assert_ne!(
+            schema_version(&schema([("a", "1"), ("b", "3")])),
+            0xef1dbe54863637db
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use std::{
+    collections::HashMap,
+    ops::{Bound, RangeBounds},
+    sync::Mutex,
+};
+
+use arrow::array::RecordBatch;
+use flume::Sender;
+use futures_core::Stream;
+use thiserror::Error;
+
+use crate::{
+    executor::Executor,
+    inmem::immutable::{ArrowArrays, Builder},
+    record::{KeyRef, Record, Schema},
+    timestamp::{Timestamp, Ts},
+    wal::log::LogType,
+    DB,
+};
+
+/// A committed mutation delivered by [`DB::subscribe`].
+#[derive(Debug)]
+pub enum Change<R>
+where
+    R: Record,
+{
+    /// `columns` is a single-row [`RecordBatch`] in the full schema of the record (including
+    /// `_null` and `_ts`)
+    Insert {
+        key: <R::Schema as Schema>::Key,
+        ts: Timestamp,
+        columns: RecordBatch,
+    },
+    Remove {
+        key: <R::Schema as Schema>::Key,
+        ts: Timestamp,
+    },
+}
+
+impl<R> Change<R>
+where
+    R: Record,
+{
+    pub(crate) fn insert(record: &R, ts: Timestamp, schema: &R::Schema) -> Self {
+        let mut builder = <R::Schema as Schema>::Columns::builder(schema.arrow_schema().clone(), 1);
+        builder.push(Ts::new(record.key(), ts), Some(record.as_record_ref()));
+
+        Change::Insert {
+            key: record.key().to_key(),
+            ts,
+            columns: builder.finish(None).as_record_batch().clone(),
+        }
+    }
+
+    /// primary key of the mutated record
+    pub fn key(&self) -> &<R::Schema as Schema>::Key {
+        match self {
+            Change::Insert { key, .. } | Change::Remove { key, .. } => key,
+        }
+    }
+
+    /// commit timestamp of the mutation
+    pub fn ts(&self) -> Timestamp {
+        match self {
+            Change::Insert { ts, .. } | Change::Remove { ts, .. } => *ts,
+        }
+    }
+}
+
+impl<R> Clone for Change<R>
+where
+    R: Record,
+{
+    fn clone(&self) -> Self {
+        match self {
+            Change::Insert { key, ts, columns } => Change::Insert {
+                key: key.clone(),
+                ts: *ts,
+                columns: columns.clone(),
+            },
+            Change::Remove { key, ts } => Change::Remove {
+                key: key.clone(),
+                ts: *ts,
+            },
+        }
+    }
+}
+
+/// Ends the stream of [`DB::subscribe`] whose subscriber fell too far behind, the changes
+/// committed since are not delivered to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("change subscriber lagged behind and was dropped")]
+pub struct Lagged;
+
+struct Subscriber<R>
+where
+    R: Record,
+{
+    range: (
+        Bound<<R::Schema as Schema>::Key>,
+        Bound<<R::Schema as Schema>::Key>,
+    ),
+    capacity: usize,
+    tx: Sender<Result<Change<R>, Lagged>>,
+}
+
+impl<R> Subscriber<R>
+where
+    R: Record,
+{
+    /// send the `changes` in range, returns whether the subscriber is kept
+    fn send(&self, changes: &[Change<R>]) -> bool {
+        for change in changes {
+            if !self.range.contains(change.key()) {
+                continue;
+            }
+            // the channel holds one more slot than `capacity`, only taken by `Lagged`
+            if self.tx.len() >= self.capacity {
+                let _ = self.tx.try_send(Err(Lagged));
+                return false;
+            }
+            if self.tx.try_send(Ok(change.clone())).is_err() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Collects the mutations of every commit by its timestamp and fans them out once its last log
+/// is written to the subscribers whose range contains the key. Subscribers whose stream was
+/// dropped are removed on the next publish, and so are the ones lagging behind.
+pub(crate) struct ChangeFeed<R>
+where
+    R: Record,
+{
+    subscribers: Mutex<Vec<Subscriber<R>>>,
+    pending: Mutex<HashMap<Timestamp, Vec<Change<R>>>>,
+}
+
+impl<R> Default for ChangeFeed<R>
+where
+    R: Record,
+{
+    fn default() -> Self {
+        Self {
+            subscribers: Mutex::new(Vec::new()),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<R> ChangeFeed<R>
+where
+    R: Record,
+{
+    pub(crate) fn is_empty(&self) -> bool {
+        self.subscribers.lock().unwrap().is_empty()
+    }
+
+    pub(crate) fn subscribe(
+        &self,
+        range: (
+            Bound<<R::Schema as Schema>::Key>,
+            Bound<<R::Schema as Schema>::Key>,
+        ),
+        capacity: usize,
+    ) -> impl Stream<Item = Result<Change<R>, Lagged>> + 'static {
+        let capacity = capacity.max(1);
+        let (tx, rx) = flume::bounded(capacity + 1);
+        self.subscribers.lock().unwrap().push(Subscriber {
+            range,
+            capacity,
+            tx,
+        });
+
+        rx.into_stream()
+    }
+
+    /// the log of `log_ty` of the commit at `change.ts()` is written
+    pub(crate) fn publish(&self, log_ty: LogType, change: Change<R>) {
+        let ts = change.ts();
+        let changes = match log_ty {
+            LogType::Full => vec![change],
+            LogType::First | LogType::Middle => {
+                self.pending
+                    .lock()
+                    .unwrap()
+                    .entry(ts)
+                    .or_default()
+                    .push(change);
+                return;
+            }
+            LogType::Last => {
+                let mut changes = self.pending.lock().unwrap().remove(&ts).unwrap_or_default();
+                changes.push(change);
+                changes
+            }
+        };
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(&changes));
+    }
+
+    /// a log of the commit at `ts` failed to be written, its changes are not delivered
+    pub(crate) fn failed(&self, ts: Timestamp) {
+        self.pending.lock().unwrap().remove(&ts);
+    }
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Subscribe to the mutations committed with primary keys in `range`.
+    ///
+    /// Every insert and remove applied after this call is delivered once its commit is written
+    /// to the WAL and the mutable memtable, in the order commits complete. Rows of a batch or a
+    /// transaction are delivered one by one after the last of them is written and share the
+    /// same commit timestamp, the rows of a commit failing partway are not delivered.
+    ///
+    /// At most `capacity` undelivered changes are buffered for the subscriber (0 is taken as
+    /// 1). A subscriber falling further behind is dropped rather than slowing down writers: its
+    /// stream yields [`Lagged`] after the buffered changes and ends, and can catch up with a
+    /// scan and a new subscription.
+    pub async fn subscribe(
+        &self,
+        range: (
+            Bound<<R::Schema as Schema>::Key>,
+            Bound<<R::Schema as Schema>::Key>,
+        ),
+        capacity: usize,
+    ) -> impl Stream<Item = Result<Change<R>, Lagged>> + 'static {
+        self.schema.read().await.changes.subscribe(range, capacity)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::ops::Bound;
+
+    use arrow::{array::AsArray, datatypes::UInt32Type};
+    use fusio::path::Path;
+    use futures_util::{FutureExt, StreamExt};
+    use tempfile::TempDir;
+
+    use super::{Change, ChangeFeed, Lagged};
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test,
+        wal::log::LogType, DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn subscribe() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        db.insert(Test {
+            vstring: "0".to_string(),
+            vu32: 0,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+
+        let mut all = Box::pin(db.subscribe((Bound::Unbounded, Bound::Unbounded), 16).await);
+        let mut ranged = Box::pin(
+            db.subscribe((Bound::Included("2".to_string()), Bound::Unbounded), 16)
+                .await,
+        );
+
+        for i in 1..4 {
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i,
+                vbool: Some(true),
+            })
+            .await
+            .unwrap();
+        }
+        db.remove("1".to_string()).await.unwrap();
+        let mut txn = db.transaction().await;
+        txn.remove("3".to_string());
+        txn.commit().await.unwrap();
+
+        let mut changes = Vec::new();
+        for _ in 0..5 {
+            changes.push(all.next().await.unwrap().unwrap());
+        }
+        assert_eq!(
+            changes
+                .iter()
+                .map(|change| (
+                    change.key().clone(),
+                    matches!(change, Change::Insert { .. })
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("1".to_string(), true),
+                ("2".to_string(), true),
+                ("3".to_string(), true),
+                ("1".to_string(), false),
+                ("3".to_string(), false),
+            ]
+        );
+        assert!(changes.windows(2).all(|w| w[0].ts() < w[1].ts()));
+        let Change::Insert { columns, .. } = &changes[1] else {
+            unreachable!()
+        };
+        assert_eq!(columns.num_rows(), 1);
+        assert_eq!(
+            columns
+                .column_by_name("vu32")
+                .unwrap()
+                .as_primitive::<UInt32Type>()
+                .value(0),
+            2
+        );
+
+        let mut keys = Vec::new();
+        for _ in 0..3 {
+            keys.push(ranged.next().await.unwrap().unwrap().key().clone());
+        }
+        assert_eq!(keys, vec!["2", "3", "3"]);
+    }
+
+    #[test]
+    fn publish_on_commit() {
+        let feed = ChangeFeed::<Test>::default();
+        let mut changes = Box::pin(feed.subscribe((Bound::Unbounded, Bound::Unbounded), 16));
+        let remove = |key: &str, ts: u32| Change::Remove {
+            key: key.to_string(),
+            ts: ts.into(),
+        };
+
+        feed.publish(LogType::First, remove("a", 1));
+        feed.publish(LogType::Middle, remove("b", 1));
+        assert!(changes.next().now_or_never().is_none());
+        feed.publish(LogType::First, remove("c", 2));
+        feed.failed(2.into());
+        feed.publish(LogType::Last, remove("d", 1));
+        feed.publish(LogType::Full, remove("e", 3));
+
+        let mut keys = Vec::new();
+        while let Some(change) = changes.next().now_or_never() {
+            keys.push(change.unwrap().unwrap().key().clone());
+        }
+        assert_eq!(keys, vec!["a", "b", "d", "e"]);
+    }
+
+    #[test]
+    fn drop_lagged() {
+        let feed = ChangeFeed::<Test>::default();
+        let mut lagged = Box::pin(feed.subscribe((Bound::Unbounded, Bound::Unbounded), 2));
+
+        for i in 0..3_u32 {
+            feed.publish(
+                LogType::Full,
+                Change::Remove {
+                    key: i.to_string(),
+                    ts: i.into(),
+                },
+            );
+        }
+        assert!(feed.is_empty());
+
+        let mut next = || lagged.next().now_or_never().unwrap();
+        assert_eq!(next().unwrap().unwrap().key(), "0");
+        assert_eq!(next().unwrap().unwrap().key(), "1");
+        assert_eq!(next().unwrap().unwrap_err(), Lagged);
+        assert!(next().is_none());
+    }
+}
@@ -0,0 +1,239 @@
+use std::sync::Arc;
+
+use fusio::{path::Path, DynFs};
+use fusio_dispatch::FsOptions;
+use fusio_log::{error::LogError, Options};
+use futures_util::StreamExt;
+
+use crate::{
+    executor::Executor,
+    fs::{copy, generate_file_id, parse_file_id, FileId, FileType},
+    record::{Record, Schema},
+    timestamp::Timestamp,
+    version::{edit::VersionEdit, set::WalHold, Version, VersionRef},
+    DbError, DbOption, DB,
+};
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Copy the [`DB`] into `path` of the fs of `fs_options`: the SSTables of the current version,
+    /// a manifest of them and the WAL segments cut at the latest commit. The copy is opened as a
+    /// [`DB`] with a [`DbOption`] on `path` and `fs_options` as its base fs, and the options the
+    /// files are read with, e.g. the encryption, of the original [`DB`].
+    ///
+    /// Writers are only blocked while the WAL is flushed and cut, the files are copied after. The
+    /// checkpoint holds every commit returned before the call.
+    pub async fn checkpoint(&self, path: &Path, fs_options: FsOptions) -> Result<(), DbError<R>> {
+        let to_fs = fs_options.clone().parse()?;
+        let (option, target) = {
+            let schema = self.schema.read().await;
+            let target = DbOption::new(path.clone(), &*schema.record_schema).base_fs(fs_options);
+            (schema.option.clone(), target)
+        };
+        let cut = self.cut_wal().await?;
+        to_fs.create_dir_all(&target.wal_dir_path()).await?;
+        to_fs.create_dir_all(&target.version_log_dir_path()).await?;
+
+        let (_, version) = self
+            .copy_wal_cut(cut, &*to_fs, |gen| target.wal_segment_path(gen))
+            .await?;
+        for (level, scopes) in version.level_slice.iter().enumerate() {
+            let level_path = option.level_fs_path(level).unwrap_or(&option.base_path);
+            let level_fs = self.ctx.manager.get_fs(level_path);
+            for scope in scopes {
+                copy(
+                    &**level_fs,
+                    &option.table_path(scope.gen, level),
+                    &*to_fs,
+                    &target.table_path(scope.gen, level),
+                    None,
+                )
+                .await?;
+            }
+        }
+
+        write_manifest(&version, to_fs, target.version_log_path(generate_file_id())).await?;
+
+        Ok(())
+    }
+
+    /// Flush the WAL and cut it at the latest commit: the segments with their sizes, which are
+    /// only appended to, and the current version the segments are replayed on. The segments are
+    /// not removed until the cut is dropped.
+    pub(crate) async fn cut_wal(&self) -> Result<WalCut<R>, DbError<R>> {
+        let schema = self.schema.write().await;
+        let hold = self.ctx.version_set.hold_wal().await;
+        schema.flush_wal().await?;
+
+        let wal_fs = self.ctx.manager.wal_fs();
+        let mut segments = Vec::new();
+        let mut segment_stream = wal_fs.list(&schema.option.wal_dir_path()).await?;
+        while let Some(file_meta) = segment_stream.next().await {
+            let file_meta = file_meta?;
+            if !file_meta.path.as_ref().ends_with("wal") {
+                continue;
+            }
+            if let Some(gen) = parse_file_id(&file_meta.path, FileType::Wal)? {
+                segments.push((gen, file_meta.path, file_meta.size));
+            }
+        }
+        // the memtables of the segments are not flushed into a newer version until the lock is
+        // released
+        let version = self.ctx.version_set.current().await;
+
+        Ok(WalCut {
+            ts: self.ctx.load_ts(),
+            segments,
+            version,
+            _hold: hold,
+        })
+    }
+
+    /// Copy the segments of `cut` to the paths `to` gives their ids on `to_fs`. Returns the ids
+    /// and sizes of the copied segments and the version of the cut they are replayed on.
+    pub(crate) async fn copy_wal_cut(
+        &self,
+        cut: WalCut<R>,
+        to_fs: &dyn DynFs,
+        to: impl Fn(FileId) -> Path,
+    ) -> Result<(Vec<(FileId, u64)>, VersionRef<R>), DbError<R>> {
+        let wal_fs = self.ctx.manager.wal_fs();
+        let mut copied = Vec::with_capacity(cut.segments.len());
+        // the hold of the cut keeps every segment on the fs until it is dropped
+        for (gen, segment, size) in &cut.segments {
+            copy(&**wal_fs, segment, to_fs, &to(*gen), Some(*size)).await?;
+            copied.push((*gen, *size));
+        }
+
+        Ok((copied, cut.version))
+    }
+}
+
+/// the WAL segments of a [`DB`] cut at a commit and the version they are replayed on
+pub(crate) struct WalCut<R: Record> {
+    /// the latest timestamp at the cut
+    pub(crate) ts: Timestamp,
+    segments: Vec<(FileId, Path, u64)>,
+    version: VersionRef<R>,
+    _hold: WalHold,
+}
+
+/// write the edits of `version` to a new version log at `path` of `fs`
+pub(crate) async fn write_manifest<R: Record>(
+    version: &Version<R>,
+    fs: Arc<dyn DynFs>,
+    path: Path,
+) -> Result<(), LogError> {
+    let mut manifest = Options::new(path)
+        .truncate(true)
+        .build_with_fs::<VersionEdit<<R::Schema as Schema>::Key>>(fs)
+        .await?;
+    manifest.write_batch(version.to_edits().iter()).await?;
+    manifest.close().await?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::Path;
+    use fusio_dispatch::FsOptions;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let checkpoint_dir = TempDir::new().unwrap();
+        let record = |key: &str, vu32: u32| Test {
+            vstring: key.to_string(),
+            vu32,
+            vbool: None,
+        };
+
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        db.insert(record("flushed", 1)).await.unwrap();
+        db.flush().await.unwrap();
+        db.insert(record("logged", 2)).await.unwrap();
+
+        let checkpoint_path = Path::from_filesystem_path(checkpoint_dir.path()).unwrap();
+        db.checkpoint(&checkpoint_path, FsOptions::Local)
+            .await
+            .unwrap();
+        // commits after the checkpoint are left out of it
+        db.insert(record("later", 3)).await.unwrap();
+
+        let checkpoint: DB<Test, TokioExecutor> = DB::new(
+            DbOption::new(checkpoint_path, &TestSchema),
+            TokioExecutor::current(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+        for (key, vu32) in [("flushed", Some(1)), ("logged", Some(2)), ("later", None)] {
+            assert_eq!(
+                checkpoint
+                    .get(&key.to_string(), |entry| entry.get().vu32)
+                    .await
+                    .unwrap(),
+                vu32
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cut_holds_flushed_segments() {
+        let temp_dir = TempDir::new().unwrap();
+        let copy_dir = TempDir::new().unwrap();
+
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        // every edit cleans the removed segments
+        option.version_log_snapshot_threshold = 0;
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        db.insert(Test {
+            vstring: "logged".to_string(),
+            vu32: 1,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+
+        let cut = db.cut_wal().await.unwrap();
+        db.flush().await.unwrap();
+        assert_eq!(db.ctx.version_set.current().await.tables_len(0), 1);
+
+        let target = DbOption::new(
+            Path::from_filesystem_path(copy_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let to_fs = FsOptions::Local.parse().unwrap();
+        to_fs.create_dir_all(&target.wal_dir_path()).await.unwrap();
+        let (copied, version) = db
+            .copy_wal_cut(cut, &*to_fs, |gen| target.wal_segment_path(gen))
+            .await
+            .unwrap();
+        // the segments of the cut are replayed on the version of the cut, not on a later one
+        assert!(!copied.is_empty());
+        assert_eq!(version.tables_len(0), 0);
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use crate::{
+    executor::Executor,
+    record::{Record, Schema},
+    timestamp::Timestamp,
+    wal::log::LogType,
+    DB,
+};
+
+/// Called once for every commit of a [`DB`], see [`DB::add_commit_hook`].
+pub trait CommitHook<R>: Send + Sync
+where
+    R: Record,
+{
+    /// the commit at `ts` wrote `keys`, in the order they were written
+    fn on_commit(&self, ts: Timestamp, keys: &[<R::Schema as Schema>::Key]);
+}
+
+impl<R, F> CommitHook<R> for F
+where
+    R: Record,
+    F: Fn(Timestamp, &[<R::Schema as Schema>::Key]) + Send + Sync,
+{
+    fn on_commit(&self, ts: Timestamp, keys: &[<R::Schema as Schema>::Key]) {
+        self(ts, keys)
+    }
+}
+
+/// Collects the keys of the logs of every commit by its timestamp and calls the hooks once its
+/// last log is written, commits of several logs may interleave with others.
+pub(crate) struct CommitHooks<R>
+where
+    R: Record,
+{
+    hooks: RwLock<Vec<Arc<dyn CommitHook<R>>>>,
+    pending: Mutex<HashMap<Timestamp, Vec<<R::Schema as Schema>::Key>>>,
+}
+
+impl<R> Default for CommitHooks<R>
+where
+    R: Record,
+{
+    fn default() -> Self {
+        Self {
+            hooks: RwLock::new(Vec::new()),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<R> CommitHooks<R>
+where
+    R: Record,
+{
+    pub(crate) fn is_empty(&self) -> bool {
+        self.hooks.read().unwrap().is_empty()
+    }
+
+    pub(crate) fn add(&self, hook: Arc<dyn CommitHook<R>>) {
+        self.hooks.write().unwrap().push(hook);
+    }
+
+    /// the log of `log_ty` of the commit at `ts` writing `key` is written
+    pub(crate) fn written(&self, log_ty: LogType, key: <R::Schema as Schema>::Key, ts: Timestamp) {
+        let keys = match log_ty {
+            LogType::Full => vec![key],
+            LogType::First | LogType::Middle => {
+                self.pending
+                    .lock()
+                    .unwrap()
+                    .entry(ts)
+                    .or_default()
+                    .push(key);
+                return;
+            }
+            LogType::Last => {
+                let mut keys = self.pending.lock().unwrap().remove(&ts).unwrap_or_default();
+                keys.push(key);
+                keys
+            }
+        };
+        for hook in self.hooks.read().unwrap().iter() {
+            hook.on_commit(ts, &keys);
+        }
+    }
+
+    /// a log of the commit at `ts` failed to be written, the commit is not reported
+    pub(crate) fn failed(&self, ts: Timestamp) {
+        self.pending.lock().unwrap().remove(&ts);
+    }
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Call `hook` after every commit applied from now on, with its timestamp and the keys it
+    /// wrote, e.g. to publish notifications or invalidate caches.
+    ///
+    /// A commit of a transaction, a batch or a single write is reported once its last log is
+    /// written to the WAL and the mutable memtable, as durable as
+    /// [`DbOption::wal_sync_mode`](crate::DbOption::wal_sync_mode) makes it, and before the write
+    /// returns. A commit failing partway is not reported, and neither are the commits replayed
+    /// from the WAL when the [`DB`] is opened. Hooks run on the task of the writer and block it,
+    /// slow work should be handed off.
+    pub async fn add_commit_hook(&self, hook: impl CommitHook<R> + 'static) {
+        self.schema.read().await.commit_hooks.add(Arc::new(hook));
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test,
+        timestamp::Timestamp, DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn commit_hooks() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        let record = |key: &str| Test {
+            vstring: key.to_string(),
+            vu32: 0,
+            vbool: None,
+        };
+        db.insert(record("before")).await.unwrap();
+
+        let commits = Arc::new(Mutex::new(Vec::<(Timestamp, Vec<String>)>::new()));
+        db.add_commit_hook({
+            let commits = commits.clone();
+            move |ts: Timestamp, keys: &[String]| commits.lock().unwrap().push((ts, keys.to_vec()))
+        })
+        .await;
+
+        db.insert(record("a")).await.unwrap();
+        db.insert_batch([record("b"), record("c")].into_iter())
+            .await
+            .unwrap();
+        let mut txn = db.transaction().await;
+        txn.insert(record("d"));
+        txn.remove("a".to_string());
+        txn.insert(record("e"));
+        txn.commit().await.unwrap();
+        db.remove("b".to_string()).await.unwrap();
+
+        let commits = commits.lock().unwrap();
+        assert_eq!(
+            commits
+                .iter()
+                .map(|(_, keys)| keys.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                vec!["a".to_string()],
+                vec!["b".to_string(), "c".to_string()],
+                vec!["a".to_string(), "d".to_string(), "e".to_string()],
+                vec!["b".to_string()],
+            ]
+        );
+        assert!(commits.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+}
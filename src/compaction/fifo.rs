@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use async_lock::RwLock;
+
+use super::{Compactor, TableSizes};
+use crate::{
+    compaction::CompactionError,
+    context::Context,
+    executor::pool::Priority,
+    fs::FileId,
+    record::Record,
+    ttl,
+    version::{edit::VersionEdit, MAX_LEVEL},
+    DbOption, DbStorage, FifoOption,
+};
+
+/// Compactor of [`CompactionOption::Fifo`](crate::CompactionOption::Fifo).
+///
+/// Tables are only added by flushes and bulk loads, and dropped from the oldest one. Versions of
+/// a key are always older than the versions replacing them, so dropping the oldest tables never
+/// makes a replaced or removed version visible again.
+pub(crate) struct FifoCompactor<R>
+where
+    R: Record,
+{
+    option: Arc<DbOption>,
+    thresholds: FifoOption,
+    schema: Arc<RwLock<DbStorage<R>>>,
+    pub(super) ctx: Arc<Context<R>>,
+    record_schema: Arc<R::Schema>,
+    table_sizes: TableSizes,
+}
+
+impl<R> FifoCompactor<R>
+where
+    R: Record,
+{
+    pub(crate) fn new(
+        schema: Arc<RwLock<DbStorage<R>>>,
+        record_schema: Arc<R::Schema>,
+        option: Arc<DbOption>,
+        thresholds: FifoOption,
+        ctx: Arc<Context<R>>,
+    ) -> Self {
+        FifoCompactor::<R> {
+            option,
+            thresholds,
+            schema,
+            ctx,
+            record_schema,
+            table_sizes: TableSizes::default(),
+        }
+    }
+
+    /// `is_paused`: major compactions are paused, see [`Compactor::check_then_compaction`]
+    pub(crate) async fn check_then_compaction(
+        &mut self,
+        is_manual: bool,
+        is_paused: bool,
+    ) -> Result<(), CompactionError<R>> {
+        let flush_permit = self.ctx.background_permit(Priority::Flush).await;
+        let mut is_compacted = Compactor::<R>::flush_immutables(
+            &self.schema,
+            &self.record_schema,
+            &self.option,
+            &self.ctx,
+            is_manual,
+        )
+        .await?;
+        drop(flush_permit);
+        if !is_compacted && !is_manual {
+            return Ok(());
+        }
+        if !is_paused {
+            let _permit = self.ctx.background_permit(Priority::Compaction).await;
+            is_compacted |= self.drop_oldest().await?;
+        }
+        if is_manual {
+            self.ctx.version_set.rewrite().await?;
+        }
+        if is_compacted {
+            Compactor::<R>::publish_statistics(&self.option, &self.ctx).await;
+        }
+        Ok(())
+    }
+
+    /// drop the oldest tables until the thresholds are met, returns whether any was dropped
+    async fn drop_oldest(&mut self) -> Result<bool, CompactionError<R>> {
+        let version = self.ctx.version_set.current().await;
+        self.table_sizes.retain(&version);
+        // file ids are ULIDs generated in order, so they sort by creation time
+        let mut tables = (0..MAX_LEVEL)
+            .flat_map(|level| {
+                version.level_slice[level]
+                    .iter()
+                    .map(move |scope| (scope.gen, level))
+            })
+            .collect::<Vec<(FileId, usize)>>();
+        tables.sort();
+        drop(version);
+
+        let mut total_size = 0;
+        if self.thresholds.max_total_size.is_some() {
+            for (gen, level) in tables.iter() {
+                total_size += self
+                    .table_sizes
+                    .get(&self.option, &self.ctx.manager, *level, *gen)
+                    .await?;
+            }
+        }
+        // the age is not checked where the clock is not available
+        let now = ttl::now();
+
+        let mut version_edits = vec![];
+        let mut delete_gens = vec![];
+        for (gen, level) in tables {
+            let is_oversized = self
+                .thresholds
+                .max_total_size
+                .is_some_and(|max_total_size| total_size > max_total_size);
+            let is_expired = self.thresholds.max_age.is_some_and(|max_age| {
+                now > 0 && now.saturating_sub(gen.timestamp_ms()) > max_age.as_millis() as u64
+            });
+            if !is_oversized && !is_expired {
+                break;
+            }
+            if self.thresholds.max_total_size.is_some() {
+                total_size -= self
+                    .table_sizes
+                    .get(&self.option, &self.ctx.manager, level, gen)
+                    .await?;
+            }
+            self.table_sizes.remove(&gen);
+            version_edits.push(VersionEdit::Remove {
+                level: level as u8,
+                gen,
+            });
+            delete_gens.push((gen, level));
+        }
+        if version_edits.is_empty() {
+            return Ok(false);
+        }
+        self.ctx
+            .version_set
+            .apply_edits(version_edits, Some(delete_gens), false)
+            .await?;
+
+        Ok(true)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test,
+        CompactionOption, DbOption, FifoOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fifo_compaction() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .compaction_option(CompactionOption::Fifo(FifoOption {
+            max_total_size: None,
+            max_age: Some(Duration::from_millis(500)),
+        }));
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        let get = |key: &'static str| {
+            let db = &db;
+            async move {
+                db.get(&key.to_string(), |entry| entry.get().vu32)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        for key in ["a", "b"] {
+            db.insert(Test {
+                vstring: key.to_string(),
+                vu32: 1,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+            db.flush().await.unwrap();
+        }
+        assert_eq!(db.ctx.version_set().current().await.tables_len(0), 2);
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        db.insert(Test {
+            vstring: "c".to_string(),
+            vu32: 1,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+
+        // the tables of `a` and `b` are older than the age limit, the table of `c` is not
+        let version = db.ctx.version_set().current().await;
+        assert_eq!(version.tables_len(0), 1);
+        assert!(version.level_slice[1..].iter().all(Vec::is_empty));
+        drop(version);
+        assert_eq!(get("a").await, None);
+        assert_eq!(get("b").await, None);
+        assert_eq!(get("c").await, Some(1));
+    }
+}
@@ -1,5 +1,6 @@
 use std::{cmp, collections::Bound, mem, sync::Arc};
 
+use arrow::array::AsArray;
 use async_lock::{RwLock, RwLockUpgradableReadGuard};
 use fusio_parquet::writer::AsyncWriter;
 use parquet::arrow::{AsyncArrowWriter, ProjectionMask};
@@ -8,11 +9,15 @@ use super::Compactor;
 use crate::{
     compaction::CompactionError,
     context::Context,
-    fs::{generate_file_id, manager::StoreManager, FileId, FileType},
+    event::{self, FlushInfo},
+    executor::pool::Priority,
+    fs::{generate_file_id, manager::StoreManager, retry::retry_write, FileId, FileType},
     inmem::{immutable::Immutable, mutable::MutableMemTable},
+    magic,
+    memory::MemoryBudget,
     ondisk::sstable::SsTable,
     record::{Record, Schema as RecordSchema},
-    scope::Scope,
+    scope::{Scope, TableStats},
     stream::{level::LevelStream, ScanStream},
     version::{edit::VersionEdit, TransactionTs, Version, MAX_LEVEL},
     DbOption, DbStorage,
@@ -24,7 +29,7 @@ where
 {
     option: Arc<DbOption>,
     schema: Arc<RwLock<DbStorage<R>>>,
-    ctx: Arc<Context<R>>,
+    pub(super) ctx: Arc<Context<R>>,
     record_schema: Arc<R::Schema>,
 }
 
@@ -46,13 +51,24 @@ where
         }
     }
 
+    /// `is_paused`: major compactions are paused, see [`Compactor::check_then_compaction`]
     pub(crate) async fn check_then_compaction(
         &mut self,
         is_manual: bool,
+        is_paused: bool,
     ) -> Result<(), CompactionError<R>> {
+        let flush_permit = self.ctx.background_permit(Priority::Flush).await;
         let mut guard = self.schema.write().await;
+        let mut is_compacted = false;
 
         guard.trigger.reset();
+        // flush all the immutable memtables once the memtables of the DBs sharing the budget
+        // exceed it
+        let over_budget = self
+            .option
+            .memory_budget
+            .as_ref()
+            .is_some_and(MemoryBudget::is_exceeded);
 
         if !guard.mutable.is_empty() {
             let trigger_clone = guard.trigger.clone();
@@ -69,24 +85,28 @@ where
             );
             let (file_id, immutable) = mutable.into_immutable().await?;
             guard.immutables.push((file_id, immutable));
-        } else if !is_manual {
+        } else if !is_manual && !over_budget {
+            guard.account_memtables();
             return Ok(());
         }
 
-        if (is_manual && !guard.immutables.is_empty())
+        guard.account_memtables();
+        if ((is_manual || over_budget) && !guard.immutables.is_empty())
             || guard.immutables.len() > self.option.immutable_chunk_max_num
         {
             let recover_wal_ids = guard.recover_wal_ids.take();
             drop(guard);
 
             let guard = self.schema.upgradable_read().await;
-            let chunk_num = if is_manual {
+            let chunk_num = if is_manual || over_budget {
                 guard.immutables.len()
             } else {
                 self.option.immutable_chunk_num
             };
             let excess = &guard.immutables[0..chunk_num];
 
+            #[cfg(feature = "metrics")]
+            let start = std::time::Instant::now();
             if let Some(scope) = Self::minor_compaction(
                 &self.option,
                 recover_wal_ids,
@@ -96,11 +116,13 @@ where
             )
             .await?
             {
+                #[cfg(feature = "metrics")]
+                self.ctx.metrics().flush(start.elapsed());
                 let version_ref = self.ctx.version_set.current().await;
                 let mut version_edits = vec![];
                 let mut delete_gens = vec![];
 
-                if self.option.is_threshold_exceeded_major(&version_ref, 0) {
+                if !is_paused && self.option.is_threshold_exceeded_major(&version_ref, 0) {
                     Self::major_compaction(
                         &version_ref,
                         &self.option,
@@ -122,18 +144,48 @@ where
                     .version_set
                     .apply_edits(version_edits, Some(delete_gens), false)
                     .await?;
+                is_compacted = true;
             }
             let mut guard = RwLockUpgradableReadGuard::upgrade(guard).await;
             let sources = guard.immutables.split_off(chunk_num);
             let _ = mem::replace(&mut guard.immutables, sources);
+            guard.account_memtables();
+        } else {
+            drop(guard);
+        }
+        drop(flush_permit);
+        if !is_paused {
+            let _permit = self.ctx.background_permit(Priority::Compaction).await;
+            is_compacted |=
+                Compactor::<R>::rewrite_cold_tables(&self.option, &self.ctx, &self.record_schema)
+                    .await?;
+            is_compacted |=
+                Compactor::<R>::compact_tombstones(&self.option, &self.ctx, &self.record_schema)
+                    .await?;
         }
         if is_manual {
-            self.ctx.version_set.rewrite().await.unwrap();
+            self.ctx.version_set.rewrite().await?;
+        }
+        if is_compacted {
+            Compactor::<R>::publish_statistics(&self.option, &self.ctx).await;
         }
         Ok(())
     }
 
-    async fn minor_compaction(
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "tonbo::flush",
+            skip_all,
+            fields(
+                memtables = batches.len(),
+                gen = tracing::field::Empty,
+                rows = tracing::field::Empty,
+                bytes = tracing::field::Empty,
+            ),
+        )
+    )]
+    pub(super) async fn minor_compaction(
         option: &DbOption,
         recover_wal_ids: Option<Vec<FileId>>,
         batches: &[(
@@ -144,6 +196,13 @@ where
         manager: &StoreManager,
     ) -> Result<Option<Scope<<R::Schema as RecordSchema>::Key>>, CompactionError<R>> {
         if !batches.is_empty() {
+            let mut info = FlushInfo {
+                memtable_num: batches.len(),
+                output: None,
+            };
+            if let Some(event_listener) = &option.event_listener {
+                event_listener.on_flush_begin(&info);
+            }
             let level_0_path = option.level_fs_path(0).unwrap_or(&option.base_path);
             let level_0_fs = manager.get_fs(level_0_path);
 
@@ -152,19 +211,7 @@ where
 
             let gen = generate_file_id();
             let mut wal_ids = Vec::with_capacity(batches.len());
-
-            let mut writer = AsyncArrowWriter::try_new(
-                AsyncWriter::new(
-                    level_0_fs
-                        .open_options(
-                            &option.table_path(gen, 0),
-                            FileType::Parquet.open_options(false),
-                        )
-                        .await?,
-                ),
-                schema.arrow_schema().clone(),
-                Some(option.write_parquet_properties.clone()),
-            )?;
+            let mut stats = TableStats::default();
 
             if let Some(mut recover_wal_ids) = recover_wal_ids {
                 wal_ids.append(&mut recover_wal_ids);
@@ -178,17 +225,51 @@ where
                         max = Some(batch_max.clone())
                     }
                 }
-                writer.write(batch.as_record_batch()).await?;
+                let record_batch = batch.as_record_batch();
+                stats.rows += record_batch.num_rows() as u64;
+                if let Some(nulls) = record_batch.column_by_name(magic::NULL) {
+                    stats.tombstones += nulls.as_boolean().true_count() as u64;
+                }
                 if let Some(file_id) = file_id {
                     wal_ids.push(*file_id);
                 }
             }
-            writer.close().await?;
+            // the table is truncated on open, so a failed write is written again from the start
+            let metadata = retry_write(option.retry_policy.as_ref(), || async {
+                let mut writer = AsyncArrowWriter::try_new(
+                    AsyncWriter::new(
+                        level_0_fs
+                            .open_options(
+                                &option.table_path(gen, 0),
+                                FileType::Parquet.open_options(false),
+                            )
+                            .await?,
+                    ),
+                    schema.arrow_schema().clone(),
+                    Some(option.table_properties(0)?),
+                )?;
+                for (_, batch) in batches {
+                    writer.write(batch.as_record_batch()).await?;
+                }
+                #[cfg(feature = "trace")]
+                tracing::Span::current()
+                    .record("gen", tracing::field::display(gen))
+                    .record("rows", stats.rows)
+                    .record("bytes", writer.bytes_written());
+                Ok::<_, CompactionError<R>>(writer.close().await?)
+            })
+            .await?;
+            stats.written(&metadata);
+            if let Some(event_listener) = &option.event_listener {
+                info.output = Some(event::table_info(option, manager, gen, 0).await?);
+                event_listener.on_flush_completed(&info);
+            }
             return Ok(Some(Scope {
                 min: min.ok_or(CompactionError::EmptyLevel)?,
                 max: max.ok_or(CompactionError::EmptyLevel)?,
                 gen,
                 wal_ids: Some(wal_ids),
+                stats: Some(stats),
             }));
         }
         Ok(None)
@@ -215,84 +296,111 @@ where
             let (meet_scopes_ll, start_ll, end_ll) =
                 Self::next_level_scopes(version, &mut min, &mut max, level, &meet_scopes_l)?;
 
-            let level_path = option.level_fs_path(level).unwrap_or(&option.base_path);
-            let level_fs = ctx.manager.get_fs(level_path);
-            let mut streams = Vec::with_capacity(meet_scopes_l.len() + meet_scopes_ll.len());
-            // This Level
-            if level == 0 {
-                for scope in meet_scopes_l.iter() {
-                    let file = level_fs
-                        .open_options(
-                            &option.table_path(scope.gen, level),
-                            FileType::Parquet.open_options(true),
-                        )
-                        .await?;
-
-                    streams.push(ScanStream::SsTable {
-                        inner: SsTable::open(ctx.parquet_lru.clone(), scope.gen, file)
-                            .await?
-                            .scan(
-                                (Bound::Unbounded, Bound::Unbounded),
-                                u32::MAX.into(),
-                                None,
-                                ProjectionMask::all(),
+            let tables = meet_scopes_l
+                .iter()
+                .map(|scope| (level, *scope))
+                .chain(meet_scopes_ll.iter().map(|scope| (level + 1, *scope)))
+                .collect::<Vec<_>>();
+            let outputs_start = version_edits.len();
+            let run =
+                Compactor::<R>::compaction_begin(option, ctx, level, level + 1, &tables).await?;
+            if option.max_subcompactions > 1 || ctx.compaction.runner().is_some() {
+                Compactor::<R>::build_subcompactions(
+                    option,
+                    &ctx.compaction,
+                    version_edits,
+                    level + 1,
+                    &tables,
+                    instance,
+                    ctx.retention_watermark(option),
+                    None,
+                )
+                .await?;
+            } else {
+                let level_path = option.level_fs_path(level).unwrap_or(&option.base_path);
+                let level_fs = ctx.manager.get_fs(level_path);
+                let mut streams = Vec::with_capacity(meet_scopes_l.len() + meet_scopes_ll.len());
+                // This Level
+                if level == 0 {
+                    for scope in meet_scopes_l.iter() {
+                        let file = level_fs
+                            .open_options(
+                                &option.table_path(scope.gen, level),
+                                FileType::Parquet.open_options(true),
                             )
-                            .await?,
+                            .await?;
+
+                        streams.push(ScanStream::SsTable {
+                            inner: SsTable::open(ctx.compaction_cache().clone(), scope.gen, file)
+                                .await?
+                                .scan(
+                                    (Bound::Unbounded, Bound::Unbounded),
+                                    u32::MAX.into(),
+                                    None,
+                                    ProjectionMask::all(),
+                                )
+                                .await?,
+                        });
+                    }
+                } else {
+                    let (lower, upper) = Compactor::<R>::full_scope(&meet_scopes_l)?;
+                    let level_scan_l = LevelStream::new(
+                        version,
+                        level,
+                        start_l,
+                        end_l,
+                        (Bound::Included(lower), Bound::Included(upper)),
+                        u32::MAX.into(),
+                        None,
+                        ProjectionMask::all(),
+                        level_fs.clone(),
+                        ctx.compaction_cache().clone(),
+                    )
+                    .ok_or(CompactionError::EmptyLevel)?;
+
+                    streams.push(ScanStream::Level {
+                        inner: level_scan_l,
                     });
                 }
-            } else {
-                let (lower, upper) = Compactor::<R>::full_scope(&meet_scopes_l)?;
-                let level_scan_l = LevelStream::new(
-                    version,
-                    level,
-                    start_l,
-                    end_l,
-                    (Bound::Included(lower), Bound::Included(upper)),
-                    u32::MAX.into(),
-                    None,
-                    ProjectionMask::all(),
-                    level_fs.clone(),
-                    ctx.parquet_lru.clone(),
-                )
-                .ok_or(CompactionError::EmptyLevel)?;
+                if !meet_scopes_ll.is_empty() {
+                    // Next Level
+                    let (lower, upper) = Compactor::<R>::full_scope(&meet_scopes_ll)?;
+                    let level_scan_ll = LevelStream::new(
+                        version,
+                        level + 1,
+                        start_ll,
+                        end_ll,
+                        (Bound::Included(lower), Bound::Included(upper)),
+                        u32::MAX.into(),
+                        None,
+                        ProjectionMask::all(),
+                        level_fs.clone(),
+                        ctx.compaction_cache().clone(),
+                    )
+                    .ok_or(CompactionError::EmptyLevel)?;
 
-                streams.push(ScanStream::Level {
-                    inner: level_scan_l,
-                });
-            }
-            if !meet_scopes_ll.is_empty() {
-                // Next Level
-                let (lower, upper) = Compactor::<R>::full_scope(&meet_scopes_ll)?;
-                let level_scan_ll = LevelStream::new(
-                    version,
+                    streams.push(ScanStream::Level {
+                        inner: level_scan_ll,
+                    });
+                }
+
+                let level_l_path = option.level_fs_path(level + 1).unwrap_or(&option.base_path);
+                let level_l_fs = ctx.manager.get_fs(level_l_path);
+                Compactor::<R>::build_tables(
+                    option,
+                    &ctx.compaction,
+                    version_edits,
                     level + 1,
-                    start_ll,
-                    end_ll,
-                    (Bound::Included(lower), Bound::Included(upper)),
-                    u32::MAX.into(),
+                    streams,
+                    instance,
+                    level_l_fs,
+                    ctx.retention_watermark(option),
                     None,
-                    ProjectionMask::all(),
-                    level_fs.clone(),
-                    ctx.parquet_lru.clone(),
                 )
-                .ok_or(CompactionError::EmptyLevel)?;
-
-                streams.push(ScanStream::Level {
-                    inner: level_scan_ll,
-                });
+                .await?;
             }
-
-            let level_l_path = option.level_fs_path(level + 1).unwrap_or(&option.base_path);
-            let level_l_fs = ctx.manager.get_fs(level_l_path);
-            Compactor::<R>::build_tables(
-                option,
-                version_edits,
-                level + 1,
-                streams,
-                instance,
-                level_l_fs,
-            )
-            .await?;
+            Compactor::<R>::compaction_completed(option, ctx, run, &version_edits[outputs_start..])
+                .await?;
 
             for scope in meet_scopes_l {
                 version_edits.push(VersionEdit::Remove {
@@ -406,7 +514,10 @@ where
 
 #[cfg(all(test, feature = "tokio"))]
 pub(crate) mod tests {
-    use std::sync::{atomic::AtomicU32, Arc};
+    use std::{
+        sync::{atomic::AtomicU32, Arc},
+        time::Duration,
+    };
 
     use flume::bounded;
     use fusio::{path::Path, DynFs};
@@ -433,7 +544,7 @@ pub(crate) mod tests {
         trigger::{TriggerFactory, TriggerType},
         version::{cleaner::Cleaner, edit::VersionEdit, set::VersionSet, Version, MAX_LEVEL},
         wal::log::LogType,
-        DbError, DbOption, DB,
+        DbError, DbOption, Projection, DB,
     };
 
     async fn build_immutable<R>(
@@ -821,12 +932,14 @@ pub(crate) mod tests {
             max: 4.to_string(),
             gen: table_gen0,
             wal_ids: None,
+            stats: None,
         });
         version.level_slice[1].push(Scope {
             min: 5.to_string(),
             max: 9.to_string(),
             gen: table_gen1,
             wal_ids: None,
+            stats: None,
         });
 
         let mut version_edits = Vec::new();
@@ -947,7 +1060,7 @@ pub(crate) mod tests {
         .unwrap();
         db.flush().await.unwrap();
 
-        let version = db.ctx.version_set.current().await;
+        let version = db.ctx.version_set().current().await;
 
         for level in 0..MAX_LEVEL {
             let sort_runs = &version.level_slice[level];
@@ -970,4 +1083,157 @@ pub(crate) mod tests {
         }
         dbg!(version);
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_major_compaction_time_travel() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .time_travel_retention(100);
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        option.major_threshold_with_sst_size = 2;
+        option.level_sst_magnification = 1;
+        option.major_default_oldest_table_num = 1;
+        option.trigger_type = TriggerType::Length(5);
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        let mut timestamps = Vec::new();
+        for i in 0..4 {
+            db.insert(Test {
+                vstring: "key".to_owned(),
+                vu32: i,
+                vbool: Some(true),
+            })
+            .await
+            .unwrap();
+            timestamps.push(db.ctx.load_ts());
+            db.flush().await.unwrap();
+        }
+
+        let version = db.ctx.version_set().current().await;
+        assert!(version.level_slice[1..]
+            .iter()
+            .any(|scopes| !scopes.is_empty()));
+        drop(version);
+
+        for (i, ts) in timestamps.iter().enumerate() {
+            let snapshot = db.snapshot_at(*ts).await.unwrap();
+            let entry = snapshot
+                .get(&"key".to_owned(), Projection::All)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(entry.value().unwrap().vu32, Some(i as u32));
+        }
+
+        let future = (u32::from(db.ctx.load_ts()) + 1).into();
+        assert!(matches!(
+            db.snapshot_at(future).await,
+            Err(DbError::OutOfRetention(ts)) if ts == future
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn periodic_compaction() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .periodic_compaction(Duration::from_millis(500));
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        db.insert(Test {
+            vstring: "key".to_owned(),
+            vu32: 1,
+            vbool: Some(true),
+        })
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+        let gen = db.ctx.version_set().current().await.level_slice[0][0].gen;
+
+        // the table is not old enough yet
+        db.flush().await.unwrap();
+        assert_eq!(
+            db.ctx.version_set().current().await.level_slice[0][0].gen,
+            gen
+        );
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        db.flush().await.unwrap();
+        // the table of level 0 moves to level 1
+        let version = db.ctx.version_set().current().await;
+        assert_eq!(version.tables_len(0), 0);
+        assert_eq!(version.tables_len(1), 1);
+        assert_ne!(version.level_slice[1][0].gen, gen);
+        drop(version);
+        assert_eq!(
+            db.get(&"key".to_owned(), |entry| entry.get().vu32)
+                .await
+                .unwrap(),
+            Some(1)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tombstone_compaction() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .tombstone_compaction_ratio(0.5);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        for i in 0..10 {
+            db.insert(Test {
+                vstring: format!("key{i}"),
+                vu32: i,
+                vbool: Some(true),
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+        let version = db.ctx.version_set().current().await;
+        let stats = version.level_slice[0][0].stats.unwrap();
+        assert_eq!((stats.rows, stats.tombstones), (10, 0));
+        assert!(stats.size.is_some_and(|size| size > 0));
+        drop(version);
+
+        for i in 1..10 {
+            db.remove(format!("key{i}")).await.unwrap();
+        }
+        db.flush().await.unwrap();
+
+        // both tables move to level 1, the tombstones and the versions they hide are dropped
+        let version = db.ctx.version_set().current().await;
+        assert_eq!(version.tables_len(0), 0);
+        assert_eq!(version.tables_len(1), 1);
+        let stats = version.level_slice[1][0].stats.unwrap();
+        assert_eq!((stats.rows, stats.tombstones), (1, 0));
+        assert!(stats.size.is_some_and(|size| size > 0));
+        drop(version);
+        for i in 0..10 {
+            let vu32 = db
+                .get(&format!("key{i}"), |entry| entry.get().vu32)
+                .await
+                .unwrap();
+            assert_eq!(vu32, (i == 0).then_some(0));
+        }
+    }
 }
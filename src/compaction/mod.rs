@@ -1,23 +1,48 @@
+pub(crate) mod fifo;
 pub(crate) mod leveled;
-use std::{pin::Pin, sync::Arc};
+pub(crate) mod rate_limit;
+pub(crate) mod size_ratio;
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    ops::Bound,
+    pin::Pin,
+    sync::Arc,
+};
 
-use fusio::DynFs;
+use async_lock::{RwLock, RwLockUpgradableReadGuard};
+use fifo::FifoCompactor;
+use fusio::{DynFs, DynRead};
 use fusio_parquet::writer::AsyncWriter;
-use futures_util::StreamExt;
+use futures_channel::oneshot;
+use futures_util::{future::try_join_all, StreamExt};
 use leveled::LeveledCompactor;
-use parquet::arrow::AsyncArrowWriter;
+use parquet::arrow::{AsyncArrowWriter, ProjectionMask};
+use size_ratio::SizeRatioCompactor;
 use thiserror::Error;
-use tokio::sync::oneshot;
+use tracing::error;
 
 use crate::{
-    fs::{generate_file_id, FileType},
-    inmem::immutable::{ArrowArrays, Builder},
+    catalog,
+    context::{CompactionContext, Context},
+    event::{self, CompactionInfo},
+    fs::{generate_file_id, manager::StoreManager, retry::retry_write, FileId, FileType},
+    inmem::{
+        immutable::{ArrowArrays, Builder},
+        mutable::MutableMemTable,
+    },
+    memory::MemoryBudget,
+    offload::CompactionJob,
+    ondisk::sstable::SsTable,
     record::{KeyRef, Record, Schema as RecordSchema},
-    scope::Scope,
-    stream::{merge::MergeStream, ScanStream},
+    retention::FilterDecision,
+    scope::{Scope, TableStats},
+    stream::{delta::DeltaMerger, merge::MergeStream, ScanStream},
+    timestamp::Timestamp,
     transaction::CommitError,
-    version::{edit::VersionEdit, VersionError},
-    DbOption,
+    ttl,
+    version::{edit::VersionEdit, TransactionTs, Version, VersionError, MAX_LEVEL},
+    DbOption, DbStorage, UpdateStrategy,
 };
 
 pub(crate) enum Compactor<R>
@@ -25,6 +50,8 @@ where
     R: Record,
 {
     Leveled(LeveledCompactor<R>),
+    SizeRatio(SizeRatioCompactor<R>),
+    Fifo(FifoCompactor<R>),
 }
 
 #[derive(Debug)]
@@ -37,63 +64,157 @@ impl<R> Compactor<R>
 where
     R: Record,
 {
+    /// flush the immutable memtables if needed, then run the major compactions of the strategy
+    /// unless a [`BulkLoadSession`](crate::bulk::BulkLoadSession) is open
     pub(crate) async fn check_then_compaction(
         &mut self,
         is_manual: bool,
     ) -> Result<(), CompactionError<R>> {
+        let is_paused = self.ctx().is_bulk_loading();
         match self {
-            Compactor::Leveled(leveled) => leveled.check_then_compaction(is_manual).await,
+            Compactor::Leveled(leveled) => {
+                leveled.check_then_compaction(is_manual, is_paused).await
+            }
+            Compactor::SizeRatio(size_ratio) => {
+                size_ratio.check_then_compaction(is_manual, is_paused).await
+            }
+            Compactor::Fifo(fifo) => fifo.check_then_compaction(is_manual, is_paused).await,
         }
     }
 
+    fn ctx(&self) -> &Context<R> {
+        match self {
+            Compactor::Leveled(leveled) => &leveled.ctx,
+            Compactor::SizeRatio(size_ratio) => &size_ratio.ctx,
+            Compactor::Fifo(fifo) => &fifo.ctx,
+        }
+    }
+
+    /// merge `streams` into tables of `level`, keeping the versions newer than `watermark` (see
+    /// [`MergeStream::retain_versions`]) and applying the merge operator and the compaction
+    /// filter of `ctx`
+    ///
+    /// With `older_tables`, the tables left out of the compaction that may hold older versions of
+    /// the keys, the tombstones at or below `watermark` of the keys none of them holds are
+    /// dropped. Tombstones are always kept with [`UpdateStrategy::MergeOnRead`] or a merge
+    /// operator, where they stop the merge of the older deltas.
+    #[allow(clippy::too_many_arguments)]
     async fn build_tables<'scan>(
         option: &DbOption,
+        ctx: &CompactionContext<R>,
         version_edits: &mut Vec<VersionEdit<<R::Schema as RecordSchema>::Key>>,
         level: usize,
         streams: Vec<ScanStream<'scan, R>>,
         schema: &R::Schema,
         fs: &Arc<dyn DynFs>,
+        watermark: Timestamp,
+        older_tables: Option<&[&Scope<<R::Schema as RecordSchema>::Key>]>,
     ) -> Result<(), CompactionError<R>> {
-        let mut stream = MergeStream::<R>::from_vec(streams, u32::MAX.into()).await?;
+        let merge_operator = ctx.merge_operator();
+        let older_tables = older_tables.filter(|_| {
+            option.update_strategy != UpdateStrategy::MergeOnRead && merge_operator.is_none()
+        });
+        let filter = ctx.compaction_filter();
+        let mut stream = MergeStream::<R>::from_vec(streams, u32::MAX.into())
+            .await?
+            .retain_versions(watermark);
+        if option.update_strategy == UpdateStrategy::MergeOnRead || merge_operator.is_some() {
+            let mut merger = DeltaMerger::new(
+                schema.arrow_schema().clone(),
+                schema.primary_key_index(),
+                ProjectionMask::all(),
+            )
+            .keep_tombstones();
+            if let Some(operator) = merge_operator {
+                merger = merger.operator(operator.clone());
+            }
+            stream = stream.merge_on_read(merger);
+        }
+
+        let now = schema.expire_at_index().map(|_| ttl::now());
 
         // Kould: is the capacity parameter necessary?
         let mut builder =
             <R::Schema as RecordSchema>::Columns::builder(schema.arrow_schema().clone(), 8192);
         let mut min = None;
         let mut max = None;
+        let mut stats = TableStats::default();
 
         while let Some(result) = Pin::new(&mut stream).next().await {
             let entry = result?;
             let key = entry.key();
 
-            if min.is_none() {
-                min = Some(key.value.clone().to_key())
-            }
-            max = Some(key.value.clone().to_key());
-            builder.push(key, entry.value());
-
-            if builder.written_size() >= option.max_sst_file_size {
+            // versions of a key always stay in the same table, otherwise tables of the level
+            // would overlap
+            if builder.written_size() >= option.max_sst_file_size
+                && max.as_ref() != Some(&key.value.clone().to_key())
+            {
                 Self::build_table(
                     option,
+                    ctx,
                     version_edits,
                     level,
                     &mut builder,
                     &mut min,
                     &mut max,
+                    &mut stats,
                     schema,
                     fs,
                 )
                 .await?;
             }
+            let mut value = entry.value();
+            let changed;
+            if let (Some(filter), Some(record)) = (filter, value.clone()) {
+                match filter.filter(record, key.ts) {
+                    FilterDecision::Keep => {}
+                    FilterDecision::Remove => value = None,
+                    FilterDecision::Change(record) => {
+                        if record.key().to_key() != key.value.clone().to_key() {
+                            return Err(CompactionError::FilterChangedKey);
+                        }
+                        changed = record;
+                        value = Some(changed.as_record_ref());
+                    }
+                }
+            }
+            if now.is_some_and(|now| ttl::is_expired::<R>(value.clone(), now)) {
+                // the tombstone keeps hiding the versions in tables that are not compacted
+                value = None;
+            }
+            if let (None, Some(older_tables)) = (&value, older_tables) {
+                let owned_key = key.value.clone().to_key();
+                if key.ts <= watermark
+                    && !older_tables.iter().any(|scope| scope.contains(&owned_key))
+                {
+                    continue;
+                }
+            }
+            let owned_key = key.value.clone().to_key();
+            if min.is_none() {
+                min = Some(owned_key.clone());
+                stats.is_single_version = true;
+            } else if max.as_ref() == Some(&owned_key) {
+                // e.g. a version kept for a named snapshot
+                stats.is_single_version = false;
+            }
+            max = Some(owned_key);
+            stats.rows += 1;
+            if value.is_none() {
+                stats.tombstones += 1;
+            }
+            builder.push(key, value);
         }
         if builder.written_size() > 0 {
             Self::build_table(
                 option,
+                ctx,
                 version_edits,
                 level,
                 &mut builder,
                 &mut min,
                 &mut max,
+                &mut stats,
                 schema,
                 fs,
             )
@@ -102,6 +223,120 @@ where
         Ok(())
     }
 
+    /// Merge `inputs`, tables with their level, into tables of `level`.
+    ///
+    /// The key range of the inputs is split into up to [`DbOption::max_subcompactions`]
+    /// sub-ranges at the smallest keys of the inputs. Sub-ranges are merged concurrently, each
+    /// reading the inputs it overlaps, and their tables are added to `version_edits` in key order.
+    /// `watermark` and `older_tables` are passed to [`Compactor::build_tables`].
+    ///
+    /// The merge runs on the [`CompactionRunner`] of `ctx` if it has one.
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "tonbo::compaction",
+            skip_all,
+            fields(
+                level = level,
+                inputs = ?inputs
+                    .iter()
+                    .map(|(level, scope)| (level, scope.gen))
+                    .collect::<Vec<_>>(),
+                min = ?inputs.iter().map(|(_, scope)| &scope.min).min(),
+                max = ?inputs.iter().map(|(_, scope)| &scope.max).max(),
+            ),
+        )
+    )]
+    pub(crate) async fn build_subcompactions(
+        option: &DbOption,
+        ctx: &CompactionContext<R>,
+        version_edits: &mut Vec<VersionEdit<<R::Schema as RecordSchema>::Key>>,
+        level: usize,
+        inputs: &[(usize, &Scope<<R::Schema as RecordSchema>::Key>)],
+        schema: &R::Schema,
+        watermark: Timestamp,
+        older_tables: Option<&[&Scope<<R::Schema as RecordSchema>::Key>]>,
+    ) -> Result<(), CompactionError<R>> {
+        if let Some(runner) = ctx.runner() {
+            let job = CompactionJob::new(level, inputs, watermark, older_tables);
+            let output = runner.run(job).await.map_err(CompactionError::Remote)?;
+            version_edits.extend(output.tables.into_iter().map(|scope| VersionEdit::Add {
+                level: level as u8,
+                scope,
+            }));
+            return Ok(());
+        }
+        let mut mins = inputs
+            .iter()
+            .map(|(_, scope)| &scope.min)
+            .collect::<Vec<_>>();
+        mins.sort();
+        mins.dedup();
+        // the smallest key of all starts the first sub-range
+        let candidates = mins.get(1..).unwrap_or_default();
+        let num = option.max_subcompactions.min(candidates.len() + 1);
+        let splits = (1..num)
+            .map(|i| candidates[i * candidates.len() / num])
+            .collect::<Vec<_>>();
+
+        let mut ranges = Vec::with_capacity(num);
+        let mut lower = Bound::Unbounded;
+        for split in splits {
+            ranges.push((lower, Bound::Excluded(split)));
+            lower = Bound::Included(split);
+        }
+        ranges.push((lower, Bound::Unbounded));
+
+        let level_path = option.level_fs_path(level).unwrap_or(&option.base_path);
+        let level_fs = ctx.manager.get_fs(level_path);
+        let subcompactions = ranges.into_iter().map(|range| async move {
+            let mut streams = Vec::new();
+            for (input_level, scope) in inputs.iter() {
+                if !scope.meets_range(range) {
+                    continue;
+                }
+                let input_path = option
+                    .level_fs_path(*input_level)
+                    .unwrap_or(&option.base_path);
+                let file = ctx
+                    .manager
+                    .get_fs(input_path)
+                    .open_options(
+                        &option.table_path(scope.gen, *input_level),
+                        FileType::Parquet.open_options(true),
+                    )
+                    .await?;
+
+                streams.push(ScanStream::SsTable {
+                    inner: SsTable::open(ctx.cache().clone(), scope.gen, file)
+                        .await?
+                        .scan(range, u32::MAX.into(), None, ProjectionMask::all())
+                        .await?,
+                });
+            }
+            let mut version_edits = Vec::new();
+            Self::build_tables(
+                option,
+                ctx,
+                &mut version_edits,
+                level,
+                streams,
+                schema,
+                level_fs,
+                watermark,
+                older_tables,
+            )
+            .await?;
+
+            Ok::<_, CompactionError<R>>(version_edits)
+        });
+        for edits in try_join_all(subcompactions).await? {
+            version_edits.extend(edits);
+        }
+        Ok(())
+    }
+
     fn full_scope<'a>(
         meet_scopes: &[&'a Scope<<R::Schema as RecordSchema>::Key>],
     ) -> Result<
@@ -116,14 +351,387 @@ where
         Ok((lower, upper))
     }
 
+    /// Freeze the mutable memtable, then flush the immutable memtables into a table of level 0
+    /// once they exceed [`DbOption::immutable_chunk_max_num`], or all of them if `is_manual`.
+    /// Returns whether a table was added.
+    ///
+    /// For the compactors that rearrange the levels after the table is added, unlike
+    /// [`LeveledCompactor`] which compacts along with the flush.
+    async fn flush_immutables(
+        schema: &RwLock<DbStorage<R>>,
+        record_schema: &Arc<R::Schema>,
+        option: &DbOption,
+        ctx: &Context<R>,
+        is_manual: bool,
+    ) -> Result<bool, CompactionError<R>> {
+        let mut guard = schema.write().await;
+
+        guard.trigger.reset();
+        // flush all the immutable memtables once the memtables of the DBs sharing the budget
+        // exceed it
+        let over_budget = option
+            .memory_budget
+            .as_ref()
+            .is_some_and(MemoryBudget::is_exceeded);
+
+        if !guard.mutable.is_empty() {
+            let trigger_clone = guard.trigger.clone();
+
+            let mutable = mem::replace(
+                &mut guard.mutable,
+                MutableMemTable::new(
+                    option,
+                    trigger_clone,
+                    ctx.manager.wal_fs().clone(),
+                    record_schema.clone(),
+                )
+                .await?,
+            );
+            let (file_id, immutable) = mutable.into_immutable().await?;
+            guard.immutables.push((file_id, immutable));
+        } else if !is_manual && !over_budget {
+            guard.account_memtables();
+            return Ok(false);
+        }
+        guard.account_memtables();
+        if !((is_manual || over_budget) && !guard.immutables.is_empty())
+            && guard.immutables.len() <= option.immutable_chunk_max_num
+        {
+            return Ok(false);
+        }
+        let recover_wal_ids = guard.recover_wal_ids.take();
+        drop(guard);
+
+        let guard = schema.upgradable_read().await;
+        let chunk_num = if is_manual || over_budget {
+            guard.immutables.len()
+        } else {
+            option.immutable_chunk_num
+        };
+        let excess = &guard.immutables[0..chunk_num];
+        let mut is_flushed = false;
+
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        if let Some(scope) = LeveledCompactor::<R>::minor_compaction(
+            option,
+            recover_wal_ids,
+            excess,
+            &guard.record_schema,
+            &ctx.manager,
+        )
+        .await?
+        {
+            #[cfg(feature = "metrics")]
+            ctx.metrics().flush(start.elapsed());
+            let ts = ctx.version_set.current().await.increase_ts();
+            ctx.version_set
+                .apply_edits(
+                    vec![
+                        VersionEdit::Add { level: 0, scope },
+                        VersionEdit::LatestTimeStamp { ts },
+                    ],
+                    None,
+                    false,
+                )
+                .await?;
+            is_flushed = true;
+        }
+        let mut guard = RwLockUpgradableReadGuard::upgrade(guard).await;
+        let sources = guard.immutables.split_off(chunk_num);
+        let _ = mem::replace(&mut guard.immutables, sources);
+        guard.account_memtables();
+
+        Ok(is_flushed)
+    }
+
+    /// notify the event listener of `option` that `inputs`, tables with their level, are being
+    /// compacted from `level` into tables of `output_level`, returns the run to complete the
+    /// compaction with
+    async fn compaction_begin(
+        option: &DbOption,
+        ctx: &Context<R>,
+        level: usize,
+        output_level: usize,
+        inputs: &[(usize, &Scope<<R::Schema as RecordSchema>::Key>)],
+    ) -> Result<CompactionRun, CompactionError<R>> {
+        let Some(event_listener) = &option.event_listener else {
+            return Ok(CompactionRun::new(None));
+        };
+        let mut info = CompactionInfo {
+            level,
+            output_level,
+            inputs: Vec::with_capacity(inputs.len()),
+            outputs: Vec::new(),
+        };
+        for (level, scope) in inputs {
+            info.inputs
+                .push(event::table_info(option, &ctx.manager, scope.gen, *level).await?);
+        }
+        event_listener.on_compaction_begin(&info);
+
+        Ok(CompactionRun::new(Some(info)))
+    }
+
+    /// notify the event listener of `option` that the compaction `run` is completed with the
+    /// tables added by `version_edits`
+    async fn compaction_completed(
+        option: &DbOption,
+        ctx: &Context<R>,
+        run: CompactionRun,
+        version_edits: &[VersionEdit<<R::Schema as RecordSchema>::Key>],
+    ) -> Result<(), CompactionError<R>> {
+        #[cfg(feature = "metrics")]
+        ctx.metrics().compaction(run.start.elapsed());
+        let (Some(event_listener), Some(mut info)) = (&option.event_listener, run.info) else {
+            return Ok(());
+        };
+        for edit in version_edits {
+            if let VersionEdit::Add { level, scope } = edit {
+                info.outputs.push(
+                    event::table_info(option, &ctx.manager, scope.gen, *level as usize).await?,
+                );
+            }
+        }
+        event_listener.on_compaction_completed(&info);
+
+        Ok(())
+    }
+
+    /// publish the statistics of the tables to [`DbOption::catalog_sink`] after a flush or a
+    /// compaction. A failure is logged instead of failing the compaction that already succeeded
+    async fn publish_statistics(option: &DbOption, ctx: &Context<R>) {
+        let Some(catalog_sink) = &option.catalog_sink else {
+            return;
+        };
+        match catalog::table_statistics(ctx).await {
+            Ok(statistics) => catalog_sink.publish(&option.base_path, statistics),
+            Err(err) => error!(
+                "[Catalog Error]: failed to take the table statistics: {}",
+                err
+            ),
+        }
+    }
+
+    /// Rewrite the tables written more than [`DbOption::periodic_compaction`] ago, returns
+    /// whether any was rewritten.
+    ///
+    /// Reads take the newest table of level 0 holding a key, so the cold tables of level 0 move
+    /// to level 1 along with the older tables of level 0. Tables of the other levels are
+    /// rewritten in their own level.
+    async fn rewrite_cold_tables(
+        option: &DbOption,
+        ctx: &Context<R>,
+        schema: &R::Schema,
+    ) -> Result<bool, CompactionError<R>> {
+        let Some(period) = option.periodic_compaction else {
+            return Ok(false);
+        };
+        // the age is not checked where the clock is not available
+        let now = ttl::now();
+        if now == 0 {
+            return Ok(false);
+        }
+        // file ids are ULIDs, they hold the time their table was written
+        let is_cold = |scope: &Scope<<R::Schema as RecordSchema>::Key>| {
+            now.saturating_sub(scope.gen.timestamp_ms()) > period.as_millis() as u64
+        };
+        let mut is_rewritten = false;
+
+        let version = ctx.version_set.current().await;
+        if let Some(last) = version.level_slice[0].iter().rposition(is_cold) {
+            let scopes = version.level_slice[0][..=last].iter().collect::<Vec<_>>();
+            Self::compact_scopes(option, ctx, &version, 0, 1, &scopes, schema, false).await?;
+            is_rewritten = true;
+        }
+        drop(version);
+        for level in 1..MAX_LEVEL {
+            loop {
+                let version = ctx.version_set.current().await;
+                // the rewritten tables are not cold anymore
+                let Some(scope) = version.level_slice[level]
+                    .iter()
+                    .find(|scope| is_cold(scope))
+                else {
+                    break;
+                };
+                Self::compact_scopes(option, ctx, &version, level, level, &[scope], schema, false)
+                    .await?;
+                is_rewritten = true;
+            }
+        }
+        Ok(is_rewritten)
+    }
+
+    /// Compact the tables whose share of tombstones reaches
+    /// [`DbOption::tombstone_compaction_ratio`] into the next level, or in their own level for
+    /// the last one, dropping the tombstones no older table needs. Returns whether any table was
+    /// compacted.
+    ///
+    /// As for the cold tables, a table of level 0 moves to level 1 along with the older tables of
+    /// level 0.
+    async fn compact_tombstones(
+        option: &DbOption,
+        ctx: &Context<R>,
+        schema: &R::Schema,
+    ) -> Result<bool, CompactionError<R>> {
+        let Some(ratio) = option.tombstone_compaction_ratio else {
+            return Ok(false);
+        };
+        // the tables written by this round are left to the next one
+        let mut outputs = HashSet::new();
+        let mut is_compacted = false;
+
+        loop {
+            let version = ctx.version_set.current().await;
+            let is_dense = |scope: &Scope<<R::Schema as RecordSchema>::Key>| {
+                !outputs.contains(&scope.gen)
+                    && scope.stats.is_some_and(|stats| {
+                        stats.tombstones > 0 && stats.tombstone_ratio() >= ratio
+                    })
+            };
+            let Some((level, index)) = version
+                .level_slice
+                .iter()
+                .enumerate()
+                .find_map(|(level, scopes)| scopes.iter().rposition(is_dense).map(|i| (level, i)))
+            else {
+                break;
+            };
+            let (scopes, output_level) = if level == 0 {
+                (version.level_slice[0][..=index].iter().collect(), 1)
+            } else {
+                (
+                    vec![&version.level_slice[level][index]],
+                    (level + 1).min(MAX_LEVEL - 1),
+                )
+            };
+            outputs.extend(
+                Self::compact_scopes(
+                    option,
+                    ctx,
+                    &version,
+                    level,
+                    output_level,
+                    &scopes,
+                    schema,
+                    true,
+                )
+                .await?,
+            );
+            is_compacted = true;
+        }
+        Ok(is_compacted)
+    }
+
+    /// Merge `scopes` of `level` with the tables they overlap in `output_level`, the next level
+    /// or `level` itself, into tables of `output_level` and apply the new version. Returns the
+    /// tables written.
+    ///
+    /// With `drop_tombstones`, the tombstones of the keys the levels below `output_level` do not
+    /// hold are dropped, see [`Compactor::build_tables`].
+    #[allow(clippy::too_many_arguments)]
+    async fn compact_scopes(
+        option: &DbOption,
+        ctx: &Context<R>,
+        version: &Version<R>,
+        level: usize,
+        output_level: usize,
+        scopes: &[&Scope<<R::Schema as RecordSchema>::Key>],
+        schema: &R::Schema,
+        drop_tombstones: bool,
+    ) -> Result<Vec<FileId>, CompactionError<R>> {
+        let mut inputs = scopes
+            .iter()
+            .map(|scope| (level, *scope))
+            .collect::<Vec<_>>();
+        if output_level != level {
+            let min = scopes
+                .iter()
+                .map(|scope| &scope.min)
+                .min()
+                .ok_or(CompactionError::EmptyLevel)?;
+            let max = scopes
+                .iter()
+                .map(|scope| &scope.max)
+                .max()
+                .ok_or(CompactionError::EmptyLevel)?;
+            inputs.extend(
+                version.level_slice[output_level]
+                    .iter()
+                    .filter(|scope| &scope.min <= max && min <= &scope.max)
+                    .map(|scope| (output_level, scope)),
+            );
+        }
+
+        // tables of the deeper levels hold the older versions
+        let older_tables = version.level_slice[output_level + 1..]
+            .iter()
+            .flatten()
+            .collect::<Vec<_>>();
+
+        let run = Self::compaction_begin(option, ctx, level, output_level, &inputs).await?;
+        let mut version_edits = vec![];
+        Self::build_subcompactions(
+            option,
+            &ctx.compaction,
+            &mut version_edits,
+            output_level,
+            &inputs,
+            schema,
+            ctx.retention_watermark(option),
+            drop_tombstones.then_some(older_tables.as_slice()),
+        )
+        .await?;
+        Self::compaction_completed(option, ctx, run, &version_edits).await?;
+        let outputs = version_edits
+            .iter()
+            .filter_map(|edit| match edit {
+                VersionEdit::Add { scope, .. } => Some(scope.gen),
+                _ => None,
+            })
+            .collect();
+
+        let mut delete_gens = Vec::with_capacity(inputs.len());
+        for (level, scope) in inputs {
+            version_edits.push(VersionEdit::Remove {
+                level: level as u8,
+                gen: scope.gen,
+            });
+            delete_gens.push((scope.gen, level));
+        }
+        ctx.version_set
+            .apply_edits(version_edits, Some(delete_gens), false)
+            .await?;
+
+        Ok(outputs)
+    }
+
     #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "tonbo::compaction::table",
+            skip_all,
+            fields(
+                level = level,
+                min = ?min,
+                max = ?max,
+                gen = tracing::field::Empty,
+                rows = stats.rows,
+                bytes = tracing::field::Empty,
+            ),
+        )
+    )]
     async fn build_table(
         option: &DbOption,
+        ctx: &CompactionContext<R>,
         version_edits: &mut Vec<VersionEdit<<R::Schema as RecordSchema>::Key>>,
         level: usize,
         builder: &mut <<R::Schema as RecordSchema>::Columns as ArrowArrays>::Builder,
         min: &mut Option<<R::Schema as RecordSchema>::Key>,
         max: &mut Option<<R::Schema as RecordSchema>::Key>,
+        stats: &mut TableStats,
         schema: &R::Schema,
         fs: &Arc<dyn DynFs>,
     ) -> Result<(), CompactionError<R>> {
@@ -132,19 +740,35 @@ where
 
         let gen = generate_file_id();
         let columns = builder.finish(None);
-        let mut writer = AsyncArrowWriter::try_new(
-            AsyncWriter::new(
-                fs.open_options(
-                    &option.table_path(gen, level),
-                    FileType::Parquet.open_options(false),
-                )
-                .await?,
-            ),
-            schema.arrow_schema().clone(),
-            Some(option.write_parquet_properties.clone()),
-        )?;
-        writer.write(columns.as_record_batch()).await?;
-        writer.close().await?;
+        // the table is truncated on open, so a failed write is written again from the start
+        let (bytes_written, metadata) = retry_write(option.retry_policy.as_ref(), || async {
+            let mut writer = AsyncArrowWriter::try_new(
+                AsyncWriter::new(
+                    fs.open_options(
+                        &option.table_path(gen, level),
+                        FileType::Parquet.open_options(false),
+                    )
+                    .await?,
+                ),
+                schema.arrow_schema().clone(),
+                Some(option.table_properties(level)?),
+            )?;
+            writer.write(columns.as_record_batch()).await?;
+            let bytes_written = writer.bytes_written() as u64;
+            Ok::<_, CompactionError<R>>((bytes_written, writer.close().await?))
+        })
+        .await?;
+        #[cfg(feature = "trace")]
+        tracing::Span::current()
+            .record("gen", tracing::field::display(gen))
+            .record("bytes", bytes_written);
+        stats.written(&metadata);
+        if let Some(rate_limiter) = ctx.rate_limiter() {
+            rate_limiter.acquire(bytes_written).await;
+        }
+        if let Some(rate_limiter) = ctx.background_rate_limiter() {
+            rate_limiter.acquire(bytes_written).await;
+        }
         version_edits.push(VersionEdit::Add {
             level: level as u8,
             scope: Scope {
@@ -152,12 +776,82 @@ where
                 max: max.take().ok_or(CompactionError::EmptyLevel)?,
                 gen,
                 wal_ids: None,
+                stats: Some(mem::take(stats)),
             },
         });
         Ok(())
     }
 }
 
+/// sizes of the tables read by a compactor, kept until the table is removed as tables are never
+/// modified
+#[derive(Default)]
+pub(crate) struct TableSizes {
+    sizes: HashMap<FileId, u64>,
+}
+
+impl TableSizes {
+    async fn get(
+        &mut self,
+        option: &DbOption,
+        manager: &StoreManager,
+        level: usize,
+        gen: FileId,
+    ) -> Result<u64, fusio::Error> {
+        if let Some(size) = self.sizes.get(&gen) {
+            return Ok(*size);
+        }
+        let level_path = option.level_fs_path(level).unwrap_or(&option.base_path);
+        let file = manager
+            .get_fs(level_path)
+            .open_options(
+                &option.table_path(gen, level),
+                FileType::Parquet.open_options(true),
+            )
+            .await?;
+        let size = file.size().await?;
+        self.sizes.insert(gen, size);
+
+        Ok(size)
+    }
+
+    fn remove(&mut self, gen: &FileId) {
+        self.sizes.remove(gen);
+    }
+
+    /// forget the tables `version` does not hold, which other compactions removed
+    fn retain<R>(&mut self, version: &Version<R>)
+    where
+        R: Record,
+    {
+        let live = version
+            .level_slice
+            .iter()
+            .flatten()
+            .map(|scope| scope.gen)
+            .collect::<HashSet<_>>();
+        self.sizes.retain(|gen, _| live.contains(gen));
+    }
+}
+
+/// a major compaction from [`Compactor::compaction_begin`] to [`Compactor::compaction_completed`]
+pub(crate) struct CompactionRun {
+    /// the info of the compaction for the event listener
+    info: Option<CompactionInfo>,
+    #[cfg(feature = "metrics")]
+    start: std::time::Instant,
+}
+
+impl CompactionRun {
+    fn new(info: Option<CompactionInfo>) -> Self {
+        Self {
+            info,
+            #[cfg(feature = "metrics")]
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum CompactionError<R>
 where
@@ -179,6 +873,10 @@ where
     Commit(#[from] CommitError<R>),
     #[error("the level being compacted does not have a table")]
     EmptyLevel,
+    #[error("the compaction filter changed the key of a record")]
+    FilterChangedKey,
+    #[error("remote compaction error: {0}")]
+    Remote(Box<dyn std::error::Error + Send + Sync + 'static>),
 }
 
 #[cfg(all(test, feature = "tokio"))]
@@ -476,30 +1174,35 @@ pub(crate) mod tests {
             max: 3.to_string(),
             gen: table_gen_1,
             wal_ids: None,
+            stats: None,
         });
         version.level_slice[0].push(Scope {
             min: 4.to_string(),
             max: 6.to_string(),
             gen: table_gen_2,
             wal_ids: None,
+            stats: None,
         });
         version.level_slice[1].push(Scope {
             min: 1.to_string(),
             max: 3.to_string(),
             gen: table_gen_3,
             wal_ids: None,
+            stats: None,
         });
         version.level_slice[1].push(Scope {
             min: 4.to_string(),
             max: 6.to_string(),
             gen: table_gen_4,
             wal_ids: None,
+            stats: None,
         });
         version.level_slice[1].push(Scope {
             min: 7.to_string(),
             max: 9.to_string(),
             gen: table_gen_5,
             wal_ids: None,
+            stats: None,
         });
         (
             (
@@ -0,0 +1,213 @@
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures_core::future::BoxFuture;
+use parquet::{
+    arrow::{arrow_reader::ArrowReaderOptions, async_reader::AsyncFileReader},
+    errors::Result as ParquetResult,
+    file::metadata::ParquetMetaData,
+};
+use parquet_lru::{BoxedFileReader, DynLruCache};
+
+use crate::{fs::FileId, option::StorageRateLimit, ParquetLru};
+
+/// Bounds the bytes and the requests sent to the storage per second, see
+/// [`DbOption::compaction_rate_limit`](crate::DbOption::compaction_rate_limit),
+/// [`DbOption::scan_rate_limit`](crate::DbOption::scan_rate_limit) and
+/// [`DbOption::background_rate_limit`](crate::DbOption::background_rate_limit).
+///
+/// Every request waits until the bytes of the requests before it are paid for at the rate, so
+/// the concurrent sub-compactions share the rate. Flushes are not charged.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+pub(crate) struct RateLimiter {
+    bytes: Option<Bucket>,
+    requests: Option<Bucket>,
+}
+
+/// A token bucket refilled at `per_sec` and holding the tokens of `burst`, kept as the time its
+/// tokens spent so far are refilled at.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+struct Bucket {
+    per_sec: u64,
+    burst: Duration,
+    /// when the tokens charged so far are paid for
+    paid_until: Mutex<Option<Instant>>,
+}
+
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+impl Bucket {
+    fn new(per_sec: u64, burst: Duration) -> Self {
+        Self {
+            per_sec: per_sec.max(1),
+            burst,
+            paid_until: Mutex::new(None),
+        }
+    }
+
+    /// charge `tokens`, returning how long to wait until they are paid for
+    fn charge(&self, tokens: u64, now: Instant) -> Duration {
+        if tokens == 0 {
+            return Duration::ZERO;
+        }
+        let cost = Duration::from_secs_f64(tokens as f64 / self.per_sec as f64);
+        let mut paid_until = self.paid_until.lock().unwrap();
+        // the tokens refilled while idle are kept up to the burst
+        let full = now.checked_sub(self.burst).unwrap_or(now);
+        let until = paid_until.map_or(full, |until| until.max(full)) + cost;
+        *paid_until = Some(until);
+        until.saturating_duration_since(now)
+    }
+}
+
+impl RateLimiter {
+    /// a limit of `bytes_per_sec` without bursts, as major compactions are throttled
+    pub(crate) fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes: Some(Bucket::new(bytes_per_sec, Duration::ZERO)),
+            requests: None,
+        }
+    }
+
+    /// the token buckets of `limit`, holding a second of tokens
+    pub(crate) fn with_limit(limit: StorageRateLimit) -> Self {
+        let burst = Duration::from_secs(1);
+        Self {
+            bytes: limit
+                .bytes_per_sec
+                .map(|bytes_per_sec| Bucket::new(bytes_per_sec, burst)),
+            requests: limit
+                .requests_per_sec
+                .map(|requests_per_sec| Bucket::new(requests_per_sec, burst)),
+        }
+    }
+
+    /// wait until `bytes` can be transferred at the rate
+    pub(crate) async fn acquire(&self, bytes: u64) {
+        self.acquire_requests(bytes, 1).await
+    }
+
+    /// wait until `requests` transferring `bytes` in all can be sent at the rates
+    pub(crate) async fn acquire_requests(&self, bytes: u64, requests: u64) {
+        // the limit needs a timer, which wasm32 lacks
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let now = Instant::now();
+            let wait = [(&self.bytes, bytes), (&self.requests, requests)]
+                .into_iter()
+                .filter_map(|(bucket, tokens)| {
+                    bucket.as_ref().map(|bucket| bucket.charge(tokens, now))
+                })
+                .max()
+                .unwrap_or_default();
+            if !wait.is_zero() {
+                crate::executor::sleep(wait).await;
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = (bytes, requests);
+    }
+}
+
+/// the cache charging the reads of the SSTables opened with it to `rate_limiter`
+pub(crate) fn cache(parquet_lru: &ParquetLru, rate_limiter: Arc<RateLimiter>) -> ParquetLru {
+    Arc::new(RateLimitedCache {
+        inner: parquet_lru.clone(),
+        rate_limiter,
+    })
+}
+
+struct RateLimitedCache {
+    inner: ParquetLru,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl DynLruCache<FileId> for RateLimitedCache {
+    fn get_reader(&self, key: FileId, reader: BoxedFileReader) -> BoxFuture<'_, BoxedFileReader> {
+        Box::pin(async move {
+            BoxedFileReader::new(RateLimitedReader {
+                inner: self.inner.get_reader(key, reader).await,
+                rate_limiter: self.rate_limiter.clone(),
+            })
+        })
+    }
+}
+
+/// waits for `rate_limiter` before every request, the bytes of the metadata are not charged
+struct RateLimitedReader {
+    inner: BoxedFileReader,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl AsyncFileReader for RateLimitedReader {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        Box::pin(async move {
+            self.rate_limiter.acquire(range.end - range.start).await;
+            self.inner.get_bytes(range).await
+        })
+    }
+
+    fn get_metadata<'s>(
+        &'s mut self,
+        options: Option<&'s ArrowReaderOptions>,
+    ) -> BoxFuture<'s, ParquetResult<Arc<ParquetMetaData>>> {
+        Box::pin(async move {
+            self.rate_limiter.acquire_requests(0, 1).await;
+            self.inner.get_metadata(options).await
+        })
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        Box::pin(async move {
+            let bytes = ranges.iter().map(|range| range.end - range.start).sum();
+            self.rate_limiter
+                .acquire_requests(bytes, ranges.len() as u64)
+                .await;
+            self.inner.get_byte_ranges(ranges).await
+        })
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::RateLimiter;
+    use crate::option::StorageRateLimit;
+
+    #[tokio::test]
+    async fn rate_limiter() {
+        let rate_limiter = RateLimiter::new(1000);
+        let start = Instant::now();
+        for _ in 0..4 {
+            rate_limiter.acquire(50).await;
+        }
+        // 200 bytes at 1000 bytes per second
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn storage_rate_limit() {
+        let rate_limiter = RateLimiter::with_limit(StorageRateLimit {
+            bytes_per_sec: None,
+            requests_per_sec: Some(10),
+        });
+        let start = Instant::now();
+        // a second of requests is kept as the burst
+        for _ in 0..10 {
+            rate_limiter.acquire_requests(1 << 20, 1).await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+        for _ in 0..3 {
+            rate_limiter.acquire_requests(1 << 20, 1).await;
+        }
+        // 3 requests over the burst at 10 requests per second
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+}
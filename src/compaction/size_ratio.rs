@@ -0,0 +1,609 @@
+use std::sync::Arc;
+
+use async_lock::RwLock;
+
+use super::{Compactor, TableSizes};
+use crate::{
+    compaction::CompactionError,
+    context::Context,
+    executor::pool::Priority,
+    fs::FileId,
+    record::{Record, Schema as RecordSchema},
+    scope::Scope,
+    version::{edit::VersionEdit, Version, MAX_LEVEL},
+    DbOption, DbStorage, SizeRatioOption,
+};
+
+/// Compactor of [`CompactionOption::SizeRatio`](crate::CompactionOption::SizeRatio).
+///
+/// Every round scores level 0 by its number of tables and the other levels by their size over
+/// their target size, then compacts the level with the highest score of at least 1 into the next
+/// one. Rounds repeat until no level exceeds its target. The last level has no target.
+pub(crate) struct SizeRatioCompactor<R>
+where
+    R: Record,
+{
+    option: Arc<DbOption>,
+    targets: SizeRatioOption,
+    schema: Arc<RwLock<DbStorage<R>>>,
+    pub(super) ctx: Arc<Context<R>>,
+    record_schema: Arc<R::Schema>,
+    table_sizes: TableSizes,
+}
+
+impl<R> SizeRatioCompactor<R>
+where
+    R: Record,
+{
+    pub(crate) fn new(
+        schema: Arc<RwLock<DbStorage<R>>>,
+        record_schema: Arc<R::Schema>,
+        option: Arc<DbOption>,
+        targets: SizeRatioOption,
+        ctx: Arc<Context<R>>,
+    ) -> Self {
+        SizeRatioCompactor::<R> {
+            option,
+            targets,
+            schema,
+            ctx,
+            record_schema,
+            table_sizes: TableSizes::default(),
+        }
+    }
+
+    /// `is_paused`: major compactions are paused, see [`Compactor::check_then_compaction`]
+    pub(crate) async fn check_then_compaction(
+        &mut self,
+        is_manual: bool,
+        is_paused: bool,
+    ) -> Result<(), CompactionError<R>> {
+        let flush_permit = self.ctx.background_permit(Priority::Flush).await;
+        let mut is_compacted = Compactor::<R>::flush_immutables(
+            &self.schema,
+            &self.record_schema,
+            &self.option,
+            &self.ctx,
+            is_manual,
+        )
+        .await?;
+        drop(flush_permit);
+        if !is_compacted && !is_manual {
+            return Ok(());
+        }
+        if !is_paused {
+            let _permit = self.ctx.background_permit(Priority::Compaction).await;
+            is_compacted |= self.major_compaction().await?;
+            is_compacted |=
+                Compactor::<R>::rewrite_cold_tables(&self.option, &self.ctx, &self.record_schema)
+                    .await?;
+            is_compacted |=
+                Compactor::<R>::compact_tombstones(&self.option, &self.ctx, &self.record_schema)
+                    .await?;
+        }
+        if is_manual {
+            self.ctx.version_set.rewrite().await?;
+        }
+        if is_compacted {
+            Compactor::<R>::publish_statistics(&self.option, &self.ctx).await;
+        }
+        Ok(())
+    }
+
+    /// compact the levels over their target until none is, returns whether any was compacted
+    async fn major_compaction(&mut self) -> Result<bool, CompactionError<R>> {
+        let mut is_compacted = false;
+
+        loop {
+            let version = self.ctx.version_set.current().await;
+            self.table_sizes.retain(&version);
+            let Some(level) = self.pick_level(&version).await? else {
+                break;
+            };
+            self.compact_level(&version, level).await?;
+            is_compacted = true;
+        }
+        Ok(is_compacted)
+    }
+
+    /// the level with the highest score of at least 1, if any
+    async fn pick_level(
+        &mut self,
+        version: &Version<R>,
+    ) -> Result<Option<usize>, CompactionError<R>> {
+        let mut picked = None;
+        let mut max_score = 1.0;
+
+        // tables of level 0 overlap, every one of them is read by a point lookup
+        let score = version.tables_len(0) as f64 / self.targets.level0_table_num.max(1) as f64;
+        if score >= max_score {
+            picked = Some(0);
+            max_score = score;
+        }
+        for level in 1..MAX_LEVEL - 1 {
+            let mut size = 0;
+            for scope in version.level_slice[level].iter() {
+                size += self.table_size(level, scope.gen).await?;
+            }
+            let score = size as f64 / self.targets.target_size(level).max(1) as f64;
+            if score >= max_score && (picked.is_none() || score > max_score) {
+                picked = Some(level);
+                max_score = score;
+            }
+        }
+        Ok(picked)
+    }
+
+    /// the table of `level` overlapping the fewest bytes of the next level for its own size
+    async fn pick_table<'v>(
+        &mut self,
+        version: &'v Version<R>,
+        level: usize,
+    ) -> Result<&'v Scope<<R::Schema as RecordSchema>::Key>, CompactionError<R>> {
+        let next_level = &version.level_slice[level + 1];
+        let mut picked = None;
+        let mut min_ratio = f64::MAX;
+
+        for scope in version.level_slice[level].iter() {
+            let (start, end) = Self::overlapping(next_level, &scope.min, &scope.max);
+            let mut overlap = 0;
+            for next_scope in next_level[start..end].iter() {
+                overlap += self.table_size(level + 1, next_scope.gen).await?;
+            }
+            let ratio = overlap as f64 / self.table_size(level, scope.gen).await?.max(1) as f64;
+            if ratio < min_ratio {
+                picked = Some(scope);
+                min_ratio = ratio;
+            }
+        }
+        picked.ok_or(CompactionError::EmptyLevel)
+    }
+
+    /// merge the picked tables of `level` with the tables they overlap in the next level
+    async fn compact_level(
+        &mut self,
+        version: &Version<R>,
+        level: usize,
+    ) -> Result<(), CompactionError<R>> {
+        let inputs = if level == 0 {
+            version.level_slice[0].iter().collect::<Vec<_>>()
+        } else {
+            vec![self.pick_table(version, level).await?]
+        };
+        let min = inputs
+            .iter()
+            .map(|scope| &scope.min)
+            .min()
+            .ok_or(CompactionError::EmptyLevel)?;
+        let max = inputs
+            .iter()
+            .map(|scope| &scope.max)
+            .max()
+            .ok_or(CompactionError::EmptyLevel)?;
+        let next_level = &version.level_slice[level + 1];
+        let (start, end) = Self::overlapping(next_level, min, max);
+        // a flushed table of level 0 keeps every version of its memtable, it is rewritten to
+        // leave the versions only level 0 may hold
+        if let ([scope], true, true) = (inputs.as_slice(), start == end, level > 0) {
+            if self.option.is_movable(level) {
+                return self.move_table(level, scope).await;
+            }
+        }
+
+        let tables = inputs
+            .iter()
+            .map(|scope| (level, *scope))
+            .chain(
+                next_level[start..end]
+                    .iter()
+                    .map(|scope| (level + 1, scope)),
+            )
+            .collect::<Vec<_>>();
+
+        let mut version_edits = vec![];
+        let mut delete_gens = vec![];
+        let run =
+            Compactor::<R>::compaction_begin(&self.option, &self.ctx, level, level + 1, &tables)
+                .await?;
+        Compactor::<R>::build_subcompactions(
+            &self.option,
+            &self.ctx.compaction,
+            &mut version_edits,
+            level + 1,
+            &tables,
+            &self.record_schema,
+            self.ctx.retention_watermark(&self.option),
+            None,
+        )
+        .await?;
+        Compactor::<R>::compaction_completed(&self.option, &self.ctx, run, &version_edits).await?;
+
+        for scope in inputs {
+            version_edits.push(VersionEdit::Remove {
+                level: level as u8,
+                gen: scope.gen,
+            });
+            delete_gens.push((scope.gen, level));
+        }
+        for scope in next_level[start..end].iter() {
+            version_edits.push(VersionEdit::Remove {
+                level: (level + 1) as u8,
+                gen: scope.gen,
+            });
+            delete_gens.push((scope.gen, level + 1));
+        }
+        for (gen, _) in delete_gens.iter() {
+            self.table_sizes.remove(gen);
+        }
+        self.ctx
+            .version_set
+            .apply_edits(version_edits, Some(delete_gens), false)
+            .await?;
+
+        Ok(())
+    }
+
+    /// move `scope` of `level`, a level below level 0 overlapping no table of the next level, to
+    /// the next level without rewriting it
+    async fn move_table(
+        &mut self,
+        level: usize,
+        scope: &Scope<<R::Schema as RecordSchema>::Key>,
+    ) -> Result<(), CompactionError<R>> {
+        let version_edits = vec![
+            VersionEdit::Remove {
+                level: level as u8,
+                gen: scope.gen,
+            },
+            VersionEdit::Add {
+                level: (level + 1) as u8,
+                scope: scope.clone(),
+            },
+        ];
+        self.ctx
+            .version_set
+            .apply_edits(version_edits, None, false)
+            .await?;
+
+        Ok(())
+    }
+
+    /// the range of the sorted `scopes` overlapping `min..=max`
+    fn overlapping(
+        scopes: &[Scope<<R::Schema as RecordSchema>::Key>],
+        min: &<R::Schema as RecordSchema>::Key,
+        max: &<R::Schema as RecordSchema>::Key,
+    ) -> (usize, usize) {
+        let start = scopes.partition_point(|scope| &scope.max < min);
+        let end = scopes.partition_point(|scope| &scope.min <= max);
+
+        (start, end.max(start))
+    }
+
+    async fn table_size(&mut self, level: usize, gen: FileId) -> Result<u64, CompactionError<R>> {
+        Ok(self
+            .table_sizes
+            .get(&self.option, &self.ctx.manager, level, gen)
+            .await?)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use fusio::path::Path;
+    use fusio_dispatch::FsOptions;
+    use parquet::{
+        basic::{Compression, ZstdLevel},
+        schema::types::ColumnPath,
+    };
+    use tempfile::TempDir;
+
+    use crate::{
+        event::{CompactionInfo, EventListener},
+        executor::tokio::TokioExecutor,
+        fs::FileId,
+        inmem::immutable::tests::TestSchema,
+        tests::Test,
+        trigger::TriggerType,
+        version::MAX_LEVEL,
+        CompactionOption, DbOption, SizeRatioOption, DB,
+    };
+
+    /// the tables written by the compactions
+    #[derive(Debug, Default)]
+    struct Compactions(Mutex<Vec<FileId>>);
+
+    impl EventListener for Compactions {
+        fn on_compaction_completed(&self, info: &CompactionInfo) {
+            self.0
+                .lock()
+                .unwrap()
+                .extend(info.outputs.iter().map(|output| output.gen));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn size_ratio_compaction() {
+        let temp_dir = TempDir::new().unwrap();
+        let targets = SizeRatioOption {
+            level0_table_num: 2,
+            level_base_size: 8 * 1024,
+            size_ratio: 2,
+        };
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .compaction_option(CompactionOption::SizeRatio(targets));
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        option.max_sst_file_size = 4 * 1024;
+        option.trigger_type = TriggerType::Length(50);
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        for round in 0..10u32 {
+            for i in 0..100u32 {
+                db.insert(Test {
+                    vstring: format!("key{:04}", (i * 37 + round) % 500),
+                    vu32: round,
+                    vbool: Some(true),
+                })
+                .await
+                .unwrap();
+            }
+            db.flush().await.unwrap();
+        }
+
+        let version = db.ctx.version_set().current().await;
+        assert!(version.tables_len(0) < targets.level0_table_num);
+        assert!(version.level_slice[1..]
+            .iter()
+            .any(|scopes| !scopes.is_empty()));
+        for level in 1..MAX_LEVEL {
+            for pair in version.level_slice[level].windows(2) {
+                assert!(pair[0].max < pair[1].min);
+            }
+        }
+        drop(version);
+
+        // the latest round writing a key wins
+        for key in [0u32, 1, 250, 499] {
+            let latest = (0..10u32)
+                .rev()
+                .find(|round| (0..100u32).any(|i| (i * 37 + round) % 500 == key));
+            let vu32 = db
+                .get(&format!("key{key:04}"), |entry| entry.get().vu32)
+                .await
+                .unwrap();
+            assert_eq!(vu32, latest);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn move_table() {
+        let temp_dir = TempDir::new().unwrap();
+        let compactions = Arc::new(Compactions::default());
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .compaction_option(CompactionOption::SizeRatio(SizeRatioOption {
+            level0_table_num: 1,
+            level_base_size: 1,
+            size_ratio: 1,
+        }))
+        .event_listener(compactions.clone());
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        for vu32 in [1, 2] {
+            db.insert(Test {
+                vstring: "a".to_string(),
+                vu32,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+
+        // the flushed table is rewritten into level 1, then moves down the levels over their
+        // target as it is
+        let version = db.ctx.version_set().current().await;
+        let gens = version.level_slice[MAX_LEVEL - 1]
+            .iter()
+            .map(|scope| scope.gen)
+            .collect::<Vec<_>>();
+        assert_eq!(gens, *compactions.0.lock().unwrap());
+        assert_eq!(gens.len(), 1);
+        drop(version);
+        assert_eq!(
+            db.get(&"a".to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap(),
+            Some(2)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn subcompactions() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .compaction_option(CompactionOption::SizeRatio(SizeRatioOption {
+            level0_table_num: 4,
+            ..Default::default()
+        }))
+        .max_subcompactions(4);
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        // the tables of level 0 start at `key000` to `key003`
+        for table in 0..4u32 {
+            for i in 0..25u32 {
+                db.insert(Test {
+                    vstring: format!("key{:03}", i * 4 + table),
+                    vu32: table,
+                    vbool: None,
+                })
+                .await
+                .unwrap();
+            }
+            db.flush().await.unwrap();
+        }
+
+        // every sub-range built its own table
+        let version = db.ctx.version_set().current().await;
+        assert_eq!(version.tables_len(0), 0);
+        assert_eq!(version.tables_len(1), 4);
+        for pair in version.level_slice[1].windows(2) {
+            assert!(pair[0].max < pair[1].min);
+        }
+        drop(version);
+
+        for key in 0..100u32 {
+            let vu32 = db
+                .get(&format!("key{key:03}"), |entry| entry.get().vu32)
+                .await
+                .unwrap();
+            assert_eq!(vu32, Some(key % 4));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn level_compression() {
+        let temp_dir = TempDir::new().unwrap();
+        let zstd = Compression::ZSTD(ZstdLevel::try_new(3).unwrap());
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .compaction_option(CompactionOption::SizeRatio(SizeRatioOption {
+            level0_table_num: 2,
+            ..Default::default()
+        }))
+        .level_compression(1, zstd)
+        .unwrap()
+        .level_column_compression(1, ColumnPath::from("vu32"), Compression::UNCOMPRESSED)
+        .unwrap();
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        // the second flush compacts level 0 into level 1, the third stays in level 0
+        for key in ["a", "b", "c"] {
+            db.insert(Test {
+                vstring: key.to_string(),
+                vu32: 1,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+            db.flush().await.unwrap();
+        }
+
+        let version = db.ctx.version_set().current().await;
+        let compression = |level: usize, column: &'static str| {
+            let version = &version;
+            let ctx = &db.ctx;
+            async move {
+                let metadata = version
+                    .table_metadata(
+                        ctx.storage_manager(),
+                        level,
+                        version.level_slice[level][0].gen,
+                        ctx.cache().clone(),
+                    )
+                    .await
+                    .unwrap();
+                metadata
+                    .row_group(0)
+                    .columns()
+                    .iter()
+                    .find(|chunk| chunk.column_path().string() == column)
+                    .unwrap()
+                    .compression()
+            }
+        };
+        assert_eq!(compression(0, "vstring").await, Compression::LZ4);
+        assert_eq!(compression(0, "vu32").await, Compression::LZ4);
+        assert_eq!(compression(1, "vstring").await, zstd);
+        assert_eq!(compression(1, "vu32").await, Compression::UNCOMPRESSED);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cold_level_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let cold_dir = TempDir::new().unwrap();
+        // the directory of level 0 is created when the DB is opened
+        let hot_path = temp_dir.path().join("hot");
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .compaction_option(CompactionOption::SizeRatio(SizeRatioOption {
+            level0_table_num: 2,
+            ..Default::default()
+        }))
+        .level_path(
+            0,
+            Path::from_filesystem_path(&hot_path).unwrap(),
+            FsOptions::Local,
+        )
+        .unwrap()
+        .cold_level_path(
+            1,
+            Path::from_filesystem_path(cold_dir.path()).unwrap(),
+            FsOptions::Local,
+        )
+        .unwrap();
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        let tables = |dir: &std::path::Path| std::fs::read_dir(dir).unwrap().count();
+        for key in ["a", "b", "c"] {
+            db.insert(Test {
+                vstring: key.to_string(),
+                vu32: 1,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+            db.flush().await.unwrap();
+        }
+
+        // the first two tables are compacted from the hot level 0 into the cold level 1
+        let version = db.ctx.version_set().current().await;
+        assert_eq!(version.tables_len(0), 1);
+        assert_eq!(version.tables_len(1), 1);
+        drop(version);
+        assert_eq!(tables(cold_dir.path()), 1);
+        for key in ["a", "b", "c"] {
+            assert_eq!(
+                db.get(&key.to_string(), |entry| entry.get().vu32)
+                    .await
+                    .unwrap(),
+                Some(1)
+            );
+        }
+    }
+}
@@ -1,13 +1,28 @@
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
 
 use arrow::datatypes::Schema;
 
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
 use crate::{
-    fs::manager::StoreManager,
+    cache::{block::BlockCache, metadata::MetadataCache},
+    compaction::rate_limit::RateLimiter,
+    executor::pool::{Priority, TaskPermit, TaskPool},
+    fs::{manager::StoreManager, FileId},
+    memory::MemoryTracker,
+    merge::MergeOperator,
+    offload::CompactionRunner,
     record::Record,
+    retention::CompactionFilter,
     timestamp::Timestamp,
     version::{set::VersionSet, TransactionTs},
-    ParquetLru,
+    DbOption, ParquetLru,
 };
 
 pub(crate) struct Context<R: Record> {
@@ -15,6 +30,133 @@ pub(crate) struct Context<R: Record> {
     pub(crate) parquet_lru: ParquetLru,
     pub(crate) version_set: VersionSet<R>,
     pub(crate) arrow_schema: Arc<Schema>,
+    bulk_loads: AtomicUsize,
+    /// the tables written by the open bulk load sessions and not installed yet
+    bulk_tables: Mutex<HashSet<FileId>>,
+    block_cache: Option<Arc<BlockCache>>,
+    metadata_cache: Option<Arc<MetadataCache>>,
+    /// the timestamps of the named snapshots by their names
+    named_snapshots: Mutex<BTreeMap<String, Timestamp>>,
+    /// the record batches held by the scans in flight
+    scan_memory: MemoryTracker,
+    task_pool: Option<TaskPool>,
+    /// shared with the [`DbStorage`](crate::DbStorage) counting the writes
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: Arc<Metrics>,
+    pub(crate) compaction: CompactionContext<R>,
+}
+
+/// What major compactions read of the [`Context`], also built by a
+/// [`CompactionWorker`](crate::offload::CompactionWorker) away from the [`DB`](crate::DB).
+pub(crate) struct CompactionContext<R: Record> {
+    pub(crate) manager: Arc<StoreManager>,
+    merge_operator: Option<Arc<dyn MergeOperator<R>>>,
+    compaction_filter: Option<Arc<dyn CompactionFilter<R>>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// shared with the removals of the tables, see
+    /// [`DbOption::background_rate_limit`](crate::DbOption::background_rate_limit)
+    background_rate_limiter: Option<Arc<RateLimiter>>,
+    /// the cache of the [`Context`] charging the reads to `rate_limiter`
+    cache: ParquetLru,
+    runner: Option<Arc<dyn CompactionRunner<R>>>,
+}
+
+impl<R> CompactionContext<R>
+where
+    R: Record,
+{
+    pub(crate) fn new(manager: Arc<StoreManager>, cache: ParquetLru) -> Self {
+        Self {
+            manager,
+            merge_operator: None,
+            compaction_filter: None,
+            rate_limiter: None,
+            background_rate_limiter: None,
+            cache,
+            runner: None,
+        }
+    }
+
+    pub(crate) fn with_merge_operator(
+        self,
+        merge_operator: Option<Arc<dyn MergeOperator<R>>>,
+    ) -> Self {
+        Self {
+            merge_operator,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_compaction_filter(
+        self,
+        compaction_filter: Option<Arc<dyn CompactionFilter<R>>>,
+    ) -> Self {
+        Self {
+            compaction_filter,
+            ..self
+        }
+    }
+
+    /// charge the writes to `rate_limiter`, the reads are charged by the cache below the caches
+    /// of the blocks, the footers and the disk, see [`CompactionContext::with_cache`]
+    pub(crate) fn with_rate_limiter(self, rate_limiter: Option<Arc<RateLimiter>>) -> Self {
+        Self {
+            rate_limiter,
+            ..self
+        }
+    }
+
+    /// charge the writes to `rate_limiter`, the reads are charged by the cache, see
+    /// [`CompactionContext::with_cache`]
+    pub(crate) fn with_background_rate_limiter(
+        self,
+        background_rate_limiter: Option<Arc<RateLimiter>>,
+    ) -> Self {
+        Self {
+            background_rate_limiter,
+            ..self
+        }
+    }
+
+    /// open the SSTables with `cache` instead of the cache of the reads of the application
+    pub(crate) fn with_cache(self, cache: ParquetLru) -> Self {
+        Self { cache, ..self }
+    }
+
+    pub(crate) fn with_compaction_runner(
+        self,
+        runner: Option<Arc<dyn CompactionRunner<R>>>,
+    ) -> Self {
+        Self { runner, ..self }
+    }
+
+    pub(crate) fn merge_operator(&self) -> Option<&Arc<dyn MergeOperator<R>>> {
+        self.merge_operator.as_ref()
+    }
+
+    pub(crate) fn compaction_filter(&self) -> Option<&Arc<dyn CompactionFilter<R>>> {
+        self.compaction_filter.as_ref()
+    }
+
+    /// set with [`DbOption::compaction_rate_limit`](crate::DbOption::compaction_rate_limit)
+    pub(crate) fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_deref()
+    }
+
+    /// set with [`DbOption::background_rate_limit`](crate::DbOption::background_rate_limit)
+    pub(crate) fn background_rate_limiter(&self) -> Option<&RateLimiter> {
+        self.background_rate_limiter.as_deref()
+    }
+
+    /// the cache SSTables are opened with
+    pub(crate) fn cache(&self) -> &ParquetLru {
+        &self.cache
+    }
+
+    /// set with [`DB::with_compaction_runner`](crate::DB::with_compaction_runner)
+    pub(crate) fn runner(&self) -> Option<&Arc<dyn CompactionRunner<R>>> {
+        self.runner.as_ref()
+    }
 }
 
 impl<R> Context<R>
@@ -28,13 +170,116 @@ where
         arrow_schema: Arc<Schema>,
     ) -> Self {
         Self {
+            compaction: CompactionContext::new(manager.clone(), parquet_lru.clone()),
             manager,
             parquet_lru,
             version_set,
             arrow_schema,
+            bulk_loads: AtomicUsize::new(0),
+            bulk_tables: Mutex::new(HashSet::new()),
+            block_cache: None,
+            metadata_cache: None,
+            named_snapshots: Mutex::new(BTreeMap::new()),
+            scan_memory: Default::default(),
+            task_pool: None,
+            #[cfg(feature = "metrics")]
+            metrics: Default::default(),
+        }
+    }
+
+    pub(crate) fn with_block_cache(self, block_cache: Option<Arc<BlockCache>>) -> Self {
+        Self {
+            block_cache,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_metadata_cache(self, metadata_cache: Option<Arc<MetadataCache>>) -> Self {
+        Self {
+            metadata_cache,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_task_pool(self, task_pool: Option<TaskPool>) -> Self {
+        Self { task_pool, ..self }
+    }
+
+    /// wait for the background work of `priority` to be allowed to run by
+    /// [`DbOption::task_pool`], it runs at once without
+    pub(crate) async fn background_permit(&self, priority: Priority) -> Option<TaskPermit> {
+        match &self.task_pool {
+            Some(task_pool) => Some(task_pool.acquire(priority).await),
+            None => None,
+        }
+    }
+
+    pub(crate) fn with_merge_operator(
+        self,
+        merge_operator: Option<Arc<dyn MergeOperator<R>>>,
+    ) -> Self {
+        Self {
+            compaction: self.compaction.with_merge_operator(merge_operator),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_compaction_filter(
+        self,
+        compaction_filter: Option<Arc<dyn CompactionFilter<R>>>,
+    ) -> Self {
+        Self {
+            compaction: self.compaction.with_compaction_filter(compaction_filter),
+            ..self
+        }
+    }
+
+    /// charge the writes of the major compactions to `rate_limiter`, their reads are charged by
+    /// the cache of [`Context::with_background_rate_limiter`]
+    pub(crate) fn with_compaction_rate_limiter(
+        self,
+        rate_limiter: Option<Arc<RateLimiter>>,
+    ) -> Self {
+        Self {
+            compaction: self.compaction.with_rate_limiter(rate_limiter),
+            ..self
+        }
+    }
+
+    /// charge the background jobs to `rate_limiter`, their SSTables opened with `cache`
+    pub(crate) fn with_background_rate_limiter(
+        self,
+        rate_limiter: Option<Arc<RateLimiter>>,
+        cache: ParquetLru,
+    ) -> Self {
+        Self {
+            compaction: self
+                .compaction
+                .with_cache(cache)
+                .with_background_rate_limiter(rate_limiter),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_compaction_runner(
+        self,
+        runner: Option<Arc<dyn CompactionRunner<R>>>,
+    ) -> Self {
+        Self {
+            compaction: self.compaction.with_compaction_runner(runner),
+            ..self
         }
     }
 
+    #[cfg(feature = "metrics")]
+    pub(crate) fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub(crate) fn scan_memory(&self) -> &MemoryTracker {
+        &self.scan_memory
+    }
+
     pub(crate) fn version_set(&self) -> &VersionSet<R> {
         &self.version_set
     }
@@ -47,6 +292,33 @@ where
         &self.parquet_lru
     }
 
+    /// set with [`DbOption::block_cache`](crate::DbOption::block_cache)
+    pub(crate) fn block_cache(&self) -> Option<&Arc<BlockCache>> {
+        self.block_cache.as_ref()
+    }
+
+    /// set with [`DbOption::metadata_cache`](crate::DbOption::metadata_cache)
+    pub(crate) fn metadata_cache(&self) -> Option<&Arc<MetadataCache>> {
+        self.metadata_cache.as_ref()
+    }
+
+    /// set with [`DB::with_merge_operator`](crate::DB::with_merge_operator)
+    pub(crate) fn merge_operator(&self) -> Option<&Arc<dyn MergeOperator<R>>> {
+        self.compaction.merge_operator()
+    }
+
+    /// set with [`DB::with_compaction_filter`](crate::DB::with_compaction_filter)
+    pub(crate) fn compaction_filter(&self) -> Option<&Arc<dyn CompactionFilter<R>>> {
+        self.compaction.compaction_filter()
+    }
+
+    /// the cache major compactions open the SSTables with, charging the reads to the rate limits
+    /// of [`DbOption::compaction_rate_limit`](crate::DbOption::compaction_rate_limit) and
+    /// [`DbOption::background_rate_limit`](crate::DbOption::background_rate_limit)
+    pub(crate) fn compaction_cache(&self) -> &ParquetLru {
+        self.compaction.cache()
+    }
+
     pub(crate) fn arrow_schema(&self) -> &Arc<Schema> {
         &self.arrow_schema
     }
@@ -58,4 +330,78 @@ where
     pub(crate) fn increase_ts(&self) -> Timestamp {
         self.version_set.increase_ts()
     }
+
+    pub(crate) fn advance_ts(&self, ts: Timestamp) -> bool {
+        self.version_set.advance_ts(ts)
+    }
+
+    /// major compaction keeps the versions newer than the returned watermark, see
+    /// [`DbOption::retention_watermark`]. The versions visible at the named snapshots are kept
+    /// too: a compaction started before a snapshot is pinned only reads versions older than it.
+    pub(crate) fn retention_watermark(&self, option: &DbOption) -> Timestamp {
+        let watermark = option.retention_watermark(self.load_ts());
+        self.named_snapshots
+            .lock()
+            .unwrap()
+            .values()
+            .fold(watermark, |watermark, ts| watermark.min(*ts))
+    }
+
+    /// pin `ts` as the named snapshot `name`, false if the name is taken
+    pub(crate) fn pin_snapshot(&self, name: String, ts: Timestamp) -> bool {
+        let mut named_snapshots = self.named_snapshots.lock().unwrap();
+        if named_snapshots.contains_key(&name) {
+            return false;
+        }
+        named_snapshots.insert(name, ts);
+        true
+    }
+
+    pub(crate) fn named_snapshot(&self, name: &str) -> Option<Timestamp> {
+        self.named_snapshots.lock().unwrap().get(name).copied()
+    }
+
+    pub(crate) fn named_snapshots(&self) -> Vec<(String, Timestamp)> {
+        self.named_snapshots
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, ts)| (name.clone(), *ts))
+            .collect()
+    }
+
+    pub(crate) fn release_snapshot(&self, name: &str) -> bool {
+        self.named_snapshots.lock().unwrap().remove(name).is_some()
+    }
+
+    pub(crate) fn start_bulk_load(&self) {
+        self.bulk_loads.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub(crate) fn finish_bulk_load(&self) {
+        self.bulk_loads.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    /// major compactions are paused while a [`BulkLoadSession`](crate::bulk::BulkLoadSession)
+    /// is open
+    pub(crate) fn is_bulk_loading(&self) -> bool {
+        self.bulk_loads.load(Ordering::Acquire) > 0
+    }
+
+    /// the table `gen` is being written by a bulk load session, kept by garbage collections
+    pub(crate) fn add_bulk_table(&self, gen: FileId) {
+        self.bulk_tables.lock().unwrap().insert(gen);
+    }
+
+    /// the tables `gens` of a bulk load session are installed or discarded
+    pub(crate) fn remove_bulk_tables(&self, gens: &[FileId]) {
+        let mut bulk_tables = self.bulk_tables.lock().unwrap();
+        for gen in gens {
+            bulk_tables.remove(gen);
+        }
+    }
+
+    pub(crate) fn bulk_tables(&self) -> Vec<FileId> {
+        self.bulk_tables.lock().unwrap().iter().copied().collect()
+    }
 }
@@ -0,0 +1,200 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    event,
+    executor::Executor,
+    fs::FileId,
+    record::{Record, Schema},
+    timestamp::Timestamp,
+    version::TransactionTs,
+    DbError, DB,
+};
+
+/// The structure of a [`DB`] at a point in time, see [`DB::describe`].
+#[derive(Debug, Clone)]
+pub struct DbDescription<K> {
+    /// latest timestamp of the [`DB`] when it was described
+    pub ts: Timestamp,
+    /// entries of the mutable memtable, including overwritten versions and tombstones
+    pub mutable_entries: usize,
+    /// immutable memtables waiting to be flushed, oldest first
+    pub immutables: Vec<MemTableDescription<K>>,
+    /// every level, empty ones included
+    pub levels: Vec<LevelDescription<K>>,
+}
+
+/// An immutable memtable waiting to be flushed into level 0.
+#[derive(Debug, Clone)]
+pub struct MemTableDescription<K> {
+    /// the WAL the memtable is recovered from, `None` when it has none
+    pub wal_id: Option<FileId>,
+    /// rows of the memtable, including overwritten versions and tombstones
+    pub rows: usize,
+    pub min_key: Option<K>,
+    pub max_key: Option<K>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LevelDescription<K> {
+    pub level: usize,
+    /// size of the SSTables of the level in bytes
+    pub size: u64,
+    /// the SSTables of the level in the order they were added on level 0 and by key
+    /// on the others
+    pub tables: Vec<TableDescription<K>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TableDescription<K> {
+    pub gen: FileId,
+    pub min_key: K,
+    pub max_key: K,
+    /// size of the file in bytes
+    pub size: u64,
+    /// rows of the table, including overwritten versions and tombstones
+    pub rows: u64,
+    /// tombstones among the rows, `None` for the tables written before they were counted and by
+    /// bulk loads
+    pub tombstones: Option<u64>,
+    /// when the table was written, taken from its id
+    pub created_at: SystemTime,
+}
+
+impl<K> DbDescription<K> {
+    /// the SSTables of all the levels, with their level
+    pub fn tables(&self) -> impl Iterator<Item = (usize, &TableDescription<K>)> {
+        self.levels
+            .iter()
+            .flat_map(|level| level.tables.iter().map(move |table| (level.level, table)))
+    }
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Describe the memtables and the SSTables of the current version: the key range, size and
+    /// rows of every table of every level, and the immutable memtables pending a flush, e.g. for
+    /// dashboards or to find hot key ranges.
+    ///
+    /// Every SSTable is opened to read its size, and its row count when the manifest has none.
+    /// The memtables and the version are read one after the other, a flush in between may show
+    /// the same rows in both.
+    pub async fn describe(&self) -> Result<DbDescription<<R::Schema as Schema>::Key>, DbError<R>> {
+        let (option, mutable_entries, immutables) = {
+            let schema = self.schema.read().await;
+            let immutables = schema
+                .immutables
+                .iter()
+                .map(|(wal_id, immutable)| {
+                    let (min_key, max_key) = immutable.scope();
+                    MemTableDescription {
+                        wal_id: *wal_id,
+                        rows: immutable.as_record_batch().num_rows(),
+                        min_key: min_key.cloned(),
+                        max_key: max_key.cloned(),
+                    }
+                })
+                .collect();
+            (schema.option.clone(), schema.mutable.len(), immutables)
+        };
+
+        let version = self.ctx.version_set.current().await;
+        let mut levels = Vec::with_capacity(version.level_slice.len());
+        for (level, scopes) in version.level_slice.iter().enumerate() {
+            let mut tables = Vec::with_capacity(scopes.len());
+            for scope in scopes {
+                let size = event::table_info(&option, self.ctx.storage_manager(), scope.gen, level)
+                    .await?
+                    .size;
+                let (rows, tombstones) = match scope.stats {
+                    Some(stats) => (stats.rows, Some(stats.tombstones)),
+                    None => {
+                        let metadata = version
+                            .table_metadata(
+                                self.ctx.storage_manager(),
+                                level,
+                                scope.gen,
+                                self.ctx.cache().clone(),
+                            )
+                            .await?;
+                        (metadata.file_metadata().num_rows() as u64, None)
+                    }
+                };
+                tables.push(TableDescription {
+                    gen: scope.gen,
+                    min_key: scope.min.clone(),
+                    max_key: scope.max.clone(),
+                    size,
+                    rows,
+                    tombstones,
+                    created_at: UNIX_EPOCH + Duration::from_millis(scope.gen.timestamp_ms()),
+                });
+            }
+            levels.push(LevelDescription {
+                level,
+                size: tables.iter().map(|table| table.size).sum(),
+                tables,
+            });
+        }
+
+        Ok(DbDescription {
+            ts: version.load_ts(),
+            mutable_entries,
+            immutables,
+            levels,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn describe() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        let record = |key: &str| Test {
+            vstring: key.to_string(),
+            vu32: 0,
+            vbool: None,
+        };
+        for key in ["b", "a", "c"] {
+            db.insert(record(key)).await.unwrap();
+        }
+        db.flush().await.unwrap();
+        db.insert(record("d")).await.unwrap();
+
+        let description = db.describe().await.unwrap();
+        assert_eq!(description.mutable_entries, 1);
+        assert!(description.immutables.is_empty());
+        assert_eq!(description.levels.len(), 7);
+
+        let tables = description.tables().collect::<Vec<_>>();
+        assert_eq!(tables.len(), 1);
+        let (level, table) = tables[0];
+        assert_eq!(level, 0);
+        assert_eq!(table.min_key, "a");
+        assert_eq!(table.max_key, "c");
+        assert_eq!(table.rows, 3);
+        assert!(table.size > 0);
+        assert_eq!(description.levels[0].size, table.size);
+    }
+}
@@ -0,0 +1,428 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+};
+
+use thiserror::Error;
+
+#[cfg(not(feature = "encryption"))]
+pub(crate) use self::disabled::{cache, encrypt_parquet, open, seal};
+#[cfg(feature = "encryption")]
+pub(crate) use self::enabled::{cache, encrypt_parquet, open, seal};
+
+/// length of the AES-128-GCM keys, the key length of Parquet modular encryption
+pub const KEY_LEN: usize = 16;
+
+/// Supplies the keys the SSTables and the WAL segments of a [`DB`](crate::DB) are encrypted
+/// with, see [`DbOption::encryption`](crate::DbOption::encryption).
+///
+/// Every file records the id of its key and new files are encrypted with the current key, so
+/// keys are rotated by changing the current one. Compactions rewrite the tables they merge with
+/// the current key, a retired key must stay available until every table encrypted with it has
+/// been compacted, which [`DbOption::periodic_compaction`](crate::DbOption::periodic_compaction)
+/// bounds. WAL segments are removed once their memtable is flushed.
+pub trait KeyProvider: Debug + Send + Sync {
+    /// the id and the key new files are encrypted with
+    fn current_key(&self) -> Result<(Vec<u8>, [u8; KEY_LEN]), EncryptionError>;
+
+    /// the key with the `id` recorded by a file
+    fn key(&self, id: &[u8]) -> Result<[u8; KEY_LEN], EncryptionError>;
+}
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("unknown encryption key {0:?}")]
+    UnknownKey(Vec<u8>),
+    #[error("encryption key provider error: {0}")]
+    Provider(Box<dyn std::error::Error + Send + Sync + 'static>),
+    #[error("data can't be encrypted")]
+    Encrypt,
+    #[error("data can't be decrypted with its key, it is corrupted")]
+    Decrypt,
+}
+
+/// A [`KeyProvider`] holding its keys in memory, the last key added is the current one.
+#[derive(Clone)]
+pub struct KeyRing {
+    current: Vec<u8>,
+    keys: HashMap<Vec<u8>, [u8; KEY_LEN]>,
+}
+
+impl KeyRing {
+    pub fn new(id: impl Into<Vec<u8>>, key: [u8; KEY_LEN]) -> Self {
+        let current = id.into();
+        Self {
+            keys: HashMap::from([(current.clone(), key)]),
+            current,
+        }
+    }
+
+    /// make `key` the current key, the files encrypted with the previous keys stay readable
+    pub fn rotate(mut self, id: impl Into<Vec<u8>>, key: [u8; KEY_LEN]) -> Self {
+        self.current = id.into();
+        self.keys.insert(self.current.clone(), key);
+        self
+    }
+}
+
+impl Debug for KeyRing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // the keys themselves are not printed
+        f.debug_struct("KeyRing")
+            .field("current", &self.current)
+            .field("ids", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl KeyProvider for KeyRing {
+    fn current_key(&self) -> Result<(Vec<u8>, [u8; KEY_LEN]), EncryptionError> {
+        Ok((self.current.clone(), self.key(&self.current)?))
+    }
+
+    fn key(&self, id: &[u8]) -> Result<[u8; KEY_LEN], EncryptionError> {
+        self.keys
+            .get(id)
+            .copied()
+            .ok_or_else(|| EncryptionError::UnknownKey(id.to_vec()))
+    }
+}
+
+#[cfg(feature = "encryption")]
+mod enabled {
+    use std::{ops::Range, sync::Arc};
+
+    use aes_gcm::{
+        aead::{Aead, AeadCore, KeyInit, OsRng},
+        Aes128Gcm, Key, Nonce,
+    };
+    use bytes::Bytes;
+    use futures_core::future::BoxFuture;
+    use parquet::{
+        arrow::{arrow_reader::ArrowReaderOptions, async_reader::AsyncFileReader},
+        encryption::{
+            decrypt::{FileDecryptionProperties, KeyRetriever},
+            encrypt::FileEncryptionProperties,
+        },
+        errors::{ParquetError, Result as ParquetResult},
+        file::{metadata::ParquetMetaData, properties::WriterPropertiesBuilder},
+    };
+    use parquet_lru::{BoxedFileReader, DynLruCache};
+
+    use super::{EncryptionError, KeyProvider};
+    use crate::{fs::FileId, wal::NONCE_LEN, ParquetLru};
+
+    /// encrypt `plaintext` with the current key, returns the id of the key, the nonce and the
+    /// ciphertext
+    pub(crate) fn seal(
+        key_provider: &dyn KeyProvider,
+        plaintext: &[u8],
+    ) -> Result<(Vec<u8>, [u8; NONCE_LEN], Vec<u8>), EncryptionError> {
+        let (id, key) = key_provider.current_key()?;
+        let nonce = Aes128Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = Aes128Gcm::new(Key::<Aes128Gcm>::from_slice(&key))
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| EncryptionError::Encrypt)?;
+        let mut nonce_bytes = [0; NONCE_LEN];
+        nonce_bytes.copy_from_slice(&nonce);
+
+        Ok((id, nonce_bytes, ciphertext))
+    }
+
+    /// decrypt the `ciphertext` sealed with the key `id`
+    pub(crate) fn open(
+        key_provider: &dyn KeyProvider,
+        id: &[u8],
+        nonce: &[u8; NONCE_LEN],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, EncryptionError> {
+        let key = key_provider.key(id)?;
+        Aes128Gcm::new(Key::<Aes128Gcm>::from_slice(&key))
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| EncryptionError::Decrypt)
+    }
+
+    /// encrypt the SSTables written with `builder` with Parquet modular encryption, their footer
+    /// records the id of the key
+    pub(crate) fn encrypt_parquet(
+        builder: WriterPropertiesBuilder,
+        key_provider: &dyn KeyProvider,
+    ) -> ParquetResult<WriterPropertiesBuilder> {
+        let (id, key) = key_provider
+            .current_key()
+            .map_err(|err| ParquetError::External(Box::new(err)))?;
+        Ok(builder.with_file_encryption_properties(
+            FileEncryptionProperties::builder(key.to_vec())
+                .with_footer_key_metadata(id)
+                .build()?,
+        ))
+    }
+
+    /// the cache SSTables are opened with, which decrypts them with the keys of `key_provider`
+    pub(crate) fn cache(
+        parquet_lru: &ParquetLru,
+        key_provider: Arc<dyn KeyProvider>,
+    ) -> ParquetResult<ParquetLru> {
+        let decryption =
+            FileDecryptionProperties::with_key_retriever(Arc::new(Retriever(key_provider)))
+                .build()?;
+
+        Ok(Arc::new(DecryptingCache {
+            inner: parquet_lru.clone(),
+            decryption,
+        }))
+    }
+
+    struct Retriever(Arc<dyn KeyProvider>);
+
+    impl KeyRetriever for Retriever {
+        fn retrieve_key(&self, key_metadata: &[u8]) -> ParquetResult<Vec<u8>> {
+            self.0
+                .key(key_metadata)
+                .map(|key| key.to_vec())
+                .map_err(|err| ParquetError::External(Box::new(err)))
+        }
+    }
+
+    struct DecryptingCache {
+        inner: ParquetLru,
+        decryption: FileDecryptionProperties,
+    }
+
+    impl DynLruCache<FileId> for DecryptingCache {
+        fn get_reader(
+            &self,
+            key: FileId,
+            reader: BoxedFileReader,
+        ) -> BoxFuture<'_, BoxedFileReader> {
+            Box::pin(async move {
+                BoxedFileReader::new(DecryptingReader {
+                    inner: self.inner.get_reader(key, reader).await,
+                    decryption: self.decryption.clone(),
+                    options: None,
+                })
+            })
+        }
+    }
+
+    /// reads the metadata with the decryption properties, the columns are decrypted with them by
+    /// the reader of the metadata
+    struct DecryptingReader {
+        inner: BoxedFileReader,
+        decryption: FileDecryptionProperties,
+        options: Option<ArrowReaderOptions>,
+    }
+
+    impl AsyncFileReader for DecryptingReader {
+        fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+            self.inner.get_bytes(range)
+        }
+
+        fn get_metadata<'s>(
+            &'s mut self,
+            options: Option<&'s ArrowReaderOptions>,
+        ) -> BoxFuture<'s, ParquetResult<Arc<ParquetMetaData>>> {
+            let options = options
+                .cloned()
+                .unwrap_or_default()
+                .with_file_decryption_properties(self.decryption.clone());
+            let options = self.options.insert(options);
+            self.inner.get_metadata(Some(&*options))
+        }
+
+        fn get_byte_ranges(
+            &mut self,
+            ranges: Vec<Range<u64>>,
+        ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+            self.inner.get_byte_ranges(ranges)
+        }
+    }
+}
+
+/// without the `encryption` feature no [`KeyProvider`] can be set, so nothing is encrypted
+#[cfg(not(feature = "encryption"))]
+mod disabled {
+    use parquet::{errors::Result as ParquetResult, file::properties::WriterPropertiesBuilder};
+
+    use super::{EncryptionError, KeyProvider};
+    use crate::{wal::NONCE_LEN, ParquetLru};
+
+    const DISABLED: &str = "a key provider is only set with the `encryption` feature";
+
+    pub(crate) fn seal(
+        _: &dyn KeyProvider,
+        _: &[u8],
+    ) -> Result<(Vec<u8>, [u8; NONCE_LEN], Vec<u8>), EncryptionError> {
+        unreachable!("{DISABLED}")
+    }
+
+    pub(crate) fn open(
+        _: &dyn KeyProvider,
+        _: &[u8],
+        _: &[u8; NONCE_LEN],
+        _: &[u8],
+    ) -> Result<Vec<u8>, EncryptionError> {
+        unreachable!("{DISABLED}")
+    }
+
+    pub(crate) fn encrypt_parquet(
+        _: WriterPropertiesBuilder,
+        _: &dyn KeyProvider,
+    ) -> ParquetResult<WriterPropertiesBuilder> {
+        unreachable!("{DISABLED}")
+    }
+
+    pub(crate) fn cache(
+        _: &ParquetLru,
+        _: std::sync::Arc<dyn KeyProvider>,
+    ) -> ParquetResult<ParquetLru> {
+        unreachable!("{DISABLED}")
+    }
+}
+
+#[cfg(all(test, feature = "tokio", feature = "encryption"))]
+mod tests {
+    use std::{fs, path::Path as StdPath, pin::pin, sync::Arc};
+
+    use fusio::{disk::LocalFs, path::Path};
+    use fusio_log::FsOptions;
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+
+    use super::KeyRing;
+    use crate::{
+        executor::tokio::TokioExecutor,
+        fs::{generate_file_id, FileType},
+        inmem::immutable::tests::TestSchema,
+        tests::Test,
+        timestamp::Ts,
+        wal::{
+            log::{Log, LogType},
+            RecoverError, WalFile,
+        },
+        CompactionOption, DbOption, SizeRatioOption, DB,
+    };
+
+    fn contains(dir: &StdPath, needle: &[u8]) -> bool {
+        fs::read_dir(dir).unwrap().any(|entry| {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                contains(&path, needle)
+            } else {
+                let bytes = fs::read(path).unwrap();
+                bytes.windows(needle.len()).any(|window| window == needle)
+            }
+        })
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sealed_wal() {
+        let temp_dir = TempDir::new().unwrap();
+        let wal_id = generate_file_id();
+        let wal_path = Path::from_filesystem_path(temp_dir.path())
+            .unwrap()
+            .child(format!("{}.{}", wal_id, FileType::Wal));
+        let key_ring = Arc::new(KeyRing::new("k1", [1; 16]));
+
+        let mut wal = WalFile::<String>::new(
+            Arc::new(LocalFs {}),
+            wal_path.clone(),
+            0,
+            wal_id,
+            Some(key_ring.clone()),
+        )
+        .await;
+        wal.write(&Log::new(
+            Ts::new("secret".into(), 0.into()),
+            Some("secret".into()),
+            Some(LogType::Full),
+        ))
+        .await
+        .unwrap();
+        wal.flush().await.unwrap();
+        assert!(!contains(temp_dir.path(), b"secret"));
+
+        let mut stream = pin!(
+            WalFile::<String>::recover(FsOptions::Local, wal_path.clone(), Some(key_ring)).await
+        );
+        let logs = stream.next().await.unwrap().unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].key.value, "secret");
+        assert_eq!(logs[0].value.as_deref(), Some("secret"));
+
+        let mut stream = pin!(WalFile::<String>::recover(FsOptions::Local, wal_path, None).await);
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(RecoverError::Sealed))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rotate_key_on_compaction() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = |key_ring: KeyRing| {
+            let mut option = DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            )
+            .compaction_option(CompactionOption::SizeRatio(SizeRatioOption {
+                level0_table_num: 2,
+                ..Default::default()
+            }))
+            .encryption(Arc::new(key_ring));
+            option.immutable_chunk_num = 1;
+            option.immutable_chunk_max_num = 0;
+            option
+        };
+        let insert = |db: &DB<Test, TokioExecutor>, key: &'static str| {
+            db.insert(Test {
+                vstring: key.to_string(),
+                vu32: 1,
+                vbool: None,
+            })
+        };
+
+        let db: DB<Test, TokioExecutor> = DB::new(
+            option(KeyRing::new("k1", [1; 16])),
+            TokioExecutor::current(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+        insert(&db, "secret-a").await.unwrap();
+        db.flush().await.unwrap();
+        assert!(!contains(temp_dir.path(), b"secret-a"));
+        drop(db);
+
+        // the table of `k1` stays readable, the compaction rewrites it with `k2`
+        let db: DB<Test, TokioExecutor> = DB::new(
+            option(KeyRing::new("k1", [1; 16]).rotate("k2", [2; 16])),
+            TokioExecutor::current(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+        let get = |db: &DB<Test, TokioExecutor>, key: &'static str| {
+            db.get(&key.to_string(), |entry| entry.get().vu32)
+        };
+        assert_eq!(get(&db, "secret-a").await.unwrap(), Some(1));
+        insert(&db, "secret-b").await.unwrap();
+        db.flush().await.unwrap();
+        let version = db.ctx.version_set().current().await;
+        assert_eq!(version.tables_len(0), 0);
+        assert_eq!(version.tables_len(1), 1);
+        drop(version);
+        assert!(!contains(temp_dir.path(), b"secret-b"));
+        drop(db);
+
+        let db: DB<Test, TokioExecutor> = DB::new(
+            option(KeyRing::new("k2", [2; 16])),
+            TokioExecutor::current(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+        assert_eq!(get(&db, "secret-a").await.unwrap(), Some(1));
+        assert_eq!(get(&db, "secret-b").await.unwrap(), Some(1));
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt::Debug;
+
+use crate::{
+    fs::{manager::StoreManager, FileId, FileType},
+    DbOption,
+};
+
+/// An SSTable written or read by a flush or a compaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableInfo {
+    pub gen: FileId,
+    pub level: usize,
+    /// size of the file in bytes
+    pub size: u64,
+}
+
+/// A flush of immutable memtables into a table of level 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlushInfo {
+    /// number of immutable memtables flushed
+    pub memtable_num: usize,
+    /// the table written, `None` when the flush begins
+    pub output: Option<TableInfo>,
+}
+
+/// A compaction of tables of `level` and `output_level` into tables of `output_level`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionInfo {
+    pub level: usize,
+    pub output_level: usize,
+    pub inputs: Vec<TableInfo>,
+    /// the tables written, empty when the compaction begins
+    pub outputs: Vec<TableInfo>,
+}
+
+/// Receives flush, compaction and table deletion events of a [`DB`](crate::DB), see
+/// [`DbOption::event_listener`](crate::DbOption::event_listener).
+///
+/// Callbacks run on the compaction and cleaner tasks and block them, implementations should hand
+/// the events off instead of doing slow work. Flushes and compactions are completed once their
+/// tables are written, right before the version adding them is applied. The tables a compaction
+/// replaces are deleted later, once no snapshot reads them.
+pub trait EventListener: Debug + Send + Sync {
+    fn on_flush_begin(&self, _info: &FlushInfo) {}
+
+    fn on_flush_completed(&self, _info: &FlushInfo) {}
+
+    fn on_compaction_begin(&self, _info: &CompactionInfo) {}
+
+    fn on_compaction_completed(&self, _info: &CompactionInfo) {}
+
+    /// the file of the table `gen` of `level` is removed
+    fn on_table_deleted(&self, _gen: FileId, _level: usize) {}
+}
+
+/// [`TableInfo`] of the table `gen` of `level`, the file is opened to read its size
+pub(crate) async fn table_info(
+    option: &DbOption,
+    manager: &StoreManager,
+    gen: FileId,
+    level: usize,
+) -> Result<TableInfo, fusio::Error> {
+    let level_path = option.level_fs_path(level).unwrap_or(&option.base_path);
+    let file = manager
+        .get_fs(level_path)
+        .open_options(
+            &option.table_path(gen, level),
+            FileType::Parquet.open_options(true),
+        )
+        .await?;
+
+    Ok(TableInfo {
+        gen,
+        level,
+        size: file.size().await?,
+    })
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::{CompactionInfo, EventListener, FlushInfo};
+    use crate::{
+        executor::tokio::TokioExecutor, fs::FileId, inmem::immutable::tests::TestSchema,
+        tests::Test, CompactionOption, DbOption, SizeRatioOption, DB,
+    };
+
+    #[derive(Debug, Default)]
+    struct Recorder {
+        flushes: Mutex<Vec<FlushInfo>>,
+        compactions: Mutex<Vec<CompactionInfo>>,
+        deleted: Mutex<Vec<(FileId, usize)>>,
+    }
+
+    impl EventListener for Recorder {
+        fn on_flush_completed(&self, info: &FlushInfo) {
+            self.flushes.lock().unwrap().push(info.clone());
+        }
+
+        fn on_compaction_begin(&self, info: &CompactionInfo) {
+            assert!(info.outputs.is_empty());
+        }
+
+        fn on_compaction_completed(&self, info: &CompactionInfo) {
+            self.compactions.lock().unwrap().push(info.clone());
+        }
+
+        fn on_table_deleted(&self, gen: FileId, level: usize) {
+            self.deleted.lock().unwrap().push((gen, level));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn event_listener() {
+        let temp_dir = TempDir::new().unwrap();
+        let recorder = Arc::new(Recorder::default());
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .compaction_option(CompactionOption::SizeRatio(SizeRatioOption {
+            level0_table_num: 2,
+            ..Default::default()
+        }))
+        .event_listener(recorder.clone());
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        for key in ["a", "b"] {
+            db.insert(Test {
+                vstring: key.to_string(),
+                vu32: 1,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+            db.flush().await.unwrap();
+        }
+
+        let flushes = recorder.flushes.lock().unwrap().clone();
+        assert_eq!(flushes.len(), 2);
+        assert!(flushes
+            .iter()
+            .all(|flush| flush.output.as_ref().is_some_and(|table| table.size > 0)));
+
+        // the second flush compacts both tables of level 0 into level 1
+        let compactions = recorder.compactions.lock().unwrap().clone();
+        assert_eq!(compactions.len(), 1);
+        let compaction = &compactions[0];
+        assert_eq!((compaction.level, compaction.output_level), (0, 1));
+        let mut inputs = compaction
+            .inputs
+            .iter()
+            .map(|table| (table.gen, table.level))
+            .collect::<Vec<_>>();
+        inputs.sort();
+        let mut flushed = flushes
+            .iter()
+            .map(|flush| (flush.output.as_ref().unwrap().gen, 0))
+            .collect::<Vec<_>>();
+        flushed.sort();
+        assert_eq!(inputs, flushed);
+        assert_eq!(compaction.outputs.len(), 1);
+        assert_eq!(compaction.outputs[0].level, 1);
+
+        // the inputs are deleted once the version reading them is dropped
+        for _ in 0..50 {
+            if recorder.deleted.lock().unwrap().len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let mut deleted = recorder.deleted.lock().unwrap().clone();
+        deleted.sort();
+        assert_eq!(deleted, flushed);
+    }
+}
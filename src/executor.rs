@@ -1,11 +1,72 @@
-use std::future::Future;
+use std::{future::Future, time::Duration};
 
 use fusio::MaybeSend;
 
+pub mod pool;
+
+/// names of the background tasks spawned by [`DB`](crate::DB), see [`Executor::spawn_named`]
+pub mod task {
+    /// commits the writes of an idle
+    /// [`BufferedWriter`](crate::write_batch::BufferedWriter) once they are older than its delay
+    pub const BUFFERED_WRITER: &str = "tonbo::buffered_writer";
+    /// removes the files of the SSTables no version refers to anymore
+    pub const CLEANER: &str = "tonbo::cleaner";
+    /// flushes the memtables and compacts the SSTables
+    pub const COMPACTION: &str = "tonbo::compaction";
+    /// freezes the memtables older than
+    /// [`FlushPolicy::max_age`](crate::FlushPolicy::max_age)
+    pub const FLUSH: &str = "tonbo::flush";
+    /// refreshes the version of a follower from the manifest of its primary
+    pub const FOLLOWER: &str = "tonbo::follower";
+    /// removes the orphan files, see [`DbOption::gc_interval`](crate::DbOption::gc_interval)
+    pub const GC: &str = "tonbo::gc";
+    /// renews the lease of [`DbOption::lease`](crate::DbOption::lease)
+    pub const LEASE: &str = "tonbo::lease";
+}
+
 pub trait Executor {
     fn spawn<F>(&self, future: F)
     where
         F: Future<Output = ()> + MaybeSend + 'static;
+
+    /// spawn the background task `name`, one of [`task`]. [`DB`](crate::DB) instruments the
+    /// future with a `tonbo_task` tracing span carrying the name.
+    ///
+    /// Override it to name, count or limit the background tasks, it spawns `future` like any
+    /// other by default.
+    fn spawn_named<F>(&self, name: &'static str, future: F)
+    where
+        F: Future<Output = ()> + MaybeSend + 'static,
+    {
+        let _ = name;
+        self.spawn(future)
+    }
+}
+
+/// Wait for `duration` on the timer of the runtime of the enabled feature, `tokio`, `async-std`
+/// or `smol` in this order, or on a thread of its own without any of them. Returns at once on
+/// wasm32, where the crate has no timer.
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(feature = "tokio")]
+    ::tokio::time::sleep(duration).await;
+    #[cfg(all(not(feature = "tokio"), feature = "async-std"))]
+    ::async_std::task::sleep(duration).await;
+    #[cfg(all(not(any(feature = "tokio", feature = "async-std")), feature = "smol"))]
+    {
+        ::smol::Timer::after(duration).await;
+    }
+    #[cfg(not(any(
+        feature = "tokio",
+        feature = "async-std",
+        feature = "smol",
+        target_arch = "wasm32"
+    )))]
+    thread::ThreadSleep::new(duration).await;
+    #[cfg(all(
+        not(any(feature = "tokio", feature = "async-std", feature = "smol")),
+        target_arch = "wasm32"
+    ))]
+    let _ = duration;
 }
 
 #[cfg(feature = "tokio")]
@@ -37,6 +98,212 @@ pub mod tokio {
         {
             self.handle.spawn(future);
         }
+
+        /// tasks are named in tokio-console when built with `--cfg tokio_unstable`
+        fn spawn_named<F>(&self, name: &'static str, future: F)
+        where
+            F: Future<Output = ()> + MaybeSend + 'static,
+        {
+            #[cfg(tokio_unstable)]
+            {
+                tokio::task::Builder::new()
+                    .name(name)
+                    .spawn_on(future, &self.handle)
+                    .expect("failed to spawn the task");
+            }
+            #[cfg(not(tokio_unstable))]
+            {
+                let _ = name;
+                self.handle.spawn(future);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async-std")]
+pub mod async_std {
+    use std::future::Future;
+
+    use async_std::task;
+    use fusio::MaybeSend;
+
+    use super::Executor;
+
+    /// Spawns on the runtime of async-std.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct AsyncStdExecutor;
+
+    impl Executor for AsyncStdExecutor {
+        fn spawn<F>(&self, future: F)
+        where
+            F: Future<Output = ()> + MaybeSend + 'static,
+        {
+            task::spawn(future);
+        }
+
+        fn spawn_named<F>(&self, name: &'static str, future: F)
+        where
+            F: Future<Output = ()> + MaybeSend + 'static,
+        {
+            task::Builder::new()
+                .name(name.to_string())
+                .spawn(future)
+                .expect("failed to spawn the task");
+        }
+    }
+}
+
+#[cfg(feature = "smol")]
+pub mod smol {
+    use std::{future::Future, sync::Arc};
+
+    use fusio::MaybeSend;
+
+    use super::Executor;
+
+    /// Spawns on an executor of smol, its global executor by default.
+    #[derive(Debug, Clone, Default)]
+    pub struct SmolExecutor {
+        executor: Option<Arc<smol::Executor<'static>>>,
+    }
+
+    impl SmolExecutor {
+        /// spawn on `executor`, driven by the application
+        pub fn new(executor: Arc<smol::Executor<'static>>) -> Self {
+            Self {
+                executor: Some(executor),
+            }
+        }
+    }
+
+    impl Executor for SmolExecutor {
+        fn spawn<F>(&self, future: F)
+        where
+            F: Future<Output = ()> + MaybeSend + 'static,
+        {
+            match &self.executor {
+                Some(executor) => executor.spawn(future).detach(),
+                None => smol::spawn(future).detach(),
+            }
+        }
+    }
+}
+
+/// An executor without any async runtime, for the applications which do not use one.
+#[cfg(not(target_arch = "wasm32"))]
+pub mod thread {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::Arc,
+        task::{Context, Poll, Wake, Waker},
+        thread::{self, Thread},
+    };
+
+    use fusio::MaybeSend;
+
+    use super::Executor;
+
+    /// Runs every future spawned on a thread of its own until it completes.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct ThreadExecutor;
+
+    impl Executor for ThreadExecutor {
+        fn spawn<F>(&self, future: F)
+        where
+            F: Future<Output = ()> + MaybeSend + 'static,
+        {
+            thread::spawn(move || block_on(future));
+        }
+
+        /// the threads are named after the tasks
+        fn spawn_named<F>(&self, name: &'static str, future: F)
+        where
+            F: Future<Output = ()> + MaybeSend + 'static,
+        {
+            thread::Builder::new()
+                .name(name.to_string())
+                .spawn(move || block_on(future))
+                .expect("failed to spawn the thread");
+        }
+    }
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// run `future` to its end on the current thread, parking it while the future waits
+    pub fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[cfg(not(any(feature = "tokio", feature = "async-std", feature = "smol")))]
+    pub(crate) use timer::ThreadSleep;
+
+    #[cfg(not(any(feature = "tokio", feature = "async-std", feature = "smol")))]
+    mod timer {
+        use std::{
+            future::Future,
+            pin::Pin,
+            sync::{Arc, Mutex},
+            task::{Context, Poll, Waker},
+            thread,
+            time::{Duration, Instant},
+        };
+
+        /// A timer waking its task from a thread of its own.
+        pub(crate) struct ThreadSleep {
+            deadline: Instant,
+            waker: Option<Arc<Mutex<Waker>>>,
+        }
+
+        impl ThreadSleep {
+            pub(crate) fn new(duration: Duration) -> Self {
+                Self {
+                    deadline: Instant::now() + duration,
+                    waker: None,
+                }
+            }
+        }
+
+        impl Future for ThreadSleep {
+            type Output = ();
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+                if Instant::now() >= self.deadline {
+                    return Poll::Ready(());
+                }
+                match &self.waker {
+                    Some(waker) => waker.lock().unwrap().clone_from(cx.waker()),
+                    None => {
+                        let waker = Arc::new(Mutex::new(cx.waker().clone()));
+                        let deadline = self.deadline;
+                        self.waker = Some(waker.clone());
+                        thread::spawn(move || {
+                            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                            waker.lock().unwrap().wake_by_ref();
+                        });
+                    }
+                }
+                Poll::Pending
+            }
+        }
     }
 }
 
@@ -73,3 +340,79 @@ pub mod opfs {
         }
     }
 }
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{
+        future::Future,
+        sync::{Arc, Mutex},
+    };
+
+    use fusio::{path::Path, MaybeSend};
+    use tempfile::TempDir;
+
+    use super::{
+        task,
+        thread::{block_on, ThreadExecutor},
+        tokio::TokioExecutor,
+        Executor,
+    };
+    use crate::{inmem::immutable::tests::TestSchema, tests::Test, DbOption, DB};
+
+    #[derive(Clone)]
+    struct NamingExecutor {
+        inner: TokioExecutor,
+        names: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Executor for NamingExecutor {
+        fn spawn<F>(&self, future: F)
+        where
+            F: Future<Output = ()> + MaybeSend + 'static,
+        {
+            self.inner.spawn(future)
+        }
+
+        fn spawn_named<F>(&self, name: &'static str, future: F)
+        where
+            F: Future<Output = ()> + MaybeSend + 'static,
+        {
+            self.names.lock().unwrap().push(name);
+            self.inner.spawn_named(name, future)
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn background_tasks_are_named() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let executor = NamingExecutor {
+            inner: TokioExecutor::current(),
+            names: Arc::default(),
+        };
+        let db: DB<Test, NamingExecutor> =
+            DB::new(option, executor.clone(), TestSchema).await.unwrap();
+        db.flush().await.unwrap();
+
+        assert_eq!(
+            *executor.names.lock().unwrap(),
+            vec![task::CLEANER, task::COMPACTION]
+        );
+    }
+    #[test]
+    fn thread_executor() {
+        let (tx, rx) = flume::bounded(1);
+        ThreadExecutor.spawn_named(task::GC, async move {
+            tx.send_async(std::thread::current().name().map(str::to_string))
+                .await
+                .unwrap();
+        });
+        assert_eq!(
+            block_on(rx.recv_async()).unwrap(),
+            Some(task::GC.to_string())
+        );
+    }
+}
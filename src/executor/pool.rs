@@ -0,0 +1,273 @@
+//! Scheduling of the background work of [`DB`](crate::DB)s on a shared [`TaskPool`].
+
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Formatter},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+/// The kinds of background work, from the most urgent to the least. Waiting work starts before
+/// any less urgent work does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// freezes and flushes of the memtables, along with the compactions of level 0 the
+    /// [`CompactionOption::Leveled`](crate::CompactionOption::Leveled) compactor runs as it
+    /// flushes. Writes stall on the memtables while flushes wait.
+    Flush,
+    /// compactions of the SSTables apart from a flush
+    Compaction,
+    /// removals of the orphan files, see [`DbOption::gc_interval`](crate::DbOption::gc_interval)
+    Gc,
+}
+
+const PRIORITIES: [Priority; 3] = [Priority::Flush, Priority::Compaction, Priority::Gc];
+
+/// A pool the background work of the [`DB`](crate::DB)s sharing it runs in, see
+/// [`DbOption::task_pool`](crate::DbOption::task_pool).
+///
+/// The pool limits how much of the work runs at once, in total and for each [`Priority`], and
+/// starts the most urgent work first. [`TaskPool::pause`] holds back the work while the traffic
+/// peaks, the work already started runs to its end.
+///
+/// ```no_run
+/// use tonbo::executor::pool::{Priority, TaskPool};
+///
+/// let pool = TaskPool::new(2).limit(Priority::Compaction, 1);
+/// // only flushes start until the pool is resumed
+/// pool.pause(Priority::Compaction);
+/// pool.resume();
+/// ```
+#[derive(Clone)]
+pub struct TaskPool {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    max_concurrency: usize,
+    limits: [usize; PRIORITIES.len()],
+    running: [usize; PRIORITIES.len()],
+    waiting: [usize; PRIORITIES.len()],
+    paused: Option<Priority>,
+    wakers: HashMap<u64, Waker>,
+    next_waiter: u64,
+}
+
+impl State {
+    fn is_runnable(&self, priority: Priority) -> bool {
+        !self.paused.is_some_and(|paused| priority >= paused)
+            && self.running[priority as usize] < self.limits[priority as usize]
+            && self.running.iter().sum::<usize>() < self.max_concurrency
+    }
+
+    /// whether work of `priority` is runnable and no more urgent work waits to run
+    fn can_start(&self, priority: Priority) -> bool {
+        self.is_runnable(priority)
+            && PRIORITIES[..priority as usize]
+                .iter()
+                .all(|&urgent| self.waiting[urgent as usize] == 0 || !self.is_runnable(urgent))
+    }
+
+    fn wake_all(&mut self) {
+        for (_, waker) in self.wakers.drain() {
+            waker.wake();
+        }
+    }
+}
+
+impl TaskPool {
+    /// a pool running up to `max_concurrency` works at once
+    pub fn new(max_concurrency: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                max_concurrency,
+                limits: [usize::MAX; PRIORITIES.len()],
+                running: [0; PRIORITIES.len()],
+                waiting: [0; PRIORITIES.len()],
+                paused: None,
+                wakers: HashMap::new(),
+                next_waiter: 0,
+            })),
+        }
+    }
+
+    /// run up to `limit` works of `priority` at once, unlimited by default
+    pub fn limit(self, priority: Priority, limit: usize) -> Self {
+        self.set_limit(priority, limit);
+        self
+    }
+
+    /// change the limit of `priority`, see [`TaskPool::limit`]
+    pub fn set_limit(&self, priority: Priority, limit: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.limits[priority as usize] = limit;
+        state.wake_all();
+    }
+
+    /// hold back the work of `priority` and of the less urgent ones until [`TaskPool::resume`]
+    pub fn pause(&self, priority: Priority) {
+        self.state.lock().unwrap().paused = Some(priority);
+    }
+
+    /// start the work held back by [`TaskPool::pause`]
+    pub fn resume(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.paused = None;
+        state.wake_all();
+    }
+
+    /// the most urgent priority held back along with the less urgent ones, `None` unless paused
+    pub fn paused(&self) -> Option<Priority> {
+        self.state.lock().unwrap().paused
+    }
+
+    /// works of `priority` running
+    pub fn running(&self, priority: Priority) -> usize {
+        self.state.lock().unwrap().running[priority as usize]
+    }
+
+    /// wait for the work of `priority` to be allowed to run, it runs until the permit is dropped
+    pub async fn acquire(&self, priority: Priority) -> TaskPermit {
+        Acquire {
+            pool: self,
+            priority,
+            waiter: None,
+        }
+        .await
+    }
+}
+
+impl Debug for TaskPool {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("TaskPool")
+            .field("max_concurrency", &state.max_concurrency)
+            .field("limits", &state.limits)
+            .field("running", &state.running)
+            .field("paused", &state.paused)
+            .finish()
+    }
+}
+
+struct Acquire<'a> {
+    pool: &'a TaskPool,
+    priority: Priority,
+    /// the id of the waker once it waits
+    waiter: Option<u64>,
+}
+
+impl Future for Acquire<'_> {
+    type Output = TaskPermit;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let (pool, priority) = (self.pool, self.priority);
+        let mut state = pool.state.lock().unwrap();
+        if state.can_start(priority) {
+            if let Some(waiter) = self.waiter.take() {
+                state.waiting[priority as usize] -= 1;
+                state.wakers.remove(&waiter);
+            }
+            state.running[priority as usize] += 1;
+            return Poll::Ready(TaskPermit {
+                pool: pool.clone(),
+                priority,
+            });
+        }
+        let waiter = match self.waiter {
+            Some(waiter) => waiter,
+            None => {
+                state.waiting[priority as usize] += 1;
+                state.next_waiter += 1;
+                state.next_waiter
+            }
+        };
+        state.wakers.insert(waiter, cx.waker().clone());
+        drop(state);
+        self.waiter = Some(waiter);
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter {
+            let mut state = self.pool.state.lock().unwrap();
+            state.waiting[self.priority as usize] -= 1;
+            state.wakers.remove(&waiter);
+            // the less urgent work may have waited for it
+            state.wake_all();
+        }
+    }
+}
+
+/// The permit of a work of the [`TaskPool`] to run, see [`TaskPool::acquire`].
+#[derive(Debug)]
+pub struct TaskPermit {
+    pool: TaskPool,
+    priority: Priority,
+}
+
+impl Drop for TaskPermit {
+    fn drop(&mut self) {
+        let mut state = self.pool.state.lock().unwrap();
+        state.running[self.priority as usize] -= 1;
+        state.wake_all();
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::FutureExt;
+
+    use super::{Priority, TaskPool};
+
+    #[tokio::test]
+    async fn urgent_work_first() {
+        let pool = TaskPool::new(1);
+        let permit = pool.acquire(Priority::Gc).await;
+        assert_eq!(pool.running(Priority::Gc), 1);
+
+        let gc = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.acquire(Priority::Gc).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let flush = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.acquire(Priority::Flush).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        drop(permit);
+        let flush = flush.await.unwrap();
+        assert!(!gc.is_finished());
+        drop(flush);
+        gc.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn pause_and_limit() {
+        let pool = TaskPool::new(4).limit(Priority::Compaction, 1);
+        let compaction = pool.acquire(Priority::Compaction).await;
+        assert!(pool.acquire(Priority::Compaction).now_or_never().is_none());
+        drop(compaction);
+
+        pool.pause(Priority::Compaction);
+        assert_eq!(pool.paused(), Some(Priority::Compaction));
+        assert!(pool.acquire(Priority::Gc).now_or_never().is_none());
+        assert!(pool.acquire(Priority::Flush).now_or_never().is_some());
+
+        let gc = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.acquire(Priority::Gc).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!gc.is_finished());
+        pool.resume();
+        gc.await.unwrap();
+    }
+}
@@ -0,0 +1,677 @@
+use std::{collections::BTreeMap, fmt::Write as _, ops::Bound, pin::pin, sync::Arc};
+
+use arrow::{
+    array::{Array, RecordBatch, UInt32Array},
+    compute::take_record_batch,
+    datatypes::SchemaRef,
+    util::display::{ArrayFormatter, FormatOptions},
+};
+use fusio::{fs::OpenOptions, path::Path, DynFs, Read, Write};
+use fusio_parquet::writer::AsyncWriter;
+use futures_util::StreamExt;
+use parquet::{arrow::AsyncArrowWriter, errors::ParquetError, file::properties::WriterProperties};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    executor::Executor,
+    fs::FileType,
+    magic::USER_COLUMN_OFFSET,
+    record::{Record, Schema},
+    timestamp::Timestamp,
+    DbError, DB,
+};
+
+/// name of the manifest of an export, next to its data files
+pub const MANIFEST: &str = "MANIFEST";
+
+const MANIFEST_HEADER: &str = "tonbo-export v1";
+
+/// rows of the batches read from the snapshot
+const EXPORT_BATCH_SIZE: usize = 8192;
+
+/// files are hashed by chunks of this size
+const DIGEST_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// directory of the rows without a partition value, as Hive names it
+const HIVE_DEFAULT_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// Data files of an export and the timestamp they were read at, written by
+/// [`DB::export_consistent`] and checked by [`verify_export`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportManifest {
+    pub ts: Timestamp,
+    pub files: Vec<ExportedFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedFile {
+    /// path of the file relative to the export directory
+    pub name: String,
+    pub rows: usize,
+    /// size in bytes
+    pub size: u64,
+    /// lowercase hex SHA-256 digest of the file
+    pub sha256: String,
+}
+
+/// configure the files written by [`DB::export_parquet`]
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    pub(crate) partition_by: Option<String>,
+    pub(crate) max_file_size: Option<usize>,
+    pub(crate) properties: Option<WriterProperties>,
+}
+
+impl ExportOptions {
+    /// write the rows in a directory `column=value` per value of `column`, rows without a value
+    /// in `column=__HIVE_DEFAULT_PARTITION__`
+    pub fn partition_by(self, column: impl Into<String>) -> Self {
+        Self {
+            partition_by: Some(column.into()),
+            ..self
+        }
+    }
+
+    /// start a new file once a file reaches `max_file_size` bytes, instead of
+    /// [`DbOption::max_sst_file_size`](crate::DbOption::max_sst_file_size)
+    pub fn max_file_size(self, max_file_size: usize) -> Self {
+        Self {
+            max_file_size: Some(max_file_size),
+            ..self
+        }
+    }
+
+    /// write the files with `properties`, e.g. their compression
+    pub fn writer_properties(self, properties: WriterProperties) -> Self {
+        Self {
+            properties: Some(properties),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("export fusio error: {0}")]
+    Fusio(#[from] fusio::Error),
+    #[error("malformed export manifest: {0}")]
+    Manifest(String),
+    #[error("exported file {0} does not match the manifest")]
+    Mismatch(String),
+    #[error("file {0} is not listed in the export manifest")]
+    Unlisted(String),
+}
+
+impl ExportManifest {
+    fn encode(&self) -> String {
+        let mut manifest = format!("{MANIFEST_HEADER}\nts {}\n", u32::from(self.ts));
+        for file in &self.files {
+            // SAFETY: writing to a String is infallible
+            writeln!(
+                manifest,
+                "file {} {} {} {}",
+                file.name, file.rows, file.size, file.sha256
+            )
+            .unwrap();
+        }
+        manifest
+    }
+
+    fn decode(manifest: &str) -> Result<Self, ExportError> {
+        let malformed = |line: &str| ExportError::Manifest(line.to_string());
+        let mut lines = manifest.lines();
+        if lines.next() != Some(MANIFEST_HEADER) {
+            return Err(malformed("missing header"));
+        }
+        let ts = lines
+            .next()
+            .and_then(|line| line.strip_prefix("ts "))
+            .and_then(|ts| ts.parse::<u32>().ok())
+            .ok_or_else(|| malformed("missing timestamp"))?;
+
+        let files = lines
+            .map(|line| {
+                let fields = line
+                    .strip_prefix("file ")
+                    .map(|file| file.split(' ').collect::<Vec<_>>())
+                    .ok_or_else(|| malformed(line))?;
+                let [name, rows, size, sha256] = fields[..] else {
+                    return Err(malformed(line));
+                };
+                Ok(ExportedFile {
+                    name: name.to_string(),
+                    rows: rows.parse().map_err(|_| malformed(line))?,
+                    size: size.parse().map_err(|_| malformed(line))?,
+                    sha256: sha256.to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            ts: ts.into(),
+            files,
+        })
+    }
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Export the records visible at `ts` to Parquet files in the directory `path` of the base
+    /// fs, along with a [`MANIFEST`] holding `ts` and the SHA-256 digest of every file.
+    ///
+    /// The data files hold the columns of the schema without `_null` and `_ts`, so they can be
+    /// loaded with [`DB::ingest_parquet`]. Anyone holding the files can check them against the
+    /// manifest with [`verify_export`].
+    ///
+    /// # Error
+    /// Returns [`DbError::OutOfRetention`] if `ts` is older than
+    /// [`DbOption::time_travel_retention`](crate::DbOption::time_travel_retention) allows.
+    pub async fn export_consistent(
+        &self,
+        path: &Path,
+        ts: Timestamp,
+    ) -> Result<ExportManifest, DbError<R>> {
+        let max_file_size = self.schema.read().await.option.max_sst_file_size;
+        let fs = self.ctx.manager.base_fs().clone();
+        let full_schema = self.ctx.arrow_schema().clone();
+        let indices = (USER_COLUMN_OFFSET..full_schema.fields().len()).collect::<Vec<_>>();
+        let schema = Arc::new(full_schema.project(&indices).map_err(ParquetError::from)?);
+        fs.create_dir_all(path).await?;
+
+        let snapshot = self.snapshot_at(ts).await?;
+        let mut stream = pin!(
+            snapshot
+                .scan((Bound::Unbounded, Bound::Unbounded))
+                .scan_batches(EXPORT_BATCH_SIZE)
+                .await?
+        );
+        let mut writer = ExportWriter::new(fs, path.clone(), None, schema, None, max_file_size);
+        while let Some(batch) = stream.next().await.transpose()? {
+            writer
+                .write(&batch.project(&indices).map_err(ParquetError::from)?)
+                .await?;
+        }
+        let files = writer.finish().await?;
+
+        let manifest = ExportManifest { ts, files };
+        let mut manifest_file = fs
+            .open_options(
+                &path.child(MANIFEST),
+                OpenOptions::default()
+                    .create(true)
+                    .write(true)
+                    .truncate(true),
+            )
+            .await?;
+        let (result, _) = manifest_file
+            .write_all(manifest.encode().into_bytes())
+            .await;
+        result?;
+        manifest_file.close().await?;
+
+        Ok(manifest)
+    }
+
+    /// Export the latest version of every record to plain Parquet files in the directory `path`
+    /// of the base fs, for engines like Spark or DuckDB to read: the files hold the columns of
+    /// the schema without `_null` and `_ts`, and neither tombstones nor overwritten versions.
+    ///
+    /// With [`ExportOptions::partition_by`], the files are partitioned Hive-style by a column,
+    /// which is written in the directory names instead of the files. Returns the written files
+    /// and the timestamp they were read at, no [`MANIFEST`] is written.
+    ///
+    /// # Error
+    /// Returns [`DbError::InvalidExport`] if the partition column is not a column of the schema.
+    pub async fn export_parquet(
+        &self,
+        path: &Path,
+        options: ExportOptions,
+    ) -> Result<ExportManifest, DbError<R>> {
+        let max_file_size = match options.max_file_size {
+            Some(max_file_size) => max_file_size,
+            None => self.schema.read().await.option.max_sst_file_size,
+        };
+        let fs = self.ctx.manager.base_fs().clone();
+        let full_schema = self.ctx.arrow_schema().clone();
+        let mut indices = (USER_COLUMN_OFFSET..full_schema.fields().len()).collect::<Vec<_>>();
+        let partition = match &options.partition_by {
+            Some(column) => {
+                let index = indices
+                    .iter()
+                    .position(|&index| full_schema.field(index).name() == column)
+                    .ok_or_else(|| {
+                        DbError::InvalidExport(format!("partition column {column} not found"))
+                    })?;
+                Some(indices.remove(index))
+            }
+            None => None,
+        };
+        let schema = Arc::new(full_schema.project(&indices).map_err(ParquetError::from)?);
+        fs.create_dir_all(path).await?;
+
+        let snapshot = self.snapshot().await;
+        let ts = snapshot.ts();
+        let mut stream = pin!(
+            snapshot
+                .scan((Bound::Unbounded, Bound::Unbounded))
+                .scan_batches(EXPORT_BATCH_SIZE)
+                .await?
+        );
+        let mut writers = BTreeMap::new();
+        while let Some(batch) = stream.next().await.transpose()? {
+            let Some(partition) = partition else {
+                let writer = writers.entry(None).or_insert_with(|| {
+                    ExportWriter::new(
+                        fs.clone(),
+                        path.clone(),
+                        None,
+                        schema.clone(),
+                        options.properties.clone(),
+                        max_file_size,
+                    )
+                });
+                writer
+                    .write(&batch.project(&indices).map_err(ParquetError::from)?)
+                    .await?;
+                continue;
+            };
+
+            let values = batch.column(partition);
+            let formatter = ArrayFormatter::try_new(values.as_ref(), &FormatOptions::default())
+                .map_err(ParquetError::from)?;
+            let mut rows = BTreeMap::<_, Vec<u32>>::new();
+            for row in 0..batch.num_rows() {
+                let value = if values.is_null(row) {
+                    HIVE_DEFAULT_PARTITION.to_string()
+                } else {
+                    formatter.value(row).to_string()
+                };
+                rows.entry(value).or_default().push(row as u32);
+            }
+            let column = full_schema.field(partition).name();
+            for (value, rows) in rows {
+                let dir = format!("{column}={value}");
+                let writer = writers.entry(Some(dir.clone())).or_insert_with(|| {
+                    ExportWriter::new(
+                        fs.clone(),
+                        path.clone(),
+                        Some(dir),
+                        schema.clone(),
+                        options.properties.clone(),
+                        max_file_size,
+                    )
+                });
+                let rows = take_record_batch(&batch, &UInt32Array::from(rows))
+                    .and_then(|rows| rows.project(&indices))
+                    .map_err(ParquetError::from)?;
+                writer.write(&rows).await?;
+            }
+        }
+
+        let mut files = Vec::new();
+        for (_, writer) in writers {
+            files.extend(writer.finish().await?);
+        }
+        Ok(ExportManifest { ts, files })
+    }
+}
+
+/// Check the export in the directory `path` of `fs` against its [`MANIFEST`]: every file listed
+/// must have the recorded size and digest, and no other Parquet file may be in the directory.
+pub async fn verify_export(fs: &dyn DynFs, path: &Path) -> Result<ExportManifest, ExportError> {
+    let mut manifest_file = fs
+        .open_options(&path.child(MANIFEST), FileType::Parquet.open_options(true))
+        .await?;
+    let (result, manifest) = manifest_file.read_to_end_at(Vec::new(), 0).await;
+    result?;
+    let manifest = String::from_utf8(manifest)
+        .map_err(|_| ExportError::Manifest("not valid UTF-8".to_string()))?;
+    let manifest = ExportManifest::decode(&manifest)?;
+
+    for file in &manifest.files {
+        let (size, sha256) = digest(fs, &path.child(file.name.as_str())).await?;
+        if size != file.size || sha256 != file.sha256 {
+            return Err(ExportError::Mismatch(file.name.clone()));
+        }
+    }
+    let mut entries = fs.list(path).await?;
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        let Some(name) = entry.path.filename() else {
+            continue;
+        };
+        if name.ends_with(".parquet") && !manifest.files.iter().any(|file| file.name == name) {
+            return Err(ExportError::Unlisted(name.to_string()));
+        }
+    }
+
+    Ok(manifest)
+}
+
+/// Parquet files of an export in the directory `dir` of `path`, a new file is started once a
+/// file reaches `max_file_size`
+struct ExportWriter {
+    fs: Arc<dyn DynFs>,
+    path: Path,
+    dir: Option<String>,
+    schema: SchemaRef,
+    properties: Option<WriterProperties>,
+    max_file_size: usize,
+    file: Option<(String, AsyncArrowWriter<AsyncWriter>, usize)>,
+    files: Vec<ExportedFile>,
+}
+
+impl ExportWriter {
+    fn new(
+        fs: Arc<dyn DynFs>,
+        path: Path,
+        dir: Option<String>,
+        schema: SchemaRef,
+        properties: Option<WriterProperties>,
+        max_file_size: usize,
+    ) -> Self {
+        Self {
+            fs,
+            path,
+            dir,
+            schema,
+            properties,
+            max_file_size,
+            file: None,
+            files: Vec::new(),
+        }
+    }
+
+    /// the path of the file `name` of the directory
+    fn file_path(&self, name: &str) -> Path {
+        match &self.dir {
+            Some(dir) => self.path.child(dir.as_str()).child(name),
+            None => self.path.child(name),
+        }
+    }
+
+    async fn write(&mut self, batch: &RecordBatch) -> Result<(), ParquetError> {
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+        if self.file.is_none() {
+            let name = format!("data-{:06}.parquet", self.files.len());
+            if let Some(dir) = &self.dir {
+                self.fs
+                    .create_dir_all(&self.path.child(dir.as_str()))
+                    .await
+                    .map_err(|err| ParquetError::External(Box::new(err)))?;
+            }
+            let file = self
+                .fs
+                .open_options(
+                    &self.file_path(&name),
+                    FileType::Parquet.open_options(false),
+                )
+                .await
+                .map_err(|err| ParquetError::External(Box::new(err)))?;
+            let writer = AsyncArrowWriter::try_new(
+                AsyncWriter::new(file),
+                self.schema.clone(),
+                self.properties.clone(),
+            )?;
+            self.file = Some((name, writer, 0));
+        }
+        // SAFETY: the file is opened above
+        let (_, writer, rows) = self.file.as_mut().unwrap();
+        writer.write(batch).await?;
+        *rows += batch.num_rows();
+        if writer.bytes_written() + writer.in_progress_size() >= self.max_file_size {
+            self.close_file().await?;
+        }
+        Ok(())
+    }
+
+    async fn close_file(&mut self) -> Result<(), ParquetError> {
+        if let Some((name, writer, rows)) = self.file.take() {
+            writer.close().await?;
+            let (size, sha256) = digest(self.fs.as_ref(), &self.file_path(&name))
+                .await
+                .map_err(|err| ParquetError::External(Box::new(err)))?;
+            let name = match &self.dir {
+                Some(dir) => format!("{dir}/{name}"),
+                None => name,
+            };
+            self.files.push(ExportedFile {
+                name,
+                rows,
+                size,
+                sha256,
+            });
+        }
+        Ok(())
+    }
+
+    /// close the last file and return the written files
+    async fn finish(mut self) -> Result<Vec<ExportedFile>, ParquetError> {
+        self.close_file().await?;
+        Ok(self.files)
+    }
+}
+
+/// size and hex SHA-256 digest of the file at `path`
+async fn digest(fs: &dyn DynFs, path: &Path) -> Result<(u64, String), fusio::Error> {
+    let mut file = fs
+        .open_options(path, FileType::Parquet.open_options(true))
+        .await?;
+    let size = file.size().await?;
+    let mut hasher = Sha256::new();
+    let mut buf = Vec::new();
+    let mut pos = 0;
+    while pos < size {
+        let len = (size - pos).min(DIGEST_CHUNK_SIZE);
+        buf.resize(len as usize, 0);
+        let (result, chunk) = file.read_exact_at(buf, pos).await;
+        result?;
+        hasher.update(&chunk);
+        buf = chunk;
+        pos += len;
+    }
+
+    let sha256 = hasher
+        .finalize()
+        .iter()
+        .fold(String::new(), |mut sha256, byte| {
+            // SAFETY: writing to a String is infallible
+            write!(sha256, "{byte:02x}").unwrap();
+            sha256
+        });
+    Ok((size, sha256))
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::io::Write;
+
+    use arrow::{array::AsArray, datatypes::UInt32Type};
+    use fusio::{disk::LocalFs, path::Path};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use tempfile::TempDir;
+
+    use super::{
+        verify_export, ExportError, ExportManifest, ExportOptions, ExportedFile, MANIFEST,
+    };
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbError,
+        DbOption, DB,
+    };
+
+    #[test]
+    fn manifest_round_trip() {
+        let manifest = ExportManifest {
+            ts: 7.into(),
+            files: vec![ExportedFile {
+                name: "data-000000.parquet".to_string(),
+                rows: 3,
+                size: 42,
+                sha256: "ab".repeat(32),
+            }],
+        };
+        assert_eq!(
+            ExportManifest::decode(&manifest.encode()).unwrap(),
+            manifest
+        );
+        assert!(matches!(
+            ExportManifest::decode("tonbo-export v1\nts x\n"),
+            Err(ExportError::Manifest(_))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn export_consistent() {
+        let temp_dir = TempDir::new().unwrap();
+        let export_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .time_travel_retention(100);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        for i in 0..10 {
+            db.insert(Test {
+                vstring: format!("k{i}"),
+                vu32: i,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+        let ts = db.snapshot().await.ts();
+        // writes after `ts` are not exported
+        for i in 10..15 {
+            db.insert(Test {
+                vstring: format!("k{i}"),
+                vu32: i,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+
+        let path = Path::from_filesystem_path(export_dir.path()).unwrap();
+        let manifest = db.export_consistent(&path, ts).await.unwrap();
+        assert_eq!(manifest.ts, ts);
+        assert_eq!(
+            manifest.files.iter().map(|file| file.rows).sum::<usize>(),
+            10
+        );
+        assert_eq!(verify_export(&LocalFs {}, &path).await.unwrap(), manifest);
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(export_dir.path().join(&manifest.files[0].name))
+            .unwrap();
+        file.write_all(b"tampered").unwrap();
+        assert!(matches!(
+            verify_export(&LocalFs {}, &path).await,
+            Err(ExportError::Mismatch(_))
+        ));
+        assert!(export_dir.path().join(MANIFEST).exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn export_parquet() {
+        let temp_dir = TempDir::new().unwrap();
+        let export_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        for (i, vbool) in [Some(true), Some(false), None, Some(true)]
+            .into_iter()
+            .enumerate()
+        {
+            db.insert(Test {
+                vstring: format!("k{i}"),
+                vu32: i as u32,
+                vbool,
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+        // overwritten and removed records are left out
+        db.insert(Test {
+            vstring: "k0".to_string(),
+            vu32: 10,
+            vbool: Some(true),
+        })
+        .await
+        .unwrap();
+        db.remove("k1".to_string()).await.unwrap();
+
+        let path = Path::from_filesystem_path(export_dir.path()).unwrap();
+        let manifest = db
+            .export_parquet(&path, ExportOptions::default().partition_by("vbool"))
+            .await
+            .unwrap();
+        let mut names = manifest
+            .files
+            .iter()
+            .map(|file| file.name.as_str())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "vbool=__HIVE_DEFAULT_PARTITION__/data-000000.parquet",
+                "vbool=true/data-000000.parquet"
+            ]
+        );
+
+        let file = std::fs::File::open(
+            export_dir
+                .path()
+                .join("vbool=true")
+                .join("data-000000.parquet"),
+        )
+        .unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        let schema = batches[0].schema();
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(columns, vec!["vstring", "vu32"]);
+        let vu32 = batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(1)
+                    .as_primitive::<UInt32Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vu32, vec![10, 3]);
+
+        assert!(matches!(
+            db.export_parquet(&path, ExportOptions::default().partition_by("missing"))
+                .await,
+            Err(DbError::InvalidExport(_))
+        ));
+    }
+}
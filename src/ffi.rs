@@ -0,0 +1,292 @@
+//! Export of scans through the Arrow C stream interface, for the consumers of other languages in
+//! the same process: DuckDB, polars, pyarrow or the Arrow Java library read the batches of a
+//! [`DB`] without copying them.
+//!
+//! The batches are read as the consumer pulls them, on the thread pulling them, which is blocked
+//! by the [`BlockOn`] of the [`BatchReader`] until the batch is read.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use fusio::path::Path;
+//! use tokio::runtime::Handle;
+//! use tonbo::{
+//!     executor::tokio::TokioExecutor,
+//!     ffi::{BatchReader, FfiScan},
+//!     DbOption, Record, DB,
+//! };
+//!
+//! #[derive(Record, Debug)]
+//! pub struct User {
+//!     #[record(primary_key)]
+//!     name: String,
+//!     age: u8,
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let option = DbOption::new(
+//!         Path::from_filesystem_path("./db_path").unwrap(),
+//!         &UserSchema,
+//!     );
+//!     let db = Arc::new(
+//!         DB::new(option, TokioExecutor::current(), UserSchema)
+//!             .await
+//!             .unwrap(),
+//!     );
+//!
+//!     let reader = BatchReader::new(db, FfiScan::default(), Handle::current())
+//!         .await
+//!         .unwrap();
+//!     // hand the stream to a consumer pulling it from a thread of its own
+//!     let stream = reader.into_ffi();
+//! }
+//! ```
+
+use std::{future::Future, ops::Bound, pin::Pin, sync::Arc};
+
+use arrow::{
+    array::{RecordBatch, RecordBatchReader},
+    datatypes::SchemaRef,
+    error::ArrowError,
+    ffi_stream::FFI_ArrowArrayStream,
+};
+use futures_core::Stream;
+use futures_util::StreamExt;
+use parquet::errors::ParquetError;
+
+use crate::{
+    executor::{thread, Executor},
+    record::{Record, Schema},
+    stream::user::{user_batch, user_scan, user_schema, UserScan},
+    DbError, DB,
+};
+
+/// rows of the batches of [`FfiScan::default`]
+const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// How a [`BatchReader`] waits for a batch on the thread of the consumer.
+pub trait BlockOn: Send + 'static {
+    fn block_on<F: Future>(&self, future: F) -> F::Output;
+}
+
+/// Enters the runtime, which reads the files of the `tokio` feature. The batches must not be
+/// pulled from a thread of the runtime.
+#[cfg(feature = "tokio")]
+impl BlockOn for tokio::runtime::Handle {
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        tokio::runtime::Handle::block_on(self, future)
+    }
+}
+
+/// Parks the thread of the consumer while it waits, for the runtimes driving their I/O from
+/// threads of their own, e.g. `async-std` and `smol`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParkThread;
+
+impl BlockOn for ParkThread {
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        thread::block_on(future)
+    }
+}
+
+/// What a [`BatchReader`] scans.
+#[derive(Debug, Clone)]
+pub struct FfiScan<K> {
+    pub lower: Bound<K>,
+    pub upper: Bound<K>,
+    /// the columns read along with the primary key, all of them if `None`
+    pub projection: Option<Vec<String>>,
+    pub limit: Option<usize>,
+    /// maximum number of rows of a batch
+    pub batch_size: usize,
+}
+
+impl<K> Default for FfiScan<K> {
+    fn default() -> Self {
+        Self {
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+            projection: None,
+            limit: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+}
+
+/// A [`RecordBatchReader`] over a scan of the latest snapshot of a [`DB`], see the
+/// [module](self).
+///
+/// The batches hold the user columns in the order of the schema, without `_null` and `_ts`. The
+/// snapshot is taken as the first batch is pulled, and holds back the freezes of the mutable
+/// memtable until the reader is dropped.
+pub struct BatchReader<B> {
+    schema: SchemaRef,
+    stream: Pin<Box<dyn Stream<Item = Result<RecordBatch, ParquetError>> + Send>>,
+    block_on: B,
+}
+
+impl<B> BatchReader<B>
+where
+    B: BlockOn,
+{
+    /// scan `db` as `scan` tells, the columns of [`FfiScan::projection`] are checked before
+    /// anything is read. The scan itself starts as the first batch is pulled.
+    pub async fn new<R, E>(
+        db: Arc<DB<R, E>>,
+        scan: FfiScan<<R::Schema as Schema>::Key>,
+        block_on: B,
+    ) -> Result<Self, DbError<R>>
+    where
+        R: Record + Send + Sync,
+        <R::Schema as Schema>::Columns: Send + Sync,
+        E: Executor + Send + Sync + 'static,
+    {
+        let primary_key_index = db.schema.read().await.record_schema.primary_key_index();
+        let schema = user_schema(
+            db.ctx.arrow_schema(),
+            primary_key_index,
+            scan.projection.as_deref(),
+        )
+        .map_err(DbError::InvalidExport)?;
+        let stream = user_scan(
+            db,
+            UserScan {
+                lower: scan.lower,
+                upper: scan.upper,
+                projection: scan.projection,
+                limit: scan.limit,
+                batch_size: scan.batch_size,
+            },
+        );
+
+        Ok(Self {
+            schema,
+            stream: Box::pin(stream),
+            block_on,
+        })
+    }
+
+    /// the stream of the Arrow C stream interface to hand the reader to a consumer
+    pub fn into_ffi(self) -> FFI_ArrowArrayStream {
+        FFI_ArrowArrayStream::new(Box::new(self))
+    }
+}
+
+impl<B> Iterator for BatchReader<B>
+where
+    B: BlockOn,
+{
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = self.block_on.block_on(self.stream.next())?;
+        Some(
+            batch
+                .map_err(|err| ArrowError::ExternalError(Box::new(err)))
+                .and_then(|batch| user_batch(&self.schema, &batch)),
+        )
+    }
+}
+
+impl<B> RecordBatchReader for BatchReader<B>
+where
+    B: BlockOn,
+{
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{ops::Bound, sync::Arc};
+
+    use arrow::{
+        array::{AsArray, RecordBatchReader},
+        datatypes::UInt32Type,
+        ffi_stream::ArrowArrayStreamReader,
+    };
+    use fusio::path::Path;
+    use tempfile::TempDir;
+    use tokio::runtime::Builder;
+
+    use super::{BatchReader, FfiScan};
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbError,
+        DbOption, DB,
+    };
+
+    #[test]
+    fn export_stream() {
+        let temp_dir = TempDir::new().unwrap();
+        let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+        let db = runtime.block_on(async {
+            let option = DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            );
+            let db = DB::new(option, TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+            for (key, vu32) in [("a", 1), ("b", 2), ("c", 3), ("d", 4)] {
+                db.insert(Test {
+                    vstring: key.to_string(),
+                    vu32,
+                    vbool: Some(true),
+                })
+                .await
+                .unwrap();
+            }
+            db.remove("b".to_string()).await.unwrap();
+            Arc::new(db)
+        });
+
+        let reader = runtime
+            .block_on(BatchReader::new(
+                db.clone(),
+                FfiScan {
+                    upper: Bound::Excluded("d".to_string()),
+                    projection: Some(vec!["vu32".to_string()]),
+                    batch_size: 1,
+                    ..Default::default()
+                },
+                runtime.handle().clone(),
+            ))
+            .unwrap();
+        let reader = ArrowArrayStreamReader::try_new(reader.into_ffi()).unwrap();
+        let names = reader
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["vstring", "vu32"]);
+
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches.len(), 2);
+        let vu32 = batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(1)
+                    .as_primitive::<UInt32Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vu32, vec![1, 3]);
+
+        assert!(matches!(
+            runtime.block_on(BatchReader::new(
+                db,
+                FfiScan {
+                    projection: Some(vec!["_ts".to_string()]),
+                    ..Default::default()
+                },
+                runtime.handle().clone(),
+            )),
+            Err(DbError::InvalidExport(_))
+        ));
+    }
+}
@@ -0,0 +1,412 @@
+//! An Arrow Flight service over a [`DB`] of [`DynRecord`]s, for the clients of any language
+//! with a Flight library.
+//!
+//! - `DoGet` scans the [`DB`], the ticket is a JSON object of the scan, every field optional:
+//!   `{"lower": {"included": 1}, "upper": {"excluded": 10}, "projection": ["name"], "limit": 100,
+//!   "batch_size": 1024}`. The batches hold the projected columns and the primary key, without
+//!   `_null` and `_ts`.
+//! - `GetFlightInfo` and `GetSchema` take the same JSON as the command of the descriptor, the
+//!   ticket of the single endpoint of the info is the command.
+//! - `DoPut` inserts the rows of the batches, the columns are matched by name and the missing ones
+//!   are null. The single result holds the number of rows inserted as its metadata.
+//! - `DoAction` runs `flush`, see [`DB::flush`], and `compact`, the same along with the compactions
+//!   due after the flush.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use tonbo::{executor::tokio::TokioExecutor, flight::FlightServer, record::DynRecord, DB};
+//! use tonic::transport::Server;
+//!
+//! async fn serve(db: Arc<DB<DynRecord, TokioExecutor>>) {
+//!     Server::builder()
+//!         .add_service(FlightServer::new(db).into_service())
+//!         .serve("127.0.0.1:50051".parse().unwrap())
+//!         .await
+//!         .unwrap();
+//! }
+//! ```
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{new_null_array, ArrayRef, BooleanArray, RecordBatch, UInt32Array},
+    datatypes::SchemaRef,
+    error::ArrowError,
+    ipc::writer::IpcWriteOptions,
+};
+use arrow_flight::{
+    decode::FlightRecordBatchStream,
+    encode::FlightDataEncoderBuilder,
+    error::FlightError,
+    flight_service_server::{FlightService, FlightServiceServer},
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo,
+    HandshakeRequest, HandshakeResponse, PollInfo, PutResult, SchemaAsIpc, SchemaResult, Ticket,
+};
+use bytes::Bytes;
+use futures_util::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use parquet::arrow::ProjectionMask;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::{
+    executor::Executor,
+    magic::USER_COLUMN_OFFSET,
+    record::{DynRecord, DynRecordRef, RecordRef, Schema, Value},
+    stream::user::{user_batch, user_scan, user_schema, UserScan},
+    DB,
+};
+
+/// rows of the batches of a scan without `batch_size`
+const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// The Flight service of a [`DB`], see the [module](self).
+pub struct FlightServer<E>
+where
+    E: Executor,
+{
+    db: Arc<DB<DynRecord, E>>,
+}
+
+impl<E> FlightServer<E>
+where
+    E: Executor + Send + Sync + 'static,
+{
+    pub fn new(db: Arc<DB<DynRecord, E>>) -> Self {
+        Self { db }
+    }
+
+    /// the service to add to a `tonic` server
+    pub fn into_service(self) -> FlightServiceServer<Self> {
+        FlightServiceServer::new(self)
+    }
+
+    /// the schema of the batches and the scan of the JSON `query`, an empty one scans everything
+    async fn query(&self, query: &[u8]) -> Result<(SchemaRef, UserScan<Value>), Status> {
+        let invalid = |reason: String| Status::invalid_argument(format!("invalid scan: {reason}"));
+        let query = match query.is_empty() {
+            true => serde_json::Value::Object(Default::default()),
+            false => serde_json::from_slice(query).map_err(|err| invalid(err.to_string()))?,
+        };
+        let record_schema = self.db.schema.read().await.record_schema.clone();
+        let key_desc = &record_schema.value_descs()[record_schema.primary_index()];
+        let scan = UserScan::from_json(&query, key_desc, DEFAULT_BATCH_SIZE).map_err(invalid)?;
+        let schema = user_schema(
+            self.db.ctx.arrow_schema(),
+            record_schema.primary_key_index(),
+            scan.projection.as_deref(),
+        )
+        .map_err(invalid)?;
+        Ok((schema, scan))
+    }
+
+    async fn flight_info(&self, descriptor: FlightDescriptor) -> Result<FlightInfo, Status> {
+        let (schema, _) = self.query(&descriptor.cmd).await?;
+        let ticket = Ticket::new(descriptor.cmd.clone());
+        Ok(FlightInfo::new()
+            .try_with_schema(&schema)
+            .map_err(|err| Status::internal(err.to_string()))?
+            .with_endpoint(FlightEndpoint::new().with_ticket(ticket))
+            .with_descriptor(descriptor))
+    }
+}
+
+/// `batch` with the columns, `_null` and `_ts` included, of `full_schema`, which reads the rows
+/// of `batch` as records
+fn full_batch(full_schema: &SchemaRef, batch: &RecordBatch) -> Result<RecordBatch, ArrowError> {
+    let user_fields = &full_schema.fields()[USER_COLUMN_OFFSET..];
+    if let Some(field) = batch
+        .schema()
+        .fields()
+        .iter()
+        .find(|field| !user_fields.iter().any(|user| user.name() == field.name()))
+    {
+        return Err(ArrowError::SchemaError(format!(
+            "unknown column {}",
+            field.name()
+        )));
+    }
+    let num_rows = batch.num_rows();
+    let columns = [
+        Arc::new(BooleanArray::from(vec![false; num_rows])) as ArrayRef,
+        Arc::new(UInt32Array::from(vec![0; num_rows])) as ArrayRef,
+    ]
+    .into_iter()
+    .chain(user_fields.iter().map(|field| {
+        batch
+            .column_by_name(field.name())
+            .cloned()
+            .unwrap_or_else(|| new_null_array(field.data_type(), num_rows))
+    }))
+    .collect::<Vec<_>>();
+    // also rejects nulls in the columns that are not nullable
+    RecordBatch::try_new(full_schema.clone(), columns)
+}
+
+#[tonic::async_trait]
+impl<E> FlightService for FlightServer<E>
+where
+    E: Executor + Send + Sync + 'static,
+{
+    type HandshakeStream = BoxStream<'static, Result<HandshakeResponse, Status>>;
+    type ListFlightsStream = BoxStream<'static, Result<FlightInfo, Status>>;
+    type DoGetStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoPutStream = BoxStream<'static, Result<PutResult, Status>>;
+    type DoActionStream = BoxStream<'static, Result<arrow_flight::Result, Status>>;
+    type ListActionsStream = BoxStream<'static, Result<ActionType, Status>>;
+    type DoExchangeStream = BoxStream<'static, Result<FlightData, Status>>;
+
+    async fn handshake(
+        &self,
+        _request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        Err(Status::unimplemented("tonbo does not authenticate"))
+    }
+
+    /// the flight of the whole [`DB`]
+    async fn list_flights(
+        &self,
+        _request: Request<Criteria>,
+    ) -> Result<Response<Self::ListFlightsStream>, Status> {
+        let info = self
+            .flight_info(FlightDescriptor::new_cmd(Bytes::new()))
+            .await;
+        Ok(Response::new(stream::once(async { info }).boxed()))
+    }
+
+    async fn get_flight_info(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        Ok(Response::new(self.flight_info(request.into_inner()).await?))
+    }
+
+    async fn poll_flight_info(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<PollInfo>, Status> {
+        Err(Status::unimplemented("scans are not long running queries"))
+    }
+
+    async fn get_schema(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<SchemaResult>, Status> {
+        let (schema, _) = self.query(&request.into_inner().cmd).await?;
+        let result = SchemaAsIpc::new(&schema, &IpcWriteOptions::default())
+            .try_into()
+            .map_err(|err: ArrowError| Status::internal(err.to_string()))?;
+        Ok(Response::new(result))
+    }
+
+    async fn do_get(
+        &self,
+        request: Request<Ticket>,
+    ) -> Result<Response<Self::DoGetStream>, Status> {
+        let (schema, scan) = self.query(&request.into_inner().ticket).await?;
+        let batches = user_scan(self.db.clone(), scan).map({
+            let schema = schema.clone();
+            move |batch| {
+                let batch = batch.map_err(|err| FlightError::ExternalError(Box::new(err)))?;
+                user_batch(&schema, &batch).map_err(FlightError::Arrow)
+            }
+        });
+        let stream = FlightDataEncoderBuilder::new()
+            .with_schema(schema)
+            .build(batches)
+            .map_err(Status::from);
+        Ok(Response::new(stream.boxed()))
+    }
+
+    async fn do_put(
+        &self,
+        request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoPutStream>, Status> {
+        let full_schema = self.db.ctx.arrow_schema().clone();
+        let mask = ProjectionMask::all();
+        let mut batches = FlightRecordBatchStream::new_from_flight_data(
+            request.into_inner().map_err(FlightError::from),
+        );
+
+        let mut rows = 0;
+        while let Some(batch) = batches.try_next().await? {
+            let batch = full_batch(&full_schema, &batch)
+                .map_err(|err| Status::invalid_argument(err.to_string()))?;
+            let records = (0..batch.num_rows())
+                .filter_map(|offset| {
+                    DynRecordRef::from_record_batch(&batch, offset, &mask, &full_schema).get()
+                })
+                .map(|record| DynRecord::new(record.columns, record.primary_index))
+                .collect::<Vec<_>>();
+            if records.is_empty() {
+                continue;
+            }
+            rows += records.len();
+            self.db
+                .insert_batch(records.into_iter())
+                .await
+                .map_err(|err| Status::internal(err.to_string()))?;
+        }
+
+        let result = PutResult {
+            app_metadata: Bytes::from(rows.to_string()),
+        };
+        Ok(Response::new(stream::once(async { Ok(result) }).boxed()))
+    }
+
+    async fn do_action(
+        &self,
+        request: Request<Action>,
+    ) -> Result<Response<Self::DoActionStream>, Status> {
+        let action = request.into_inner();
+        match action.r#type.as_str() {
+            "flush" | "compact" => self
+                .db
+                .flush()
+                .await
+                .map_err(|err| Status::internal(err.to_string()))?,
+            name => return Err(Status::invalid_argument(format!("unknown action {name:?}"))),
+        }
+        Ok(Response::new(stream::empty().boxed()))
+    }
+
+    async fn list_actions(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<Self::ListActionsStream>, Status> {
+        let actions = [
+            ("flush", "flush the memtables into SSTables"),
+            (
+                "compact",
+                "flush the memtables along with the compactions due after the flush",
+            ),
+        ]
+        .map(|(name, description)| {
+            Ok(ActionType {
+                r#type: name.to_string(),
+                description: description.to_string(),
+            })
+        });
+        Ok(Response::new(stream::iter(actions).boxed()))
+    }
+
+    async fn do_exchange(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        Err(Status::unimplemented("tonbo does not exchange"))
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{AsArray, Int64Array, RecordBatch, StringArray},
+        datatypes::{DataType, Field, Int64Type, Schema as ArrowSchema},
+    };
+    use arrow_flight::{
+        encode::FlightDataEncoderBuilder, Action, FlightClient, FlightDescriptor, Ticket,
+    };
+    use fusio::path::Path;
+    use futures_util::{stream, TryStreamExt};
+    use tempfile::TempDir;
+    use tokio::net::TcpListener;
+    use tonic::transport::{server::TcpIncoming, Channel, Server};
+
+    use super::FlightServer;
+    use crate::{dyn_schema, executor::tokio::TokioExecutor, DbOption, DB};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn flight_service() {
+        let temp_dir = TempDir::new().unwrap();
+        let schema = dyn_schema!(("id", Int64, false), ("name", String, true), 0);
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &schema,
+        );
+        let db = Arc::new(
+            DB::new(option, TokioExecutor::current(), schema)
+                .await
+                .unwrap(),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(FlightServer::new(db.clone()).into_service())
+                .serve_with_incoming(TcpIncoming::from_listener(listener, true, None).unwrap()),
+        );
+        let channel = Channel::from_shared(format!("http://{addr}"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut client = FlightClient::new(channel);
+
+        let batch = RecordBatch::try_new(
+            Arc::new(ArrowSchema::new(vec![
+                Field::new("name", DataType::Utf8, true),
+                Field::new("id", DataType::Int64, false),
+            ])),
+            vec![
+                Arc::new(StringArray::from(vec![Some("a"), None, Some("c")])),
+                Arc::new(Int64Array::from(vec![1, 2, 3])),
+            ],
+        )
+        .unwrap();
+        let put = FlightDataEncoderBuilder::new().build(stream::iter([Ok(batch)]));
+        let results = client
+            .do_put(put)
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results[0].app_metadata, "3");
+
+        let query = r#"{"lower": {"excluded": 1}, "projection": ["id"]}"#;
+        let schema = client
+            .get_schema(FlightDescriptor::new_cmd(query))
+            .await
+            .unwrap();
+        assert_eq!(schema.field(0).name(), "id");
+        assert_eq!(schema.fields().len(), 1);
+        let batches = client
+            .do_get(Ticket::new(query))
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let ids = batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_primitive::<Int64Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![2, 3]);
+
+        client
+            .do_action(Action::new("flush", ""))
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(db.ctx.version_set.current().await.level_slice[0].len(), 1);
+        let err = client
+            .do_get(Ticket::new(r#"{"projection": ["age"]}"#))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown column age"));
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use crate::{
+    executor::Executor,
+    record::{Record, Schema},
+    DbError, DbOption, DB,
+};
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Clone + Send + Sync + 'static,
+{
+    /// Open a follower of the [`DB`] another process, the primary, writes at the base path of
+    /// `option`, e.g. on a bucket shared by several readers to scale out the reads of a dataset.
+    ///
+    /// The follower is opened like [`DB::open_read_only`] and refreshes its version from the
+    /// manifest of the primary every `refresh_interval`, reading the SSTables flushed or
+    /// compacted since. The commits of the primary are read once they are flushed into
+    /// SSTables, not before. A read racing a compaction of the primary may fail on a table
+    /// removed meanwhile, retrying it after the next refresh succeeds.
+    ///
+    /// The refreshes stop once the follower is dropped. They do not run on wasm32, which has no
+    /// timer, [`DB::refresh`] is called by hand there.
+    pub async fn open_follower(
+        option: DbOption,
+        executor: E,
+        schema: R::Schema,
+        refresh_interval: Duration,
+    ) -> Result<Self, DbError<R>> {
+        let db = Self::open_read_only(option, executor.clone(), schema).await?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use tracing::{error, info_span, Instrument};
+
+            use crate::executor::{sleep, task};
+
+            let ctx = std::sync::Arc::downgrade(&db.ctx);
+            executor.spawn_named(
+                task::FOLLOWER,
+                async move {
+                    loop {
+                        sleep(refresh_interval).await;
+                        let Some(ctx) = ctx.upgrade() else {
+                            break;
+                        };
+                        if let Err(err) = ctx.version_set.refresh().await {
+                            error!("[Follower Error]: {}", err)
+                        }
+                    }
+                }
+                .instrument(info_span!("tonbo_task", name = task::FOLLOWER)),
+            );
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = (executor, refresh_interval);
+
+        Ok(db)
+    }
+
+    /// Refresh the version of a [`DB`] opened read-only from the manifest, catching up on the
+    /// SSTables its writer flushed or compacted since it was opened. It does nothing on a
+    /// writable [`DB`], whose version is always the latest.
+    pub async fn refresh(&self) -> Result<(), DbError<R>> {
+        if !self.schema.read().await.option.read_only {
+            return Ok(());
+        }
+        self.ctx.version_set.refresh().await?;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn follower() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = || {
+            DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            )
+        };
+        let record = |key: &str, vu32: u32| Test {
+            vstring: key.to_string(),
+            vu32,
+            vbool: None,
+        };
+        async fn get(db: &DB<Test, TokioExecutor>, key: &str) -> Option<u32> {
+            db.get(&key.to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap()
+        }
+
+        let primary: DB<Test, TokioExecutor> =
+            DB::new(option(), TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+        primary.insert(record("first", 1)).await.unwrap();
+        primary.flush().await.unwrap();
+
+        let follower: DB<Test, TokioExecutor> = DB::open_follower(
+            option(),
+            TokioExecutor::current(),
+            TestSchema,
+            Duration::from_secs(3600),
+        )
+        .await
+        .unwrap();
+        assert_eq!(get(&follower, "first").await, Some(1));
+
+        primary.insert(record("second", 2)).await.unwrap();
+        primary.insert(record("first", 3)).await.unwrap();
+        // only the flushed commits are read
+        assert_eq!(get(&follower, "second").await, None);
+        primary.flush().await.unwrap();
+        assert_eq!(get(&follower, "second").await, None);
+
+        follower.refresh().await.unwrap();
+        assert_eq!(get(&follower, "first").await, Some(3));
+        assert_eq!(get(&follower, "second").await, Some(2));
+
+        // refreshed in the background
+        let follower: DB<Test, TokioExecutor> = DB::open_follower(
+            option(),
+            TokioExecutor::current(),
+            TestSchema,
+            Duration::from_millis(10),
+        )
+        .await
+        .unwrap();
+        primary.insert(record("third", 4)).await.unwrap();
+        primary.flush().await.unwrap();
+        let mut attempts = 0;
+        while get(&follower, "third").await.is_none() {
+            attempts += 1;
+            assert!(attempts < 500);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
@@ -2,11 +2,20 @@ use std::{collections::HashMap, sync::Arc};
 
 use fusio::{disk::LocalFs, dynamic::DynFs, path::Path, Error};
 use fusio_dispatch::FsOptions;
+use parquet_lru::BoxedFileReader;
+
+use crate::{
+    cache::table::{self, TableCache},
+    fs::{retry::retry, FileId},
+    DbOption,
+};
 
 pub struct StoreManager {
     base_fs: Arc<dyn DynFs>,
+    wal_fs: Arc<dyn DynFs>,
     local_fs: Arc<dyn DynFs>,
     fs_map: HashMap<Path, Arc<dyn DynFs>>,
+    table_cache: Option<Arc<TableCache>>,
 }
 
 impl StoreManager {
@@ -22,16 +31,58 @@ impl StoreManager {
         let base_fs = base_options.parse()?;
 
         Ok(StoreManager {
+            wal_fs: base_fs.clone(),
             base_fs,
             fs_map,
             local_fs: Arc::new(LocalFs {}),
+            table_cache: None,
+        })
+    }
+
+    /// keep the WAL segments on the fs of `wal_options` instead of the base fs
+    pub fn with_wal_fs(self, wal_options: FsOptions) -> Result<Self, Error> {
+        Ok(StoreManager {
+            wal_fs: wal_options.parse()?,
+            ..self
         })
     }
 
+    /// keep the SSTables read open in `table_cache`, see
+    /// [`DbOption::max_open_tables`](crate::DbOption::max_open_tables)
+    pub(crate) fn with_table_cache(self, table_cache: Option<Arc<TableCache>>) -> Self {
+        StoreManager {
+            table_cache,
+            ..self
+        }
+    }
+
+    pub(crate) fn table_cache(&self) -> Option<&Arc<TableCache>> {
+        self.table_cache.as_ref()
+    }
+
+    /// a reader of the table `gen` of `level`, kept open by the table cache if there is one
+    pub(crate) async fn open_table(
+        &self,
+        option: &DbOption,
+        level: usize,
+        gen: FileId,
+    ) -> Result<BoxedFileReader, Error> {
+        let fs = self.get_fs(option.level_fs_path(level).unwrap_or(&option.base_path));
+        let path = option.table_path(gen, level);
+        retry(option.retry_policy.as_ref(), || {
+            table::open(self.table_cache.as_ref(), fs, &path, gen)
+        })
+        .await
+    }
+
     pub fn base_fs(&self) -> &Arc<dyn DynFs> {
         &self.base_fs
     }
 
+    pub fn wal_fs(&self) -> &Arc<dyn DynFs> {
+        &self.wal_fs
+    }
+
     pub fn local_fs(&self) -> &Arc<dyn DynFs> {
         &self.local_fs
     }
@@ -1,11 +1,14 @@
 pub mod manager;
+#[cfg(feature = "aws")]
+pub mod remote;
+pub(crate) mod retry;
 
 use std::{
     fmt::{Display, Formatter},
     str::FromStr,
 };
 
-use fusio::{fs::OpenOptions, path::Path};
+use fusio::{fs::OpenOptions, path::Path, DynFs, Read, Write};
 use once_cell::sync::OnceCell;
 use ulid::{DecodeError, Ulid};
 
@@ -68,3 +71,42 @@ pub(crate) fn parse_file_id(path: &Path, suffix: FileType) -> Result<Option<File
         })
         .transpose()
 }
+
+/// bytes read at once by [`copy`]
+const COPY_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// copy the first `len` bytes of the file at `from` of `from_fs`, or all of them, to the file at
+/// `to` of `to_fs`, which is created or truncated. Returns the number of bytes copied.
+pub(crate) async fn copy(
+    from_fs: &dyn DynFs,
+    from: &Path,
+    to_fs: &dyn DynFs,
+    to: &Path,
+    len: Option<u64>,
+) -> Result<u64, fusio::Error> {
+    let mut source = from_fs
+        .open_options(from, FileType::Parquet.open_options(true))
+        .await?;
+    let size = match len {
+        Some(len) => len,
+        None => source.size().await?,
+    };
+    let mut target = to_fs
+        .open_options(to, FileType::Parquet.open_options(false))
+        .await?;
+    let mut buf = Vec::new();
+    let mut pos = 0;
+    while pos < size {
+        let len = (size - pos).min(COPY_CHUNK_SIZE);
+        buf.resize(len as usize, 0);
+        let (result, chunk) = source.read_exact_at(buf, pos).await;
+        result?;
+        let (result, chunk) = target.write_all(chunk).await;
+        result?;
+        buf = chunk;
+        pos += len;
+    }
+    target.close().await?;
+
+    Ok(size)
+}
@@ -0,0 +1,54 @@
+//! The [`FsOptions`] of the object stores speaking the S3 API besides S3 itself, for
+//! [`DbOption::base_fs`](crate::DbOption::base_fs) and the level and WAL paths.
+
+use fusio::remotes::aws::AwsCredential;
+use fusio_dispatch::FsOptions;
+
+/// the endpoint of the XML API of Google Cloud Storage
+const GCS_ENDPOINT: &str = "https://storage.googleapis.com";
+
+/// A bucket of Google Cloud Storage through its XML API, `credential` is an HMAC key of a
+/// service account: its access id and its secret.
+///
+/// GCS has no conditional writes through the XML API alike the ones of S3, the version history
+/// is kept safe of another writer by [`DbOption::lease`](crate::DbOption::lease).
+pub fn gcs(bucket: impl Into<String>, credential: AwsCredential) -> FsOptions {
+    FsOptions::S3 {
+        bucket: bucket.into(),
+        credential: Some(credential),
+        endpoint: Some(GCS_ENDPOINT.to_string()),
+        // the signing region of interoperable requests
+        region: Some("auto".to_string()),
+        sign_payload: None,
+        checksum: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fusio::remotes::aws::AwsCredential;
+    use fusio_dispatch::FsOptions;
+
+    use super::gcs;
+
+    #[test]
+    fn gcs_options() {
+        let credential = AwsCredential {
+            key_id: "GOOG1E".to_string(),
+            secret_key: "secret".to_string(),
+            token: None,
+        };
+        let FsOptions::S3 {
+            bucket,
+            endpoint,
+            region,
+            ..
+        } = gcs("tonbo", credential)
+        else {
+            panic!("not S3 options");
+        };
+        assert_eq!(bucket, "tonbo");
+        assert_eq!(endpoint.as_deref(), Some("https://storage.googleapis.com"));
+        assert_eq!(region.as_deref(), Some("auto"));
+    }
+}
@@ -0,0 +1,404 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::pin::pin;
+use std::{
+    error::Error,
+    future::Future,
+    io::{self, ErrorKind},
+    ops::Range,
+    sync::Arc,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures_core::future::BoxFuture;
+#[cfg(not(target_arch = "wasm32"))]
+use futures_util::future::{select, Either};
+use parquet::{
+    arrow::{arrow_reader::ArrowReaderOptions, async_reader::AsyncFileReader},
+    errors::{ParquetError, Result as ParquetResult},
+    file::metadata::ParquetMetaData,
+};
+use parquet_lru::{BoxedFileReader, DynLruCache};
+
+use crate::{executor, fs::FileId, option::RetryPolicy, ParquetLru};
+
+/// the HTTP statuses of the remote stores worth retrying: throttling and the 5xx errors on their
+/// side
+const TRANSIENT_STATUSES: [u16; 5] = [429, 500, 502, 503, 504];
+
+/// the words of the errors of the remote stores worth retrying: throttling, failures on their
+/// side and the timeouts of the requests, for the errors without an [`io::Error`] to tell
+const TRANSIENT_MESSAGES: [&str; 6] = [
+    "SlowDown",
+    "Throttl",
+    "InternalError",
+    "ServiceUnavailable",
+    "RequestTimeout",
+    "timed out",
+];
+
+/// whether `err` may go away by retrying the request: a timeout, a dropped connection, or a
+/// remote store throttling or failing on its side. A missing file, a denied request or a
+/// corrupted table is not.
+pub(crate) fn is_transient(err: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            // the errors of the remote stores are told by their messages
+            if err.kind() != ErrorKind::Other {
+                return matches!(
+                    err.kind(),
+                    ErrorKind::TimedOut
+                        | ErrorKind::Interrupted
+                        | ErrorKind::WouldBlock
+                        | ErrorKind::ConnectionReset
+                        | ErrorKind::ConnectionAborted
+                        | ErrorKind::ConnectionRefused
+                        | ErrorKind::BrokenPipe
+                );
+            }
+        }
+        source = err.source();
+    }
+    let message = err.to_string();
+    has_transient_status(&message)
+        || TRANSIENT_MESSAGES
+            .iter()
+            .any(|transient| message.contains(transient))
+}
+
+/// whether `message` tells a status of [`TRANSIENT_STATUSES`] as a whole word after `status`,
+/// e.g. `status: 503` or `StatusCode(503)`, so that the digits of paths and ids are not taken
+/// for one
+fn has_transient_status(message: &str) -> bool {
+    let mut is_status = false;
+    for word in message
+        .split(|char: char| !char.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        if word.eq_ignore_ascii_case("status") || word.eq_ignore_ascii_case("statuscode") {
+            is_status = true;
+        } else if !(is_status && word.eq_ignore_ascii_case("code")) {
+            if is_status
+                && word
+                    .parse::<u16>()
+                    .is_ok_and(|status| TRANSIENT_STATUSES.contains(&status))
+            {
+                return true;
+            }
+            is_status = false;
+        }
+    }
+    false
+}
+
+fn timed_out() -> io::Error {
+    io::Error::new(ErrorKind::TimedOut, "storage request timed out")
+}
+
+/// `future` unless it runs longer than `timeout`. The timeout needs a timer, which wasm32 lacks.
+async fn within<F: Future>(timeout: Option<Duration>, future: F) -> Option<F::Output> {
+    match timeout {
+        #[cfg(not(target_arch = "wasm32"))]
+        Some(timeout) => match select(pin!(future), pin!(executor::sleep(timeout))).await {
+            Either::Left((output, _)) => Some(output),
+            Either::Right(_) => None,
+        },
+        _ => Some(future.await),
+    }
+}
+
+/// The attempts of a request left by its [`RetryPolicy`].
+struct Attempts {
+    policy: RetryPolicy,
+    attempt: usize,
+    backoff: Duration,
+}
+
+impl Attempts {
+    fn new(policy: RetryPolicy) -> Self {
+        Self {
+            backoff: policy.backoff,
+            policy,
+            attempt: 1,
+        }
+    }
+
+    /// wait out the backoff before the next attempt after `err`, `false` if `err` is not
+    /// transient or the attempts are spent
+    async fn retry(&mut self, err: &(dyn Error + 'static)) -> bool {
+        if self.attempt >= self.policy.max_attempts || !is_transient(err) {
+            return false;
+        }
+        self.attempt += 1;
+        executor::sleep(self.backoff).await;
+        self.backoff = self.backoff.saturating_mul(2).min(self.policy.max_backoff);
+        true
+    }
+}
+
+/// run the request of `op` as `policy` tells, once without a policy
+pub(crate) async fn retry<T, F, Fut>(
+    policy: Option<&RetryPolicy>,
+    mut op: F,
+) -> Result<T, fusio::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, fusio::Error>>,
+{
+    let Some(policy) = policy else {
+        return op().await;
+    };
+    let mut attempts = Attempts::new(*policy);
+    loop {
+        let err = match within(policy.timeout, op()).await {
+            Some(Ok(output)) => return Ok(output),
+            Some(Err(err)) => err,
+            None => fusio::Error::Io(timed_out()),
+        };
+        if !attempts.retry(&err).await {
+            return Err(err);
+        }
+    }
+}
+
+/// run the whole write of `op` again on transient errors as `policy` tells, once without a
+/// policy. `op` must write its file from the start, e.g. by truncating it, so that an attempt
+/// leaves nothing of the failed ones. The write is not timed out, as a large table may take
+/// longer than a request.
+pub(crate) async fn retry_write<T, E, F, Fut>(
+    policy: Option<&RetryPolicy>,
+    mut op: F,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Error + 'static,
+{
+    let Some(policy) = policy else {
+        return op().await;
+    };
+    let mut attempts = Attempts::new(*policy);
+    loop {
+        let err = match op().await {
+            Ok(output) => return Ok(output),
+            Err(err) => err,
+        };
+        if !attempts.retry(&err).await {
+            return Err(err);
+        }
+    }
+}
+
+/// the cache SSTables are opened with, which retries their reads as `policy` tells, see
+/// [`DbOption::retry_policy`](crate::DbOption::retry_policy)
+pub(crate) fn cache(parquet_lru: &ParquetLru, policy: RetryPolicy) -> ParquetLru {
+    Arc::new(RetryCache {
+        inner: parquet_lru.clone(),
+        policy,
+    })
+}
+
+struct RetryCache {
+    inner: ParquetLru,
+    policy: RetryPolicy,
+}
+
+impl DynLruCache<FileId> for RetryCache {
+    fn get_reader(&self, key: FileId, reader: BoxedFileReader) -> BoxFuture<'_, BoxedFileReader> {
+        Box::pin(async move {
+            BoxedFileReader::new(RetryReader {
+                inner: self.inner.get_reader(key, reader).await,
+                policy: self.policy,
+            })
+        })
+    }
+}
+
+/// sends the requests of the reads again on transient errors
+struct RetryReader {
+    inner: BoxedFileReader,
+    policy: RetryPolicy,
+}
+
+impl RetryReader {
+    fn timed_out() -> ParquetError {
+        ParquetError::External(Box::new(timed_out()))
+    }
+}
+
+impl AsyncFileReader for RetryReader {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        Box::pin(async move {
+            let mut attempts = Attempts::new(self.policy);
+            loop {
+                let err =
+                    match within(self.policy.timeout, self.inner.get_bytes(range.clone())).await {
+                        Some(Ok(bytes)) => return Ok(bytes),
+                        Some(Err(err)) => err,
+                        None => Self::timed_out(),
+                    };
+                if !attempts.retry(&err).await {
+                    return Err(err);
+                }
+            }
+        })
+    }
+
+    fn get_metadata<'s>(
+        &'s mut self,
+        options: Option<&'s ArrowReaderOptions>,
+    ) -> BoxFuture<'s, ParquetResult<Arc<ParquetMetaData>>> {
+        Box::pin(async move {
+            let mut attempts = Attempts::new(self.policy);
+            loop {
+                let err = match within(self.policy.timeout, self.inner.get_metadata(options)).await
+                {
+                    Some(Ok(metadata)) => return Ok(metadata),
+                    Some(Err(err)) => err,
+                    None => Self::timed_out(),
+                };
+                if !attempts.retry(&err).await {
+                    return Err(err);
+                }
+            }
+        })
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        Box::pin(async move {
+            let mut attempts = Attempts::new(self.policy);
+            loop {
+                let err = match within(
+                    self.policy.timeout,
+                    self.inner.get_byte_ranges(ranges.clone()),
+                )
+                .await
+                {
+                    Some(Ok(bytes)) => return Ok(bytes),
+                    Some(Err(err)) => err,
+                    None => Self::timed_out(),
+                };
+                if !attempts.retry(&err).await {
+                    return Err(err);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{
+        io::{self, ErrorKind},
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use parquet::errors::ParquetError;
+
+    use super::{is_transient, retry, retry_write};
+    use crate::option::RetryPolicy;
+
+    #[test]
+    fn transient_errors() {
+        let reset = fusio::Error::Io(io::Error::from(ErrorKind::ConnectionReset));
+        assert!(is_transient(&reset));
+        let not_found = fusio::Error::Io(io::Error::from(ErrorKind::NotFound));
+        assert!(!is_transient(&not_found));
+        let throttled = ParquetError::External("503 Slow Down: SlowDown".into());
+        assert!(is_transient(&throttled));
+        let unavailable = ParquetError::External("request failed, status: 503".into());
+        assert!(is_transient(&unavailable));
+        let throttled = ParquetError::External("StatusCode(429)".into());
+        assert!(is_transient(&throttled));
+        // the digits of a path are not a status
+        let missing = ParquetError::External("no table 15003/4290 of status 404".into());
+        assert!(!is_transient(&missing));
+        let corrupted = ParquetError::General("invalid footer".to_string());
+        assert!(!is_transient(&corrupted));
+    }
+
+    #[tokio::test]
+    async fn retry_policy() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+            timeout: Some(Duration::from_millis(50)),
+        };
+        let counter = AtomicUsize::new(0);
+        let attempts = &counter;
+        let result = retry(Some(&policy), || async move {
+            match attempts.fetch_add(1, Ordering::Relaxed) {
+                0 => Err(fusio::Error::Io(io::Error::from(
+                    ErrorKind::ConnectionReset,
+                ))),
+                // times out
+                1 => {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    Ok(0)
+                }
+                attempt => Ok(attempt),
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 2);
+
+        attempts.store(0, Ordering::Relaxed);
+        let result: Result<(), _> = retry(Some(&policy), || async move {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            Err(fusio::Error::Io(io::Error::from(ErrorKind::NotFound)))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn retry_write_policy() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+            timeout: Some(Duration::from_millis(1)),
+        };
+        let counter = AtomicUsize::new(0);
+        let attempts = &counter;
+        // a write is not timed out
+        let result = retry_write(Some(&policy), || async move {
+            match attempts.fetch_add(1, Ordering::Relaxed) {
+                0 => Err(ParquetError::External("request failed, status: 503".into())),
+                attempt => {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 1);
+
+        attempts.store(0, Ordering::Relaxed);
+        let result: Result<(), _> = retry_write(Some(&policy), || async move {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            Err(ParquetError::General("invalid footer".to_string()))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+
+        attempts.store(0, Ordering::Relaxed);
+        let result: Result<(), _> = retry_write(None, || async move {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            Err(fusio::Error::Io(io::Error::from(
+                ErrorKind::ConnectionReset,
+            )))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::HashSet,
+    io::ErrorKind,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_lock::RwLock;
+use fusio::path::Path;
+use futures_util::StreamExt;
+
+use crate::{
+    context::Context,
+    executor::Executor,
+    fs::{parse_file_id, retry::retry, FileId, FileType},
+    record::{Record, Schema},
+    version::MAX_LEVEL,
+    DbError, DbStorage, DB,
+};
+
+/// Files removed by [`DB::collect_garbage`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    pub tables: Vec<Path>,
+    pub wal_segments: Vec<Path>,
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Remove the SSTables and the WAL segments no longer referred to, left behind by a crash
+    /// between writing a file and recording it in the manifest, or between removing it from the
+    /// manifest and deleting it, e.g. mid-compaction on S3. See [`DbOption::gc_interval`] to
+    /// collect them in the background.
+    ///
+    /// The directories of the levels and of the WAL are listed and compared with the manifest,
+    /// the versions still read by snapshots, the tables of the open bulk load sessions, the WAL
+    /// segments of the memtables and the ones waiting to be archived. Files younger than
+    /// [`DbOption::gc_grace`] are kept, as the flushes and compactions in flight write them
+    /// before recording them. The directories must not be shared with another [`DB`].
+    ///
+    /// [`DbOption::gc_interval`]: crate::DbOption::gc_interval
+    /// [`DbOption::gc_grace`]: crate::DbOption::gc_grace
+    pub async fn collect_garbage(&self) -> Result<GcReport, DbError<R>> {
+        collect_garbage(&self.schema, &self.ctx).await
+    }
+}
+
+pub(crate) async fn collect_garbage<R>(
+    schema: &RwLock<DbStorage<R>>,
+    ctx: &Context<R>,
+) -> Result<GcReport, DbError<R>>
+where
+    R: Record,
+{
+    let option = schema.read().await.option.clone();
+    if option.read_only {
+        return Err(DbError::ReadOnly);
+    }
+    let manager = ctx.storage_manager();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let expired = |gen: FileId| Duration::from_millis(gen.timestamp_ms()) + option.gc_grace <= now;
+
+    // files are listed before the references are read, a file written in between is not listed
+    let mut dirs = Vec::new();
+    for level in 0..MAX_LEVEL {
+        let dir = option.level_fs_path(level).unwrap_or(&option.base_path);
+        if !dirs.contains(dir) {
+            dirs.push(dir.clone());
+        }
+    }
+    let mut tables = Vec::new();
+    for dir in &dirs {
+        let fs = manager.get_fs(dir);
+        let mut stream = retry(option.retry_policy.as_ref(), || fs.list(dir)).await?;
+        while let Some(file_meta) = stream.next().await {
+            let file_meta = file_meta?;
+            // the directories of the WAL and of the version log may share the path of a level
+            if !file_meta
+                .path
+                .filename()
+                .is_some_and(|name| name.ends_with(&format!(".{}", FileType::Parquet)))
+            {
+                continue;
+            }
+            if let Ok(Some(gen)) = parse_file_id(&file_meta.path, FileType::Parquet) {
+                tables.push((dir, gen, file_meta.path));
+            }
+        }
+    }
+    let mut wal_segments = Vec::new();
+    let wal_dir = option.wal_dir_path();
+    let mut stream = retry(option.retry_policy.as_ref(), || {
+        manager.wal_fs().list(&wal_dir)
+    })
+    .await?;
+    while let Some(file_meta) = stream.next().await {
+        let file_meta = file_meta?;
+        if !file_meta.path.as_ref().ends_with("wal") {
+            continue;
+        }
+        if let Ok(Some(wal_id)) = parse_file_id(&file_meta.path, FileType::Wal) {
+            wal_segments.push((wal_id, file_meta.path));
+        }
+    }
+    drop(stream);
+
+    // flushes hold the lock of the memtables until their tables are in the version, so the WAL
+    // segments and the tables of a flush are found in one or the other
+    let (referenced_tables, referenced_wal) = {
+        let schema = schema.read().await;
+        let mut wal_ids = schema
+            .immutables
+            .iter()
+            .filter_map(|(wal_id, _)| *wal_id)
+            .chain(schema.recover_wal_ids.iter().flatten().copied())
+            .collect::<HashSet<_>>();
+        wal_ids.extend(schema.mutable.wal_id().await);
+        // bulk loaded tables are installed before they are removed from the bulk load tables,
+        // which are read first
+        let bulk_tables = ctx.bulk_tables();
+        let (mut tables, version_wal_ids) = ctx.version_set.referenced().await?;
+        tables.extend(bulk_tables);
+        wal_ids.extend(version_wal_ids);
+        (tables, wal_ids)
+    };
+
+    let mut report = GcReport::default();
+    for (dir, gen, path) in tables {
+        if referenced_tables.contains(&gen) || !expired(gen) {
+            continue;
+        }
+        match retry(option.retry_policy.as_ref(), || {
+            manager.get_fs(dir).remove(&path)
+        })
+        .await
+        {
+            Ok(()) => report.tables.push(path),
+            Err(fusio::Error::Io(err)) if err.kind() == ErrorKind::NotFound => (),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    for (wal_id, path) in wal_segments {
+        if referenced_wal.contains(&wal_id) || !expired(wal_id) {
+            continue;
+        }
+        match retry(option.retry_policy.as_ref(), || {
+            manager.wal_fs().remove(&path)
+        })
+        .await
+        {
+            Ok(()) => report.wal_segments.push(path),
+            Err(fusio::Error::Io(err)) if err.kind() == ErrorKind::NotFound => (),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, fs::generate_file_id, inmem::immutable::tests::TestSchema,
+        tests::Test, DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn collect_garbage() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .gc_grace(Duration::ZERO);
+        let db: DB<Test, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+        db.insert(Test {
+            vstring: "key".to_string(),
+            vu32: 0,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+
+        // the files a crash left behind
+        let orphan_table = option.table_path(generate_file_id(), 0);
+        let orphan_wal = option.wal_segment_path(generate_file_id());
+        for path in [&orphan_table, &orphan_wal] {
+            std::fs::write(fusio::path::path_to_local(path).unwrap(), b"orphan").unwrap();
+        }
+
+        let report = db.collect_garbage().await.unwrap();
+        assert_eq!(report.tables, vec![orphan_table]);
+        assert_eq!(report.wal_segments, vec![orphan_wal]);
+        assert_eq!(
+            db.get(&"key".to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap(),
+            Some(0)
+        );
+        assert_eq!(db.collect_garbage().await.unwrap(), Default::default());
+        drop(db);
+
+        // files younger than the grace are kept
+        let db: DB<Test, TokioExecutor> = DB::new(
+            option.gc_grace(Duration::from_secs(60)),
+            TokioExecutor::current(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+        let young_table = temp_dir
+            .path()
+            .join(format!("{}.parquet", generate_file_id()));
+        std::fs::write(&young_table, b"young").unwrap();
+        assert!(db.collect_garbage().await.unwrap().tables.is_empty());
+        assert!(young_table.exists());
+    }
+}
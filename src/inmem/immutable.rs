@@ -6,10 +6,10 @@ use std::{
 };
 
 use arrow::{array::RecordBatch, datatypes::Schema as ArrowSchema};
-use crossbeam_skiplist::SkipMap;
 use parquet::arrow::ProjectionMask;
 
 use crate::{
+    inmem::{conflict_range, map::MemMap},
     record::{option::OptionRecordRef, Key, Record, RecordRef, Schema},
     stream::record_batch::RecordBatchEntry,
     timestamp::{Timestamp, Ts, TsRef, EPOCH},
@@ -60,13 +60,13 @@ where
     A::Record: Send,
 {
     pub(crate) fn new(
-        mutable: SkipMap<Ts<<<A::Record as Record>::Schema as Schema>::Key>, Option<A::Record>>,
+        mutable: Box<dyn MemMap<<<A::Record as Record>::Schema as Schema>::Key, Option<A::Record>>>,
         schema: Arc<ArrowSchema>,
     ) -> Self {
         let mut index = BTreeMap::new();
         let mut builder = A::builder(schema, mutable.len());
 
-        for (offset, (key, value)) in mutable.into_iter().enumerate() {
+        for (offset, (key, value)) in mutable.into_entries().enumerate() {
             builder.push(
                 Ts::new(key.value.as_key_ref(), key.ts),
                 value.as_ref().map(Record::as_record_ref),
@@ -154,6 +154,22 @@ where
             .next()
             .is_some()
     }
+
+    /// whether a key in `range` was written after `ts`
+    pub(crate) fn check_range_conflict(
+        &self,
+        range: (
+            Bound<&<<A::Record as Record>::Schema as Schema>::Key>,
+            Bound<&<<A::Record as Record>::Schema as Schema>::Key>,
+        ),
+        ts: Timestamp,
+    ) -> bool {
+        self.index
+            .range::<TsRef<<<A::Record as Record>::Schema as Schema>::Key>, _>(conflict_range(
+                range,
+            ))
+            .any(|(key, _)| key.ts > ts)
+    }
 }
 
 pub(crate) struct ImmutableScan<'iter, R>
@@ -163,6 +179,7 @@ where
     range: Range<'iter, Ts<<R::Schema as Schema>::Key>, u32>,
     record_batch: &'iter RecordBatch,
     projection_mask: ProjectionMask,
+    borrow_values: bool,
 }
 
 impl<'iter, R> ImmutableScan<'iter, R>
@@ -178,6 +195,16 @@ where
             range,
             record_batch,
             projection_mask,
+            borrow_values: false,
+        }
+    }
+
+    /// borrow the values of the records from the record batch, see
+    /// [`RecordRef::from_record_batch_borrowed`](crate::record::RecordRef::from_record_batch_borrowed)
+    pub(crate) fn borrow_values(self, borrow_values: bool) -> Self {
+        Self {
+            borrow_values,
+            ..self
         }
     }
 }
@@ -191,14 +218,19 @@ where
     fn next(&mut self) -> Option<Self::Item> {
         self.range.next().map(|(_, &offset)| {
             let schema = self.record_batch.schema();
-            let record_ref = R::Ref::from_record_batch(
+            let from_record_batch = if self.borrow_values {
+                R::Ref::from_record_batch_borrowed
+            } else {
+                R::Ref::from_record_batch
+            };
+            let record_ref = from_record_batch(
                 self.record_batch,
                 offset as usize,
                 &self.projection_mask,
                 &schema,
             );
             // TODO: remove cloning record batch
-            RecordBatchEntry::new(self.record_batch.clone(), {
+            RecordBatchEntry::new(self.record_batch.clone(), offset as usize, {
                 // Safety: record_ref self-references the record batch
                 unsafe {
                     transmute::<OptionRecordRef<R::Ref<'_>>, OptionRecordRef<R::Ref<'static>>>(
@@ -0,0 +1,387 @@
+//! The ordered maps the mutable memtable keeps the versions of the keys written in, see
+//! [`DbOption::memtable`](crate::DbOption::memtable) and
+//! [`DbOption::memtable_shards`](crate::DbOption::memtable_shards).
+
+use std::{
+    borrow::Borrow,
+    cmp::Ordering,
+    collections::BTreeSet,
+    hash::{BuildHasher, Hash, RandomState},
+    iter::{self, Peekable},
+    marker::PhantomData,
+    ops::Bound,
+    sync::{Arc, RwLock},
+};
+
+use crossbeam_skiplist::{map, SkipMap};
+
+use crate::{
+    timestamp::{Ts, TsRef},
+    MemTableKind,
+};
+
+type TsBounds<'a, K> = (Bound<&'a TsRef<K>>, Bound<&'a TsRef<K>>);
+
+/// An ordered map from the versions of the keys to their values, written while it is read.
+pub(crate) trait MemMap<K, V>: Send + Sync {
+    /// insert `value` at `key`, replacing the value of the same version
+    fn insert(&self, key: Ts<K>, value: V) -> MapEntry<'_, K, V>;
+
+    fn range<'a>(&'a self, range: TsBounds<'a, K>) -> MapRange<'a, K, V>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the entries in the order of their keys
+    fn into_entries(self: Box<Self>) -> Box<dyn Iterator<Item = (Ts<K>, V)>>;
+}
+
+/// an empty map of `kind`, split into `shards` maps of `kind` by the hash of the keys
+pub(crate) fn new_map<K, V>(kind: MemTableKind, shards: usize) -> Box<dyn MemMap<K, V>>
+where
+    K: Ord + Hash + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    if shards > 1 {
+        return Box::new(ShardedMap {
+            shards: (0..shards).map(|_| new_map(kind, 1)).collect(),
+            hasher: RandomState::new(),
+        });
+    }
+    match kind {
+        MemTableKind::SkipList => Box::new(SkipMap::<Ts<K>, V>::new()),
+        MemTableKind::BTree => Box::new(BTreeMemMap {
+            entries: RwLock::new(BTreeSet::new()),
+        }),
+    }
+}
+
+/// An entry of the mutable memtable.
+pub struct MapEntry<'a, K, V>(EntryInner<'a, K, V>);
+
+enum EntryInner<'a, K, V> {
+    SkipList(map::Entry<'a, Ts<K>, V>),
+    BTree(Arc<(Ts<K>, V)>, PhantomData<&'a ()>),
+}
+
+impl<K, V> MapEntry<'_, K, V> {
+    pub fn key(&self) -> &Ts<K> {
+        match &self.0 {
+            EntryInner::SkipList(entry) => entry.key(),
+            EntryInner::BTree(entry, _) => &entry.0,
+        }
+    }
+
+    pub fn value(&self) -> &V {
+        match &self.0 {
+            EntryInner::SkipList(entry) => entry.value(),
+            EntryInner::BTree(entry, _) => &entry.1,
+        }
+    }
+}
+
+impl<K, V> Clone for MapEntry<'_, K, V> {
+    fn clone(&self) -> Self {
+        MapEntry(match &self.0 {
+            EntryInner::SkipList(entry) => EntryInner::SkipList(entry.clone()),
+            EntryInner::BTree(entry, _) => EntryInner::BTree(entry.clone(), PhantomData),
+        })
+    }
+}
+
+/// The entries of the mutable memtable in a range, in the order of their keys.
+pub struct MapRange<'a, K, V>(RangeInner<'a, K, V>);
+
+enum RangeInner<'a, K, V> {
+    SkipList(map::Range<'a, TsRef<K>, TsBounds<'a, K>, Ts<K>, V>),
+    BTree(BTreeRange<'a, K, V>),
+    Sharded(Vec<Peekable<ShardRange<'a, K, V>>>),
+}
+
+impl<'a, K, V> Iterator for MapRange<'a, K, V>
+where
+    K: Ord,
+{
+    type Item = MapEntry<'a, K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.0 {
+            RangeInner::SkipList(range) => range
+                .next()
+                .map(|entry| MapEntry(EntryInner::SkipList(entry))),
+            RangeInner::BTree(range) => range.next(),
+            RangeInner::Sharded(ranges) => next_sorted(ranges, MapEntry::key),
+        }
+    }
+}
+
+impl<K, V> MemMap<K, V> for SkipMap<Ts<K>, V>
+where
+    K: Ord + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    fn insert(&self, key: Ts<K>, value: V) -> MapEntry<'_, K, V> {
+        MapEntry(EntryInner::SkipList(SkipMap::insert(self, key, value)))
+    }
+
+    fn range<'a>(&'a self, range: TsBounds<'a, K>) -> MapRange<'a, K, V> {
+        MapRange(RangeInner::SkipList(SkipMap::range(self, range)))
+    }
+
+    fn len(&self) -> usize {
+        SkipMap::len(self)
+    }
+
+    fn into_entries(self: Box<Self>) -> Box<dyn Iterator<Item = (Ts<K>, V)>> {
+        Box::new((*self).into_iter())
+    }
+}
+
+/// A B-tree of the entries behind a lock. Scans share the entries they read with the map and
+/// look the next one up under the lock, so they never block the writes for long.
+struct BTreeMemMap<K, V> {
+    entries: RwLock<BTreeSet<Shared<K, V>>>,
+}
+
+struct Shared<K, V>(Arc<(Ts<K>, V)>);
+
+impl<K, V> Shared<K, V> {
+    fn key(&self) -> &TsRef<K> {
+        TsRef::new(&self.0 .0.value, self.0 .0.ts)
+    }
+}
+
+impl<K, V> Borrow<TsRef<K>> for Shared<K, V> {
+    fn borrow(&self) -> &TsRef<K> {
+        self.key()
+    }
+}
+
+impl<K: Ord, V> PartialEq for Shared<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: Ord, V> Eq for Shared<K, V> {}
+
+impl<K: Ord, V> PartialOrd for Shared<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, V> Ord for Shared<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(other.key())
+    }
+}
+
+impl<K, V> MemMap<K, V> for BTreeMemMap<K, V>
+where
+    K: Ord + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    fn insert(&self, key: Ts<K>, value: V) -> MapEntry<'_, K, V> {
+        let entry = Arc::new((key, value));
+        self.entries.write().unwrap().replace(Shared(entry.clone()));
+        MapEntry(EntryInner::BTree(entry, PhantomData))
+    }
+
+    fn range<'a>(&'a self, range: TsBounds<'a, K>) -> MapRange<'a, K, V> {
+        MapRange(RangeInner::BTree(BTreeRange {
+            map: self,
+            lower: range.0,
+            upper: range.1,
+            last: None,
+        }))
+    }
+
+    fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    fn into_entries(self: Box<Self>) -> Box<dyn Iterator<Item = (Ts<K>, V)>> {
+        Box::new(
+            self.entries
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(|Shared(entry)| {
+                    Arc::into_inner(entry).expect("entries are not shared once the map is consumed")
+                }),
+        )
+    }
+}
+
+struct BTreeRange<'a, K, V> {
+    map: &'a BTreeMemMap<K, V>,
+    lower: Bound<&'a TsRef<K>>,
+    upper: Bound<&'a TsRef<K>>,
+    // the entry returned last, the range goes on after it
+    last: Option<Arc<(Ts<K>, V)>>,
+}
+
+impl<'a, K, V> BTreeRange<'a, K, V>
+where
+    K: Ord,
+{
+    fn next(&mut self) -> Option<MapEntry<'a, K, V>> {
+        let next = {
+            let lower = match &self.last {
+                Some(last) => Bound::Excluded(TsRef::new(&last.0.value, last.0.ts)),
+                None => self.lower,
+            };
+            if is_empty_range(lower, self.upper) {
+                return None;
+            }
+            let entries = self.map.entries.read().unwrap();
+            entries
+                .range::<TsRef<K>, _>((lower, self.upper))
+                .next()?
+                .0
+                .clone()
+        };
+        self.last = Some(next.clone());
+        Some(MapEntry(EntryInner::BTree(next, PhantomData)))
+    }
+}
+
+type ShardRange<'a, K, V> = Box<dyn Iterator<Item = MapEntry<'a, K, V>> + Send + 'a>;
+
+/// The maps of the keys of each hash, written under their own synchronization. Every version of
+/// a key is in the same shard, the ranges of the shards are merged.
+struct ShardedMap<K, V> {
+    shards: Vec<Box<dyn MemMap<K, V>>>,
+    hasher: RandomState,
+}
+
+impl<K, V> MemMap<K, V> for ShardedMap<K, V>
+where
+    K: Ord + Hash + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    fn insert(&self, key: Ts<K>, value: V) -> MapEntry<'_, K, V> {
+        let shard = self.hasher.hash_one(&key.value) as usize % self.shards.len();
+        self.shards[shard].insert(key, value)
+    }
+
+    fn range<'a>(&'a self, range: TsBounds<'a, K>) -> MapRange<'a, K, V> {
+        MapRange(RangeInner::Sharded(
+            self.shards
+                .iter()
+                .map(|shard| (Box::new(shard.range(range)) as ShardRange<'a, K, V>).peekable())
+                .collect(),
+        ))
+    }
+
+    fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len()).sum()
+    }
+
+    fn into_entries(self: Box<Self>) -> Box<dyn Iterator<Item = (Ts<K>, V)>> {
+        let mut shards = self
+            .shards
+            .into_iter()
+            .map(|shard| shard.into_entries().peekable())
+            .collect::<Vec<_>>();
+        Box::new(iter::from_fn(move || next_sorted(&mut shards, pair_key)))
+    }
+}
+
+fn pair_key<K, V>(pair: &(Ts<K>, V)) -> &Ts<K> {
+    &pair.0
+}
+
+/// the next item of the sorted `iters`, in the order of the keys
+fn next_sorted<I, K>(iters: &mut [Peekable<I>], key: fn(&I::Item) -> &Ts<K>) -> Option<I::Item>
+where
+    I: Iterator,
+    K: Ord,
+{
+    let (next, _) = iters
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(idx, iter)| iter.peek().map(|item| (idx, key(item))))
+        .min_by(|(_, a), (_, b)| a.cmp(b))?;
+    iters[next].next()
+}
+
+/// whether no key is in the range, [`BTreeSet::range`] panics on it
+fn is_empty_range<Q: Ord + ?Sized>(lower: Bound<&Q>, upper: Bound<&Q>) -> bool {
+    match (lower, upper) {
+        (Bound::Included(lower), Bound::Included(upper)) => lower > upper,
+        (
+            Bound::Included(lower) | Bound::Excluded(lower),
+            Bound::Included(upper) | Bound::Excluded(upper),
+        ) => lower >= upper,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use super::new_map;
+    use crate::{
+        timestamp::{Ts, TsRef},
+        MemTableKind,
+    };
+
+    #[test]
+    fn map_range() {
+        for (kind, shards) in [
+            (MemTableKind::SkipList, 1),
+            (MemTableKind::BTree, 1),
+            (MemTableKind::SkipList, 3),
+            (MemTableKind::BTree, 3),
+        ] {
+            let map = new_map::<String, u32>(kind, shards);
+            for (key, ts) in [("b", 1), ("a", 1), ("b", 3), ("c", 2)] {
+                map.insert(Ts::new(key.to_string(), ts.into()), ts);
+            }
+            let entry = map.insert(Ts::new("a".to_string(), 1.into()), 10);
+            assert_eq!(*entry.value(), 10);
+            assert_eq!(map.len(), 4);
+
+            let keys = map
+                .range((Bound::Unbounded, Bound::Unbounded))
+                .map(|entry| (entry.key().value.clone(), entry.key().ts))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                keys,
+                vec![
+                    ("a".to_string(), 1.into()),
+                    ("b".to_string(), 3.into()),
+                    ("b".to_string(), 1.into()),
+                    ("c".to_string(), 2.into()),
+                ]
+            );
+
+            let key = "b".to_string();
+            let mut range = map.range((
+                Bound::Included(TsRef::new(&key, 2.into())),
+                Bound::Included(TsRef::new(&key, 0.into())),
+            ));
+            assert_eq!(*range.next().unwrap().value(), 1);
+            assert!(range.next().is_none());
+            assert!(map
+                .range((
+                    Bound::Excluded(TsRef::new(&key, 1.into())),
+                    Bound::Excluded(TsRef::new(&key, 1.into())),
+                ))
+                .next()
+                .is_none());
+
+            let entries = map
+                .into_entries()
+                .map(|(_, value)| value)
+                .collect::<Vec<_>>();
+            assert_eq!(entries, vec![10, 3, 1, 2]);
+        }
+    }
+}
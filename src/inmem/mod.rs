@@ -1,2 +1,24 @@
+use std::ops::Bound;
+
+use crate::timestamp::{TsRef, EPOCH};
+
 pub mod immutable;
+pub mod map;
 pub(crate) mod mutable;
+
+/// the range of every version of the keys in `range`, newest first
+pub(crate) fn conflict_range<'r, K>(
+    range: (Bound<&'r K>, Bound<&'r K>),
+) -> (Bound<&'r TsRef<K>>, Bound<&'r TsRef<K>>) {
+    let lower = match range.0 {
+        Bound::Included(key) => Bound::Included(TsRef::new(key, u32::MAX.into())),
+        Bound::Excluded(key) => Bound::Excluded(TsRef::new(key, EPOCH)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    let upper = match range.1 {
+        Bound::Included(key) => Bound::Included(TsRef::new(key, EPOCH)),
+        Bound::Excluded(key) => Bound::Excluded(TsRef::new(key, u32::MAX.into())),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (lower, upper)
+}
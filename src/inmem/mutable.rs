@@ -1,44 +1,55 @@
-use std::{ops::Bound, sync::Arc};
+use std::{
+    ops::Bound,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant},
+};
 
 use async_lock::Mutex;
-use crossbeam_skiplist::{
-    map::{Entry, Range},
-    SkipMap,
-};
 use fusio::DynFs;
+use fusio_log::Encode;
 
 use crate::{
     fs::{generate_file_id, FileId},
-    inmem::immutable::Immutable,
+    inmem::{
+        conflict_range,
+        immutable::Immutable,
+        map::{new_map, MapEntry, MapRange, MemMap},
+    },
     record::{KeyRef, Record, Schema},
     timestamp::{Timestamp, Ts, TsRef, EPOCH},
     trigger::FreezeTrigger,
     wal::{
+        group::GroupCommit,
         log::{Log, LogType},
         WalFile,
     },
-    DbError, DbOption,
+    DbError, DbOption, FlushPolicy, WalSyncMode,
 };
 
-pub(crate) type MutableScan<'scan, R> = Range<
-    'scan,
-    TsRef<<<R as Record>::Schema as Schema>::Key>,
-    (
-        Bound<&'scan TsRef<<<R as Record>::Schema as Schema>::Key>>,
-        Bound<&'scan TsRef<<<R as Record>::Schema as Schema>::Key>>,
-    ),
-    Ts<<<R as Record>::Schema as Schema>::Key>,
-    Option<R>,
->;
+pub(crate) type MutableScan<'scan, R> =
+    MapRange<'scan, <<R as Record>::Schema as Schema>::Key, Option<R>>;
 
 pub(crate) struct MutableMemTable<R>
 where
     R: Record,
 {
-    data: SkipMap<Ts<<R::Schema as Schema>::Key>, Option<R>>,
+    data: Box<dyn MemMap<<R::Schema as Schema>::Key, Option<R>>>,
     wal: Option<Mutex<WalFile<R>>>,
+    group_commit: Option<GroupCommit>,
+    wal_sync_mode: WalSyncMode,
     trigger: Arc<dyn FreezeTrigger<R>>,
     schema: Arc<R::Schema>,
+    /// bytes of the keys and values written
+    size: AtomicUsize,
+    flush_policy: FlushPolicy,
+    /// records written
+    rows: AtomicUsize,
+    /// bytes logged to the WAL
+    wal_bytes: AtomicUsize,
+    first_write: OnceLock<Instant>,
 }
 
 impl<R> MutableMemTable<R>
@@ -52,28 +63,47 @@ where
         schema: Arc<R::Schema>,
     ) -> Result<Self, fusio::Error> {
         let mut wal = None;
+        let mut group_commit = None;
         if option.use_wal {
+            group_commit = option.wal_group_commit.map(GroupCommit::new);
             let file_id = generate_file_id();
 
             wal = Some(Mutex::new(
                 WalFile::<R>::new(
                     fs,
-                    option.wal_path(file_id),
+                    option.wal_segment_path(file_id),
                     option.wal_buffer_size,
                     file_id,
+                    option.encryption.clone(),
                 )
-                .await,
+                .await
+                .with_compression(option.wal_compression),
             ));
         };
 
         Ok(Self {
-            data: Default::default(),
+            data: new_map(option.memtable, option.memtable_shards),
             wal,
+            group_commit,
+            wal_sync_mode: option.wal_sync_mode,
             trigger,
             schema,
+            size: AtomicUsize::new(0),
+            flush_policy: option.flush_policy,
+            rows: AtomicUsize::new(0),
+            wal_bytes: AtomicUsize::new(0),
+            first_write: OnceLock::new(),
         })
     }
 
+    /// the id of the WAL segment of the memtable, `None` without WAL
+    pub(crate) async fn wal_id(&self) -> Option<FileId> {
+        match &self.wal {
+            Some(wal) => Some(wal.lock().await.file_id()),
+            None => None,
+        }
+    }
+
     pub(crate) async fn destroy(&mut self) -> Result<(), DbError<R>> {
         if let Some(wal) = self.wal.take() {
             wal.into_inner().remove().await?;
@@ -115,30 +145,62 @@ where
         let timestamped_key = Ts::new(key, ts);
 
         let record_entry = Log::new(timestamped_key, value, log_ty);
-        if let (Some(_log_ty), Some(wal)) = (log_ty, &self.wal) {
-            wal.lock()
-                .await
-                .write(&record_entry)
-                .await
+        if let (Some(log_ty), Some(wal)) = (log_ty, &self.wal) {
+            // the last log of a commit waits for the logs of the commit to be written
+            let commit = matches!(log_ty, LogType::Full | LogType::Last);
+            {
+                self.wal_bytes
+                    .fetch_add(record_entry.size(), Ordering::Relaxed);
+                let mut wal = wal.lock().await;
+                wal.write(&record_entry)
+                    .await
+                    .map_err(|e| DbError::WalWrite(Box::new(e)))?;
+                match self.wal_sync_mode {
+                    WalSyncMode::Commit if commit && self.group_commit.is_none() => {
+                        wal.sync().await
+                    }
+                    WalSyncMode::Interval(interval) if commit => wal.sync_every(interval).await,
+                    _ => Ok(()),
+                }
                 .map_err(|e| DbError::WalWrite(Box::new(e)))?;
+            }
+            if let (true, Some(group_commit)) = (commit, &self.group_commit) {
+                group_commit
+                    .commit(wal)
+                    .await
+                    .map_err(|e| DbError::WalWrite(Box::new(e)))?;
+            }
         }
 
+        self.size.fetch_add(
+            record_entry.key.value().size() + record_entry.value.as_ref().map_or(0, Record::size),
+            Ordering::Relaxed,
+        );
+        let rows = self.rows.fetch_add(1, Ordering::Relaxed) + 1;
+        self.first_write.get_or_init(Instant::now);
         let entry = self.data.insert(record_entry.key, record_entry.value);
 
-        Ok(entry
+        let is_excess = entry
             .value()
             .as_ref()
             .map(|v| self.trigger.check_if_exceed(v))
-            .unwrap_or(false))
+            .unwrap_or(false);
+        Ok(is_excess
+            || self.flush_policy.max_rows.is_some_and(|max| rows >= max)
+            || self
+                .flush_policy
+                .max_wal_bytes
+                .is_some_and(|max| self.wal_bytes.load(Ordering::Relaxed) >= max)
+            || self.is_expired())
     }
 
     pub(crate) fn get(
         &self,
         key: &<R::Schema as Schema>::Key,
         ts: Timestamp,
-    ) -> Option<Entry<'_, Ts<<R::Schema as Schema>::Key>, Option<R>>> {
+    ) -> Option<MapEntry<'_, <R::Schema as Schema>::Key, Option<R>>> {
         self.data
-            .range::<TsRef<<R::Schema as Schema>::Key>, _>((
+            .range((
                 Bound::Included(TsRef::new(key, ts)),
                 Bound::Included(TsRef::new(key, EPOCH)),
             ))
@@ -173,7 +235,7 @@ where
 
     pub(crate) fn check_conflict(&self, key: &<R::Schema as Schema>::Key, ts: Timestamp) -> bool {
         self.data
-            .range::<TsRef<<R::Schema as Schema>::Key>, _>((
+            .range((
                 Bound::Excluded(TsRef::new(key, u32::MAX.into())),
                 Bound::Excluded(TsRef::new(key, ts)),
             ))
@@ -181,6 +243,20 @@ where
             .is_some()
     }
 
+    /// whether a key in `range` was written after `ts`
+    pub(crate) fn check_range_conflict(
+        &self,
+        range: (
+            Bound<&<R::Schema as Schema>::Key>,
+            Bound<&<R::Schema as Schema>::Key>,
+        ),
+        ts: Timestamp,
+    ) -> bool {
+        self.data
+            .range(conflict_range(range))
+            .any(|entry| entry.key().ts > ts)
+    }
+
     pub(crate) async fn into_immutable(
         self,
     ) -> Result<
@@ -218,13 +294,33 @@ where
     pub(crate) fn len(&self) -> usize {
         self.data.len()
     }
+
+    /// bytes of the keys and values written, overwritten versions included
+    pub(crate) fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// time since the first write, `None` before it
+    pub(crate) fn age(&self) -> Option<Duration> {
+        self.first_write.get().map(Instant::elapsed)
+    }
+
+    /// whether the memtable is older than [`FlushPolicy::max_age`]
+    pub(crate) fn is_expired(&self) -> bool {
+        match (self.flush_policy.max_age, self.age()) {
+            (Some(max_age), Some(age)) => age >= max_age,
+            _ => false,
+        }
+    }
 }
 
 #[cfg(all(test, feature = "tokio"))]
 mod tests {
-    use std::{ops::Bound, sync::Arc};
+    use std::{ops::Bound, sync::Arc, time::Duration};
 
     use fusio::{disk::TokioFs, path::Path, DynFs};
+    use fusio_log::FsOptions;
+    use futures_util::{future::join_all, StreamExt};
 
     use super::MutableMemTable;
     use crate::{
@@ -233,8 +329,8 @@ mod tests {
         tests::{Test, TestRef},
         timestamp::Ts,
         trigger::TriggerFactory,
-        wal::log::LogType,
-        DbOption,
+        wal::{log::LogType, WalFile},
+        DbOption, MemTableKind, WalSyncMode,
     };
 
     #[tokio::test]
@@ -294,6 +390,96 @@ mod tests {
         assert!(mem_table.get(&key_2, 1_u32.into()).is_some());
     }
 
+    #[tokio::test]
+    async fn group_commit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fs = Arc::new(TokioFs) as Arc<dyn DynFs>;
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .wal_buffer_size(1024 * 1024)
+        .wal_group_commit(Duration::from_millis(1));
+        fs.create_dir_all(&option.wal_dir_path()).await.unwrap();
+
+        let trigger = TriggerFactory::create(option.trigger_type);
+        let mem_table =
+            MutableMemTable::<Test>::new(&option, trigger, fs.clone(), Arc::new(TestSchema {}))
+                .await
+                .unwrap();
+        let commits = (0..100_u32).map(|i| {
+            mem_table.insert(
+                LogType::Full,
+                Test {
+                    vstring: i.to_string(),
+                    vu32: i,
+                    vbool: None,
+                },
+                i.into(),
+            )
+        });
+        for result in join_all(commits).await {
+            result.unwrap();
+        }
+
+        // the logs are written once the commits return, though they fit in the WAL buffer
+        assert_eq!(written_logs(&option, &mem_table).await, 100);
+    }
+
+    #[tokio::test]
+    async fn wal_sync_mode() {
+        for (wal_sync_mode, logs) in [
+            (WalSyncMode::Commit, 2),
+            (WalSyncMode::Interval(Duration::from_secs(3600)), 1),
+            (WalSyncMode::Buffered, 0),
+        ] {
+            let temp_dir = tempfile::tempdir().unwrap();
+            let fs = Arc::new(TokioFs) as Arc<dyn DynFs>;
+            let option = DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            )
+            .wal_buffer_size(1024 * 1024)
+            .wal_sync_mode(wal_sync_mode);
+            fs.create_dir_all(&option.wal_dir_path()).await.unwrap();
+
+            let trigger = TriggerFactory::create(option.trigger_type);
+            let mem_table =
+                MutableMemTable::<Test>::new(&option, trigger, fs.clone(), Arc::new(TestSchema {}))
+                    .await
+                    .unwrap();
+            for i in 0..2_u32 {
+                mem_table
+                    .insert(
+                        LogType::Full,
+                        Test {
+                            vstring: i.to_string(),
+                            vu32: i,
+                            vbool: None,
+                        },
+                        i.into(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            assert_eq!(written_logs(&option, &mem_table).await, logs);
+        }
+    }
+
+    /// the logs of the WAL segment of `mem_table` written out of the WAL buffer
+    async fn written_logs(option: &DbOption, mem_table: &MutableMemTable<Test>) -> usize {
+        let file_id = mem_table.wal.as_ref().unwrap().lock().await.file_id();
+        let mut stream = std::pin::pin!(
+            WalFile::<Test>::recover(FsOptions::Local, option.wal_segment_path(file_id), None)
+                .await
+        );
+        let mut logs = 0;
+        while let Some(batch) = stream.next().await {
+            logs += batch.unwrap().len();
+        }
+        logs
+    }
+
     #[tokio::test]
     async fn range() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -384,6 +570,60 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn btree_range() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fs = Arc::new(TokioFs) as Arc<dyn DynFs>;
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &StringSchema,
+        )
+        .memtable(MemTableKind::BTree);
+        fs.create_dir_all(&option.wal_dir_path()).await.unwrap();
+
+        let trigger = TriggerFactory::create(option.trigger_type);
+
+        let mutable =
+            MutableMemTable::<String>::new(&option, trigger, fs.clone(), Arc::new(StringSchema))
+                .await
+                .unwrap();
+
+        for (key, ts) in [("1", 0_u32), ("2", 0), ("2", 1), ("3", 1), ("4", 0)] {
+            mutable
+                .insert(LogType::Full, key.into(), ts.into())
+                .await
+                .unwrap();
+        }
+
+        let lower = "1".to_string();
+        let upper = "4".to_string();
+        let keys = mutable
+            .scan(
+                (Bound::Excluded(&lower), Bound::Excluded(&upper)),
+                0_u32.into(),
+            )
+            .map(|entry| entry.key().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                Ts::new("2".into(), 1_u32.into()),
+                Ts::new("2".into(), 0_u32.into()),
+                Ts::new("3".into(), 1_u32.into()),
+            ]
+        );
+        assert_eq!(
+            mutable.get(&"2".to_string(), 0_u32.into()).unwrap().key(),
+            &Ts::new("2".into(), 0_u32.into())
+        );
+        assert!(mutable.check_conflict(&"3".to_string(), 0_u32.into()));
+        assert!(!mutable.check_conflict(&"3".to_string(), 1_u32.into()));
+        assert!(!mutable.check_conflict(&"3".to_string(), u32::MAX.into()));
+
+        let (_, immutable) = mutable.into_immutable().await.unwrap();
+        assert_eq!(immutable.as_record_batch().num_rows(), 5);
+    }
+
     #[tokio::test]
     async fn test_dyn_read() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -0,0 +1,223 @@
+use std::sync::Arc;
+
+use arrow::{
+    array::{Array, ArrayRef, AsArray},
+    compute::{sort_to_indices, SortOptions},
+    datatypes::SchemaRef,
+};
+use fusio::{path::Path, DynFs};
+use fusio_parquet::reader::AsyncReader;
+use futures_util::StreamExt;
+use parquet::arrow::{
+    arrow_reader::statistics::StatisticsConverter, ParquetRecordBatchStreamBuilder, ProjectionMask,
+};
+
+use crate::{fs::FileType, magic, ondisk::sstable::SsTable, record::DynRecord, DbError};
+
+/// What an SSTable holds, read from its footer by [`SsTable::inspect`].
+#[derive(Debug, Clone)]
+pub struct SsTableInspection {
+    /// the Arrow schema of the table, `_null` and `_ts` included
+    pub schema: SchemaRef,
+    /// the primary key column, taken from the sort order of the table
+    pub primary_key: Option<String>,
+    /// smallest primary key of the table as a single-row array, `None` when it is empty
+    pub min_key: Option<ArrayRef>,
+    /// largest primary key of the table as a single-row array, `None` when it is empty
+    pub max_key: Option<ArrayRef>,
+    /// rows of the table, including overwritten versions and tombstones
+    pub rows: u64,
+    /// tombstones among the rows
+    pub tombstones: u64,
+    pub row_groups: usize,
+    pub created_by: Option<String>,
+    /// the statistics of every column of the schema, in its order
+    pub columns: Vec<ColumnInspection>,
+}
+
+/// The statistics of a column of an SSTable, see [`SsTableInspection::columns`].
+#[derive(Debug, Clone)]
+pub struct ColumnInspection {
+    pub name: String,
+    /// smallest value of the column as a single-row array, `None` without statistics
+    pub min: Option<ArrayRef>,
+    /// largest value of the column as a single-row array, `None` without statistics
+    pub max: Option<ArrayRef>,
+    /// `None` when a row group has no null count
+    pub null_count: Option<u64>,
+    /// size of the column chunks in bytes, `0` for nested columns
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+}
+
+impl SsTable<DynRecord> {
+    /// Inspect the SSTable at `path` of `fs`, written by any [`DB`](crate::DB) whatever its
+    /// record, e.g. to audit the tables on disk from an offline admin tool. The key range and
+    /// the column statistics are read from the footer, only the `_null` column is read to count
+    /// the tombstones.
+    ///
+    /// Encrypted tables can't be inspected, see
+    /// [`DbOption::encryption`](crate::DbOption::encryption).
+    pub async fn inspect(
+        fs: &Arc<dyn DynFs>,
+        path: &Path,
+    ) -> Result<SsTableInspection, DbError<DynRecord>> {
+        let file = fs
+            .open_options(path, FileType::Parquet.open_options(true))
+            .await?;
+        let size = file.size().await?;
+        let builder =
+            ParquetRecordBatchStreamBuilder::new(AsyncReader::new(file, size).await?).await?;
+        let schema = builder.schema().clone();
+        let metadata = builder.metadata().clone();
+        let parquet_schema = metadata.file_metadata().schema_descr();
+        let row_groups = metadata.row_groups();
+
+        let mut columns = Vec::with_capacity(schema.fields().len());
+        for field in schema.fields() {
+            let converter = StatisticsConverter::try_new(field.name(), &schema, parquet_schema)?;
+            let null_counts = converter.row_group_null_counts(row_groups)?;
+            let (compressed_size, uncompressed_size) = match converter.parquet_column_index() {
+                Some(index) if parquet_schema.column(index).path().parts().len() == 1 => row_groups
+                    .iter()
+                    .fold((0, 0), |(compressed, uncompressed), row_group| {
+                        let column = row_group.column(index);
+                        (
+                            compressed + column.compressed_size() as u64,
+                            uncompressed + column.uncompressed_size() as u64,
+                        )
+                    }),
+                _ => (0, 0),
+            };
+            columns.push(ColumnInspection {
+                name: field.name().clone(),
+                min: extreme(&converter.row_group_mins(row_groups)?, false),
+                max: extreme(&converter.row_group_maxes(row_groups)?, true),
+                null_count: (null_counts.null_count() == 0)
+                    .then(|| null_counts.values().iter().sum()),
+                compressed_size,
+                uncompressed_size,
+            });
+        }
+
+        // tables are sorted by `_ts` and their primary key
+        let ts_index = schema.index_of(magic::TS).ok();
+        let primary_key = row_groups
+            .first()
+            .and_then(|row_group| row_group.sorting_columns())
+            .and_then(|sorting_columns| {
+                sorting_columns
+                    .iter()
+                    .map(|sorting_column| sorting_column.column_idx as usize)
+                    .find(|index| Some(*index) != ts_index)
+            })
+            .and_then(|index| parquet_schema.columns().get(index))
+            .map(|column| column.path().parts()[0].clone());
+        let (min_key, max_key) = primary_key
+            .as_ref()
+            .and_then(|primary_key| columns.iter().find(|column| &column.name == primary_key))
+            .map(|column| (column.min.clone(), column.max.clone()))
+            .unwrap_or_default();
+
+        let mut tombstones = 0;
+        if let Ok(null_index) = schema.index_of(magic::NULL) {
+            let mut stream = builder
+                .with_projection(ProjectionMask::roots(parquet_schema, [null_index]))
+                .build()?;
+            while let Some(batch) = stream.next().await.transpose()? {
+                tombstones += batch.column(0).as_boolean().true_count() as u64;
+            }
+        }
+
+        Ok(SsTableInspection {
+            primary_key,
+            min_key,
+            max_key,
+            rows: metadata.file_metadata().num_rows() as u64,
+            tombstones,
+            row_groups: row_groups.len(),
+            created_by: metadata.file_metadata().created_by().map(str::to_string),
+            columns,
+            schema,
+        })
+    }
+}
+
+/// the smallest or, if `descending`, the largest valid value of `array` as a single-row array
+fn extreme(array: &ArrayRef, descending: bool) -> Option<ArrayRef> {
+    let options = SortOptions {
+        descending,
+        nulls_first: false,
+    };
+    let indices = sort_to_indices(array, Some(options), Some(1)).ok()?;
+    let index = *indices.values().first()? as usize;
+    array.is_valid(index).then(|| array.slice(index, 1))
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, AsArray};
+    use fusio::{disk::LocalFs, path::Path, DynFs};
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema,
+        ondisk::sstable::SsTable, tests::Test, DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn inspect() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+        for (key, vu32) in [("b", 2), ("a", 1)] {
+            db.insert(Test {
+                vstring: key.to_string(),
+                vu32,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.remove("c".to_string()).await.unwrap();
+        db.flush().await.unwrap();
+
+        let gen = db.ctx.version_set.current().await.level_slice[0][0].gen;
+        let fs: Arc<dyn DynFs> = Arc::new(LocalFs {});
+        let inspection = SsTable::inspect(&fs, &option.table_path(gen, 0))
+            .await
+            .unwrap();
+
+        assert_eq!(inspection.rows, 3);
+        assert_eq!(inspection.tombstones, 1);
+        assert_eq!(inspection.primary_key.as_deref(), Some("vstring"));
+        let key = |key: &Option<ArrayRef>| {
+            key.as_ref()
+                .unwrap()
+                .as_string::<i32>()
+                .value(0)
+                .to_string()
+        };
+        assert_eq!(key(&inspection.min_key), "a");
+        assert_eq!(key(&inspection.max_key), "c");
+        assert_eq!(
+            inspection
+                .columns
+                .iter()
+                .map(|column| column.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["_null", "_ts", "vstring", "vu32", "vbool"]
+        );
+        let vbool = &inspection.columns[4];
+        assert_eq!(vbool.null_count, Some(3));
+        assert!(vbool.min.is_none());
+    }
+}
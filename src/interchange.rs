@@ -0,0 +1,644 @@
+use std::{ops::Bound, pin::pin, str::FromStr, sync::Arc};
+
+use arrow::{array::RecordBatch, csv::WriterBuilder, error::ArrowError, json::LineDelimitedWriter};
+use fusio::{path::Path, Read, Write};
+use futures_util::StreamExt;
+use parquet::errors::ParquetError;
+
+use crate::{
+    executor::Executor,
+    fs::FileType,
+    magic::USER_COLUMN_OFFSET,
+    record::{Cell, DataType, DynRecord, Schema, Value, ValueDesc, F32, F64},
+    DbError, DB,
+};
+
+/// rows committed at once by [`DB::import_text`]
+const IMPORT_BATCH_SIZE: usize = 1024;
+
+/// rows of the batches read from the snapshot by [`DB::export_text`]
+const EXPORT_BATCH_SIZE: usize = 8192;
+
+/// bytes read at once from the imported file
+const READ_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Text formats of the rows imported by [`DB::import_text`] and exported by
+/// [`DB::export_text`].
+///
+/// Values are coerced to the types of the columns: numbers and booleans may be given as strings,
+/// `Bytes` columns as hex strings. Columns missing in a row are null.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextFormat {
+    /// comma separated values with a header naming the columns, in any order. Fields are quoted
+    /// with `"`, empty fields are null.
+    Csv,
+    /// a JSON object per line, keyed by the names of the columns
+    NdJson,
+}
+
+/// configure [`DB::import_text`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportOptions {
+    pub(crate) max_bad_rows: usize,
+}
+
+impl ImportOptions {
+    /// skip up to `max_bad_rows` rows that can't be read as records, reporting them in
+    /// [`ImportReport::bad_rows`], instead of failing on the first one
+    pub fn max_bad_rows(self, max_bad_rows: usize) -> Self {
+        Self { max_bad_rows }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// rows written to the [`DB`]
+    pub rows: usize,
+    /// rows skipped, see [`ImportOptions::max_bad_rows`]
+    pub bad_rows: Vec<BadRow>,
+}
+
+/// a row that can't be read as a record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadRow {
+    /// line of the file the row starts at, from 1
+    pub line: usize,
+    pub error: String,
+}
+
+impl<E> DB<DynRecord, E>
+where
+    E: Executor + Send + Sync + 'static,
+{
+    /// Import the rows of the file at `path` of the base fs in `format`, streaming them into the
+    /// [`DB`] by batches. Every batch is committed on its own, the batches committed before an
+    /// error stay in the [`DB`].
+    ///
+    /// # Error
+    /// Returns [`DbError::InvalidImport`] on the bad row exceeding
+    /// [`ImportOptions::max_bad_rows`], or if the CSV header names an unknown column.
+    pub async fn import_text(
+        &self,
+        path: &Path,
+        format: TextFormat,
+        options: ImportOptions,
+    ) -> Result<ImportReport, DbError<DynRecord>> {
+        let schema = self.schema.read().await.record_schema.clone();
+        let descs = schema.value_descs();
+        let file = self
+            .ctx
+            .manager
+            .base_fs()
+            .open_options(path, FileType::Parquet.open_options(true))
+            .await?;
+        let mut lines = Lines::new(file).await?;
+
+        let mut header = None;
+        if format == TextFormat::Csv {
+            let Some((_, line)) = lines.next().await? else {
+                return Ok(ImportReport::default());
+            };
+            let names = String::from_utf8(line)
+                .ok()
+                .and_then(|line| split_record(&line))
+                .ok_or_else(|| DbError::InvalidImport("malformed CSV header".to_string()))?;
+            header = Some(
+                names
+                    .iter()
+                    .map(|name| {
+                        let name = name.as_deref().unwrap_or_default();
+                        descs
+                            .iter()
+                            .position(|desc| desc.name == name)
+                            .ok_or_else(|| DbError::InvalidImport(format!("unknown column {name}")))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+        }
+
+        let mut report = ImportReport::default();
+        let mut records = Vec::with_capacity(IMPORT_BATCH_SIZE);
+        while let Some((line, row)) = lines.next().await? {
+            if row.is_empty() {
+                continue;
+            }
+            let record = match &header {
+                Some(header) => csv_record(&mut lines, row, header, descs).await?,
+                None => json_record(&row, descs),
+            };
+            match record {
+                Ok(values) => records.push(DynRecord::new(values, schema.primary_index())),
+                Err(error) => {
+                    if report.bad_rows.len() == options.max_bad_rows {
+                        return Err(DbError::InvalidImport(format!("line {line}: {error}")));
+                    }
+                    report.bad_rows.push(BadRow { line, error });
+                }
+            }
+            if records.len() == IMPORT_BATCH_SIZE {
+                report.rows += records.len();
+                self.write_batch(records.drain(..), self.ctx.increase_ts())
+                    .await?;
+            }
+        }
+        if !records.is_empty() {
+            report.rows += records.len();
+            self.write_batch(records.into_iter(), self.ctx.increase_ts())
+                .await?;
+        }
+
+        Ok(report)
+    }
+
+    /// Export the latest version of every record to the file at `path` of the base fs in
+    /// `format`. Returns the number of exported records.
+    pub async fn export_text(
+        &self,
+        path: &Path,
+        format: TextFormat,
+    ) -> Result<usize, DbError<DynRecord>> {
+        let full_schema = self.ctx.arrow_schema().clone();
+        let indices = (USER_COLUMN_OFFSET..full_schema.fields().len()).collect::<Vec<_>>();
+        let mut file = self
+            .ctx
+            .manager
+            .base_fs()
+            .open_options(path, FileType::Parquet.open_options(false))
+            .await?;
+
+        let snapshot = self.snapshot().await;
+        let mut stream = pin!(
+            snapshot
+                .scan((Bound::Unbounded, Bound::Unbounded))
+                .scan_batches(EXPORT_BATCH_SIZE)
+                .await?
+        );
+        let mut rows = 0;
+        let mut is_first = true;
+        while let Some(batch) = stream.next().await.transpose()? {
+            let batch = batch.project(&indices).map_err(ParquetError::from)?;
+            let (result, _) = file
+                .write_all(encode(format, &batch, is_first).map_err(ParquetError::from)?)
+                .await;
+            result?;
+            rows += batch.num_rows();
+            is_first = false;
+        }
+        // the header is written even without records
+        if is_first && format == TextFormat::Csv {
+            let schema = Arc::new(full_schema.project(&indices).map_err(ParquetError::from)?);
+            let (result, _) = file
+                .write_all(
+                    encode(format, &RecordBatch::new_empty(schema), true)
+                        .map_err(ParquetError::from)?,
+                )
+                .await;
+            result?;
+        }
+        file.close().await?;
+
+        Ok(rows)
+    }
+}
+
+/// the rows of `batch` in `format`, after the CSV header with `with_header`
+fn encode(
+    format: TextFormat,
+    batch: &RecordBatch,
+    with_header: bool,
+) -> Result<Vec<u8>, ArrowError> {
+    let mut bytes = Vec::new();
+    match format {
+        TextFormat::Csv => WriterBuilder::new()
+            .with_header(with_header)
+            .build(&mut bytes)
+            .write(batch)?,
+        TextFormat::NdJson => {
+            let mut writer = LineDelimitedWriter::new(&mut bytes);
+            writer.write(batch)?;
+            writer.finish()?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// the values of the CSV row starting with `row`, which may go on over the next lines within a
+/// quoted field
+async fn csv_record<F: Read>(
+    lines: &mut Lines<F>,
+    row: Vec<u8>,
+    header: &[usize],
+    descs: &[ValueDesc],
+) -> Result<Result<Vec<Value>, String>, fusio::Error> {
+    let Ok(mut row) = String::from_utf8(row) else {
+        return Ok(Err("not valid UTF-8".to_string()));
+    };
+    let fields = loop {
+        if let Some(fields) = split_record(&row) {
+            break fields;
+        }
+        match lines.next().await? {
+            Some((_, line)) => {
+                let Ok(line) = String::from_utf8(line) else {
+                    return Ok(Err("not valid UTF-8".to_string()));
+                };
+                row.push('\n');
+                row.push_str(&line);
+            }
+            None => return Ok(Err("unterminated quoted field".to_string())),
+        }
+    };
+    if fields.len() != header.len() {
+        return Ok(Err(format!(
+            "expected {} fields, found {}",
+            header.len(),
+            fields.len()
+        )));
+    }
+
+    let mut row = vec![None; descs.len()];
+    for (&column, field) in header.iter().zip(fields.iter()) {
+        row[column] = field.as_deref();
+    }
+    Ok(descs
+        .iter()
+        .zip(row)
+        .map(|(desc, field)| match field {
+            Some(field) => value(desc, Field::Text(field)),
+            None => value(desc, Field::Null),
+        })
+        .collect())
+}
+
+/// the values of the JSON object `row`
+fn json_record(row: &[u8], descs: &[ValueDesc]) -> Result<Vec<Value>, String> {
+    let row = serde_json::from_slice::<serde_json::Value>(row).map_err(|err| err.to_string())?;
+    let serde_json::Value::Object(object) = row else {
+        return Err("not a JSON object".to_string());
+    };
+    json_object(&object, descs)
+}
+
+/// the values of the columns `descs` of the JSON object `object`, the missing columns are null
+pub(crate) fn json_object(
+    object: &serde_json::Map<String, serde_json::Value>,
+    descs: &[ValueDesc],
+) -> Result<Vec<Value>, String> {
+    if let Some(name) = object
+        .keys()
+        .find(|name| !descs.iter().any(|desc| &desc.name == *name))
+    {
+        return Err(format!("unknown column {name}"));
+    }
+    descs
+        .iter()
+        .map(|desc| match object.get(&desc.name) {
+            None | Some(serde_json::Value::Null) => value(desc, Field::Null),
+            Some(field) => value(desc, Field::Json(field)),
+        })
+        .collect()
+}
+
+/// a field of an imported row
+enum Field<'a> {
+    Null,
+    Text(&'a str),
+    Json(&'a serde_json::Value),
+}
+
+impl Field<'_> {
+    fn text(&self) -> Option<&str> {
+        match self {
+            Field::Text(text) => Some(text),
+            Field::Json(serde_json::Value::String(text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn integer<T>(&self) -> Option<T>
+    where
+        T: FromStr + TryFrom<i64> + TryFrom<u64>,
+    {
+        match self {
+            Field::Json(serde_json::Value::Number(number)) => number
+                .as_i64()
+                .and_then(|number| T::try_from(number).ok())
+                .or_else(|| number.as_u64().and_then(|number| T::try_from(number).ok())),
+            _ => self.text()?.trim().parse().ok(),
+        }
+    }
+
+    fn float(&self) -> Option<f64> {
+        match self {
+            Field::Json(serde_json::Value::Number(number)) => number.as_f64(),
+            _ => self.text()?.trim().parse().ok(),
+        }
+    }
+
+    fn boolean(&self) -> Option<bool> {
+        if let Field::Json(serde_json::Value::Bool(boolean)) = self {
+            return Some(*boolean);
+        }
+        match self.text()?.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None,
+        }
+    }
+
+    fn string(&self) -> Option<String> {
+        match self {
+            Field::Json(serde_json::Value::Number(number)) => Some(number.to_string()),
+            Field::Json(serde_json::Value::Bool(boolean)) => Some(boolean.to_string()),
+            _ => self.text().map(str::to_string),
+        }
+    }
+
+    fn bytes(&self) -> Option<Vec<u8>> {
+        if let Field::Json(serde_json::Value::Array(bytes)) = self {
+            return bytes
+                .iter()
+                .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                .collect();
+        }
+        let hex = self.text()?.trim();
+        if hex.len() % 2 != 0 {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+}
+
+/// the value of the column `desc` read from `field`
+fn value(desc: &ValueDesc, field: Field<'_>) -> Result<Value, String> {
+    if let Field::Null = field {
+        if !desc.is_nullable {
+            return Err(format!("column {} is not nullable", desc.name));
+        }
+        return Ok(Value::with_none_value(
+            desc.datatype,
+            desc.name.clone(),
+            true,
+        ));
+    }
+
+    macro_rules! typed {
+        ($value:expr) => {{
+            let value = $value.ok_or_else(|| {
+                format!("column {} can't be read as {:?}", desc.name, desc.datatype)
+            })?;
+            Cell::from(value)
+        }};
+    }
+    let value = match desc.datatype {
+        DataType::UInt8 => typed!(field.integer::<u8>()),
+        DataType::UInt16 => typed!(field.integer::<u16>()),
+        DataType::UInt32 => typed!(field.integer::<u32>()),
+        DataType::UInt64 => typed!(field.integer::<u64>()),
+        DataType::Int8 => typed!(field.integer::<i8>()),
+        DataType::Int16 => typed!(field.integer::<i16>()),
+        DataType::Int32 => typed!(field.integer::<i32>()),
+        DataType::Int64 => typed!(field.integer::<i64>()),
+        DataType::String => typed!(field.string()),
+        DataType::Boolean => typed!(field.boolean()),
+        DataType::Bytes => typed!(field.bytes()),
+        DataType::Float32 => typed!(field.float().map(|float| F32::from(float as f32))),
+        DataType::Float64 => typed!(field.float().map(F64::from)),
+    };
+    Ok(Value::with_cell(desc.name.clone(), value, desc.is_nullable))
+}
+
+/// read `field` of a JSON document as a value of `desc`, e.g. a key given to a server
+#[cfg(any(feature = "flight", feature = "server"))]
+pub(crate) fn json_value(desc: &ValueDesc, field: &serde_json::Value) -> Result<Value, String> {
+    match field {
+        serde_json::Value::Null => value(desc, Field::Null),
+        field => value(desc, Field::Json(field)),
+    }
+}
+
+/// Split a CSV record into its fields, `None` for the empty fields. Returns `None` while a quoted
+/// field is left open at the end of `record`.
+fn split_record(record: &str) -> Option<Vec<Option<String>>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut is_quoted = false;
+    let mut in_quotes = false;
+    let mut chars = record.chars().peekable();
+    while let Some(char) = chars.next() {
+        match char {
+            '"' if in_quotes => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    in_quotes = false;
+                }
+            }
+            '"' if field.is_empty() && !is_quoted => {
+                is_quoted = true;
+                in_quotes = true;
+            }
+            ',' if !in_quotes => {
+                let field = std::mem::take(&mut field);
+                fields.push((is_quoted || !field.is_empty()).then_some(field));
+                is_quoted = false;
+            }
+            char => field.push(char),
+        }
+    }
+    if in_quotes {
+        return None;
+    }
+    fields.push((is_quoted || !field.is_empty()).then_some(field));
+    Some(fields)
+}
+
+/// the lines of a file, read by chunks
+struct Lines<F> {
+    file: F,
+    size: u64,
+    pos: u64,
+    buf: Vec<u8>,
+    start: usize,
+    line: usize,
+}
+
+impl<F: Read> Lines<F> {
+    async fn new(mut file: F) -> Result<Self, fusio::Error> {
+        let size = file.size().await?;
+        Ok(Self {
+            file,
+            size,
+            pos: 0,
+            buf: Vec::new(),
+            start: 0,
+            line: 0,
+        })
+    }
+
+    /// the next line without its line break and its number, from 1
+    async fn next(&mut self) -> Result<Option<(usize, Vec<u8>)>, fusio::Error> {
+        loop {
+            if let Some(end) = self.buf[self.start..]
+                .iter()
+                .position(|&byte| byte == b'\n')
+            {
+                let mut line = self.buf[self.start..self.start + end].to_vec();
+                self.start += end + 1;
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                self.line += 1;
+                return Ok(Some((self.line, line)));
+            }
+            if self.pos == self.size {
+                if self.start == self.buf.len() {
+                    return Ok(None);
+                }
+                let line = self.buf[self.start..].to_vec();
+                self.start = self.buf.len();
+                self.line += 1;
+                return Ok(Some((self.line, line)));
+            }
+
+            self.buf.drain(..self.start);
+            self.start = 0;
+            let len = (self.size - self.pos).min(READ_CHUNK_SIZE);
+            let (result, chunk) = self
+                .file
+                .read_exact_at(vec![0; len as usize], self.pos)
+                .await;
+            result?;
+            self.buf.extend_from_slice(&chunk);
+            self.pos += len;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::{split_record, BadRow, ImportOptions, TextFormat};
+    use crate::{
+        cast_arc_value, dyn_schema,
+        executor::tokio::TokioExecutor,
+        record::{DataType, DynRecord, DynSchema, Value},
+        DbError, DbOption, DB,
+    };
+
+    fn schema() -> DynSchema {
+        dyn_schema!(
+            ("id", Int64, false),
+            ("name", String, true),
+            ("data", Bytes, true),
+            0
+        )
+    }
+
+    async fn open(dir: &TempDir) -> DB<DynRecord, TokioExecutor> {
+        let option = DbOption::new(Path::from_filesystem_path(dir.path()).unwrap(), &schema());
+        DB::new(option, TokioExecutor::current(), schema())
+            .await
+            .unwrap()
+    }
+
+    async fn row(
+        db: &DB<DynRecord, TokioExecutor>,
+        id: i64,
+    ) -> Option<(Option<String>, Option<Vec<u8>>)> {
+        let key = Value::new(DataType::Int64, "id".to_string(), Arc::new(id), false);
+        db.get(&key, |entry| {
+            let columns = entry.get().columns;
+            (
+                cast_arc_value!(columns[1].value, Option<String>).clone(),
+                cast_arc_value!(columns[2].value, Option<Vec<u8>>).clone(),
+            )
+        })
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn split_csv_records() {
+        assert_eq!(
+            split_record(r#"1,"a, ""b""",,"""#).unwrap(),
+            vec![
+                Some("1".to_string()),
+                Some(r#"a, "b""#.to_string()),
+                None,
+                Some(String::new())
+            ]
+        );
+        assert!(split_record("1,\"open").is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn import_export_text() {
+        let temp_dir = TempDir::new().unwrap();
+        let files_dir = TempDir::new().unwrap();
+        let csv = files_dir.path().join("rows.csv");
+        std::fs::write(
+            &csv,
+            "name,id,data\n\"Smith, J\",1,0a0b\n,2,\nbad,x,\n\"multi\nline\",3,ff\n",
+        )
+        .unwrap();
+        let db = open(&temp_dir).await;
+
+        let csv_path = Path::from_filesystem_path(&csv).unwrap();
+        assert!(matches!(
+            db.import_text(&csv_path, TextFormat::Csv, ImportOptions::default())
+                .await,
+            Err(DbError::InvalidImport(_))
+        ));
+        let report = db
+            .import_text(
+                &csv_path,
+                TextFormat::Csv,
+                ImportOptions::default().max_bad_rows(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.rows, 3);
+        assert_eq!(
+            report.bad_rows,
+            vec![BadRow {
+                line: 4,
+                error: "column id can't be read as Int64".to_string()
+            }]
+        );
+
+        let expected = [
+            (1, Some("Smith, J".to_string()), Some(vec![10, 11])),
+            (2, None, None),
+            (3, Some("multi\nline".to_string()), Some(vec![255])),
+        ];
+        for (id, name, data) in expected.clone() {
+            assert_eq!(row(&db, id).await, Some((name, data)));
+        }
+
+        // the exported rows are imported back as they were
+        for format in [TextFormat::Csv, TextFormat::NdJson] {
+            let exported = Path::from_filesystem_path(files_dir.path().join("export")).unwrap();
+            assert_eq!(db.export_text(&exported, format).await.unwrap(), 3);
+
+            let import_dir = TempDir::new().unwrap();
+            let imported = open(&import_dir).await;
+            let report = imported
+                .import_text(&exported, format, ImportOptions::default())
+                .await
+                .unwrap();
+            assert_eq!(report.rows, 3);
+            for (id, name, data) in expected.clone() {
+                assert_eq!(row(&imported, id).await, Some((name, data)));
+            }
+        }
+    }
+}
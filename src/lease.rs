@@ -0,0 +1,489 @@
+//! The lease guarding the path of a [`DB`](crate::DB) against a second writer, see
+//! [`DbOption::lease`](crate::DbOption::lease).
+
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fmt::Debug,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path as StdPath, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use fusio::path::{path_to_local, Path};
+use futures_core::future::BoxFuture;
+
+use crate::fs::{generate_file_id, FileId};
+
+const LEASE_HEADER: &str = "tonbo-lease v1";
+
+/// Keeps the leases of [`DbOption::lease`](crate::DbOption::lease), by default a lease file next
+/// to the [`DB`](crate::DB), see [`DbOption::lease_store`](crate::DbOption::lease_store).
+///
+/// `take` is a compare-and-swap: of two holders taking a free lease at once exactly one gets it,
+/// e.g. a conditional write of a DynamoDB item or an etcd transaction comparing the revision of
+/// the key. The lease files on the local disk are created exclusively, a [`DB`](crate::DB) on an
+/// object store needs a lease store.
+///
+/// Every take returns a fencing epoch, written with the edits of the version log: the edits of a
+/// holder which lost its lease are left out on recovery once the new holder wrote its epoch.
+pub trait LeaseStore: Debug + Send + Sync {
+    /// hold the lease of `path` for `holder` until `expires_at`, in milliseconds since the unix
+    /// epoch, unless another holder holds it until later than now. Returns the fencing epoch of
+    /// the lease if `holder` holds it, larger than the epochs of the takes before, e.g. the
+    /// version of the item or the revision of the key.
+    fn take<'a>(
+        &'a self,
+        path: &'a Path,
+        holder: FileId,
+        expires_at: u64,
+    ) -> BoxFuture<'a, Result<Option<u64>, Box<dyn Error + Send + Sync>>>;
+
+    /// drop the lease of `path` if `holder` still holds it
+    fn release<'a>(
+        &'a self,
+        path: &'a Path,
+        holder: FileId,
+    ) -> BoxFuture<'a, Result<(), Box<dyn Error + Send + Sync>>>;
+}
+
+/// The lease files at the path of a [`LeaseStore::take`] on the local disk, naming the holder and
+/// until when. Every take writes the next generation of the lease, `{path}.{generation}`, which
+/// is created at once with its content and fails if it exists: of two holders taking the same
+/// generation exactly one creates it. A release writes an expired generation, so generations
+/// are never taken twice, and the generation is the fencing epoch.
+#[derive(Debug, Default)]
+pub(crate) struct FileLeaseStore;
+
+/// the holder of a [`DB`](crate::DB) path and its lease store
+#[derive(Clone)]
+pub(crate) struct LeaseFile {
+    store: Arc<dyn LeaseStore>,
+    path: Path,
+    token: FileId,
+}
+
+/// The ownership of a [`DB`](crate::DB) path by the writer holding it, see
+/// [`DbOption::lease`](crate::DbOption::lease).
+pub(crate) struct Lease {
+    file: LeaseFile,
+    duration: Duration,
+    /// milliseconds since the unix epoch the lease is held until, 0 once it is lost
+    expires_at: AtomicU64,
+    /// the fencing epoch of the last take, see [`LeaseStore::take`]
+    epoch: AtomicU64,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// the outcome of taking a lease file
+pub(crate) enum Acquired<T> {
+    Held(T),
+    /// the lease is held by another process
+    Owned,
+}
+
+impl FileLeaseStore {
+    /// the generations of the lease at `path` and their files, oldest first
+    fn generations(path: &StdPath) -> io::Result<Vec<(u64, PathBuf)>> {
+        let mut generations = Vec::new();
+        let (Some(dir), Some(name)) = (path.parent(), path.file_name().and_then(OsStr::to_str))
+        else {
+            return Ok(generations);
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(generations),
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if let Some(generation) = entry
+                .file_name()
+                .to_str()
+                .and_then(|file_name| file_name.strip_prefix(name)?.strip_prefix('.'))
+                .and_then(|generation| generation.parse::<u64>().ok())
+            {
+                generations.push((generation, entry.path()));
+            }
+        }
+        generations.sort();
+        Ok(generations)
+    }
+
+    /// the newest generation of the lease at `path` with its token and expiry, `None` if there
+    /// is none
+    #[allow(clippy::type_complexity)]
+    fn newest(path: &StdPath) -> io::Result<Option<(u64, Option<(FileId, u64)>)>> {
+        loop {
+            let Some((generation, file)) = Self::generations(path)?.pop() else {
+                return Ok(None);
+            };
+            let content = match fs::read_to_string(&file) {
+                Ok(content) => content,
+                // removed once a newer generation is written, which is read next
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            let mut lines = content.lines();
+            if lines.next() != Some(LEASE_HEADER) {
+                return Ok(Some((generation, None)));
+            }
+            let token = lines.next().and_then(|token| token.parse::<FileId>().ok());
+            let expires_at = lines.next().and_then(|expires_at| expires_at.parse().ok());
+            return Ok(Some((generation, token.zip(expires_at))));
+        }
+    }
+
+    /// write the generation `generation` of the lease at `path`, `false` if another holder
+    /// wrote it first. The older generations are removed.
+    fn create(path: &StdPath, generation: u64, token: FileId, expires_at: u64) -> io::Result<bool> {
+        let file_name = path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "invalid lease path"))?;
+        // linked whole to the name of its generation, which is never seen half written
+        let staged = path.with_file_name(format!("{file_name}.{token}.staged"));
+        fs::write(&staged, format!("{LEASE_HEADER}\n{token}\n{expires_at}\n"))?;
+        let linked = fs::hard_link(
+            &staged,
+            path.with_file_name(format!("{file_name}.{generation}")),
+        );
+        let _ = fs::remove_file(&staged);
+        match linked {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => return Ok(false),
+            Err(err) => return Err(err),
+        }
+        for (older, file) in Self::generations(path)? {
+            if older < generation {
+                let _ = fs::remove_file(file);
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl LeaseStore for FileLeaseStore {
+    /// write the next generation of the lease if it is not held by another process
+    fn take<'a>(
+        &'a self,
+        path: &'a Path,
+        holder: FileId,
+        expires_at: u64,
+    ) -> BoxFuture<'a, Result<Option<u64>, Box<dyn Error + Send + Sync>>> {
+        Box::pin(async move {
+            let path = path_to_local(path)?;
+            let generation = match Self::newest(&path)? {
+                Some((_, Some((token, held_until))))
+                    if token != holder && held_until > now_millis() =>
+                {
+                    return Ok(None);
+                }
+                Some((generation, _)) => generation + 1,
+                None => 0,
+            };
+            Ok(Self::create(&path, generation, holder, expires_at)?.then_some(generation))
+        })
+    }
+
+    fn release<'a>(
+        &'a self,
+        path: &'a Path,
+        holder: FileId,
+    ) -> BoxFuture<'a, Result<(), Box<dyn Error + Send + Sync>>> {
+        Box::pin(async move {
+            let path = path_to_local(path)?;
+            if let Some((generation, Some((token, _)))) = Self::newest(&path)? {
+                if token == holder {
+                    Self::create(&path, generation + 1, holder, 0)?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+impl LeaseFile {
+    /// take the lease until `expires_at`, held with its fencing epoch
+    async fn take(&self, expires_at: u64) -> Result<Acquired<u64>, Box<dyn Error + Send + Sync>> {
+        Ok(
+            match self.store.take(&self.path, self.token, expires_at).await? {
+                Some(epoch) => Acquired::Held(epoch),
+                None => Acquired::Owned,
+            },
+        )
+    }
+
+    /// drop the lease if this process still holds it
+    pub(crate) async fn release(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.store.release(&self.path, self.token).await
+    }
+}
+
+impl Lease {
+    /// take the lease at `path` of `store` for `duration`
+    pub(crate) async fn acquire(
+        store: Arc<dyn LeaseStore>,
+        path: Path,
+        duration: Duration,
+    ) -> Result<Acquired<Self>, Box<dyn Error + Send + Sync>> {
+        let file = LeaseFile {
+            store,
+            path,
+            token: generate_file_id(),
+        };
+        let expires_at = now_millis() + duration.as_millis() as u64;
+        Ok(match file.take(expires_at).await? {
+            Acquired::Held(epoch) => Acquired::Held(Lease {
+                file,
+                duration,
+                expires_at: AtomicU64::new(expires_at),
+                epoch: AtomicU64::new(epoch),
+            }),
+            Acquired::Owned => Acquired::Owned,
+        })
+    }
+
+    /// extend the lease by its duration from now, a lease taken over by another process after it
+    /// expired is lost for good
+    pub(crate) async fn renew(&self) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        if self.expires_at.load(Ordering::Acquire) == 0 {
+            return Ok(false);
+        }
+        let expires_at = now_millis() + self.duration.as_millis() as u64;
+        match self.file.take(expires_at).await? {
+            Acquired::Held(epoch) => {
+                self.epoch.store(epoch, Ordering::Release);
+                self.expires_at.store(expires_at, Ordering::Release);
+                Ok(true)
+            }
+            Acquired::Owned => {
+                self.expires_at.store(0, Ordering::Release);
+                Ok(false)
+            }
+        }
+    }
+
+    /// the lease is held for now, so the path may be written
+    pub(crate) fn is_held(&self) -> bool {
+        now_millis() < self.expires_at.load(Ordering::Acquire)
+    }
+
+    /// the fencing epoch the edits of the version log are written with
+    pub(crate) fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// the lease is renewed at a third of its duration, so two renewals may fail before it expires
+    pub(crate) fn renew_interval(&self) -> Duration {
+        self.duration / 3
+    }
+
+    pub(crate) fn file(&self) -> LeaseFile {
+        self.file.clone()
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{
+        collections::HashMap,
+        error::Error,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use fusio::path::Path;
+    use futures_core::future::BoxFuture;
+    use tempfile::TempDir;
+
+    use super::{now_millis, FileLeaseStore, LeaseStore};
+    use crate::{
+        executor::tokio::TokioExecutor, fs::FileId, inmem::immutable::tests::TestSchema,
+        tests::Test, transaction::CommitError, DbError, DbOption, DB,
+    };
+
+    /// a compare-and-swap store of the leases in memory, the epochs count the takes
+    #[derive(Debug, Default)]
+    struct MemoryStore(Mutex<HashMap<Path, (FileId, u64, u64)>>);
+
+    impl LeaseStore for MemoryStore {
+        fn take<'a>(
+            &'a self,
+            path: &'a Path,
+            holder: FileId,
+            expires_at: u64,
+        ) -> BoxFuture<'a, Result<Option<u64>, Box<dyn Error + Send + Sync>>> {
+            let mut leases = self.0.lock().unwrap();
+            let epoch = match leases.get(path) {
+                Some(&(token, held_until, epoch)) => {
+                    (token == holder || held_until <= now_millis()).then_some(epoch + 1)
+                }
+                None => Some(0),
+            };
+            if let Some(epoch) = epoch {
+                leases.insert(path.clone(), (holder, expires_at, epoch));
+            }
+            Box::pin(async move { Ok(epoch) })
+        }
+
+        fn release<'a>(
+            &'a self,
+            path: &'a Path,
+            holder: FileId,
+        ) -> BoxFuture<'a, Result<(), Box<dyn Error + Send + Sync>>> {
+            let mut leases = self.0.lock().unwrap();
+            if let Some(lease) = leases.get_mut(path).filter(|lease| lease.0 == holder) {
+                // expired rather than removed, so the epochs keep growing
+                lease.1 = 0;
+            }
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn lease() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = || {
+            DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            )
+            .lease(Duration::from_millis(300))
+        };
+        let open =
+            || DB::<Test, TokioExecutor>::new(option(), TokioExecutor::current(), TestSchema);
+
+        let db = open().await.unwrap();
+        assert!(matches!(open().await, Err(DbError::AlreadyOwned(_))));
+        // the lease is renewed while the DB is open
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(matches!(open().await, Err(DbError::AlreadyOwned(_))));
+        db.insert(Test {
+            vstring: "key".to_string(),
+            vu32: 0,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        let reader: DB<Test, TokioExecutor> =
+            DB::open_read_only(option(), TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+        drop(reader);
+
+        // the lease is released once the DB is dropped
+        drop(db);
+        let mut attempts = 0;
+        while let Err(err) = open().await {
+            assert!(matches!(err, DbError::AlreadyOwned(_)));
+            attempts += 1;
+            assert!(attempts < 100);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn file_lease_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = Path::from_filesystem_path(temp_dir.path())
+            .unwrap()
+            .child("LEASE");
+        let store = Arc::new(FileLeaseStore);
+        let expires_at = now_millis() + 60_000;
+
+        // of the holders taking the free lease at once exactly one gets it
+        let takes = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                let path = path.clone();
+                tokio::spawn(async move {
+                    let holder = FileId::new();
+                    store
+                        .take(&path, holder, expires_at)
+                        .await
+                        .unwrap()
+                        .map(|epoch| (holder, epoch))
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut holders = Vec::new();
+        for take in takes {
+            holders.extend(take.await.unwrap());
+        }
+        assert_eq!(holders.len(), 1);
+        let (holder, epoch) = holders[0];
+        assert_eq!(epoch, 0);
+        assert_eq!(
+            store.take(&path, holder, expires_at).await.unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            store.take(&path, FileId::new(), expires_at).await.unwrap(),
+            None
+        );
+
+        // a released lease is taken by the next holder with a larger epoch, the older
+        // generations are removed
+        store.release(&path, holder).await.unwrap();
+        assert_eq!(
+            store.take(&path, FileId::new(), expires_at).await.unwrap(),
+            Some(3)
+        );
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn lease_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = Arc::new(MemoryStore::default());
+        let option = || {
+            DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            )
+            .lease(Duration::from_millis(300))
+            .lease_store(store.clone())
+        };
+        let open =
+            || DB::<Test, TokioExecutor>::new(option(), TokioExecutor::current(), TestSchema);
+
+        let db = open().await.unwrap();
+        assert!(matches!(open().await, Err(DbError::AlreadyOwned(_))));
+        // no lease file is written besides the store
+        assert!(!temp_dir.path().join("LEASE").exists());
+
+        // another process takes the lease over
+        let path = option().lease_path();
+        store
+            .0
+            .lock()
+            .unwrap()
+            .insert(path, (FileId::new(), u64::MAX, u64::MAX));
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        let err = db
+            .insert(Test {
+                vstring: "key".to_string(),
+                vu32: 0,
+                vbool: None,
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CommitError::Database(DbError::AlreadyOwned(_))
+        ));
+        assert!(db.flush().await.is_err());
+    }
+}
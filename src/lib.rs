@@ -106,72 +106,140 @@
 //!     }
 //! }
 //! ```
+#[cfg(all(feature = "admin", unix))]
+pub mod admin;
+pub mod aggregate;
+pub mod archive;
+pub mod backup;
+pub mod bulk;
+mod cache;
+pub mod catalog;
+pub mod changelog;
+mod checkpoint;
+pub mod commit_hook;
 mod compaction;
 mod context;
+pub mod describe;
+pub mod encryption;
+pub mod event;
 pub mod executor;
+pub mod export;
+#[cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+pub mod ffi;
+#[cfg(feature = "flight")]
+pub mod flight;
+mod follower;
 pub mod fs;
+pub mod gc;
 pub mod inmem;
+pub mod inspect;
+pub mod interchange;
+pub mod lease;
 pub mod magic;
+pub mod memory;
+pub mod merge;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod offload;
 mod ondisk;
 pub mod option;
 pub mod record;
+pub mod repair;
+mod restore;
+pub mod retention;
 mod scope;
+#[cfg(feature = "server")]
+pub mod server;
 pub mod snapshot;
+pub mod sql;
 pub mod stream;
+#[cfg(feature = "tokio")]
+pub mod sync;
+pub mod tables;
 pub mod timestamp;
 pub mod transaction;
 mod trigger;
+pub mod ttl;
+mod update;
+pub mod verify;
 mod version;
 mod wal;
-
-use std::{collections::HashMap, io, marker::PhantomData, mem, ops::Bound, pin::pin, sync::Arc};
+pub mod write_batch;
+
+use std::{
+    collections::HashMap,
+    io, mem,
+    ops::Bound,
+    pin::pin,
+    sync::{Arc, Weak},
+    time::Instant,
+};
 
 pub use arrow;
-use async_lock::RwLock;
+use arrow::array::RecordBatch;
+use async_lock::{Mutex, RwLock};
 use async_stream::stream;
-use compaction::leveled::LeveledCompactor;
+use changelog::{Change, ChangeFeed};
+use commit_hook::CommitHooks;
+use compaction::{fifo::FifoCompactor, leveled::LeveledCompactor, size_ratio::SizeRatioCompactor};
 use context::Context;
 use flume::{bounded, Sender};
 use fs::FileId;
+use fusio::path::Path;
 pub use fusio::{SeqRead, Write};
 pub use fusio_log::{Decode, Encode};
-use futures_core::Stream;
+use futures_channel::oneshot;
+use futures_core::{future::BoxFuture, Stream};
 use futures_util::StreamExt;
 use inmem::{immutable::Immutable, mutable::MutableMemTable};
-use lockable::LockableHashMap;
+use lease::{Acquired, FileLeaseStore, Lease};
+use lockable::{AsyncLimit, LockableHashMap};
 use magic::USER_COLUMN_OFFSET;
+use memory::MemTableUsage;
 pub use once_cell;
 pub use parquet;
 use parquet::{
     arrow::{ArrowSchemaConverter, ProjectionMask},
     errors::ParquetError,
 };
+pub use parquet_lru;
 use parquet_lru::{DynLruCache, NoCache};
-use record::Record;
+use record::{KeyRef, Record};
 use thiserror::Error;
 use timestamp::{Timestamp, TsRef};
-use tokio::sync::oneshot;
 pub use tonbo_macros::{KeyAttributes, Record};
-use tracing::error;
+use tracing::{error, info_span, warn, Instrument};
 use transaction::{CommitError, Transaction, TransactionEntry};
 use trigger::FreezeTrigger;
 use wal::log::Log;
 
-pub use crate::option::*;
 use crate::{
-    compaction::{CompactTask, CompactionError, Compactor},
-    executor::Executor,
+    cache::{block::BlockCache, disk::DiskCache, metadata::MetadataCache, table::TableCache},
+    compaction::{
+        rate_limit::{self, RateLimiter},
+        CompactTask, CompactionError, Compactor,
+    },
+    executor::{pool::Priority, task, Executor},
     fs::{manager::StoreManager, parse_file_id, FileType},
+    merge::MergeOperator,
+    offload::CompactionRunner,
+    ondisk::deadline,
     record::Schema,
+    retention::CompactionFilter,
     snapshot::Snapshot,
     stream::{
-        mem_projection::MemProjectionStream, merge::MergeStream, package::PackageStream, Entry,
-        ScanStream,
+        batch::BatchStream, delta::DeltaMerger, mem_projection::MemProjectionStream,
+        merge::MergeStream, package::PackageStream, Entry, ScanExpr, ScanStream,
     },
     trigger::TriggerFactory,
     version::{cleaner::Cleaner, set::VersionSet, TransactionTs, Version, VersionError},
     wal::{log::LogType, RecoverError, WalFile},
 };
+pub use crate::{
+    cache::{block::BlockCacheStats, metadata::MetadataCacheStats, table::TableCacheStats},
+    ondisk::sstable::SsTable,
+    option::*,
+};
 
 pub struct DB<R, E>
 where
@@ -181,7 +249,12 @@ where
     schema: Arc<RwLock<DbStorage<R>>>,
     ctx: Arc<Context<R>>,
     lock_map: LockMap<<R::Schema as Schema>::Key>,
-    _p: PhantomData<E>,
+    /// serializes the validation and writes of [`IsolationLevel::Serializable`] commits
+    serializable_lock: Arc<Mutex<()>>,
+    /// the lease of [`DbOption::lease`], released once the [`DB`] is dropped
+    _lease: Option<Arc<Lease>>,
+    /// spawns the tasks of the [`BufferedWriter`](write_batch::BufferedWriter)s
+    executor: Arc<E>,
 }
 
 impl<R, E> DB<R, E>
@@ -201,6 +274,61 @@ where
             executor,
             schema,
             Arc::new(NoCache::default()),
+            None,
+            None,
+            None,
+        )
+        .await
+    }
+
+    /// Open the [`DB`] at the base path of `option` read-only, e.g. for an analytics sidecar
+    /// reading a live or archived dataset: the SSTables of the latest version in the manifest
+    /// are read, nothing is written to the directories of the [`DB`].
+    ///
+    /// The WAL is not replayed, so the commits not flushed into SSTables yet are not read.
+    /// No compaction runs, and writes, [`DB::flush`] and [`DB::destroy`] fail with
+    /// [`DbError::ReadOnly`].
+    pub async fn open_read_only(
+        option: DbOption,
+        executor: E,
+        schema: R::Schema,
+    ) -> Result<Self, DbError<R>> {
+        let option = DbOption {
+            read_only: true,
+            use_wal: false,
+            ..option
+        };
+        Self::build(
+            Arc::new(option),
+            executor,
+            schema,
+            Arc::new(NoCache::default()),
+            None,
+            None,
+            None,
+        )
+        .await
+    }
+
+    /// Open [`DB`] like [`DB::new`], with the SSTables read through `parquet_lru`. With the
+    /// `foyer` feature, [`FoyerCache`](parquet_lru::foyer::FoyerCache) keeps the metadata of the
+    /// tables in memory and their byte ranges in a hybrid memory and disk cache, so repeated
+    /// gets and scans of hot ranges stop fetching them from the object store again. Its
+    /// [`stats`](parquet_lru::foyer::FoyerCache::stats) report the hit rates.
+    pub async fn with_parquet_lru(
+        option: DbOption,
+        executor: E,
+        schema: R::Schema,
+        parquet_lru: ParquetLru,
+    ) -> Result<Self, DbError<R>> {
+        Self::build(
+            Arc::new(option),
+            executor,
+            schema,
+            parquet_lru,
+            None,
+            None,
+            None,
         )
         .await
     }
@@ -217,13 +345,23 @@ where
         executor: E,
         schema: R::Schema,
         lru_cache: ParquetLru,
+        merge_operator: Option<Arc<dyn MergeOperator<R>>>,
+        compaction_filter: Option<Arc<dyn CompactionFilter<R>>>,
+        compaction_runner: Option<Arc<dyn CompactionRunner<R>>>,
     ) -> Result<Self, DbError<R>> {
         let record_schema = Arc::new(schema);
-        let manager = Arc::new(StoreManager::new(
-            option.base_fs.clone(),
-            option.level_paths.clone(),
-        )?);
-        {
+        let mut manager = StoreManager::new(option.base_fs.clone(), option.level_paths.clone())?;
+        if let Some((_, fs_options)) = &option.wal_path {
+            manager = manager.with_wal_fs(fs_options.clone())?;
+        }
+        let manager = Arc::new(
+            manager.with_table_cache(
+                option
+                    .max_open_tables
+                    .map(|capacity| Arc::new(TableCache::new(capacity))),
+            ),
+        );
+        if !option.read_only {
             manager
                 .local_fs()
                 .create_dir_all(&option.wal_dir_path())
@@ -235,7 +373,7 @@ where
                 .await
                 .map_err(DbError::Fusio)?;
             manager
-                .base_fs()
+                .wal_fs()
                 .create_dir_all(&option.wal_dir_path())
                 .await
                 .map_err(DbError::Fusio)?;
@@ -244,28 +382,154 @@ where
                 .create_dir_all(&option.version_log_dir_path())
                 .await
                 .map_err(DbError::Fusio)?;
+            for (level_path, _) in option.level_paths.iter().flatten() {
+                manager
+                    .get_fs(level_path)
+                    .create_dir_all(level_path)
+                    .await
+                    .map_err(DbError::Fusio)?;
+            }
         }
+        let lease = match option.lease {
+            // the lease would expire without its renewals
+            #[cfg(target_arch = "wasm32")]
+            Some(_) if !option.read_only => {
+                return Err(DbError::Lease("leases are not renewed on wasm32".into()));
+            }
+            Some(duration) if !option.read_only => {
+                let store: Arc<dyn lease::LeaseStore> = match &option.lease_store {
+                    Some(store) => store.clone(),
+                    // the lease files are only created exclusively on the local disk
+                    None if manager.base_fs().file_system() == manager.local_fs().file_system() => {
+                        Arc::new(FileLeaseStore)
+                    }
+                    None => {
+                        return Err(DbError::Lease(
+                            "a lease on an object store needs a lease store".into(),
+                        ));
+                    }
+                };
+                match Lease::acquire(store, option.lease_path(), duration)
+                    .await
+                    .map_err(DbError::Lease)?
+                {
+                    Acquired::Held(lease) => Some(Arc::new(lease)),
+                    Acquired::Owned => return Err(DbError::AlreadyOwned(option.lease_path())),
+                }
+            }
+            _ => None,
+        };
         let (task_tx, task_rx) = bounded(1);
 
-        let (mut cleaner, clean_sender) = Cleaner::new(option.clone(), manager.clone());
+        let background_rate_limiter = option
+            .background_rate_limit
+            .map(|limit| Arc::new(RateLimiter::with_limit(limit)));
+        let (cleaner, clean_sender) = Cleaner::new(option.clone(), manager.clone());
+        let mut cleaner = cleaner.with_rate_limiter(background_rate_limiter.clone());
 
-        let version_set = VersionSet::new(clean_sender, option.clone(), manager.clone()).await?;
-        let schema = Arc::new(RwLock::new(
-            DbStorage::new(
-                option.clone(),
-                task_tx,
-                &version_set,
-                record_schema.clone(),
-                &manager,
-            )
-            .await?,
-        ));
-        let ctx = Arc::new(Context::new(
+        let version_set = VersionSet::new(clean_sender, option.clone(), manager.clone())
+            .await?
+            .with_lease(lease.as_ref().map(Arc::downgrade));
+        if lease.is_some() {
+            // the epoch of the lease is written at once, the edits the previous holder writes
+            // after it are left out on recovery
+            version_set.apply_edits(Vec::new(), None, false).await?;
+        }
+        let mut storage = DbStorage::new(
+            option.clone(),
+            task_tx,
+            &version_set,
+            record_schema.clone(),
+            &manager,
+        )
+        .await?;
+        storage.lease = lease.as_ref().map(Arc::downgrade);
+        #[cfg(feature = "metrics")]
+        let metrics = storage.metrics.clone();
+        let schema = Arc::new(RwLock::new(storage));
+        let disk_cache = match &option.disk_cache {
+            Some((path, capacity)) => {
+                Some(Arc::new(DiskCache::new(path.clone(), *capacity).await?))
+            }
+            None => None,
+        };
+        let block_cache = option
+            .block_cache
+            .map(|capacity| Arc::new(BlockCache::new(capacity)));
+        // the footers are cached once decrypted
+        let metadata_cache = option
+            .metadata_cache
+            .map(|capacity| Arc::new(MetadataCache::new(capacity)));
+        // the caches above `lru_cache`, which is charged to the rate limits of the requests
+        // reaching the storage
+        let layered = |lru_cache: ParquetLru| -> Result<ParquetLru, DbError<R>> {
+            let lru_cache = match option.retry_policy {
+                Some(retry_policy) => fs::retry::cache(&lru_cache, retry_policy),
+                None => lru_cache,
+            };
+            let lru_cache = match &disk_cache {
+                Some(disk_cache) => cache::disk::cache(&lru_cache, disk_cache.clone()),
+                None => lru_cache,
+            };
+            let lru_cache = match &block_cache {
+                Some(block_cache) => cache::block::cache(&lru_cache, block_cache.clone()),
+                None => lru_cache,
+            };
+            let lru_cache = match &option.encryption {
+                Some(key_provider) => encryption::cache(&lru_cache, key_provider.clone())?,
+                None => lru_cache,
+            };
+            Ok(match &metadata_cache {
+                Some(metadata_cache) => cache::metadata::cache(&lru_cache, metadata_cache.clone()),
+                None => lru_cache,
+            })
+        };
+        let compaction_rate_limiter = option
+            .compaction_rate_limit
+            .map(|bytes_per_sec| Arc::new(RateLimiter::new(bytes_per_sec)));
+        let scan_cache = match option.scan_rate_limit {
+            Some(limit) => layered(rate_limit::cache(
+                &lru_cache,
+                Arc::new(RateLimiter::with_limit(limit)),
+            ))?,
+            None => layered(lru_cache.clone())?,
+        };
+        // the background jobs are charged apart from the reads of the application, the hits of
+        // the caches are not charged either
+        let background_cache = match (&background_rate_limiter, &compaction_rate_limiter) {
+            (None, None) if option.scan_rate_limit.is_none() => scan_cache.clone(),
+            (background_rate_limiter, compaction_rate_limiter) => {
+                let mut lru_cache = lru_cache;
+                for rate_limiter in [background_rate_limiter, compaction_rate_limiter]
+                    .into_iter()
+                    .flatten()
+                {
+                    lru_cache = rate_limit::cache(&lru_cache, rate_limiter.clone());
+                }
+                layered(lru_cache)?
+            }
+        };
+        let lru_cache = scan_cache;
+        #[allow(unused_mut)]
+        let mut ctx = Context::new(
             manager,
             lru_cache.clone(),
             version_set,
             record_schema.arrow_schema().clone(),
-        ));
+        )
+        .with_background_rate_limiter(background_rate_limiter, background_cache)
+        .with_merge_operator(merge_operator)
+        .with_compaction_filter(compaction_filter)
+        .with_block_cache(block_cache)
+        .with_metadata_cache(metadata_cache)
+        .with_task_pool(option.task_pool.clone())
+        .with_compaction_rate_limiter(compaction_rate_limiter)
+        .with_compaction_runner(compaction_runner);
+        #[cfg(feature = "metrics")]
+        {
+            ctx.metrics = metrics;
+        }
+        let ctx = Arc::new(ctx);
         let mut compactor = match option.compaction_option {
             CompactionOption::Leveled => Compactor::Leveled(LeveledCompactor::<R>::new(
                 schema.clone(),
@@ -273,38 +537,148 @@ where
                 option.clone(),
                 ctx.clone(),
             )),
+            CompactionOption::SizeRatio(targets) => {
+                Compactor::SizeRatio(SizeRatioCompactor::<R>::new(
+                    schema.clone(),
+                    record_schema,
+                    option.clone(),
+                    targets,
+                    ctx.clone(),
+                ))
+            }
+            CompactionOption::Fifo(thresholds) => Compactor::Fifo(FifoCompactor::<R>::new(
+                schema.clone(),
+                record_schema,
+                option.clone(),
+                thresholds,
+                ctx.clone(),
+            )),
         };
 
-        executor.spawn(async move {
-            if let Err(err) = cleaner.listen().await {
-                error!("[Cleaner Error]: {}", err)
+        executor.spawn_named(
+            task::CLEANER,
+            async move {
+                if let Err(err) = cleaner.listen().await {
+                    error!("[Cleaner Error]: {}", err)
+                }
             }
-        });
+            .instrument(info_span!("tonbo_task", name = task::CLEANER)),
+        );
 
-        executor.spawn(async move {
-            while let Ok(task) = task_rx.recv_async().await {
-                if let Err(err) = match task {
-                    CompactTask::Freeze => compactor.check_then_compaction(false).await,
-                    CompactTask::Flush(option_tx) => {
-                        let mut result = compactor.check_then_compaction(true).await;
-                        if let Some(tx) = option_tx {
-                            if result.is_ok() {
-                                result = tx.send(()).map_err(|_| CompactionError::ChannelClose);
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(lease) = &lease {
+            let file = lease.file();
+            let renew_interval = lease.renew_interval();
+            let lease = Arc::downgrade(lease);
+            executor.spawn_named(
+                task::LEASE,
+                async move {
+                    loop {
+                        executor::sleep(renew_interval).await;
+                        let Some(lease) = lease.upgrade() else {
+                            break;
+                        };
+                        match lease.renew().await {
+                            Ok(true) => {}
+                            Ok(false) => {
+                                error!("[Lease Error]: the lease is taken over by another process");
+                                break;
                             }
+                            Err(err) => error!("[Lease Error]: {}", err),
                         }
-                        result
                     }
-                } {
-                    error!("[Compaction Error]: {}", err)
+                    // the DB is dropped, or the lease is lost and not released
+                    if let Err(err) = file.release().await {
+                        error!("[Lease Error]: {}", err)
+                    }
                 }
-            }
-        });
+                .instrument(info_span!("tonbo_task", name = task::LEASE)),
+            );
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let (Some(interval), false) = (option.gc_interval, option.read_only) {
+            let schema = Arc::downgrade(&schema);
+            let ctx = Arc::downgrade(&ctx);
+            executor.spawn_named(
+                task::GC,
+                async move {
+                    loop {
+                        executor::sleep(interval).await;
+                        let (Some(schema), Some(ctx)) = (schema.upgrade(), ctx.upgrade()) else {
+                            break;
+                        };
+                        let _permit = ctx.background_permit(Priority::Gc).await;
+                        if let Err(err) = gc::collect_garbage(&schema, &ctx).await {
+                            error!("[GC Error]: {}", err)
+                        }
+                    }
+                }
+                .instrument(info_span!("tonbo_task", name = task::GC)),
+            );
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let (Some(max_age), false) = (option.flush_policy.max_age, option.read_only) {
+            let schema = Arc::downgrade(&schema);
+            executor.spawn_named(
+                task::FLUSH,
+                async move {
+                    let mut wait = max_age;
+                    loop {
+                        executor::sleep(wait).await;
+                        let Some(schema) = schema.upgrade() else {
+                            break;
+                        };
+                        let schema = schema.read().await;
+                        wait = match schema.mutable.age() {
+                            Some(age) if age >= max_age => {
+                                let _ = schema.compaction_tx.try_send(CompactTask::Freeze);
+                                max_age
+                            }
+                            Some(age) => max_age - age,
+                            None => max_age,
+                        };
+                    }
+                }
+                .instrument(info_span!("tonbo_task", name = task::FLUSH)),
+            );
+        }
+
+        // the memtables of a read-only DB stay empty, there is nothing to flush or compact
+        if !option.read_only {
+            executor.spawn_named(
+                task::COMPACTION,
+                async move {
+                    while let Ok(task) = task_rx.recv_async().await {
+                        if let Err(err) = match task {
+                            CompactTask::Freeze => compactor.check_then_compaction(false).await,
+                            CompactTask::Flush(option_tx) => {
+                                let mut result = compactor.check_then_compaction(true).await;
+                                if let Some(tx) = option_tx {
+                                    if result.is_ok() {
+                                        result =
+                                            tx.send(()).map_err(|_| CompactionError::ChannelClose);
+                                    }
+                                }
+                                result
+                            }
+                        } {
+                            error!("[Compaction Error]: {}", err)
+                        }
+                    }
+                }
+                .instrument(info_span!("tonbo_task", name = task::COMPACTION)),
+            );
+        }
 
         Ok(Self {
             schema,
             lock_map: Arc::new(Default::default()),
+            serializable_lock: Arc::new(Mutex::new(())),
+            _lease: lease,
             ctx,
-            _p: Default::default(),
+            executor: Arc::new(executor),
         })
     }
 
@@ -336,7 +710,60 @@ where
     /// txn.commit().await.unwrap();
     /// ```
     pub async fn transaction(&self) -> Transaction<'_, R> {
-        Transaction::new(self.snapshot().await, self.lock_map.clone())
+        Transaction::new(
+            self.snapshot().await,
+            self.lock_map.clone(),
+            self.serializable_lock.clone(),
+        )
+    }
+
+    /// Run `f` on a new [`Transaction`] and commit it, retrying both with a new transaction
+    /// when the commit fails with [`CommitError::WriteConflict`] or
+    /// [`CommitError::ReadConflict`], as configured by [`DbOption::transaction_retry`].
+    ///
+    /// `f` runs once per attempt, so side effects outside of the transaction should be left to
+    /// the returned value. An error of `f` is returned without committing or retrying, and the
+    /// conflict of the last attempt is returned once the attempts run out.
+    ///
+    /// ```ignore
+    /// let age = db
+    ///     .run_txn(|txn| {
+    ///         Box::pin(async move {
+    ///             let age = txn
+    ///                 .get(&"Alice".to_string(), Projection::All)
+    ///                 .await?
+    ///                 .map(|entry| entry.get().age)
+    ///                 .unwrap_or(0);
+    ///             txn.insert(User {
+    ///                 name: "Alice".into(),
+    ///                 email: None,
+    ///                 age: age + 1,
+    ///             });
+    ///             Ok(age + 1)
+    ///         })
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn run_txn<T, F>(&self, mut f: F) -> Result<T, CommitError<R>>
+    where
+        F: for<'t> FnMut(&'t mut Transaction<'_, R>) -> BoxFuture<'t, Result<T, CommitError<R>>>,
+    {
+        let retry = self.schema.read().await.option.transaction_retry;
+        let mut backoff = retry.backoff;
+        let mut attempt = 1;
+        loop {
+            let mut txn = self.transaction().await;
+            let value = f(&mut txn).await?;
+            match txn.commit().await {
+                Ok(()) => return Ok(value),
+                Err(CommitError::WriteConflict(_) | CommitError::ReadConflict(_))
+                    if attempt < retry.max_attempts => {}
+                Err(err) => return Err(err),
+            }
+            attempt += 1;
+            executor::sleep(backoff).await;
+            backoff = backoff.saturating_mul(2).min(retry.max_backoff);
+        }
     }
 
     pub async fn snapshot(&self) -> Snapshot<'_, R> {
@@ -347,6 +774,90 @@ where
         )
     }
 
+    /// create a read-only view of the [`DB`] as of the earlier timestamp `ts`.
+    ///
+    /// `ts` must be within the latest [`DbOption::time_travel_retention`] timestamps, older
+    /// versions may already be removed by compaction and [`DbError::OutOfRetention`] is returned.
+    pub async fn snapshot_at(&self, ts: Timestamp) -> Result<Snapshot<'_, R>, DbError<R>> {
+        let share = self.schema.read().await;
+        let version = self.ctx.version_set().current().await;
+        let latest = u32::from(version.load_ts());
+
+        match latest.checked_sub(u32::from(ts)) {
+            Some(distance) if distance <= share.option.time_travel_retention => {
+                Ok(Snapshot::with_ts(share, version, ts, self.ctx.clone()))
+            }
+            _ => Err(DbError::OutOfRetention(ts)),
+        }
+    }
+
+    /// create a read-only view of the [`DB`] configured by `options`.
+    ///
+    /// With [`ReadOptions::max_staleness`], snapshots taken within that duration share the same
+    /// pinned version and read timestamp, so they do not observe commits made in the meantime.
+    /// The pin is dropped as soon as a flush or compaction installs a new version.
+    pub async fn snapshot_with(&self, options: ReadOptions) -> Snapshot<'_, R> {
+        let share = self.schema.read().await;
+
+        let snapshot = match options.max_staleness {
+            Some(max_staleness) => {
+                let (version, ts) = self.ctx.version_set().pinned(max_staleness).await;
+                Snapshot::with_ts(share, version, ts, self.ctx.clone())
+            }
+            None => Snapshot::new(
+                share,
+                self.ctx.version_set().current().await,
+                self.ctx.clone(),
+            ),
+        };
+        snapshot.with_deadline(options.deadline)
+    }
+
+    /// Pin the latest timestamp as the named snapshot `name` and return it. Major compactions
+    /// keep every version visible at the timestamp until the snapshot is released with
+    /// [`DB::release_named_snapshot`], so [`DB::named_snapshot`] reads it however long a job
+    /// runs, beyond [`DbOption::time_travel_retention`].
+    ///
+    /// Pins are kept in memory and dropped with the [`DB`]. The versions they keep cost space
+    /// for frequently updated keys.
+    ///
+    /// # Error
+    /// Returns [`DbError::SnapshotExists`] if a snapshot is named `name` already.
+    pub async fn create_named_snapshot(
+        &self,
+        name: impl Into<String>,
+    ) -> Result<Timestamp, DbError<R>> {
+        let name = name.into();
+        let ts = self.ctx.load_ts();
+        if !self.ctx.pin_snapshot(name.clone(), ts) {
+            return Err(DbError::SnapshotExists(name));
+        }
+        Ok(ts)
+    }
+
+    /// read the named snapshot `name`, `None` if there is none
+    pub async fn named_snapshot(&self, name: &str) -> Option<Snapshot<'_, R>> {
+        let share = self.schema.read().await;
+        let ts = self.ctx.named_snapshot(name)?;
+        Some(Snapshot::with_ts(
+            share,
+            self.ctx.version_set().current().await,
+            ts,
+            self.ctx.clone(),
+        ))
+    }
+
+    /// the names and timestamps of the named snapshots, sorted by name
+    pub fn named_snapshots(&self) -> Vec<(String, Timestamp)> {
+        self.ctx.named_snapshots()
+    }
+
+    /// release the named snapshot `name`, the versions it kept are removed by the next major
+    /// compactions. Returns whether there was a snapshot `name`.
+    pub fn release_named_snapshot(&self, name: &str) -> bool {
+        self.ctx.release_snapshot(name)
+    }
+
     /// insert a single tonbo record
     pub async fn insert(&self, record: R) -> Result<(), CommitError<R>> {
         Ok(self.write(record, self.ctx.increase_ts()).await?)
@@ -362,6 +873,7 @@ where
 
     /// delete the record with the primary key as the `key`
     pub async fn remove(&self, key: <R::Schema as Schema>::Key) -> Result<bool, CommitError<R>> {
+        self.stall().await?;
         Ok(self
             .schema
             .read()
@@ -370,10 +882,118 @@ where
             .await?)
     }
 
+    /// insert `record` with the commit timestamp `ts` instead of the next one of the [`DB`], to
+    /// replay writes of another store in their original order, e.g. by a replication receiver.
+    ///
+    /// Later writes are given timestamps newer than `ts`. The timestamp is kept when the write is
+    /// recovered from the WAL.
+    ///
+    /// # Error
+    /// Returns [`DbError::StaleTimestamp`] if `ts` is not newer than the latest timestamp of the
+    /// [`DB`], as the write would change what existing snapshots read.
+    pub async fn insert_at(&self, record: R, ts: Timestamp) -> Result<(), CommitError<R>> {
+        if !self.ctx.advance_ts(ts) {
+            return Err(DbError::StaleTimestamp(ts).into());
+        }
+        Ok(self.write(record, ts).await?)
+    }
+
+    /// delete the record with the primary key as the `key` at the commit timestamp `ts`, see
+    /// [`DB::insert_at`]
+    pub async fn remove_at(
+        &self,
+        key: <R::Schema as Schema>::Key,
+        ts: Timestamp,
+    ) -> Result<bool, CommitError<R>> {
+        self.stall().await?;
+        if !self.ctx.advance_ts(ts) {
+            return Err(DbError::StaleTimestamp(ts).into());
+        }
+        Ok(self
+            .schema
+            .read()
+            .await
+            .remove(LogType::Full, key, ts)
+            .await?)
+    }
+
+    /// insert `record` only if `expected` accepts the committed record with the same primary key,
+    /// `None` if the key is absent, without opening a [`Transaction`].
+    ///
+    /// The check and the write are atomic with respect to transactions and other conditional
+    /// puts. Returns `Ok(Err(conflict))` with the value `expected` rejected the current record
+    /// with, the record is not inserted then.
+    ///
+    /// # Error
+    /// Returns [`CommitError::WriteConflict`] if the key is written without a lock, e.g. by
+    /// [`DB::insert`], between the check and the write.
+    pub async fn put_if<T>(
+        &self,
+        record: R,
+        expected: impl FnOnce(Option<R::Ref<'_>>) -> Result<(), T>,
+    ) -> Result<Result<(), T>, CommitError<R>> {
+        self.stall().await?;
+        let key = record.key().to_key();
+        // SAFETY: Error is Never
+        let _key_guard = self
+            .lock_map
+            .async_lock(key.clone(), AsyncLimit::no_limit())
+            .await
+            .unwrap();
+        let schema = self.schema.read().await;
+        let version = self.ctx.version_set().current().await;
+        let ts = self.ctx.load_ts();
+
+        let current = schema
+            .get(&self.ctx, &version, &key, ts, Projection::All, None)
+            .await?;
+        if let Err(conflict) = expected(current.as_ref().and_then(Entry::value)) {
+            return Ok(Err(conflict));
+        }
+        drop(current);
+        if schema.check_conflict(&key, ts) {
+            return Err(CommitError::WriteConflict(key));
+        }
+        if schema
+            .write(LogType::Full, record, self.ctx.increase_ts())
+            .await?
+        {
+            let _ = schema.compaction_tx.try_send(CompactTask::Freeze);
+        }
+
+        Ok(Ok(()))
+    }
+
+    /// statistics of the block cache, `None` without [`DbOption::block_cache`]
+    pub fn block_cache_stats(&self) -> Option<BlockCacheStats> {
+        self.ctx
+            .block_cache()
+            .map(|block_cache| block_cache.stats())
+    }
+
+    /// statistics of the footer cache, `None` without [`DbOption::metadata_cache`]
+    pub fn metadata_cache_stats(&self) -> Option<MetadataCacheStats> {
+        self.ctx
+            .metadata_cache()
+            .map(|metadata_cache| metadata_cache.stats())
+    }
+
+    /// statistics of the SSTables kept open, `None` without [`DbOption::max_open_tables`]
+    pub fn table_cache_stats(&self) -> Option<TableCacheStats> {
+        self.ctx
+            .manager
+            .table_cache()
+            .map(|table_cache| table_cache.stats())
+    }
+
     /// trigger compaction manually. This will flush the WAL and trigger compaction
     pub async fn flush(&self) -> Result<(), CommitError<R>> {
         let (tx, rx) = oneshot::channel();
-        let compaction_tx = { self.schema.read().await.compaction_tx.clone() };
+        let compaction_tx = {
+            let schema = self.schema.read().await;
+            schema.check_writable()?;
+            schema.compaction_tx.clone()
+        };
         compaction_tx
             .send_async(CompactTask::Flush(Some(tx)))
             .await?;
@@ -399,6 +1019,7 @@ where
                 key,
                 self.ctx.load_ts(),
                 Projection::All,
+                None,
             )
             .await?
             .and_then(|entry| {
@@ -410,6 +1031,43 @@ where
             }))
     }
 
+    /// get the records of `keys` and process them using closure `f`, results are returned in the
+    /// order of `keys`.
+    ///
+    /// Keys are looked up together, so every SSTable is opened and read at most once instead of
+    /// once per key.
+    pub async fn get_many<T>(
+        &self,
+        keys: &[<R::Schema as Schema>::Key],
+        mut f: impl FnMut(TransactionEntry<'_, R>) -> Option<T>,
+    ) -> Result<Vec<Option<T>>, CommitError<R>> {
+        let keys = keys.iter().collect::<Vec<_>>();
+        let schema = self.schema.read().await;
+        let current = self.ctx.version_set.current().await;
+
+        Ok(schema
+            .get_many(
+                &self.ctx,
+                &current,
+                &keys,
+                self.ctx.load_ts(),
+                Projection::All,
+                None,
+            )
+            .await?
+            .into_iter()
+            .map(|entry| {
+                entry.and_then(|entry| {
+                    if entry.value().is_none() {
+                        None
+                    } else {
+                        f(TransactionEntry::Stream(entry))
+                    }
+                })
+            })
+            .collect())
+    }
+
     /// scan records with primary keys in the `range` and process them using closure `f`
     pub async fn scan<'scan, T: 'scan>(
         &'scan self,
@@ -438,6 +1096,7 @@ where
     }
 
     pub(crate) async fn write(&self, record: R, ts: Timestamp) -> Result<(), DbError<R>> {
+        self.stall().await?;
         let schema = self.schema.read().await;
 
         if schema.write(LogType::Full, record, ts).await? {
@@ -452,6 +1111,7 @@ where
         mut records: impl ExactSizeIterator<Item = R>,
         ts: Timestamp,
     ) -> Result<(), DbError<R>> {
+        self.stall().await?;
         let schema = self.schema.read().await;
 
         if let Some(first) = records.next() {
@@ -477,6 +1137,12 @@ where
         Ok(())
     }
 
+    /// apply [`DbOption::write_stall`] before a write, without holding the memtables so the
+    /// flushes the write waits for can proceed
+    pub(crate) async fn stall(&self) -> Result<(), DbError<R>> {
+        stall(&self.schema, &self.ctx).await
+    }
+
     /// flush WAL to the stable storage. If WAL is disabled, this method will do nothing.
     ///
     /// There is no guarantee that the data will be flushed to WAL because of the buffer. So it is
@@ -491,6 +1157,9 @@ where
     ///
     /// **Note:** This will remove all wal and manifest file in the directory.
     pub async fn destroy(self) -> Result<(), DbError<R>> {
+        if self.schema.read().await.option.read_only {
+            return Err(DbError::ReadOnly);
+        }
         self.schema.write().await.destroy(&self.ctx.manager).await?;
         if let Some(ctx) = Arc::into_inner(self.ctx) {
             ctx.version_set.destroy().await?;
@@ -500,6 +1169,118 @@ where
     }
 }
 
+/// scan records with primary keys in the `range` across several tables with the same schema and
+/// process them using closure `f`.
+///
+/// Every table is read under its own snapshot and the results are merged by primary key, records
+/// with equal keys are returned in the order of `dbs`. Removed records are skipped.
+///
+/// # Panics
+///
+/// Panics if the Arrow schemas of `dbs` differ.
+pub fn union_scan<'scan, R, E, T: 'scan>(
+    dbs: &'scan [&'scan DB<R, E>],
+    range: (
+        Bound<&'scan <R::Schema as Schema>::Key>,
+        Bound<&'scan <R::Schema as Schema>::Key>,
+    ),
+    mut f: impl FnMut(TransactionEntry<'_, R>) -> T + 'scan,
+) -> impl Stream<Item = Result<T, CommitError<R>>> + 'scan
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    if let Some((first, rest)) = dbs.split_first() {
+        assert!(
+            rest.iter()
+                .all(|db| db.ctx.arrow_schema() == first.ctx.arrow_schema()),
+            "union scan requires tables with the same schema"
+        );
+    }
+
+    stream! {
+        let mut snapshots = Vec::with_capacity(dbs.len());
+        for db in dbs {
+            snapshots.push(db.snapshot().await);
+        }
+        let mut scans = Vec::with_capacity(snapshots.len());
+        for snapshot in snapshots.iter() {
+            scans.push(snapshot.scan(range).take().await?);
+        }
+        let mut heads = Vec::with_capacity(scans.len());
+        for scan in scans.iter_mut() {
+            heads.push(next_live(scan).await.transpose()?);
+        }
+
+        loop {
+            let mut min: Option<usize> = None;
+            for (i, head) in heads.iter().enumerate() {
+                let Some(entry) = head else {
+                    continue;
+                };
+                if !matches!(
+                    min.and_then(|min| heads[min].as_ref()),
+                    Some(min) if min.key().value() <= entry.key().value()
+                ) {
+                    min = Some(i);
+                }
+            }
+            let Some(min) = min else {
+                break;
+            };
+            let next = next_live(&mut scans[min]).await.transpose()?;
+            let entry = mem::replace(&mut heads[min], next).unwrap();
+
+            yield Ok(f(TransactionEntry::Stream(entry)))
+        }
+    }
+}
+
+async fn next_live<'scan, R>(
+    scan: &mut (impl Stream<Item = Result<Entry<'scan, R>, ParquetError>> + Unpin),
+) -> Option<Result<Entry<'scan, R>, ParquetError>>
+where
+    R: Record,
+{
+    loop {
+        match scan.next().await {
+            Some(Ok(entry)) if entry.value().is_none() => continue,
+            next => return next,
+        }
+    }
+}
+
+/// apply [`DbOption::write_stall`] to a write of the DB holding `schema` and `ctx`, see
+/// [`DB::stall`]
+pub(crate) async fn stall<R>(
+    schema: &RwLock<DbStorage<R>>,
+    ctx: &Context<R>,
+) -> Result<(), DbError<R>>
+where
+    R: Record,
+{
+    let (write_stall, immutables) = {
+        let schema = schema.read().await;
+        let Some(write_stall) = schema.option.write_stall else {
+            return Ok(());
+        };
+        (write_stall, schema.immutables.len())
+    };
+    if immutables <= write_stall.max_immutables
+        && ctx.version_set.current().await.tables_len(0) <= write_stall.max_level0_tables
+    {
+        return Ok(());
+    }
+    match write_stall.policy {
+        StallPolicy::Delay(delay) => {
+            executor::sleep(delay).await;
+            Ok(())
+        }
+        StallPolicy::Reject => Err(DbError::Busy),
+    }
+}
+
 pub(crate) struct DbStorage<R>
 where
     R: Record,
@@ -511,6 +1292,14 @@ where
     trigger: Arc<dyn FreezeTrigger<R>>,
     record_schema: Arc<R::Schema>,
     option: Arc<DbOption>,
+    changes: ChangeFeed<R>,
+    commit_hooks: CommitHooks<R>,
+    /// the lease of [`DbOption::lease`], the writes fail once it is lost
+    lease: Option<Weak<Lease>>,
+    /// the bytes of the memtables counted by [`DbOption::memory_budget`]
+    memory: Arc<MemTableUsage>,
+    #[cfg(feature = "metrics")]
+    metrics: Arc<metrics::Metrics>,
 }
 
 impl<R> DbStorage<R>
@@ -524,14 +1313,17 @@ where
         record_schema: Arc<R::Schema>,
         manager: &StoreManager,
     ) -> Result<Self, DbError<R>> {
-        let base_fs = manager.base_fs();
+        let wal_fs = manager.wal_fs();
         let wal_dir_path = option.wal_dir_path();
         let mut transaction_map = HashMap::new();
         let mut wal_ids = Vec::new();
 
-        let wal_metas = {
+        // a read-only DB only reads the SSTables
+        let wal_metas = if option.read_only {
+            Vec::new()
+        } else {
             let mut wal_metas = Vec::new();
-            let mut wal_stream = base_fs.list(&wal_dir_path).await?;
+            let mut wal_stream = wal_fs.list(&wal_dir_path).await?;
 
             while let Some(file_meta) = wal_stream.next().await {
                 let file_meta = file_meta?;
@@ -544,11 +1336,15 @@ where
         };
 
         let trigger = TriggerFactory::create(option.trigger_type);
+        let memory = Arc::new(MemTableUsage::new(compaction_tx.clone()));
+        if let Some(memory_budget) = &option.memory_budget {
+            memory_budget.register(&memory);
+        }
         let mut schema = DbStorage {
             mutable: MutableMemTable::new(
                 &option,
                 trigger.clone(),
-                manager.base_fs().clone(),
+                wal_fs.clone(),
                 record_schema.clone(),
             )
             .await?,
@@ -558,6 +1354,12 @@ where
             trigger,
             record_schema,
             option: option.clone(),
+            changes: Default::default(),
+            commit_hooks: Default::default(),
+            lease: None,
+            memory,
+            #[cfg(feature = "metrics")]
+            metrics: Default::default(),
         };
 
         for wal_meta in wal_metas {
@@ -567,10 +1369,29 @@ where
             let wal_id = parse_file_id(&wal_path, FileType::Wal)?.unwrap();
             wal_ids.push(wal_id);
 
-            let mut recover_stream =
-                pin!(WalFile::<R>::recover(option.base_fs.clone(), wal_path).await);
+            let mut recover_stream = pin!(
+                WalFile::<R>::recover(
+                    option.wal_fs().clone(),
+                    wal_path.clone(),
+                    option.encryption.clone()
+                )
+                .await
+            );
             while let Some(record) = recover_stream.next().await {
-                let record_batch = record?;
+                let record_batch = match record {
+                    Ok(record_batch) => record_batch,
+                    Err(RecoverError::Torn) => {
+                        warn!("dropping the torn last log of WAL segment {wal_path}");
+                        break;
+                    }
+                    // the logs of the buffered commits may be torn by a crash, the commits did
+                    // not wait for them to be written
+                    Err(err) if !option.wal_syncs_commits() => {
+                        warn!("dropping the logs of WAL segment {wal_path} after: {err}");
+                        break;
+                    }
+                    Err(err) => return Err(err.into()),
+                };
 
                 for entry in record_batch {
                     let Log {
@@ -584,7 +1405,7 @@ where
                     let is_excess = match log_type.unwrap() {
                         LogType::Full => {
                             schema
-                                .recover_append(key, version_set.increase_ts(), value)
+                                .recover_append(key, version_set.recover_ts(ts), value)
                                 .await?
                         }
                         LogType::First => {
@@ -600,7 +1421,7 @@ where
                             let mut records = transaction_map.remove(&ts).unwrap();
                             records.push((key, value));
 
-                            let ts = version_set.increase_ts();
+                            let ts = version_set.recover_ts(ts);
                             for (key, value_option) in records {
                                 is_excess = schema.recover_append(key, ts, value_option).await?;
                             }
@@ -618,8 +1439,43 @@ where
         Ok(schema)
     }
 
+    /// the [`DB`] is not read-only and holds its lease
+    fn check_writable(&self) -> Result<(), DbError<R>> {
+        if self.option.read_only {
+            return Err(DbError::ReadOnly);
+        }
+        if let Some(lease) = &self.lease {
+            if !lease.upgrade().is_some_and(|lease| lease.is_held()) {
+                return Err(DbError::AlreadyOwned(self.option.lease_path()));
+            }
+        }
+        Ok(())
+    }
+
     async fn write(&self, log_ty: LogType, record: R, ts: Timestamp) -> Result<bool, DbError<R>> {
-        self.mutable.insert(log_ty, record, ts).await
+        self.check_writable()?;
+        let change =
+            (!self.changes.is_empty()).then(|| Change::insert(&record, ts, &self.record_schema));
+        let key = (!self.commit_hooks.is_empty()).then(|| record.key().to_key());
+        let is_excess = self
+            .mutable
+            .insert(log_ty, record, ts)
+            .await
+            .inspect_err(|_| {
+                self.changes.failed(ts);
+                self.commit_hooks.failed(ts);
+            })?;
+        self.account_mutable();
+
+        if let Some(change) = change {
+            self.changes.publish(log_ty, change);
+        }
+        if let Some(key) = key {
+            self.commit_hooks.written(log_ty, key, ts);
+        }
+        #[cfg(feature = "metrics")]
+        self.metrics.write();
+        Ok(is_excess)
     }
 
     async fn remove(
@@ -628,7 +1484,53 @@ where
         key: <R::Schema as Schema>::Key,
         ts: Timestamp,
     ) -> Result<bool, DbError<R>> {
-        self.mutable.remove(log_ty, key, ts).await
+        self.check_writable()?;
+        let change = (!self.changes.is_empty()).then(|| Change::Remove {
+            key: key.clone(),
+            ts,
+        });
+        let hook_key = (!self.commit_hooks.is_empty()).then(|| key.clone());
+        let is_excess = self
+            .mutable
+            .remove(log_ty, key, ts)
+            .await
+            .inspect_err(|_| {
+                self.changes.failed(ts);
+                self.commit_hooks.failed(ts);
+            })?;
+        self.account_mutable();
+
+        if let Some(change) = change {
+            self.changes.publish(log_ty, change);
+        }
+        if let Some(key) = hook_key {
+            self.commit_hooks.written(log_ty, key, ts);
+        }
+        #[cfg(feature = "metrics")]
+        self.metrics.write();
+        Ok(is_excess)
+    }
+
+    /// count the mutable memtable grown by a write into [`DbOption::memory_budget`], asking
+    /// for a flush once the budget is exceeded
+    fn account_mutable(&self) {
+        if let Some(memory_budget) = &self.option.memory_budget {
+            self.memory.set_mutable(self.mutable.size());
+            memory_budget.enforce();
+        }
+    }
+
+    /// count the memtables changed by a freeze or a flush into [`DbOption::memory_budget`]
+    pub(crate) fn account_memtables(&self) {
+        self.memory.set(self.mutable.size(), self.immutables_size());
+    }
+
+    /// bytes of the Arrow arrays of the immutable memtables
+    pub(crate) fn immutables_size(&self) -> usize {
+        self.immutables
+            .iter()
+            .map(|(_, immutable)| immutable.as_record_batch().get_array_memory_size())
+            .sum()
     }
 
     async fn recover_append(
@@ -640,37 +1542,48 @@ where
         self.mutable.append(None, key, ts, value).await
     }
 
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(name = "tonbo::get", skip_all, fields(key = ?key, ts = u32::from(ts)))
+    )]
     async fn get<'get>(
         &'get self,
-        ctx: &Context<R>,
+        ctx: &Arc<Context<R>>,
         version: &'get Version<R>,
         key: &'get <R::Schema as Schema>::Key,
         ts: Timestamp,
         projection: Projection<'get>,
+        deadline: Option<Instant>,
     ) -> Result<Option<Entry<'get, R>>, DbError<R>> {
-        let primary_key_index = self.record_schema.primary_key_index();
-        let schema = ctx.arrow_schema();
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+        let entry = self
+            .newest(ctx, version, key, ts, projection, deadline)
+            .await?;
+        #[cfg(feature = "metrics")]
+        ctx.metrics().get(start.elapsed());
+        let now = ttl::now();
 
-        let projection = match projection {
-            Projection::All => ProjectionMask::all(),
-            Projection::Parts(projection) => {
-                let mut fixed_projection: Vec<usize> = [0, 1, primary_key_index]
-                    .into_iter()
-                    .chain(projection.into_iter().map(|name| {
-                        schema
-                            .index_of(name)
-                            .unwrap_or_else(|_| panic!("unexpected field {}", name))
-                    }))
-                    .collect();
-                fixed_projection.dedup();
+        Ok(entry.filter(|entry| !self.is_expired(entry, now)))
+    }
 
-                ProjectionMask::roots(
-                    &ArrowSchemaConverter::new().convert(schema).unwrap(),
-                    fixed_projection,
-                )
-            }
-        };
+    /// the newest version of `key` visible at `ts`
+    async fn newest<'get>(
+        &'get self,
+        ctx: &Arc<Context<R>>,
+        version: &'get Version<R>,
+        key: &'get <R::Schema as Schema>::Key,
+        ts: Timestamp,
+        projection: Projection<'get>,
+        deadline: Option<Instant>,
+    ) -> Result<Option<Entry<'get, R>>, DbError<R>> {
+        let projection = self.projection_mask(ctx, projection);
 
+        if self.is_merge_on_read(ctx) {
+            return self
+                .merged_get(ctx, version, key, ts, projection, deadline)
+                .await;
+        }
         if let Some(entry) = self.mutable.get(key, ts) {
             return Ok(Some(Entry::Projection((
                 Box::new(Entry::Mutable(entry)),
@@ -689,12 +1602,219 @@ where
                 ctx.storage_manager(),
                 TsRef::new(key, ts),
                 projection,
-                ctx.cache().clone(),
+                deadline::cache(ctx.cache(), deadline),
             )
             .await?
             .map(|entry| Entry::RecordBatch(entry)))
     }
 
+    async fn get_many<'get>(
+        &'get self,
+        ctx: &Arc<Context<R>>,
+        version: &'get Version<R>,
+        keys: &[&'get <R::Schema as Schema>::Key],
+        ts: Timestamp,
+        projection: Projection<'get>,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<Option<Entry<'get, R>>>, DbError<R>> {
+        let projection = self.projection_mask(ctx, projection);
+
+        if self.is_merge_on_read(ctx) {
+            let mut entries = Vec::with_capacity(keys.len());
+            for key in keys {
+                entries.push(
+                    self.merged_get(ctx, version, key, ts, projection.clone(), deadline)
+                        .await?,
+                );
+            }
+            return Ok(entries);
+        }
+
+        // sorted and deduplicated positions of `keys`, so that every SSTable is read once
+        let mut sorted = (0..keys.len()).collect::<Vec<_>>();
+        sorted.sort_by(|a, b| keys[*a].cmp(keys[*b]));
+        sorted.dedup_by(|a, b| keys[*a] == keys[*b]);
+
+        let mut entries = sorted.iter().map(|_| None).collect::<Vec<_>>();
+        let mut pending = Vec::new();
+        'keys: for (i, key) in sorted.iter().map(|index| keys[*index]).enumerate() {
+            if let Some(entry) = self.mutable.get(key, ts) {
+                entries[i] = Some(Entry::Projection((
+                    Box::new(Entry::Mutable(entry)),
+                    Arc::new(projection.clone()),
+                )));
+                continue;
+            }
+            for (_, immutable) in self.immutables.iter().rev() {
+                if let Some(entry) = immutable.get(key, ts, projection.clone()) {
+                    entries[i] = Some(Entry::RecordBatch(entry));
+                    continue 'keys;
+                }
+            }
+            pending.push(i);
+        }
+        if !pending.is_empty() {
+            let pending_keys = pending.iter().map(|i| keys[sorted[*i]]).collect::<Vec<_>>();
+            let pending_entries = version
+                .query_many(
+                    ctx.storage_manager(),
+                    &pending_keys,
+                    ts,
+                    projection,
+                    deadline::cache(ctx.cache(), deadline),
+                )
+                .await?;
+            for (i, entry) in pending.into_iter().zip(pending_entries) {
+                entries[i] = entry.map(Entry::RecordBatch);
+            }
+        }
+
+        let now = ttl::now();
+        Ok(keys
+            .iter()
+            .map(|key| {
+                let i = sorted
+                    .binary_search_by(|index| keys[*index].cmp(key))
+                    .expect("every key has been queried");
+                entries[i]
+                    .clone()
+                    .filter(|entry| !self.is_expired(entry, now))
+            })
+            .collect())
+    }
+
+    /// whether the versions of a key are merged, with [`UpdateStrategy::MergeOnRead`] or a
+    /// [`MergeOperator`]
+    fn is_merge_on_read(&self, ctx: &Context<R>) -> bool {
+        self.option.update_strategy == UpdateStrategy::MergeOnRead || ctx.merge_operator().is_some()
+    }
+
+    /// whether `entry` is a record that expired at `now`, see [`ttl`]
+    fn is_expired(&self, entry: &Entry<'_, R>, now: u64) -> bool {
+        self.record_schema.expire_at_index().is_some() && ttl::is_expired::<R>(entry.value(), now)
+    }
+
+    /// look `key` up with [`UpdateStrategy::MergeOnRead`] or a [`MergeOperator`]. Its versions
+    /// are read from the newest memtable or table to the oldest one until a tombstone or a
+    /// version holding every merged column (see [`DeltaMerger::is_complete`]), the older ones
+    /// are not opened
+    async fn merged_get<'get>(
+        &'get self,
+        ctx: &Arc<Context<R>>,
+        version: &'get Version<R>,
+        key: &'get <R::Schema as Schema>::Key,
+        ts: Timestamp,
+        projection: ProjectionMask,
+        deadline: Option<Instant>,
+    ) -> Result<Option<Entry<'get, R>>, DbError<R>> {
+        let primary_key_index = self.record_schema.primary_key_index();
+        // the operator combines whole records, so the projection is not pushed down for it
+        let (merger, projection) = match ctx.merge_operator() {
+            Some(operator) => (
+                DeltaMerger::new(
+                    ctx.arrow_schema().clone(),
+                    primary_key_index,
+                    ProjectionMask::all(),
+                )
+                .operator(operator.clone()),
+                ProjectionMask::all(),
+            ),
+            None => (
+                DeltaMerger::new(
+                    ctx.arrow_schema().clone(),
+                    primary_key_index,
+                    projection.clone(),
+                ),
+                projection,
+            ),
+        };
+        let range = (Bound::Included(key), Bound::Included(key));
+        let mut memtables: Vec<ScanStream<'get, R>> = vec![self.mutable.scan(range, ts).into()];
+        for (_, immutable) in self.immutables.iter().rev() {
+            memtables.push(immutable.scan(range, ts, projection.clone()).into());
+        }
+        let mut memtables = memtables.into_iter();
+        let mut tables = version.key_tables(key).into_iter();
+        let parquet_lru = deadline::cache(ctx.cache(), deadline);
+
+        let mut versions = Vec::new();
+        'sources: loop {
+            let mut source = match memtables.next() {
+                Some(memtable) => memtable,
+                None => match tables.next() {
+                    Some((level, gen)) => version
+                        .key_versions(
+                            ctx.storage_manager(),
+                            key,
+                            ts,
+                            level,
+                            gen,
+                            projection.clone(),
+                            parquet_lru.clone(),
+                        )
+                        .await?
+                        .into(),
+                    None => break,
+                },
+            };
+            while let Some(entry) = source.next().await.transpose()? {
+                if entry.key().ts > ts {
+                    continue;
+                }
+                if entry.value().is_none() {
+                    // a tombstone hides the older versions, it is the entry if it is the newest
+                    if versions.is_empty() {
+                        versions.push(entry);
+                    }
+                    break 'sources;
+                }
+                let is_complete = merger.is_complete(&entry);
+                versions.push(entry);
+                if is_complete {
+                    break 'sources;
+                }
+            }
+        }
+
+        Ok(match versions.len() {
+            0 => None,
+            1 => match versions.pop() {
+                Some(Entry::Mutable(entry)) => Some(Entry::Projection((
+                    Box::new(Entry::Mutable(entry)),
+                    Arc::new(projection),
+                ))),
+                entry => entry,
+            },
+            _ => Some(merger.merge(&versions).map_err(ParquetError::from)?),
+        })
+    }
+
+    fn projection_mask(&self, ctx: &Context<R>, projection: Projection<'_>) -> ProjectionMask {
+        let primary_key_index = self.record_schema.primary_key_index();
+        let schema = ctx.arrow_schema();
+
+        match projection {
+            Projection::All => ProjectionMask::all(),
+            Projection::Parts(projection) => {
+                let mut fixed_projection: Vec<usize> = [0, 1, primary_key_index]
+                    .into_iter()
+                    .chain(projection.into_iter().map(|name| {
+                        schema
+                            .index_of(name)
+                            .unwrap_or_else(|_| panic!("unexpected field {}", name))
+                    }))
+                    .collect();
+                fixed_projection.dedup();
+                ttl::project_expire_at(self.record_schema.as_ref(), &mut fixed_projection);
+
+                ProjectionMask::roots(
+                    &ArrowSchemaConverter::new().convert(schema).unwrap(),
+                    fixed_projection,
+                )
+            }
+        }
+    }
+
     fn check_conflict(&self, key: &<R::Schema as Schema>::Key, ts: Timestamp) -> bool {
         self.mutable.check_conflict(key, ts)
             || self
@@ -704,6 +1824,22 @@ where
                 .any(|(_, immutable)| immutable.check_conflict(key, ts))
     }
 
+    fn check_range_conflict(
+        &self,
+        range: (
+            Bound<&<R::Schema as Schema>::Key>,
+            Bound<&<R::Schema as Schema>::Key>,
+        ),
+        ts: Timestamp,
+    ) -> bool {
+        self.mutable.check_range_conflict(range, ts)
+            || self
+                .immutables
+                .iter()
+                .rev()
+                .any(|(_, immutable)| immutable.check_range_conflict(range, ts))
+    }
+
     async fn flush_wal(&self) -> Result<(), DbError<R>> {
         self.mutable.flush_wal().await?;
         Ok(())
@@ -712,10 +1848,9 @@ where
     async fn destroy(&mut self, manager: &StoreManager) -> Result<(), DbError<R>> {
         self.mutable.destroy().await?;
 
-        let base_fs = manager.base_fs();
+        let fs = manager.wal_fs();
         let wal_dir_path = self.option.wal_dir_path();
-        let mut wal_stream = base_fs.list(&wal_dir_path).await?;
-        let fs = manager.base_fs();
+        let mut wal_stream = fs.list(&wal_dir_path).await?;
 
         while let Some(file_meta) = wal_stream.next().await {
             fs.remove(&file_meta?.path).await?;
@@ -742,7 +1877,12 @@ where
     limit: Option<usize>,
     projection_indices: Option<Vec<usize>>,
     projection: ProjectionMask,
+    key_projection: KeyProjection,
+    is_key_projected: bool,
+    exprs: Vec<ScanExpr>,
     ctx: Arc<Context<R>>,
+    deadline: Option<Instant>,
+    borrow_values: bool,
 }
 
 impl<'scan, 'range, R> Scan<'scan, 'range, R>
@@ -772,10 +1912,20 @@ where
             limit: None,
             projection_indices: None,
             projection: ProjectionMask::all(),
+            key_projection: KeyProjection::default(),
+            is_key_projected: false,
+            exprs: Vec::new(),
             ctx,
+            deadline: None,
+            borrow_values: false,
         }
     }
 
+    /// bound the SSTable reads of the scan by `deadline`, see [`ReadOptions::deadline`]
+    pub(crate) fn deadline(self, deadline: Option<Instant>) -> Self {
+        Self { deadline, ..self }
+    }
+
     /// limit for the scan
     pub fn limit(self, limit: usize) -> Self {
         Self {
@@ -796,9 +1946,11 @@ where
             })
             .collect::<Vec<usize>>();
         let primary_key_index = self.schema.record_schema.primary_key_index();
+        let is_key_projected = projection.contains(&primary_key_index);
         let mut fixed_projection = vec![0, 1, primary_key_index];
         fixed_projection.append(&mut projection);
         fixed_projection.dedup();
+        ttl::project_expire_at(self.schema.record_schema.as_ref(), &mut fixed_projection);
 
         let mask = ProjectionMask::roots(
             &ArrowSchemaConverter::new().convert(schema).unwrap(),
@@ -808,10 +1960,40 @@ where
         Self {
             projection: mask,
             projection_indices: Some(fixed_projection),
+            is_key_projected,
+            ..self
+        }
+    }
+
+    /// how the primary key column is materialized, see [`KeyProjection`]
+    pub fn key_projection(self, key_projection: KeyProjection) -> Self {
+        Self {
+            key_projection,
+            ..self
+        }
+    }
+
+    /// leave the values of the `String` and `Bytes` columns of dynamic records in the record
+    /// batches of the memtables and the SSTables instead of copying them into the records
+    /// returned by [`Scan::take`], read them with [`DynRecordRef::str`](record::DynRecordRef::str)
+    /// and [`DynRecordRef::bytes`](record::DynRecordRef::bytes). Records of `#[derive(Record)]`
+    /// borrow them either way.
+    ///
+    /// Records merged on read (see [`UpdateStrategy::MergeOnRead`] and [`MergeOperator`]) are
+    /// still copied.
+    pub fn borrow_values(self) -> Self {
+        Self {
+            borrow_values: true,
             ..self
         }
     }
 
+    /// compute a column from every batch returned by [`Scan::scan_batches`]
+    pub fn expression(mut self, expr: ScanExpr) -> Self {
+        self.exprs.push(expr);
+        self
+    }
+
     /// fields in projection Record by field indices
     pub fn projection_with_index(self, mut projection: Vec<usize>) -> Self {
         // skip two columns: _null and _ts
@@ -819,9 +2001,11 @@ where
             *p += USER_COLUMN_OFFSET;
         }
         let primary_key_index = self.schema.record_schema.primary_key_index();
+        let is_key_projected = projection.contains(&primary_key_index);
         let mut fixed_projection = vec![0, 1, primary_key_index];
         fixed_projection.append(&mut projection);
         fixed_projection.dedup();
+        ttl::project_expire_at(self.schema.record_schema.as_ref(), &mut fixed_projection);
 
         let mask = ProjectionMask::roots(
             &ArrowSchemaConverter::new()
@@ -833,20 +2017,80 @@ where
         Self {
             projection: mask,
             projection_indices: Some(fixed_projection),
+            is_key_projected,
             ..self
         }
     }
 
-    /// get a Stream that returns single row of Record
-    pub async fn take(
-        self,
-    ) -> Result<impl Stream<Item = Result<Entry<'scan, R>, ParquetError>>, DbError<R>> {
+    /// narrow the projection down to `_null`, `_ts` and the primary key for
+    /// [`KeyProjection::KeyOnly`]
+    fn apply_key_projection(&mut self) {
+        if self.key_projection != KeyProjection::KeyOnly {
+            return;
+        }
+        let record_schema = &self.schema.record_schema;
+        let mut fixed_projection = vec![0, 1, record_schema.primary_key_index()];
+        ttl::project_expire_at(record_schema.as_ref(), &mut fixed_projection);
+
+        self.projection = ProjectionMask::roots(
+            &ArrowSchemaConverter::new()
+                .convert(record_schema.arrow_schema())
+                .unwrap(),
+            fixed_projection.clone(),
+        );
+        self.projection_indices = Some(fixed_projection);
+    }
+
+    /// folds the versions of a key for [`UpdateStrategy::MergeOnRead`] or a [`MergeOperator`],
+    /// rows of SSTables are then read without the limit since a key may need more than one of
+    /// them. The operator combines whole records, so the projection is not pushed down for it
+    fn delta_merger(&mut self) -> Option<DeltaMerger<R>> {
+        let primary_key_index = self.schema.record_schema.primary_key_index();
+        if let Some(operator) = self.ctx.merge_operator() {
+            self.projection = ProjectionMask::all();
+            return Some(
+                DeltaMerger::new(
+                    self.ctx.arrow_schema().clone(),
+                    primary_key_index,
+                    ProjectionMask::all(),
+                )
+                .operator(operator.clone()),
+            );
+        }
+        (self.schema.option.update_strategy == UpdateStrategy::MergeOnRead).then(|| {
+            DeltaMerger::new(
+                self.ctx.arrow_schema().clone(),
+                primary_key_index,
+                self.projection.clone(),
+            )
+        })
+    }
+
+    /// the time expired records are skipped at, if the schema has an expiry column. Rows of
+    /// SSTables are then read without the limit since expired rows do not count for it
+    fn expiry_now(&self) -> Option<u64> {
+        self.schema
+            .record_schema
+            .expire_at_index()
+            .map(|_| ttl::now())
+    }
+
+    /// merge the streams of the memtables and of the SSTables of the scan. The limit is only
+    /// pushed down to the SSTables, the callers apply it to the merged stream
+    async fn merge_stream(
+        &mut self,
+        borrow_values: bool,
+    ) -> Result<MergeStream<'scan, R>, DbError<R>> {
+        self.apply_key_projection();
+        let merger = self.delta_merger();
+        let now = self.expiry_now();
+        let borrow_values = borrow_values && merger.is_none();
+        let projection = mem::replace(&mut self.projection, ProjectionMask::all());
+        let fn_pre_stream = mem::replace(&mut self.fn_pre_stream, Box::new(|_| None));
         let mut streams = Vec::new();
         let is_projection = self.projection_indices.is_some();
 
-        if let Some(pre_stream) =
-            (self.fn_pre_stream)(is_projection.then(|| self.projection.clone()))
-        {
+        if let Some(pre_stream) = fn_pre_stream(is_projection.then(|| projection.clone())) {
             streams.push(pre_stream);
         }
 
@@ -858,15 +2102,15 @@ where
                 .scan((self.lower, self.upper), self.ts)
                 .into();
             if is_projection {
-                mutable_scan =
-                    MemProjectionStream::new(mutable_scan, self.projection.clone()).into();
+                mutable_scan = MemProjectionStream::new(mutable_scan, projection.clone()).into();
             }
             streams.push(mutable_scan);
         }
         for (_, immutable) in self.schema.immutables.iter().rev() {
             streams.push(
                 immutable
-                    .scan((self.lower, self.upper), self.ts, self.projection.clone())
+                    .scan((self.lower, self.upper), self.ts, projection.clone())
+                    .borrow_values(borrow_values)
                     .into(),
             );
         }
@@ -876,73 +2120,106 @@ where
                 &mut streams,
                 (self.lower, self.upper),
                 self.ts,
-                self.limit,
-                self.projection,
+                self.limit.filter(|_| merger.is_none() && now.is_none()),
+                projection,
+                deadline::cache(self.ctx.cache(), self.deadline),
+                borrow_values,
             )
-            .await?;
+            .await
+            .map_err(|err| DbError::from(err).or_deadline_exceeded())?;
 
         let mut merge_stream = MergeStream::from_vec(streams, self.ts).await?;
+        if let Some(merger) = merger {
+            merge_stream = merge_stream.merge_on_read(merger);
+        }
+        if let Some(now) = now {
+            merge_stream = merge_stream.expire(now);
+        }
+        Ok(merge_stream)
+    }
+
+    /// get a Stream that returns single row of Record
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "tonbo::scan",
+            skip_all,
+            fields(lower = ?self.lower, upper = ?self.upper, ts = u32::from(self.ts)),
+        )
+    )]
+    pub async fn take(
+        mut self,
+    ) -> Result<impl Stream<Item = Result<Entry<'scan, R>, ParquetError>>, DbError<R>> {
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+        let mut merge_stream = self.merge_stream(self.borrow_values).await?;
         if let Some(limit) = self.limit {
             merge_stream = merge_stream.limit(limit);
         }
+        #[cfg(feature = "metrics")]
+        self.ctx.metrics().scan(start.elapsed());
         Ok(merge_stream)
     }
 
-    /// Get a Stream that returns RecordBatch consisting of a `batch_size` number of records
+    /// Get a Stream that returns RecordBatch consisting of a `batch_size` number of records, a
+    /// `batch_size` of 0 is taken as 1
     pub async fn package(
-        self,
+        mut self,
         batch_size: usize,
     ) -> Result<
         impl Stream<Item = Result<<R::Schema as Schema>::Columns, ParquetError>> + 'scan,
         DbError<R>,
     > {
-        let mut streams = Vec::new();
-        let is_projection = self.projection_indices.is_some();
+        let merge_stream = self.merge_stream(false).await?;
 
-        if let Some(pre_stream) =
-            (self.fn_pre_stream)(is_projection.then(|| self.projection.clone()))
-        {
-            streams.push(pre_stream);
-        }
+        Ok(PackageStream::new(
+            batch_size.max(1),
+            merge_stream,
+            self.projection_indices,
+            self.ctx.arrow_schema().clone(),
+        ))
+    }
 
-        // Mutable
-        {
-            let mut mutable_scan = self
-                .schema
-                .mutable
-                .scan((self.lower, self.upper), self.ts)
-                .into();
-            if is_projection {
-                mutable_scan =
-                    MemProjectionStream::new(mutable_scan, self.projection.clone()).into();
-            }
-            streams.push(mutable_scan);
-        }
-        for (_, immutable) in self.schema.immutables.iter().rev() {
-            streams.push(
-                immutable
-                    .scan((self.lower, self.upper), self.ts, self.projection.clone())
-                    .into(),
-            );
+    /// Get a Stream that returns arrow [`RecordBatch`]es of at most `batch_size` rows, a
+    /// `batch_size` of 0 is taken as 1.
+    ///
+    /// Unlike [`Scan::package`], rows read from immutable memtables and SSTables are gathered
+    /// from their source batches instead of being decoded and rebuilt row by row.
+    pub async fn scan_batches(
+        mut self,
+        batch_size: usize,
+    ) -> Result<impl Stream<Item = Result<RecordBatch, ParquetError>> + 'scan, DbError<R>> {
+        let mut merge_stream = self.merge_stream(false).await?;
+        if let Some(limit) = self.limit {
+            merge_stream = merge_stream.limit(limit);
         }
-        self.version
-            .streams(
-                &self.ctx,
-                &mut streams,
-                (self.lower, self.upper),
-                self.ts,
-                self.limit,
-                self.projection,
-            )
-            .await?;
-        let merge_stream = MergeStream::from_vec(streams, self.ts).await?;
 
-        Ok(PackageStream::new(
-            batch_size,
+        let primary_key = (self.key_projection == KeyProjection::ValueOnly
+            && !self.is_key_projected)
+            .then(|| {
+                let primary_key_index = self.schema.record_schema.primary_key_index();
+                self.ctx
+                    .arrow_schema()
+                    .field(primary_key_index)
+                    .name()
+                    .clone()
+            });
+
+        Ok(BatchStream::new(
+            batch_size.max(1),
             merge_stream,
             self.projection_indices,
             self.ctx.arrow_schema().clone(),
-        ))
+            self.exprs,
+        )
+        .map(move |batch| -> Result<RecordBatch, ParquetError> {
+            let mut batch = batch?;
+            if let Some(primary_key) = &primary_key {
+                let index = batch.schema().index_of(primary_key)?;
+                batch.remove_column(index);
+            }
+            Ok(batch)
+        }))
     }
 }
 
@@ -971,15 +2248,87 @@ where
     ExceedsMaxLevel,
     #[error("write log error: {0}")]
     Logger(#[from] fusio_log::error::LogError),
+    #[error("timestamp {0:?} is out of the time travel retention")]
+    OutOfRetention(Timestamp),
+    #[error("bulk load keys must be written in strictly ascending order")]
+    UnsortedBulkLoad,
+    #[error("bulk load range overlaps existing tables")]
+    BulkLoadOverlap,
+    #[error("merge requires a merge operator")]
+    MissingMergeOperator,
+    #[error("read deadline exceeded")]
+    DeadlineExceeded,
+    #[error("invalid partial update: {0}")]
+    InvalidUpdate(String),
+    #[error("invalid parquet ingest: {0}")]
+    InvalidIngest(String),
+    #[error("invalid parquet export: {0}")]
+    InvalidExport(String),
+    #[error("invalid import: {0}")]
+    InvalidImport(String),
+    #[error("invalid table: {0}")]
+    InvalidTable(String),
+    #[error("named snapshot {0} exists")]
+    SnapshotExists(String),
+    #[error("timestamp {0:?} is not newer than the latest timestamp")]
+    StaleTimestamp(Timestamp),
+    #[error("writes are stalled until flushes and compactions catch up")]
+    Busy,
+    #[error("the DB is opened read-only")]
+    ReadOnly,
+    #[error("the DB is owned by another process holding the lease {0}")]
+    AlreadyOwned(Path),
+    #[error("lease error: {0}")]
+    Lease(Box<dyn std::error::Error + Send + Sync + 'static>),
+}
+
+impl<R> DbError<R>
+where
+    R: Record,
+{
+    /// surface SSTable reads stopped by [`ReadOptions::deadline`] as
+    /// [`DbError::DeadlineExceeded`]
+    pub(crate) fn or_deadline_exceeded(self) -> Self {
+        match &self {
+            DbError::Parquet(err) | DbError::Version(VersionError::Parquet(err))
+                if deadline::is_exceeded(err) =>
+            {
+                DbError::DeadlineExceeded
+            }
+            _ => self,
+        }
+    }
 }
 
 type LockMap<K> = Arc<LockableHashMap<K, ()>>;
 
+#[derive(Clone)]
 pub enum Projection<'r> {
     All,
     Parts(Vec<&'r str>),
 }
 
+/// How a [`Scan`] materializes the primary key column.
+///
+/// Records always carry their primary key since it is needed to merge and deduplicate them, both
+/// [`RecordRef::projection`](record::RecordRef::projection) and
+/// [`RecordRef::from_record_batch`](record::RecordRef::from_record_batch) keep it whatever the
+/// projection mask is. This only changes which of the other columns are read and which columns
+/// end up in the batches returned by [`Scan::scan_batches`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyProjection {
+    /// The primary key is materialized together with the projected columns, whether or not it is
+    /// listed in the projection.
+    #[default]
+    Always,
+    /// Only the primary key is materialized, projected value columns are not read.
+    KeyOnly,
+    /// The primary key is left out of the batches returned by [`Scan::scan_batches`] unless it is
+    /// listed in the projection. [`Scan::package`] always contains it as its arrays are typed by
+    /// the record.
+    ValueOnly,
+}
+
 pub type ParquetLru = Arc<dyn DynLruCache<FileId> + Send + Sync>;
 
 #[cfg(all(test, feature = "tokio"))]
@@ -987,7 +2336,11 @@ pub(crate) mod tests {
     use std::{
         collections::{BTreeMap, Bound},
         mem,
-        sync::Arc,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
     };
 
     use arrow::{
@@ -1007,7 +2360,10 @@ pub(crate) mod tests {
 
     use crate::{
         cast_arc_value,
-        compaction::{leveled::LeveledCompactor, CompactTask, CompactionError, Compactor},
+        compaction::{
+            fifo::FifoCompactor, leveled::LeveledCompactor, size_ratio::SizeRatioCompactor,
+            CompactTask, CompactionError, Compactor,
+        },
         context::Context,
         executor::{tokio::TokioExecutor, Executor},
         fs::{generate_file_id, manager::StoreManager},
@@ -1018,10 +2374,13 @@ pub(crate) mod tests {
             DataType, DynRecord, Key, RecordDecodeError, RecordEncodeError, RecordRef,
             Schema as RecordSchema, Value, F32, F64,
         },
+        snapshot::Snapshot,
+        transaction::CommitError,
         trigger::{TriggerFactory, TriggerType},
         version::{cleaner::Cleaner, set::tests::build_version_set, Version},
         wal::log::LogType,
-        CompactionOption, DbError, DbOption, Projection, Record, DB,
+        CompactionOption, DbError, DbOption, FlushPolicy, Projection, Record, StallPolicy,
+        TransactionRetry, WalSyncMode, WriteStall, DB,
     };
 
     #[derive(Debug, PartialEq, Eq, Clone)]
@@ -1364,11 +2723,17 @@ pub(crate) mod tests {
             crate::DbStorage {
                 mutable,
                 immutables,
+                memory: Arc::new(MemTableUsage::new(compaction_tx.clone())),
                 compaction_tx,
                 recover_wal_ids: None,
                 trigger,
                 record_schema: Arc::new(TestSchema {}),
                 option,
+                changes: Default::default(),
+                commit_hooks: Default::default(),
+                lease: None,
+                #[cfg(feature = "metrics")]
+                metrics: Default::default(),
             },
             compaction_rx,
         ))
@@ -1413,6 +2778,22 @@ pub(crate) mod tests {
                 option.clone(),
                 ctx.clone(),
             )),
+            CompactionOption::SizeRatio(targets) => {
+                Compactor::SizeRatio(SizeRatioCompactor::<R>::new(
+                    schema.clone(),
+                    record_schema,
+                    option.clone(),
+                    targets,
+                    ctx.clone(),
+                ))
+            }
+            CompactionOption::Fifo(thresholds) => Compactor::Fifo(FifoCompactor::<R>::new(
+                schema.clone(),
+                record_schema,
+                option.clone(),
+                thresholds,
+                ctx.clone(),
+            )),
         };
 
         executor.spawn(async move {
@@ -1444,8 +2825,10 @@ pub(crate) mod tests {
         Ok(DB {
             schema,
             lock_map: Arc::new(Default::default()),
+            serializable_lock: Arc::new(Mutex::new(())),
+            _lease: None,
             ctx,
-            _p: Default::default(),
+            executor: Arc::new(executor),
         })
     }
 
@@ -1505,9 +2888,68 @@ pub(crate) mod tests {
         assert_eq!(option1.get().vbool, Some(true));
     }
 
+    #[cfg(feature = "foyer")]
     #[tokio::test(flavor = "multi_thread")]
-    async fn test_flush() {
-        let temp_dir = TempDir::new().unwrap();
+    async fn foyer_cache() {
+        use foyer::{CacheBuilder, DirectFsDeviceOptions, Engine, HybridCacheBuilder};
+        use parquet_lru::foyer::FoyerCache;
+
+        let temp_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let cache = FoyerCache::new(
+            CacheBuilder::new(32).build(),
+            HybridCacheBuilder::new()
+                .memory(16 * 1024 * 1024)
+                .storage(Engine::Large)
+                .with_device_options(
+                    DirectFsDeviceOptions::new(cache_dir.path()).with_capacity(16 * 1024 * 1024),
+                )
+                .build()
+                .await
+                .unwrap(),
+        );
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> = DB::with_parquet_lru(
+            option,
+            TokioExecutor::current(),
+            TestSchema,
+            Arc::new(cache.clone()),
+        )
+        .await
+        .unwrap();
+        db.insert(Test {
+            vstring: "key".to_string(),
+            vu32: 1,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+
+        let get = || async {
+            db.get(&"key".to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap()
+        };
+        assert_eq!(get().await, Some(1));
+        let stats = cache.stats();
+        assert!(stats.data_misses > 0);
+        // the second get reads the same pages from the cache
+        assert_eq!(get().await, Some(1));
+        let second = cache.stats();
+        assert!(second.meta_hits > stats.meta_hits);
+        assert!(second.data_hits > stats.data_hits);
+        assert_eq!(second.data_misses, stats.data_misses);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_flush() {
+        let temp_dir = TempDir::new().unwrap();
 
         let mut option = DbOption::new(
             Path::from_filesystem_path(temp_dir.path()).unwrap(),
@@ -1651,10 +3093,16 @@ pub(crate) mod tests {
                 .unwrap(),
             immutables: Default::default(),
             compaction_tx: task_tx.clone(),
+            memory: Arc::new(MemTableUsage::new(task_tx.clone())),
             recover_wal_ids: None,
             trigger,
             record_schema: Arc::new(TestSchema),
             option: option.clone(),
+            changes: Default::default(),
+            commit_hooks: Default::default(),
+            lease: None,
+            #[cfg(feature = "metrics")]
+            metrics: Default::default(),
         };
 
         for (i, item) in test_items().into_iter().enumerate() {
@@ -1727,10 +3175,16 @@ pub(crate) mod tests {
             .unwrap(),
             immutables: Default::default(),
             compaction_tx: task_tx.clone(),
+            memory: Arc::new(MemTableUsage::new(task_tx.clone())),
             recover_wal_ids: None,
             trigger,
             record_schema: dyn_schema.clone(),
             option,
+            changes: Default::default(),
+            commit_hooks: Default::default(),
+            lease: None,
+            #[cfg(feature = "metrics")]
+            metrics: Default::default(),
         };
 
         for item in test_dyn_items().into_iter() {
@@ -1816,6 +3270,599 @@ pub(crate) mod tests {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_insert_at() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .time_travel_retention(10);
+        let db: DB<Test, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+        let record = |key: &str, vu32: u32| Test {
+            vstring: key.to_string(),
+            vu32,
+            vbool: None,
+        };
+
+        db.insert_at(record("a", 1), 10.into()).await.unwrap();
+        assert_eq!(u32::from(db.snapshot().await.ts()), 10);
+        db.remove_at("a".to_string(), 12.into()).await.unwrap();
+        // writes must be newer than existing snapshots
+        assert!(matches!(
+            db.insert_at(record("b", 1), 12.into()).await,
+            Err(CommitError::Database(DbError::StaleTimestamp(_)))
+        ));
+        db.insert(record("b", 2)).await.unwrap();
+        assert_eq!(u32::from(db.snapshot().await.ts()), 13);
+
+        let snapshot = db.snapshot_at(11.into()).await.unwrap();
+        let vu32 = snapshot
+            .get(&"a".to_string(), Projection::All)
+            .await
+            .unwrap()
+            .and_then(|entry| entry.value().and_then(|record| record.vu32));
+        assert_eq!(vu32, Some(1));
+        drop(snapshot);
+
+        db.insert_at(record("c", 3), 20.into()).await.unwrap();
+        db.flush_wal().await.unwrap();
+        drop(db);
+        // timestamps are kept when the WAL is recovered
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        assert_eq!(u32::from(db.snapshot().await.ts()), 20);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_named_snapshot() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        option.major_threshold_with_sst_size = 2;
+        option.level_sst_magnification = 1;
+        option.major_default_oldest_table_num = 1;
+        option.trigger_type = TriggerType::Length(5);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        let record = |vu32: u32| Test {
+            vstring: "key".to_string(),
+            vu32,
+            vbool: None,
+        };
+        async fn get(snapshot: Snapshot<'_, Test>) -> Option<u32> {
+            snapshot
+                .get(&"key".to_string(), Projection::All)
+                .await
+                .unwrap()
+                .and_then(|entry| entry.value().and_then(|record| record.vu32))
+        }
+
+        db.insert(record(0)).await.unwrap();
+        db.flush().await.unwrap();
+        let ts = db.create_named_snapshot("report").await.unwrap();
+        assert!(matches!(
+            db.create_named_snapshot("report").await,
+            Err(DbError::SnapshotExists(name)) if name == "report"
+        ));
+        // compactions merge the versions of the key into the deeper levels
+        for vu32 in 1..4 {
+            db.insert(record(vu32)).await.unwrap();
+            db.flush().await.unwrap();
+        }
+        assert!(db.ctx.version_set().current().await.level_slice[1..]
+            .iter()
+            .any(|scopes| !scopes.is_empty()));
+
+        assert_eq!(db.named_snapshots(), vec![("report".to_string(), ts)]);
+        assert_eq!(
+            get(db.named_snapshot("report").await.unwrap()).await,
+            Some(0)
+        );
+        assert_eq!(get(db.snapshot().await).await, Some(3));
+        assert!(db.release_named_snapshot("report"));
+        assert!(!db.release_named_snapshot("report"));
+        assert!(db.named_snapshot("report").await.is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_open_read_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let record = |key: &str| Test {
+            vstring: key.to_string(),
+            vu32: 0,
+            vbool: None,
+        };
+        let files = || {
+            let mut files = ["wal", "version"]
+                .into_iter()
+                .flat_map(|dir| std::fs::read_dir(temp_dir.path().join(dir)).unwrap())
+                .map(|entry| entry.unwrap().path())
+                .collect::<Vec<_>>();
+            files.sort();
+            files
+        };
+
+        let db: DB<Test, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+        db.insert(record("flushed")).await.unwrap();
+        db.flush().await.unwrap();
+        db.insert(record("logged")).await.unwrap();
+        db.flush_wal().await.unwrap();
+        drop(db);
+        let before = files();
+
+        let db: DB<Test, TokioExecutor> =
+            DB::open_read_only(option, TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+        let get = |key: &str| db.get(&key.to_string(), |entry| entry.get().vu32);
+        assert!(get("flushed").await.unwrap().is_some());
+        // the WAL is not replayed
+        assert!(get("logged").await.unwrap().is_none());
+        assert!(matches!(
+            db.insert(record("new")).await,
+            Err(CommitError::Database(DbError::ReadOnly))
+        ));
+        let mut txn = db.transaction().await;
+        txn.remove("flushed".to_string());
+        assert!(matches!(
+            txn.commit().await,
+            Err(CommitError::Database(DbError::ReadOnly))
+        ));
+        assert!(matches!(
+            db.flush().await,
+            Err(CommitError::Database(DbError::ReadOnly))
+        ));
+        assert!(matches!(db.destroy().await, Err(DbError::ReadOnly)));
+        assert_eq!(files(), before);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_wal_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let wal_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .wal_path(
+            Path::from_filesystem_path(wal_dir.path()).unwrap(),
+            FsOptions::Local,
+        );
+        let db: DB<Test, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+        db.insert(Test {
+            vstring: "a".to_string(),
+            vu32: 1,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        db.flush_wal().await.unwrap();
+        drop(db);
+
+        assert!(std::fs::read_dir(wal_dir.path()).unwrap().count() > 0);
+        assert!(!temp_dir.path().join("wal").exists());
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get(&"a".to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap(),
+            Some(1)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_recover_torn_wal() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+        for key in ["a", "b"] {
+            db.insert(Test {
+                vstring: key.to_string(),
+                vu32: 1,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+            db.flush_wal().await.unwrap();
+        }
+        drop(db);
+
+        // the log of `b` is cut by a crash while it was written
+        let segment = std::fs::read_dir(temp_dir.path().join("wal"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .max()
+            .unwrap();
+        let bytes = std::fs::read(&segment).unwrap();
+        std::fs::write(&segment, &bytes[..bytes.len() - 2]).unwrap();
+
+        // the commit of the torn log did not return, even when commits wait for their logs
+        let db: DB<Test, TokioExecutor> = DB::new(
+            option.wal_sync_mode(WalSyncMode::Commit),
+            TokioExecutor::current(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+        let get = |key: &'static str| {
+            let db = &db;
+            async move {
+                db.get(&key.to_string(), |entry| entry.get().vu32)
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(get("a").await, Some(1));
+        assert_eq!(get("b").await, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_write_stall() {
+        let temp_dir = TempDir::new().unwrap();
+        let write_stall = WriteStall {
+            max_immutables: usize::MAX,
+            max_level0_tables: 1,
+            policy: StallPolicy::Reject,
+        };
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .write_stall(write_stall);
+        let db: DB<Test, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+        let record = |key: &str| Test {
+            vstring: key.to_string(),
+            vu32: 0,
+            vbool: None,
+        };
+
+        for key in ["a", "b"] {
+            db.insert(record(key)).await.unwrap();
+            db.flush().await.unwrap();
+        }
+        // level 0 holds two tables
+        assert!(matches!(
+            db.insert(record("c")).await,
+            Err(CommitError::Database(DbError::Busy))
+        ));
+        assert!(matches!(
+            db.remove("a".to_string()).await,
+            Err(CommitError::Database(DbError::Busy))
+        ));
+        drop(db);
+
+        let option = option.write_stall(WriteStall {
+            policy: StallPolicy::Delay(Duration::from_millis(100)),
+            ..write_stall
+        });
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        let start = Instant::now();
+        db.insert(record("c")).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        let vu32 = db.get(&"c".to_string(), |e| e.get().vu32).await.unwrap();
+        assert_eq!(vu32, Some(0));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_flush_policy() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .flush_policy(FlushPolicy {
+            max_rows: Some(2),
+            max_age: Some(Duration::from_millis(200)),
+            max_immutables: Some(0),
+            ..Default::default()
+        });
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        let record = |key: &str| Test {
+            vstring: key.to_string(),
+            vu32: 0,
+            vbool: None,
+        };
+
+        db.insert(record("a")).await.unwrap();
+        db.insert(record("b")).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(db.ctx.version_set.current().await.tables_len(0), 1);
+
+        // flushed in the background once it is old enough
+        db.insert(record("c")).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert_eq!(db.ctx.version_set.current().await.tables_len(0), 2);
+        let vu32 = db.get(&"c".to_string(), |e| e.get().vu32).await.unwrap();
+        assert_eq!(vu32, Some(0));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_put_if() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        let record = |vu32: u32| Test {
+            vstring: "key".to_string(),
+            vu32,
+            vbool: None,
+        };
+        // compare the counter with `expected` and return the current one otherwise
+        let expected = |expected: Option<u32>| {
+            move |current: Option<TestRef<'_>>| {
+                let vu32 = current.and_then(|record| record.vu32);
+                if vu32 == expected {
+                    Ok(())
+                } else {
+                    Err(vu32)
+                }
+            }
+        };
+
+        assert_eq!(db.put_if(record(1), expected(None)).await.unwrap(), Ok(()));
+        assert_eq!(
+            db.put_if(record(2), expected(None)).await.unwrap(),
+            Err(Some(1))
+        );
+        db.flush().await.unwrap();
+        assert_eq!(
+            db.put_if(record(2), expected(Some(1))).await.unwrap(),
+            Ok(())
+        );
+        assert_eq!(
+            db.put_if(record(3), expected(Some(1))).await.unwrap(),
+            Err(Some(2))
+        );
+        let vu32 = db.get(&"key".to_string(), |e| e.get().vu32).await.unwrap();
+        assert_eq!(vu32, Some(2));
+
+        db.remove("key".to_string()).await.unwrap();
+        assert_eq!(db.put_if(record(4), expected(None)).await.unwrap(), Ok(()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_run_txn() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .transaction_retry(TransactionRetry {
+            max_attempts: 3,
+            backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        });
+        let db: Arc<DB<Test, TokioExecutor>> = Arc::new(
+            DB::new(option, TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap(),
+        );
+        let record = |vu32: u32| Test {
+            vstring: "key".to_string(),
+            vu32,
+            vbool: None,
+        };
+        // increment the counter, racing it with a write in the first `races` attempts
+        let increment = |races: usize| {
+            let db = db.clone();
+            let attempts = Arc::new(AtomicUsize::new(0));
+            let run = {
+                let attempts = attempts.clone();
+                async move {
+                    db.run_txn(|txn| {
+                        let db = db.clone();
+                        let attempts = attempts.clone();
+                        Box::pin(async move {
+                            let vu32 = txn
+                                .get(&"key".to_string(), Projection::All)
+                                .await?
+                                .and_then(|entry| entry.get().vu32)
+                                .unwrap_or(0);
+                            if attempts.fetch_add(1, Ordering::SeqCst) < races {
+                                db.insert(record(vu32 + 10)).await?;
+                            }
+                            txn.insert(record(vu32 + 1));
+                            Ok(vu32 + 1)
+                        })
+                    })
+                    .await
+                }
+            };
+            async move { (run.await, attempts.load(Ordering::SeqCst)) }
+        };
+
+        let (vu32, attempts) = increment(0).await;
+        assert_eq!((vu32.unwrap(), attempts), (1, 1));
+        let (vu32, attempts) = increment(1).await;
+        assert_eq!((vu32.unwrap(), attempts), (12, 2));
+        let (vu32, attempts) = increment(3).await;
+        assert!(matches!(vu32, Err(CommitError::WriteConflict(_))));
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_get_many() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 1;
+        option.major_threshold_with_sst_size = 3;
+        option.major_default_oldest_table_num = 1;
+        option.trigger_type = TriggerType::Length(5);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        for (idx, item) in test_items().into_iter().enumerate() {
+            if idx % 3 == 0 {
+                db.remove(item.vstring).await.unwrap();
+            } else {
+                db.write(item, 0.into()).await.unwrap();
+            }
+        }
+        db.flush().await.unwrap();
+        db.insert(Test {
+            vstring: 1.to_string(),
+            vu32: 100,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+
+        let keys = [31, 4, 3, 1, 40, 4, 20]
+            .into_iter()
+            .map(|i: i32| i.to_string())
+            .collect::<Vec<_>>();
+        let values = db
+            .get_many(&keys, |e| Some(e.get().vu32.unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(
+            values,
+            vec![Some(31), Some(4), None, Some(100), None, Some(4), Some(20)]
+        );
+        for (key, value) in keys.iter().zip(values) {
+            let expected = db.get(key, |e| Some(e.get().vu32.unwrap())).await.unwrap();
+            assert_eq!(value, expected);
+        }
+
+        let mut txn = db.transaction().await;
+        txn.insert(Test {
+            vstring: 31.to_string(),
+            vu32: 310,
+            vbool: None,
+        });
+        let entries = txn
+            .get_many(&keys[..4], Projection::Parts(vec!["vu32"]))
+            .await
+            .unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| entry.as_ref().map(|entry| entry.get().vu32.unwrap()))
+                .collect::<Vec<_>>(),
+            vec![Some(310), Some(4), None, Some(100)]
+        );
+        assert!(entries
+            .iter()
+            .flatten()
+            .all(|entry| entry.get().vbool.is_none()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_union_scan() {
+        let temp_dir_1 = TempDir::new().unwrap();
+        let temp_dir_2 = TempDir::new().unwrap();
+        let db_1: DB<Test, TokioExecutor> = DB::new(
+            DbOption::new(
+                Path::from_filesystem_path(temp_dir_1.path()).unwrap(),
+                &TestSchema,
+            ),
+            TokioExecutor::current(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+        let db_2: DB<Test, TokioExecutor> = DB::new(
+            DbOption::new(
+                Path::from_filesystem_path(temp_dir_2.path()).unwrap(),
+                &TestSchema,
+            ),
+            TokioExecutor::current(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+
+        for i in 0..10 {
+            let db = if i % 2 == 0 { &db_1 } else { &db_2 };
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db_1.flush().await.unwrap();
+        db_1.remove(4.to_string()).await.unwrap();
+        db_2.insert(Test {
+            vstring: 0.to_string(),
+            vu32: 100,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+
+        let upper = 8.to_string();
+        let dbs = [&db_1, &db_2];
+        let values =
+            crate::union_scan(&dbs, (Bound::Unbounded, Bound::Excluded(&upper)), |entry| {
+                let record = entry.get();
+                (record.vstring.to_string(), record.vu32.unwrap())
+            })
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            values,
+            vec![
+                (0.to_string(), 0),
+                (0.to_string(), 100),
+                (1.to_string(), 1),
+                (2.to_string(), 2),
+                (3.to_string(), 3),
+                (5.to_string(), 5),
+                (6.to_string(), 6),
+                (7.to_string(), 7),
+            ]
+        );
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_read_write_dyn() {
         let temp_dir = TempDir::new().unwrap();
@@ -1994,6 +4041,46 @@ pub(crate) mod tests {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dyn_scan_borrow_values() {
+        let temp_dir = TempDir::new().unwrap();
+        let dyn_schema = test_dyn_item_schema();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &dyn_schema,
+        );
+        let db: DB<DynRecord, TokioExecutor> =
+            DB::new(option, TokioExecutor::current(), test_dyn_item_schema())
+                .await
+                .unwrap();
+        let mut items = test_dyn_items().into_iter();
+        for item in items.by_ref().take(40) {
+            db.insert(item).await.unwrap();
+        }
+        db.flush().await.unwrap();
+        // the records of the mutable memtable are copied
+        for item in items {
+            db.insert(item).await.unwrap();
+        }
+
+        let tx = db.transaction().await;
+        let mut scan = tx
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .borrow_values()
+            .take()
+            .await
+            .unwrap();
+        let mut i = 0_i32;
+        while let Some(entry) = scan.next().await.transpose().unwrap() {
+            let record = entry.value().unwrap();
+            assert_eq!(record.str(4), Some(i.to_string().as_str()));
+            assert_eq!(record.str(5), Some(format!("{}@tonbo.io", i).as_str()));
+            assert_eq!(record.bytes(7), Some(i.to_le_bytes().as_slice()));
+            i += 1;
+        }
+        assert_eq!(i, 50);
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_dyn_multiple_db() {
         let temp_dir1 = TempDir::with_prefix("db1").unwrap();
@@ -1,2 +1,3 @@
+pub const NULL: &str = "_null";
 pub const TS: &str = "_ts";
 pub(crate) const USER_COLUMN_OFFSET: usize = 2;
@@ -0,0 +1,336 @@
+use std::{
+    fmt::{Debug, Formatter},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+use flume::Sender;
+
+use crate::{
+    compaction::CompactTask,
+    executor::Executor,
+    record::{Record, Schema},
+    DB,
+};
+
+/// Bytes held in memory by a [`DB`], see [`DB::memory_usage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// keys and values written to the mutable memtable
+    pub mutable: usize,
+    /// Arrow arrays of the immutable memtables waiting to be flushed
+    pub immutables: usize,
+    /// blocks of the block cache, 0 without
+    /// [`DbOption::block_cache`](crate::DbOption::block_cache)
+    pub block_cache: u64,
+    /// footers of the footer cache, 0 without
+    /// [`DbOption::metadata_cache`](crate::DbOption::metadata_cache)
+    pub metadata_cache: u64,
+    /// record batches decoded from the SSTables by the scans in flight
+    pub scans: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> u64 {
+        (self.mutable + self.immutables + self.scans) as u64
+            + self.block_cache
+            + self.metadata_cache
+    }
+}
+
+/// A limit of the bytes of the memtables of every [`DB`] sharing it, e.g. all the tables of
+/// [`Tables`](crate::tables::Tables), see
+/// [`DbOption::memory_budget`](crate::DbOption::memory_budget).
+///
+/// Once the memtables of the [`DB`]s exceed the budget, the [`DB`] holding the most of them is
+/// asked to freeze its mutable memtable and flush all of its immutable memtables, before the
+/// thresholds of its own [`DbOption`](crate::DbOption) are reached.
+///
+/// ```no_run
+/// use fusio::path::Path;
+/// use tonbo::{dyn_schema, executor::tokio::TokioExecutor, memory::MemoryBudget, tables::Tables};
+///
+/// # async fn open() {
+/// let budget = MemoryBudget::new(256 * 1024 * 1024);
+/// let tables = Tables::builder(
+///     Path::from_filesystem_path("./db_path").unwrap(),
+///     TokioExecutor::current(),
+/// )
+/// .table("users", dyn_schema!(("id", Int64, false), 0), {
+///     let budget = budget.clone();
+///     move |option| option.memory_budget(budget)
+/// })
+/// .table("events", dyn_schema!(("id", UInt64, false), 0), {
+///     let budget = budget.clone();
+///     move |option| option.memory_budget(budget)
+/// })
+/// .build()
+/// .await
+/// .unwrap();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct MemoryBudget {
+    inner: Arc<BudgetInner>,
+}
+
+struct BudgetInner {
+    limit: usize,
+    members: Mutex<Vec<Weak<MemTableUsage>>>,
+}
+
+impl Debug for MemoryBudget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryBudget")
+            .field("limit", &self.inner.limit)
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+impl MemoryBudget {
+    /// a budget of `limit` bytes
+    pub fn new(limit: usize) -> Self {
+        Self {
+            inner: Arc::new(BudgetInner {
+                limit,
+                members: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.inner.limit
+    }
+
+    /// bytes of the memtables of the open [`DB`]s sharing the budget
+    pub fn used(&self) -> usize {
+        let mut members = self.inner.members.lock().unwrap();
+        members.retain(|member| member.strong_count() > 0);
+        members
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|member| member.total())
+            .sum()
+    }
+
+    pub(crate) fn is_exceeded(&self) -> bool {
+        self.used() > self.inner.limit
+    }
+
+    pub(crate) fn register(&self, member: &Arc<MemTableUsage>) {
+        self.inner
+            .members
+            .lock()
+            .unwrap()
+            .push(Arc::downgrade(member));
+    }
+
+    /// ask the [`DB`] holding the most bytes to flush once the budget is exceeded
+    pub(crate) fn enforce(&self) {
+        let largest = {
+            let mut members = self.inner.members.lock().unwrap();
+            members.retain(|member| member.strong_count() > 0);
+            let members = members.iter().filter_map(Weak::upgrade).collect::<Vec<_>>();
+            if members.iter().map(|member| member.total()).sum::<usize>() <= self.inner.limit {
+                return;
+            }
+            members.into_iter().max_by_key(|member| member.total())
+        };
+        if let Some(largest) = largest {
+            largest.request_flush();
+        }
+    }
+}
+
+/// the bytes of the memtables of a [`DB`] counted by its [`MemoryBudget`]
+pub(crate) struct MemTableUsage {
+    mutable: AtomicUsize,
+    immutables: AtomicUsize,
+    /// a flush is asked for and the compaction task has not handled it yet
+    flush_requested: AtomicBool,
+    compaction_tx: Sender<CompactTask>,
+}
+
+impl MemTableUsage {
+    pub(crate) fn new(compaction_tx: Sender<CompactTask>) -> Self {
+        Self {
+            mutable: AtomicUsize::new(0),
+            immutables: AtomicUsize::new(0),
+            flush_requested: AtomicBool::new(false),
+            compaction_tx,
+        }
+    }
+
+    fn total(&self) -> usize {
+        self.mutable.load(Ordering::Relaxed) + self.immutables.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_mutable(&self, bytes: usize) {
+        self.mutable.store(bytes, Ordering::Relaxed);
+    }
+
+    /// the memtables are changed by the compaction task, a flush may be asked for again
+    pub(crate) fn set(&self, mutable: usize, immutables: usize) {
+        self.mutable.store(mutable, Ordering::Relaxed);
+        self.immutables.store(immutables, Ordering::Relaxed);
+        self.flush_requested.store(false, Ordering::Release);
+    }
+
+    fn request_flush(&self) {
+        if !self.flush_requested.swap(true, Ordering::AcqRel)
+            && self.compaction_tx.try_send(CompactTask::Freeze).is_err()
+        {
+            self.flush_requested.store(false, Ordering::Release);
+        }
+    }
+}
+
+/// The bytes of the record batches held by the scans in flight.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MemoryTracker(Arc<AtomicUsize>);
+
+impl MemoryTracker {
+    pub(crate) fn reserve(&self, bytes: usize) -> Reservation {
+        self.0.fetch_add(bytes, Ordering::Relaxed);
+        Reservation {
+            tracker: self.clone(),
+            bytes,
+        }
+    }
+
+    pub(crate) fn used(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// bytes counted by a [`MemoryTracker`] until dropped
+#[derive(Debug)]
+pub(crate) struct Reservation {
+    tracker: MemoryTracker,
+    bytes: usize,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.tracker.0.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// The bytes held in memory by the memtables, the caches and the scans in flight of
+    /// the [`DB`]. The memtables are counted by the sizes of their keys and values, and of the
+    /// Arrow arrays once frozen, without the overhead of their indexes.
+    pub async fn memory_usage(&self) -> MemoryUsage {
+        let (mutable, immutables) = {
+            let schema = self.schema.read().await;
+            (schema.mutable.size(), schema.immutables_size())
+        };
+        MemoryUsage {
+            mutable,
+            immutables,
+            block_cache: self.block_cache_stats().map_or(0, |stats| stats.size),
+            metadata_cache: self.metadata_cache_stats().map_or(0, |stats| stats.size),
+            scans: self.ctx.scan_memory().used(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{ops::Bound, time::Duration};
+
+    use fusio::path::Path;
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, memory::MemoryBudget,
+        tests::Test, DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn memory_usage() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        let record = |key: String| Test {
+            vstring: key,
+            vu32: 0,
+            vbool: None,
+        };
+        for i in 0..100 {
+            db.insert(record(format!("key{i:03}"))).await.unwrap();
+        }
+        let usage = db.memory_usage().await;
+        assert!(usage.mutable > 0);
+        assert_eq!(usage.immutables, 0);
+        assert_eq!(usage.scans, 0);
+
+        db.flush().await.unwrap();
+        assert_eq!(db.memory_usage().await.mutable, 0);
+
+        let txn = db.transaction().await;
+        {
+            let mut scan = txn
+                .scan((Bound::Unbounded, Bound::Unbounded))
+                .take()
+                .await
+                .unwrap();
+            scan.next().await.unwrap().unwrap();
+            assert!(db.memory_usage().await.scans > 0);
+        }
+        assert_eq!(db.memory_usage().await.scans, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn memory_budget() {
+        let temp_dir = TempDir::new().unwrap();
+        let budget = MemoryBudget::new(1024);
+        let open = |name: &str| {
+            let path = temp_dir.path().join(name);
+            std::fs::create_dir_all(&path).unwrap();
+            DB::<Test, TokioExecutor>::new(
+                DbOption::new(Path::from_filesystem_path(path).unwrap(), &TestSchema)
+                    .memory_budget(budget.clone()),
+                TokioExecutor::current(),
+                TestSchema,
+            )
+        };
+        let a = open("a").await.unwrap();
+        let b = open("b").await.unwrap();
+        let record = |key: String| Test {
+            vstring: key,
+            vu32: 0,
+            vbool: None,
+        };
+        for i in 0..40 {
+            a.insert(record(format!("key{i:03}"))).await.unwrap();
+        }
+        assert!(budget.used() <= budget.limit());
+
+        // the writes of `b` exceeding the budget flush the memtables of `a` holding the most of it
+        let mut i = 0;
+        while a.memory_usage().await.mutable > 0 {
+            b.insert(record(format!("b{i:03}"))).await.unwrap();
+            i += 1;
+            assert!(i < 100);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(a.memory_usage().await.immutables, 0);
+        assert!(b.memory_usage().await.mutable > 0);
+        assert!(budget.used() <= budget.limit());
+    }
+}
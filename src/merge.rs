@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use parquet_lru::NoCache;
+
+use crate::{
+    executor::Executor,
+    record::{Record, Schema},
+    transaction::CommitError,
+    DbError, DbOption, DB,
+};
+
+/// Combines the versions of a key of a [`DB`] opened with [`DB::with_merge_operator`].
+///
+/// The operator must be associative: versions are combined lazily when the key is read, and
+/// compactions combine any run of consecutive versions ahead of time.
+pub trait MergeOperator<R>: Send + Sync
+where
+    R: Record,
+{
+    /// combine `operand` with `existing`, the result of the older versions of the key
+    fn merge(&self, existing: R::Ref<'_>, operand: R::Ref<'_>) -> R;
+}
+
+impl<R, F> MergeOperator<R> for F
+where
+    R: Record,
+    F: for<'e, 'o> Fn(R::Ref<'e>, R::Ref<'o>) -> R + Send + Sync,
+{
+    fn merge(&self, existing: R::Ref<'_>, operand: R::Ref<'_>) -> R {
+        self(existing, operand)
+    }
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Open [`DB`] like [`DB::new`], with every write of a key being an operand of `operator`.
+    ///
+    /// Reads return the versions of a key combined from the oldest one, up to the latest removal
+    /// of the key. Inserts are operands as well, remove a key to reset it. Operands are combined
+    /// from whole records, so projections are not pushed down to the SSTables.
+    pub async fn with_merge_operator(
+        option: DbOption,
+        executor: E,
+        schema: R::Schema,
+        operator: impl MergeOperator<R> + 'static,
+    ) -> Result<Self, DbError<R>> {
+        Self::build(
+            Arc::new(option),
+            executor,
+            schema,
+            Arc::new(NoCache::default()),
+            Some(Arc::new(operator)),
+            None,
+            None,
+        )
+        .await
+    }
+
+    /// append `operand` to the versions of its key, without reading them
+    ///
+    /// # Error
+    /// Returns [`DbError::MissingMergeOperator`] if the [`DB`] was not opened with
+    /// [`DB::with_merge_operator`].
+    pub async fn merge(&self, operand: R) -> Result<(), CommitError<R>> {
+        if self.ctx.merge_operator().is_none() {
+            return Err(DbError::MissingMergeOperator.into());
+        }
+        self.insert(operand).await
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor,
+        inmem::immutable::tests::TestSchema,
+        tests::{Test, TestRef},
+        transaction::CommitError,
+        trigger::TriggerType,
+        DbError, DbOption, Projection, DB,
+    };
+
+    fn counter(key: &str, vu32: u32) -> Test {
+        Test {
+            vstring: key.to_string(),
+            vu32,
+            vbool: None,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn merge_operator() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        option.major_threshold_with_sst_size = 2;
+        option.level_sst_magnification = 1;
+        option.major_default_oldest_table_num = 1;
+        option.trigger_type = TriggerType::Length(5);
+
+        let sum = |existing: TestRef<'_>, operand: TestRef<'_>| Test {
+            vstring: operand.vstring.to_string(),
+            vu32: existing.vu32.unwrap_or(0) + operand.vu32.unwrap_or(0),
+            vbool: operand.vbool.or(existing.vbool),
+        };
+        let db: DB<Test, TokioExecutor> =
+            DB::with_merge_operator(option, TokioExecutor::current(), TestSchema, sum)
+                .await
+                .unwrap();
+
+        for i in 1..=4 {
+            db.merge(counter("a", i)).await.unwrap();
+            db.merge(counter("b", 10)).await.unwrap();
+            // operands are combined across the memtables, the SSTables and their compactions
+            db.flush().await.unwrap();
+        }
+        db.merge(counter("a", 100)).await.unwrap();
+        db.remove("b".to_string()).await.unwrap();
+        db.merge(counter("b", 1)).await.unwrap();
+
+        let version = db.ctx.version_set().current().await;
+        assert!(version.level_slice[1..]
+            .iter()
+            .any(|scopes| !scopes.is_empty()));
+        drop(version);
+
+        let vu32 = db.get(&"a".to_string(), |e| e.get().vu32).await.unwrap();
+        assert_eq!(vu32, Some(110));
+        // removing a key resets it
+        let vu32 = db.get(&"b".to_string(), |e| e.get().vu32).await.unwrap();
+        assert_eq!(vu32, Some(1));
+
+        let mut txn = db.transaction().await;
+        txn.insert(counter("a", 1000));
+        let vu32 = txn
+            .get(&"a".to_string(), Projection::All)
+            .await
+            .unwrap()
+            .unwrap()
+            .get()
+            .vu32;
+        assert_eq!(vu32, Some(1110));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn merge_without_operator() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            db.merge(counter("a", 1)).await,
+            Err(CommitError::Database(DbError::MissingMergeOperator))
+        ));
+    }
+}
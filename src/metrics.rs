@@ -0,0 +1,506 @@
+//! Counters and latency histograms of a [`DB`], read with [`DB::metrics`].
+//!
+//! [`MetricsSnapshot::encode_prometheus`] renders a snapshot in the Prometheus text exposition
+//! format, to serve it on a scrape endpoint. With the `prometheus` feature the counters and
+//! histograms are also recorded into a [`prometheus::Registry`], see [`DB::register_metrics`].
+
+use std::{
+    fmt::Write as _,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    cache::block::BlockCacheStats,
+    executor::Executor,
+    record::{Record, Schema},
+    version::MAX_LEVEL,
+    DB,
+};
+
+/// the upper bounds of the buckets of the latency histograms, in seconds
+const LATENCY_BUCKETS: [f64; 14] = [
+    0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0, 30.0,
+];
+
+/// a histogram of durations over [`LATENCY_BUCKETS`]
+#[derive(Default)]
+pub(crate) struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| secs <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        HistogramSnapshot {
+            buckets: LATENCY_BUCKETS
+                .iter()
+                .zip(&self.buckets)
+                .map(|(bound, count)| {
+                    cumulative += count.load(Ordering::Relaxed);
+                    (*bound, cumulative)
+                })
+                .collect(),
+            count: self.count.load(Ordering::Relaxed),
+            sum: Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// The metrics recorded by a [`DB`] since it is opened.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    writes: AtomicU64,
+    gets: Histogram,
+    scans: Histogram,
+    flushes: Histogram,
+    compactions: Histogram,
+    #[cfg(feature = "prometheus")]
+    prometheus: std::sync::OnceLock<prometheus_metrics::PrometheusMetrics>,
+}
+
+impl Metrics {
+    /// a record is inserted or removed
+    pub(crate) fn write(&self) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "prometheus")]
+        if let Some(prometheus) = self.prometheus.get() {
+            prometheus.writes.inc();
+        }
+    }
+
+    /// a get took `duration`
+    pub(crate) fn get(&self, duration: Duration) {
+        self.gets.observe(duration);
+        #[cfg(feature = "prometheus")]
+        if let Some(prometheus) = self.prometheus.get() {
+            prometheus.gets.observe(duration.as_secs_f64());
+        }
+    }
+
+    /// a scan took `duration` to open
+    pub(crate) fn scan(&self, duration: Duration) {
+        self.scans.observe(duration);
+        #[cfg(feature = "prometheus")]
+        if let Some(prometheus) = self.prometheus.get() {
+            prometheus.scans.observe(duration.as_secs_f64());
+        }
+    }
+
+    /// a flush took `duration`
+    pub(crate) fn flush(&self, duration: Duration) {
+        self.flushes.observe(duration);
+        #[cfg(feature = "prometheus")]
+        if let Some(prometheus) = self.prometheus.get() {
+            prometheus.flushes.observe(duration.as_secs_f64());
+        }
+    }
+
+    /// a major compaction took `duration`
+    pub(crate) fn compaction(&self, duration: Duration) {
+        self.compactions.observe(duration);
+        #[cfg(feature = "prometheus")]
+        if let Some(prometheus) = self.prometheus.get() {
+            prometheus.compactions.observe(duration.as_secs_f64());
+        }
+    }
+}
+
+/// The counts and sum of the durations observed by a histogram.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistogramSnapshot {
+    /// the upper bounds of the buckets in seconds with the number of durations up to them
+    pub buckets: Vec<(f64, u64)>,
+    /// number of durations observed
+    pub count: u64,
+    pub sum: Duration,
+}
+
+/// The metrics of a [`DB`] at a point in time, from [`DB::metrics`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsSnapshot {
+    /// records inserted or removed since the [`DB`] is opened
+    pub writes: u64,
+    /// latencies of the gets, of [`DB::get`], snapshots and transactions
+    pub gets: HistogramSnapshot,
+    /// latencies of opening the scans, up to their first entry being ready
+    pub scans: HistogramSnapshot,
+    /// durations of the flushes of immutable memtables into level 0
+    pub flushes: HistogramSnapshot,
+    /// durations of the major compactions
+    pub compactions: HistogramSnapshot,
+    /// entries of the mutable memtable
+    pub mutable_entries: usize,
+    /// immutable memtables waiting to be flushed
+    pub immutable_memtables: usize,
+    /// rows of the immutable memtables
+    pub immutable_rows: usize,
+    /// number of SSTables of every level
+    pub level_tables: [usize; MAX_LEVEL],
+    /// statistics of the block cache, `None` without
+    /// [`DbOption::block_cache`](crate::DbOption::block_cache)
+    pub block_cache: Option<BlockCacheStats>,
+}
+
+impl MetricsSnapshot {
+    /// Render the snapshot in the Prometheus text exposition format, every metric named with the
+    /// `tonbo_` prefix.
+    pub fn encode_prometheus(&self) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, ty: &str, help: &str, samples: &[(String, String)]| {
+            let _ = writeln!(text, "# HELP tonbo_{name} {help}");
+            let _ = writeln!(text, "# TYPE tonbo_{name} {ty}");
+            for (suffix, value) in samples {
+                let _ = writeln!(text, "tonbo_{name}{suffix} {value}");
+            }
+        };
+        let histogram = |histogram: &HistogramSnapshot| {
+            let mut samples = histogram
+                .buckets
+                .iter()
+                .map(|(bound, count)| (format!("_bucket{{le=\"{bound}\"}}"), count.to_string()))
+                .collect::<Vec<_>>();
+            samples.push(("_bucket{le=\"+Inf\"}".into(), histogram.count.to_string()));
+            samples.push(("_sum".into(), histogram.sum.as_secs_f64().to_string()));
+            samples.push(("_count".into(), histogram.count.to_string()));
+            samples
+        };
+
+        metric(
+            "writes_total",
+            "counter",
+            "Records inserted or removed.",
+            &[(String::new(), self.writes.to_string())],
+        );
+        metric(
+            "get_duration_seconds",
+            "histogram",
+            "Latencies of the gets.",
+            &histogram(&self.gets),
+        );
+        metric(
+            "scan_duration_seconds",
+            "histogram",
+            "Latencies of opening the scans.",
+            &histogram(&self.scans),
+        );
+        metric(
+            "flush_duration_seconds",
+            "histogram",
+            "Durations of the flushes of immutable memtables.",
+            &histogram(&self.flushes),
+        );
+        metric(
+            "compaction_duration_seconds",
+            "histogram",
+            "Durations of the major compactions.",
+            &histogram(&self.compactions),
+        );
+        metric(
+            "mutable_entries",
+            "gauge",
+            "Entries of the mutable memtable.",
+            &[(String::new(), self.mutable_entries.to_string())],
+        );
+        metric(
+            "immutable_memtables",
+            "gauge",
+            "Immutable memtables waiting to be flushed.",
+            &[(String::new(), self.immutable_memtables.to_string())],
+        );
+        metric(
+            "immutable_rows",
+            "gauge",
+            "Rows of the immutable memtables.",
+            &[(String::new(), self.immutable_rows.to_string())],
+        );
+        metric(
+            "level_tables",
+            "gauge",
+            "SSTables of every level.",
+            &self
+                .level_tables
+                .iter()
+                .enumerate()
+                .map(|(level, tables)| (format!("{{level=\"{level}\"}}"), tables.to_string()))
+                .collect::<Vec<_>>(),
+        );
+        if let Some(block_cache) = &self.block_cache {
+            metric(
+                "block_cache_hits_total",
+                "counter",
+                "Lookups served by the block cache.",
+                &[(String::new(), block_cache.hits.to_string())],
+            );
+            metric(
+                "block_cache_misses_total",
+                "counter",
+                "Lookups of the block cache read from the tables.",
+                &[(String::new(), block_cache.misses.to_string())],
+            );
+        }
+        text
+    }
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// A snapshot of the counters and latencies recorded since the [`DB`] is opened, along with
+    /// the sizes of the memtables, the number of SSTables of every level and the statistics of
+    /// the block cache.
+    pub async fn metrics(&self) -> MetricsSnapshot {
+        let metrics = self.ctx.metrics();
+        let (mutable_entries, immutable_memtables, immutable_rows) = {
+            let schema = self.schema.read().await;
+            (
+                schema.mutable.len(),
+                schema.immutables.len(),
+                schema
+                    .immutables
+                    .iter()
+                    .map(|(_, immutable)| immutable.as_record_batch().num_rows())
+                    .sum(),
+            )
+        };
+        let version = self.ctx.version_set.current().await;
+        let mut level_tables = [0; MAX_LEVEL];
+        for (level, tables) in level_tables.iter_mut().enumerate() {
+            *tables = version.tables_len(level);
+        }
+        let snapshot = MetricsSnapshot {
+            writes: metrics.writes.load(Ordering::Relaxed),
+            gets: metrics.gets.snapshot(),
+            scans: metrics.scans.snapshot(),
+            flushes: metrics.flushes.snapshot(),
+            compactions: metrics.compactions.snapshot(),
+            mutable_entries,
+            immutable_memtables,
+            immutable_rows,
+            level_tables,
+            block_cache: self.block_cache_stats(),
+        };
+        #[cfg(feature = "prometheus")]
+        if let Some(prometheus) = metrics.prometheus.get() {
+            prometheus.set_gauges(&snapshot);
+        }
+        snapshot
+    }
+
+    /// Record the counters and latencies of the [`DB`] into `registry` from now on, named like
+    /// in [`MetricsSnapshot::encode_prometheus`] and labeled with `table="<table>"`, so several
+    /// [`DB`]s can share a registry. The gauges, the sizes of the memtables and the tables of
+    /// every level, are set by every call of [`DB::metrics`], e.g. right before the registry is
+    /// gathered by a scrape.
+    ///
+    /// # Error
+    /// Fails if the metrics of the [`DB`] are registered already, into any registry, or if
+    /// another [`DB`] registered its metrics into `registry` with the same `table`.
+    #[cfg(feature = "prometheus")]
+    pub fn register_metrics(
+        &self,
+        registry: &prometheus::Registry,
+        table: &str,
+    ) -> prometheus::Result<()> {
+        let prometheus = prometheus_metrics::PrometheusMetrics::register(registry, table)?;
+        self.ctx
+            .metrics()
+            .prometheus
+            .set(prometheus)
+            .map_err(|_| prometheus::Error::Msg("the metrics are registered already".into()))
+    }
+}
+
+#[cfg(feature = "prometheus")]
+mod prometheus_metrics {
+    use prometheus::{Histogram, HistogramOpts, IntCounter, IntGauge, IntGaugeVec, Opts, Registry};
+
+    use super::{MetricsSnapshot, LATENCY_BUCKETS};
+
+    pub(super) struct PrometheusMetrics {
+        pub(super) writes: IntCounter,
+        pub(super) gets: Histogram,
+        pub(super) scans: Histogram,
+        pub(super) flushes: Histogram,
+        pub(super) compactions: Histogram,
+        mutable_entries: IntGauge,
+        immutable_memtables: IntGauge,
+        immutable_rows: IntGauge,
+        level_tables: IntGaugeVec,
+    }
+
+    impl PrometheusMetrics {
+        pub(super) fn register(registry: &Registry, table: &str) -> prometheus::Result<Self> {
+            let opts = |name: &str, help: &str| {
+                Opts::new(format!("tonbo_{name}"), help).const_label("table", table)
+            };
+            let histogram = |name: &str, help: &str| {
+                Histogram::with_opts(
+                    HistogramOpts::from(opts(name, help)).buckets(LATENCY_BUCKETS.to_vec()),
+                )
+            };
+            let gauge = |name: &str, help: &str| IntGauge::with_opts(opts(name, help));
+            let metrics = Self {
+                writes: IntCounter::with_opts(opts(
+                    "writes_total",
+                    "Records inserted or removed.",
+                ))?,
+                gets: histogram("get_duration_seconds", "Latencies of the gets.")?,
+                scans: histogram("scan_duration_seconds", "Latencies of opening the scans.")?,
+                flushes: histogram(
+                    "flush_duration_seconds",
+                    "Durations of the flushes of immutable memtables.",
+                )?,
+                compactions: histogram(
+                    "compaction_duration_seconds",
+                    "Durations of the major compactions.",
+                )?,
+                mutable_entries: gauge("mutable_entries", "Entries of the mutable memtable.")?,
+                immutable_memtables: gauge(
+                    "immutable_memtables",
+                    "Immutable memtables waiting to be flushed.",
+                )?,
+                immutable_rows: gauge("immutable_rows", "Rows of the immutable memtables.")?,
+                level_tables: IntGaugeVec::new(
+                    opts("level_tables", "SSTables of every level."),
+                    &["level"],
+                )?,
+            };
+            registry.register(Box::new(metrics.writes.clone()))?;
+            registry.register(Box::new(metrics.gets.clone()))?;
+            registry.register(Box::new(metrics.scans.clone()))?;
+            registry.register(Box::new(metrics.flushes.clone()))?;
+            registry.register(Box::new(metrics.compactions.clone()))?;
+            registry.register(Box::new(metrics.mutable_entries.clone()))?;
+            registry.register(Box::new(metrics.immutable_memtables.clone()))?;
+            registry.register(Box::new(metrics.immutable_rows.clone()))?;
+            registry.register(Box::new(metrics.level_tables.clone()))?;
+            Ok(metrics)
+        }
+
+        pub(super) fn set_gauges(&self, snapshot: &MetricsSnapshot) {
+            self.mutable_entries.set(snapshot.mutable_entries as i64);
+            self.immutable_memtables
+                .set(snapshot.immutable_memtables as i64);
+            self.immutable_rows.set(snapshot.immutable_rows as i64);
+            for (level, tables) in snapshot.level_tables.iter().enumerate() {
+                self.level_tables
+                    .with_label_values(&[&level.to_string()])
+                    .set(*tables as i64);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn metrics() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        for key in ["a", "b", "c"] {
+            db.insert(Test {
+                vstring: key.to_string(),
+                vu32: 0,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+        db.remove("c".to_string()).await.unwrap();
+        db.get(&"a".to_string(), |_| Some(())).await.unwrap();
+        db.get(&"d".to_string(), |_| Some(())).await.unwrap();
+
+        let metrics = db.metrics().await;
+        assert_eq!(metrics.writes, 4);
+        assert_eq!(metrics.gets.count, 2);
+        assert_eq!(metrics.gets.buckets.last().unwrap().1, 2);
+        assert_eq!(metrics.flushes.count, 1);
+        assert_eq!(metrics.mutable_entries, 1);
+        assert_eq!(metrics.level_tables[0], 1);
+
+        let text = metrics.encode_prometheus();
+        assert!(text.contains("tonbo_writes_total 4\n"));
+        assert!(text.contains("tonbo_get_duration_seconds_count 2\n"));
+        assert!(text.contains("tonbo_level_tables{level=\"0\"} 1\n"));
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn register_metrics_of_dbs() {
+        let registry = prometheus::Registry::new();
+        let mut dbs = Vec::new();
+        let mut temp_dirs = Vec::new();
+        for table in ["a", "b"] {
+            let temp_dir = TempDir::new().unwrap();
+            let option = DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            );
+            let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+            db.register_metrics(&registry, table).unwrap();
+            dbs.push(db);
+            temp_dirs.push(temp_dir);
+        }
+        dbs[1]
+            .insert(Test {
+                vstring: "a".to_string(),
+                vu32: 0,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+
+        let writes = registry
+            .gather()
+            .into_iter()
+            .find(|family| family.get_name() == "tonbo_writes_total")
+            .unwrap();
+        let mut writes = writes
+            .get_metric()
+            .iter()
+            .map(|metric| {
+                (
+                    metric.get_label()[0].get_value().to_string(),
+                    metric.get_counter().get_value(),
+                )
+            })
+            .collect::<Vec<_>>();
+        writes.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(writes, vec![("a".to_string(), 0.0), ("b".to_string(), 1.0)]);
+    }
+}
@@ -0,0 +1,466 @@
+use std::{error::Error, sync::Arc};
+
+use fusio::{SeqRead, Write};
+use fusio_log::{Decode, Encode};
+use futures_core::future::BoxFuture;
+use parquet_lru::NoCache;
+
+pub use crate::compaction::CompactionError;
+use crate::{
+    compaction::{
+        rate_limit::{self, RateLimiter},
+        Compactor,
+    },
+    context::CompactionContext,
+    encryption,
+    executor::Executor,
+    fs::{manager::StoreManager, FileId},
+    merge::MergeOperator,
+    record::{Record, Schema},
+    retention::CompactionFilter,
+    scope::Scope,
+    timestamp::Timestamp,
+    version::edit::VersionEdit,
+    DbError, DbOption, ParquetLru, DB,
+};
+
+/// A major compaction shipped to a [`CompactionRunner`]: the tables to merge and the level the
+/// merged tables are written to.
+///
+/// Jobs are encoded with [`Encode`] to be sent to a [`CompactionWorker`], which answers with a
+/// [`CompactionOutput`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionJob<K> {
+    level: usize,
+    inputs: Vec<(usize, Scope<K>)>,
+    watermark: Timestamp,
+    older_tables: Option<Vec<Scope<K>>>,
+}
+
+impl<K> CompactionJob<K>
+where
+    K: Clone,
+{
+    pub(crate) fn new(
+        level: usize,
+        inputs: &[(usize, &Scope<K>)],
+        watermark: Timestamp,
+        older_tables: Option<&[&Scope<K>]>,
+    ) -> Self {
+        Self {
+            level,
+            inputs: inputs
+                .iter()
+                .map(|(level, scope)| (*level, (*scope).clone()))
+                .collect(),
+            watermark,
+            older_tables: older_tables
+                .map(|scopes| scopes.iter().map(|scope| (*scope).clone()).collect()),
+        }
+    }
+}
+
+impl<K> CompactionJob<K> {
+    /// the level the merged tables are written to
+    pub fn output_level(&self) -> usize {
+        self.level
+    }
+
+    /// the tables to merge with their level
+    pub fn inputs(&self) -> impl Iterator<Item = (usize, FileId)> + '_ {
+        self.inputs.iter().map(|(level, scope)| (*level, scope.gen))
+    }
+}
+
+/// The tables written by a [`CompactionJob`], in key order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionOutput<K> {
+    pub(crate) tables: Vec<Scope<K>>,
+}
+
+impl<K> CompactionOutput<K> {
+    pub fn tables(&self) -> impl Iterator<Item = FileId> + '_ {
+        self.tables.iter().map(|scope| scope.gen)
+    }
+}
+
+/// Runs the major compactions of a [`DB`] opened with [`DB::with_compaction_runner`], e.g. by
+/// sending the jobs to a [`CompactionWorker`] in another process.
+///
+/// The compactor of the [`DB`] waits for the output and applies it to the version like a local
+/// compaction, the runner only writes the tables. Tables written by a job that fails are not
+/// removed. Flushes of the memtables always run in the [`DB`].
+pub trait CompactionRunner<R>: Send + Sync
+where
+    R: Record,
+{
+    #[allow(clippy::type_complexity)]
+    fn run(
+        &self,
+        job: CompactionJob<<R::Schema as Schema>::Key>,
+    ) -> BoxFuture<
+        '_,
+        Result<CompactionOutput<<R::Schema as Schema>::Key>, Box<dyn Error + Send + Sync>>,
+    >;
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Open [`DB`] like [`DB::new`], with the major compactions merged by `runner`.
+    pub async fn with_compaction_runner(
+        option: DbOption,
+        executor: E,
+        schema: R::Schema,
+        runner: impl CompactionRunner<R> + 'static,
+    ) -> Result<Self, DbError<R>> {
+        Self::build(
+            Arc::new(option),
+            executor,
+            schema,
+            Arc::new(NoCache::default()),
+            None,
+            None,
+            Some(Arc::new(runner)),
+        )
+        .await
+    }
+}
+
+/// Merges [`CompactionJob`]s away from their [`DB`].
+///
+/// The worker reads and writes the SSTables through the file systems of its [`DbOption`], which
+/// must reach the same storage as the one of the [`DB`]. The merge operator and the compaction
+/// filter of the [`DB`] must be set on the worker as well.
+pub struct CompactionWorker<R>
+where
+    R: Record,
+{
+    option: DbOption,
+    schema: R::Schema,
+    ctx: CompactionContext<R>,
+}
+
+impl<R> CompactionWorker<R>
+where
+    R: Record,
+{
+    pub fn new(option: DbOption, schema: R::Schema) -> Result<Self, fusio::Error> {
+        let manager = Arc::new(StoreManager::new(
+            option.base_fs.clone(),
+            option.level_paths.clone(),
+        )?);
+        let mut lru_cache: ParquetLru = Arc::new(NoCache::default());
+        let rate_limiter = option
+            .background_rate_limit
+            .map(|limit| Arc::new(RateLimiter::with_limit(limit)));
+        let compaction_rate_limiter = option
+            .compaction_rate_limit
+            .map(|bytes_per_sec| Arc::new(RateLimiter::new(bytes_per_sec)));
+        for rate_limiter in [&rate_limiter, &compaction_rate_limiter]
+            .into_iter()
+            .flatten()
+        {
+            lru_cache = rate_limit::cache(&lru_cache, rate_limiter.clone());
+        }
+        if let Some(key_provider) = &option.encryption {
+            lru_cache = encryption::cache(&lru_cache, key_provider.clone())
+                .map_err(|err| fusio::Error::Other(Box::new(err)))?;
+        }
+        let ctx = CompactionContext::new(manager, lru_cache)
+            .with_background_rate_limiter(rate_limiter)
+            .with_rate_limiter(compaction_rate_limiter);
+
+        Ok(Self {
+            option,
+            schema,
+            ctx,
+        })
+    }
+
+    pub fn with_merge_operator(self, operator: impl MergeOperator<R> + 'static) -> Self {
+        Self {
+            ctx: self.ctx.with_merge_operator(Some(Arc::new(operator))),
+            ..self
+        }
+    }
+
+    pub fn with_compaction_filter(self, filter: impl CompactionFilter<R> + 'static) -> Self {
+        Self {
+            ctx: self.ctx.with_compaction_filter(Some(Arc::new(filter))),
+            ..self
+        }
+    }
+
+    /// merge the tables of `job`, split into [`DbOption::max_subcompactions`] sub-ranges of the
+    /// worker
+    pub async fn run(
+        &self,
+        job: &CompactionJob<<R::Schema as Schema>::Key>,
+    ) -> Result<CompactionOutput<<R::Schema as Schema>::Key>, CompactionError<R>> {
+        let inputs = job
+            .inputs
+            .iter()
+            .map(|(level, scope)| (*level, scope))
+            .collect::<Vec<_>>();
+        let older_tables = job
+            .older_tables
+            .as_ref()
+            .map(|scopes| scopes.iter().collect::<Vec<_>>());
+        let mut version_edits = Vec::new();
+        Compactor::<R>::build_subcompactions(
+            &self.option,
+            &self.ctx,
+            &mut version_edits,
+            job.level,
+            &inputs,
+            &self.schema,
+            job.watermark,
+            older_tables.as_deref(),
+        )
+        .await?;
+
+        Ok(CompactionOutput {
+            tables: version_edits
+                .into_iter()
+                .filter_map(|edit| match edit {
+                    VersionEdit::Add { scope, .. } => Some(scope),
+                    _ => None,
+                })
+                .collect(),
+        })
+    }
+}
+
+impl<K> Encode for CompactionJob<K>
+where
+    K: Encode + Sync,
+{
+    type Error = <K as Encode>::Error;
+
+    async fn encode<W>(&self, writer: &mut W) -> Result<(), Self::Error>
+    where
+        W: Write,
+    {
+        (self.level as u8).encode(writer).await?;
+        self.watermark.encode(writer).await?;
+        (self.inputs.len() as u32).encode(writer).await?;
+        for (level, scope) in self.inputs.iter() {
+            (*level as u8).encode(writer).await?;
+            scope.encode(writer).await?;
+        }
+        match &self.older_tables {
+            None => 0u8.encode(writer).await?,
+            Some(scopes) => {
+                1u8.encode(writer).await?;
+                (scopes.len() as u32).encode(writer).await?;
+                for scope in scopes {
+                    scope.encode(writer).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        let scopes = self
+            .inputs
+            .iter()
+            .map(|(_, scope)| scope)
+            .chain(self.older_tables.iter().flatten());
+        2 + self.watermark.size() + 8 + scopes.map(|scope| scope.size() + 1).sum::<usize>()
+    }
+}
+
+impl<K> Decode for CompactionJob<K>
+where
+    K: Decode + Send,
+{
+    type Error = <K as Decode>::Error;
+
+    async fn decode<R: SeqRead>(reader: &mut R) -> Result<Self, Self::Error> {
+        let level = u8::decode(reader).await? as usize;
+        let watermark = Timestamp::decode(reader).await?;
+        let len = u32::decode(reader).await? as usize;
+        let mut inputs = Vec::with_capacity(len);
+        for _ in 0..len {
+            let level = u8::decode(reader).await? as usize;
+            inputs.push((level, Scope::decode(reader).await?));
+        }
+        let older_tables = match u8::decode(reader).await? {
+            0 => None,
+            _ => {
+                let len = u32::decode(reader).await? as usize;
+                let mut scopes = Vec::with_capacity(len);
+                for _ in 0..len {
+                    scopes.push(Scope::decode(reader).await?);
+                }
+                Some(scopes)
+            }
+        };
+
+        Ok(Self {
+            level,
+            inputs,
+            watermark,
+            older_tables,
+        })
+    }
+}
+
+impl<K> Encode for CompactionOutput<K>
+where
+    K: Encode + Sync,
+{
+    type Error = <K as Encode>::Error;
+
+    async fn encode<W>(&self, writer: &mut W) -> Result<(), Self::Error>
+    where
+        W: Write,
+    {
+        (self.tables.len() as u32).encode(writer).await?;
+        for scope in self.tables.iter() {
+            scope.encode(writer).await?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        4 + self.tables.iter().map(Scope::size).sum::<usize>()
+    }
+}
+
+impl<K> Decode for CompactionOutput<K>
+where
+    K: Decode + Send,
+{
+    type Error = <K as Decode>::Error;
+
+    async fn decode<R: SeqRead>(reader: &mut R) -> Result<Self, Self::Error> {
+        let len = u32::decode(reader).await? as usize;
+        let mut tables = Vec::with_capacity(len);
+        for _ in 0..len {
+            tables.push(Scope::decode(reader).await?);
+        }
+
+        Ok(Self { tables })
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{
+        error::Error,
+        io::Cursor,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use fusio::path::Path;
+    use fusio_log::{Decode, Encode};
+    use futures_core::future::BoxFuture;
+    use tempfile::TempDir;
+    use tokio::io::AsyncSeekExt;
+
+    use super::{CompactionJob, CompactionOutput, CompactionRunner, CompactionWorker};
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test,
+        CompactionOption, DbOption, SizeRatioOption, DB,
+    };
+
+    /// send `value` through a buffer like a runner talking to another process
+    async fn transport<T>(value: &T) -> T
+    where
+        T: Encode + Decode,
+        <T as Encode>::Error: std::fmt::Debug,
+        <T as Decode>::Error: std::fmt::Debug,
+    {
+        let mut buf = Vec::new();
+        let mut cursor = Cursor::new(&mut buf);
+        value.encode(&mut cursor).await.unwrap();
+        cursor.seek(std::io::SeekFrom::Start(0)).await.unwrap();
+        T::decode(&mut cursor).await.unwrap()
+    }
+
+    struct WorkerRunner {
+        worker: CompactionWorker<Test>,
+        jobs: Arc<AtomicUsize>,
+    }
+
+    impl CompactionRunner<Test> for WorkerRunner {
+        fn run(
+            &self,
+            job: CompactionJob<String>,
+        ) -> BoxFuture<'_, Result<CompactionOutput<String>, Box<dyn Error + Send + Sync>>> {
+            Box::pin(async move {
+                self.jobs.fetch_add(1, Ordering::Relaxed);
+                let job = transport(&job).await;
+                let output = self.worker.run(&job).await?;
+                Ok(transport(&output).await)
+            })
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn compaction_runner() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = || {
+            let mut option = DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            )
+            .compaction_option(CompactionOption::SizeRatio(SizeRatioOption {
+                level0_table_num: 2,
+                ..Default::default()
+            }));
+            option.immutable_chunk_num = 1;
+            option.immutable_chunk_max_num = 0;
+            option
+        };
+        let jobs = Arc::new(AtomicUsize::new(0));
+        let runner = WorkerRunner {
+            worker: CompactionWorker::new(option(), TestSchema).unwrap(),
+            jobs: jobs.clone(),
+        };
+
+        let db: DB<Test, TokioExecutor> =
+            DB::with_compaction_runner(option(), TokioExecutor::current(), TestSchema, runner)
+                .await
+                .unwrap();
+        for (key, vu32) in [("a", 1), ("b", 1), ("a", 2)] {
+            db.insert(Test {
+                vstring: key.to_string(),
+                vu32,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+            db.flush().await.unwrap();
+        }
+
+        // the second flush compacts level 0 into level 1 through the runner
+        let version = db.ctx.version_set().current().await;
+        assert_eq!(version.tables_len(0), 1);
+        assert_eq!(version.tables_len(1), 1);
+        drop(version);
+        assert_eq!(jobs.load(Ordering::Relaxed), 1);
+
+        assert_eq!(
+            db.get(&"a".to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap(),
+            Some(2)
+        );
+        assert_eq!(
+            db.get(&"b".to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap(),
+            Some(1)
+        );
+    }
+}
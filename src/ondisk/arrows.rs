@@ -1,16 +1,24 @@
 use std::ops::Bound;
 
 use arrow::{
-    array::{BooleanArray, Datum},
+    array::{Array, ArrayRef, BooleanArray, Datum},
     buffer::BooleanBuffer,
-    compute::kernels::cmp::{gt, gt_eq, lt_eq},
+    compute::kernels::{
+        boolean::or,
+        cmp::{eq, gt, gt_eq, lt, lt_eq},
+    },
+    datatypes::Schema as ArrowSchema,
     error::ArrowError,
 };
 use parquet::{
     arrow::{
-        arrow_reader::{ArrowPredicate, ArrowPredicateFn, RowFilter},
+        arrow_reader::{
+            statistics::StatisticsConverter, ArrowPredicate, ArrowPredicateFn, RowFilter,
+            RowSelection, RowSelector,
+        },
         ProjectionMask,
     },
+    file::metadata::ParquetMetaData,
     schema::types::SchemaDescriptor,
 };
 
@@ -89,3 +97,136 @@ where
 
     RowFilter::new(predictions)
 }
+
+/// row filter selecting the versions of `keys` visible at `ts`
+pub(crate) unsafe fn get_keys_filter<R>(
+    schema_descriptor: &SchemaDescriptor,
+    keys: &[&<R::Schema as Schema>::Key],
+    ts: Timestamp,
+) -> RowFilter
+where
+    R: Record,
+{
+    let keys = keys
+        .iter()
+        .map(|key| &*(*key as *const <R::Schema as Schema>::Key))
+        .collect::<Vec<&'static <R::Schema as Schema>::Key>>();
+
+    let predictions: Vec<Box<dyn ArrowPredicate>> = vec![
+        Box::new(ArrowPredicateFn::new(
+            ProjectionMask::roots(schema_descriptor, [1]),
+            move |record_batch| lt_eq(record_batch.column(0), &ts.to_arrow_scalar() as &dyn Datum),
+        )),
+        Box::new(ArrowPredicateFn::new(
+            ProjectionMask::roots(schema_descriptor, [2]),
+            move |record_batch| {
+                let column = record_batch.column(0);
+                keys.iter().try_fold(
+                    BooleanArray::new(BooleanBuffer::new_unset(column.len()), None),
+                    |selected, key| or(&selected, &eq(column, key.to_arrow_datum().as_ref())?),
+                )
+            },
+        )),
+    ];
+
+    RowFilter::new(predictions)
+}
+
+/// The row groups whose primary key statistics overlap one of `ranges` and, when the table has a
+/// page index, the rows of their pages overlapping one of them. `None` when the statistics can't
+/// be compared with the keys, the whole table is read then.
+pub(crate) fn get_pages_selection<R>(
+    metadata: &ParquetMetaData,
+    full_schema: &ArrowSchema,
+    ranges: &[(
+        Bound<&<R::Schema as Schema>::Key>,
+        Bound<&<R::Schema as Schema>::Key>,
+    )],
+) -> Option<(Vec<usize>, Option<RowSelection>)>
+where
+    R: Record,
+{
+    let converter = StatisticsConverter::try_new(
+        full_schema.field(2).name(),
+        full_schema,
+        metadata.file_metadata().schema_descr(),
+    )
+    .ok()?;
+    let row_groups = overlapping::<R>(
+        &converter.row_group_mins(metadata.row_groups()).ok()?,
+        &converter.row_group_maxes(metadata.row_groups()).ok()?,
+        ranges,
+    )?
+    .into_iter()
+    .enumerate()
+    .filter_map(|(i, is_overlapping)| is_overlapping.then_some(i))
+    .collect::<Vec<_>>();
+
+    let pages = || {
+        let column_index = metadata.column_index()?;
+        let offset_index = metadata.offset_index()?;
+        let pages = overlapping::<R>(
+            &converter
+                .data_page_mins(column_index, offset_index, &row_groups)
+                .ok()?,
+            &converter
+                .data_page_maxes(column_index, offset_index, &row_groups)
+                .ok()?,
+            ranges,
+        )?;
+        let row_counts = converter
+            .data_page_row_counts(offset_index, metadata.row_groups(), &row_groups)
+            .ok()??;
+
+        Some(RowSelection::from(
+            pages
+                .into_iter()
+                .zip(row_counts.values().iter())
+                .map(|(is_overlapping, row_count)| match is_overlapping {
+                    true => RowSelector::select(*row_count as usize),
+                    false => RowSelector::skip(*row_count as usize),
+                })
+                .collect::<Vec<_>>(),
+        ))
+    };
+    let selection = pages();
+
+    Some((row_groups, selection))
+}
+
+/// whether each of the chunks with the `mins` and `maxes` keys may hold a key of one of `ranges`,
+/// chunks without statistics may
+fn overlapping<R>(
+    mins: &ArrayRef,
+    maxes: &ArrayRef,
+    ranges: &[(
+        Bound<&<R::Schema as Schema>::Key>,
+        Bound<&<R::Schema as Schema>::Key>,
+    )],
+) -> Option<Vec<bool>>
+where
+    R: Record,
+{
+    let mut is_overlapping = vec![false; mins.len()];
+    for (lower, upper) in ranges {
+        let below = match lower {
+            Bound::Included(key) => Some(lt(maxes, key.to_arrow_datum().as_ref()).ok()?),
+            Bound::Excluded(key) => Some(lt_eq(maxes, key.to_arrow_datum().as_ref()).ok()?),
+            Bound::Unbounded => None,
+        };
+        let above = match upper {
+            Bound::Included(key) => Some(gt(mins, key.to_arrow_datum().as_ref()).ok()?),
+            Bound::Excluded(key) => Some(gt_eq(mins, key.to_arrow_datum().as_ref()).ok()?),
+            Bound::Unbounded => None,
+        };
+        let is_pruned = |pruned: &Option<BooleanArray>, i: usize| {
+            pruned
+                .as_ref()
+                .is_some_and(|pruned| pruned.is_valid(i) && pruned.value(i))
+        };
+        for (i, is_overlapping) in is_overlapping.iter_mut().enumerate() {
+            *is_overlapping |= !is_pruned(&below, i) && !is_pruned(&above, i);
+        }
+    }
+    Some(is_overlapping)
+}
@@ -0,0 +1,94 @@
+use std::{ops::Range, sync::Arc, time::Instant};
+
+use bytes::Bytes;
+use futures_core::future::BoxFuture;
+use parquet::{
+    arrow::{arrow_reader::ArrowReaderOptions, async_reader::AsyncFileReader},
+    errors::{ParquetError, Result as ParquetResult},
+    file::metadata::ParquetMetaData,
+};
+use parquet_lru::{BoxedFileReader, DynLruCache};
+use thiserror::Error;
+
+use crate::{fs::FileId, ParquetLru};
+
+#[derive(Debug, Error)]
+#[error("read deadline exceeded")]
+struct DeadlineExceeded;
+
+/// the cache SSTables are opened with by a read bounded by `deadline`, see
+/// [`ReadOptions::deadline`](crate::ReadOptions::deadline)
+pub(crate) fn cache(parquet_lru: &ParquetLru, deadline: Option<Instant>) -> ParquetLru {
+    match deadline {
+        Some(deadline) => Arc::new(DeadlineCache {
+            inner: parquet_lru.clone(),
+            deadline,
+        }),
+        None => parquet_lru.clone(),
+    }
+}
+
+/// whether `err` was returned by an SSTable read after its deadline
+pub(crate) fn is_exceeded(err: &ParquetError) -> bool {
+    matches!(err, ParquetError::External(source) if source.is::<DeadlineExceeded>())
+}
+
+fn check(deadline: Instant) -> ParquetResult<()> {
+    // `Instant` is not available on wasm32-unknown-unknown
+    if !cfg!(target_arch = "wasm32") && Instant::now() >= deadline {
+        return Err(ParquetError::External(Box::new(DeadlineExceeded)));
+    }
+    Ok(())
+}
+
+struct DeadlineCache {
+    inner: ParquetLru,
+    deadline: Instant,
+}
+
+impl DynLruCache<FileId> for DeadlineCache {
+    fn get_reader(&self, key: FileId, reader: BoxedFileReader) -> BoxFuture<'_, BoxedFileReader> {
+        Box::pin(async move {
+            BoxedFileReader::new(DeadlineReader {
+                inner: self.inner.get_reader(key, reader).await,
+                deadline: self.deadline,
+            })
+        })
+    }
+}
+
+/// fails every request issued after `deadline` instead of sending it to the storage. Requests in
+/// flight are cancelled by dropping the read.
+struct DeadlineReader {
+    inner: BoxedFileReader,
+    deadline: Instant,
+}
+
+impl AsyncFileReader for DeadlineReader {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        Box::pin(async move {
+            check(self.deadline)?;
+            self.inner.get_bytes(range).await
+        })
+    }
+
+    fn get_metadata<'s>(
+        &'s mut self,
+        options: Option<&'s ArrowReaderOptions>,
+    ) -> BoxFuture<'s, ParquetResult<Arc<ParquetMetaData>>> {
+        Box::pin(async move {
+            check(self.deadline)?;
+            self.inner.get_metadata(options).await
+        })
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        Box::pin(async move {
+            check(self.deadline)?;
+            self.inner.get_byte_ranges(ranges).await
+        })
+    }
+}
@@ -1,3 +1,5 @@
 mod arrows;
+pub(crate) mod deadline;
+pub(crate) mod readahead;
 pub(crate) mod scan;
 pub(crate) mod sstable;
@@ -0,0 +1,413 @@
+use std::{
+    collections::BTreeSet,
+    future::Future,
+    ops::Range,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use fusio::{path::Path, DynFs};
+use futures_core::future::BoxFuture;
+use futures_util::{future::poll_fn, stream::FuturesUnordered, StreamExt};
+use parquet::{
+    arrow::{arrow_reader::ArrowReaderOptions, async_reader::AsyncFileReader},
+    errors::{ParquetError, Result as ParquetResult},
+    file::metadata::ParquetMetaData,
+};
+use parquet_lru::BoxedFileReader;
+
+use crate::{
+    cache::table::{self, TableCache},
+    fs::FileId,
+    DbOption, ParquetLru,
+};
+
+/// opens another reader of the table being scanned, the row groups are prefetched with their own
+/// readers while the scan reads the current one
+pub(crate) type OpenReader =
+    Arc<dyn Fn() -> BoxFuture<'static, Result<BoxedFileReader, fusio::Error>> + Send + Sync>;
+
+/// How far a scan reads ahead of the row group it decodes, see
+/// [`DbOption::scan_readahead`](crate::DbOption::scan_readahead).
+#[derive(Clone)]
+pub(crate) struct Readahead {
+    /// bytes of the column chunks prefetched and not read yet by the scan
+    pub(crate) bytes: u64,
+    /// row groups prefetched at once
+    pub(crate) concurrency: usize,
+    pub(crate) open: OpenReader,
+}
+
+impl Readahead {
+    /// the readahead of the scans of the table `gen` at `path` of `fs`, read through
+    /// `parquet_lru`, `None` without [`DbOption::scan_readahead`]
+    pub(crate) fn new(
+        option: &DbOption,
+        table_cache: Option<Arc<TableCache>>,
+        fs: Arc<dyn DynFs>,
+        path: Path,
+        gen: FileId,
+        parquet_lru: ParquetLru,
+    ) -> Option<Self> {
+        let (bytes, concurrency) = option.scan_readahead?;
+        Some(Readahead {
+            bytes,
+            concurrency,
+            open: Arc::new(move || {
+                let table_cache = table_cache.clone();
+                let fs = fs.clone();
+                let path = path.clone();
+                let parquet_lru = parquet_lru.clone();
+                Box::pin(async move {
+                    let reader = table::open(table_cache.as_ref(), &fs, &path, gen).await?;
+                    Ok(parquet_lru.get_reader(gen, reader).await)
+                })
+            }),
+        })
+    }
+}
+
+/// a row group fetched in the background
+struct Fetch {
+    reader: Option<BoxedFileReader>,
+    /// the index of the row group in the plan
+    index: usize,
+    ranges: Vec<Range<u64>>,
+    result: ParquetResult<Vec<Bytes>>,
+}
+
+/// The column chunks of the row groups a scan reads next, fetched while it decodes the current
+/// one. The chunks of the columns the scan read so far are fetched, the others are read when
+/// the scan asks for them.
+pub(crate) struct Prefetcher {
+    readahead: Readahead,
+    metadata: Option<Arc<ParquetMetaData>>,
+    /// the row groups of the scan in the order they are read
+    plan: Vec<usize>,
+    /// the index in the plan of the row group the scan reads
+    current: usize,
+    /// the index in the plan of the next row group to prefetch
+    scheduled: usize,
+    /// the columns the scan read
+    columns: BTreeSet<usize>,
+    readers: Vec<BoxedFileReader>,
+    in_flight: FuturesUnordered<Pin<Box<dyn Future<Output = Fetch> + Send>>>,
+    /// the chunks in flight with the index of their row group
+    in_flight_ranges: Vec<(usize, Range<u64>)>,
+    fetched: Vec<(usize, Range<u64>, Bytes)>,
+    /// bytes of the chunks in flight and fetched
+    buffered: u64,
+    /// prefetching stops on the first error, the scan reads the row groups itself
+    failed: bool,
+}
+
+impl Prefetcher {
+    pub(crate) fn new(readahead: Readahead) -> Self {
+        Self {
+            readahead,
+            metadata: None,
+            plan: Vec::new(),
+            current: 0,
+            scheduled: 1,
+            columns: BTreeSet::new(),
+            readers: Vec::new(),
+            in_flight: FuturesUnordered::new(),
+            in_flight_ranges: Vec::new(),
+            fetched: Vec::new(),
+            buffered: 0,
+            failed: false,
+        }
+    }
+
+    /// prefetch the row groups `plan` of the table of `metadata`
+    pub(crate) fn plan(&mut self, metadata: Arc<ParquetMetaData>, plan: Vec<usize>) {
+        self.metadata = Some(metadata);
+        self.plan = plan;
+    }
+
+    /// the chunk of every column of the row group `index` of the plan
+    fn chunks(&self, index: usize) -> impl Iterator<Item = (usize, Range<u64>)> + '_ {
+        // SAFETY: chunks are read once planned
+        let row_group = self.metadata.as_ref().unwrap().row_group(self.plan[index]);
+        row_group
+            .columns()
+            .iter()
+            .enumerate()
+            .map(|(column, chunk)| {
+                let (start, len) = chunk.byte_range();
+                (column, start..start + len)
+            })
+    }
+
+    /// the row group of the plan and the column `range` is read from
+    fn locate(&self, range: &Range<u64>) -> Option<(usize, usize)> {
+        self.metadata.as_ref()?;
+        (self.current..self.plan.len()).find_map(|index| {
+            self.chunks(index)
+                .find(|(_, chunk)| chunk.start <= range.start && range.end <= chunk.end)
+                .map(|(column, _)| (index, column))
+        })
+    }
+
+    /// `ranges` from the prefetched chunks, `None` when one of them is neither prefetched nor
+    /// in flight
+    fn poll_serve(
+        &mut self,
+        ranges: &[Range<u64>],
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Vec<Bytes>>> {
+        if let Some((index, _)) = ranges.first().and_then(|range| self.locate(range)) {
+            self.advance(index);
+        }
+        for range in ranges {
+            if let Some((index, column)) = self.locate(range) {
+                if index == self.current {
+                    self.columns.insert(column);
+                }
+            }
+        }
+        loop {
+            let served = ranges
+                .iter()
+                .map(|range| {
+                    self.fetched
+                        .iter()
+                        .find(|(_, chunk, _)| chunk.start <= range.start && range.end <= chunk.end)
+                        .map(|(_, chunk, bytes)| {
+                            bytes.slice(
+                                (range.start - chunk.start) as usize
+                                    ..(range.end - chunk.start) as usize,
+                            )
+                        })
+                })
+                .collect::<Option<Vec<_>>>();
+            if served.is_some() {
+                return Poll::Ready(served);
+            }
+            let in_flight = ranges.iter().all(|range| {
+                self.fetched
+                    .iter()
+                    .map(|(_, chunk, _)| chunk)
+                    .chain(self.in_flight_ranges.iter().map(|(_, chunk)| chunk))
+                    .any(|chunk| chunk.start <= range.start && range.end <= chunk.end)
+            });
+            if !in_flight {
+                return Poll::Ready(None);
+            }
+            match self.in_flight.poll_next_unpin(cx) {
+                Poll::Ready(Some(fetch)) => self.complete(fetch),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    /// the scan reads the row group `index` of the plan, the chunks of the row groups before it
+    /// are dropped
+    fn advance(&mut self, index: usize) {
+        if index <= self.current {
+            return;
+        }
+        self.current = index;
+        self.scheduled = self.scheduled.max(index + 1);
+        let mut released = 0;
+        self.fetched.retain(|(index, chunk, _)| {
+            let keep = *index >= self.current;
+            if !keep {
+                released += chunk.end - chunk.start;
+            }
+            keep
+        });
+        self.buffered -= released;
+    }
+
+    fn complete(&mut self, fetch: Fetch) {
+        self.in_flight_ranges
+            .retain(|(index, _)| *index != fetch.index);
+        if let Some(reader) = fetch.reader {
+            self.readers.push(reader);
+        }
+        let size = fetch
+            .ranges
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum::<u64>();
+        match fetch.result {
+            Ok(chunks) if fetch.index >= self.current => self.fetched.extend(
+                fetch
+                    .ranges
+                    .into_iter()
+                    .zip(chunks)
+                    .map(|(range, bytes)| (fetch.index, range, bytes)),
+            ),
+            Ok(_) => self.buffered -= size,
+            Err(_) => {
+                self.failed = true;
+                self.buffered -= size;
+            }
+        }
+    }
+
+    /// drive the fetches in flight and start the next ones within the readahead
+    pub(crate) fn poll_progress(&mut self, cx: &mut Context<'_>) {
+        loop {
+            self.schedule();
+            match self.in_flight.poll_next_unpin(cx) {
+                Poll::Ready(Some(fetch)) => self.complete(fetch),
+                _ => return,
+            }
+        }
+    }
+
+    fn schedule(&mut self) {
+        // the columns are known once the scan read its first row group
+        while !self.failed
+            && !self.columns.is_empty()
+            && self.scheduled < self.plan.len()
+            && self.in_flight.len() < self.readahead.concurrency
+        {
+            let index = self.scheduled;
+            let ranges = self
+                .chunks(index)
+                .filter(|(column, _)| self.columns.contains(column))
+                .map(|(_, chunk)| chunk)
+                .collect::<Vec<_>>();
+            let size = ranges
+                .iter()
+                .map(|range| range.end - range.start)
+                .sum::<u64>();
+            if self.buffered > 0 && self.buffered + size > self.readahead.bytes {
+                return;
+            }
+            self.scheduled += 1;
+            self.buffered += size;
+            self.in_flight_ranges
+                .extend(ranges.iter().map(|range| (index, range.clone())));
+
+            let reader = self.readers.pop();
+            let open = self.readahead.open.clone();
+            self.in_flight.push(Box::pin(async move {
+                let mut reader = match reader {
+                    Some(reader) => reader,
+                    None => match open().await {
+                        Ok(reader) => reader,
+                        Err(err) => {
+                            return Fetch {
+                                reader: None,
+                                index,
+                                ranges,
+                                result: Err(ParquetError::External(Box::new(err))),
+                            }
+                        }
+                    },
+                };
+                let result = reader.get_byte_ranges(ranges.clone()).await;
+                Fetch {
+                    reader: Some(reader),
+                    index,
+                    ranges,
+                    result,
+                }
+            }));
+        }
+    }
+}
+
+/// reads the SSTable being scanned from the chunks of `prefetcher` first
+pub(crate) struct ReadaheadReader {
+    pub(crate) inner: BoxedFileReader,
+    pub(crate) prefetcher: Arc<Mutex<Prefetcher>>,
+}
+
+impl AsyncFileReader for ReadaheadReader {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        Box::pin(async move {
+            let ranges = vec![range];
+            let served =
+                poll_fn(|cx| self.prefetcher.lock().unwrap().poll_serve(&ranges, cx)).await;
+            match served {
+                // SAFETY: a chunk is served for every range
+                Some(mut chunks) => Ok(chunks.pop().unwrap()),
+                None => self.inner.get_bytes(ranges[0].clone()).await,
+            }
+        })
+    }
+
+    fn get_metadata<'s>(
+        &'s mut self,
+        options: Option<&'s ArrowReaderOptions>,
+    ) -> BoxFuture<'s, ParquetResult<Arc<ParquetMetaData>>> {
+        self.inner.get_metadata(options)
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        Box::pin(async move {
+            let served =
+                poll_fn(|cx| self.prefetcher.lock().unwrap().poll_serve(&ranges, cx)).await;
+            match served {
+                Some(chunks) => Ok(chunks),
+                None => self.inner.get_byte_ranges(ranges).await,
+            }
+        })
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::ops::Bound;
+
+    use fusio::path::Path;
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scan_ahead() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .max_row_group_size(4)
+        .scan_readahead(1024 * 1024, 2)
+        .max_open_tables(8);
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        for i in 0..20u32 {
+            db.insert(Test {
+                vstring: format!("{i:02}"),
+                vu32: i,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+
+        let txn = db.transaction().await;
+        let mut scan = txn
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .take()
+            .await
+            .unwrap();
+        let mut values = Vec::new();
+        while let Some(entry) = scan.next().await.transpose().unwrap() {
+            values.push(entry.value().unwrap().vu32.unwrap());
+        }
+        assert_eq!(values, (0..20).collect::<Vec<_>>());
+        // the row groups after the first one are read with readers of their own
+        assert!(db.table_cache_stats().unwrap().misses > 1);
+    }
+}
@@ -1,7 +1,7 @@
 use std::{
     marker::PhantomData,
     pin::Pin,
-    sync::Arc,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
 };
 
@@ -13,7 +13,9 @@ use parquet::arrow::{
 };
 use pin_project_lite::pin_project;
 
+use super::readahead::Prefetcher;
 use crate::{
+    memory::{MemoryTracker, Reservation},
     record::Record,
     stream::record_batch::{RecordBatchEntry, RecordBatchIterator},
 };
@@ -26,6 +28,12 @@ pin_project! {
         iter: Option<RecordBatchIterator<R>>,
         projection_mask: ProjectionMask,
         full_schema: Arc<Schema>,
+        memory: Option<MemoryTracker>,
+        // the record batch of `iter` counted by `memory`
+        reservation: Option<Reservation>,
+        // the row groups read ahead, driven while the scan is polled
+        prefetcher: Option<Arc<Mutex<Prefetcher>>>,
+        borrow_values: bool,
         _marker: PhantomData<&'scan ()>
     }
 }
@@ -41,9 +49,38 @@ impl<R> SsTableScan<'_, R> {
             iter: None,
             projection_mask,
             full_schema,
+            memory: None,
+            reservation: None,
+            prefetcher: None,
+            borrow_values: false,
             _marker: PhantomData,
         }
     }
+
+    /// count the record batch being read into `memory`
+    pub(crate) fn with_memory(self, memory: MemoryTracker) -> Self {
+        SsTableScan {
+            memory: Some(memory),
+            ..self
+        }
+    }
+
+    /// read the row groups ahead with `prefetcher`
+    pub(crate) fn with_prefetcher(self, prefetcher: Arc<Mutex<Prefetcher>>) -> Self {
+        SsTableScan {
+            prefetcher: Some(prefetcher),
+            ..self
+        }
+    }
+
+    /// borrow the values of the records from the record batches, see
+    /// [`RecordRef::from_record_batch_borrowed`](crate::record::RecordRef::from_record_batch_borrowed)
+    pub(crate) fn borrow_values(self, borrow_values: bool) -> Self {
+        SsTableScan {
+            borrow_values,
+            ..self
+        }
+    }
 }
 
 impl<'scan, R> Stream for SsTableScan<'scan, R>
@@ -54,6 +91,9 @@ where
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut this = self.project();
+        if let Some(prefetcher) = this.prefetcher {
+            prefetcher.lock().unwrap().poll_progress(cx);
+        }
         loop {
             match this.iter {
                 Some(iter) => {
@@ -61,6 +101,7 @@ where
                         return Poll::Ready(Some(Ok(entry)));
                     }
                     *this.iter = None;
+                    *this.reservation = None;
                 }
                 None => {
                     let record_batch = ready!(this.stream.as_mut().poll_next(cx)).transpose()?;
@@ -68,11 +109,18 @@ where
                         Some(record_batch) => record_batch,
                         None => return Poll::Ready(None),
                     };
-                    *this.iter = Some(RecordBatchIterator::new(
-                        record_batch,
-                        this.projection_mask.clone(),
-                        this.full_schema.clone(),
-                    ));
+                    *this.reservation = this
+                        .memory
+                        .as_ref()
+                        .map(|memory| memory.reserve(record_batch.get_array_memory_size()));
+                    *this.iter = Some(
+                        RecordBatchIterator::new(
+                            record_batch,
+                            this.projection_mask.clone(),
+                            this.full_schema.clone(),
+                        )
+                        .borrow_values(*this.borrow_values),
+                    );
                 }
             }
         }
@@ -1,5 +1,10 @@
-use std::{marker::PhantomData, ops::Bound, sync::Arc};
+use std::{
+    marker::PhantomData,
+    ops::Bound,
+    sync::{Arc, Mutex},
+};
 
+use arrow::datatypes::SchemaRef;
 use fusio::{dynamic::DynFile, DynRead};
 use fusio_parquet::reader::AsyncReader;
 use futures_util::StreamExt;
@@ -9,23 +14,31 @@ use parquet::{
         async_reader::{AsyncFileReader, AsyncReader as ParquetAsyncReader},
         ParquetRecordBatchStreamBuilder, ProjectionMask,
     },
-    errors::Result as ParquetResult,
+    errors::{ParquetError, Result as ParquetResult},
+    file::metadata::ParquetMetaData,
 };
 use parquet_lru::{BoxedFileReader, DynLruCache};
 use ulid::Ulid;
 
-use super::{arrows::get_range_filter, scan::SsTableScan};
+use super::{
+    arrows::{get_keys_filter, get_pages_selection, get_range_filter},
+    readahead::{Prefetcher, Readahead, ReadaheadReader},
+    scan::SsTableScan,
+};
 use crate::{
-    record::{Record, Schema},
+    record::{Key, KeyRef, Record, Schema},
     stream::record_batch::RecordBatchEntry,
     timestamp::{Timestamp, TsRef},
 };
 
-pub(crate) struct SsTable<R>
+/// A table of sorted records on disk, written by a flush or a compaction as a Parquet file, see
+/// [`SsTable::inspect`].
+pub struct SsTable<R>
 where
     R: Record,
 {
     reader: BoxedFileReader,
+    readahead: Option<Readahead>,
     _marker: PhantomData<R>,
 }
 
@@ -40,15 +53,31 @@ where
     ) -> Result<Self, fusio::Error> {
         let size = file.size().await?;
 
-        Ok(SsTable {
-            reader: lru_cache
-                .get_reader(
-                    id,
-                    BoxedFileReader::new(AsyncReader::new(file, size).await?),
-                )
-                .await,
+        Ok(Self::from_reader(
+            lru_cache,
+            id,
+            BoxedFileReader::new(AsyncReader::new(file, size).await?),
+        )
+        .await)
+    }
+
+    /// the table read by `reader`, see
+    /// [`StoreManager::open_table`](crate::fs::manager::StoreManager::open_table)
+    pub(crate) async fn from_reader(
+        lru_cache: Arc<dyn DynLruCache<Ulid> + Send + Sync>,
+        id: Ulid,
+        reader: BoxedFileReader,
+    ) -> Self {
+        SsTable {
+            reader: lru_cache.get_reader(id, reader).await,
+            readahead: None,
             _marker: PhantomData,
-        })
+        }
+    }
+
+    /// prefetch the row groups of the scans with `readahead`
+    pub(crate) fn with_readahead(self, readahead: Option<Readahead>) -> Self {
+        SsTable { readahead, ..self }
     }
 
     async fn into_parquet_builder(
@@ -68,6 +97,80 @@ where
         Ok(builder.with_projection(projection_mask))
     }
 
+    /// read only the row groups and the pages whose primary keys overlap one of `ranges`, along
+    /// with the row groups read
+    fn select_pages(
+        builder: ArrowReaderBuilder<ParquetAsyncReader<Box<dyn AsyncFileReader + 'static>>>,
+        ranges: &[(
+            Bound<&<R::Schema as Schema>::Key>,
+            Bound<&<R::Schema as Schema>::Key>,
+        )],
+    ) -> (
+        ArrowReaderBuilder<ParquetAsyncReader<Box<dyn AsyncFileReader + 'static>>>,
+        Vec<usize>,
+    ) {
+        match get_pages_selection::<R>(builder.metadata(), builder.schema(), ranges) {
+            Some((row_groups, Some(selection))) => (
+                builder
+                    .with_row_groups(row_groups.clone())
+                    .with_row_selection(selection),
+                row_groups,
+            ),
+            Some((row_groups, None)) => (builder.with_row_groups(row_groups.clone()), row_groups),
+            None => {
+                let row_groups = (0..builder.metadata().num_row_groups()).collect();
+                (builder, row_groups)
+            }
+        }
+    }
+
+    /// read every page of the table, returns its smallest and largest keys or `None` when it is
+    /// empty
+    pub(crate) async fn key_range(
+        self,
+    ) -> ParquetResult<Option<(<R::Schema as Schema>::Key, <R::Schema as Schema>::Key)>> {
+        let (_, mut scan) = self.entries().await?;
+
+        let mut range: Option<(<R::Schema as Schema>::Key, <R::Schema as Schema>::Key)> = None;
+        while let Some(entry) = scan.next().await.transpose()? {
+            let key = entry.key();
+            if let Some((_, max)) = &range {
+                if key < max.as_key_ref() {
+                    return Err(ParquetError::General(
+                        "the keys of the table are not sorted".to_string(),
+                    ));
+                }
+            }
+            let key = key.to_key();
+            range = Some(match range {
+                Some((min, _)) => (min, key),
+                None => (key.clone(), key),
+            });
+        }
+        Ok(range)
+    }
+
+    /// read every entry of the table, tombstones and overwritten versions included, along with
+    /// the Arrow schema of the table
+    pub(crate) async fn entries(self) -> ParquetResult<(SchemaRef, SsTableScan<'static, R>)> {
+        let builder = self
+            .into_parquet_builder(None, ProjectionMask::all())
+            .await?;
+        let full_schema = builder.schema().clone();
+        Ok((
+            full_schema.clone(),
+            SsTableScan::new(builder.build()?, ProjectionMask::all(), full_schema),
+        ))
+    }
+
+    pub(crate) async fn metadata(self) -> ParquetResult<Arc<ParquetMetaData>> {
+        Ok(self
+            .into_parquet_builder(None, ProjectionMask::all())
+            .await?
+            .metadata()
+            .clone())
+    }
+
     pub(crate) async fn get(
         self,
         key: &TsRef<<R::Schema as Schema>::Key>,
@@ -85,6 +188,60 @@ where
         .transpose()
     }
 
+    /// get the versions of sorted and deduplicated `keys` visible at `ts` with a single pass over
+    /// the table, results are returned in the order of `keys`
+    pub(crate) async fn get_many(
+        self,
+        keys: &[&<R::Schema as Schema>::Key],
+        ts: Timestamp,
+        projection_mask: ProjectionMask,
+    ) -> ParquetResult<Vec<Option<RecordBatchEntry<R>>>> {
+        let mut entries = keys.iter().map(|_| None).collect::<Vec<_>>();
+        if keys.is_empty() {
+            return Ok(entries);
+        }
+        let builder = self
+            .into_parquet_builder(None, projection_mask.clone())
+            .await?;
+        let ranges = keys
+            .iter()
+            .map(|key| (Bound::Included(*key), Bound::Included(*key)))
+            .collect::<Vec<_>>();
+        let (builder, _) = Self::select_pages(builder, &ranges);
+
+        let schema_descriptor = builder.metadata().file_metadata().schema_descr();
+        let full_schema = builder.schema().clone();
+
+        // Safety: filter's lifetime relies on keys' lifetime, the scan is dropped before returning
+        let filter = unsafe { get_keys_filter::<R>(schema_descriptor, keys, ts) };
+        let mut scan = SsTableScan::<R>::new(
+            builder.with_row_filter(filter).build()?,
+            projection_mask,
+            full_schema,
+        );
+
+        let mut index = 0;
+        while let Some(entry) = scan.next().await.transpose()? {
+            // rows are ordered by key and then by newest timestamp, so the first row of each key
+            // is the visible one
+            let is_found = {
+                let key = entry.key();
+                while index < keys.len() && keys[index].as_key_ref() < key {
+                    index += 1;
+                }
+                index < keys.len() && keys[index].as_key_ref() == key
+            };
+            if is_found {
+                entries[index] = Some(entry);
+                index += 1;
+            }
+            if index == keys.len() {
+                break;
+            }
+        }
+        Ok(entries)
+    }
+
     pub(crate) async fn scan<'scan>(
         self,
         range: (
@@ -95,9 +252,25 @@ where
         limit: Option<usize>,
         projection_mask: ProjectionMask,
     ) -> Result<SsTableScan<'scan, R>, parquet::errors::ParquetError> {
-        let builder = self
+        let mut table = self;
+        let prefetcher = table.readahead.take().map(|readahead| {
+            let prefetcher = Arc::new(Mutex::new(Prefetcher::new(readahead)));
+            table.reader = BoxedFileReader::new(ReadaheadReader {
+                inner: table.reader,
+                prefetcher: prefetcher.clone(),
+            });
+            prefetcher
+        });
+        let builder = table
             .into_parquet_builder(limit, projection_mask.clone())
             .await?;
+        let (builder, row_groups) = Self::select_pages(builder, &[range]);
+        if let Some(prefetcher) = &prefetcher {
+            prefetcher
+                .lock()
+                .unwrap()
+                .plan(builder.metadata().clone(), row_groups);
+        }
 
         let schema_descriptor = builder.metadata().file_metadata().schema_descr();
         let full_schema = builder.schema().clone();
@@ -106,11 +279,15 @@ where
         // it
         let filter = unsafe { get_range_filter::<R>(schema_descriptor, range, ts) };
 
-        Ok(SsTableScan::new(
+        let scan = SsTableScan::new(
             builder.with_row_filter(filter).build()?,
             projection_mask,
             full_schema,
-        ))
+        );
+        Ok(match prefetcher {
+            Some(prefetcher) => scan.with_prefetcher(prefetcher),
+            None => scan,
+        })
     }
 }
 
@@ -138,10 +315,11 @@ pub(crate) mod tests {
         executor::tokio::TokioExecutor,
         fs::{manager::StoreManager, FileType},
         inmem::immutable::tests::TestSchema,
+        ondisk::arrows::get_pages_selection,
         record::{Record, Schema},
         tests::{get_test_record_batch, Test},
         timestamp::Ts,
-        DbOption,
+        DbOption, DB,
     };
 
     async fn write_record_batch(
@@ -376,4 +554,84 @@ pub(crate) mod tests {
             assert_eq!(entry_1.get().unwrap().vbool, None);
         }
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn page_index() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .max_row_group_size(20)
+        .data_page_size(1)
+        .page_index();
+        // the page size is checked once per write batch
+        let properties = option
+            .write_parquet_properties
+            .clone()
+            .into_builder()
+            .set_write_batch_size(5)
+            .build();
+        option = option.write_parquet_option(properties);
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        for i in 0..100u32 {
+            db.insert(Test {
+                vstring: format!("key{i:03}"),
+                vu32: i,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+
+        let version = db.ctx.version_set().current().await;
+        let metadata = version
+            .table_metadata(
+                db.ctx.storage_manager(),
+                0,
+                version.level_slice[0][0].gen,
+                db.ctx.cache().clone(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(metadata.num_row_groups(), 5);
+        assert!(metadata.column_index().is_some());
+
+        // only the page of 5 rows holding the keys in the 3rd row group is read
+        let (lower, upper) = ("key042".to_string(), "key044".to_string());
+        let (row_groups, selection) = get_pages_selection::<Test>(
+            &metadata,
+            TestSchema.arrow_schema(),
+            &[(Bound::Included(&lower), Bound::Included(&upper))],
+        )
+        .unwrap();
+        assert_eq!(row_groups, vec![2]);
+        assert_eq!(selection.unwrap().row_count(), 5);
+        drop(version);
+
+        {
+            let snapshot = db.snapshot().await;
+            let mut scan = snapshot
+                .scan((Bound::Excluded(&lower), Bound::Unbounded))
+                .take()
+                .await
+                .unwrap();
+            let mut vu32s = Vec::new();
+            while let Some(entry) = scan.next().await.transpose().unwrap() {
+                vu32s.push(entry.value().unwrap().vu32.unwrap());
+            }
+            assert_eq!(vu32s, (43..100).collect::<Vec<_>>());
+        }
+
+        let keys = ["key007", "key063", "key100"].map(str::to_string);
+        let vu32s = db
+            .get_many(&keys, |entry| Some(entry.get().vu32))
+            .await
+            .unwrap();
+        assert_eq!(vu32s, vec![Some(7), Some(63), None]);
+    }
 }
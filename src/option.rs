@@ -1,4 +1,8 @@
-use std::fmt::{Debug, Formatter};
+use std::{
+    fmt::{Debug, Formatter},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 pub use fusio::path::Path;
 #[cfg(feature = "aws")]
@@ -6,13 +10,23 @@ pub use fusio::remotes::aws::AwsCredential;
 pub use fusio_dispatch::FsOptions;
 use parquet::{
     basic::Compression,
+    errors::ParquetError,
     file::properties::{EnabledStatistics, WriterProperties},
+    schema::types::ColumnPath,
 };
 use thiserror::Error;
 
 use crate::{
+    archive::WalArchiver,
+    catalog::CatalogSink,
+    encryption::{self, KeyProvider},
+    event::EventListener,
+    executor::pool::TaskPool,
     fs::{FileId, FileType},
+    lease::LeaseStore,
+    memory::MemoryBudget,
     record::{Record, Schema},
+    timestamp::Timestamp,
     trigger::TriggerType,
     version::{Version, MAX_LEVEL},
 };
@@ -22,6 +36,219 @@ const DEFAULT_WAL_BUFFER_SIZE: usize = 4 * 1024;
 #[derive(Clone)]
 pub enum CompactionOption {
     Leveled,
+    /// Leveled compaction driven by the size of the levels: each level targets
+    /// [`SizeRatioOption::size_ratio`] times the size of the level above it, the level furthest
+    /// over its target is compacted first, and its table overlapping the least bytes of the next
+    /// level is picked. This keeps the levels sorted runs of bounded size, which reduces read
+    /// amplification for read-heavy workloads.
+    SizeRatio(SizeRatioOption),
+    /// Tables are never compacted: the oldest ones are dropped once the tables exceed
+    /// [`FifoOption::max_total_size`] or once they are older than [`FifoOption::max_age`]. Meant
+    /// for time-series whose records are only kept for a while, like logs and metrics, as every
+    /// table stays in level 0 and is searched by reads.
+    Fifo(FifoOption),
+}
+
+/// targets of [`CompactionOption::SizeRatio`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeRatioOption {
+    /// number of tables of level 0 from which they are compacted into level 1
+    pub level0_table_num: usize,
+    /// target size in bytes of level 1
+    pub level_base_size: u64,
+    /// each level below level 1 targets `size_ratio` times the size of the level above it
+    pub size_ratio: u64,
+}
+
+impl Default for SizeRatioOption {
+    fn default() -> Self {
+        Self {
+            level0_table_num: 4,
+            level_base_size: 256 * 1024 * 1024,
+            size_ratio: 10,
+        }
+    }
+}
+
+/// thresholds of [`CompactionOption::Fifo`], the tables are kept forever if none is set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FifoOption {
+    /// drop the oldest tables while all the tables exceed this size in bytes
+    pub max_total_size: Option<u64>,
+    /// drop the tables written longer ago than this
+    pub max_age: Option<Duration>,
+}
+
+impl SizeRatioOption {
+    /// target size in bytes of `level`, which is not level 0
+    pub(crate) fn target_size(&self, level: usize) -> u64 {
+        self.size_ratio
+            .saturating_pow(level as u32 - 1)
+            .saturating_mul(self.level_base_size)
+    }
+}
+
+/// When the writes of a [`DB`](crate::DB) stall, see [`DbOption::write_stall`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteStall {
+    /// writes stall while more immutable memtables wait for their flush
+    pub max_immutables: usize,
+    /// writes stall while level 0 holds more tables
+    pub max_level0_tables: usize,
+    pub policy: StallPolicy,
+}
+
+/// What a stalled write does, see [`WriteStall`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallPolicy {
+    /// wait for this long before writing. The delay is skipped on wasm32, which has no timer.
+    Delay(Duration),
+    /// fail with [`DbError::Busy`](crate::DbError::Busy) without writing
+    Reject,
+}
+
+/// When the mutable memtable of a [`DB`](crate::DB) is frozen and the immutable memtables are
+/// flushed, see [`DbOption::flush_policy`]. The mutable memtable is frozen once any of its limits
+/// is reached, the limits left `None` do not apply.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlushPolicy {
+    /// bytes of the records written, 64 MiB if `None`
+    pub max_size: Option<usize>,
+    /// records written, removals included
+    pub max_rows: Option<usize>,
+    /// bytes logged to the WAL since the memtable was frozen last, before the compression
+    pub max_wal_bytes: Option<usize>,
+    /// time since the first write to the memtable. The memtable is checked as it is written, and
+    /// in the background except on wasm32, which has no timer, so a table written rarely still
+    /// flushes in time.
+    pub max_age: Option<Duration>,
+    /// the immutable memtables are flushed once more of them wait, 5 if `None`
+    pub max_immutables: Option<usize>,
+}
+
+/// How [`DB::run_txn`](crate::DB::run_txn) retries a transaction that failed to commit on a
+/// conflict, see [`DbOption::transaction_retry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionRetry {
+    /// attempts before the conflict is returned, the first one included
+    pub max_attempts: usize,
+    /// the wait before the first retry, doubled after every retry. The wait is skipped on
+    /// wasm32, which has no timer.
+    pub backoff: Duration,
+    /// the longest wait between two attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for TransactionRetry {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+/// How the requests to the storage of a [`DB`](crate::DB) are sent again after a transient
+/// error, e.g. a 503 of S3 or a dropped connection, see [`DbOption::retry_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// attempts before the error is returned, the first one included
+    pub max_attempts: usize,
+    /// the wait before the first retry, doubled after every retry. The wait is skipped on
+    /// wasm32, which has no timer.
+    pub backoff: Duration,
+    /// the longest wait between two attempts
+    pub max_backoff: Duration,
+    /// an attempt running longer fails as timed out and is retried, attempts are not bounded if
+    /// `None`. Not applied on wasm32 nor to the writes of whole files.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(5),
+            timeout: None,
+        }
+    }
+}
+
+/// The rates of the requests to the storage of a [`DB`](crate::DB), see
+/// [`DbOption::scan_rate_limit`] and [`DbOption::background_rate_limit`]. A rate left `None` is
+/// not limited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageRateLimit {
+    /// the bytes read or written per second
+    pub bytes_per_sec: Option<u64>,
+    /// the requests sent per second
+    pub requests_per_sec: Option<u64>,
+}
+
+/// When the logs of the commits are written from the WAL buffer to the WAL segment, see
+/// [`DbOption::wal_sync_mode`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WalSyncMode {
+    /// every commit writes its logs before it returns, a crash loses no commit that returned.
+    /// [`DbOption::wal_group_commit`] groups the writes of concurrent commits.
+    ///
+    /// Recovery fails on a log that can't be read instead of dropping it, as it may belong to a
+    /// commit that returned.
+    Commit,
+    /// a commit writes the logs buffered since the last write older than the interval, a crash
+    /// loses the commits of up to an interval. The logs of the last commits stay buffered until
+    /// the next commit, a rotation or [`DB::flush_wal`](crate::DB::flush_wal).
+    Interval(Duration),
+    /// the logs are written when the WAL buffer fills up, the WAL segment rotates or
+    /// [`DB::flush_wal`](crate::DB::flush_wal) is called, a crash loses the buffered commits
+    #[default]
+    Buffered,
+}
+
+/// Codec the logs of the WAL segments are compressed with, see [`DbOption::wal_compression`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalCompression {
+    Lz4,
+    /// Zstandard at the compression level, 1 to 22 or negative for faster levels
+    Zstd(i32),
+}
+
+/// how [`Transaction`](crate::transaction::Transaction)s are isolated from each other, see
+/// [`DbOption::isolation_level`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// a transaction reads a snapshot and fails to commit if a key it writes was committed by
+    /// another transaction since, reads are not checked so write skews are possible
+    #[default]
+    Snapshot,
+    /// the keys and ranges a transaction reads are tracked too, it fails to commit if another
+    /// transaction committed a write into them since its snapshot
+    Serializable,
+}
+
+/// how writes to an existing key are applied, see [`DbOption::update_strategy`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UpdateStrategy {
+    /// every write replaces the whole record
+    #[default]
+    CopyOnWrite,
+    /// every write is a delta, its null columns keep the values of the older versions of the
+    /// key. Versions are merged when the key is read and when they are compacted together.
+    MergeOnRead,
+}
+
+/// the structure the mutable memtable keeps its writes in, see [`DbOption::memtable`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MemTableKind {
+    /// a lock-free skiplist, writes never wait for each other or for reads
+    #[default]
+    SkipList,
+    /// a B-tree behind a read-write lock. Its nodes pack many keys together, which suits small
+    /// fixed-size keys better than the nodes of a skiplist, but writes are serialized and a scan
+    /// looks each of its entries up again under the lock.
+    BTree,
 }
 
 /// configure the operating parameters of each component in the [`DB`](crate::DB)
@@ -31,6 +258,7 @@ pub struct DbOption {
     pub(crate) base_path: Path,
     pub(crate) base_fs: FsOptions,
     pub(crate) level_paths: Vec<Option<(Path, FsOptions)>>,
+    pub(crate) wal_path: Option<(Path, FsOptions)>,
     pub(crate) immutable_chunk_num: usize,
     pub(crate) immutable_chunk_max_num: usize,
     pub(crate) level_sst_magnification: usize,
@@ -38,12 +266,54 @@ pub struct DbOption {
     pub(crate) major_l_selection_table_max_num: usize,
     pub(crate) major_threshold_with_sst_size: usize,
     pub(crate) max_sst_file_size: usize,
+    pub(crate) max_subcompactions: usize,
     pub(crate) version_log_snapshot_threshold: u32,
     pub(crate) trigger_type: TriggerType,
     pub(crate) use_wal: bool,
+    /// set by [`DB::open_read_only`](crate::DB::open_read_only)
+    pub(crate) read_only: bool,
+    pub(crate) lease: Option<Duration>,
+    pub(crate) lease_store: Option<Arc<dyn LeaseStore>>,
+    pub(crate) gc_interval: Option<Duration>,
+    pub(crate) gc_grace: Duration,
     pub(crate) wal_buffer_size: usize,
+    pub(crate) wal_group_commit: Option<Duration>,
+    pub(crate) wal_sync_mode: WalSyncMode,
+    pub(crate) wal_compression: Option<WalCompression>,
+    pub(crate) wal_archiver: Option<Arc<dyn WalArchiver>>,
     pub(crate) write_parquet_properties: WriterProperties,
+    pub(crate) level_compressions: Vec<LevelCompression>,
+    pub(crate) max_row_group_size: Option<usize>,
+    pub(crate) data_page_size: Option<usize>,
+    pub(crate) page_index: bool,
     pub(crate) compaction_option: CompactionOption,
+    pub(crate) compaction_rate_limit: Option<u64>,
+    pub(crate) scan_rate_limit: Option<StorageRateLimit>,
+    pub(crate) background_rate_limit: Option<StorageRateLimit>,
+    pub(crate) periodic_compaction: Option<Duration>,
+    pub(crate) tombstone_compaction_ratio: Option<f64>,
+    pub(crate) catalog_sink: Option<Arc<dyn CatalogSink>>,
+    pub(crate) disk_cache: Option<(Path, u64)>,
+    pub(crate) block_cache: Option<u64>,
+    pub(crate) max_open_tables: Option<usize>,
+    pub(crate) metadata_cache: Option<u64>,
+    pub(crate) scan_readahead: Option<(u64, usize)>,
+    pub(crate) scan_parallelism: usize,
+    pub(crate) memory_budget: Option<MemoryBudget>,
+    pub(crate) encryption: Option<Arc<dyn KeyProvider>>,
+    pub(crate) event_listener: Option<Arc<dyn EventListener>>,
+    pub(crate) time_travel_retention: u32,
+    pub(crate) transaction_max_rows: Option<usize>,
+    pub(crate) transaction_max_bytes: Option<usize>,
+    pub(crate) isolation_level: IsolationLevel,
+    pub(crate) transaction_retry: TransactionRetry,
+    pub(crate) retry_policy: Option<RetryPolicy>,
+    pub(crate) update_strategy: UpdateStrategy,
+    pub(crate) write_stall: Option<WriteStall>,
+    pub(crate) memtable: MemTableKind,
+    pub(crate) memtable_shards: usize,
+    pub(crate) flush_policy: FlushPolicy,
+    pub(crate) task_pool: Option<TaskPool>,
 }
 
 impl DbOption {
@@ -57,6 +327,7 @@ impl DbOption {
             major_threshold_with_sst_size: 4,
             level_sst_magnification: 10,
             max_sst_file_size: 256 * 1024 * 1024,
+            max_subcompactions: 1,
             clean_channel_buffer: 10,
             base_path,
             write_parquet_properties: WriterProperties::builder()
@@ -68,14 +339,55 @@ impl DbOption {
                 .build(),
 
             use_wal: true,
+            read_only: false,
+            lease: None,
+            lease_store: None,
+            gc_interval: None,
+            gc_grace: Duration::from_secs(60 * 60),
             wal_buffer_size: DEFAULT_WAL_BUFFER_SIZE,
+            wal_group_commit: None,
+            wal_sync_mode: WalSyncMode::default(),
+            wal_compression: None,
+            wal_archiver: None,
             major_default_oldest_table_num: 3,
             major_l_selection_table_max_num: 4,
             trigger_type: TriggerType::SizeOfMem(64 * 1024 * 1024),
             version_log_snapshot_threshold: 200,
             level_paths: vec![None; MAX_LEVEL],
+            wal_path: None,
             base_fs: FsOptions::Local,
+            level_compressions: vec![LevelCompression::default(); MAX_LEVEL],
+            max_row_group_size: None,
+            data_page_size: None,
+            page_index: false,
             compaction_option: CompactionOption::Leveled,
+            compaction_rate_limit: None,
+            scan_rate_limit: None,
+            background_rate_limit: None,
+            periodic_compaction: None,
+            tombstone_compaction_ratio: None,
+            catalog_sink: None,
+            disk_cache: None,
+            block_cache: None,
+            max_open_tables: None,
+            metadata_cache: None,
+            scan_readahead: None,
+            scan_parallelism: 1,
+            memory_budget: None,
+            encryption: None,
+            event_listener: None,
+            time_travel_retention: 0,
+            transaction_max_rows: None,
+            transaction_max_bytes: None,
+            isolation_level: IsolationLevel::Snapshot,
+            transaction_retry: TransactionRetry::default(),
+            retry_policy: None,
+            update_strategy: UpdateStrategy::CopyOnWrite,
+            write_stall: None,
+            memtable: MemTableKind::SkipList,
+            memtable_shards: 1,
+            flush_policy: FlushPolicy::default(),
+            task_pool: None,
         }
     }
 }
@@ -121,6 +433,16 @@ impl DbOption {
         }
     }
 
+    /// Split the key range of a major compaction into up to `max_subcompactions` sub-ranges
+    /// merged concurrently, 1 by default. Sub-ranges start at the smallest keys of the input
+    /// tables, so a compaction of fewer tables is split into fewer sub-ranges.
+    pub fn max_subcompactions(self, max_subcompactions: usize) -> Self {
+        DbOption {
+            max_subcompactions: max_subcompactions.max(1),
+            ..self
+        }
+    }
+
     /// cached message size in parquet cleaner
     pub fn clean_channel_buffer(self, clean_channel_buffer: usize) -> Self {
         DbOption {
@@ -129,7 +451,9 @@ impl DbOption {
         }
     }
 
-    /// specific settings for Parquet
+    /// specific settings for Parquet, the sizes of [`DbOption::max_row_group_size`] and
+    /// [`DbOption::data_page_size`] and the codecs of [`DbOption::level_compression`] and
+    /// [`DbOption::level_column_compression`] apply over them
     pub fn write_parquet_option(self, write_parquet_properties: WriterProperties) -> Self {
         DbOption {
             write_parquet_properties,
@@ -157,6 +481,47 @@ impl DbOption {
         }
     }
 
+    /// When the logs of the commits are written to the WAL segment, the default
+    /// [`WalSyncMode::Buffered`] writes them when the WAL buffer fills up.
+    pub fn wal_sync_mode(self, wal_sync_mode: WalSyncMode) -> Self {
+        DbOption {
+            wal_sync_mode,
+            ..self
+        }
+    }
+
+    /// Compress every log of the WAL with `compression`, before it is checked or sealed. Logs that
+    /// don't shrink, e.g. the ones of small records, are written uncompressed. Recovery
+    /// decompresses the logs whatever the option, so it can be changed between restarts.
+    pub fn wal_compression(self, compression: WalCompression) -> Self {
+        DbOption {
+            wal_compression: Some(compression),
+            ..self
+        }
+    }
+
+    /// Hand the WAL segments to `wal_archiver` once their memtables are flushed, before they are
+    /// removed, e.g. [`CopyArchiver`](crate::archive::CopyArchiver) to copy them to an object
+    /// store for point-in-time recovery.
+    pub fn wal_archiver(self, wal_archiver: Arc<dyn WalArchiver>) -> Self {
+        DbOption {
+            wal_archiver: Some(wal_archiver),
+            ..self
+        }
+    }
+
+    /// Write the logs of every commit to the WAL segment before the commit returns, instead of
+    /// leaving them in the WAL buffer until it fills up or [`DB::flush_wal`](crate::DB::flush_wal)
+    /// is called. Concurrent commits are grouped: the first commit of a group waits up to
+    /// `max_delay` (e.g. 1ms) for the next ones, then the logs of the whole group are written at
+    /// once. Recovery is as strict as with [`WalSyncMode::Commit`].
+    pub fn wal_group_commit(self, max_delay: Duration) -> Self {
+        DbOption {
+            wal_group_commit: Some(max_delay),
+            ..self
+        }
+    }
+
     /// When selecting the compaction level during major compaction, if there are no sstables with
     /// intersecting targets, the oldest sstables will be selected by default.
     pub fn major_default_oldest_table_num(self, major_default_oldest_table_num: usize) -> Self {
@@ -167,13 +532,25 @@ impl DbOption {
     }
 
     /// VersionLog will use version_log_snapshot_threshold as the cycle to SnapShot to reduce the
-    /// size.
+    /// size: once the log holds that many edits, a snapshot of the version is written to a new
+    /// log and the old log is removed, so opening the [`DB`](crate::DB) replays at most that many
+    /// edits. A crash while the snapshot is written recovers from the old log.
     pub fn version_log_snapshot_threshold(self, version_log_snapshot_threshold: u32) -> Self {
         DbOption {
             version_log_snapshot_threshold,
             ..self
         }
     }
+    /// Store the WAL segments in `path` of the fs of `fs_options` instead of the `wal`
+    /// directory of the base path, e.g. on a local NVMe disk while the SSTables are on S3. The
+    /// WAL is recovered from there when the [`DB`](crate::DB) is opened.
+    pub fn wal_path(self, path: Path, fs_options: FsOptions) -> Self {
+        Self {
+            wal_path: Some((path, fs_options)),
+            ..self
+        }
+    }
+
     /// set the path where files will be stored in the level.
     pub fn level_path(
         mut self,
@@ -188,6 +565,88 @@ impl DbOption {
         Ok(self)
     }
 
+    /// Store the SSTables of `level` and of all the levels below it in `path` of the fs of
+    /// `fs_options`, e.g. to keep the recent data of the upper levels on a local disk and the
+    /// history in the last levels on S3. Compactions into these levels read their inputs from
+    /// the fs of the upper level and write the merged tables to this one, so tables move to
+    /// the cold storage as they are compacted down.
+    pub fn cold_level_path(
+        mut self,
+        level: usize,
+        path: Path,
+        fs_options: FsOptions,
+    ) -> Result<Self, ExceedsMaxLevel> {
+        if level >= MAX_LEVEL {
+            return Err(ExceedsMaxLevel);
+        }
+        for level_path in self.level_paths[level..].iter_mut() {
+            *level_path = Some((path.clone(), fs_options.clone()));
+        }
+        Ok(self)
+    }
+
+    /// Maximum number of rows in a row group of the SSTables, 1024 * 1024 by default. Reads skip
+    /// the row groups whose primary keys are out of the range they read.
+    pub fn max_row_group_size(self, max_row_group_size: usize) -> Self {
+        DbOption {
+            max_row_group_size: Some(max_row_group_size.max(1)),
+            ..self
+        }
+    }
+
+    /// Maximum size in bytes of a data page of the SSTables, 1 MiB by default. Smaller pages
+    /// let reads of the page index fetch less data for a key, at the cost of a larger index.
+    pub fn data_page_size(self, data_page_size: usize) -> Self {
+        DbOption {
+            data_page_size: Some(data_page_size),
+            ..self
+        }
+    }
+
+    /// Write the column index of every column of the SSTables instead of the primary key column
+    /// only, e.g. to prune pages on other columns in readers of the Parquet files. Reads of the
+    /// [`DB`](crate::DB) fetch only the pages holding the keys they read with the index of the
+    /// primary key column, which large values on object storage benefit from most.
+    pub fn page_index(self) -> Self {
+        DbOption {
+            page_index: true,
+            ..self
+        }
+    }
+
+    /// Compress the SSTables of `level` with `compression` instead of the codec of
+    /// [`DbOption::write_parquet_option`], LZ4 by default. Level 0 holds the flushed memtables,
+    /// which are soon compacted again, so a fast codec suits it while deeper levels keep most of
+    /// the data and compress better with e.g. `Compression::ZSTD`.
+    pub fn level_compression(
+        mut self,
+        level: usize,
+        compression: Compression,
+    ) -> Result<Self, ExceedsMaxLevel> {
+        if level >= MAX_LEVEL {
+            return Err(ExceedsMaxLevel);
+        }
+        self.level_compressions[level].compression = Some(compression);
+        Ok(self)
+    }
+
+    /// Compress the `column` of the SSTables of `level` with `compression`, over the codec of
+    /// the level.
+    pub fn level_column_compression(
+        mut self,
+        level: usize,
+        column: ColumnPath,
+        compression: Compression,
+    ) -> Result<Self, ExceedsMaxLevel> {
+        if level >= MAX_LEVEL {
+            return Err(ExceedsMaxLevel);
+        }
+        let columns = &mut self.level_compressions[level].columns;
+        columns.retain(|(path, _)| path != &column);
+        columns.push((column, compression));
+        Ok(self)
+    }
+
     /// set the base path option.
     ///
     /// This will be the default option for all wal, manifest and SSTables. Use
@@ -203,12 +662,403 @@ impl DbOption {
             ..self
         }
     }
+
+    /// Bound the bytes major compactions read from and write to the SSTables to
+    /// `bytes_per_sec`, unlimited by default. The reads served by the block, metadata or disk
+    /// caches are not charged. Flushes of the memtables are never throttled, so writes are not
+    /// held back by a slow compaction sharing the storage, and compactions keep leaving room to
+    /// the reads of the application.
+    ///
+    /// The limit is not enforced on wasm32, which has no timer.
+    pub fn compaction_rate_limit(self, bytes_per_sec: u64) -> Self {
+        Self {
+            compaction_rate_limit: Some(bytes_per_sec),
+            ..self
+        }
+    }
+
+    /// Bound the requests the reads of the application, gets and scans, send to the storage of
+    /// the SSTables to `limit`, unlimited by default. The reads served by the block, metadata
+    /// or disk caches are not charged.
+    ///
+    /// The limits are token buckets holding a second of their rates, so a short burst of reads
+    /// is sent at once. With [`DbOption::background_rate_limit`], the application and the
+    /// background jobs share the budget of a storage, e.g. the requests per second of a bucket
+    /// of S3, without a compaction starving the reads. Not enforced on wasm32, which has no timer.
+    pub fn scan_rate_limit(self, limit: StorageRateLimit) -> Self {
+        Self {
+            scan_rate_limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Bound the requests the background jobs send to the storage to `limit` apart from
+    /// [`DbOption::scan_rate_limit`], unlimited by default: the reads and the writes of the
+    /// SSTables by major compactions, also of a
+    /// [`CompactionWorker`](crate::offload::CompactionWorker), and the removals of the tables
+    /// they replace. A compaction is charged in addition to
+    /// [`DbOption::compaction_rate_limit`].
+    ///
+    /// Flushes are not charged, as for [`DbOption::compaction_rate_limit`], so writes are not
+    /// stalled by a busy budget. Not enforced on wasm32, which has no timer.
+    pub fn background_rate_limit(self, limit: StorageRateLimit) -> Self {
+        Self {
+            background_rate_limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Rewrite the SSTables written more than `period` ago, disabled by default.
+    ///
+    /// Tables are rewritten in their own level, and those of level 0 are moved to level 1, so TTL
+    /// expiry, the compaction filter and the current [`WriterProperties`] apply to key ranges
+    /// that never receive new writes. Cold
+    /// tables are checked whenever the compactor runs, after flushes and on
+    /// [`DB::flush`](crate::DB::flush). Tables of [`CompactionOption::Fifo`] are never rewritten,
+    /// as their age decides when they are dropped.
+    pub fn periodic_compaction(self, period: Duration) -> Self {
+        Self {
+            periodic_compaction: Some(period),
+            ..self
+        }
+    }
+
+    /// Compact the SSTables whose rows are tombstones for at least `ratio`, between 0 and 1,
+    /// disabled by default.
+    ///
+    /// Such a table is merged into the next level, where the tombstones older than
+    /// [`DbOption::time_travel_retention`] are dropped along with the versions they hide unless
+    /// a deeper level may still hold the key, so scans stop reading them soon after bulk
+    /// deletes. Tables are checked whenever the compactor runs, tables written before the
+    /// tombstones were counted and by bulk loads are never picked. Like
+    /// [`DbOption::periodic_compaction`], it does not apply to [`CompactionOption::Fifo`].
+    pub fn tombstone_compaction_ratio(self, ratio: f64) -> Self {
+        Self {
+            tombstone_compaction_ratio: Some(ratio),
+            ..self
+        }
+    }
+
+    /// publish table statistics to `catalog_sink` after every flush and compaction
+    pub fn catalog_sink(self, catalog_sink: Arc<dyn CatalogSink>) -> Self {
+        Self {
+            catalog_sink: Some(catalog_sink),
+            ..self
+        }
+    }
+
+    /// Cache the pages read from the SSTables in the directory `path` of the local disk, up to
+    /// `capacity` bytes, evicting the least recently read pages first. Meant for the levels
+    /// stored on an object store with [`DbOption::level_path`]: pages read again are served from
+    /// the disk instead of being fetched from the object store. The pages cached before are
+    /// removed when the [`DB`](crate::DB) is opened, the other files of `path` are left alone.
+    pub fn disk_cache(self, path: Path, capacity: u64) -> Self {
+        Self {
+            disk_cache: Some((path, capacity)),
+            ..self
+        }
+    }
+
+    /// Cache the column chunks read from the SSTables in memory, up to `capacity` bytes,
+    /// evicting the least recently read blocks first. The cache is shared by every scan of the
+    /// [`DB`](crate::DB) and read before the [`DbOption::disk_cache`], see
+    /// [`DB::block_cache_stats`](crate::DB::block_cache_stats). The parsed footers are cached by
+    /// the [`DbOption::metadata_cache`].
+    pub fn block_cache(self, capacity: u64) -> Self {
+        Self {
+            block_cache: Some(capacity),
+            ..self
+        }
+    }
+
+    /// Cache the parsed footers of the SSTables in memory, up to `capacity` bytes of
+    /// [`ParquetMetaData`](parquet::file::metadata::ParquetMetaData), evicting the least recently
+    /// read ones first. Gets skip fetching and parsing the footer of the tables they read again,
+    /// two round trips with the levels on an object store. The footers are cached on their own,
+    /// scans filling the [`DbOption::block_cache`] don't evict them, see
+    /// [`DB::metadata_cache_stats`](crate::DB::metadata_cache_stats).
+    pub fn metadata_cache(self, capacity: u64) -> Self {
+        Self {
+            metadata_cache: Some(capacity),
+            ..self
+        }
+    }
+
+    /// Prefetch the row groups a scan reads next while it decodes the current one, up to
+    /// `concurrency` row groups at once and `bytes` of column chunks not read yet, overlapping the
+    /// latency of an object store with the decoding. Every row group prefetched at once is read
+    /// with its own reader of the table. Only the columns the scan read in the first row group
+    /// of a table are prefetched, gets are not read ahead.
+    pub fn scan_readahead(self, bytes: u64, concurrency: usize) -> Self {
+        Self {
+            scan_readahead: Some((bytes, concurrency)),
+            ..self
+        }
+    }
+
+    /// Read up to `scan_parallelism` SSTables of a level at once in a scan: while the merge reads
+    /// a table, the next ones are opened and their first row group fetched and decoded, using
+    /// the bandwidth of an object store. Their entries are still yielded in the order of the
+    /// level. Defaults to 1, reading the tables one after the other.
+    pub fn scan_parallelism(self, scan_parallelism: usize) -> Self {
+        Self {
+            scan_parallelism: scan_parallelism.max(1),
+            ..self
+        }
+    }
+
+    /// Keep up to `max_open_tables` SSTables open between reads with their footers, closing the
+    /// least recently read ones first, so that gets and scans reading a table again neither open
+    /// it nor read its footer. The tables being read count into the limit and are never closed
+    /// early, see [`DB::table_cache_stats`](crate::DB::table_cache_stats). Compactions open the
+    /// tables they merge on their own.
+    pub fn max_open_tables(self, max_open_tables: usize) -> Self {
+        Self {
+            max_open_tables: Some(max_open_tables),
+            ..self
+        }
+    }
+
+    /// Count the memtables of the [`DB`](crate::DB) into `memory_budget`, shared with other
+    /// [`DB`](crate::DB)s, e.g. the tables of [`Tables`](crate::tables::Tables). Once the
+    /// memtables of all of them exceed the budget, the one holding the most is flushed early,
+    /// see [`MemoryBudget`].
+    pub fn memory_budget(self, memory_budget: MemoryBudget) -> Self {
+        Self {
+            memory_budget: Some(memory_budget),
+            ..self
+        }
+    }
+
+    /// Encrypt the SSTables and the WAL segments with AES-GCM and the keys of `key_provider`.
+    /// SSTables use Parquet modular encryption and record the id of their key in their footer,
+    /// every WAL log is sealed on its own. The manifest is not encrypted, nor are the files of
+    /// [`SsTableWriter`](crate::bulk::SsTableWriter), which are encrypted once ingested and
+    /// compacted. See [`KeyProvider`] for key rotation.
+    #[cfg(feature = "encryption")]
+    pub fn encryption(self, key_provider: Arc<dyn KeyProvider>) -> Self {
+        Self {
+            encryption: Some(key_provider),
+            ..self
+        }
+    }
+
+    /// notify `event_listener` of flushes, compactions and table deletions
+    pub fn event_listener(self, event_listener: Arc<dyn EventListener>) -> Self {
+        Self {
+            event_listener: Some(event_listener),
+            ..self
+        }
+    }
+
+    /// number of most recent timestamps that can be read with
+    /// [`DB::snapshot_at`](crate::DB::snapshot_at), default value is 0
+    ///
+    /// Major compaction keeps every version written within the window, so a larger window costs
+    /// more space for frequently updated keys.
+    pub fn time_travel_retention(self, time_travel_retention: u32) -> Self {
+        Self {
+            time_travel_retention,
+            ..self
+        }
+    }
+
+    /// maximum number of distinct keys written by a single
+    /// [`Transaction`](crate::transaction::Transaction), unlimited by default
+    pub fn transaction_max_rows(self, transaction_max_rows: usize) -> Self {
+        Self {
+            transaction_max_rows: Some(transaction_max_rows),
+            ..self
+        }
+    }
+
+    /// maximum size in bytes of the records written by a single
+    /// [`Transaction`](crate::transaction::Transaction), unlimited by default. A removal
+    /// counts the size of its key.
+    pub fn transaction_max_bytes(self, transaction_max_bytes: usize) -> Self {
+        Self {
+            transaction_max_bytes: Some(transaction_max_bytes),
+            ..self
+        }
+    }
+
+    /// default value is [`IsolationLevel::Snapshot`].
+    ///
+    /// With [`IsolationLevel::Serializable`] transactions which commit writes into the keys and
+    /// ranges a transaction read since its snapshot abort it with
+    /// [`CommitError::ReadConflict`](crate::transaction::CommitError::ReadConflict), which
+    /// rules out write skews, e.g. two transactions checking a value is unused before both
+    /// inserting it. The guarantee holds among transactions, writes outside of them like
+    /// [`DB::insert`](crate::DB::insert) are not ordered with the commits. Serializable commits
+    /// are validated one at a time, and a read range costs a walk over the memtables at commit.
+    pub fn isolation_level(self, isolation_level: IsolationLevel) -> Self {
+        Self {
+            isolation_level,
+            ..self
+        }
+    }
+
+    /// how [`DB::run_txn`](crate::DB::run_txn) retries conflicting transactions, default value
+    /// is [`TransactionRetry::default`]: 10 attempts waiting from 1ms up to 100ms between them
+    pub fn transaction_retry(self, transaction_retry: TransactionRetry) -> Self {
+        Self {
+            transaction_retry,
+            ..self
+        }
+    }
+
+    /// Send the requests to the storage again as `retry_policy` tells when they fail on a
+    /// transient error: the reads of the SSTables, the opening of a table, and the listings and
+    /// removals of the cleaner and [`DB::collect_garbage`](crate::DB::collect_garbage). A
+    /// missing file or a denied request fails at once. The requests are sent once by default.
+    ///
+    /// The SSTables of flushes and compactions and the whole version logs, i.e. the snapshots
+    /// and the copies pushed to the base storage, are written again from the start, without
+    /// `timeout`. The edits appended to the version log and the WAL are not retried.
+    pub fn retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// default value is [`UpdateStrategy::CopyOnWrite`].
+    ///
+    /// With [`UpdateStrategy::MergeOnRead`] a column cannot be set back to null by a write,
+    /// remove the record first to start over from an empty row. Reads have to look up every
+    /// version of a key down to its last removal until compactions merge them.
+    pub fn update_strategy(self, update_strategy: UpdateStrategy) -> Self {
+        Self {
+            update_strategy,
+            ..self
+        }
+    }
+
+    /// Guard the path of [`DB`](crate::DB) against a second writer, e.g. one more process
+    /// started on a shared bucket: opening takes a lease of `duration` on the path and fails with
+    /// [`DbError::AlreadyOwned`](crate::DbError::AlreadyOwned) while another process holds it.
+    /// On the local disk the lease is written to lease files created exclusively, so of two
+    /// processes taking it at once exactly one gets it. On an object store it needs a
+    /// [`DbOption::lease_store`], opening fails with
+    /// [`DbError::Lease`](crate::DbError::Lease) without one. No lease is taken by default.
+    ///
+    /// The lease is renewed in the background and released once the [`DB`](crate::DB) is
+    /// dropped. The writes fail with [`DbError::AlreadyOwned`](crate::DbError::AlreadyOwned)
+    /// once the lease expires without being renewed, e.g. on a stalled process, and a path left
+    /// by a crashed process is opened again after its lease expires. The edits of the version
+    /// log are written with the fencing epoch of the lease, so the edits a stalled process writes
+    /// after the next holder opened the path are left out on recovery. Leases are not renewed on
+    /// wasm32, where opening with one fails with [`DbError::Lease`](crate::DbError::Lease).
+    /// [`DB::open_read_only`](crate::DB::open_read_only) takes no lease.
+    pub fn lease(self, duration: Duration) -> Self {
+        Self {
+            lease: Some(duration),
+            ..self
+        }
+    }
+
+    /// Keep the lease of [`DbOption::lease`] in `lease_store` instead of the lease file, e.g. a
+    /// DynamoDB table or etcd, whose conditional writes let exactly one of two processes racing
+    /// on the same path hold it. The version log is only written while the lease is held, with
+    /// the fencing epoch returned by [`LeaseStore::take`].
+    pub fn lease_store(self, lease_store: Arc<dyn LeaseStore>) -> Self {
+        Self {
+            lease_store: Some(lease_store),
+            ..self
+        }
+    }
+
+    /// Run [`DB::collect_garbage`](crate::DB::collect_garbage) every `interval` in the
+    /// background, except on wasm32, removing the SSTables and WAL segments left behind by
+    /// crashes, e.g. mid-compaction on S3. Orphan files are only removed on demand by default.
+    pub fn gc_interval(self, interval: Duration) -> Self {
+        Self {
+            gc_interval: Some(interval),
+            ..self
+        }
+    }
+
+    /// Keep the files younger than `grace` from the garbage collection, one hour by default. The
+    /// tables written by a flush or a compaction are only referred to once it is done, so the
+    /// grace must be longer than the slowest compaction.
+    pub fn gc_grace(self, grace: Duration) -> Self {
+        Self {
+            gc_grace: grace,
+            ..self
+        }
+    }
+
+    /// Throttle the writes of [`DB`](crate::DB) while flushes or compactions fall behind, so
+    /// bursts of writes do not grow the memtables and level 0 without bound. Writes are never
+    /// stalled by default.
+    ///
+    /// Writes of the [`DB`](crate::DB) stall when they start, commits of transactions are not
+    /// stalled.
+    pub fn write_stall(self, write_stall: WriteStall) -> Self {
+        Self {
+            write_stall: Some(write_stall),
+            ..self
+        }
+    }
+
+    /// the structure of the mutable memtable, default value is [`MemTableKind::SkipList`]. The
+    /// immutable memtables and the SSTables do not depend on it.
+    pub fn memtable(self, memtable: MemTableKind) -> Self {
+        Self { memtable, ..self }
+    }
+
+    /// split the mutable memtable by the hash of the keys into `memtable_shards` maps, each
+    /// synchronized on its own, so concurrent writers of different keys do not contend on one
+    /// map, default value is 1. Scans merge the shards. With the WAL enabled the writes are
+    /// still appended to the one WAL segment in turn.
+    pub fn memtable_shards(self, memtable_shards: usize) -> Self {
+        Self {
+            memtable_shards: memtable_shards.max(1),
+            ..self
+        }
+    }
+
+    /// Freeze the mutable memtable once any limit of `flush_policy` is reached, and flush the
+    /// immutable memtables once more than [`FlushPolicy::max_immutables`] wait. By default the
+    /// memtable is frozen at 64 MiB and flushed once more than 5 immutable memtables wait.
+    pub fn flush_policy(self, flush_policy: FlushPolicy) -> Self {
+        let immutable_chunk_max_num = flush_policy
+            .max_immutables
+            .unwrap_or(self.immutable_chunk_max_num);
+        Self {
+            trigger_type: flush_policy
+                .max_size
+                .map_or(self.trigger_type, TriggerType::SizeOfMem),
+            // a flush takes the oldest `immutable_chunk_num` of the waiting memtables
+            immutable_chunk_num: self.immutable_chunk_num.min(immutable_chunk_max_num + 1),
+            immutable_chunk_max_num,
+            flush_policy,
+            ..self
+        }
+    }
+
+    /// Run the flushes, compactions and garbage collections of the [`DB`](crate::DB) in
+    /// `task_pool`, shared with other [`DB`](crate::DB)s, by their
+    /// [`Priority`](crate::executor::pool::Priority). They run as soon as they are due by default.
+    pub fn task_pool(self, task_pool: TaskPool) -> Self {
+        Self {
+            task_pool: Some(task_pool),
+            ..self
+        }
+    }
 }
 
 #[derive(Debug, Error)]
 #[error("exceeds max level, max level is {}", MAX_LEVEL)]
 pub struct ExceedsMaxLevel;
 
+/// the codecs of a level set over the [`WriterProperties`] of the [`DbOption`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct LevelCompression {
+    compression: Option<Compression>,
+    columns: Vec<(ColumnPath, Compression)>,
+}
+
 impl DbOption {
     pub(crate) fn table_path(&self, gen: FileId, level: usize) -> Path {
         self.level_paths[level]
@@ -219,14 +1069,29 @@ impl DbOption {
     }
 
     pub(crate) fn wal_dir_path(&self) -> Path {
-        self.base_path.child("wal")
+        match &self.wal_path {
+            Some((path, _)) => path.clone(),
+            None => self.base_path.child("wal"),
+        }
+    }
+
+    /// the fs of [`DbOption::wal_dir_path`]
+    pub(crate) fn wal_fs(&self) -> &FsOptions {
+        self.wal_path
+            .as_ref()
+            .map_or(&self.base_fs, |(_, fs_options)| fs_options)
     }
 
-    pub(crate) fn wal_path(&self, gen: FileId) -> Path {
+    pub(crate) fn wal_segment_path(&self, gen: FileId) -> Path {
         self.wal_dir_path()
             .child(format!("{}.{}", gen, FileType::Wal))
     }
 
+    /// a commit returns once its logs are written to the WAL segment
+    pub(crate) fn wal_syncs_commits(&self) -> bool {
+        self.wal_sync_mode == WalSyncMode::Commit || self.wal_group_commit.is_some()
+    }
+
     pub(crate) fn version_log_dir_path(&self) -> Path {
         self.base_path.child("version")
     }
@@ -236,10 +1101,65 @@ impl DbOption {
             .child(format!("{}.{}", gen, FileType::Log))
     }
 
+    pub(crate) fn lease_path(&self) -> Path {
+        self.base_path.child("LEASE")
+    }
+
     pub(crate) fn level_fs_path(&self, level: usize) -> Option<&Path> {
         self.level_paths[level].as_ref().map(|(path, _)| path)
     }
 
+    /// whether a table of `level` moves to the next level as it is: both levels keep their
+    /// tables at the same path and write them with the same codecs
+    pub(crate) fn is_movable(&self, level: usize) -> bool {
+        self.level_fs_path(level) == self.level_fs_path(level + 1)
+            && self.level_compressions[level] == self.level_compressions[level + 1]
+    }
+
+    /// the [`WriterProperties`] the SSTables of `level` are written with
+    pub(crate) fn parquet_properties(&self, level: usize) -> WriterProperties {
+        let level_compression = &self.level_compressions[level];
+        let mut builder = self.write_parquet_properties.clone().into_builder();
+        if let Some(max_row_group_size) = self.max_row_group_size {
+            builder = builder.set_max_row_group_size(max_row_group_size);
+        }
+        if let Some(data_page_size) = self.data_page_size {
+            builder = builder.set_data_page_size_limit(data_page_size);
+        }
+        if self.page_index {
+            builder = builder.set_statistics_enabled(EnabledStatistics::Page);
+        }
+        if let Some(compression) = level_compression.compression {
+            builder = builder.set_compression(compression);
+        }
+        for (column, compression) in level_compression.columns.iter() {
+            builder = builder.set_column_compression(column.clone(), *compression);
+        }
+        builder.build()
+    }
+
+    /// the [`WriterProperties`] the SSTables of `level` are written with by the [`DB`](crate::DB),
+    /// which are encrypted with the current key of [`DbOption::encryption`]
+    pub(crate) fn table_properties(&self, level: usize) -> Result<WriterProperties, ParquetError> {
+        let properties = self.parquet_properties(level);
+        Ok(match &self.encryption {
+            Some(key_provider) => {
+                encryption::encrypt_parquet(properties.into_builder(), key_provider.as_ref())?
+                    .build()
+            }
+            None => properties,
+        })
+    }
+
+    /// major compaction keeps the versions newer than the returned watermark when `ts` is the
+    /// latest timestamp, see [`DbOption::time_travel_retention`]
+    pub(crate) fn retention_watermark(&self, ts: Timestamp) -> Timestamp {
+        match self.time_travel_retention {
+            0 => u32::MAX.into(),
+            retention => u32::from(ts).saturating_sub(retention).into(),
+        }
+    }
+
     pub(crate) fn is_threshold_exceeded_major<R: Record>(
         &self,
         version: &Version<R>,
@@ -255,6 +1175,7 @@ impl Debug for DbOption {
         f.debug_struct("DbOption")
             .field("clean_channel_buffer", &self.clean_channel_buffer)
             .field("base_path", &self.base_path)
+            .field("wal_path", &self.wal_path.as_ref().map(|(path, _)| path))
             // TODO
             // .field("level_paths", &self.level_paths)
             .field("immutable_chunk_num", &self.immutable_chunk_num)
@@ -273,13 +1194,88 @@ impl Debug for DbOption {
                 &self.major_threshold_with_sst_size,
             )
             .field("max_sst_file_size", &self.max_sst_file_size)
+            .field("max_subcompactions", &self.max_subcompactions)
             .field(
                 "version_log_snapshot_threshold",
                 &self.version_log_snapshot_threshold,
             )
             .field("trigger_type", &self.trigger_type)
             .field("use_wal", &self.use_wal)
+            .field("read_only", &self.read_only)
+            .field("lease", &self.lease)
+            .field("lease_store", &self.lease_store)
+            .field("gc_interval", &self.gc_interval)
+            .field("gc_grace", &self.gc_grace)
+            .field("wal_group_commit", &self.wal_group_commit)
+            .field("wal_sync_mode", &self.wal_sync_mode)
+            .field("wal_compression", &self.wal_compression)
+            .field("wal_archiver", &self.wal_archiver)
             .field("write_parquet_properties", &self.write_parquet_properties)
+            .field("level_compressions", &self.level_compressions)
+            .field("max_row_group_size", &self.max_row_group_size)
+            .field("data_page_size", &self.data_page_size)
+            .field("page_index", &self.page_index)
+            .field("compaction_rate_limit", &self.compaction_rate_limit)
+            .field("scan_rate_limit", &self.scan_rate_limit)
+            .field("background_rate_limit", &self.background_rate_limit)
+            .field("periodic_compaction", &self.periodic_compaction)
+            .field(
+                "tombstone_compaction_ratio",
+                &self.tombstone_compaction_ratio,
+            )
+            .field("catalog_sink", &self.catalog_sink)
+            .field("disk_cache", &self.disk_cache)
+            .field("block_cache", &self.block_cache)
+            .field("max_open_tables", &self.max_open_tables)
+            .field("metadata_cache", &self.metadata_cache)
+            .field("scan_readahead", &self.scan_readahead)
+            .field("scan_parallelism", &self.scan_parallelism)
+            .field("memory_budget", &self.memory_budget)
+            .field("encryption", &self.encryption)
+            .field("event_listener", &self.event_listener)
+            .field("time_travel_retention", &self.time_travel_retention)
+            .field("transaction_max_rows", &self.transaction_max_rows)
+            .field("transaction_max_bytes", &self.transaction_max_bytes)
+            .field("isolation_level", &self.isolation_level)
+            .field("transaction_retry", &self.transaction_retry)
+            .field("retry_policy", &self.retry_policy)
+            .field("update_strategy", &self.update_strategy)
+            .field("write_stall", &self.write_stall)
+            .field("memtable", &self.memtable)
+            .field("memtable_shards", &self.memtable_shards)
+            .field("flush_policy", &self.flush_policy)
+            .field("task_pool", &self.task_pool)
             .finish()
     }
 }
+
+/// configure a single read view of the [`DB`](crate::DB), see
+/// [`DB::snapshot_with`](crate::DB::snapshot_with)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadOptions {
+    pub(crate) max_staleness: Option<Duration>,
+    pub(crate) deadline: Option<Instant>,
+}
+
+impl ReadOptions {
+    /// allow the read to be served from a version pinned up to `max_staleness` ago instead of
+    /// synchronizing with the latest commits. The read may miss data written during that window.
+    pub fn max_staleness(self, max_staleness: Duration) -> Self {
+        Self {
+            max_staleness: Some(max_staleness),
+            ..self
+        }
+    }
+
+    /// give up reading SSTables once `deadline` passes: no further request is sent to the
+    /// storage and the read fails with
+    /// [`DbError::DeadlineExceeded`](crate::DbError::DeadlineExceeded), or with a
+    /// [`ParquetError`](parquet::errors::ParquetError) yielded by the stream of a scan. Dropping
+    /// the read cancels the requests in flight. Deadlines are ignored on wasm32-unknown-unknown.
+    pub fn deadline(self, deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+}
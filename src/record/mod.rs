@@ -35,6 +35,12 @@ pub trait Schema: Debug + Send + Sync {
     /// the location of the primary key column in the parquet schema and the sort order within a
     /// RowGroup of a leaf column
     fn primary_key_path(&self) -> (ColumnPath, Vec<SortingColumn>);
+
+    /// Returns the index of the column holding the expiry of records, see
+    /// [`RecordRef::expire_at`]. The column is always read, like the primary key column.
+    fn expire_at_index(&self) -> Option<usize> {
+        None
+    }
 }
 
 pub trait Record: 'static + Sized + Decode + Debug + Send + Sync {
@@ -66,18 +72,43 @@ pub trait RecordRef<'r>: Clone + Sized + Encode + Send + Sync {
 
     /// Do projection on the record. Only keep the columns specified in the projection mask.
     ///
-    /// **Note**: Primary key column are always kept.
+    /// **Note**: Primary key column are always kept. Columns excluded by the mask must end up
+    /// exactly as [`RecordRef::from_record_batch`] leaves them, so that a projected record has
+    /// the same [`Encode::size`] however it was produced.
     fn projection(&mut self, projection_mask: &ProjectionMask);
 
+    /// Returns when the record expires, in milliseconds since the unix epoch. Expired records
+    /// are invisible to reads and dropped by compactions, see [`crate::ttl`].
+    fn expire_at(&self) -> Option<u64> {
+        None
+    }
+
     /// Get the [`RecordRef`] from the [`RecordBatch`] at the given offset.
     ///
     /// `full_schema` is the combination of `_null`, `_ts` and all fields defined in the [`Schema`].
+    ///
+    /// **Note**: Primary key column is always read, even if it is not included in
+    /// `projection_mask`. Columns excluded by the mask are empty, the same as after
+    /// [`RecordRef::projection`].
     fn from_record_batch(
         record_batch: &'r RecordBatch,
         offset: usize,
         projection_mask: &'r ProjectionMask,
         full_schema: &'r Arc<ArrowSchema>,
     ) -> OptionRecordRef<'r, Self>;
+
+    /// Same as [`RecordRef::from_record_batch`], but the values of variable-length columns may
+    /// be borrowed from `record_batch` instead of copied, see
+    /// [`Scan::borrow_values`](crate::Scan::borrow_values). Records whose references borrow them
+    /// already, like the ones of `#[derive(Record)]`, keep the default.
+    fn from_record_batch_borrowed(
+        record_batch: &'r RecordBatch,
+        offset: usize,
+        projection_mask: &'r ProjectionMask,
+        full_schema: &'r Arc<ArrowSchema>,
+    ) -> OptionRecordRef<'r, Self> {
+        Self::from_record_batch(record_batch, offset, projection_mask, full_schema)
+    }
 }
 
 #[derive(Debug, Error)]
@@ -3,7 +3,7 @@ use std::{marker::PhantomData, mem::transmute};
 use super::{Key, Record, RecordRef, Schema};
 use crate::timestamp::{Timestamp, Ts};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OptionRecordRef<'r, R>
 where
     R: RecordRef<'r>,
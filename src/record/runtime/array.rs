@@ -1,10 +1,10 @@
-use std::{any::Any, mem, sync::Arc};
+use std::{mem, sync::Arc};
 
 use arrow::{
     array::{
-        Array, ArrayBuilder, ArrayRef, ArrowPrimitiveType, BooleanArray, BooleanBufferBuilder,
-        BooleanBuilder, GenericBinaryArray, GenericBinaryBuilder, PrimitiveArray, PrimitiveBuilder,
-        StringArray, StringBuilder, UInt32Builder,
+        Array, ArrayBuilder, ArrayRef, ArrowPrimitiveType, AsArray, BooleanArray,
+        BooleanBufferBuilder, BooleanBuilder, GenericBinaryBuilder, PrimitiveBuilder,
+        StringBuilder, UInt32Builder,
     },
     datatypes::{
         Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, Schema as ArrowSchema,
@@ -12,7 +12,12 @@ use arrow::{
     },
 };
 
-use super::{record::DynRecord, record_ref::DynRecordRef, value::Value, DataType};
+use super::{
+    record::DynRecord,
+    record_ref::DynRecordRef,
+    value::{Cell, Value, ValueDesc},
+    DataType,
+};
 use crate::{
     cast_arc_value,
     inmem::immutable::{ArrowArrays, Builder},
@@ -25,7 +30,7 @@ use crate::{
 pub struct DynRecordImmutableArrays {
     _null: Arc<arrow::array::BooleanArray>,
     _ts: Arc<arrow::array::UInt32Array>,
-    columns: Vec<Value>,
+    columns: Vec<(ValueDesc, ArrayRef)>,
     record_batch: arrow::record_batch::RecordBatch,
 }
 
@@ -135,126 +140,35 @@ impl ArrowArrays for DynRecordImmutableArrays {
             .parse::<usize>()
             .unwrap();
         let mut columns = vec![];
-        for (idx, col) in self.columns.iter().enumerate() {
-            if projection_mask.leaf_included(idx + USER_COLUMN_OFFSET) {
-                let datatype = col.datatype();
-                let name = col.desc.name.to_string();
-                let nullable = col.is_nullable();
-                let value: Arc<dyn Any + Send + Sync> = match &datatype {
-                    DataType::UInt8 => {
-                        let v = Self::primitive_value::<UInt8Type>(col, offset);
-                        if primary_key_index == idx {
-                            Arc::new(v)
-                        } else {
-                            Arc::new(Some(v))
-                        }
-                    }
-                    DataType::UInt16 => {
-                        let v = Self::primitive_value::<UInt16Type>(col, offset);
-                        if primary_key_index == idx {
-                            Arc::new(v)
-                        } else {
-                            Arc::new(Some(v))
-                        }
-                    }
-                    DataType::UInt32 => {
-                        let v = Self::primitive_value::<UInt32Type>(col, offset);
-                        if primary_key_index == idx {
-                            Arc::new(v)
-                        } else {
-                            Arc::new(Some(v))
-                        }
-                    }
-                    DataType::UInt64 => {
-                        let v = Self::primitive_value::<UInt64Type>(col, offset);
-                        if primary_key_index == idx {
-                            Arc::new(v)
-                        } else {
-                            Arc::new(Some(v))
-                        }
-                    }
-                    DataType::Int8 => {
-                        let v = Self::primitive_value::<Int8Type>(col, offset);
-                        if primary_key_index == idx {
-                            Arc::new(v)
-                        } else {
-                            Arc::new(Some(v))
-                        }
-                    }
-                    DataType::Int16 => {
-                        let v = Self::primitive_value::<Int16Type>(col, offset);
-                        if primary_key_index == idx {
-                            Arc::new(v)
-                        } else {
-                            Arc::new(Some(v))
-                        }
-                    }
-                    DataType::Int32 => {
-                        let v = Self::primitive_value::<Int32Type>(col, offset);
-                        if primary_key_index == idx {
-                            Arc::new(v)
-                        } else {
-                            Arc::new(Some(v))
-                        }
-                    }
-                    DataType::Int64 => {
-                        let v = Self::primitive_value::<Int64Type>(col, offset);
-                        if primary_key_index == idx {
-                            Arc::new(v)
-                        } else {
-                            Arc::new(Some(v))
-                        }
-                    }
-                    DataType::Float32 => {
-                        let v = Self::primitive_value::<Float32Type>(col, offset);
-                        if primary_key_index == idx {
-                            Arc::new(F32::from(v))
-                        } else {
-                            Arc::new(Some(F32::from(v)))
-                        }
-                    }
-                    DataType::Float64 => {
-                        let v = Self::primitive_value::<Float64Type>(col, offset);
-                        if primary_key_index == idx {
-                            Arc::new(F64::from(v))
-                        } else {
-                            Arc::new(Some(F64::from(v)))
-                        }
-                    }
-                    DataType::String => {
-                        let v = cast_arc_value!(col.value, StringArray)
-                            .value(offset)
-                            .to_owned();
-                        if primary_key_index == idx {
-                            Arc::new(v)
-                        } else {
-                            Arc::new(Some(v))
-                        }
-                    }
-                    DataType::Boolean => {
-                        let v = cast_arc_value!(col.value, BooleanArray).value(offset);
-                        if primary_key_index == idx {
-                            Arc::new(v)
-                        } else {
-                            Arc::new(Some(v))
-                        }
-                    }
-                    DataType::Bytes => {
-                        let v = cast_arc_value!(col.value, GenericBinaryArray<i32>)
-                            .value(offset)
-                            .to_owned();
-                        if primary_key_index == idx {
-                            Arc::new(v)
-                        } else {
-                            Arc::new(Some(v))
-                        }
-                    }
-                };
-
-                columns.push(Value::new(datatype, name, value, nullable));
-            } else {
-                columns.push(col.clone());
+        for (idx, (desc, col)) in self.columns.iter().enumerate() {
+            if !projection_mask.leaf_included(idx + USER_COLUMN_OFFSET) {
+                columns.push(Value::with_none_value(
+                    desc.datatype,
+                    desc.name.clone(),
+                    desc.is_nullable,
+                ));
+                continue;
             }
+            let value = match desc.datatype {
+                DataType::UInt8 => Cell::from(Self::primitive_value::<UInt8Type>(col, offset)),
+                DataType::UInt16 => Cell::from(Self::primitive_value::<UInt16Type>(col, offset)),
+                DataType::UInt32 => Cell::from(Self::primitive_value::<UInt32Type>(col, offset)),
+                DataType::UInt64 => Cell::from(Self::primitive_value::<UInt64Type>(col, offset)),
+                DataType::Int8 => Cell::from(Self::primitive_value::<Int8Type>(col, offset)),
+                DataType::Int16 => Cell::from(Self::primitive_value::<Int16Type>(col, offset)),
+                DataType::Int32 => Cell::from(Self::primitive_value::<Int32Type>(col, offset)),
+                DataType::Int64 => Cell::from(Self::primitive_value::<Int64Type>(col, offset)),
+                DataType::Float32 => {
+                    Cell::from(F32::from(Self::primitive_value::<Float32Type>(col, offset)))
+                }
+                DataType::Float64 => {
+                    Cell::from(F64::from(Self::primitive_value::<Float64Type>(col, offset)))
+                }
+                DataType::String => Cell::from(col.as_string::<i32>().value(offset).to_owned()),
+                DataType::Boolean => Cell::from(col.as_boolean().value(offset)),
+                DataType::Bytes => Cell::from(col.as_binary::<i32>().value(offset).to_owned()),
+            };
+            columns.push(Value::with_cell(desc.name.clone(), value, desc.is_nullable));
         }
         Some(Some(DynRecordRef::new(columns, primary_key_index)))
     }
@@ -264,11 +178,11 @@ impl ArrowArrays for DynRecordImmutableArrays {
     }
 }
 impl DynRecordImmutableArrays {
-    fn primitive_value<T>(col: &Value, offset: usize) -> T::Native
+    fn primitive_value<T>(col: &ArrayRef, offset: usize) -> T::Native
     where
         T: ArrowPrimitiveType,
     {
-        cast_arc_value!(col.value, PrimitiveArray<T>).value(offset)
+        col.as_primitive::<T>().value(offset)
     }
 }
 
@@ -567,7 +481,6 @@ impl Builder<DynRecordImmutableArrays> for DynRecordBuilder {
     }
 
     fn finish(&mut self, indices: Option<&[usize]>) -> DynRecordImmutableArrays {
-        let mut columns = vec![];
         let _null = Arc::new(BooleanArray::new(self._null.finish(), None));
         let _ts = Arc::new(self._ts.finish());
 
@@ -578,20 +491,12 @@ impl Builder<DynRecordImmutableArrays> for DynRecordBuilder {
             .zip(self.datatypes.iter())
             .enumerate()
         {
-            let field = self.schema.field(idx + USER_COLUMN_OFFSET);
-            let is_nullable = field.is_nullable();
             match datatype {
                 DataType::UInt8 => {
                     let value = Arc::new(
                         Self::as_builder_mut::<PrimitiveBuilder<UInt8Type>>(builder.as_mut())
                             .finish(),
                     );
-                    columns.push(Value::new(
-                        DataType::UInt8,
-                        field.name().to_owned(),
-                        value.clone(),
-                        is_nullable,
-                    ));
                     array_refs.push(value);
                 }
                 DataType::UInt16 => {
@@ -599,12 +504,6 @@ impl Builder<DynRecordImmutableArrays> for DynRecordBuilder {
                         Self::as_builder_mut::<PrimitiveBuilder<UInt16Type>>(builder.as_mut())
                             .finish(),
                     );
-                    columns.push(Value::new(
-                        DataType::UInt16,
-                        field.name().to_owned(),
-                        value.clone(),
-                        is_nullable,
-                    ));
                     array_refs.push(value);
                 }
                 DataType::UInt32 => {
@@ -612,12 +511,6 @@ impl Builder<DynRecordImmutableArrays> for DynRecordBuilder {
                         Self::as_builder_mut::<PrimitiveBuilder<UInt32Type>>(builder.as_mut())
                             .finish(),
                     );
-                    columns.push(Value::new(
-                        DataType::UInt32,
-                        field.name().to_owned(),
-                        value.clone(),
-                        is_nullable,
-                    ));
                     array_refs.push(value);
                 }
                 DataType::UInt64 => {
@@ -625,12 +518,6 @@ impl Builder<DynRecordImmutableArrays> for DynRecordBuilder {
                         Self::as_builder_mut::<PrimitiveBuilder<UInt64Type>>(builder.as_mut())
                             .finish(),
                     );
-                    columns.push(Value::new(
-                        DataType::UInt64,
-                        field.name().to_owned(),
-                        value.clone(),
-                        is_nullable,
-                    ));
                     array_refs.push(value);
                 }
                 DataType::Int8 => {
@@ -638,12 +525,6 @@ impl Builder<DynRecordImmutableArrays> for DynRecordBuilder {
                         Self::as_builder_mut::<PrimitiveBuilder<Int8Type>>(builder.as_mut())
                             .finish(),
                     );
-                    columns.push(Value::new(
-                        DataType::Int8,
-                        field.name().to_owned(),
-                        value.clone(),
-                        is_nullable,
-                    ));
                     array_refs.push(value);
                 }
                 DataType::Int16 => {
@@ -651,12 +532,6 @@ impl Builder<DynRecordImmutableArrays> for DynRecordBuilder {
                         Self::as_builder_mut::<PrimitiveBuilder<Int16Type>>(builder.as_mut())
                             .finish(),
                     );
-                    columns.push(Value::new(
-                        DataType::Int16,
-                        field.name().to_owned(),
-                        value.clone(),
-                        is_nullable,
-                    ));
                     array_refs.push(value);
                 }
                 DataType::Int32 => {
@@ -664,12 +539,6 @@ impl Builder<DynRecordImmutableArrays> for DynRecordBuilder {
                         Self::as_builder_mut::<PrimitiveBuilder<Int32Type>>(builder.as_mut())
                             .finish(),
                     );
-                    columns.push(Value::new(
-                        DataType::Int32,
-                        field.name().to_owned(),
-                        value.clone(),
-                        is_nullable,
-                    ));
                     array_refs.push(value);
                 }
                 DataType::Int64 => {
@@ -677,12 +546,6 @@ impl Builder<DynRecordImmutableArrays> for DynRecordBuilder {
                         Self::as_builder_mut::<PrimitiveBuilder<Int64Type>>(builder.as_mut())
                             .finish(),
                     );
-                    columns.push(Value::new(
-                        DataType::Int64,
-                        field.name().to_owned(),
-                        value.clone(),
-                        is_nullable,
-                    ));
                     array_refs.push(value);
                 }
                 DataType::Float32 => {
@@ -690,12 +553,6 @@ impl Builder<DynRecordImmutableArrays> for DynRecordBuilder {
                         Self::as_builder_mut::<PrimitiveBuilder<Float32Type>>(builder.as_mut())
                             .finish(),
                     );
-                    columns.push(Value::new(
-                        DataType::Float32,
-                        field.name().to_owned(),
-                        value.clone(),
-                        is_nullable,
-                    ));
                     array_refs.push(value);
                 }
                 DataType::Float64 => {
@@ -703,34 +560,16 @@ impl Builder<DynRecordImmutableArrays> for DynRecordBuilder {
                         Self::as_builder_mut::<PrimitiveBuilder<Float64Type>>(builder.as_mut())
                             .finish(),
                     );
-                    columns.push(Value::new(
-                        DataType::Float64,
-                        field.name().to_owned(),
-                        value.clone(),
-                        is_nullable,
-                    ));
                     array_refs.push(value);
                 }
                 DataType::String => {
                     let value =
                         Arc::new(Self::as_builder_mut::<StringBuilder>(builder.as_mut()).finish());
-                    columns.push(Value::new(
-                        DataType::String,
-                        field.name().to_owned(),
-                        value.clone(),
-                        is_nullable,
-                    ));
                     array_refs.push(value);
                 }
                 DataType::Boolean => {
                     let value =
                         Arc::new(Self::as_builder_mut::<BooleanBuilder>(builder.as_mut()).finish());
-                    columns.push(Value::new(
-                        DataType::Boolean,
-                        field.name().to_owned(),
-                        value.clone(),
-                        is_nullable,
-                    ));
                     array_refs.push(value);
                 }
                 DataType::Bytes => {
@@ -738,17 +577,26 @@ impl Builder<DynRecordImmutableArrays> for DynRecordBuilder {
                         Self::as_builder_mut::<GenericBinaryBuilder<i32>>(builder.as_mut())
                             .finish(),
                     );
-                    columns.push(Value::new(
-                        DataType::Bytes,
-                        field.name().to_owned(),
-                        value.clone(),
-                        is_nullable,
-                    ));
                     array_refs.push(value);
                 }
             };
         }
 
+        let columns = self
+            .schema
+            .fields()
+            .iter()
+            .zip(array_refs.iter())
+            .skip(USER_COLUMN_OFFSET)
+            .map(|(field, array)| {
+                let desc = ValueDesc::new(
+                    field.name().to_owned(),
+                    DataType::from(field.data_type()),
+                    field.is_nullable(),
+                );
+                (desc, array.clone())
+            })
+            .collect();
         let mut record_batch =
             arrow::record_batch::RecordBatch::try_new(self.schema.clone(), array_refs)
                 .expect("create record batch must be successful");
@@ -49,7 +49,7 @@ impl From<&ArrowDataType> for DataType {
     }
 }
 
-/// Cast the `Arc<dyn Any>` to the value of given type.
+/// Cast the [`Cell`] of a [`Value`] to the value of given type, see [`Cell::downcast_ref`].
 #[macro_export]
 macro_rules! cast_arc_value {
     ($value:expr, $type:ty) => {
@@ -1,13 +1,8 @@
-use std::sync::Arc;
-
 use fusio::SeqRead;
 use fusio_log::{Decode, Encode};
 
-use super::{schema::DynSchema, DataType, DynRecordRef, Value};
-use crate::{
-    cast_arc_value,
-    record::{Record, RecordDecodeError, F32, F64},
-};
+use super::{schema::DynSchema, DynRecordRef, Value};
+use crate::record::{Record, RecordDecodeError};
 
 #[derive(Debug)]
 pub struct DynRecord {
@@ -34,34 +29,9 @@ impl Decode for DynRecord {
     {
         let len = u32::decode(reader).await? as usize;
         let primary_index = u32::decode(reader).await? as usize;
-        let mut values = vec![];
-        // keep invariant for record: nullable --> Some(v); non-nullable --> v
-        for i in 0..len {
-            let mut col = Value::decode(reader).await?;
-            if i != primary_index && !col.is_nullable() {
-                col.value = match col.datatype() {
-                    DataType::UInt8 => Arc::new(cast_arc_value!(col.value, Option<u8>).unwrap()),
-                    DataType::UInt16 => Arc::new(cast_arc_value!(col.value, Option<u16>).unwrap()),
-                    DataType::UInt32 => Arc::new(cast_arc_value!(col.value, Option<u32>).unwrap()),
-                    DataType::UInt64 => Arc::new(cast_arc_value!(col.value, Option<u64>).unwrap()),
-                    DataType::Int8 => Arc::new(cast_arc_value!(col.value, Option<i8>).unwrap()),
-                    DataType::Int16 => Arc::new(cast_arc_value!(col.value, Option<i16>).unwrap()),
-                    DataType::Int32 => Arc::new(cast_arc_value!(col.value, Option<i32>).unwrap()),
-                    DataType::Int64 => Arc::new(cast_arc_value!(col.value, Option<i64>).unwrap()),
-                    DataType::Float32 => Arc::new(cast_arc_value!(col.value, Option<F32>).unwrap()),
-                    DataType::Float64 => Arc::new(cast_arc_value!(col.value, Option<F64>).unwrap()),
-                    DataType::String => {
-                        Arc::new(cast_arc_value!(col.value, Option<String>).clone().unwrap())
-                    }
-                    DataType::Boolean => {
-                        Arc::new(cast_arc_value!(col.value, Option<bool>).unwrap())
-                    }
-                    DataType::Bytes => {
-                        Arc::new(cast_arc_value!(col.value, Option<Vec<u8>>).clone().unwrap())
-                    }
-                };
-            }
-            values.push(col);
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len {
+            values.push(Value::decode(reader).await?);
         }
 
         Ok(DynRecord {
@@ -77,41 +47,7 @@ impl Record for DynRecord {
     type Ref<'r> = DynRecordRef<'r>;
 
     fn as_record_ref(&self) -> Self::Ref<'_> {
-        let mut columns = vec![];
-        for (idx, col) in self.values.iter().enumerate() {
-            let datatype = col.datatype();
-            let is_nullable = col.is_nullable();
-            let mut value = col.value.clone();
-            if idx != self.primary_index && !is_nullable {
-                value = match datatype {
-                    DataType::UInt8 => Arc::new(Some(*cast_arc_value!(col.value, u8))),
-                    DataType::UInt16 => Arc::new(Some(*cast_arc_value!(col.value, u16))),
-                    DataType::UInt32 => Arc::new(Some(*cast_arc_value!(col.value, u32))),
-                    DataType::UInt64 => Arc::new(Some(*cast_arc_value!(col.value, u64))),
-                    DataType::Int8 => Arc::new(Some(*cast_arc_value!(col.value, i8))),
-                    DataType::Int16 => Arc::new(Some(*cast_arc_value!(col.value, i16))),
-                    DataType::Int32 => Arc::new(Some(*cast_arc_value!(col.value, i32))),
-                    DataType::Int64 => Arc::new(Some(*cast_arc_value!(col.value, i64))),
-                    DataType::Float32 => Arc::new(Some(*cast_arc_value!(col.value, F32))),
-                    DataType::Float64 => Arc::new(Some(*cast_arc_value!(col.value, F64))),
-                    DataType::String => {
-                        Arc::new(Some(cast_arc_value!(col.value, String).to_owned()))
-                    }
-                    DataType::Boolean => Arc::new(Some(*cast_arc_value!(col.value, bool))),
-                    DataType::Bytes => {
-                        Arc::new(Some(cast_arc_value!(col.value, Vec<u8>).to_owned()))
-                    }
-                };
-            }
-
-            columns.push(Value::new(
-                datatype,
-                col.desc.name.to_owned(),
-                value,
-                is_nullable,
-            ));
-        }
-        DynRecordRef::new(columns, self.primary_index)
+        DynRecordRef::new(self.values.clone(), self.primary_index)
     }
 
     fn size(&self) -> usize {
@@ -163,12 +99,10 @@ macro_rules! dyn_record {
 
 #[cfg(test)]
 pub(crate) mod test {
-    use std::sync::Arc;
-
     use super::{DynRecord, DynSchema};
     use crate::{
         dyn_schema,
-        record::{F32, F64},
+        record::{Cell, F32, F64},
     };
 
     #[allow(unused)]
@@ -206,7 +140,7 @@ pub(crate) mod test {
                 0
             );
             if i >= 45 {
-                record.values[2].value = Arc::<Option<i16>>::new(None);
+                record.values[2].value = Cell::from(None::<i16>);
             }
 
             items.push(record);
@@ -1,7 +1,7 @@
-use std::{any::Any, marker::PhantomData, mem, sync::Arc};
+use std::{borrow::Cow, marker::PhantomData, mem, sync::Arc};
 
 use arrow::{
-    array::{Array, ArrayRef, ArrowPrimitiveType, AsArray},
+    array::{Array, ArrayRef, ArrowPrimitiveType, AsArray, RecordBatch},
     datatypes::{
         Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, Schema as ArrowSchema,
         UInt16Type, UInt32Type, UInt64Type, UInt8Type,
@@ -10,7 +10,7 @@ use arrow::{
 use fusio::Write;
 use fusio_log::Encode;
 
-use super::{DataType, DynRecord, Value};
+use super::{Cell, DataType, DynRecord, Value};
 use crate::{
     magic::USER_COLUMN_OFFSET,
     record::{
@@ -23,17 +23,93 @@ pub struct DynRecordRef<'r> {
     pub columns: Vec<Value>,
     // XXX: log encode should keep the same behavior
     pub primary_index: usize,
+    // the values of `columns` left in the record batch the record is read from, by column index
+    borrowed: Vec<(usize, Borrowed<'r>)>,
     _marker: PhantomData<&'r ()>,
 }
 
+#[derive(Clone, Copy)]
+enum Borrowed<'r> {
+    String(&'r str),
+    Bytes(&'r [u8]),
+}
+
 impl<'r> DynRecordRef<'r> {
     pub(crate) fn new(columns: Vec<Value>, primary_index: usize) -> Self {
         Self {
             columns,
             primary_index,
+            borrowed: Vec::new(),
             _marker: PhantomData,
         }
     }
+
+    /// The value of the `String` column at `index`, `None` when it is null or not projected.
+    ///
+    /// Records of a [`Scan::borrow_values`](crate::Scan::borrow_values) scan borrow it from the
+    /// record batch they are read from, their [`DynRecordRef::columns`] leave it empty.
+    ///
+    /// # Panics
+    ///
+    /// If the column at `index` is not a `String` column.
+    pub fn str(&self, index: usize) -> Option<&str> {
+        match self.borrowed(index) {
+            Some(Borrowed::String(value)) => Some(value),
+            _ => {
+                let value = &self.columns[index].value;
+                let value = value.downcast_ref::<String>().or_else(|| {
+                    value
+                        .downcast_ref::<Option<String>>()
+                        .expect("unexpected datatype, expected String")
+                        .as_ref()
+                });
+                value.map(String::as_str)
+            }
+        }
+    }
+
+    /// The value of the `Bytes` column at `index`, `None` when it is null or not projected.
+    /// Borrowed the same as [`DynRecordRef::str`].
+    ///
+    /// # Panics
+    ///
+    /// If the column at `index` is not a `Bytes` column.
+    pub fn bytes(&self, index: usize) -> Option<&[u8]> {
+        match self.borrowed(index) {
+            Some(Borrowed::Bytes(value)) => Some(value),
+            _ => {
+                let value = &self.columns[index].value;
+                let value = value.downcast_ref::<Vec<u8>>().or_else(|| {
+                    value
+                        .downcast_ref::<Option<Vec<u8>>>()
+                        .expect("unexpected datatype, expected Bytes")
+                        .as_ref()
+                });
+                value.map(Vec::as_slice)
+            }
+        }
+    }
+
+    fn borrowed(&self, index: usize) -> Option<Borrowed<'r>> {
+        self.borrowed
+            .iter()
+            .find(|(idx, _)| *idx == index)
+            .map(|(_, value)| *value)
+    }
+
+    /// the column at `index`, with its value copied out of the record batch if it is borrowed
+    fn owned_column(&self, index: usize) -> Cow<'_, Value> {
+        let column = &self.columns[index];
+        let value = match self.borrowed(index) {
+            Some(Borrowed::String(value)) => Cell::from(value.to_owned()),
+            Some(Borrowed::Bytes(value)) => Cell::from(value.to_owned()),
+            None => return Cow::Borrowed(column),
+        };
+        Cow::Owned(Value {
+            desc: column.desc.clone(),
+            value,
+        })
+    }
 }
 
 impl<'r> Encode for DynRecordRef<'r> {
@@ -45,16 +121,19 @@ impl<'r> Encode for DynRecordRef<'r> {
     {
         (self.columns.len() as u32).encode(writer).await?;
         (self.primary_index as u32).encode(writer).await?;
-        for col in self.columns.iter() {
-            col.encode(writer).await.map_err(RecordEncodeError::Fusio)?;
+        for idx in 0..self.columns.len() {
+            self.owned_column(idx)
+                .encode(writer)
+                .await
+                .map_err(RecordEncodeError::Fusio)?;
         }
         Ok(())
     }
 
     fn size(&self) -> usize {
         let mut size = 2 * mem::size_of::<u32>();
-        for col in self.columns.iter() {
-            size += col.size();
+        for idx in 0..self.columns.len() {
+            size += self.owned_column(idx).size();
         }
         size
     }
@@ -71,11 +150,46 @@ impl<'r> RecordRef<'r> for DynRecordRef<'r> {
     }
 
     fn from_record_batch(
-        record_batch: &'r arrow::array::RecordBatch,
+        record_batch: &'r RecordBatch,
+        offset: usize,
+        projection_mask: &'r parquet::arrow::ProjectionMask,
+        full_schema: &'r Arc<ArrowSchema>,
+    ) -> OptionRecordRef<'r, Self> {
+        Self::read(record_batch, offset, projection_mask, full_schema, false)
+    }
+
+    fn from_record_batch_borrowed(
+        record_batch: &'r RecordBatch,
         offset: usize,
         projection_mask: &'r parquet::arrow::ProjectionMask,
         full_schema: &'r Arc<ArrowSchema>,
     ) -> OptionRecordRef<'r, Self> {
+        Self::read(record_batch, offset, projection_mask, full_schema, true)
+    }
+
+    fn projection(&mut self, projection_mask: &parquet::arrow::ProjectionMask) {
+        for (idx, col) in self.columns.iter_mut().enumerate() {
+            if idx != self.primary_index && !projection_mask.leaf_included(idx + USER_COLUMN_OFFSET)
+            {
+                col.value = Cell::none(col.datatype());
+            }
+        }
+        self.borrowed.retain(|(idx, _)| {
+            *idx == self.primary_index || projection_mask.leaf_included(idx + USER_COLUMN_OFFSET)
+        });
+    }
+}
+
+impl<'r> DynRecordRef<'r> {
+    /// read the record at `offset`, `borrow` leaves the values of its `String` and `Bytes`
+    /// columns in `record_batch`
+    fn read(
+        record_batch: &'r RecordBatch,
+        offset: usize,
+        projection_mask: &'r parquet::arrow::ProjectionMask,
+        full_schema: &'r Arc<ArrowSchema>,
+        borrow: bool,
+    ) -> OptionRecordRef<'r, DynRecordRef<'r>> {
         let null = record_batch.column(0).as_boolean().value(offset);
         let metadata = full_schema.metadata();
 
@@ -91,6 +205,7 @@ impl<'r> RecordRef<'r> for DynRecordRef<'r> {
             .into();
 
         let mut columns = vec![];
+        let mut borrowed = Vec::new();
 
         for (idx, field) in full_schema.flattened_fields().iter().enumerate().skip(2) {
             let datatype = DataType::from(field.data_type());
@@ -171,59 +286,70 @@ impl<'r> RecordRef<'r> for DynRecordRef<'r> {
                     let v = col.as_primitive::<Float32Type>();
 
                     if primary_index == idx - 2 {
-                        Arc::new(F32::from(v.value(offset))) as Arc<dyn Any + Send + Sync>
+                        Cell::from(F32::from(v.value(offset)))
                     } else {
                         let value = (!v.is_null(offset) && projection_mask.leaf_included(idx))
                             .then_some(F32::from(v.value(offset)));
-                        Arc::new(value) as Arc<dyn Any + Send + Sync>
+                        Cell::from(value)
                     }
                 }
                 DataType::Float64 => {
                     let v = col.as_primitive::<Float64Type>();
 
                     if primary_index == idx - 2 {
-                        Arc::new(F64::from(v.value(offset))) as Arc<dyn Any + Send + Sync>
+                        Cell::from(F64::from(v.value(offset)))
                     } else {
                         let value = (!v.is_null(offset) && projection_mask.leaf_included(idx))
                             .then_some(F64::from(v.value(offset)));
-                        Arc::new(value) as Arc<dyn Any + Send + Sync>
+                        Cell::from(value)
                     }
                 }
                 DataType::String => {
                     let v = col.as_string::<i32>();
 
                     if primary_index == idx - 2 {
-                        Arc::new(v.value(offset).to_owned()) as Arc<dyn Any + Send + Sync>
+                        Cell::from(v.value(offset).to_owned())
                     } else {
                         let value = (!v.is_null(offset) && projection_mask.leaf_included(idx))
-                            .then_some(v.value(offset).to_owned());
-                        Arc::new(value) as Arc<dyn Any + Send + Sync>
+                            .then(|| v.value(offset));
+                        match value {
+                            Some(value) if borrow => {
+                                borrowed.push((idx - 2, Borrowed::String(value)));
+                                Cell::none(DataType::String)
+                            }
+                            value => Cell::from(value.map(str::to_owned)),
+                        }
                     }
                 }
                 DataType::Boolean => {
                     let v = col.as_boolean();
 
                     if primary_index == idx - 2 {
-                        Arc::new(v.value(offset).to_owned()) as Arc<dyn Any + Send + Sync>
+                        Cell::from(v.value(offset).to_owned())
                     } else {
                         let value = (!v.is_null(offset) && projection_mask.leaf_included(idx))
                             .then_some(v.value(offset).to_owned());
-                        Arc::new(value) as Arc<dyn Any + Send + Sync>
+                        Cell::from(value)
                     }
                 }
                 DataType::Bytes => {
                     let v = col.as_binary::<i32>();
                     if primary_index == idx - 2 {
-                        Arc::new(v.value(offset).to_owned()) as Arc<dyn Any + Send + Sync>
+                        Cell::from(v.value(offset).to_owned())
                     } else {
                         let value = (!v.is_null(offset) && projection_mask.leaf_included(idx))
-                            .then_some(v.value(offset).to_owned());
-                        Arc::new(value) as Arc<dyn Any + Send + Sync>
+                            .then(|| v.value(offset));
+                        match value {
+                            Some(value) if borrow => {
+                                borrowed.push((idx - 2, Borrowed::Bytes(value)));
+                                Cell::none(DataType::Bytes)
+                            }
+                            value => Cell::from(value.map(<[u8]>::to_vec)),
+                        }
                     }
                 }
             };
-            columns.push(Value::new(
-                datatype,
+            columns.push(Value::with_cell(
                 field.name().to_owned(),
                 value,
                 is_nullable,
@@ -233,62 +359,46 @@ impl<'r> RecordRef<'r> for DynRecordRef<'r> {
         let record = DynRecordRef {
             columns,
             primary_index,
+            borrowed,
             _marker: PhantomData,
         };
         OptionRecordRef::new(ts, record, null)
     }
 
-    fn projection(&mut self, projection_mask: &parquet::arrow::ProjectionMask) {
-        for (idx, col) in self.columns.iter_mut().enumerate() {
-            if idx != self.primary_index && !projection_mask.leaf_included(idx + USER_COLUMN_OFFSET)
-            {
-                match col.datatype() {
-                    DataType::UInt8 => col.value = Arc::<Option<u8>>::new(None),
-                    DataType::UInt16 => col.value = Arc::<Option<u16>>::new(None),
-                    DataType::UInt32 => col.value = Arc::<Option<u32>>::new(None),
-                    DataType::UInt64 => col.value = Arc::<Option<u64>>::new(None),
-                    DataType::Int8 => col.value = Arc::<Option<i8>>::new(None),
-                    DataType::Int16 => col.value = Arc::<Option<i16>>::new(None),
-                    DataType::Int32 => col.value = Arc::<Option<i32>>::new(None),
-                    DataType::Int64 => col.value = Arc::<Option<i64>>::new(None),
-                    DataType::Float32 => col.value = Arc::<Option<F32>>::new(None),
-                    DataType::Float64 => col.value = Arc::<Option<F64>>::new(None),
-                    DataType::String => col.value = Arc::<Option<String>>::new(None),
-                    DataType::Boolean => col.value = Arc::<Option<bool>>::new(None),
-                    DataType::Bytes => col.value = Arc::<Option<Vec<u8>>>::new(None),
-                };
-            }
-        }
-    }
-}
-
-impl<'r> DynRecordRef<'r> {
     fn primitive_value<T>(
         col: &ArrayRef,
         offset: usize,
         idx: usize,
         projection_mask: &'r parquet::arrow::ProjectionMask,
         primary: bool,
-    ) -> Arc<dyn Any + Send + Sync>
+    ) -> Cell
     where
         T: ArrowPrimitiveType,
+        Cell: From<Option<T::Native>>,
     {
         let v = col.as_primitive::<T>();
 
         if primary {
-            Arc::new(v.value(offset)) as Arc<dyn Any + Send + Sync>
+            Cell::from(Some(v.value(offset)))
         } else {
             let value = (!v.is_null(offset) && projection_mask.leaf_included(idx))
                 .then_some(v.value(offset));
-            Arc::new(value) as Arc<dyn Any + Send + Sync>
+            Cell::from(value)
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{
+        AsArray, BinaryArray, BooleanArray, Int64Array, RecordBatch, StringArray, UInt32Array,
+    };
+    use fusio_log::Encode;
     use parquet::arrow::{ArrowSchemaConverter, ProjectionMask};
 
+    use super::DynRecordRef;
     use crate::{
         cast_arc_value, dyn_record, dyn_schema,
         record::{Record, RecordRef, Schema, F32, F64},
@@ -423,4 +533,92 @@ mod tests {
             assert_eq!(*cast_arc_value!(columns[6].value, Option<Vec<u8>>), None);
         }
     }
+
+    #[test]
+    fn test_from_record_batch_borrowed() {
+        let schema = dyn_schema!(
+            ("id", String, false),
+            ("name", String, true),
+            ("data", Bytes, true),
+            0
+        );
+        let full_schema = schema.arrow_schema();
+        let batch = RecordBatch::try_new(
+            full_schema.clone(),
+            vec![
+                Arc::new(BooleanArray::from(vec![false])),
+                Arc::new(UInt32Array::from(vec![0])),
+                Arc::new(StringArray::from(vec!["key"])),
+                Arc::new(StringArray::from(vec![Some("Jack")])),
+                Arc::new(BinaryArray::from(vec![None::<&[u8]>])),
+            ],
+        )
+        .unwrap();
+        let mask = ProjectionMask::all();
+
+        let copied = DynRecordRef::from_record_batch(&batch, 0, &mask, full_schema)
+            .get()
+            .unwrap();
+        let borrowed = DynRecordRef::from_record_batch_borrowed(&batch, 0, &mask, full_schema)
+            .get()
+            .unwrap();
+        let name = batch.column(3).as_string::<i32>().value(0);
+        assert!(std::ptr::eq(borrowed.str(1).unwrap(), name));
+        assert!(!std::ptr::eq(copied.str(1).unwrap(), name));
+        assert_eq!(
+            *cast_arc_value!(borrowed.columns[1].value, Option<String>),
+            None
+        );
+        assert_eq!(borrowed.str(0), Some("key"));
+        assert_eq!(borrowed.bytes(2), None);
+        assert_eq!(borrowed.size(), copied.size());
+    }
+
+    #[test]
+    fn test_from_record_batch_matches_projection() {
+        let schema = dyn_schema!(
+            ("id", Int64, false),
+            ("foo", Float32, true),
+            ("bar", Float64, false),
+            0
+        );
+        let record = dyn_record!(
+            ("id", Int64, false, 1i64),
+            ("foo", Float32, true, Some(F32::from(1.5))),
+            ("bar", Float64, false, F64::from(2.5)),
+            0
+        );
+        let full_schema = schema.arrow_schema();
+        let mask = ProjectionMask::roots(
+            &ArrowSchemaConverter::new().convert(full_schema).unwrap(),
+            vec![0, 1, 2],
+        );
+        // a batch read with `mask` only carries `_null`, `_ts` and the primary key
+        let batch = RecordBatch::try_new(
+            Arc::new(full_schema.project(&[0, 1, 2]).unwrap()),
+            vec![
+                Arc::new(BooleanArray::from(vec![false])),
+                Arc::new(UInt32Array::from(vec![0])),
+                Arc::new(Int64Array::from(vec![1])),
+            ],
+        )
+        .unwrap();
+
+        let from_batch = DynRecordRef::from_record_batch(&batch, 0, &mask, full_schema)
+            .get()
+            .unwrap();
+        let mut projected = record.as_record_ref();
+        projected.projection(&mask);
+
+        assert_eq!(from_batch.size(), projected.size());
+        assert_eq!(*cast_arc_value!(from_batch.columns[0].value, i64), 1);
+        assert_eq!(
+            *cast_arc_value!(from_batch.columns[1].value, Option<F32>),
+            None
+        );
+        assert_eq!(
+            *cast_arc_value!(from_batch.columns[2].value, Option<F64>),
+            None
+        );
+    }
 }
@@ -33,6 +33,16 @@ impl DynSchema {
             arrow_schema,
         }
     }
+
+    /// the columns of the records, without `_null` and `_ts`
+    pub(crate) fn value_descs(&self) -> &[ValueDesc] {
+        &self.schema
+    }
+
+    /// the index of the primary key among [`DynSchema::value_descs`]
+    pub(crate) fn primary_index(&self) -> usize {
+        self.primary_index
+    }
 }
 
 impl Schema for DynSchema {
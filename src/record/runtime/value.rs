@@ -1,4 +1,9 @@
-use std::{any::Any, fmt::Debug, hash::Hash, sync::Arc};
+use std::{
+    any::Any,
+    fmt::Debug,
+    hash::Hash,
+    sync::{Arc, OnceLock},
+};
 
 use arrow::{
     array::{
@@ -49,10 +54,161 @@ impl ValueDesc {
     }
 }
 
+/// The value of a cell in a [`Value`].
+///
+/// Every datatype is stored inline, or behind a shared pointer for variable-length ones, so
+/// that a dynamic record does not need an allocation per column. A null cell is `None`,
+/// whether or not the column is nullable.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Cell {
+    UInt8(Option<u8>),
+    UInt16(Option<u16>),
+    UInt32(Option<u32>),
+    UInt64(Option<u64>),
+    Int8(Option<i8>),
+    Int16(Option<i16>),
+    Int32(Option<i32>),
+    Int64(Option<i64>),
+    Float32(Option<F32>),
+    Float64(Option<F64>),
+    String(Arc<Option<String>>),
+    Boolean(Option<bool>),
+    Bytes(Arc<Option<Vec<u8>>>),
+}
+
+/// Evaluate `$body` with `$value` bound to the `&Option<T>` held by the cell.
+macro_rules! with_cell {
+    ($cell:expr, $value:ident => $body:expr) => {
+        match $cell {
+            Cell::UInt8($value) => $body,
+            Cell::UInt16($value) => $body,
+            Cell::UInt32($value) => $body,
+            Cell::UInt64($value) => $body,
+            Cell::Int8($value) => $body,
+            Cell::Int16($value) => $body,
+            Cell::Int32($value) => $body,
+            Cell::Int64($value) => $body,
+            Cell::Float32($value) => $body,
+            Cell::Float64($value) => $body,
+            Cell::String($value) => {
+                let $value: &Option<String> = $value;
+                $body
+            }
+            Cell::Boolean($value) => $body,
+            Cell::Bytes($value) => {
+                let $value: &Option<Vec<u8>> = $value;
+                $body
+            }
+        }
+    };
+}
+
+impl Cell {
+    /// The null cell of `datatype`.
+    pub fn none(datatype: DataType) -> Self {
+        static NONE_STRING: OnceLock<Arc<Option<String>>> = OnceLock::new();
+        static NONE_BYTES: OnceLock<Arc<Option<Vec<u8>>>> = OnceLock::new();
+
+        match datatype {
+            DataType::UInt8 => Cell::UInt8(None),
+            DataType::UInt16 => Cell::UInt16(None),
+            DataType::UInt32 => Cell::UInt32(None),
+            DataType::UInt64 => Cell::UInt64(None),
+            DataType::Int8 => Cell::Int8(None),
+            DataType::Int16 => Cell::Int16(None),
+            DataType::Int32 => Cell::Int32(None),
+            DataType::Int64 => Cell::Int64(None),
+            DataType::Float32 => Cell::Float32(None),
+            DataType::Float64 => Cell::Float64(None),
+            DataType::String => Cell::String(NONE_STRING.get_or_init(|| Arc::new(None)).clone()),
+            DataType::Boolean => Cell::Boolean(None),
+            DataType::Bytes => Cell::Bytes(NONE_BYTES.get_or_init(|| Arc::new(None)).clone()),
+        }
+    }
+
+    pub fn datatype(&self) -> DataType {
+        match self {
+            Cell::UInt8(_) => DataType::UInt8,
+            Cell::UInt16(_) => DataType::UInt16,
+            Cell::UInt32(_) => DataType::UInt32,
+            Cell::UInt64(_) => DataType::UInt64,
+            Cell::Int8(_) => DataType::Int8,
+            Cell::Int16(_) => DataType::Int16,
+            Cell::Int32(_) => DataType::Int32,
+            Cell::Int64(_) => DataType::Int64,
+            Cell::Float32(_) => DataType::Float32,
+            Cell::Float64(_) => DataType::Float64,
+            Cell::String(_) => DataType::String,
+            Cell::Boolean(_) => DataType::Boolean,
+            Cell::Bytes(_) => DataType::Bytes,
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        with_cell!(self, value => value.is_none())
+    }
+
+    /// Returns the value as `&Option<T>` when `T` is `Option<_>`, or as `&T` when the cell is not
+    /// null. Returns `None` if `T` does not match the datatype of the cell.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        with_cell!(self, value => (value as &dyn Any).downcast_ref::<T>().or_else(|| {
+            value
+                .as_ref()
+                .and_then(|value| (value as &dyn Any).downcast_ref::<T>())
+        }))
+    }
+}
+
+impl AsRef<Cell> for Cell {
+    fn as_ref(&self) -> &Cell {
+        self
+    }
+}
+
+macro_rules! implement_cell {
+    ([], $({$Type:ty, $DataType:ident}), *) => {
+        $(
+            impl From<$Type> for Cell {
+                fn from(value: $Type) -> Self {
+                    Cell::$DataType(Some(value).into())
+                }
+            }
+
+            impl From<Option<$Type>> for Cell {
+                fn from(value: Option<$Type>) -> Self {
+                    Cell::$DataType(value.into())
+                }
+            }
+        )*
+
+        impl Cell {
+            /// Convert a type-erased `T` or `Option<T>` of `datatype` into a cell.
+            ///
+            /// # Panics
+            ///
+            /// Panics if the value is not of `datatype`.
+            pub fn from_any(datatype: DataType, value: Arc<dyn Any + Send + Sync>) -> Self {
+                match datatype {
+                    $(
+                        DataType::$DataType => match value.downcast::<Option<$Type>>() {
+                            Ok(value) => Cell::from(Arc::unwrap_or_clone(value)),
+                            Err(value) => Cell::from(Arc::unwrap_or_clone(
+                                value
+                                    .downcast::<$Type>()
+                                    .expect(stringify!("unexpected datatype, expected " $Type)),
+                            )),
+                        },
+                    )*
+                }
+            }
+        }
+    };
+}
+
 #[derive(Clone)]
 pub struct Value {
     pub desc: ValueDesc,
-    pub value: Arc<dyn Any + Send + Sync>,
+    pub value: Cell,
 }
 
 impl Value {
@@ -64,54 +220,22 @@ impl Value {
     ) -> Self {
         Self {
             desc: ValueDesc::new(name, datatype, is_nullable),
+            value: Cell::from_any(datatype, value),
+        }
+    }
+
+    pub fn with_cell(name: String, value: Cell, is_nullable: bool) -> Self {
+        Self {
+            desc: ValueDesc::new(name, value.datatype(), is_nullable),
             value,
         }
     }
 
+    /// the null value of a column, holding the same cell as a null read from a record batch or
+    /// decoded, e.g. `Option<F32>` for [`DataType::Float32`] rather than `Option<f32>`, so that a
+    /// column left out of a projection compares and encodes like any other null of its type
     pub(crate) fn with_none_value(datatype: DataType, name: String, is_nullable: bool) -> Self {
-        match datatype {
-            DataType::UInt8 => Self::new(datatype, name, Arc::<Option<u8>>::new(None), is_nullable),
-            DataType::UInt16 => {
-                Self::new(datatype, name, Arc::<Option<u16>>::new(None), is_nullable)
-            }
-            DataType::UInt32 => {
-                Self::new(datatype, name, Arc::<Option<u32>>::new(None), is_nullable)
-            }
-            DataType::UInt64 => {
-                Self::new(datatype, name, Arc::<Option<u64>>::new(None), is_nullable)
-            }
-            DataType::Int8 => Self::new(datatype, name, Arc::<Option<i8>>::new(None), is_nullable),
-            DataType::Int16 => {
-                Self::new(datatype, name, Arc::<Option<i16>>::new(None), is_nullable)
-            }
-            DataType::Int32 => {
-                Self::new(datatype, name, Arc::<Option<i32>>::new(None), is_nullable)
-            }
-            DataType::Int64 => {
-                Self::new(datatype, name, Arc::<Option<i64>>::new(None), is_nullable)
-            }
-            DataType::Float32 => {
-                Self::new(datatype, name, Arc::<Option<f32>>::new(None), is_nullable)
-            }
-            DataType::Float64 => {
-                Self::new(datatype, name, Arc::<Option<f64>>::new(None), is_nullable)
-            }
-            DataType::String => Self::new(
-                datatype,
-                name,
-                Arc::<Option<String>>::new(None),
-                is_nullable,
-            ),
-            DataType::Boolean => {
-                Self::new(datatype, name, Arc::<Option<bool>>::new(None), is_nullable)
-            }
-            DataType::Bytes => Self::new(
-                datatype,
-                name,
-                Arc::<Option<Vec<u8>>>::new(None),
-                is_nullable,
-            ),
-        }
+        Self::with_cell(name, Cell::none(datatype), is_nullable)
     }
 
     pub fn datatype(&self) -> DataType {
@@ -135,79 +259,36 @@ impl PartialOrd for Value {
     }
 }
 
-macro_rules! implement_col {
-    ([], $({$Type:ty, $DataType:ident}), *) => {
-        impl Ord for Value {
-            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-                match self.datatype() {
-                    $(
-                        DataType::$DataType => self
-                            .value
-                            .downcast_ref::<$Type>()
-                            .cmp(&other.value.downcast_ref::<$Type>()),
-                    )*
-                }
-            }
-        }
-
-        impl PartialEq for Value {
-            fn eq(&self, other: &Self) -> bool {
-                self.name() == other.name()
-                && self.datatype() == other.datatype()
-                && self.is_nullable() == other.is_nullable()
-                && match self.datatype() {
-                        $(
-                            DataType::$DataType => {
-                                if let Some(v) = self
-                                    .value
-                                    .downcast_ref::<$Type>() {
-                                        v.eq(other.value.downcast_ref::<$Type>().unwrap())
-                                    } else {
-                                        self.value
-                                            .downcast_ref::<Option<$Type>>()
-                                            .unwrap()
-                                            .eq(other.value.downcast_ref::<Option<$Type>>().unwrap())
-                                    }
-                            }
-                        )*
-                    }
-            }
-        }
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.value.cmp(&other.value)
+    }
+}
 
-        impl Hash for Value {
-            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-                match self.datatype() {
-                    $(
-                        DataType::$DataType => self.value.downcast_ref::<$Type>().hash(state),
-                    )*
-                }
-            }
-        }
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.desc.name == other.desc.name
+            && self.datatype() == other.datatype()
+            && self.is_nullable() == other.is_nullable()
+            && self.value == other.value
+    }
+}
 
-        impl Debug for Value {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                let mut debug_struct = f.debug_struct("Value");
-                debug_struct.field("name", &self.name());
-                match self.datatype() {
-                    $(
-                        DataType::$DataType => {
-                            debug_struct.field("datatype", &stringify!($Type));
-                            if let Some(value) = self.value.as_ref().downcast_ref::<$Type>() {
-                                debug_struct.field("value", value);
-                            } else {
-                                debug_struct.field(
-                                    "value",
-                                    self.value.as_ref().downcast_ref::<Option<$Type>>().unwrap(),
-                                );
-                            }
-                        }
-                    )*
-                }
-                debug_struct.field("nullable", &self.is_nullable()).finish()
-            }
-        }
+impl Hash for Value {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.value.hash(state)
+    }
+}
 
-    };
+impl Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug_struct = f.debug_struct("Value");
+        debug_struct
+            .field("name", &self.desc.name)
+            .field("datatype", &self.datatype());
+        with_cell!(&self.value, value => debug_struct.field("value", value));
+        debug_struct.field("nullable", &self.is_nullable()).finish()
+    }
 }
 
 macro_rules! implement_key_col {
@@ -292,85 +373,59 @@ macro_rules! implement_decode_col {
                 let datatype = Self::tag_to_datatype(tag);
                 let is_nullable = bool::decode(reader).await?;
                 let is_some = !bool::decode(reader).await?;
-                let value =
-                    match datatype {
-                        $(
-                            DataType::$DataType => match is_some {
-                                true => Arc::new(Option::<$Type>::decode(reader).await.map_err(
-                                    |err| match err {
-                                        DecodeError::Io(error) => fusio::Error::Io(error),
-                                        DecodeError::Fusio(error) => error,
-                                        DecodeError::Inner(error) => fusio::Error::Other(Box::new(error)),
-                                    },
-                                )?) as Arc<dyn Any + Send + Sync>,
-                                false => Arc::new(<$Type>::decode(reader).await?) as Arc<dyn Any + Send + Sync>,
-                            },
-                        )*
-                    };
+                let value = match datatype {
+                    $(
+                        DataType::$DataType => match is_some {
+                            true => Cell::from(Option::<$Type>::decode(reader).await.map_err(
+                                |err| match err {
+                                    DecodeError::Io(error) => fusio::Error::Io(error),
+                                    DecodeError::Fusio(error) => error,
+                                    DecodeError::Inner(error) => fusio::Error::Other(Box::new(error)),
+                                },
+                            )?),
+                            false => Cell::from(<$Type>::decode(reader).await?),
+                        },
+                    )*
+                };
                 let name = String::decode(reader).await?;
-                Ok(Value::new(
-                    datatype,
-                    name,
-                    value,
-                    is_nullable,
-                ))
+                Ok(Value::with_cell(name, value, is_nullable))
             }
         }
     }
 }
 
-macro_rules! implement_encode_col {
-    ([], $({$Type:ty, $DataType:ident}), *) => {
-        impl Encode for Value {
-            type Error = fusio::Error;
-
-            async fn encode<W>(&self, writer: &mut W) -> Result<(), Self::Error>
-            where
-                W: Write,
-            {
-                Self::tag(self.datatype()).encode(writer).await?;
-                self.is_nullable().encode(writer).await?;
-                match self.datatype() {
-                        $(
-                            DataType::$DataType => {
-                                if let Some(value) = self.value.as_ref().downcast_ref::<$Type>() {
-                                    true.encode(writer).await?;
-                                    value.encode(writer).await?
-                                } else {
-                                    false.encode(writer).await?;
-                                    self.value
-                                        .as_ref()
-                                        .downcast_ref::<Option<$Type>>()
-                                        .unwrap()
-                                        .encode(writer)
-                                        .await
-                                        .map_err(|err| fusio::Error::Other(Box::new(err)))?;
-                                }
-                            }
-                        )*
-                };
-                self.desc.name.encode(writer).await?;
-                Ok(())
+impl Encode for Value {
+    type Error = fusio::Error;
+
+    async fn encode<W>(&self, writer: &mut W) -> Result<(), Self::Error>
+    where
+        W: Write,
+    {
+        Self::tag(self.datatype()).encode(writer).await?;
+        self.is_nullable().encode(writer).await?;
+        with_cell!(&self.value, value => match value {
+            Some(value) => {
+                true.encode(writer).await?;
+                value.encode(writer).await?
             }
-
-            fn size(&self) -> usize {
-                3 + self.desc.name.size() + match self.desc.datatype {
-                    $(
-                        DataType::$DataType => {
-                            if let Some(value) = self.value.as_ref().downcast_ref::<$Type>() {
-                                value.size()
-                            } else {
-                                self.value
-                                    .as_ref()
-                                    .downcast_ref::<Option<$Type>>()
-                                    .unwrap()
-                                    .size()
-                            }
-                        }
-                    )*
-                }
+            None => {
+                false.encode(writer).await?;
+                value
+                    .encode(writer)
+                    .await
+                    .map_err(|err| fusio::Error::Other(Box::new(err)))?;
             }
-        }
+        });
+        self.desc.name.encode(writer).await?;
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        3 + self.desc.name.size()
+            + with_cell!(&self.value, value => match value {
+                Some(value) => value.size(),
+                None => value.size(),
+            })
     }
 }
 
@@ -459,16 +514,18 @@ implement_key_col!(
     { i8, Int8, Int8Array }, { i16, Int16, Int16Array }, { i32, Int32, Int32Array }, { i64, Int64, Int64Array }
     // { F32, Float32, Float32Array }, { F64, Float64, Float64Array }
 );
-for_datatype! { implement_col }
+for_datatype! { implement_cell }
 for_datatype! { implement_decode_col }
-for_datatype! { implement_encode_col }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::{io::Cursor, sync::Arc};
 
-    use super::Value;
-    use crate::record::DataType;
+    use fusio_log::{Decode, Encode};
+    use tokio::io::AsyncSeekExt;
+
+    use super::{Cell, Value};
+    use crate::record::{DataType, F32, F64};
 
     #[test]
     fn test_value_eq() {
@@ -517,4 +574,85 @@ mod tests {
             assert_ne!(value1, value3);
         }
     }
+
+    #[test]
+    fn test_cell_downcast() {
+        let cell = Cell::from(Some(1_i32));
+        assert_eq!(cell.datatype(), DataType::Int32);
+        assert_eq!(cell.downcast_ref::<i32>(), Some(&1));
+        assert_eq!(cell.downcast_ref::<Option<i32>>(), Some(&Some(1)));
+        assert_eq!(cell.downcast_ref::<i64>(), None);
+
+        let cell = Cell::none(DataType::String);
+        assert!(cell.is_none());
+        assert_eq!(cell.downcast_ref::<String>(), None);
+        assert_eq!(cell.downcast_ref::<Option<String>>(), Some(&None));
+
+        assert_eq!(
+            Cell::from_any(DataType::String, Arc::new("tonbo".to_string())),
+            Cell::from("tonbo".to_string())
+        );
+        assert_eq!(
+            Cell::from_any(DataType::Bytes, Arc::new(None::<Vec<u8>>)),
+            Cell::none(DataType::Bytes)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_none_float_value() {
+        for datatype in [DataType::Float32, DataType::Float64] {
+            let none = Value::with_none_value(datatype, "float".to_string(), true);
+            let some = match datatype {
+                DataType::Float32 => Cell::from(Some(F32::from(1.5))),
+                _ => Cell::from(Some(F64::from(1.5))),
+            };
+            let some = Value::with_cell("float".to_string(), some, true);
+            assert_eq!(none.datatype(), datatype);
+            assert_eq!(
+                none,
+                Value::with_cell("float".to_string(), Cell::none(datatype), true)
+            );
+            assert!(none < some);
+
+            let mut bytes = Vec::new();
+            let mut cursor = Cursor::new(&mut bytes);
+            none.encode(&mut cursor).await.unwrap();
+            assert_eq!(cursor.get_ref().len(), none.size());
+            cursor.seek(std::io::SeekFrom::Start(0)).await.unwrap();
+            assert_eq!(Value::decode(&mut cursor).await.unwrap(), none);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_value_encode_and_decode() {
+        for value in [
+            Value::with_cell("id".to_string(), Cell::from(1_u64), false),
+            Value::with_cell("name".to_string(), Cell::from("tonbo".to_string()), true),
+            Value::with_cell("bytes".to_string(), Cell::none(DataType::Bytes), true),
+        ] {
+            let mut bytes = Vec::new();
+            let mut cursor = Cursor::new(&mut bytes);
+            value.encode(&mut cursor).await.unwrap();
+            assert_eq!(cursor.get_ref().len(), value.size());
+
+            cursor.seek(std::io::SeekFrom::Start(0)).await.unwrap();
+            assert_eq!(Value::decode(&mut cursor).await.unwrap(), value);
+        }
+        {
+            // values encoded as `Option<T>`
+            let mut bytes = Vec::new();
+            let mut cursor = Cursor::new(&mut bytes);
+            6_u8.encode(&mut cursor).await.unwrap();
+            true.encode(&mut cursor).await.unwrap();
+            false.encode(&mut cursor).await.unwrap();
+            Some(7_i32).encode(&mut cursor).await.unwrap();
+            "i32".to_string().encode(&mut cursor).await.unwrap();
+
+            cursor.seek(std::io::SeekFrom::Start(0)).await.unwrap();
+            assert_eq!(
+                Value::decode(&mut cursor).await.unwrap(),
+                Value::with_cell("i32".to_string(), Cell::from(7_i32), true)
+            );
+        }
+    }
 }
@@ -0,0 +1,451 @@
+use std::{pin::pin, sync::Arc};
+
+use fusio::{path::Path, DynFs};
+use fusio_log::Options;
+use futures_util::StreamExt;
+use parquet_lru::NoCache;
+
+use crate::{
+    encryption,
+    executor::Executor,
+    fs::{manager::StoreManager, parse_file_id, FileId, FileType},
+    ondisk::sstable::SsTable,
+    record::{KeyRef, Record, Schema},
+    scope::{Scope, TableStats},
+    timestamp::Timestamp,
+    version::{edit::VersionEdit, set::VersionSet, MAX_LEVEL},
+    wal::{log::WalRecord, RecoverError, WalFile},
+    DbError, DbOption, ParquetLru, DB,
+};
+
+/// Result of [`DB::repair`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairReport {
+    /// number of tables of every level of the rebuilt manifest
+    pub levels: Vec<usize>,
+    /// latest timestamp of the tables, the [`DB`] recovers its clock from it
+    pub ts: Timestamp,
+    /// tables left out of the manifest and on disk, because they can't be read, are empty or hold
+    /// other records than the schema, with the reason
+    pub skipped_tables: Vec<(Path, String)>,
+    /// WAL segments removed because their commits are in the tables already
+    pub flushed_wal_segments: Vec<Path>,
+    /// WAL segments cut before the first log that can't be read, with the reason
+    pub corrupted_wal_segments: Vec<(Path, String)>,
+}
+
+impl RepairReport {
+    /// number of tables of the rebuilt manifest
+    pub fn tables(&self) -> usize {
+        self.levels.iter().sum()
+    }
+}
+
+/// a table found on disk with its scope and the latest timestamp of its entries
+struct Survey<K> {
+    dir: usize,
+    scope: Scope<K>,
+    ts: Timestamp,
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Rebuild the manifest of the [`DB`] at the paths of `option` from the SSTables found in
+    /// them, when its version log is lost or corrupted. The [`DB`] must be closed, it is opened
+    /// with [`DB::new`] once repaired.
+    ///
+    /// Every table is read whole to recompute its key range, rows and latest timestamp. Tables
+    /// that can't be read or hold other records than `schema` are left out. Levels are
+    /// recomputed from the key ranges and the timestamps: every table is placed above the older
+    /// tables it overlaps, so that the newest version of a key is found first, on the levels of
+    /// its directory when [`DbOption::level_path`] gives levels their own.
+    ///
+    /// WAL segments whose commits are all in the tables are removed, the others are replayed
+    /// when the [`DB`] is opened. A segment with a log that can't be read before its last is
+    /// cut before it when commits wait for the WAL (see [`DbOption::wal_sync_mode`]), as the
+    /// [`DB`] would fail to open with it. A torn last log is left to the recovery of the [`DB`],
+    /// which drops it.
+    pub async fn repair(option: DbOption, schema: R::Schema) -> Result<RepairReport, DbError<R>> {
+        let mut manager = StoreManager::new(option.base_fs.clone(), option.level_paths.clone())?;
+        if let Some((_, fs_options)) = &option.wal_path {
+            manager = manager.with_wal_fs(fs_options.clone())?;
+        }
+        let mut lru_cache: ParquetLru = Arc::new(NoCache::default());
+        if let Some(key_provider) = &option.encryption {
+            lru_cache = encryption::cache(&lru_cache, key_provider.clone())?;
+        }
+        let mut report = RepairReport {
+            levels: vec![0; MAX_LEVEL],
+            ts: Timestamp::from(0),
+            skipped_tables: Vec::new(),
+            flushed_wal_segments: Vec::new(),
+            corrupted_wal_segments: Vec::new(),
+        };
+
+        // the directories of the tables and the levels sharing each of them
+        let mut dirs: Vec<(Path, Vec<usize>)> = Vec::new();
+        for level in 0..MAX_LEVEL {
+            let dir = option.level_fs_path(level).unwrap_or(&option.base_path);
+            match dirs.iter_mut().find(|(path, _)| path == dir) {
+                Some((_, levels)) => levels.push(level),
+                None => dirs.push((dir.clone(), vec![level])),
+            }
+        }
+
+        let mut surveys = Vec::new();
+        for (dir, (dir_path, _)) in dirs.iter().enumerate() {
+            let fs = manager.get_fs(dir_path);
+            let mut paths = Vec::new();
+            let mut stream = fs.list(dir_path).await?;
+            while let Some(file_meta) = stream.next().await {
+                let file_meta = file_meta?;
+                // the directories of the WAL and of the version log may share the path of a level
+                if file_meta
+                    .path
+                    .filename()
+                    .is_some_and(|name| name.ends_with(&format!(".{}", FileType::Parquet)))
+                {
+                    paths.push(file_meta.path);
+                }
+            }
+            drop(stream);
+
+            for path in paths {
+                match Self::survey_table(fs, &lru_cache, &schema, &path).await {
+                    Ok(Some((scope, ts))) => surveys.push(Survey { dir, scope, ts }),
+                    Ok(None) => report
+                        .skipped_tables
+                        .push((path, "the table is empty".to_string())),
+                    Err(reason) => report.skipped_tables.push((path, reason)),
+                }
+            }
+        }
+
+        // the oldest tables are placed first, on the deepest level below the tables they overlap.
+        // Tables of level 0 may overlap, they are ordered by their timestamps.
+        surveys.sort_by_key(|survey| survey.ts);
+        let mut placed: Vec<(usize, Survey<_>)> = Vec::with_capacity(surveys.len());
+        for survey in surveys {
+            let bound = placed
+                .iter()
+                .filter(|(_, other)| {
+                    other.scope.min <= survey.scope.max && survey.scope.min <= other.scope.max
+                })
+                .map(|(level, _)| level.saturating_sub(1))
+                .min()
+                .unwrap_or(MAX_LEVEL - 1);
+            let levels = &dirs[survey.dir].1;
+            let level = levels
+                .iter()
+                .rev()
+                .find(|level| **level <= bound)
+                .copied()
+                .unwrap_or(levels[0]);
+            placed.push((level, survey));
+        }
+
+        let mut edits = Vec::with_capacity(placed.len() + 2);
+        for (level, survey) in placed {
+            report.levels[level] += 1;
+            report.ts = report.ts.max(survey.ts);
+            edits.push(VersionEdit::Add {
+                level: level as u8,
+                scope: survey.scope,
+            });
+        }
+        edits.push(VersionEdit::LatestTimeStamp { ts: report.ts });
+        edits.push(VersionEdit::NewLogLength { len: 0 });
+
+        let wal_fs = manager.wal_fs();
+        wal_fs.create_dir_all(&option.wal_dir_path()).await?;
+        let mut segments = Vec::new();
+        let mut segment_stream = wal_fs.list(&option.wal_dir_path()).await?;
+        while let Some(file_meta) = segment_stream.next().await {
+            let file_meta = file_meta?;
+            if file_meta.path.as_ref().ends_with("wal") {
+                segments.push(file_meta.path);
+            }
+        }
+        drop(segment_stream);
+        for segment in segments {
+            let mut latest = None;
+            let mut batches = 0;
+            let mut corruption = None;
+            {
+                let mut stream = pin!(
+                    WalFile::<R>::recover(
+                        option.wal_fs().clone(),
+                        segment.clone(),
+                        option.encryption.clone()
+                    )
+                    .await
+                );
+                while let Some(logs) = stream.next().await {
+                    match logs {
+                        Ok(logs) => {
+                            batches += 1;
+                            latest = logs.iter().map(|log| log.key.ts).chain(latest).max();
+                        }
+                        // a crash tore the last log, the commits before it are kept
+                        Err(RecoverError::Torn) => break,
+                        Err(err) => {
+                            corruption = Some(err.to_string());
+                            break;
+                        }
+                    }
+                }
+            }
+            match corruption {
+                Some(reason) if option.wal_syncs_commits() => {
+                    Self::cut_segment(wal_fs, &option, &segment, batches).await?;
+                    report.corrupted_wal_segments.push((segment, reason));
+                }
+                Some(_) => (),
+                None if latest.map_or(true, |latest| latest <= report.ts) => {
+                    wal_fs.remove(&segment).await?;
+                    report.flushed_wal_segments.push(segment);
+                }
+                None => (),
+            }
+        }
+
+        VersionSet::<R>::reset(&option, &manager, &edits).await?;
+
+        Ok(report)
+    }
+
+    /// rewrite the WAL segment at `path` with its first `batches` batches of logs, which are
+    /// read whole, the segment is removed without any
+    async fn cut_segment(
+        wal_fs: &Arc<dyn DynFs>,
+        option: &DbOption,
+        path: &Path,
+        batches: usize,
+    ) -> Result<(), DbError<R>> {
+        let mut kept = Vec::with_capacity(batches);
+        {
+            let mut stream = pin!(WalFile::<R>::recover_records(
+                option.wal_fs().clone(),
+                path.clone()
+            )
+            .await
+            .take(batches));
+            while let Some(Ok(batch)) = stream.next().await {
+                kept.push(batch);
+            }
+        }
+        if kept.is_empty() {
+            wal_fs.remove(path).await?;
+            return Ok(());
+        }
+        // the records are copied as written, sealed records stay sealed
+        let mut log = Options::new(path.clone())
+            .truncate(true)
+            .build_with_fs::<WalRecord<R>>(wal_fs.clone())
+            .await?;
+        for batch in kept {
+            log.write_batch(batch.iter()).await?;
+        }
+        log.close().await?;
+        Ok(())
+    }
+
+    /// the scope of the table at `path` and the latest timestamp of its entries, `None` when it
+    /// is empty
+    async fn survey_table(
+        fs: &Arc<dyn DynFs>,
+        lru_cache: &ParquetLru,
+        schema: &R::Schema,
+        path: &Path,
+    ) -> Result<Option<(Scope<<R::Schema as Schema>::Key>, Timestamp)>, String> {
+        let gen: FileId = parse_file_id(path, FileType::Parquet)
+            .map_err(|err| err.to_string())?
+            .ok_or_else(|| "the table is not named by its id".to_string())?;
+        let file = fs
+            .open_options(path, FileType::Parquet.open_options(true))
+            .await
+            .map_err(|err| err.to_string())?;
+        let table = SsTable::<R>::open(lru_cache.clone(), gen, file)
+            .await
+            .map_err(|err| err.to_string())?;
+        let (table_schema, mut entries) = table.entries().await.map_err(|err| err.to_string())?;
+        if table_schema.fields() != schema.arrow_schema().fields() {
+            return Err("the table holds other records than the schema".to_string());
+        }
+
+        let mut stats = TableStats::default();
+        let mut survey: Option<(Scope<<R::Schema as Schema>::Key>, Timestamp)> = None;
+        while let Some(entry) = entries
+            .next()
+            .await
+            .transpose()
+            .map_err(|err| err.to_string())?
+        {
+            stats.rows += 1;
+            if entry.get().is_none() {
+                stats.tombstones += 1;
+            }
+            let ts = entry.internal_key().ts;
+            let key = entry.key().to_key();
+            match &mut survey {
+                Some((scope, latest)) => {
+                    if key < scope.max {
+                        return Err("the keys of the table are not sorted".to_string());
+                    }
+                    if key == scope.max {
+                        stats.is_single_version = false;
+                    }
+                    scope.max = key;
+                    *latest = (*latest).max(ts);
+                }
+                None => {
+                    stats.is_single_version = true;
+                    survey = Some((
+                        Scope {
+                            min: key.clone(),
+                            max: key,
+                            gen,
+                            wal_ids: None,
+                            stats: None,
+                        },
+                        ts,
+                    ))
+                }
+            }
+        }
+        Ok(survey.map(|(mut scope, ts)| {
+            scope.stats = Some(stats);
+            (scope, ts)
+        }))
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::fs;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        WalSyncMode, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn repair() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let record = |key: &str, vu32| Test {
+            vstring: key.to_string(),
+            vu32,
+            vbool: None,
+        };
+        {
+            let db: DB<Test, TokioExecutor> =
+                DB::new(option.clone(), TokioExecutor::current(), TestSchema)
+                    .await
+                    .unwrap();
+            db.insert(record("a", 1)).await.unwrap();
+            db.insert(record("b", 1)).await.unwrap();
+            db.flush().await.unwrap();
+            db.insert(record("a", 2)).await.unwrap();
+            db.flush().await.unwrap();
+        }
+        // the manifest is lost
+        for entry in fs::read_dir(temp_dir.path().join("version")).unwrap() {
+            fs::remove_file(entry.unwrap().path()).unwrap();
+        }
+        fs::write(temp_dir.path().join("foreign.parquet"), b"not a table").unwrap();
+
+        let report = DB::<Test, TokioExecutor>::repair(option.clone(), TestSchema)
+            .await
+            .unwrap();
+        assert_eq!(report.tables(), 2);
+        // the newer table overlaps the older one, it is placed above it
+        assert_eq!(report.levels[6], 1);
+        assert_eq!(report.levels[5], 1);
+        assert_eq!(report.skipped_tables.len(), 1);
+        assert!(report.corrupted_wal_segments.is_empty());
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get(&"a".to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap(),
+            Some(2)
+        );
+        assert_eq!(
+            db.get(&"b".to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap(),
+            Some(1)
+        );
+        assert!(db.snapshot().await.ts() >= report.ts);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn repair_keeps_torn_segment() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .wal_sync_mode(WalSyncMode::Commit);
+        {
+            let db: DB<Test, TokioExecutor> =
+                DB::new(option.clone(), TokioExecutor::current(), TestSchema)
+                    .await
+                    .unwrap();
+            for key in ["a", "b"] {
+                db.insert(Test {
+                    vstring: key.to_string(),
+                    vu32: 1,
+                    vbool: None,
+                })
+                .await
+                .unwrap();
+            }
+        }
+        // the log of `b` is cut by a crash while it was written
+        let segment = fs::read_dir(temp_dir.path().join("wal"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .max()
+            .unwrap();
+        let bytes = fs::read(&segment).unwrap();
+        fs::write(&segment, &bytes[..bytes.len() - 2]).unwrap();
+
+        let report = DB::<Test, TokioExecutor>::repair(option.clone(), TestSchema)
+            .await
+            .unwrap();
+        assert!(report.corrupted_wal_segments.is_empty());
+        assert!(report.flushed_wal_segments.is_empty());
+        assert!(segment.exists());
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get(&"a".to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            db.get(&"b".to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use std::{collections::HashMap, pin::pin};
+
+use fusio::path::Path;
+use fusio_dispatch::FsOptions;
+use futures_util::StreamExt;
+
+use crate::{
+    compaction::CompactTask,
+    executor::Executor,
+    record::{Record, Schema},
+    timestamp::Timestamp,
+    wal::{
+        log::{Log, LogType},
+        WalFile,
+    },
+    DbError, DB,
+};
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Replay the WAL segments archived in `path` of the fs of `fs_options`, e.g. by a
+    /// [`CopyArchiver`](crate::archive::CopyArchiver), up to the commits at `ts`, to recover
+    /// the [`DB`] to a point in time. Open the [`DB`] on a backup of the original one, taken
+    /// before `ts` while its WAL segments were archived, then restore it.
+    ///
+    /// Commits not newer than the latest timestamp of the [`DB`] are in the backup already and
+    /// commits newer than `ts` are dropped, a transaction is replayed whole or not at all.
+    /// Returns the latest timestamp of the [`DB`] once the commits are replayed.
+    pub async fn restore_to(
+        &self,
+        path: &Path,
+        fs_options: FsOptions,
+        ts: Timestamp,
+    ) -> Result<Timestamp, DbError<R>> {
+        let option = self.schema.read().await.option.clone();
+        let backup_ts = self.ctx.load_ts();
+
+        let fs = fs_options.clone().parse()?;
+        let mut segments = Vec::new();
+        let mut segment_stream = fs.list(path).await?;
+        while let Some(file_meta) = segment_stream.next().await {
+            let file_meta = file_meta?;
+            if file_meta.path.as_ref().ends_with("wal") {
+                segments.push(file_meta.path);
+            }
+        }
+        drop(segment_stream);
+        // segments are named by their time ordered ids
+        segments.sort();
+
+        let mut transactions = HashMap::new();
+        for segment in segments {
+            let mut stream = pin!(
+                WalFile::<R>::recover(fs_options.clone(), segment, option.encryption.clone()).await
+            );
+            while let Some(logs) = stream.next().await {
+                for log in logs? {
+                    let commit_ts = log.key.ts;
+                    let commit = match log.log_type.unwrap() {
+                        LogType::Full => vec![log],
+                        LogType::First => {
+                            transactions.insert(commit_ts, vec![log]);
+                            continue;
+                        }
+                        LogType::Middle => {
+                            transactions
+                                .entry(commit_ts)
+                                .or_insert_with(Vec::new)
+                                .push(log);
+                            continue;
+                        }
+                        LogType::Last => {
+                            let mut commit = transactions.remove(&commit_ts).unwrap_or_default();
+                            commit.push(log);
+                            commit
+                        }
+                    };
+                    if commit_ts > backup_ts && commit_ts <= ts {
+                        self.ctx.advance_ts(commit_ts);
+                        self.replay(commit).await?;
+                    }
+                }
+            }
+        }
+
+        Ok(self.ctx.load_ts())
+    }
+
+    /// write the logs of a commit with their timestamp and log types
+    async fn replay(&self, commit: Vec<Log<R>>) -> Result<(), DbError<R>> {
+        let schema = self.schema.read().await;
+        let mut is_excess = false;
+        for log in commit {
+            let log_type = log.log_type.unwrap();
+            is_excess = match log.value {
+                Some(record) => schema.write(log_type, record, log.key.ts).await?,
+                None => schema.remove(log_type, log.key.value, log.key.ts).await?,
+            };
+        }
+        if is_excess {
+            let _ = schema.compaction_tx.try_send(CompactTask::Freeze);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use fusio::path::Path;
+    use fusio_dispatch::FsOptions;
+    use tempfile::TempDir;
+
+    use crate::{
+        archive::CopyArchiver, executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema,
+        tests::Test, DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn restore_to() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_dir = TempDir::new().unwrap();
+        let backup_dir = TempDir::new().unwrap();
+        let archive_path = Path::from_filesystem_path(archive_dir.path()).unwrap();
+        let record = |key: &str| Test {
+            vstring: key.to_string(),
+            vu32: 0,
+            vbool: None,
+        };
+
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .version_log_snapshot_threshold(1)
+        .wal_archiver(Arc::new(
+            CopyArchiver::new(archive_path.clone(), FsOptions::Local)
+                .await
+                .unwrap(),
+        ));
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        db.insert(record("a")).await.unwrap();
+        db.insert(record("b")).await.unwrap();
+        let before_delete = db.snapshot().await.ts();
+        // the accidental delete
+        db.remove("a".to_string()).await.unwrap();
+        db.flush().await.unwrap();
+        drop(db);
+
+        // the backup was taken before the writes
+        let db: DB<Test, TokioExecutor> = DB::new(
+            DbOption::new(
+                Path::from_filesystem_path(backup_dir.path()).unwrap(),
+                &TestSchema,
+            ),
+            TokioExecutor::current(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+        let ts = db
+            .restore_to(&archive_path, FsOptions::Local, before_delete)
+            .await
+            .unwrap();
+        assert_eq!(ts, before_delete);
+        for key in ["a", "b"] {
+            assert!(db
+                .get(&key.to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap()
+                .is_some());
+        }
+    }
+}
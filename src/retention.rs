@@ -0,0 +1,254 @@
+use std::{ops::Bound, sync::Arc};
+
+use fusio_log::Encode;
+use futures_util::StreamExt;
+use parquet_lru::NoCache;
+
+use crate::{
+    executor::Executor,
+    record::{Record, Schema},
+    timestamp::Timestamp,
+    DbError, DbOption, DB,
+};
+
+/// Decides whether a row should be dropped by a retention policy (TTL, archival, compaction
+/// filter and so on).
+pub trait RetentionPolicy<R>: Send + Sync
+where
+    R: Record,
+{
+    /// returns `true` if `record` written at `ts` would be removed
+    fn should_remove(&self, record: R::Ref<'_>, ts: Timestamp) -> bool;
+}
+
+impl<R, F> RetentionPolicy<R> for F
+where
+    R: Record,
+    F: for<'r> Fn(R::Ref<'r>, Timestamp) -> bool + Send + Sync,
+{
+    fn should_remove(&self, record: R::Ref<'_>, ts: Timestamp) -> bool {
+        self(record, ts)
+    }
+}
+
+/// What a [`CompactionFilter`] does with a version of a key.
+pub enum FilterDecision<R> {
+    Keep,
+    /// replace the version with a tombstone, which keeps hiding the older versions of the key
+    /// until they are compacted with it
+    Remove,
+    /// replace the version with a record of the same key
+    Change(R),
+}
+
+/// Inspects the versions of the keys rewritten by major compactions, and drops or transforms
+/// them, see [`DB::with_compaction_filter`].
+///
+/// The filter sees the versions at most once per compaction and only gets to them when they are
+/// compacted, so it is meant for garbage collection rather than for changes reads must observe.
+/// Tombstones are not passed to the filter.
+pub trait CompactionFilter<R>: Send + Sync
+where
+    R: Record,
+{
+    /// decide what to do with `record`, written at `ts`
+    fn filter(&self, record: R::Ref<'_>, ts: Timestamp) -> FilterDecision<R>;
+}
+
+impl<R, F> CompactionFilter<R> for F
+where
+    R: Record,
+    F: for<'r> Fn(R::Ref<'r>, Timestamp) -> FilterDecision<R> + Send + Sync,
+{
+    fn filter(&self, record: R::Ref<'_>, ts: Timestamp) -> FilterDecision<R> {
+        self(record, ts)
+    }
+}
+
+/// What a [`RetentionPolicy`] would remove, reported by [`DB::simulate_retention`].
+///
+/// Byte counts are the encoded size of the rows, not the size they take in Parquet files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionReport {
+    pub scanned_rows: usize,
+    pub scanned_bytes: usize,
+    pub removed_rows: usize,
+    pub removed_bytes: usize,
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Open [`DB`] like [`DB::new`], with `filter` applied to every version rewritten by a major
+    /// compaction.
+    ///
+    /// A [`RetentionPolicy`] checked with [`DB::simulate_retention`] is applied by returning
+    /// [`FilterDecision::Remove`] for the records it removes. A filter changing the key of a record
+    /// fails the compaction.
+    pub async fn with_compaction_filter(
+        option: DbOption,
+        executor: E,
+        schema: R::Schema,
+        filter: impl CompactionFilter<R> + 'static,
+    ) -> Result<Self, DbError<R>> {
+        Self::build(
+            Arc::new(option),
+            executor,
+            schema,
+            Arc::new(NoCache::default()),
+            None,
+            Some(Arc::new(filter)),
+            None,
+        )
+        .await
+    }
+
+    /// Dry-run `policy` against the current snapshot and report how many live rows and bytes it
+    /// would remove. No data is mutated.
+    pub async fn simulate_retention(
+        &self,
+        policy: &impl RetentionPolicy<R>,
+    ) -> Result<RetentionReport, DbError<R>> {
+        let snapshot = self.snapshot().await;
+        let mut stream = snapshot
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .take()
+            .await?;
+        let mut report = RetentionReport::default();
+
+        while let Some(entry) = stream.next().await.transpose()? {
+            let Some(record) = entry.value() else {
+                continue;
+            };
+            let size = record.size();
+            report.scanned_rows += 1;
+            report.scanned_bytes += size;
+
+            if policy.should_remove(record, entry.key().ts()) {
+                report.removed_rows += 1;
+                report.removed_bytes += size;
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::{FilterDecision, RetentionReport};
+    use crate::{
+        executor::tokio::TokioExecutor,
+        inmem::immutable::tests::TestSchema,
+        tests::{Test, TestRef},
+        timestamp::Timestamp,
+        CompactionOption, DbOption, SizeRatioOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn simulate_retention() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        for i in 0..10 {
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i,
+                vbool: Some(true),
+            })
+            .await
+            .unwrap();
+            if i == 4 {
+                db.flush().await.unwrap();
+            }
+        }
+        db.remove(9.to_string()).await.unwrap();
+
+        let policy = |record: TestRef<'_>, _: Timestamp| record.vu32.is_some_and(|v| v < 3);
+        let report = db.simulate_retention(&policy).await.unwrap();
+        assert_eq!(report.scanned_rows, 9);
+        assert_eq!(report.removed_rows, 3);
+        assert!(report.removed_bytes > 0 && report.removed_bytes < report.scanned_bytes);
+
+        // nothing is removed by the simulation
+        assert_eq!(db.simulate_retention(&policy).await.unwrap(), report);
+        let vu32 = db.get(&0.to_string(), |e| e.get().vu32).await.unwrap();
+        assert_eq!(vu32, Some(0));
+
+        let keep_all = |_: TestRef<'_>, _: Timestamp| false;
+        assert_eq!(
+            db.simulate_retention(&keep_all).await.unwrap(),
+            RetentionReport {
+                removed_rows: 0,
+                removed_bytes: 0,
+                ..report
+            }
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn compaction_filter() {
+        let temp_dir = TempDir::new().unwrap();
+        // every flushed table is compacted into level 1
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .compaction_option(CompactionOption::SizeRatio(SizeRatioOption {
+            level0_table_num: 1,
+            ..Default::default()
+        }));
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+
+        let filter = |record: TestRef<'_>, _: Timestamp| match record.vu32 {
+            Some(vu32) if vu32 < 3 => FilterDecision::Remove,
+            Some(5) => FilterDecision::Change(Test {
+                vstring: record.vstring.to_string(),
+                vu32: 50,
+                vbool: record.vbool,
+            }),
+            _ => FilterDecision::Keep,
+        };
+        let db: DB<Test, TokioExecutor> =
+            DB::with_compaction_filter(option, TokioExecutor::current(), TestSchema, filter)
+                .await
+                .unwrap();
+
+        for i in 0..10 {
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i,
+                vbool: Some(true),
+            })
+            .await
+            .unwrap();
+        }
+        // the records are only filtered once compacted
+        let vu32 = db.get(&0.to_string(), |e| e.get().vu32).await.unwrap();
+        assert_eq!(vu32, Some(0));
+        db.flush().await.unwrap();
+        assert_eq!(db.ctx.version_set().current().await.tables_len(0), 0);
+
+        let get = |key: u32| {
+            let db = &db;
+            async move { db.get(&key.to_string(), |e| e.get().vu32).await.unwrap() }
+        };
+        assert_eq!(get(0).await, None);
+        assert_eq!(get(2).await, None);
+        assert_eq!(get(3).await, Some(3));
+        assert_eq!(get(5).await, Some(50));
+        assert_eq!(get(9).await, Some(9));
+    }
+}
@@ -2,6 +2,7 @@ use std::ops::Bound;
 
 use fusio::{SeqRead, Write};
 use fusio_log::{Decode, Encode};
+use parquet::format::FileMetaData;
 
 use crate::fs::FileId;
 
@@ -11,6 +12,41 @@ pub(crate) struct Scope<K> {
     pub(crate) max: K,
     pub(crate) gen: FileId,
     pub(crate) wal_ids: Option<Vec<FileId>>,
+    /// `None` for the tables written before the statistics were recorded and by bulk loads
+    pub(crate) stats: Option<TableStats>,
+}
+
+/// rows of a table, including overwritten versions, and the tombstones among them
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub(crate) struct TableStats {
+    pub(crate) rows: u64,
+    pub(crate) tombstones: u64,
+    /// compressed bytes of the row groups, `None` for the tables recorded before the size was
+    pub(crate) size: Option<u64>,
+    /// whether the table is known to hold a single version of every key, e.g. not a flushed
+    /// table or one compacted while older versions were retained
+    pub(crate) is_single_version: bool,
+}
+
+impl TableStats {
+    /// record the size of the table written with `metadata`
+    pub(crate) fn written(&mut self, metadata: &FileMetaData) {
+        self.size = Some(
+            metadata
+                .row_groups
+                .iter()
+                .map(|row_group| {
+                    row_group
+                        .total_compressed_size
+                        .unwrap_or(row_group.total_byte_size) as u64
+                })
+                .sum(),
+        );
+    }
+
+    pub(crate) fn tombstone_ratio(&self) -> f64 {
+        self.tombstones as f64 / self.rows.max(1) as f64
+    }
 }
 
 impl<K> Clone for Scope<K>
@@ -23,6 +59,7 @@ where
             max: self.max.clone(),
             gen: self.gen,
             wal_ids: self.wal_ids.clone(),
+            stats: self.stats,
         }
     }
 }
@@ -91,17 +128,25 @@ where
         let (result, _) = writer.write_all(&self.gen.to_bytes()[..]).await;
         result?;
 
-        match &self.wal_ids {
-            None => {
-                0u8.encode(writer).await?;
+        // bit 0: the wal ids follow, bit 1: the statistics follow them, bit 2: the size follows
+        // the statistics, bit 3: the table holds a single version of every key
+        let tag = u8::from(self.wal_ids.is_some())
+            | (u8::from(self.stats.is_some()) << 1)
+            | (u8::from(self.stats.is_some_and(|stats| stats.size.is_some())) << 2)
+            | (u8::from(self.stats.is_some_and(|stats| stats.is_single_version)) << 3);
+        tag.encode(writer).await?;
+        if let Some(ids) = &self.wal_ids {
+            (ids.len() as u32).encode(writer).await?;
+            for id in ids {
+                let (result, _) = writer.write_all(&id.to_bytes()[..]).await;
+                result?;
             }
-            Some(ids) => {
-                1u8.encode(writer).await?;
-                (ids.len() as u32).encode(writer).await?;
-                for id in ids {
-                    let (result, _) = writer.write_all(&id.to_bytes()[..]).await;
-                    result?;
-                }
+        }
+        if let Some(stats) = &self.stats {
+            stats.rows.encode(writer).await?;
+            stats.tombstones.encode(writer).await?;
+            if let Some(size) = stats.size {
+                size.encode(writer).await?;
             }
         }
         Ok(())
@@ -129,20 +174,33 @@ where
             result?;
             FileId::from_bytes(buf)
         };
-        let wal_ids = match u8::decode(reader).await? {
-            0 => None,
-            1 => {
-                let len = u32::decode(reader).await? as usize;
-                let mut ids = Vec::with_capacity(len);
-
-                for _ in 0..len {
-                    let (result, _) = reader.read_exact(buf.as_mut_slice()).await;
-                    result?;
-                    ids.push(FileId::from_bytes(buf));
-                }
-                Some(ids)
+        let tag = u8::decode(reader).await?;
+        let wal_ids = if tag & 1 == 1 {
+            let len = u32::decode(reader).await? as usize;
+            let mut ids = Vec::with_capacity(len);
+
+            for _ in 0..len {
+                let (result, _) = reader.read_exact(buf.as_mut_slice()).await;
+                result?;
+                ids.push(FileId::from_bytes(buf));
             }
-            _ => unreachable!(),
+            Some(ids)
+        } else {
+            None
+        };
+        let stats = if tag & 2 == 2 {
+            Some(TableStats {
+                rows: u64::decode(reader).await?,
+                tombstones: u64::decode(reader).await?,
+                size: if tag & 4 == 4 {
+                    Some(u64::decode(reader).await?)
+                } else {
+                    None
+                },
+                is_single_version: tag & 8 == 8,
+            })
+        } else {
+            None
         };
 
         Ok(Scope {
@@ -150,6 +208,7 @@ where
             max,
             gen,
             wal_ids,
+            stats,
         })
     }
 }
@@ -168,6 +227,7 @@ mod test {
             max: 200,
             gen: generate_file_id(),
             wal_ids: None,
+            stats: None,
         };
 
         // test out of range
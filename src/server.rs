@@ -0,0 +1,434 @@
+//! An HTTP server of JSON endpoints over a [`DB`] of [`DynRecord`]s, for the handful of remote
+//! clients of an embedded [`DB`] which don't embed tonbo themselves.
+//!
+//! Every endpoint takes a JSON object by `POST` and answers a JSON object, or
+//! `{"error": <reason>}` along with the status of the failure. The records are JSON objects of
+//! their columns, the keys are values of the primary key.
+//!
+//! - `/get`: `{"key": 1, "projection": ["name"]}`, answers `{"record": {...}}`, the record is
+//!   `null` if the key is not found
+//! - `/insert`: `{"records": [{...}]}`, answers `{"rows": 1}`
+//! - `/remove`: `{"key": 1}`, answers `{"removed": true}`
+//! - `/scan`: `{"lower": {"included": 1}, "upper": {"excluded": 10}, "projection": ["name"],
+//!   "limit": 100}`, every field optional, answers `{"records": [{...}]}`
+//! - `/transaction`: `{"insert": [{...}], "remove": [1]}`, commits the writes at once, answers
+//!   `{"rows": 2}`. A write conflict answers `409 Conflict`.
+//!
+//! The columns read are the projected ones and the primary key, the columns missing in an
+//! inserted record are null. The [`Authenticate`] hook of [`HttpServer::with_authenticate`] is
+//! called with the `Authorization` header of every request.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use tokio::net::TcpListener;
+//! use tonbo::{executor::tokio::TokioExecutor, record::DynRecord, server::HttpServer, DB};
+//!
+//! async fn serve(db: Arc<DB<DynRecord, TokioExecutor>>) {
+//!     let listener = TcpListener::bind("127.0.0.1:8080").await.unwrap();
+//!     HttpServer::new(db)
+//!         .with_authenticate(|authorization: Option<&str>| match authorization {
+//!             Some("Bearer secret") => Ok(()),
+//!             _ => Err("unknown token".to_string()),
+//!         })
+//!         .serve(listener)
+//!         .await
+//!         .unwrap();
+//! }
+//! ```
+
+use std::{io, ops::Bound, pin::pin, sync::Arc};
+
+use arrow::json::ArrayWriter;
+use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use futures_util::StreamExt;
+use serde_json::json;
+use tokio::net::TcpListener;
+
+use crate::{
+    executor::Executor,
+    interchange::{json_object, json_value},
+    record::{DynRecord, DynSchema, Schema, Value, ValueDesc},
+    stream::user::{user_batch, user_scan, user_schema, UserScan},
+    transaction::CommitError,
+    DB,
+};
+
+/// rows of the batches read by a scan
+const SCAN_BATCH_SIZE: usize = 1024;
+
+/// Called with the `Authorization` header of every request of an [`HttpServer`], the request is
+/// answered `401 Unauthorized` with the reason of an error.
+pub trait Authenticate: Send + Sync {
+    fn authenticate(&self, authorization: Option<&str>) -> Result<(), String>;
+}
+
+impl<F> Authenticate for F
+where
+    F: Fn(Option<&str>) -> Result<(), String> + Send + Sync,
+{
+    fn authenticate(&self, authorization: Option<&str>) -> Result<(), String> {
+        self(authorization)
+    }
+}
+
+/// The JSON endpoints of a [`DB`], see the [module](self).
+pub struct HttpServer<E>
+where
+    E: Executor,
+{
+    db: Arc<DB<DynRecord, E>>,
+    authenticate: Option<Arc<dyn Authenticate>>,
+}
+
+impl<E> HttpServer<E>
+where
+    E: Executor + Send + Sync + 'static,
+{
+    /// the endpoints of `db`, every request is served without [`HttpServer::with_authenticate`]
+    pub fn new(db: Arc<DB<DynRecord, E>>) -> Self {
+        Self {
+            db,
+            authenticate: None,
+        }
+    }
+
+    pub fn with_authenticate(self, authenticate: impl Authenticate + 'static) -> Self {
+        Self {
+            authenticate: Some(Arc::new(authenticate)),
+            ..self
+        }
+    }
+
+    /// the routes of the endpoints, to be merged into the router of an application
+    pub fn into_router(self) -> Router {
+        let router = Router::new()
+            .route("/get", post(get::<E>))
+            .route("/insert", post(insert::<E>))
+            .route("/remove", post(remove::<E>))
+            .route("/scan", post(scan::<E>))
+            .route("/transaction", post(transaction::<E>))
+            .with_state(self.db);
+        match self.authenticate {
+            Some(authenticate) => {
+                router.layer(middleware::from_fn_with_state(authenticate, authorize))
+            }
+            None => router,
+        }
+    }
+
+    /// serve the connections of `listener` until accepting one fails, the caller spawns the
+    /// returned future on its runtime
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        axum::serve(listener, self.into_router()).await
+    }
+}
+
+/// the failure of a request
+struct Error(StatusCode, String);
+
+impl Error {
+    fn invalid(reason: impl ToString) -> Self {
+        Self(StatusCode::BAD_REQUEST, reason.to_string())
+    }
+
+    fn internal(reason: impl ToString) -> Self {
+        Self(StatusCode::INTERNAL_SERVER_ERROR, reason.to_string())
+    }
+
+    fn commit(err: CommitError<DynRecord>) -> Self {
+        match err {
+            CommitError::WriteConflict(_) | CommitError::ReadConflict(_) => {
+                Self(StatusCode::CONFLICT, err.to_string())
+            }
+            err => Self::internal(err),
+        }
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+type Answer = Result<Json<serde_json::Value>, Error>;
+
+async fn authorize(
+    State(authenticate): State<Arc<dyn Authenticate>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorization = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok());
+    match authenticate.authenticate(authorization) {
+        Ok(()) => next.run(request).await,
+        Err(reason) => Error(StatusCode::UNAUTHORIZED, reason).into_response(),
+    }
+}
+
+async fn get<E>(
+    State(db): State<Arc<DB<DynRecord, E>>>,
+    Json(body): Json<serde_json::Value>,
+) -> Answer
+where
+    E: Executor + Send + Sync + 'static,
+{
+    let schema = db.schema.read().await.record_schema.clone();
+    let mut scan =
+        UserScan::from_json(&body, key_desc(&schema), SCAN_BATCH_SIZE).map_err(Error::invalid)?;
+    let key = key(&schema, body.get("key"))?;
+    scan.lower = Bound::Included(key.clone());
+    scan.upper = Bound::Included(key);
+    scan.limit = Some(1);
+
+    let record = records(&db, &schema, scan).await?.pop();
+    Ok(Json(json!({ "record": record })))
+}
+
+async fn insert<E>(
+    State(db): State<Arc<DB<DynRecord, E>>>,
+    Json(body): Json<serde_json::Value>,
+) -> Answer
+where
+    E: Executor + Send + Sync + 'static,
+{
+    let schema = db.schema.read().await.record_schema.clone();
+    let records = dyn_records(&schema, body.get("records"))?;
+    let rows = records.len();
+    if rows > 0 {
+        db.insert_batch(records.into_iter())
+            .await
+            .map_err(Error::commit)?;
+    }
+    Ok(Json(json!({ "rows": rows })))
+}
+
+async fn remove<E>(
+    State(db): State<Arc<DB<DynRecord, E>>>,
+    Json(body): Json<serde_json::Value>,
+) -> Answer
+where
+    E: Executor + Send + Sync + 'static,
+{
+    let schema = db.schema.read().await.record_schema.clone();
+    let key = key(&schema, body.get("key"))?;
+    let removed = db.remove(key).await.map_err(Error::commit)?;
+    Ok(Json(json!({ "removed": removed })))
+}
+
+async fn scan<E>(
+    State(db): State<Arc<DB<DynRecord, E>>>,
+    Json(body): Json<serde_json::Value>,
+) -> Answer
+where
+    E: Executor + Send + Sync + 'static,
+{
+    let schema = db.schema.read().await.record_schema.clone();
+    let scan =
+        UserScan::from_json(&body, key_desc(&schema), SCAN_BATCH_SIZE).map_err(Error::invalid)?;
+    let records = records(&db, &schema, scan).await?;
+    Ok(Json(json!({ "records": records })))
+}
+
+async fn transaction<E>(
+    State(db): State<Arc<DB<DynRecord, E>>>,
+    Json(body): Json<serde_json::Value>,
+) -> Answer
+where
+    E: Executor + Send + Sync + 'static,
+{
+    let schema = db.schema.read().await.record_schema.clone();
+    let records = dyn_records(&schema, body.get("insert"))?;
+    let keys = match body.get("remove") {
+        None | Some(serde_json::Value::Null) => Vec::new(),
+        Some(serde_json::Value::Array(keys)) => keys
+            .iter()
+            .map(|key| key_value(&schema, key))
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err(Error::invalid("remove is not a list of keys")),
+    };
+    let rows = records.len() + keys.len();
+
+    let mut txn = db.transaction().await;
+    for record in records {
+        txn.insert(record);
+    }
+    for key in keys {
+        txn.remove(key);
+    }
+    txn.commit().await.map_err(Error::commit)?;
+    Ok(Json(json!({ "rows": rows })))
+}
+
+fn key_desc(schema: &DynSchema) -> &ValueDesc {
+    &schema.value_descs()[schema.primary_index()]
+}
+
+fn key(schema: &DynSchema, key: Option<&serde_json::Value>) -> Result<Value, Error> {
+    key_value(schema, key.ok_or_else(|| Error::invalid("key is missing"))?)
+}
+
+fn key_value(schema: &DynSchema, key: &serde_json::Value) -> Result<Value, Error> {
+    json_value(key_desc(schema), key).map_err(|err| Error::invalid(format!("invalid key: {err}")))
+}
+
+/// the records of the JSON objects of `records`
+fn dyn_records(
+    schema: &DynSchema,
+    records: Option<&serde_json::Value>,
+) -> Result<Vec<DynRecord>, Error> {
+    let records = match records {
+        None | Some(serde_json::Value::Null) => return Ok(Vec::new()),
+        Some(serde_json::Value::Array(records)) => records,
+        Some(_) => return Err(Error::invalid("records are not a list")),
+    };
+    records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            let serde_json::Value::Object(object) = record else {
+                return Err(Error::invalid(format!("record {i} is not a JSON object")));
+            };
+            json_object(object, schema.value_descs())
+                .map(|values| DynRecord::new(values, schema.primary_index()))
+                .map_err(|err| Error::invalid(format!("record {i}: {err}")))
+        })
+        .collect()
+}
+
+/// the JSON objects of the records read by `scan`
+async fn records<E>(
+    db: &Arc<DB<DynRecord, E>>,
+    schema: &DynSchema,
+    scan: UserScan<Value>,
+) -> Result<Vec<serde_json::Value>, Error>
+where
+    E: Executor + Send + Sync + 'static,
+{
+    let batch_schema = user_schema(
+        db.ctx.arrow_schema(),
+        schema.primary_key_index(),
+        scan.projection.as_deref(),
+    )
+    .map_err(|err| Error::invalid(format!("invalid scan: {err}")))?;
+    let mut batches = pin!(user_scan(db.clone(), scan));
+    let mut writer = ArrayWriter::new(Vec::new());
+    while let Some(batch) = batches.next().await {
+        let batch = batch.map_err(Error::internal)?;
+        writer
+            .write(&user_batch(&batch_schema, &batch).map_err(Error::internal)?)
+            .map_err(Error::internal)?;
+    }
+    writer.finish().map_err(Error::internal)?;
+    serde_json::from_slice(&writer.into_inner()).map_err(Error::internal)
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use fusio::path::Path;
+    use serde_json::json;
+    use tempfile::TempDir;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::HttpServer;
+    use crate::{dyn_schema, executor::tokio::TokioExecutor, DbOption, DB};
+
+    /// the status and the JSON body of the answer to `body` posted to `path`
+    async fn post(
+        addr: std::net::SocketAddr,
+        path: &str,
+        token: &str,
+        body: serde_json::Value,
+    ) -> (u16, serde_json::Value) {
+        let body = body.to_string();
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "POST {path} HTTP/1.1\r\nHost: localhost\r\nAuthorization: {token}\r\nContent-Type: \
+             application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn http_endpoints() {
+        let temp_dir = TempDir::new().unwrap();
+        let schema = dyn_schema!(("id", Int64, false), ("name", String, true), 0);
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &schema,
+        );
+        let db = Arc::new(
+            DB::new(option, TokioExecutor::current(), schema)
+                .await
+                .unwrap(),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            HttpServer::new(db)
+                .with_authenticate(|authorization: Option<&str>| match authorization {
+                    Some("Bearer secret") => Ok(()),
+                    _ => Err("unknown token".to_string()),
+                })
+                .serve(listener),
+        );
+        let token = "Bearer secret";
+
+        let (status, body) = post(addr, "/scan", "Bearer guess", json!({})).await;
+        assert_eq!(status, 401);
+        assert_eq!(body, json!({ "error": "unknown token" }));
+
+        let records = json!([
+            { "id": 1, "name": "a" },
+            { "id": 2 },
+            { "id": 3, "name": "c" },
+        ]);
+        let (status, body) = post(addr, "/insert", token, json!({ "records": records })).await;
+        assert_eq!((status, body), (200, json!({ "rows": 3 })));
+        let (_, body) = post(addr, "/remove", token, json!({ "key": 3 })).await;
+        assert_eq!(body, json!({ "removed": true }));
+
+        let (_, body) = post(addr, "/get", token, json!({ "key": 1 })).await;
+        assert_eq!(body, json!({ "record": { "id": 1, "name": "a" } }));
+        let (_, body) = post(addr, "/get", token, json!({ "key": 3 })).await;
+        assert_eq!(body, json!({ "record": null }));
+
+        let transaction = json!({ "insert": [{ "id": 4, "name": "d" }], "remove": [1] });
+        let (_, body) = post(addr, "/transaction", token, transaction).await;
+        assert_eq!(body, json!({ "rows": 2 }));
+        let (_, body) = post(
+            addr,
+            "/scan",
+            token,
+            json!({ "lower": { "excluded": 1 }, "projection": ["id"] }),
+        )
+        .await;
+        assert_eq!(body, json!({ "records": [{ "id": 2 }, { "id": 4 }] }));
+
+        let (status, _) = post(addr, "/insert", token, json!({ "records": [{ "age": 1 }] })).await;
+        assert_eq!(status, 400);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::Bound, sync::Arc};
+use std::{collections::Bound, sync::Arc, time::Instant};
 
 use async_lock::RwLockReadGuard;
 use parquet::arrow::ProjectionMask;
@@ -20,6 +20,7 @@ where
     share: RwLockReadGuard<'s, DbStorage<R>>,
     version: VersionRef<R>,
     ctx: Arc<Context<R>>,
+    deadline: Option<Instant>,
 }
 
 impl<'s, R> Snapshot<'s, R>
@@ -33,8 +34,16 @@ where
     ) -> Result<Option<stream::Entry<'get, R>>, DbError<R>> {
         Ok(self
             .share
-            .get(&self.ctx, &self.version, key, self.ts, projection)
-            .await?
+            .get(
+                &self.ctx,
+                &self.version,
+                key,
+                self.ts,
+                projection,
+                self.deadline,
+            )
+            .await
+            .map_err(DbError::or_deadline_exceeded)?
             .and_then(|entry| {
                 if entry.value().is_none() {
                     None
@@ -44,6 +53,30 @@ where
             }))
     }
 
+    /// get the records of `keys` in one batched lookup, results are returned in the order of
+    /// `keys`
+    pub async fn get_many<'get>(
+        &'get self,
+        keys: &[&'get <R::Schema as RecordSchema>::Key],
+        projection: Projection<'get>,
+    ) -> Result<Vec<Option<stream::Entry<'get, R>>>, DbError<R>> {
+        Ok(self
+            .share
+            .get_many(
+                &self.ctx,
+                &self.version,
+                keys,
+                self.ts,
+                projection,
+                self.deadline,
+            )
+            .await
+            .map_err(DbError::or_deadline_exceeded)?
+            .into_iter()
+            .map(|entry| entry.filter(|entry| entry.value().is_some()))
+            .collect())
+    }
+
     pub fn scan<'scan, 'range>(
         &'scan self,
         range: (
@@ -59,21 +92,40 @@ where
             Box::new(move |_: Option<ProjectionMask>| None),
             self.ctx.clone(),
         )
+        .deadline(self.deadline)
     }
 
     pub(crate) fn new(
         share: RwLockReadGuard<'s, DbStorage<R>>,
         version: VersionRef<R>,
         ctx: Arc<Context<R>>,
+    ) -> Self {
+        let ts = version.load_ts();
+        Self::with_ts(share, version, ts, ctx)
+    }
+
+    /// read view at `ts` instead of the latest timestamp
+    pub(crate) fn with_ts(
+        share: RwLockReadGuard<'s, DbStorage<R>>,
+        version: VersionRef<R>,
+        ts: Timestamp,
+        ctx: Arc<Context<R>>,
     ) -> Self {
         Self {
-            ts: version.load_ts(),
+            ts,
             share,
             version,
             ctx,
+            deadline: None,
         }
     }
 
+    /// bound the SSTable reads of the snapshot by `deadline`, see
+    /// [`ReadOptions::deadline`](crate::ReadOptions::deadline)
+    pub(crate) fn with_deadline(self, deadline: Option<Instant>) -> Self {
+        Self { deadline, ..self }
+    }
+
     pub(crate) fn ts(&self) -> Timestamp {
         self.ts
     }
@@ -82,6 +134,14 @@ where
         self.version.increase_ts()
     }
 
+    pub(crate) fn version(&self) -> &VersionRef<R> {
+        &self.version
+    }
+
+    pub(crate) fn ctx(&self) -> &Arc<Context<R>> {
+        &self.ctx
+    }
+
     pub(crate) fn schema(&self) -> &DbStorage<R> {
         &self.share
     }
@@ -104,12 +164,17 @@ where
             fn_pre_stream,
             self.ctx.clone(),
         )
+        .deadline(self.deadline)
     }
 }
 
 #[cfg(all(test, feature = "tokio"))]
 mod tests {
-    use std::{collections::Bound, sync::Arc};
+    use std::{
+        collections::Bound,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
 
     use fusio::path::Path;
     use fusio_dispatch::FsOptions;
@@ -121,8 +186,8 @@ mod tests {
         executor::tokio::TokioExecutor,
         fs::manager::StoreManager,
         inmem::immutable::tests::TestSchema,
-        tests::{build_db, build_schema},
-        DbOption,
+        tests::{build_db, build_schema, Test},
+        DbError, DbOption, Projection, ReadOptions, DB,
     };
 
     #[tokio::test(flavor = "multi_thread")]
@@ -214,4 +279,118 @@ mod tests {
         let entry_14 = stream.next().await.unwrap().unwrap();
         assert_eq!(entry_14.key().value, "funk");
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn snapshot_with_max_staleness() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        let insert = |i: u32| {
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i,
+                vbool: None,
+            })
+        };
+        let options = ReadOptions::default().max_staleness(Duration::from_secs(3600));
+
+        insert(0).await.unwrap();
+        let ts = {
+            let snapshot = db.snapshot_with(options).await;
+            assert!(snapshot
+                .get(&0.to_string(), Projection::All)
+                .await
+                .unwrap()
+                .is_some());
+            snapshot.ts()
+        };
+
+        insert(1).await.unwrap();
+        {
+            // served from the pinned version
+            let snapshot = db.snapshot_with(options).await;
+            assert_eq!(snapshot.ts(), ts);
+            assert!(snapshot
+                .get(&1.to_string(), Projection::All)
+                .await
+                .unwrap()
+                .is_none());
+        }
+        {
+            let snapshot = db.snapshot_with(ReadOptions::default()).await;
+            assert!(snapshot.ts() > ts);
+            assert!(snapshot
+                .get(&1.to_string(), Projection::All)
+                .await
+                .unwrap()
+                .is_some());
+        }
+
+        // a new version drops the pin
+        db.flush().await.unwrap();
+        let snapshot = db.snapshot_with(options).await;
+        assert!(snapshot.ts() > ts);
+        assert!(snapshot
+            .get(&1.to_string(), Projection::All)
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn snapshot_with_deadline() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        for i in 0..2 {
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+
+        let options = ReadOptions::default().deadline(Instant::now() + Duration::from_secs(3600));
+        let snapshot = db.snapshot_with(options).await;
+        assert!(snapshot
+            .get(&0.to_string(), Projection::All)
+            .await
+            .unwrap()
+            .is_some());
+        drop(snapshot);
+
+        // the deadline has passed before the SSTables are read
+        let options = ReadOptions::default().deadline(Instant::now());
+        let snapshot = db.snapshot_with(options).await;
+        assert!(matches!(
+            snapshot.get(&0.to_string(), Projection::All).await,
+            Err(DbError::DeadlineExceeded)
+        ));
+        assert!(matches!(
+            snapshot
+                .get_many(&[&0.to_string(), &1.to_string()], Projection::All)
+                .await,
+            Err(DbError::DeadlineExceeded)
+        ));
+        assert!(matches!(
+            snapshot
+                .scan((Bound::Unbounded, Bound::Unbounded))
+                .take()
+                .await,
+            Err(DbError::DeadlineExceeded)
+        ));
+    }
 }
@@ -0,0 +1,1006 @@
+//! A minimal SQL front end over [`DB`].
+//!
+//! Only single-table queries of the following form are supported:
+//!
+//! ```sql
+//! SELECT * | column [, column ...] FROM table
+//!     [WHERE predicate]
+//!     [LIMIT n]
+//! ```
+//!
+//! where a predicate combines `column <op> literal` (`=`, `!=`, `<>`, `<`, `<=`, `>`, `>=`) and
+//! `column IS [NOT] NULL` with `AND`, `OR` and parentheses. Literals are integers, floats,
+//! `'strings'`, `TRUE`, `FALSE` and `NULL`. Keywords are case-insensitive and identifiers can be
+//! double-quoted. The table name is not checked since a [`DB`] holds a single table.
+use std::{fmt, iter::Peekable, ops::Bound, str::Chars, sync::Arc};
+
+use arrow::{
+    array::{
+        Array, ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, Scalar, StringArray,
+        UInt32Array, UInt64Array,
+    },
+    compute::{
+        and_kleene, cast, cast_with_options, filter_record_batch, is_not_null, is_null,
+        kernels::cmp::{eq, gt, gt_eq, lt, lt_eq, neq},
+        or_kleene, CastOptions,
+    },
+    datatypes::{DataType, Schema as ArrowSchema},
+    error::ArrowError,
+};
+use futures_core::Stream;
+use futures_util::StreamExt;
+use parquet::arrow::{ArrowSchemaConverter, ProjectionMask};
+use thiserror::Error;
+
+use crate::{
+    executor::Executor,
+    magic,
+    record::{KeyRef, Record, RecordRef, Schema},
+    DbError, DB,
+};
+
+const SCAN_BATCH_SIZE: usize = 8192;
+
+#[derive(Debug, Error)]
+pub enum SqlError<R>
+where
+    R: Record,
+{
+    #[error("sql parse error: {0}")]
+    Parse(String),
+    #[error("sql unknown column: {0}")]
+    UnknownColumn(String),
+    #[error("sql type mismatch: {0}")]
+    TypeMismatch(String),
+    #[error("sql arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+    #[error("sql db error: {0}")]
+    Db(#[from] DbError<R>),
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Run a `SELECT` query against the latest snapshot and return the matching rows as a stream
+    /// of [`RecordBatch`]es. See the [module documentation](crate::sql) for the supported syntax.
+    ///
+    /// Only the selected and filtered columns are read. The comparisons of the primary key with a
+    /// literal at the top level of the `WHERE` clause (`pk = x`, `pk < x`, `pk >= x`, ...) bound
+    /// the range of the scan, the other predicates are evaluated batch-wise with Arrow compute
+    /// kernels. A literal out of the range of the type of its column, e.g. `-1` compared with an
+    /// unsigned column, is compared as a float.
+    ///
+    /// Syntax errors, unknown columns and literals compared with columns of another type (a
+    /// string with a number, ...) are reported before anything is read.
+    pub async fn query_sql(
+        &self,
+        sql: &str,
+    ) -> Result<impl Stream<Item = Result<RecordBatch, SqlError<R>>> + '_, SqlError<R>> {
+        let query = parse(sql).map_err(SqlError::Parse)?;
+        let snapshot = self.snapshot().await;
+        let primary_key_index = snapshot.schema().record_schema.primary_key_index();
+        let schema = self.ctx.arrow_schema();
+        let plan = Plan::new::<R>(query, schema, schema.field(primary_key_index).name())?;
+        let (lower, upper) = plan.key_range::<R>(schema, primary_key_index)?;
+
+        Ok(async_stream::stream! {
+            if is_empty_range(&lower, &upper) {
+                return;
+            }
+            let mut scan = snapshot.scan((lower.as_ref(), upper.as_ref()));
+            let projection = plan.scan_columns.iter().map(String::as_str).collect::<Vec<_>>();
+            scan = scan.projection(&projection);
+            if let (None, Some(limit)) = (&plan.predicate, plan.limit) {
+                scan = scan.limit(limit);
+            }
+            let mut batches = scan.scan_batches(SCAN_BATCH_SIZE).await?;
+            let mut remaining = plan.limit.unwrap_or(usize::MAX);
+
+            while remaining > 0 {
+                let Some(batch) = batches.next().await else {
+                    break;
+                };
+                let batch = batch.map_err(DbError::<R>::from)?;
+                let exceeds_bound = plan.exceeds_upper_bound(&batch)?;
+                let mut batch = match &plan.predicate {
+                    Some(predicate) => filter_record_batch(&batch, &predicate.evaluate(&batch)?)?,
+                    None => batch,
+                };
+                if batch.num_rows() > remaining {
+                    batch = batch.slice(0, remaining);
+                }
+                remaining -= batch.num_rows();
+
+                if batch.num_rows() > 0 {
+                    yield Ok(plan.output(&batch)?);
+                }
+                if exceeds_bound {
+                    break;
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Number(String),
+    Str(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) | Token::Number(word) => write!(f, "{}", word),
+            Token::Quoted(ident) => write!(f, "\"{}\"", ident),
+            Token::Str(string) => write!(f, "'{}'", string),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, String> {
+    fn take_while(chars: &mut Peekable<Chars<'_>>, f: impl Fn(char) -> bool) -> String {
+        let mut buf = String::new();
+        while let Some(c) = chars.next_if(|c| f(*c)) {
+            buf.push(c);
+        }
+        buf
+    }
+
+    fn quoted(chars: &mut Peekable<Chars<'_>>, quote: char) -> Result<String, String> {
+        let mut buf = String::new();
+        loop {
+            match chars.next() {
+                // a doubled quote escapes itself
+                Some(c) if c == quote && chars.next_if_eq(&quote).is_some() => buf.push(c),
+                Some(c) if c == quote => return Ok(buf),
+                Some(c) => buf.push(c),
+                None => return Err(format!("unterminated {}", quote)),
+            }
+        }
+    }
+
+    let mut chars = sql.chars().peekable();
+    let mut tokens = Vec::new();
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                Token::Word(take_while(&mut chars, |c| c.is_alphanumeric() || c == '_'))
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut number = String::new();
+                if let Some(sign) = chars.next_if_eq(&'-') {
+                    number.push(sign);
+                }
+                number.push_str(&take_while(&mut chars, |c| c.is_ascii_digit() || c == '.'));
+                Token::Number(number)
+            }
+            '\'' => {
+                chars.next();
+                Token::Str(quoted(&mut chars, '\'')?)
+            }
+            '"' => {
+                chars.next();
+                Token::Quoted(quoted(&mut chars, '"')?)
+            }
+            _ => {
+                chars.next();
+                let symbol = match (c, chars.peek().copied()) {
+                    ('<', Some('=')) => "<=",
+                    ('>', Some('=')) => ">=",
+                    ('<', Some('>')) | ('!', Some('=')) => "!=",
+                    ('=', _) => "=",
+                    ('<', _) => "<",
+                    ('>', _) => ">",
+                    ('*', _) => "*",
+                    (',', _) => ",",
+                    ('(', _) => "(",
+                    (')', _) => ")",
+                    (';', _) => ";",
+                    _ => return Err(format!("unexpected character {}", c)),
+                };
+                if symbol.len() == 2 {
+                    chars.next();
+                }
+                Token::Symbol(symbol)
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+    Cmp {
+        column: String,
+        op: CmpOp,
+        literal: Literal,
+    },
+    IsNull {
+        column: String,
+        negated: bool,
+    },
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+}
+
+#[derive(Debug, PartialEq)]
+struct Query {
+    /// `None` for `*`
+    columns: Option<Vec<String>>,
+    predicate: Option<Predicate>,
+    limit: Option<usize>,
+}
+
+fn parse(sql: &str) -> Result<Query, String> {
+    Parser::new(sql)?.parse_query()
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(sql: &str) -> Result<Self, String> {
+        Ok(Self {
+            tokens: tokenize(sql)?,
+            pos: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn bump(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, String> {
+        Err(match self.tokens.get(self.pos) {
+            Some(token) => format!("expected {}, found {}", expected, token),
+            None => format!("expected {}, found end of input", expected),
+        })
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let is_keyword = self.is_keyword(keyword);
+        if is_keyword {
+            self.pos += 1;
+        }
+        is_keyword
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let is_symbol = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if is_symbol {
+            self.pos += 1;
+        }
+        is_symbol
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if !self.eat_keyword(keyword) {
+            return self.error(keyword);
+        }
+        Ok(())
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Word(word)) if !is_reserved(word) => {}
+            Some(Token::Quoted(_)) => {}
+            _ => return self.error("identifier"),
+        }
+        match self.bump() {
+            Some(Token::Word(ident)) | Some(Token::Quoted(ident)) => Ok(ident),
+            _ => unreachable!(),
+        }
+    }
+
+    fn parse_query(mut self) -> Result<Query, String> {
+        self.expect_keyword("SELECT")?;
+        let columns = if self.eat_symbol("*") {
+            None
+        } else {
+            let mut columns = vec![self.identifier()?];
+            while self.eat_symbol(",") {
+                columns.push(self.identifier()?);
+            }
+            Some(columns)
+        };
+        self.expect_keyword("FROM")?;
+        self.identifier()?;
+
+        let predicate = if self.eat_keyword("WHERE") {
+            Some(self.parse_or()?)
+        } else {
+            None
+        };
+        let limit = if self.eat_keyword("LIMIT") {
+            match self.peek() {
+                Some(Token::Number(number)) => match number.parse::<usize>() {
+                    Ok(limit) => {
+                        self.pos += 1;
+                        Some(limit)
+                    }
+                    Err(_) => return self.error("non-negative integer"),
+                },
+                _ => return self.error("non-negative integer"),
+            }
+        } else {
+            None
+        };
+        self.eat_symbol(";");
+        if self.peek().is_some() {
+            return self.error("end of input");
+        }
+
+        Ok(Query {
+            columns,
+            predicate,
+            limit,
+        })
+    }
+
+    fn parse_or(&mut self) -> Result<Predicate, String> {
+        let mut predicate = self.parse_and()?;
+        while self.eat_keyword("OR") {
+            predicate = Predicate::Or(Box::new(predicate), Box::new(self.parse_and()?));
+        }
+        Ok(predicate)
+    }
+
+    fn parse_and(&mut self) -> Result<Predicate, String> {
+        let mut predicate = self.parse_primary()?;
+        while self.eat_keyword("AND") {
+            predicate = Predicate::And(Box::new(predicate), Box::new(self.parse_primary()?));
+        }
+        Ok(predicate)
+    }
+
+    fn parse_primary(&mut self) -> Result<Predicate, String> {
+        if self.eat_symbol("(") {
+            let predicate = self.parse_or()?;
+            if !self.eat_symbol(")") {
+                return self.error(")");
+            }
+            return Ok(predicate);
+        }
+        let column = self.identifier()?;
+
+        if self.eat_keyword("IS") {
+            let negated = self.eat_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(Predicate::IsNull { column, negated });
+        }
+        let op = match self.peek() {
+            Some(Token::Symbol("=")) => CmpOp::Eq,
+            Some(Token::Symbol("!=")) => CmpOp::NotEq,
+            Some(Token::Symbol("<")) => CmpOp::Lt,
+            Some(Token::Symbol("<=")) => CmpOp::LtEq,
+            Some(Token::Symbol(">")) => CmpOp::Gt,
+            Some(Token::Symbol(">=")) => CmpOp::GtEq,
+            _ => return self.error("comparison operator"),
+        };
+        self.pos += 1;
+        let literal = self.literal()?;
+
+        Ok(Predicate::Cmp {
+            column,
+            op,
+            literal,
+        })
+    }
+
+    fn literal(&mut self) -> Result<Literal, String> {
+        let literal = match self.peek() {
+            Some(Token::Number(number)) => {
+                if let Ok(v) = number.parse::<i64>() {
+                    Literal::Int(v)
+                } else if let Ok(v) = number.parse::<u64>() {
+                    Literal::UInt(v)
+                } else if let Ok(v) = number.parse::<f64>() {
+                    Literal::Float(v)
+                } else {
+                    return self.error("number");
+                }
+            }
+            Some(Token::Str(string)) => Literal::Str(string.clone()),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("TRUE") => Literal::Bool(true),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("FALSE") => Literal::Bool(false),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("NULL") => Literal::Null,
+            _ => return self.error("literal"),
+        };
+        self.pos += 1;
+        Ok(literal)
+    }
+}
+
+fn is_reserved(word: &str) -> bool {
+    [
+        "SELECT", "FROM", "WHERE", "LIMIT", "AND", "OR", "IS", "NOT", "NULL", "TRUE", "FALSE",
+    ]
+    .iter()
+    .any(|keyword| word.eq_ignore_ascii_case(keyword))
+}
+
+impl Predicate {
+    fn columns<'a>(&'a self, columns: &mut Vec<&'a str>) {
+        match self {
+            Predicate::Cmp { column, .. } | Predicate::IsNull { column, .. } => {
+                columns.push(column)
+            }
+            Predicate::And(lhs, rhs) | Predicate::Or(lhs, rhs) => {
+                lhs.columns(columns);
+                rhs.columns(columns);
+            }
+        }
+    }
+
+    /// the literals compared with the columns that they cannot be compared with
+    fn check_types(&self, schema: &ArrowSchema) -> Result<(), String> {
+        match self {
+            Predicate::Cmp {
+                column, literal, ..
+            } => {
+                let data_type = schema
+                    .field_with_name(column)
+                    .map_err(|_| column.clone())?
+                    .data_type();
+                if !literal.is_comparable(data_type) {
+                    return Err(format!(
+                        "{} of type {} compared with {}",
+                        column, data_type, literal
+                    ));
+                }
+                Ok(())
+            }
+            Predicate::IsNull { .. } => Ok(()),
+            Predicate::And(lhs, rhs) | Predicate::Or(lhs, rhs) => {
+                lhs.check_types(schema)?;
+                rhs.check_types(schema)
+            }
+        }
+    }
+
+    /// top-level conjuncts comparing `column` with a literal
+    fn bounds<'a>(&'a self, column: &str, bounds: &mut Vec<(CmpOp, &'a Literal)>) {
+        match self {
+            Predicate::Cmp {
+                column: c,
+                op,
+                literal,
+            } if c == column && *op != CmpOp::NotEq && *literal != Literal::Null => {
+                bounds.push((*op, literal))
+            }
+            Predicate::And(lhs, rhs) => {
+                lhs.bounds(column, bounds);
+                rhs.bounds(column, bounds);
+            }
+            _ => {}
+        }
+    }
+
+    /// top-level conjuncts bounding `column` from above
+    fn upper_bounds<'a>(&'a self, column: &str, bounds: &mut Vec<&'a Predicate>) {
+        match self {
+            Predicate::Cmp {
+                column: c,
+                op: CmpOp::Eq | CmpOp::Lt | CmpOp::LtEq,
+                literal,
+            } if c == column && *literal != Literal::Null => bounds.push(self),
+            Predicate::And(lhs, rhs) => {
+                lhs.upper_bounds(column, bounds);
+                rhs.upper_bounds(column, bounds);
+            }
+            _ => {}
+        }
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<BooleanArray, ArrowError> {
+        match self {
+            Predicate::Cmp {
+                column,
+                op,
+                literal,
+            } => {
+                let mut array = column_by_name(batch, column)?.clone();
+                let mut literal_array = literal.to_array(array.data_type())?;
+                // fractions and literals out of the range of the type of the column are compared
+                // as floats
+                if (matches!(literal, Literal::Float(_)) && array.data_type().is_integer())
+                    || (literal_array.is_null(0) && *literal != Literal::Null)
+                {
+                    array = cast(&array, &DataType::Float64)?;
+                    literal_array = literal.to_array(&DataType::Float64)?;
+                }
+                let scalar = Scalar::new(literal_array);
+
+                match op {
+                    CmpOp::Eq => eq(&array, &scalar),
+                    CmpOp::NotEq => neq(&array, &scalar),
+                    CmpOp::Lt => lt(&array, &scalar),
+                    CmpOp::LtEq => lt_eq(&array, &scalar),
+                    CmpOp::Gt => gt(&array, &scalar),
+                    CmpOp::GtEq => gt_eq(&array, &scalar),
+                }
+            }
+            Predicate::IsNull { column, negated } => {
+                let array = column_by_name(batch, column)?;
+                if *negated {
+                    is_not_null(array)
+                } else {
+                    is_null(array)
+                }
+            }
+            Predicate::And(lhs, rhs) => and_kleene(&lhs.evaluate(batch)?, &rhs.evaluate(batch)?),
+            Predicate::Or(lhs, rhs) => or_kleene(&lhs.evaluate(batch)?, &rhs.evaluate(batch)?),
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Int(v) => write!(f, "{}", v),
+            Literal::UInt(v) => write!(f, "{}", v),
+            Literal::Float(v) => write!(f, "{}", v),
+            Literal::Str(v) => write!(f, "'{}'", v),
+            Literal::Bool(v) => write!(f, "{}", v),
+            Literal::Null => write!(f, "NULL"),
+        }
+    }
+}
+
+impl Literal {
+    /// whether the literal can be compared with a column of `data_type`, numbers are only
+    /// compared with numbers, strings with strings and booleans with booleans
+    fn is_comparable(&self, data_type: &DataType) -> bool {
+        match self {
+            Literal::Int(_) | Literal::UInt(_) | Literal::Float(_) => data_type.is_numeric(),
+            Literal::Str(_) => matches!(
+                data_type,
+                DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+            ),
+            Literal::Bool(_) => data_type == &DataType::Boolean,
+            Literal::Null => true,
+        }
+    }
+
+    /// single-element array of `data_type` holding exactly the literal, `None` if converting it
+    /// would truncate or overflow it
+    fn to_exact_array(&self, data_type: &DataType) -> Result<Option<ArrayRef>, ArrowError> {
+        if matches!(self, Literal::Float(_)) && !data_type.is_floating() {
+            return Ok(None);
+        }
+        let array = self.to_array(data_type)?;
+        Ok((!array.is_null(0)).then_some(array))
+    }
+
+    /// single-element array of `data_type`, null if the literal does not fit into it, see
+    /// [`Literal::is_comparable`] for the types checked beforehand
+    fn to_array(&self, data_type: &DataType) -> Result<ArrayRef, ArrowError> {
+        let array: ArrayRef = match self {
+            Literal::Int(v) => Arc::new(Int64Array::from(vec![*v])),
+            Literal::UInt(v) => Arc::new(UInt64Array::from(vec![*v])),
+            Literal::Float(v) => Arc::new(Float64Array::from(vec![*v])),
+            Literal::Str(v) => Arc::new(StringArray::from(vec![v.as_str()])),
+            Literal::Bool(v) => Arc::new(BooleanArray::from(vec![*v])),
+            Literal::Null => return Ok(arrow::array::new_null_array(data_type, 1)),
+        };
+        cast_with_options(
+            &array,
+            data_type,
+            &CastOptions {
+                safe: true,
+                ..Default::default()
+            },
+        )
+    }
+}
+
+fn column_by_name<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a ArrayRef, ArrowError> {
+    batch
+        .column_by_name(name)
+        .ok_or_else(|| ArrowError::SchemaError(format!("column {} not found in batch", name)))
+}
+
+/// whether no key is in the range of `lower` and `upper`
+fn is_empty_range<K: Ord>(lower: &Bound<K>, upper: &Bound<K>) -> bool {
+    match (lower, upper) {
+        (Bound::Included(lower), Bound::Included(upper)) => lower > upper,
+        (Bound::Included(lower), Bound::Excluded(upper))
+        | (Bound::Excluded(lower), Bound::Included(upper))
+        | (Bound::Excluded(lower), Bound::Excluded(upper)) => lower >= upper,
+        _ => false,
+    }
+}
+
+/// the tighter of two bounds on the same side of a range, `is_tighter` compares their keys
+fn tighter<K: Ord>(current: Bound<K>, bound: Bound<K>, is_tighter: fn(&K, &K) -> bool) -> Bound<K> {
+    match (&current, &bound) {
+        (_, Bound::Unbounded) => current,
+        (Bound::Unbounded, _) => bound,
+        (
+            Bound::Included(current_key) | Bound::Excluded(current_key),
+            Bound::Included(key) | Bound::Excluded(key),
+        ) => {
+            if is_tighter(key, current_key)
+                || (key == current_key && matches!(bound, Bound::Excluded(_)))
+            {
+                bound
+            } else {
+                current
+            }
+        }
+    }
+}
+
+/// the key of `array` alone, a single value of the type of the primary key
+fn key_of<R>(
+    array: ArrayRef,
+    schema: &Arc<ArrowSchema>,
+    primary_key_index: usize,
+) -> Result<<R::Schema as Schema>::Key, ArrowError>
+where
+    R: Record,
+{
+    let indices = [0, 1, primary_key_index];
+    let batch = RecordBatch::try_new(
+        Arc::new(schema.project(&indices)?),
+        vec![
+            Arc::new(BooleanArray::from(vec![false])),
+            Arc::new(UInt32Array::from(vec![0])),
+            array,
+        ],
+    )?;
+    let mask = ProjectionMask::roots(
+        &ArrowSchemaConverter::new()
+            .convert(schema)
+            .map_err(|err| ArrowError::ExternalError(Box::new(err)))?,
+        indices,
+    );
+    Ok(R::Ref::from_record_batch(&batch, 0, &mask, schema)
+        .key()
+        .value
+        .to_key())
+}
+
+struct Plan {
+    /// user columns passed to [`crate::Scan::projection`]
+    scan_columns: Vec<String>,
+    output_columns: Vec<String>,
+    predicate: Option<Predicate>,
+    /// top-level comparisons of the primary key, pushed down to the range of the scan
+    key_bounds: Vec<(CmpOp, Literal)>,
+    upper_bounds: Vec<Predicate>,
+    limit: Option<usize>,
+}
+
+impl Plan {
+    fn new<R>(query: Query, schema: &ArrowSchema, primary_key: &str) -> Result<Self, SqlError<R>>
+    where
+        R: Record,
+    {
+        let output_columns = match query.columns {
+            Some(columns) => columns,
+            None => schema
+                .fields()
+                .iter()
+                .skip(magic::USER_COLUMN_OFFSET)
+                .map(|field| field.name().clone())
+                .collect(),
+        };
+        let mut referenced = output_columns
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        if let Some(predicate) = &query.predicate {
+            predicate.columns(&mut referenced);
+        }
+        for column in referenced.iter() {
+            if schema.index_of(column).is_err() {
+                return Err(SqlError::UnknownColumn(column.to_string()));
+            }
+        }
+        if let Some(predicate) = &query.predicate {
+            predicate
+                .check_types(schema)
+                .map_err(SqlError::TypeMismatch)?;
+        }
+        // `_null`, `_ts` and the primary key are always read
+        let mut scan_columns = Vec::new();
+        for column in referenced {
+            if column != magic::NULL
+                && column != magic::TS
+                && column != primary_key
+                && !scan_columns.iter().any(|c| c == column)
+            {
+                scan_columns.push(column.to_string());
+            }
+        }
+        let mut key_bounds = Vec::new();
+        let mut upper_bounds = Vec::new();
+        if let Some(predicate) = &query.predicate {
+            predicate.bounds(primary_key, &mut key_bounds);
+            predicate.upper_bounds(primary_key, &mut upper_bounds);
+        }
+        let key_bounds = key_bounds
+            .into_iter()
+            .map(|(op, literal)| (op, literal.clone()))
+            .collect();
+        let upper_bounds = upper_bounds.into_iter().cloned().collect();
+
+        Ok(Self {
+            scan_columns,
+            output_columns,
+            predicate: query.predicate,
+            key_bounds,
+            upper_bounds,
+            limit: query.limit,
+        })
+    }
+
+    /// the range of the primary key of the scan, the tightest of the comparisons of the key.
+    /// Comparisons with literals that do not convert exactly to the type of the key are left to
+    /// the predicate
+    #[allow(clippy::type_complexity)]
+    fn key_range<R>(
+        &self,
+        schema: &Arc<ArrowSchema>,
+        primary_key_index: usize,
+    ) -> Result<
+        (
+            Bound<<R::Schema as Schema>::Key>,
+            Bound<<R::Schema as Schema>::Key>,
+        ),
+        ArrowError,
+    >
+    where
+        R: Record,
+    {
+        let data_type = schema.field(primary_key_index).data_type();
+        let mut lower = Bound::Unbounded;
+        let mut upper = Bound::Unbounded;
+        for (op, literal) in self.key_bounds.iter() {
+            let Some(array) = literal.to_exact_array(data_type)? else {
+                continue;
+            };
+            let key = key_of::<R>(array, schema, primary_key_index)?;
+            let (key_lower, key_upper) = match op {
+                CmpOp::Eq => (Bound::Included(key.clone()), Bound::Included(key)),
+                CmpOp::Lt => (Bound::Unbounded, Bound::Excluded(key)),
+                CmpOp::LtEq => (Bound::Unbounded, Bound::Included(key)),
+                CmpOp::Gt => (Bound::Excluded(key), Bound::Unbounded),
+                CmpOp::GtEq => (Bound::Included(key), Bound::Unbounded),
+                CmpOp::NotEq => continue,
+            };
+            lower = tighter(lower, key_lower, |a, b| a > b);
+            upper = tighter(upper, key_upper, |a, b| a < b);
+        }
+        Ok((lower, upper))
+    }
+
+    /// whether the last (largest) primary key of `batch` already fails an upper bound, in which
+    /// case no later batch can match
+    fn exceeds_upper_bound(&self, batch: &RecordBatch) -> Result<bool, ArrowError> {
+        if batch.num_rows() == 0 {
+            return Ok(false);
+        }
+        let last = batch.slice(batch.num_rows() - 1, 1);
+        for bound in self.upper_bounds.iter() {
+            let result = bound.evaluate(&last)?;
+            if result.is_valid(0) && !result.value(0) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn output(&self, batch: &RecordBatch) -> Result<RecordBatch, ArrowError> {
+        let schema = batch.schema();
+        let indices = self
+            .output_columns
+            .iter()
+            .map(|column| schema.index_of(column))
+            .collect::<Result<Vec<_>, _>>()?;
+        batch.project(&indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, CmpOp, Literal, Predicate, Query};
+
+    #[test]
+    fn parse_query() {
+        assert_eq!(
+            parse("select * from t").unwrap(),
+            Query {
+                columns: None,
+                predicate: None,
+                limit: None,
+            }
+        );
+        assert_eq!(
+            parse(
+                "SELECT vstring, \"vu32\" FROM t WHERE vu32 >= 2 AND (vbool IS NOT NULL OR \
+                 vstring <> 'it''s') LIMIT 10;"
+            )
+            .unwrap(),
+            Query {
+                columns: Some(vec!["vstring".into(), "vu32".into()]),
+                predicate: Some(Predicate::And(
+                    Box::new(Predicate::Cmp {
+                        column: "vu32".into(),
+                        op: CmpOp::GtEq,
+                        literal: Literal::Int(2),
+                    }),
+                    Box::new(Predicate::Or(
+                        Box::new(Predicate::IsNull {
+                            column: "vbool".into(),
+                            negated: true,
+                        }),
+                        Box::new(Predicate::Cmp {
+                            column: "vstring".into(),
+                            op: CmpOp::NotEq,
+                            literal: Literal::Str("it's".into()),
+                        }),
+                    )),
+                )),
+                limit: Some(10),
+            }
+        );
+
+        assert!(matches!(parse("select from t"), Err(_)));
+        assert!(matches!(parse("select * from t where a = "), Err(_)));
+        assert!(matches!(parse("select * from t limit -1"), Err(_)));
+        assert!(matches!(parse("select * from t where a = 'x"), Err(_)));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn query_sql() {
+        use arrow::{
+            array::{AsArray, RecordBatch},
+            datatypes::UInt32Type,
+        };
+        use fusio::path::Path;
+        use futures_util::StreamExt;
+        use tempfile::TempDir;
+
+        use super::SqlError;
+        use crate::{
+            executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test,
+            DbOption, DB,
+        };
+
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        for i in 0..10 {
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i,
+                vbool: (i % 2 == 0).then_some(true),
+            })
+            .await
+            .unwrap();
+            if i == 4 {
+                db.flush().await.unwrap();
+            }
+        }
+        db.remove(6.to_string()).await.unwrap();
+
+        let collect = |sql: &'static str| {
+            let db = &db;
+            async move {
+                db.query_sql(sql)
+                    .await
+                    .unwrap()
+                    .map(|batch| batch.unwrap())
+                    .collect::<Vec<RecordBatch>>()
+                    .await
+            }
+        };
+        let vu32 = |batches: &[RecordBatch]| {
+            batches
+                .iter()
+                .flat_map(|batch| {
+                    batch
+                        .column_by_name("vu32")
+                        .unwrap()
+                        .as_primitive::<UInt32Type>()
+                        .values()
+                        .to_vec()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let batches = collect("SELECT * FROM test").await;
+        assert_eq!(batches[0].num_columns(), 3);
+        assert_eq!(vu32(&batches), vec![0, 1, 2, 3, 4, 5, 7, 8, 9]);
+
+        let batches =
+            collect("select vu32 from test where vu32 > 2 and vbool is not null limit 2").await;
+        assert_eq!(batches[0].num_columns(), 1);
+        assert_eq!(vu32(&batches), vec![4, 8]);
+
+        let batches = collect("select vu32 from test where vstring <= '3' or vu32 = 9.0").await;
+        assert_eq!(vu32(&batches), vec![0, 1, 2, 3, 9]);
+
+        let batches = collect("select vu32 from test where vu32 < 1.5").await;
+        assert_eq!(vu32(&batches), vec![0, 1]);
+
+        // bounds of the primary key narrow the scan
+        let batches = collect("select vu32 from test where vstring >= '5' and vstring < '8'").await;
+        assert_eq!(vu32(&batches), vec![5, 7]);
+        let batches = collect("select vu32 from test where vstring = '3' and vu32 > 0").await;
+        assert_eq!(vu32(&batches), vec![3]);
+        let batches = collect("select vu32 from test where vstring > '5' and vstring < '2'").await;
+        assert!(batches.is_empty());
+
+        // a literal out of the range of an unsigned column
+        let batches = collect("select vu32 from test where vu32 > -1").await;
+        assert_eq!(vu32(&batches), vec![0, 1, 2, 3, 4, 5, 7, 8, 9]);
+        let batches = collect("select vu32 from test where vu32 = -1").await;
+        assert!(batches.is_empty());
+
+        assert!(matches!(
+            db.query_sql("select vu32 from test where vstring = 1")
+                .await,
+            Err(SqlError::TypeMismatch(_))
+        ));
+
+        assert!(matches!(
+            db.query_sql("select missing from test").await,
+            Err(SqlError::UnknownColumn(column)) if column == "missing"
+        ));
+    }
+}
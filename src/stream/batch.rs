@@ -0,0 +1,409 @@
+use std::{
+    mem,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use arrow::{
+    array::{Array, ArrayRef, RecordBatch},
+    compute::interleave,
+    datatypes::Schema as ArrowSchema,
+};
+use futures_core::Stream;
+use parquet::errors::ParquetError;
+use pin_project_lite::pin_project;
+
+use crate::{
+    inmem::immutable::{ArrowArrays, Builder},
+    record::{Record, Schema},
+    stream::{
+        expr::{self, ScanExpr},
+        merge::MergeStream,
+        Entry,
+    },
+};
+
+/// placeholder source index of rows pushed into the in-memory builder, resolved when the batch is
+/// finished
+const MEMORY_SOURCE: usize = usize::MAX;
+
+pin_project! {
+    /// Assembles the merged LSM stream into [`RecordBatch`]es of at most `batch_size` rows.
+    ///
+    /// Rows coming from immutable memtables and SSTables are gathered from their source arrays with
+    /// [`interleave`], only rows living in the mutable memtable (or in a transaction) are pushed
+    /// through the [`Builder`]. Attached [`ScanExpr`]s are evaluated on every assembled batch.
+    pub struct BatchStream<'batch, R>
+    where
+        R: Record,
+    {
+        batch_size: usize,
+        inner: MergeStream<'batch, R>,
+        builder: <<R::Schema as Schema>::Columns as ArrowArrays>::Builder,
+        builder_len: usize,
+        sources: Vec<Vec<ArrayRef>>,
+        indices: Vec<(usize, usize)>,
+        projection_indices: Option<Vec<usize>>,
+        schema: Arc<ArrowSchema>,
+        exprs: Vec<ScanExpr>,
+    }
+}
+
+impl<'batch, R> BatchStream<'batch, R>
+where
+    R: Record,
+{
+    pub(crate) fn new(
+        batch_size: usize,
+        merge: MergeStream<'batch, R>,
+        projection_indices: Option<Vec<usize>>,
+        full_schema: Arc<ArrowSchema>,
+        exprs: Vec<ScanExpr>,
+    ) -> Self {
+        let schema = match &projection_indices {
+            Some(indices) => Arc::new(
+                full_schema
+                    .project(indices)
+                    .expect("projection indices must be successful"),
+            ),
+            None => full_schema.clone(),
+        };
+
+        Self {
+            batch_size,
+            inner: merge,
+            builder: <R::Schema as Schema>::Columns::builder(full_schema, batch_size),
+            builder_len: 0,
+            sources: Vec::new(),
+            indices: Vec::with_capacity(batch_size),
+            projection_indices,
+            schema,
+            exprs,
+        }
+    }
+
+    /// take the columns of `batch` in the order of `schema`
+    fn source_columns(
+        schema: &ArrowSchema,
+        batch: &RecordBatch,
+    ) -> Result<Vec<ArrayRef>, ParquetError> {
+        schema
+            .fields()
+            .iter()
+            .map(|field| {
+                batch.column_by_name(field.name()).cloned().ok_or_else(|| {
+                    ParquetError::General(format!("column {} not found in batch", field.name()))
+                })
+            })
+            .collect()
+    }
+}
+
+impl<'batch, R> Stream for BatchStream<'batch, R>
+where
+    R: Record,
+{
+    type Item = Result<RecordBatch, ParquetError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let project = self.project();
+
+        while project.indices.len() < *project.batch_size {
+            match Pin::new(&mut *project.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(Entry::RecordBatch(entry)))) => {
+                    // filter null
+                    if entry.get().is_none() {
+                        continue;
+                    }
+                    let batch = entry.record_batch();
+                    // `_null` is always the first column of a source, so it identifies the batch
+                    let is_same_source = project
+                        .sources
+                        .last()
+                        .is_some_and(|source| Arc::ptr_eq(&source[0], batch.column(0)));
+                    if !is_same_source {
+                        match Self::source_columns(project.schema, batch) {
+                            Ok(columns) => project.sources.push(columns),
+                            Err(err) => return Poll::Ready(Some(Err(err))),
+                        }
+                    }
+                    project
+                        .indices
+                        .push((project.sources.len() - 1, entry.offset()));
+                }
+                Poll::Ready(Some(Ok(entry))) => {
+                    if let Some(record) = entry.value() {
+                        project.builder.push(entry.key(), Some(record));
+                        project.indices.push((MEMORY_SOURCE, *project.builder_len));
+                        *project.builder_len += 1;
+                    }
+                }
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) => break,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        if project.indices.is_empty() {
+            return Poll::Ready(None);
+        }
+
+        if mem::take(project.builder_len) != 0 {
+            let columns = project
+                .builder
+                .finish(project.projection_indices.as_deref());
+            let memory_source = project.sources.len();
+
+            match Self::source_columns(project.schema, columns.as_record_batch()) {
+                Ok(columns) => project.sources.push(columns),
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+            for (source, _) in project.indices.iter_mut() {
+                if *source == MEMORY_SOURCE {
+                    *source = memory_source;
+                }
+            }
+        }
+        let indices = mem::take(project.indices);
+        let sources = mem::take(project.sources);
+
+        let result = (0..project.schema.fields().len())
+            .map(|i| {
+                let arrays = sources
+                    .iter()
+                    .map(|source| source[i].as_ref())
+                    .collect::<Vec<&dyn Array>>();
+                interleave(&arrays, &indices)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|columns| RecordBatch::try_new(project.schema.clone(), columns))
+            .and_then(|batch| expr::evaluate(project.exprs, batch))
+            .map_err(ParquetError::from);
+
+        Poll::Ready(Some(result))
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::ops::Bound;
+
+    use arrow::{
+        array::{AsArray, RecordBatch},
+        compute::{cast, kernels::substring::substring},
+        datatypes::{DataType, UInt32Type, UInt64Type},
+    };
+    use fusio::path::Path;
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, stream::ScanExpr,
+        tests::Test, DbOption, KeyProjection, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scan_batches() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        for i in 0..6 {
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i,
+                vbool: Some(true),
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+        for i in 4..10 {
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i * 10,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.remove(0.to_string()).await.unwrap();
+
+        let txn = db.transaction().await;
+        let batches = txn
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .projection(&["vu32"])
+            .scan_batches(4)
+            .await
+            .unwrap()
+            .map(|batch| batch.unwrap())
+            .collect::<Vec<RecordBatch>>()
+            .await;
+
+        assert_eq!(
+            batches
+                .iter()
+                .map(RecordBatch::num_rows)
+                .collect::<Vec<_>>(),
+            vec![4, 4, 1]
+        );
+        for batch in batches.iter() {
+            assert_eq!(batch.num_columns(), 4);
+        }
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+        for batch in batches.iter() {
+            let vstring = batch.column_by_name("vstring").unwrap().as_string::<i32>();
+            let vu32 = batch
+                .column_by_name("vu32")
+                .unwrap()
+                .as_primitive::<UInt32Type>();
+            for i in 0..batch.num_rows() {
+                keys.push(vstring.value(i).to_string());
+                values.push(vu32.value(i));
+            }
+        }
+        assert_eq!(
+            keys,
+            (1..10).map(|i: u32| i.to_string()).collect::<Vec<_>>()
+        );
+        assert_eq!(values, vec![1, 2, 3, 40, 50, 60, 70, 80, 90]);
+
+        // a batch size of 0 is taken as 1
+        let rows = txn
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .scan_batches(0)
+            .await
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(rows, vec![1; 9]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scan_batches_with_expression() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        for i in 0..3 {
+            db.insert(Test {
+                vstring: format!("key_{i}"),
+                vu32: i,
+                vbool: Some(true),
+            })
+            .await
+            .unwrap();
+        }
+
+        let txn = db.transaction().await;
+        let batches = txn
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .expression(ScanExpr::new("vu32", |batch: &RecordBatch| {
+                cast(batch.column_by_name("vu32").unwrap(), &DataType::UInt64)
+            }))
+            .expression(ScanExpr::new("suffix", |batch: &RecordBatch| {
+                substring(batch.column_by_name("vstring").unwrap(), 4, None)
+            }))
+            .scan_batches(8)
+            .await
+            .unwrap()
+            .map(|batch| batch.unwrap())
+            .collect::<Vec<RecordBatch>>()
+            .await;
+
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_columns(), 6);
+        assert_eq!(
+            batch
+                .column_by_name("vu32")
+                .unwrap()
+                .as_primitive::<UInt64Type>()
+                .values(),
+            &[0, 1, 2]
+        );
+        let suffix = batch.column_by_name("suffix").unwrap().as_string::<i32>();
+        assert_eq!(
+            suffix.iter().flatten().collect::<Vec<_>>(),
+            vec!["0", "1", "2"]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scan_batches_key_projection() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        for i in 0..4 {
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i,
+                vbool: Some(true),
+            })
+            .await
+            .unwrap();
+            if i == 1 {
+                db.flush().await.unwrap();
+            }
+        }
+
+        let txn = db.transaction().await;
+        let column_names = |key_projection: KeyProjection, projection: &'static [&'static str]| {
+            let txn = &txn;
+            async move {
+                let batches = txn
+                    .scan((Bound::Unbounded, Bound::Unbounded))
+                    .projection(projection)
+                    .key_projection(key_projection)
+                    .scan_batches(8)
+                    .await
+                    .unwrap()
+                    .map(|batch| batch.unwrap())
+                    .collect::<Vec<RecordBatch>>()
+                    .await;
+                assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), 4);
+                batches[0]
+                    .schema()
+                    .fields()
+                    .iter()
+                    .map(|field| field.name().clone())
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(
+            column_names(KeyProjection::Always, &["vu32"]).await,
+            vec!["_null", "_ts", "vstring", "vu32"]
+        );
+        assert_eq!(
+            column_names(KeyProjection::KeyOnly, &["vu32"]).await,
+            vec!["_null", "_ts", "vstring"]
+        );
+        assert_eq!(
+            column_names(KeyProjection::ValueOnly, &["vu32"]).await,
+            vec!["_null", "_ts", "vu32"]
+        );
+        assert_eq!(
+            column_names(KeyProjection::ValueOnly, &["vstring", "vu32"]).await,
+            vec!["_null", "_ts", "vstring", "vu32"]
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use std::{mem::transmute, sync::Arc};
+
+use arrow::{
+    array::{new_null_array, Array, ArrayRef, RecordBatch},
+    datatypes::Schema as ArrowSchema,
+    error::ArrowError,
+};
+use parquet::arrow::ProjectionMask;
+
+use crate::{
+    inmem::immutable::{ArrowArrays, Builder},
+    merge::MergeOperator,
+    record::{option::OptionRecordRef, Record, RecordRef, Schema},
+    stream::{record_batch::RecordBatchEntry, Entry},
+};
+
+/// Folds the versions of a key written under
+/// [`UpdateStrategy::MergeOnRead`](crate::UpdateStrategy::MergeOnRead) into a single row: every
+/// column takes its newest non-null value. With a [`MergeOperator`], the versions are combined
+/// by the operator instead.
+pub(crate) struct DeltaMerger<R>
+where
+    R: Record,
+{
+    full_schema: Arc<ArrowSchema>,
+    schema: Arc<ArrowSchema>,
+    indices: Vec<usize>,
+    projection: ProjectionMask,
+    keeps_tombstones: bool,
+    operator: Option<Arc<dyn MergeOperator<R>>>,
+}
+
+impl<R> DeltaMerger<R>
+where
+    R: Record,
+{
+    /// merged rows only hold the columns included in `projection`, besides `_null`, `_ts` and
+    /// the primary key, the same as rows read from SSTables
+    pub(crate) fn new(
+        full_schema: Arc<ArrowSchema>,
+        primary_key_index: usize,
+        projection: ProjectionMask,
+    ) -> Self {
+        let indices = (0..full_schema.fields().len())
+            .filter(|i| *i < 2 || *i == primary_key_index || projection.leaf_included(*i))
+            .collect::<Vec<_>>();
+        let schema = Arc::new(
+            full_schema
+                .project(&indices)
+                .expect("projection indices must be successful"),
+        );
+
+        Self {
+            full_schema,
+            schema,
+            indices,
+            projection,
+            keeps_tombstones: false,
+            operator: None,
+        }
+    }
+
+    /// keep the tombstone that ends the versions folded into a row, compactions need it to hide
+    /// the versions of tables that are not compacted
+    pub(crate) fn keep_tombstones(self) -> Self {
+        Self {
+            keeps_tombstones: true,
+            ..self
+        }
+    }
+
+    /// combine the versions with `operator`, which needs versions read with every column
+    pub(crate) fn operator(self, operator: Arc<dyn MergeOperator<R>>) -> Self {
+        Self {
+            operator: Some(operator),
+            ..self
+        }
+    }
+
+    pub(crate) fn keeps_tombstones(&self) -> bool {
+        self.keeps_tombstones
+    }
+
+    /// whether no older version changes the merge of `entry` and the versions newer than it: it
+    /// holds every merged column. Versions combined by an operator never are
+    pub(crate) fn is_complete(&self, entry: &Entry<'_, R>) -> bool {
+        if self.operator.is_some() {
+            return false;
+        }
+        let (batch, offset) = self.source(entry);
+        self.indices.iter().skip(2).all(|index| {
+            batch
+                .column_by_name(self.full_schema.field(*index).name())
+                .is_some_and(|column| column.is_valid(offset))
+        })
+    }
+
+    /// merge `versions` of a key, ordered from the newest. None of them may be a tombstone.
+    pub(crate) fn merge<'entry>(
+        &self,
+        versions: &[Entry<'entry, R>],
+    ) -> Result<Entry<'entry, R>, ArrowError> {
+        if let Some(operator) = &self.operator {
+            return self.combine(operator.as_ref(), versions);
+        }
+        let sources = versions
+            .iter()
+            .map(|entry| self.source(entry))
+            .collect::<Vec<_>>();
+        let columns = self
+            .indices
+            .iter()
+            .map(|index| {
+                let field = self.full_schema.field(*index);
+                sources
+                    .iter()
+                    .find_map(|(batch, offset)| {
+                        batch
+                            .column_by_name(field.name())
+                            .filter(|column| *index < 2 || column.is_valid(*offset))
+                            .map(|column| column.slice(*offset, 1))
+                    })
+                    .unwrap_or_else(|| new_null_array(field.data_type(), 1))
+            })
+            .collect::<Vec<ArrayRef>>();
+
+        Ok(self.entry(RecordBatch::try_new(self.schema.clone(), columns)?))
+    }
+
+    /// apply `operator` from the oldest version, the merged row keeps the key and the timestamp of
+    /// the newest one
+    fn combine<'entry>(
+        &self,
+        operator: &dyn MergeOperator<R>,
+        versions: &[Entry<'entry, R>],
+    ) -> Result<Entry<'entry, R>, ArrowError> {
+        let mut operands = versions.iter().rev();
+        let Some(oldest) = operands.next() else {
+            return Err(ArrowError::InvalidArgumentError(
+                "no version to merge".to_string(),
+            ));
+        };
+        let mut merged = None::<R>;
+        for operand in operands.filter_map(Entry::value) {
+            merged = Some(match &merged {
+                Some(existing) => operator.merge(existing.as_record_ref(), operand),
+                None => match oldest.value() {
+                    Some(existing) => operator.merge(existing, operand),
+                    None => continue,
+                },
+            });
+        }
+
+        let mut builder = <R::Schema as Schema>::Columns::builder(self.full_schema.clone(), 1);
+        match &merged {
+            Some(merged) => builder.push(versions[0].key(), Some(merged.as_record_ref())),
+            None => builder.push(versions[0].key(), oldest.value()),
+        }
+        let columns = builder.finish(Some(self.indices.as_slice()));
+
+        Ok(self.entry(columns.as_record_batch().clone()))
+    }
+
+    fn entry<'entry>(&self, record_batch: RecordBatch) -> Entry<'entry, R> {
+        let record_ref =
+            R::Ref::from_record_batch(&record_batch, 0, &self.projection, &self.full_schema);
+        // Safety: self-referring lifetime is safe
+        let record_ref = unsafe {
+            transmute::<OptionRecordRef<'_, R::Ref<'_>>, OptionRecordRef<'static, R::Ref<'static>>>(
+                record_ref,
+            )
+        };
+        Entry::RecordBatch(RecordBatchEntry::new(record_batch.clone(), 0, record_ref))
+    }
+
+    /// the batch and the row offset holding `entry`, rows living in memory are built into a
+    /// single-row batch
+    fn source(&self, entry: &Entry<'_, R>) -> (RecordBatch, usize) {
+        match entry {
+            Entry::RecordBatch(entry) => (entry.record_batch().clone(), entry.offset()),
+            Entry::Projection((entry, _)) => self.source(entry),
+            Entry::Transaction(_) | Entry::Mutable(_) => {
+                let mut builder =
+                    <R::Schema as Schema>::Columns::builder(self.full_schema.clone(), 1);
+                builder.push(entry.key(), entry.value());
+                (builder.finish(None).as_record_batch().clone(), 0)
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::ops::Bound;
+
+    use arrow::array::{AsArray, RecordBatch};
+    use fusio::path::Path;
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        Projection, UpdateStrategy, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn merge_on_read() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .update_strategy(UpdateStrategy::MergeOnRead);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        for key in ["a", "b"] {
+            db.insert(Test {
+                vstring: key.to_string(),
+                vu32: 1,
+                vbool: Some(true),
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+        // deltas only carry `vu32`
+        for key in ["a", "b"] {
+            db.insert(Test {
+                vstring: key.to_string(),
+                vu32: 2,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.remove("b".to_string()).await.unwrap();
+        db.insert(Test {
+            vstring: "b".to_string(),
+            vu32: 3,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+
+        let row = |key: &'static str| {
+            let db = &db;
+            async move {
+                db.get(&key.to_string(), |entry| {
+                    let record = entry.get();
+                    Some((record.vu32, record.vbool))
+                })
+                .await
+                .unwrap()
+            }
+        };
+        assert_eq!(row("a").await, Some((Some(2), Some(true))));
+        // the removal resets the columns
+        assert_eq!(row("b").await, Some((Some(3), None)));
+
+        let txn = db.transaction().await;
+        let vbool = txn
+            .get(&"a".to_string(), Projection::Parts(vec!["vbool"]))
+            .await
+            .unwrap()
+            .unwrap()
+            .get()
+            .vbool;
+        assert_eq!(vbool, Some(true));
+        drop(txn);
+
+        let mut txn = db.transaction().await;
+        txn.insert(Test {
+            vstring: "a".to_string(),
+            vu32: 4,
+            vbool: None,
+        });
+        let entry = txn.get(&"a".to_string(), Projection::All).await.unwrap();
+        let record = entry.as_ref().unwrap().get();
+        assert_eq!((record.vu32, record.vbool), (Some(4), Some(true)));
+        drop(entry);
+        txn.commit().await.unwrap();
+
+        db.flush().await.unwrap();
+        let txn = db.transaction().await;
+        let batches = txn
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .projection(&["vbool"])
+            .scan_batches(8)
+            .await
+            .unwrap()
+            .map(|batch| batch.unwrap())
+            .collect::<Vec<RecordBatch>>()
+            .await;
+        let vbool = batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column_by_name("vbool")
+                    .unwrap()
+                    .as_boolean()
+                    .iter()
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vbool, vec![Some(true), None]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn merged_get_stops_at_complete_version() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .update_strategy(UpdateStrategy::MergeOnRead);
+        let db: DB<Test, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+        let get = || async {
+            db.get(&"a".to_string(), |entry| {
+                let record = entry.get();
+                Some((record.vu32, record.vbool))
+            })
+            .await
+            .unwrap()
+        };
+
+        for vbool in [Some(true), None] {
+            db.insert(Test {
+                vstring: "a".to_string(),
+                vu32: 1,
+                vbool,
+            })
+            .await
+            .unwrap();
+            db.flush().await.unwrap();
+        }
+        // the delta of the newer table is merged with the older one
+        assert_eq!(get().await, Some((Some(1), Some(true))));
+
+        db.insert(Test {
+            vstring: "a".to_string(),
+            vu32: 2,
+            vbool: Some(false),
+        })
+        .await
+        .unwrap();
+        // the version of the memtable holds every column, the tables are not opened
+        let version = db.ctx.version_set.current().await;
+        assert_eq!(version.tables_len(0), 2);
+        for scope in version.level_slice[0].iter() {
+            std::fs::remove_file(
+                fusio::path::path_to_local(&option.table_path(scope.gen, 0)).unwrap(),
+            )
+            .unwrap();
+        }
+        drop(version);
+        assert_eq!(get().await, Some((Some(2), Some(false))));
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{ArrayRef, RecordBatch},
+    datatypes::{Field, Schema as ArrowSchema},
+    error::ArrowError,
+};
+
+type ExprFn = dyn Fn(&RecordBatch) -> Result<ArrayRef, ArrowError> + Send + Sync;
+
+/// A named column computed from every scanned [`RecordBatch`].
+///
+/// The expression is evaluated batch-wise inside [`crate::Scan::scan_batches`], so it is usually
+/// built on top of Arrow compute kernels (`cast`, `substring`, arithmetic and so on). If `name`
+/// is already a column of the batch, that column is replaced, otherwise the result is appended.
+/// Expressions are evaluated in the order they are attached and can refer to columns produced by
+/// earlier ones.
+#[derive(Clone)]
+pub struct ScanExpr {
+    name: String,
+    func: Arc<ExprFn>,
+}
+
+impl ScanExpr {
+    pub fn new<F>(name: impl Into<String>, func: F) -> Self
+    where
+        F: Fn(&RecordBatch) -> Result<ArrayRef, ArrowError> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            func: Arc::new(func),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Debug for ScanExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScanExpr")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// evaluate `exprs` against `batch`, replacing or appending their columns
+pub(crate) fn evaluate(exprs: &[ScanExpr], batch: RecordBatch) -> Result<RecordBatch, ArrowError> {
+    exprs.iter().try_fold(batch, |batch, expr| {
+        let array = (expr.func)(&batch)?;
+        let field = Arc::new(Field::new(
+            expr.name.as_str(),
+            array.data_type().clone(),
+            true,
+        ));
+        let schema = batch.schema();
+        let mut fields = schema.fields().to_vec();
+        let (_, mut columns, _) = batch.into_parts();
+
+        match schema.index_of(&expr.name) {
+            Ok(index) => {
+                fields[index] = field;
+                columns[index] = array;
+            }
+            Err(_) => {
+                fields.push(field);
+                columns.push(array);
+            }
+        }
+        RecordBatch::try_new(
+            Arc::new(ArrowSchema::new_with_metadata(
+                fields,
+                schema.metadata().clone(),
+            )),
+            columns,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{AsArray, RecordBatch, StringArray, UInt32Array, UInt64Array},
+        compute::{
+            cast,
+            kernels::{numeric::mul, substring::substring},
+        },
+        datatypes::{DataType, Field, Schema, UInt64Type},
+    };
+
+    use super::{evaluate, ScanExpr};
+
+    #[test]
+    fn evaluate_exprs() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, false),
+            Field::new("age", DataType::UInt32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(vec!["tonbo", "fusio"])),
+                Arc::new(UInt32Array::from(vec![1, 2])),
+            ],
+        )
+        .unwrap();
+
+        let exprs = vec![
+            ScanExpr::new("prefix", |batch: &RecordBatch| {
+                substring(batch.column_by_name("name").unwrap(), 0, Some(2))
+            }),
+            ScanExpr::new("age", |batch: &RecordBatch| {
+                cast(batch.column_by_name("age").unwrap(), &DataType::UInt64)
+            }),
+            ScanExpr::new("double_age", |batch: &RecordBatch| {
+                let age = batch.column_by_name("age").unwrap();
+                mul(age, &UInt64Array::new_scalar(2))
+            }),
+        ];
+        let batch = evaluate(&exprs, batch).unwrap();
+
+        assert_eq!(batch.num_columns(), 4);
+        let prefix = batch.column_by_name("prefix").unwrap().as_string::<i32>();
+        assert_eq!(prefix.value(0), "to");
+        assert_eq!(prefix.value(1), "fu");
+
+        let age = batch.column_by_name("age").unwrap();
+        assert_eq!(age.data_type(), &DataType::UInt64);
+        assert_eq!(batch.schema().index_of("age").unwrap(), 1);
+
+        let double_age = batch
+            .column_by_name("double_age")
+            .unwrap()
+            .as_primitive::<UInt64Type>();
+        assert_eq!(double_age.values(), &[2, 4]);
+    }
+}
@@ -6,19 +6,18 @@ use std::{
     task::{Context, Poll},
 };
 
-use fusio::{
-    dynamic::{DynFile, MaybeSendFuture},
-    path::Path,
-    DynFs, Error,
-};
+use fusio::DynFs;
 use futures_core::Stream;
+use futures_util::StreamExt;
 use parquet::{arrow::ProjectionMask, errors::ParquetError};
 use parquet_lru::DynLruCache;
 use ulid::Ulid;
 
 use crate::{
-    fs::{FileId, FileType},
-    ondisk::{scan::SsTableScan, sstable::SsTable},
+    cache::table::{self, TableCache},
+    fs::FileId,
+    memory::MemoryTracker,
+    ondisk::{readahead::Readahead, scan::SsTableScan, sstable::SsTable},
     record::{Record, Schema},
     scope::Scope,
     stream::record_batch::RecordBatchEntry,
@@ -27,20 +26,17 @@ use crate::{
     DbOption,
 };
 
-enum FutureStatus<'level, R>
+/// the scan of a table with its first entry, `None` when the range has no entry in the table
+type Opened<'level, R> =
+    Result<Option<(SsTableScan<'level, R>, RecordBatchEntry<R>)>, ParquetError>;
+
+/// a table of the level opened ahead of the one being read
+enum Ahead<'level, R>
 where
     R: Record,
 {
-    Init(FileId),
-    Ready(SsTableScan<'level, R>),
-    OpenFile(
-        Ulid,
-        Pin<Box<dyn MaybeSendFuture<Output = Result<Box<dyn DynFile>, Error>> + 'level>>,
-    ),
-    OpenSst(Pin<Box<dyn MaybeSendFuture<Output = Result<SsTable<R>, Error>> + 'level>>),
-    LoadStream(
-        Pin<Box<dyn Future<Output = Result<SsTableScan<'level, R>, ParquetError>> + Send + 'level>>,
-    ),
+    Opening(Pin<Box<dyn Future<Output = Opened<'level, R>> + Send + 'level>>),
+    Opened(Opened<'level, R>),
 }
 
 pub(crate) struct LevelStream<'level, R>
@@ -55,10 +51,15 @@ where
     gens: VecDeque<FileId>,
     limit: Option<usize>,
     projection_mask: ProjectionMask,
-    status: FutureStatus<'level, R>,
+    /// the scan of the table being read
+    current: Option<SsTableScan<'level, R>>,
+    /// the next tables, up to [`DbOption::scan_parallelism`] tables are read at once
+    ahead: VecDeque<Ahead<'level, R>>,
     fs: Arc<dyn DynFs>,
-    path: Option<Path>,
     parquet_lru: Arc<dyn DynLruCache<Ulid> + Send + Sync>,
+    memory: Option<MemoryTracker>,
+    table_cache: Option<Arc<TableCache>>,
+    borrow_values: bool,
 }
 
 impl<'level, R> LevelStream<'level, R>
@@ -83,12 +84,13 @@ where
         parquet_lru: Arc<dyn DynLruCache<Ulid> + Send + Sync>,
     ) -> Option<Self> {
         let (lower, upper) = range;
-        let mut gens: VecDeque<FileId> = version.level_slice[level][start..end + 1]
+        let gens: VecDeque<FileId> = version.level_slice[level][start..end + 1]
             .iter()
             .map(Scope::gen)
             .collect();
-        let first_gen = gens.pop_front()?;
-        let status = FutureStatus::Init(first_gen);
+        if gens.is_empty() {
+            return None;
+        }
 
         Some(LevelStream {
             lower,
@@ -99,12 +101,75 @@ where
             gens,
             limit,
             projection_mask,
-            status,
+            current: None,
+            ahead: VecDeque::new(),
             fs,
-            path: None,
             parquet_lru,
+            memory: None,
+            table_cache: None,
+            borrow_values: false,
         })
     }
+
+    /// count the record batches being read into `memory`
+    pub(crate) fn with_memory(self, memory: MemoryTracker) -> Self {
+        LevelStream {
+            memory: Some(memory),
+            ..self
+        }
+    }
+
+    /// keep the tables read open in `table_cache`
+    pub(crate) fn with_table_cache(self, table_cache: Option<Arc<TableCache>>) -> Self {
+        LevelStream {
+            table_cache,
+            ..self
+        }
+    }
+
+    /// borrow the values of the records from the record batches, see
+    /// [`RecordRef::from_record_batch_borrowed`](crate::record::RecordRef::from_record_batch_borrowed)
+    pub(crate) fn borrow_values(self, borrow_values: bool) -> Self {
+        LevelStream {
+            borrow_values,
+            ..self
+        }
+    }
+
+    /// open the table `gen` and read its first entry
+    fn open_table(&self, gen: FileId) -> Ahead<'level, R> {
+        let fs = self.fs.clone();
+        let path = self.option.table_path(gen, self.level);
+        let table_cache = self.table_cache.clone();
+        let parquet_lru = self.parquet_lru.clone();
+        let readahead = Readahead::new(
+            &self.option,
+            table_cache.clone(),
+            fs.clone(),
+            path.clone(),
+            gen,
+            parquet_lru.clone(),
+        );
+        let memory = self.memory.clone();
+        let (range, ts, limit) = ((self.lower, self.upper), self.ts, self.limit);
+        let projection_mask = self.projection_mask.clone();
+        let borrow_values = self.borrow_values;
+        Ahead::Opening(Box::pin(async move {
+            let reader = table::open(table_cache.as_ref(), &fs, &path, gen)
+                .await
+                .map_err(|err| ParquetError::External(Box::new(err)))?;
+            let mut scan = SsTable::from_reader(parquet_lru, gen, reader)
+                .await
+                .with_readahead(readahead)
+                .scan(range, ts, limit, projection_mask)
+                .await?
+                .borrow_values(borrow_values);
+            if let Some(memory) = memory {
+                scan = scan.with_memory(memory);
+            }
+            Ok(scan.next().await.transpose()?.map(|entry| (scan, entry)))
+        }))
+    }
 }
 
 impl<'level, R> Stream for LevelStream<'level, R>
@@ -115,104 +180,61 @@ where
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         loop {
-            return match &mut self.status {
-                FutureStatus::Init(gen) => {
-                    let gen = *gen;
-                    self.path = Some(self.option.table_path(gen, self.level));
-
-                    let reader = self.fs.open_options(
-                        self.path.as_ref().unwrap(),
-                        FileType::Parquet.open_options(true),
-                    );
-                    #[allow(clippy::missing_transmute_annotations)]
-                    let reader = unsafe {
-                        std::mem::transmute::<
-                            _,
-                            Pin<
-                                Box<
-                                    dyn MaybeSendFuture<Output = Result<Box<dyn DynFile>, Error>>
-                                        + 'static,
-                                >,
-                            >,
-                        >(reader)
-                    };
-                    self.status = FutureStatus::OpenFile(gen, reader);
-                    continue;
-                }
-                FutureStatus::Ready(stream) => match Pin::new(stream).poll_next(cx) {
-                    Poll::Ready(None) => match self.gens.pop_front() {
-                        None => Poll::Ready(None),
-                        Some(gen) => {
-                            self.path = Some(self.option.table_path(gen, self.level));
-
-                            let reader = self.fs.open_options(
-                                self.path.as_ref().unwrap(),
-                                FileType::Parquet.open_options(true),
-                            );
-                            #[allow(clippy::missing_transmute_annotations)]
-                            let reader = unsafe {
-                                std::mem::transmute::<
-                                    _,
-                                    Pin<
-                                        Box<
-                                            dyn MaybeSendFuture<
-                                                    Output = Result<Box<dyn DynFile>, Error>,
-                                                > + 'static,
-                                        >,
-                                    >,
-                                >(reader)
-                            };
-                            self.status = FutureStatus::OpenFile(gen, reader);
-                            continue;
-                        }
-                    },
-                    Poll::Ready(Some(result)) => {
-                        if let Some(limit) = &mut self.limit {
-                            *limit -= 1;
-                        }
-                        Poll::Ready(Some(result))
-                    }
-                    Poll::Pending => Poll::Pending,
-                },
-                FutureStatus::OpenFile(id, file_future) => match Pin::new(file_future).poll(cx) {
-                    Poll::Ready(Ok(file)) => {
-                        let id = *id;
-                        self.status = FutureStatus::OpenSst(Box::pin(SsTable::open(
-                            self.parquet_lru.clone(),
-                            id,
-                            file,
-                        )));
-                        continue;
+            if self.limit == Some(0) {
+                return Poll::Ready(None);
+            }
+            // the tables are read in order, the next ones are opened and their first row groups
+            // fetched while the current one is read
+            let parallelism = self.option.scan_parallelism.max(1);
+            while self.ahead.len() + usize::from(self.current.is_some()) < parallelism {
+                match self.gens.pop_front() {
+                    Some(gen) => {
+                        let ahead = self.open_table(gen);
+                        self.ahead.push_back(ahead);
                     }
-                    Poll::Ready(Err(err)) => {
-                        Poll::Ready(Some(Err(ParquetError::External(Box::new(err)))))
+                    None => break,
+                }
+            }
+            for ahead in self.ahead.iter_mut() {
+                if let Ahead::Opening(future) = ahead {
+                    if let Poll::Ready(opened) = future.as_mut().poll(cx) {
+                        *ahead = Ahead::Opened(opened);
                     }
-                    Poll::Pending => Poll::Pending,
-                },
-                FutureStatus::OpenSst(sst_future) => match Pin::new(sst_future).poll(cx) {
-                    Poll::Ready(Ok(sst)) => {
-                        self.status = FutureStatus::LoadStream(Box::pin(sst.scan(
-                            (self.lower, self.upper),
-                            self.ts,
-                            self.limit,
-                            self.projection_mask.clone(),
-                        )));
+                }
+            }
+
+            let entry = if let Some(scan) = &mut self.current {
+                match Pin::new(scan).poll_next(cx) {
+                    Poll::Ready(Some(result)) => result,
+                    Poll::Ready(None) => {
+                        self.current = None;
                         continue;
                     }
-                    Poll::Ready(Err(err)) => {
-                        Poll::Ready(Some(Err(ParquetError::External(Box::new(err)))))
-                    }
-                    Poll::Pending => Poll::Pending,
-                },
-                FutureStatus::LoadStream(stream_future) => match Pin::new(stream_future).poll(cx) {
-                    Poll::Ready(Ok(scan)) => {
-                        self.status = FutureStatus::Ready(scan);
-                        continue;
+                    Poll::Pending => return Poll::Pending,
+                }
+            } else {
+                match self.ahead.front() {
+                    None => return Poll::Ready(None),
+                    Some(Ahead::Opening(_)) => return Poll::Pending,
+                    Some(Ahead::Opened(_)) => {}
+                }
+                // SAFETY: the next table is opened
+                let Some(Ahead::Opened(opened)) = self.ahead.pop_front() else {
+                    unreachable!()
+                };
+                match opened {
+                    Ok(Some((scan, entry))) => {
+                        self.current = Some(scan);
+                        Ok(entry)
                     }
-                    Poll::Ready(Err(err)) => Poll::Ready(Some(Err(err))),
-                    Poll::Pending => Poll::Pending,
-                },
+                    Ok(None) => continue,
+                    Err(err) => return Poll::Ready(Some(Err(err))),
+                }
             };
+            if let Some(limit) = &mut self.limit {
+                *limit -= 1;
+            }
+            return Poll::Ready(Some(entry));
         }
     }
 }
@@ -373,4 +395,53 @@ mod tests {
             assert!(entry_5.get().unwrap().vbool.is_none());
         }
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_tables_at_once() {
+        let mut keys = Vec::new();
+        for scan_parallelism in [1, 3] {
+            let temp_dir = TempDir::new().unwrap();
+            let manager = StoreManager::new(FsOptions::Local, vec![]).unwrap();
+            let option = Arc::new(
+                DbOption::new(
+                    Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                    &TestSchema {},
+                )
+                .scan_parallelism(scan_parallelism),
+            );
+            manager
+                .base_fs()
+                .create_dir_all(&option.version_log_dir_path())
+                .await
+                .unwrap();
+            manager
+                .base_fs()
+                .create_dir_all(&option.wal_dir_path())
+                .await
+                .unwrap();
+            let (_, version) = build_version(&option, &manager, &Arc::new(TestSchema)).await;
+
+            let mut level_stream = LevelStream::new(
+                &version,
+                1,
+                0,
+                version.level_slice[1].len() - 1,
+                (Bound::Unbounded, Bound::Unbounded),
+                1_u32.into(),
+                None,
+                ProjectionMask::all(),
+                manager.base_fs().clone(),
+                Arc::new(NoCache::default()),
+            )
+            .unwrap();
+            let mut level_keys = Vec::new();
+            while let Some(entry) = level_stream.next().await {
+                level_keys.push(entry.unwrap().get().unwrap().vstring.to_string());
+            }
+            keys.push(level_keys);
+        }
+        // the tables opened ahead are read in the order of the level
+        assert!(!keys[0].is_empty());
+        assert_eq!(keys[0], keys[1]);
+    }
 }
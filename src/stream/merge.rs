@@ -1,18 +1,21 @@
 use std::{
     cmp::Ordering,
-    collections::BinaryHeap,
+    collections::{BinaryHeap, VecDeque},
+    mem,
     pin::Pin,
     task::{Context, Poll},
 };
 
 use futures_core::{ready, Stream};
 use futures_util::stream::StreamExt;
+use parquet::errors::ParquetError;
 use pin_project_lite::pin_project;
 
-use super::{Entry, ScanStream};
-use crate::{record::Record, timestamp::Timestamp};
+use super::{delta::DeltaMerger, Entry, ScanStream};
+use crate::{record::Record, timestamp::Timestamp, ttl};
 
 pin_project! {
+    #[project = MergeStreamProj]
     pub struct MergeStream<'merge, R>
     where
         R: Record,
@@ -21,7 +24,16 @@ pin_project! {
         peeked: BinaryHeap<CmpEntry<'merge, R>>,
         buf: Option<Entry<'merge, R>>,
         ts: Timestamp,
+        watermark: Timestamp,
         limit: Option<usize>,
+        merger: Option<DeltaMerger<R>>,
+        // older versions of the key in `buf` that are folded into it
+        deltas: Vec<Entry<'merge, R>>,
+        tombstone: Option<Entry<'merge, R>>,
+        is_folded: bool,
+        ready: VecDeque<Entry<'merge, R>>,
+        // records that expired at this time are skipped
+        now: Option<u64>,
     }
 }
 
@@ -46,7 +58,14 @@ where
             peeked,
             buf: None,
             ts,
+            watermark: u32::MAX.into(),
             limit: None,
+            merger: None,
+            deltas: Vec::new(),
+            tombstone: None,
+            is_folded: false,
+            ready: VecDeque::new(),
+            now: None,
         };
         merge_stream.next().await;
 
@@ -60,6 +79,29 @@ where
             ..self
         }
     }
+
+    /// keep every version of a key newer than `watermark` and the newest one at or below it,
+    /// instead of only the newest version
+    pub(crate) fn retain_versions(self, watermark: Timestamp) -> Self {
+        Self { watermark, ..self }
+    }
+
+    /// fold the older versions of a key into the newest one with `merger` instead of skipping
+    /// them, until a tombstone is met
+    pub(crate) fn merge_on_read(self, merger: DeltaMerger<R>) -> Self {
+        Self {
+            merger: Some(merger),
+            ..self
+        }
+    }
+
+    /// skip the records that expired at `now`, their older versions are hidden as by a tombstone
+    pub(crate) fn expire(self, now: u64) -> Self {
+        Self {
+            now: Some(now),
+            ..self
+        }
+    }
 }
 
 impl<'merge, R> Stream for MergeStream<'merge, R>
@@ -69,8 +111,11 @@ where
     type Item = Result<Entry<'merge, R>, parquet::errors::ParquetError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let this = self.project();
-        let ts = this.ts;
+        let mut this = self.project();
+        let ts = *this.ts;
+        if let Some(entry) = this.ready.pop_front() {
+            return Poll::Ready(Some(Ok(entry)));
+        }
         if let Some(limit) = this.limit.as_ref() {
             if *limit == 0 {
                 return Poll::Ready(None);
@@ -85,21 +130,78 @@ where
             if let Some(next) = next {
                 this.peeked.push(CmpEntry::new(offset, next));
             }
-            if peeked.entry.key().ts > *ts {
+            if peeked.entry.key().ts > ts {
                 continue;
             }
             if let Some(buf) = this.buf {
-                if buf.key().value == peeked.entry.key().value {
+                // versions of a key are ordered from the newest
+                if buf.key().value == peeked.entry.key().value && buf.key().ts <= *this.watermark {
+                    if let (Some(merger), false) = (this.merger.as_ref(), *this.is_folded) {
+                        if buf.value().is_none() {
+                            *this.is_folded = true;
+                        } else if peeked.entry.value().is_none() {
+                            *this.is_folded = true;
+                            if merger.keeps_tombstones() {
+                                *this.tombstone = Some(peeked.entry);
+                            }
+                        } else {
+                            this.deltas.push(peeked.entry);
+                        }
+                    }
                     continue;
                 }
             }
+            let buf = this.buf.replace(peeked.entry);
+            let Some(entry) = Self::fold(&mut this, buf)? else {
+                return Poll::Ready(None);
+            };
+            if Self::is_expired(&this, &entry) {
+                continue;
+            }
             if let Some(limit) = this.limit.as_ref() {
                 this.limit.replace(*limit - 1);
             }
+            return Poll::Ready(Some(Ok(entry)));
+        }
+        let buf = this.buf.take();
+        Poll::Ready(
+            Self::fold(&mut this, buf)
+                .map(|entry| entry.filter(|entry| !Self::is_expired(&this, entry)))
+                .transpose(),
+        )
+    }
+}
 
-            return Poll::Ready(this.buf.replace(peeked.entry).map(Ok));
+impl<'merge, R> MergeStream<'merge, R>
+where
+    R: Record,
+{
+    /// merge the pending versions into `buf`, the tombstone ending them is returned on the next
+    /// poll
+    fn fold(
+        this: &mut MergeStreamProj<'_, 'merge, R>,
+        buf: Option<Entry<'merge, R>>,
+    ) -> Result<Option<Entry<'merge, R>>, ParquetError> {
+        *this.is_folded = false;
+        let Some(buf) = buf else {
+            return Ok(None);
+        };
+        if let Some(tombstone) = this.tombstone.take() {
+            this.ready.push_back(tombstone);
+        }
+        if this.deltas.is_empty() {
+            return Ok(Some(buf));
         }
-        Poll::Ready(this.buf.take().map(Ok))
+        let mut versions = mem::take(&mut *this.deltas);
+        versions.insert(0, buf);
+
+        // SAFETY: deltas are only collected with a merger
+        Ok(Some(this.merger.as_ref().unwrap().merge(&versions)?))
+    }
+
+    fn is_expired(this: &MergeStreamProj<'_, 'merge, R>, entry: &Entry<'merge, R>) -> bool {
+        this.now
+            .is_some_and(|now| ttl::is_expired::<R>(entry.value(), now))
     }
 }
 
@@ -365,6 +467,58 @@ mod tests {
         };
     }
 
+    #[tokio::test]
+    async fn merge_retain_versions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fs = Arc::new(TokioFs) as Arc<dyn DynFs>;
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &StringSchema,
+        );
+
+        fs.create_dir_all(&option.wal_dir_path()).await.unwrap();
+
+        let trigger = TriggerFactory::create(option.trigger_type);
+
+        let m1 =
+            MutableMemTable::<String>::new(&option, trigger, fs.clone(), Arc::new(StringSchema))
+                .await
+                .unwrap();
+        for ts in 1..4_u32 {
+            m1.insert(LogType::Full, "1".into(), ts.into())
+                .await
+                .unwrap();
+        }
+        m1.insert(LogType::Full, "2".into(), 1_u32.into())
+            .await
+            .unwrap();
+
+        let lower = "1".to_string();
+        let upper = "2".to_string();
+        let bound = (Bound::Included(&lower), Bound::Included(&upper));
+        let merge =
+            MergeStream::<String>::from_vec(vec![m1.scan(bound, 3.into()).into()], 3.into())
+                .await
+                .unwrap()
+                .retain_versions(2.into());
+
+        let keys = merge
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (entry.key().value.to_string(), u32::from(entry.key().ts))
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            keys,
+            vec![
+                ("1".to_string(), 3),
+                ("1".to_string(), 2),
+                ("2".to_string(), 1)
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn merge_mutable_limit() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -1,8 +1,17 @@
+pub(crate) mod batch;
+pub(crate) mod delta;
+pub(crate) mod expr;
 pub(crate) mod level;
 pub(crate) mod mem_projection;
 pub(crate) mod merge;
 pub(crate) mod package;
 pub(crate) mod record_batch;
+#[cfg(any(
+    all(feature = "ffi", not(target_arch = "wasm32")),
+    feature = "flight",
+    feature = "server"
+))]
+pub(crate) mod user;
 
 use std::{
     fmt::{self, Debug, Formatter},
@@ -12,6 +21,7 @@ use std::{
     task::{Context, Poll},
 };
 
+pub use expr::ScanExpr;
 use futures_core::Stream;
 use futures_util::{ready, stream};
 use parquet::arrow::ProjectionMask;
@@ -19,7 +29,7 @@ use pin_project_lite::pin_project;
 use record_batch::RecordBatchEntry;
 
 use crate::{
-    inmem::{immutable::ImmutableScan, mutable::MutableScan},
+    inmem::{immutable::ImmutableScan, map::MapEntry, mutable::MutableScan},
     ondisk::scan::SsTableScan,
     record::{Key, Record, RecordRef, Schema},
     stream::{level::LevelStream, mem_projection::MemProjectionStream},
@@ -37,7 +47,7 @@ where
             &'entry Option<R>,
         ),
     ),
-    Mutable(crossbeam_skiplist::map::Entry<'entry, Ts<<R::Schema as Schema>::Key>, Option<R>>),
+    Mutable(MapEntry<'entry, <R::Schema as Schema>::Key, Option<R>>),
     Projection((Box<Entry<'entry, R>>, Arc<ProjectionMask>)),
     RecordBatch(RecordBatchEntry<R>),
 }
@@ -79,6 +89,22 @@ where
     }
 }
 
+impl<R> Clone for Entry<'_, R>
+where
+    R: Record,
+{
+    fn clone(&self) -> Self {
+        match self {
+            Entry::Transaction((key, value)) => Entry::Transaction((key.clone(), *value)),
+            Entry::Mutable(entry) => Entry::Mutable(entry.clone()),
+            Entry::Projection((entry, projection_mask)) => {
+                Entry::Projection((entry.clone(), projection_mask.clone()))
+            }
+            Entry::RecordBatch(entry) => Entry::RecordBatch(entry.clone()),
+        }
+    }
+}
+
 impl<R> fmt::Debug for Entry<'_, R>
 where
     R: Record + Debug,
@@ -17,7 +17,8 @@ pub struct RecordBatchEntry<R>
 where
     R: Record,
 {
-    _record_batch: RecordBatch,
+    record_batch: RecordBatch,
+    offset: usize,
     record_ref: OptionRecordRef<'static, R::Ref<'static>>,
 }
 
@@ -26,15 +27,27 @@ where
     R: Record,
 {
     pub(crate) fn new(
-        _record_batch: RecordBatch,
+        record_batch: RecordBatch,
+        offset: usize,
         record_ref: OptionRecordRef<'static, R::Ref<'static>>,
     ) -> Self {
         Self {
-            _record_batch,
+            record_batch,
+            offset,
             record_ref,
         }
     }
 
+    /// the [`RecordBatch`] this entry is read from
+    pub(crate) fn record_batch(&self) -> &RecordBatch {
+        &self.record_batch
+    }
+
+    /// row offset of this entry in [`RecordBatchEntry::record_batch`]
+    pub(crate) fn offset(&self) -> usize {
+        self.offset
+    }
+
     pub(crate) fn internal_key(&self) -> Ts<<<R::Schema as RecordSchema>::Key as Key>::Ref<'_>> {
         self.record_ref.key()
     }
@@ -49,6 +62,19 @@ where
     }
 }
 
+impl<R> Clone for RecordBatchEntry<R>
+where
+    R: Record,
+{
+    fn clone(&self) -> Self {
+        Self {
+            record_batch: self.record_batch.clone(),
+            offset: self.offset,
+            record_ref: self.record_ref.clone(),
+        }
+    }
+}
+
 impl<R> Debug for RecordBatchEntry<R>
 where
     R: Record + Debug,
@@ -64,6 +90,7 @@ pub struct RecordBatchIterator<R> {
     offset: usize,
     projection_mask: ProjectionMask,
     full_schema: Arc<Schema>,
+    borrow_values: bool,
     _marker: PhantomData<R>,
 }
 
@@ -81,9 +108,18 @@ where
             offset: 0,
             projection_mask,
             full_schema,
+            borrow_values: false,
             _marker: PhantomData,
         }
     }
+
+    /// read the records with [`RecordRef::from_record_batch_borrowed`]
+    pub(crate) fn borrow_values(self, borrow_values: bool) -> Self {
+        Self {
+            borrow_values,
+            ..self
+        }
+    }
 }
 
 impl<R> Iterator for RecordBatchIterator<R>
@@ -98,13 +134,18 @@ where
         }
 
         let record_batch = self.record_batch.clone();
-        let record = R::Ref::from_record_batch(
+        let from_record_batch = if self.borrow_values {
+            R::Ref::from_record_batch_borrowed
+        } else {
+            R::Ref::from_record_batch
+        };
+        let record = from_record_batch(
             &self.record_batch,
             self.offset,
             &self.projection_mask,
             &self.full_schema,
         );
-        let entry = RecordBatchEntry::new(record_batch, unsafe {
+        let entry = RecordBatchEntry::new(record_batch, self.offset, unsafe {
             // Safety: self-referring lifetime is safe
             transmute::<OptionRecordRef<'_, R::Ref<'_>>, OptionRecordRef<'static, R::Ref<'static>>>(
                 record,
@@ -0,0 +1,168 @@
+//! Scans of the user columns, without `_null` and `_ts`, owning the [`DB`] they read so that
+//! they outlive the caller, for the consumers out of the crate.
+
+use std::{ops::Bound, sync::Arc};
+
+use arrow::{
+    array::RecordBatch,
+    datatypes::{Schema as ArrowSchema, SchemaRef},
+    error::ArrowError,
+};
+use futures_core::Stream;
+use futures_util::StreamExt;
+use parquet::errors::ParquetError;
+
+use crate::{
+    executor::Executor,
+    magic::USER_COLUMN_OFFSET,
+    record::{Record, Schema},
+    DB,
+};
+#[cfg(any(feature = "flight", feature = "server"))]
+use crate::{
+    interchange::json_value,
+    record::{Value, ValueDesc},
+};
+
+/// What a scan of the user columns reads.
+pub(crate) struct UserScan<K> {
+    pub(crate) lower: Bound<K>,
+    pub(crate) upper: Bound<K>,
+    /// the columns read along with the primary key, all of them if `None`
+    pub(crate) projection: Option<Vec<String>>,
+    pub(crate) limit: Option<usize>,
+    pub(crate) batch_size: usize,
+}
+
+#[cfg(any(feature = "flight", feature = "server"))]
+impl UserScan<Value> {
+    /// the scan of the JSON object `query`, every field optional: `{"lower": {"included": 1},
+    /// "upper": {"excluded": 10}, "projection": ["name"], "limit": 100, "batch_size": 1024}`.
+    /// The keys of the bounds are values of `key_desc`.
+    pub(crate) fn from_json(
+        query: &serde_json::Value,
+        key_desc: &ValueDesc,
+        batch_size: usize,
+    ) -> Result<Self, String> {
+        let bound = |name: &str| match query.get(name) {
+            None | Some(serde_json::Value::Null) => Ok(Bound::Unbounded),
+            Some(bound) => match (bound.get("included"), bound.get("excluded")) {
+                (Some(key), None) => json_value(key_desc, key).map(Bound::Included),
+                (None, Some(key)) => json_value(key_desc, key).map(Bound::Excluded),
+                _ => Err(format!("{name} is neither included nor excluded")),
+            },
+        };
+        let projection = match query.get("projection") {
+            None | Some(serde_json::Value::Null) => None,
+            Some(serde_json::Value::Array(names)) => Some(
+                names
+                    .iter()
+                    .map(|name| name.as_str().map(str::to_string))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| "projection is not a list of names".to_string())?,
+            ),
+            Some(_) => return Err("projection is not a list of names".to_string()),
+        };
+        let number = |name: &str| match query.get(name) {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(number) => number
+                .as_u64()
+                .map(|number| Some(number as usize))
+                .ok_or_else(|| format!("{name} is not a number")),
+        };
+
+        Ok(Self {
+            lower: bound("lower")?,
+            upper: bound("upper")?,
+            projection,
+            limit: number("limit")?,
+            batch_size: number("batch_size")?.unwrap_or(batch_size),
+        })
+    }
+}
+
+/// the schema of the batches of a scan of `projection`, with the user columns in the order of
+/// `full_schema` and the primary key always. Errors on a column `full_schema` lacks.
+pub(crate) fn user_schema(
+    full_schema: &ArrowSchema,
+    primary_key_index: usize,
+    projection: Option<&[String]>,
+) -> Result<SchemaRef, String> {
+    let mut indices = match projection {
+        Some(projection) => projection
+            .iter()
+            .map(|name| {
+                full_schema
+                    .index_of(name)
+                    .ok()
+                    .filter(|&index| index >= USER_COLUMN_OFFSET)
+                    .ok_or_else(|| format!("unknown column {name}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => (USER_COLUMN_OFFSET..full_schema.fields().len()).collect(),
+    };
+    indices.push(primary_key_index);
+    indices.sort_unstable();
+    indices.dedup();
+
+    full_schema
+        .project(&indices)
+        .map(Arc::new)
+        .map_err(|err| err.to_string())
+}
+
+/// the columns of `schema` out of a batch of [`user_scan`]
+pub(crate) fn user_batch(
+    schema: &SchemaRef,
+    batch: &RecordBatch,
+) -> Result<RecordBatch, ArrowError> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            batch.column_by_name(field.name()).cloned().ok_or_else(|| {
+                ArrowError::SchemaError(format!("column {} not found in batch", field.name()))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    RecordBatch::try_new(schema.clone(), columns)
+}
+
+/// scan the latest snapshot of `db` as the stream is polled, the batches still hold `_null` and
+/// `_ts`, see [`user_batch`]. The snapshot holds back the freezes of the mutable memtable until
+/// the stream is dropped.
+pub(crate) fn user_scan<R, E>(
+    db: Arc<DB<R, E>>,
+    scan: UserScan<<R::Schema as Schema>::Key>,
+) -> impl Stream<Item = Result<RecordBatch, ParquetError>> + Send + 'static
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    let UserScan {
+        lower,
+        upper,
+        projection,
+        limit,
+        batch_size,
+    } = scan;
+    async_stream::try_stream! {
+        let snapshot = db.snapshot().await;
+        let mut scan = snapshot.scan((lower.as_ref(), upper.as_ref()));
+        if let Some(projection) = &projection {
+            scan = scan.projection(&projection.iter().map(String::as_str).collect::<Vec<_>>());
+        }
+        if let Some(limit) = limit {
+            scan = scan.limit(limit);
+        }
+        let mut batches = scan
+            .scan_batches(batch_size)
+            .await
+            .map_err(|err| ParquetError::General(err.to_string()))?;
+
+        while let Some(batch) = batches.next().await {
+            yield batch?;
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! A blocking API over [`DB`], for the applications and tools which are not async.
+//!
+//! [`Db`] runs the [`DB`] and its background tasks on a tokio runtime of its own, every call
+//! blocks the calling thread until it is done. The calls must not be made from an async context.
+//!
+//! ```no_run
+//! use std::ops::Bound;
+//!
+//! use fusio::path::Path;
+//! use tonbo::{sync::Db, DbOption, Record};
+//!
+//! #[derive(Record, Debug)]
+//! pub struct User {
+//!     #[record(primary_key)]
+//!     name: String,
+//!     age: u8,
+//! }
+//!
+//! let option = DbOption::new(
+//!     Path::from_filesystem_path("./db_path").unwrap(),
+//!     &UserSchema,
+//! );
+//! let db = Db::<User>::open(option, UserSchema).unwrap();
+//!
+//! let mut txn = db.transaction();
+//! txn.insert(User {
+//!     name: "Alice".into(),
+//!     age: 22,
+//! });
+//! txn.commit().unwrap();
+//!
+//! for age in db.scan((Bound::Unbounded, Bound::Unbounded), |entry| {
+//!     entry.get().age
+//! }) {
+//!     println!("{:?}", age.unwrap());
+//! }
+//! ```
+
+use std::{ops::Bound, pin::Pin};
+
+use futures_core::Stream;
+use futures_util::StreamExt;
+use parquet::errors::ParquetError;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{
+    executor::tokio::TokioExecutor,
+    record::{Record, Schema},
+    stream::Entry,
+    transaction::{CommitError, Transaction, TransactionEntry},
+    DbError, DbOption, Projection, DB,
+};
+
+/// A [`DB`] called from blocking code, see the [module](self).
+pub struct Db<R>
+where
+    R: Record,
+{
+    // dropped before the runtime its tasks run on
+    db: DB<R, TokioExecutor>,
+    runtime: Runtime,
+}
+
+impl<R> Db<R>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+{
+    /// open the [`DB`] of `option` on a runtime of its own, see [`DB::new`]
+    pub fn open(option: DbOption, schema: R::Schema) -> Result<Self, DbError<R>> {
+        let runtime = Builder::new_multi_thread().enable_all().build()?;
+        let db = runtime.block_on(DB::new(option, TokioExecutor::current(), schema))?;
+        Ok(Self { db, runtime })
+    }
+
+    /// the [`DB`], to be called from [`Db::runtime`]
+    pub fn as_async(&self) -> &DB<R, TokioExecutor> {
+        &self.db
+    }
+
+    /// the runtime the [`DB`] runs on
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+
+    /// see [`DB::insert`]
+    pub fn insert(&self, record: R) -> Result<(), CommitError<R>> {
+        self.runtime.block_on(self.db.insert(record))
+    }
+
+    /// see [`DB::insert_batch`]
+    pub fn insert_batch(
+        &self,
+        records: impl ExactSizeIterator<Item = R>,
+    ) -> Result<(), CommitError<R>> {
+        self.runtime.block_on(self.db.insert_batch(records))
+    }
+
+    /// see [`DB::remove`]
+    pub fn remove(&self, key: <R::Schema as Schema>::Key) -> Result<bool, CommitError<R>> {
+        self.runtime.block_on(self.db.remove(key))
+    }
+
+    /// see [`DB::get`]
+    pub fn get<T>(
+        &self,
+        key: &<R::Schema as Schema>::Key,
+        f: impl FnMut(TransactionEntry<'_, R>) -> Option<T>,
+    ) -> Result<Option<T>, CommitError<R>> {
+        self.runtime.block_on(self.db.get(key, f))
+    }
+
+    /// see [`DB::scan`], the records are read as the iterator is advanced
+    pub fn scan<'scan, T: 'scan>(
+        &'scan self,
+        range: (
+            Bound<&'scan <R::Schema as Schema>::Key>,
+            Bound<&'scan <R::Schema as Schema>::Key>,
+        ),
+        f: impl FnMut(TransactionEntry<'_, R>) -> T + 'scan,
+    ) -> Iter<'scan, Result<T, CommitError<R>>> {
+        let stream = self.runtime.block_on(self.db.scan(range, f));
+        Iter {
+            runtime: &self.runtime,
+            stream: Box::pin(stream),
+        }
+    }
+
+    /// see [`DB::transaction`]
+    pub fn transaction(&self) -> Txn<'_, R> {
+        Txn {
+            txn: self.runtime.block_on(self.db.transaction()),
+            runtime: &self.runtime,
+        }
+    }
+
+    /// see [`DB::flush`]
+    pub fn flush(&self) -> Result<(), CommitError<R>> {
+        self.runtime.block_on(self.db.flush())
+    }
+
+    /// see [`DB::flush_wal`]
+    pub fn flush_wal(&self) -> Result<(), DbError<R>> {
+        self.runtime.block_on(self.db.flush_wal())
+    }
+}
+
+/// A [`Transaction`] of a [`Db`], see [`Db::transaction`].
+pub struct Txn<'txn, R>
+where
+    R: Record,
+{
+    txn: Transaction<'txn, R>,
+    runtime: &'txn Runtime,
+}
+
+impl<'txn, R> Txn<'txn, R>
+where
+    R: Record + Send,
+{
+    /// see [`Transaction::get`]
+    pub fn get<'get>(
+        &'get self,
+        key: &'get <R::Schema as Schema>::Key,
+        projection: Projection<'get>,
+    ) -> Result<Option<TransactionEntry<'get, R>>, DbError<R>> {
+        self.runtime.block_on(self.txn.get(key, projection))
+    }
+
+    /// see [`Transaction::scan`], the records are read as the iterator is advanced
+    pub fn scan<'scan, 'range>(
+        &'scan self,
+        range: (
+            Bound<&'range <R::Schema as Schema>::Key>,
+            Bound<&'range <R::Schema as Schema>::Key>,
+        ),
+    ) -> Result<Iter<'scan, Result<Entry<'scan, R>, ParquetError>>, DbError<R>>
+    where
+        'range: 'scan,
+    {
+        let stream = self.runtime.block_on(self.txn.scan(range).take())?;
+        Ok(Iter {
+            runtime: self.runtime,
+            stream: Box::pin(stream),
+        })
+    }
+
+    /// see [`Transaction::insert`]
+    pub fn insert(&mut self, value: R) {
+        self.txn.insert(value)
+    }
+
+    /// see [`Transaction::remove`]
+    pub fn remove(&mut self, key: <R::Schema as Schema>::Key) {
+        self.txn.remove(key)
+    }
+
+    /// see [`Transaction::commit`]
+    pub fn commit(self) -> Result<(), CommitError<R>> {
+        self.runtime.block_on(self.txn.commit())
+    }
+
+    /// the [`Transaction`], to be driven on [`Db::runtime`]
+    pub fn into_async(self) -> Transaction<'txn, R> {
+        self.txn
+    }
+}
+
+/// The items of a scan of a [`Db`] or a [`Txn`], read from the runtime of the [`Db`] one at a
+/// time.
+pub struct Iter<'scan, T> {
+    runtime: &'scan Runtime,
+    stream: Pin<Box<dyn Stream<Item = T> + 'scan>>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime.block_on(self.stream.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::Db;
+    use crate::{inmem::immutable::tests::TestSchema, tests::Test, DbOption, Projection};
+
+    #[test]
+    fn blocking_db() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db = Db::<Test>::open(option, TestSchema).unwrap();
+        for (key, vu32) in [("a", 1), ("b", 2), ("e", 5)] {
+            db.insert(Test {
+                vstring: key.to_string(),
+                vu32,
+                vbool: None,
+            })
+            .unwrap();
+        }
+        db.remove("e".to_string()).unwrap();
+        db.flush().unwrap();
+
+        let vu32 = db.get(&"a".to_string(), |entry| entry.get().vu32).unwrap();
+        assert_eq!(vu32, Some(1));
+        assert_eq!(
+            db.get(&"e".to_string(), |entry| entry.get().vu32).unwrap(),
+            None
+        );
+        let upper = "e".to_string();
+        let keys = db
+            .scan((Bound::Unbounded, Bound::Excluded(&upper)), |entry| {
+                entry.get().vstring.to_string()
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(keys, vec!["a", "b"]);
+
+        let mut txn = db.transaction();
+        txn.insert(Test {
+            vstring: "d".to_string(),
+            vu32: 4,
+            vbool: None,
+        });
+        assert!(txn
+            .get(&"d".to_string(), Projection::All)
+            .unwrap()
+            .is_some());
+        let keys = txn
+            .scan((Bound::Unbounded, Bound::Excluded(&upper)))
+            .unwrap()
+            .map(|entry| entry.unwrap().key().value.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["a", "b", "d"]);
+        txn.commit().unwrap();
+
+        let vu32 = db.get(&"d".to_string(), |entry| entry.get().vu32).unwrap();
+        assert_eq!(vu32, Some(4));
+    }
+}
@@ -0,0 +1,617 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    io::ErrorKind,
+    sync::{Arc, RwLock as StdRwLock},
+};
+
+use async_lock::Mutex;
+use fusio::{path::Path, DynFs, Read, Write};
+use fusio_dispatch::FsOptions;
+use futures_util::StreamExt;
+
+use crate::{
+    executor::Executor,
+    fs::{generate_file_id, manager::StoreManager, FileId, FileType},
+    record::{DataType, DynRecord, DynSchema, ValueDesc},
+    DbError, DbOption, DB,
+};
+
+const MANIFEST_HEADER: &str = "tonbo-tables v1";
+
+/// suffix of the manifests of the tables, next to their directories
+const MANIFEST_SUFFIX: &str = ".tables";
+
+/// configure the [`DbOption`] of a table of [`Tables`]
+type Configure = Box<dyn FnOnce(DbOption) -> DbOption + Send>;
+
+/// Named tables of [`DynRecord`]s with their own schemas and options, opened together under one
+/// base path and run on one executor. Every table is a [`DB`] in the directory of its name,
+/// with its own [`DbOption`] on the base fs of the tables. Tables are created and dropped while
+/// the others are online, a manifest under the base path on the base fs keeps their names and
+/// schemas.
+///
+/// A table runs its own WAL and compaction task, the tables are not written atomically together
+/// and one table compacting does not hold back the others.
+///
+/// ```no_run
+/// use fusio::path::Path;
+/// use tonbo::{dyn_schema, executor::tokio::TokioExecutor, tables::Tables};
+///
+/// # async fn open() {
+/// let base_path = Path::from_filesystem_path("./db_path").unwrap();
+/// let tables = Tables::builder(base_path, TokioExecutor::current())
+///     .table(
+///         "users",
+///         dyn_schema!(("id", Int64, false), ("name", String, true), 0),
+///         |option| option,
+///     )
+///     .table("events", dyn_schema!(("id", UInt64, false), 0), |option| {
+///         option.max_sst_file_size(64 * 1024 * 1024)
+///     })
+///     .build()
+///     .await
+///     .unwrap();
+/// let users = tables.table("users").unwrap();
+/// # }
+/// ```
+pub struct Tables<E>
+where
+    E: Executor,
+{
+    base_path: Path,
+    base_fs: FsOptions,
+    manager: StoreManager,
+    executor: E,
+    tables: StdRwLock<HashMap<String, Arc<DB<DynRecord, E>>>>,
+    /// the schemas of the tables, written to the manifest. Its lock orders the creations and
+    /// drops of tables.
+    schemas: Mutex<BTreeMap<String, (Vec<ValueDesc>, usize)>>,
+}
+
+impl<E> Tables<E>
+where
+    E: Executor + Clone + Send + Sync + 'static,
+{
+    /// declare the tables under `base_path`, run on `executor`
+    pub fn builder(base_path: Path, executor: E) -> TablesBuilder<E> {
+        TablesBuilder {
+            base_path,
+            base_fs: FsOptions::Local,
+            executor,
+            tables: Vec::new(),
+        }
+    }
+
+    /// the table `name`, `None` if there is none
+    pub fn table(&self, name: &str) -> Option<Arc<DB<DynRecord, E>>> {
+        self.tables.read().unwrap().get(name).cloned()
+    }
+
+    /// the names of the tables, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self
+            .tables
+            .read()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    pub fn base_path(&self) -> &Path {
+        &self.base_path
+    }
+
+    /// Create the table `name` of records of `schema` while the tables are online, opened with
+    /// the [`DbOption`] on its directory that `configure` returns. The table is written to the
+    /// manifest, so it is opened again with the other tables, with the default [`DbOption`]
+    /// unless [`TablesBuilder::table`] declares it.
+    ///
+    /// # Error
+    /// Returns [`DbError::InvalidTable`] if the table exists or `name` is not a valid directory
+    /// name.
+    pub async fn create_table(
+        &self,
+        name: impl Into<String>,
+        schema: DynSchema,
+        configure: impl FnOnce(DbOption) -> DbOption,
+    ) -> Result<Arc<DB<DynRecord, E>>, DbError<DynRecord>> {
+        let name = name.into();
+        let path = table_path(&self.base_path, &name)?;
+        let mut schemas = self.schemas.lock().await;
+        if schemas.contains_key(&name) {
+            return Err(DbError::InvalidTable(format!("table {name} exists")));
+        }
+        let descs = (schema.value_descs().to_vec(), schema.primary_index());
+        let option = configure(DbOption::new(path, &schema).base_fs(self.base_fs.clone()));
+        let db = Arc::new(DB::new(option, self.executor.clone(), schema).await?);
+
+        schemas.insert(name.clone(), descs);
+        if let Err(err) = write_manifest(&**self.manager.base_fs(), &self.base_path, &schemas).await
+        {
+            schemas.remove(&name);
+            return Err(err.into());
+        }
+        self.tables.write().unwrap().insert(name, db.clone());
+        Ok(db)
+    }
+
+    /// Drop the table `name` while the tables are online: the table is removed from the
+    /// manifest, then its files are removed.
+    ///
+    /// # Error
+    /// Returns [`DbError::InvalidTable`] if there is no table `name`, or if a handle of the table
+    /// from [`Tables::table`] is alive, in which case the table is kept.
+    pub async fn drop_table(&self, name: &str) -> Result<(), DbError<DynRecord>> {
+        let mut schemas = self.schemas.lock().await;
+        let db = {
+            let mut tables = self.tables.write().unwrap();
+            let Some(db) = tables.remove(name) else {
+                return Err(DbError::InvalidTable(format!("table {name} not found")));
+            };
+            match Arc::try_unwrap(db) {
+                Ok(db) => db,
+                Err(db) => {
+                    tables.insert(name.to_string(), db);
+                    return Err(DbError::InvalidTable(format!("table {name} is in use")));
+                }
+            }
+        };
+        let descs = schemas.remove(name);
+        if let Err(err) = write_manifest(&**self.manager.base_fs(), &self.base_path, &schemas).await
+        {
+            if let Some(descs) = descs {
+                schemas.insert(name.to_string(), descs);
+            }
+            self.tables
+                .write()
+                .unwrap()
+                .insert(name.to_string(), Arc::new(db));
+            return Err(err.into());
+        }
+        drop(schemas);
+
+        // the table is gone from the manifest, files left by a failure below are only garbage
+        let option = db.schema.read().await.option.clone();
+        db.destroy().await?;
+        let mut dirs = vec![
+            (option.wal_dir_path(), option.wal_fs().clone()),
+            (option.version_log_dir_path(), option.base_fs.clone()),
+            (option.base_path.clone(), option.base_fs.clone()),
+        ];
+        dirs.extend(option.level_paths.iter().flatten().cloned());
+        for (dir, fs_options) in dirs {
+            remove_files(&*fs_options.parse()?, &dir).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Declares the tables of [`Tables`], see [`Tables::builder`].
+pub struct TablesBuilder<E> {
+    base_path: Path,
+    base_fs: FsOptions,
+    executor: E,
+    tables: Vec<(String, DynSchema, Configure)>,
+}
+
+impl<E> TablesBuilder<E>
+where
+    E: Executor + Clone + Send + Sync + 'static,
+{
+    /// Declare the table `name` of records of `schema`, opened with the [`DbOption`] on its
+    /// directory that `configure` returns.
+    ///
+    /// A table is found by its name when the tables are opened again, so its schema must stay
+    /// the same.
+    pub fn table(
+        mut self,
+        name: impl Into<String>,
+        schema: DynSchema,
+        configure: impl FnOnce(DbOption) -> DbOption + Send + 'static,
+    ) -> Self {
+        self.tables.push((name.into(), schema, Box::new(configure)));
+        self
+    }
+
+    /// keep the tables and their manifest on the fs of `base_fs` instead of the local disk, the
+    /// [`DbOption`] of a table starts with it as its base fs
+    pub fn base_fs(self, base_fs: FsOptions) -> Self {
+        Self { base_fs, ..self }
+    }
+
+    /// Open the tables of the manifest under the base path, created by [`Tables::create_table`],
+    /// and every declared table.
+    ///
+    /// # Error
+    /// Returns [`DbError::InvalidTable`] if a name is declared twice or is not a valid directory
+    /// name, or if a declared table has another schema in the manifest.
+    pub async fn build(self) -> Result<Tables<E>, DbError<DynRecord>> {
+        let manager = StoreManager::new(self.base_fs.clone(), vec![])?;
+        let fs = manager.base_fs();
+        fs.create_dir_all(&self.base_path).await?;
+        let mut schemas = read_manifest(&**fs, &self.base_path).await?;
+        let mut declared = HashMap::with_capacity(self.tables.len());
+        for (name, schema, configure) in self.tables {
+            table_path(&self.base_path, &name)?;
+            if declared.contains_key(&name) {
+                return Err(DbError::InvalidTable(format!(
+                    "table {name} is declared twice"
+                )));
+            }
+            let descs = (schema.value_descs().to_vec(), schema.primary_index());
+            match schemas.get(&name) {
+                Some(manifest) if !is_same_schema(manifest, &descs) => {
+                    return Err(DbError::InvalidTable(format!(
+                        "table {name} has another schema in the manifest"
+                    )));
+                }
+                Some(_) => {}
+                None => {
+                    schemas.insert(name.clone(), descs);
+                }
+            }
+            declared.insert(name, (schema, configure));
+        }
+
+        let mut tables = HashMap::with_capacity(schemas.len());
+        for (name, (descs, primary_index)) in &schemas {
+            let path = table_path(&self.base_path, name)?;
+            let (schema, configure) = declared.remove(name).unwrap_or_else(|| {
+                (
+                    DynSchema::new(descs.clone(), *primary_index),
+                    Box::new(|option| option) as Configure,
+                )
+            });
+            let option = configure(DbOption::new(path, &schema).base_fs(self.base_fs.clone()));
+            let db = DB::new(option, self.executor.clone(), schema).await?;
+            tables.insert(name.clone(), Arc::new(db));
+        }
+        write_manifest(&**fs, &self.base_path, &schemas).await?;
+
+        Ok(Tables {
+            base_path: self.base_path,
+            base_fs: self.base_fs,
+            manager,
+            executor: self.executor,
+            tables: StdRwLock::new(tables),
+            schemas: Mutex::new(schemas),
+        })
+    }
+}
+
+fn is_same_schema(a: &(Vec<ValueDesc>, usize), b: &(Vec<ValueDesc>, usize)) -> bool {
+    a.1 == b.1
+        && a.0.len() == b.0.len()
+        && a.0.iter().zip(b.0.iter()).all(|(a, b)| {
+            a.name == b.name && a.datatype == b.datatype && a.is_nullable == b.is_nullable
+        })
+}
+
+/// the directory of the table `name`
+fn table_path(base_path: &Path, name: &str) -> Result<Path, DbError<DynRecord>> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || !name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || matches!(char, '_' | '-' | '.'))
+    {
+        return Err(DbError::InvalidTable(format!(
+            "invalid table name {name:?}"
+        )));
+    }
+    Ok(base_path.child(name))
+}
+
+/// Read the newest manifest under `base_path`. Manifests are written as new files named by
+/// their time ordered ids, so a manifest is never seen half written.
+async fn read_manifest(
+    fs: &dyn DynFs,
+    base_path: &Path,
+) -> Result<BTreeMap<String, (Vec<ValueDesc>, usize)>, DbError<DynRecord>> {
+    let mut manifests = manifest_ids(fs, base_path).await?;
+    manifests.sort();
+    let Some(id) = manifests.pop() else {
+        return Ok(BTreeMap::new());
+    };
+    let mut file = fs
+        .open_options(
+            &manifest_path(base_path, id),
+            FileType::Parquet.open_options(true),
+        )
+        .await?;
+    let (result, manifest) = file.read_to_end_at(Vec::new(), 0).await;
+    result?;
+    let manifest = String::from_utf8(manifest)
+        .map_err(|_| DbError::InvalidTable("manifest is not valid UTF-8".to_string()))?;
+    decode_manifest(&manifest)
+        .ok_or_else(|| DbError::InvalidTable("malformed manifest".to_string()))
+}
+
+/// write `schemas` to a new manifest under `base_path` and remove the older ones
+async fn write_manifest(
+    fs: &dyn DynFs,
+    base_path: &Path,
+    schemas: &BTreeMap<String, (Vec<ValueDesc>, usize)>,
+) -> Result<(), fusio::Error> {
+    let older = manifest_ids(fs, base_path).await?;
+    let mut file = fs
+        .open_options(
+            &manifest_path(base_path, generate_file_id()),
+            FileType::Parquet.open_options(false),
+        )
+        .await?;
+    let (result, _) = file.write_all(encode_manifest(schemas).into_bytes()).await;
+    result?;
+    file.close().await?;
+
+    for id in older {
+        fs.remove(&manifest_path(base_path, id)).await?;
+    }
+    Ok(())
+}
+
+async fn manifest_ids(fs: &dyn DynFs, base_path: &Path) -> Result<Vec<FileId>, fusio::Error> {
+    let mut ids = Vec::new();
+    let mut entries = fs.list(base_path).await?;
+    while let Some(entry) = entries.next().await {
+        if let Some(id) = entry?
+            .path
+            .filename()
+            .and_then(|name| name.strip_suffix(MANIFEST_SUFFIX))
+            .and_then(|id| id.parse::<FileId>().ok())
+        {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+fn manifest_path(base_path: &Path, id: FileId) -> Path {
+    base_path.child(format!("{id}{MANIFEST_SUFFIX}"))
+}
+
+fn encode_manifest(schemas: &BTreeMap<String, (Vec<ValueDesc>, usize)>) -> String {
+    let mut manifest = format!("{MANIFEST_HEADER}\n");
+    for (name, (descs, primary_index)) in schemas {
+        // SAFETY: writing to a String is infallible
+        writeln!(manifest, "table {name} {primary_index}").unwrap();
+        for desc in descs {
+            writeln!(
+                manifest,
+                "column {} {:?} {}",
+                desc.name, desc.datatype, desc.is_nullable
+            )
+            .unwrap();
+        }
+    }
+    manifest
+}
+
+fn decode_manifest(manifest: &str) -> Option<BTreeMap<String, (Vec<ValueDesc>, usize)>> {
+    let mut lines = manifest.lines();
+    if lines.next() != Some(MANIFEST_HEADER) {
+        return None;
+    }
+    let mut schemas = BTreeMap::new();
+    let mut table = None;
+    for line in lines {
+        match line.split(' ').collect::<Vec<_>>()[..] {
+            ["table", name, primary_index] => {
+                if let Some((name, schema)) = table.take() {
+                    schemas.insert(name, schema);
+                }
+                table = Some((name.to_string(), (Vec::new(), primary_index.parse().ok()?)));
+            }
+            ["column", name, datatype, is_nullable] => {
+                let (_, (descs, _)) = table.as_mut()?;
+                descs.push(ValueDesc::new(
+                    name.to_string(),
+                    parse_datatype(datatype)?,
+                    is_nullable.parse().ok()?,
+                ));
+            }
+            _ => return None,
+        }
+    }
+    if let Some((name, schema)) = table {
+        schemas.insert(name, schema);
+    }
+    Some(schemas)
+}
+
+fn parse_datatype(datatype: &str) -> Option<DataType> {
+    Some(match datatype {
+        "UInt8" => DataType::UInt8,
+        "UInt16" => DataType::UInt16,
+        "UInt32" => DataType::UInt32,
+        "UInt64" => DataType::UInt64,
+        "Int8" => DataType::Int8,
+        "Int16" => DataType::Int16,
+        "Int32" => DataType::Int32,
+        "Int64" => DataType::Int64,
+        "String" => DataType::String,
+        "Boolean" => DataType::Boolean,
+        "Bytes" => DataType::Bytes,
+        "Float32" => DataType::Float32,
+        "Float64" => DataType::Float64,
+        _ => return None,
+    })
+}
+
+/// remove the files in the directory `path`, leaving its subdirectories
+async fn remove_files(fs: &dyn DynFs, path: &Path) -> Result<(), fusio::Error> {
+    let mut files = Vec::new();
+    let mut entries = match fs.list(path).await {
+        Ok(entries) => entries,
+        Err(fusio::Error::Io(err)) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        if entry.path.filename().is_some_and(|name| name.contains('.')) {
+            files.push(entry.path);
+        }
+    }
+    drop(entries);
+    for file in files {
+        match fs.remove(&file).await {
+            Ok(()) => {}
+            Err(fusio::Error::Io(err)) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::Tables;
+    use crate::{
+        dyn_schema,
+        executor::tokio::TokioExecutor,
+        record::{DataType, DynRecord, Value},
+        DbError,
+    };
+
+    fn record(id: i64, name: &str) -> DynRecord {
+        DynRecord::new(
+            vec![
+                Value::new(DataType::Int64, "id".to_string(), Arc::new(id), false),
+                Value::new(
+                    DataType::String,
+                    "name".to_string(),
+                    Arc::new(Some(name.to_string())),
+                    true,
+                ),
+            ],
+            0,
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn open_tables() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = Path::from_filesystem_path(temp_dir.path()).unwrap();
+        let open = || {
+            Tables::builder(base_path.clone(), TokioExecutor::current())
+                .table(
+                    "users",
+                    dyn_schema!(("id", Int64, false), ("name", String, true), 0),
+                    |option| option,
+                )
+                .table(
+                    "teams",
+                    dyn_schema!(("id", Int64, false), ("name", String, true), 0),
+                    |option| option.max_sst_file_size(1024 * 1024),
+                )
+                .build()
+        };
+
+        let tables = open().await.unwrap();
+        assert_eq!(tables.names(), vec!["teams", "users"]);
+        assert!(tables.table("missing").is_none());
+        let key = Value::new(DataType::Int64, "id".to_string(), Arc::new(1_i64), false);
+        let users = tables.table("users").unwrap();
+        users.insert(record(1, "alice")).await.unwrap();
+        users.flush_wal().await.unwrap();
+        drop(users);
+        drop(tables);
+        assert!(temp_dir.path().join("users").join("wal").exists());
+
+        // the tables are opened again from their directories
+        let tables = open().await.unwrap();
+        assert!(tables
+            .table("users")
+            .unwrap()
+            .get(&key, |_| ())
+            .await
+            .unwrap()
+            .is_some());
+        assert!(tables
+            .table("teams")
+            .unwrap()
+            .get(&key, |_| ())
+            .await
+            .unwrap()
+            .is_none());
+
+        assert!(matches!(
+            Tables::builder(base_path.clone(), TokioExecutor::current())
+                .table("../users", dyn_schema!(("id", Int64, false), 0), |option| {
+                    option
+                })
+                .build()
+                .await,
+            Err(DbError::InvalidTable(_))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn create_and_drop_table() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = Path::from_filesystem_path(temp_dir.path()).unwrap();
+        let open = || Tables::builder(base_path.clone(), TokioExecutor::current()).build();
+        let schema = || dyn_schema!(("id", Int64, false), ("name", String, true), 0);
+        let key = Value::new(DataType::Int64, "id".to_string(), Arc::new(1_i64), false);
+
+        let tables = open().await.unwrap();
+        let users = tables
+            .create_table("users", schema(), |option| option)
+            .await
+            .unwrap();
+        assert!(matches!(
+            tables
+                .create_table("users", schema(), |option| option)
+                .await,
+            Err(DbError::InvalidTable(_))
+        ));
+        users.insert(record(1, "alice")).await.unwrap();
+        users.flush_wal().await.unwrap();
+        drop(users);
+        drop(tables);
+
+        // the created table is opened again from the manifest
+        let tables = open().await.unwrap();
+        assert_eq!(tables.names(), vec!["users"]);
+        let users = tables.table("users").unwrap();
+        assert!(users.get(&key, |_| ()).await.unwrap().is_some());
+        assert!(matches!(
+            Tables::builder(base_path.clone(), TokioExecutor::current())
+                .table("users", dyn_schema!(("id", Int64, false), 0), |option| {
+                    option
+                })
+                .build()
+                .await,
+            Err(DbError::InvalidTable(_))
+        ));
+
+        // a table is not dropped under a live handle
+        assert!(matches!(
+            tables.drop_table("users").await,
+            Err(DbError::InvalidTable(_))
+        ));
+        drop(users);
+        tables.drop_table("users").await.unwrap();
+        assert!(tables.table("users").is_none());
+        assert!(!temp_dir
+            .path()
+            .join("users")
+            .join("wal")
+            .read_dir()
+            .unwrap()
+            .any(|entry| entry.unwrap().path().extension().is_some()));
+        drop(tables);
+
+        let tables = open().await.unwrap();
+        assert!(tables.names().is_empty());
+    }
+}
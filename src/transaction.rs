@@ -1,13 +1,15 @@
 use std::{
-    collections::{
-        btree_map::{Entry, Range},
-        BTreeMap, Bound,
-    },
+    collections::{btree_map::Range, BTreeMap, Bound},
     io,
     mem::transmute,
+    pin::pin,
+    sync::{Arc, Mutex as StdMutex},
 };
 
+use async_lock::Mutex;
 use flume::SendError;
+use fusio_log::Encode;
+use futures_util::StreamExt;
 use lockable::AsyncLimit;
 use parquet::{
     arrow::{ArrowSchemaConverter, ProjectionMask},
@@ -21,10 +23,13 @@ use crate::{
     snapshot::Snapshot,
     stream::{self, mem_projection::MemProjectionStream},
     timestamp::{Timestamp, Ts},
+    ttl,
     wal::log::LogType,
-    DbError, DbStorage, LockMap, Projection, Record, Scan,
+    DbError, DbStorage, IsolationLevel, LockMap, Projection, Record, Scan,
 };
 
+type KeyRange<K> = (Bound<K>, Bound<K>);
+
 pub(crate) struct TransactionScan<'scan, R: Record> {
     inner: Range<'scan, <R::Schema as RecordSchema>::Key, Option<R>>,
     ts: Timestamp,
@@ -50,13 +55,21 @@ where
 ///
 /// Transaction will store all mutations in local [`BTreeMap`] and only write to memtable when
 /// committed successfully. Otherwise, all mutations will be rolled back.
+///
+/// With [`IsolationLevel::Serializable`] the keys and ranges read by the transaction are
+/// checked at commit too, see [`DbOption::isolation_level`](crate::DbOption::isolation_level).
 pub struct Transaction<'txn, R>
 where
     R: Record,
 {
     local: BTreeMap<<R::Schema as RecordSchema>::Key, Option<R>>,
+    local_size: usize,
+    exceeded: Option<TransactionLimit>,
     snapshot: Snapshot<'txn, R>,
     lock_map: LockMap<<R::Schema as RecordSchema>::Key>,
+    serializable_lock: Arc<Mutex<()>>,
+    /// the keys and ranges read, tracked with [`IsolationLevel::Serializable`]
+    reads: StdMutex<Vec<KeyRange<<R::Schema as RecordSchema>::Key>>>,
 }
 
 impl<'txn, R> Transaction<'txn, R>
@@ -66,11 +79,36 @@ where
     pub(crate) fn new(
         snapshot: Snapshot<'txn, R>,
         lock_map: LockMap<<R::Schema as RecordSchema>::Key>,
+        serializable_lock: Arc<Mutex<()>>,
     ) -> Self {
         Self {
             local: BTreeMap::new(),
+            local_size: 0,
+            exceeded: None,
             snapshot,
             lock_map,
+            serializable_lock,
+            reads: StdMutex::new(Vec::new()),
+        }
+    }
+
+    fn is_serializable(&self) -> bool {
+        self.snapshot.schema().option.isolation_level == IsolationLevel::Serializable
+    }
+
+    /// track a read of `range` with [`IsolationLevel::Serializable`]
+    fn track_read(
+        &self,
+        range: (
+            Bound<&<R::Schema as RecordSchema>::Key>,
+            Bound<&<R::Schema as RecordSchema>::Key>,
+        ),
+    ) {
+        if self.is_serializable() {
+            self.reads
+                .lock()
+                .unwrap()
+                .push((range.0.cloned(), range.1.cloned()));
         }
     }
 
@@ -81,31 +119,14 @@ where
         key: &'get <R::Schema as RecordSchema>::Key,
         projection: Projection<'get>,
     ) -> Result<Option<TransactionEntry<'get, R>>, DbError<R>> {
+        self.track_read((Bound::Included(key), Bound::Included(key)));
         Ok(match self.local.get(key).and_then(|v| v.as_ref()) {
+            Some(_) if self.is_merge_on_read() => self.merged_get(key, projection).await?,
+            Some(v) if ttl::is_expired::<R>(Some(v.as_record_ref()), ttl::now()) => None,
             Some(v) => {
                 let mut record_ref = v.as_record_ref();
-                if let Projection::Parts(projection) = projection {
-                    let primary_key_index =
-                        self.snapshot.schema().record_schema.primary_key_index();
-                    let schema = self.snapshot.schema().record_schema.arrow_schema();
-                    let mut projection = projection
-                        .iter()
-                        .map(|name| {
-                            schema
-                                .index_of(name)
-                                .unwrap_or_else(|_| panic!("unexpected field {}", name))
-                        })
-                        .collect::<Vec<usize>>();
-
-                    let mut fixed_projection = vec![0, 1, primary_key_index];
-                    fixed_projection.append(&mut projection);
-                    fixed_projection.dedup();
-
-                    let mask = ProjectionMask::roots(
-                        &ArrowSchemaConverter::new().convert(schema).unwrap(),
-                        fixed_projection.clone(),
-                    );
-                    record_ref.projection(&mask);
+                if let Projection::Parts(projection) = &projection {
+                    record_ref.projection(&self.projection_mask(projection));
                 }
                 Some(TransactionEntry::Local(record_ref))
             }
@@ -117,6 +138,106 @@ where
         })
     }
 
+    /// get the records of `keys` and get only the data specified in [`Projection`], results are
+    /// returned in the order of `keys`.
+    ///
+    /// Keys that are not written in this transaction are looked up together, see
+    /// [`DB::get_many`](crate::DB::get_many).
+    pub async fn get_many<'get>(
+        &'get self,
+        keys: &'get [<R::Schema as RecordSchema>::Key],
+        projection: Projection<'get>,
+    ) -> Result<Vec<Option<TransactionEntry<'get, R>>>, DbError<R>> {
+        let mask = match &projection {
+            Projection::All => None,
+            Projection::Parts(projection) => Some(self.projection_mask(projection)),
+        };
+        let mut entries = Vec::with_capacity(keys.len());
+        let mut remote_keys = Vec::new();
+        let mut remote_indices = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            self.track_read((Bound::Included(key), Bound::Included(key)));
+            match self.local.get(key).and_then(|v| v.as_ref()) {
+                Some(_) if self.is_merge_on_read() => {
+                    entries.push(self.merged_get(key, projection.clone()).await?);
+                }
+                Some(v) if ttl::is_expired::<R>(Some(v.as_record_ref()), ttl::now()) => {
+                    entries.push(None);
+                }
+                Some(v) => {
+                    let mut record_ref = v.as_record_ref();
+                    if let Some(mask) = &mask {
+                        record_ref.projection(mask);
+                    }
+                    entries.push(Some(TransactionEntry::Local(record_ref)));
+                }
+                None => {
+                    entries.push(None);
+                    remote_keys.push(key);
+                    remote_indices.push(i);
+                }
+            }
+        }
+        if remote_keys.is_empty() {
+            return Ok(entries);
+        }
+
+        let remote_entries = self.snapshot.get_many(&remote_keys, projection).await?;
+        for (i, entry) in remote_indices.into_iter().zip(remote_entries) {
+            entries[i] = entry.map(TransactionEntry::Stream);
+        }
+        Ok(entries)
+    }
+
+    fn is_merge_on_read(&self) -> bool {
+        self.snapshot.schema().is_merge_on_read(self.snapshot.ctx())
+    }
+
+    /// merge the local delta of `key` with its committed versions
+    async fn merged_get<'get>(
+        &'get self,
+        key: &'get <R::Schema as RecordSchema>::Key,
+        projection: Projection<'get>,
+    ) -> Result<Option<TransactionEntry<'get, R>>, DbError<R>> {
+        let mut scan = self.scan((Bound::Included(key), Bound::Included(key)));
+        if let Projection::Parts(projection) = &projection {
+            scan = scan.projection(projection);
+        }
+        let mut stream = pin!(scan.take().await?);
+
+        Ok(stream
+            .next()
+            .await
+            .transpose()?
+            .map(TransactionEntry::Stream))
+    }
+
+    fn projection_mask(&self, projection: &[&str]) -> ProjectionMask {
+        let primary_key_index = self.snapshot.schema().record_schema.primary_key_index();
+        let schema = self.snapshot.schema().record_schema.arrow_schema();
+        let mut projection = projection
+            .iter()
+            .map(|name| {
+                schema
+                    .index_of(name)
+                    .unwrap_or_else(|_| panic!("unexpected field {}", name))
+            })
+            .collect::<Vec<usize>>();
+
+        let mut fixed_projection = vec![0, 1, primary_key_index];
+        fixed_projection.append(&mut projection);
+        fixed_projection.dedup();
+        ttl::project_expire_at(
+            self.snapshot.schema().record_schema.as_ref(),
+            &mut fixed_projection,
+        );
+
+        ProjectionMask::roots(
+            &ArrowSchemaConverter::new().convert(schema).unwrap(),
+            fixed_projection,
+        )
+    }
+
     /// scan records with primary keys in the `range`, return a [`Scan`] that can be convert to a
     /// [`futures_core::Stream`] by using [`Scan::take`].
     ///
@@ -146,6 +267,7 @@ where
             Bound<&'range <R::Schema as RecordSchema>::Key>,
         ),
     ) -> Scan<'scan, 'range, R> {
+        self.track_read(range);
         let ts = self.snapshot.ts();
         let inner = self.local.range(range);
         self.snapshot._scan(
@@ -161,21 +283,78 @@ where
     }
 
     /// insert a sequence of data as a single batch on this transaction
+    ///
+    /// If the write would exceed the limits set by
+    /// [`DbOption::transaction_max_rows`](crate::DbOption::transaction_max_rows) or
+    /// [`DbOption::transaction_max_bytes`](crate::DbOption::transaction_max_bytes), it is
+    /// dropped and [`Transaction::commit`] fails with [`CommitError::LimitExceeded`].
     pub fn insert(&mut self, value: R) {
         self.entry(value.key().to_key(), Some(value))
     }
 
-    /// delete the record with the primary key as the `key` on this transaction
+    /// delete the record with the primary key as the `key` on this transaction, limits are
+    /// handled the same as [`Transaction::insert`]
     pub fn remove(&mut self, key: <R::Schema as RecordSchema>::Key) {
         self.entry(key, None)
     }
 
+    /// insert `value` on this transaction, or return [`CommitError::LimitExceeded`] right away
+    /// if it would exceed the limits of the transaction. The transaction stays usable after a
+    /// rejected write.
+    pub fn try_insert(&mut self, value: R) -> Result<(), CommitError<R>> {
+        self.try_entry(value.key().to_key(), Some(value))
+            .map_err(CommitError::LimitExceeded)
+    }
+
+    /// delete the record with the primary key as the `key` on this transaction, or return
+    /// [`CommitError::LimitExceeded`], see [`Transaction::try_insert`]
+    pub fn try_remove(
+        &mut self,
+        key: <R::Schema as RecordSchema>::Key,
+    ) -> Result<(), CommitError<R>> {
+        self.try_entry(key, None)
+            .map_err(CommitError::LimitExceeded)
+    }
+
     fn entry(&mut self, key: <R::Schema as RecordSchema>::Key, value: Option<R>) {
-        match self.local.entry(key) {
-            Entry::Vacant(v) => {
-                v.insert(value);
+        if let Err(limit) = self.try_entry(key, value) {
+            self.exceeded.get_or_insert(limit);
+        }
+    }
+
+    fn try_entry(
+        &mut self,
+        key: <R::Schema as RecordSchema>::Key,
+        value: Option<R>,
+    ) -> Result<(), TransactionLimit> {
+        let option = &self.snapshot.schema().option;
+        let replaced = self
+            .local
+            .get(&key)
+            .map(|value| Self::entry_size(&key, value));
+        let rows = self.local.len() + usize::from(replaced.is_none());
+        let size = self.local_size - replaced.unwrap_or(0) + Self::entry_size(&key, &value);
+
+        if let Some(max_rows) = option.transaction_max_rows {
+            if rows > max_rows {
+                return Err(TransactionLimit::Rows(max_rows));
             }
-            Entry::Occupied(mut o) => *o.get_mut() = value,
+        }
+        if let Some(max_bytes) = option.transaction_max_bytes {
+            if size > max_bytes {
+                return Err(TransactionLimit::Bytes(max_bytes));
+            }
+        }
+        self.local.insert(key, value);
+        self.local_size = size;
+
+        Ok(())
+    }
+
+    fn entry_size(key: &<R::Schema as RecordSchema>::Key, value: &Option<R>) -> usize {
+        match value {
+            Some(record) => record.size(),
+            None => key.size(),
         }
     }
 
@@ -184,8 +363,27 @@ where
     ///
     /// # Error
     /// This function will return an error if the mutation in the transaction conflict with
-    /// other committed transaction
+    /// other committed transaction, or if a write exceeded the limits of the transaction. With
+    /// [`IsolationLevel::Serializable`] it also returns [`CommitError::ReadConflict`] if another
+    /// transaction committed a write into a key or range read by this one.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "tonbo::commit",
+            skip_all,
+            fields(keys = self.local.len(), reads = tracing::field::Empty),
+        )
+    )]
     pub async fn commit(mut self) -> Result<(), CommitError<R>> {
+        if let Some(limit) = self.exceeded {
+            return Err(CommitError::LimitExceeded(limit));
+        }
+        let serializable_lock = self.serializable_lock.clone();
+        let _serializable_guard = if self.is_serializable() {
+            Some(serializable_lock.lock().await)
+        } else {
+            None
+        };
         let mut _key_guards = Vec::new();
 
         for (key, _) in self.local.iter() {
@@ -206,6 +404,19 @@ where
                 return Err(CommitError::WriteConflict(key.clone()));
             }
         }
+        #[cfg(feature = "trace")]
+        tracing::Span::current().record("reads", self.reads.get_mut().unwrap().len());
+        // the reads are validated under the lock, so a transaction committed after this one
+        // sees its writes
+        for (lower, upper) in self.reads.get_mut().unwrap().drain(..) {
+            if self
+                .snapshot
+                .schema()
+                .check_range_conflict((lower.as_ref(), upper.as_ref()), self.snapshot.ts())
+            {
+                return Err(CommitError::ReadConflict((lower, upper)));
+            }
+        }
 
         let len = self.local.len();
         let is_excess = match len {
@@ -279,6 +490,15 @@ where
     }
 }
 
+/// A cap on the local write set of a [`Transaction`], holding the configured maximum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionLimit {
+    /// see [`DbOption::transaction_max_rows`](crate::DbOption::transaction_max_rows)
+    Rows(usize),
+    /// see [`DbOption::transaction_max_bytes`](crate::DbOption::transaction_max_bytes)
+    Bytes(usize),
+}
+
 #[derive(Debug, Error)]
 pub enum CommitError<R>
 where
@@ -292,10 +512,16 @@ where
     Database(#[from] DbError<R>),
     #[error("transaction write conflict: {:?}", .0)]
     WriteConflict(<R::Schema as RecordSchema>::Key),
+    /// another transaction committed a write into the key range read, with
+    /// [`IsolationLevel::Serializable`]
+    #[error("transaction read conflict: {:?}", .0)]
+    ReadConflict(KeyRange<<R::Schema as RecordSchema>::Key>),
     #[error("Failed to send compact task")]
     SendCompactTaskError(#[from] SendError<CompactTask>),
     #[error("Channel is closed")]
     ChannelClose,
+    #[error("transaction write set exceeds the limit: {:?}", .0)]
+    LimitExceeded(TransactionLimit),
 }
 
 #[cfg(all(test, feature = "tokio"))]
@@ -317,8 +543,8 @@ mod tests {
             test::StringSchema,
         },
         tests::{build_db, build_schema, Test},
-        transaction::CommitError,
-        DbOption, Projection, DB,
+        transaction::{CommitError, TransactionLimit},
+        DbOption, IsolationLevel, Projection, DB,
     };
 
     #[tokio::test(flavor = "multi_thread")]
@@ -490,6 +716,130 @@ mod tests {
         unreachable!();
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serializable_read_conflicts() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &StringSchema,
+        )
+        .isolation_level(IsolationLevel::Serializable);
+
+        let db = DB::<String, TokioExecutor>::new(option, TokioExecutor::current(), StringSchema)
+            .await
+            .unwrap();
+
+        // both transactions check the key the other inserts is absent, a write skew
+        let mut txn_0 = db.transaction().await;
+        let mut txn_1 = db.transaction().await;
+        let a = "a".to_string();
+        let b = "b".to_string();
+        assert!(txn_0.get(&a, Projection::All).await.unwrap().is_none());
+        assert!(txn_1.get(&b, Projection::All).await.unwrap().is_none());
+        txn_0.insert(b.clone());
+        txn_1.insert(a.clone());
+
+        txn_0.commit().await.unwrap();
+        assert!(matches!(
+            txn_1.commit().await,
+            Err(CommitError::ReadConflict((Bound::Included(key), Bound::Included(_)))) if key == b
+        ));
+
+        // a record committed into a scanned range
+        let mut txn_0 = db.transaction().await;
+        let lower = "c".to_string();
+        let upper = "e".to_string();
+        let mut stream = txn_0
+            .scan((Bound::Included(&lower), Bound::Excluded(&upper)))
+            .take()
+            .await
+            .unwrap();
+        while let Some(entry) = stream.next().await {
+            entry.unwrap();
+        }
+        drop(stream);
+        txn_0.insert("z".to_string());
+        let mut txn_1 = db.transaction().await;
+        txn_1.insert("d".to_string());
+        txn_1.commit().await.unwrap();
+        assert!(matches!(
+            txn_0.commit().await,
+            Err(CommitError::ReadConflict(_))
+        ));
+
+        // writes outside of the read ranges commit
+        let mut txn_0 = db.transaction().await;
+        assert!(txn_0.get(&b, Projection::All).await.unwrap().is_some());
+        txn_0.insert("f".to_string());
+        let mut txn_1 = db.transaction().await;
+        txn_1.insert("g".to_string());
+        txn_1.commit().await.unwrap();
+        txn_0.commit().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn transaction_limits() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &StringSchema,
+        )
+        .transaction_max_rows(2)
+        .transaction_max_bytes(8);
+
+        let db = DB::<String, TokioExecutor>::new(option, TokioExecutor::current(), StringSchema)
+            .await
+            .unwrap();
+
+        let mut txn = db.transaction().await;
+        txn.try_insert("aaaa".to_string()).unwrap();
+        txn.try_insert("bbbb".to_string()).unwrap();
+        assert!(matches!(
+            txn.try_insert("cc".to_string()),
+            Err(CommitError::LimitExceeded(TransactionLimit::Rows(2)))
+        ));
+        // overwriting a key does not count as a new row
+        txn.try_insert("aaaa".to_string()).unwrap();
+        assert!(matches!(
+            txn.try_insert("bbbbb".to_string()),
+            Err(CommitError::LimitExceeded(TransactionLimit::Rows(2)))
+        ));
+        txn.commit().await.unwrap();
+
+        let mut txn = db.transaction().await;
+        txn.try_insert("cccccc".to_string()).unwrap();
+        assert!(matches!(
+            txn.try_insert("ddd".to_string()),
+            Err(CommitError::LimitExceeded(TransactionLimit::Bytes(8)))
+        ));
+        txn.commit().await.unwrap();
+
+        let mut txn = db.transaction().await;
+        txn.insert("e".to_string());
+        txn.insert("f".to_string());
+        txn.insert("g".to_string());
+        assert!(matches!(
+            txn.commit().await,
+            Err(CommitError::LimitExceeded(TransactionLimit::Rows(2)))
+        ));
+
+        let txn = db.transaction().await;
+        for key in ["aaaa", "bbbb", "cccccc"] {
+            assert!(txn
+                .get(&key.to_string(), Projection::All)
+                .await
+                .unwrap()
+                .is_some());
+        }
+        for key in ["cc", "ddd", "e", "f", "g"] {
+            assert!(txn
+                .get(&key.to_string(), Projection::All)
+                .await
+                .unwrap()
+                .is_none());
+        }
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn transaction_projection() {
         let temp_dir = TempDir::new().unwrap();
@@ -0,0 +1,74 @@
+//! Per-record expiry.
+//!
+//! A record expires once the wall clock passes the value of its `#[record(expire_at)]` column, in
+//! milliseconds since the unix epoch. Expired records are invisible to reads, as if they were
+//! removed, and compactions replace them with tombstones, so their columns are physically dropped.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use tonbo::{ttl, Record};
+//!
+//! #[derive(Record, Debug)]
+//! pub struct Session {
+//!     #[record(primary_key)]
+//!     id: String,
+//!     token: String,
+//!     #[record(expire_at)]
+//!     expire_at: Option<u64>,
+//! }
+//!
+//! let session = Session {
+//!     id: "alice".to_string(),
+//!     token: "secret".to_string(),
+//!     expire_at: Some(ttl::expire_after(Duration::from_secs(3600))),
+//! };
+//! ```
+//!
+//! Records with a null expiry never expire. Expiry is evaluated with the wall clock, which is not
+//! available on wasm32-unknown-unknown, records never expire there.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::record::{Record, RecordRef, Schema};
+
+/// the expiry of a record living for `ttl` from now
+pub fn expire_after(ttl: Duration) -> u64 {
+    expire_at(SystemTime::now() + ttl)
+}
+
+/// the expiry of a record living until `time`
+pub fn expire_at(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// the current time records are checked against, `0` expires nothing
+pub(crate) fn now() -> u64 {
+    if cfg!(target_arch = "wasm32") {
+        return 0;
+    }
+    expire_at(SystemTime::now())
+}
+
+pub(crate) fn is_expired<R>(record: Option<R::Ref<'_>>, now: u64) -> bool
+where
+    R: Record,
+{
+    record
+        .and_then(|record| record.expire_at())
+        .is_some_and(|expire_at| expire_at < now)
+}
+
+/// keep the expiry column in the projected column `indices`, records are checked with it
+pub(crate) fn project_expire_at<S>(schema: &S, indices: &mut Vec<usize>)
+where
+    S: Schema,
+{
+    if let Some(index) = schema.expire_at_index() {
+        if !indices.contains(&index) {
+            indices.push(index);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::{io::Cursor, sync::Arc};
+
+use arrow::{
+    array::{
+        make_array, new_null_array, Array, ArrayRef, BooleanArray, Datum, RecordBatch, UInt32Array,
+    },
+    datatypes::Schema as ArrowSchema,
+};
+use fusio_log::{Decode, Encode};
+use parquet::arrow::ProjectionMask;
+
+use crate::{
+    executor::Executor,
+    record::{Key, Record, RecordRef, Schema},
+    transaction::CommitError,
+    DbError, UpdateStrategy, DB,
+};
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// set the `columns` of the record with `key`, without reading it. Columns hold single-row
+    /// arrays typed like the columns of the schema.
+    ///
+    /// The update is written as a delta of [`UpdateStrategy::MergeOnRead`]: the columns left out
+    /// keep the values of the older versions of the key, which are merged with the update when
+    /// the key is read and when they are compacted together. If the key is absent, the update
+    /// inserts a record made of `columns`.
+    ///
+    /// # Error
+    /// Returns [`DbError::InvalidUpdate`] if the [`DB`] does not use
+    /// [`UpdateStrategy::MergeOnRead`], if a column is unknown or mistyped, or if a column left
+    /// out is not nullable.
+    pub async fn update<'c>(
+        &self,
+        key: <R::Schema as Schema>::Key,
+        columns: impl IntoIterator<Item = (&'c str, ArrayRef)>,
+    ) -> Result<(), CommitError<R>> {
+        let record = {
+            let schema = self.schema.read().await;
+            if schema.option.update_strategy != UpdateStrategy::MergeOnRead
+                || self.ctx.merge_operator().is_some()
+            {
+                return Err(DbError::InvalidUpdate(
+                    "partial updates require UpdateStrategy::MergeOnRead".to_string(),
+                )
+                .into());
+            }
+            let full_schema = schema.record_schema.arrow_schema().clone();
+            delta::<R>(
+                &full_schema,
+                schema.record_schema.primary_key_index(),
+                key,
+                columns,
+            )
+            .await?
+        };
+
+        self.insert(record).await
+    }
+}
+
+/// build the record holding `columns` of `key`, the other columns are null
+async fn delta<'c, R>(
+    full_schema: &Arc<ArrowSchema>,
+    primary_key_index: usize,
+    key: <R::Schema as Schema>::Key,
+    columns: impl IntoIterator<Item = (&'c str, ArrayRef)>,
+) -> Result<R, DbError<R>>
+where
+    R: Record,
+{
+    let mut arrays = full_schema
+        .fields()
+        .iter()
+        .map(|field| new_null_array(field.data_type(), 1))
+        .collect::<Vec<ArrayRef>>();
+    arrays[0] = Arc::new(BooleanArray::from(vec![false]));
+    arrays[1] = Arc::new(UInt32Array::from(vec![0]));
+    arrays[primary_key_index] = make_array(key.to_arrow_datum().get().0.to_data());
+
+    for (name, column) in columns {
+        let index = full_schema
+            .index_of(name)
+            .ok()
+            .filter(|index| *index >= 2 && *index != primary_key_index)
+            .ok_or_else(|| DbError::InvalidUpdate(format!("unknown column {name}")))?;
+        let field = full_schema.field(index);
+        if column.len() != 1 || column.data_type() != field.data_type() {
+            return Err(DbError::InvalidUpdate(format!(
+                "column {name} must be a single {} value",
+                field.data_type()
+            )));
+        }
+        arrays[index] = column;
+    }
+    let batch = RecordBatch::try_new(full_schema.clone(), arrays)
+        .map_err(|err| DbError::InvalidUpdate(err.to_string()))?;
+
+    let projection = ProjectionMask::all();
+    let record_ref = R::Ref::from_record_batch(&batch, 0, &projection, full_schema)
+        .get()
+        .expect("the delta is not a tombstone");
+    // records are decoded from the encoding of their references, as when the WAL is recovered
+    let mut bytes = Vec::new();
+    let mut cursor = Cursor::new(&mut bytes);
+    record_ref
+        .encode(&mut cursor)
+        .await
+        .map_err(|err| DbError::InvalidUpdate(format!("failed to encode the delta: {err}")))?;
+    cursor.set_position(0);
+    R::decode(&mut cursor)
+        .await
+        .map_err(|err| DbError::InvalidUpdate(format!("failed to decode the delta: {err}")))
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, BooleanArray, UInt32Array};
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test,
+        transaction::CommitError, DbError, DbOption, UpdateStrategy, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn partial_update() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .update_strategy(UpdateStrategy::MergeOnRead);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        let row = |key: &'static str| {
+            let db = &db;
+            async move {
+                db.get(&key.to_string(), |entry| {
+                    let record = entry.get();
+                    Some((record.vu32, record.vbool))
+                })
+                .await
+                .unwrap()
+            }
+        };
+
+        db.insert(Test {
+            vstring: "a".to_string(),
+            vu32: 1,
+            vbool: Some(false),
+        })
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+        db.update(
+            "a".to_string(),
+            [
+                ("vu32", Arc::new(UInt32Array::from(vec![2])) as ArrayRef),
+                (
+                    "vbool",
+                    Arc::new(BooleanArray::from(vec![true])) as ArrayRef,
+                ),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(row("a").await, Some((Some(2), Some(true))));
+        db.update(
+            "a".to_string(),
+            [("vu32", Arc::new(UInt32Array::from(vec![3])) as ArrayRef)],
+        )
+        .await
+        .unwrap();
+        // `vbool` keeps the value of the older versions
+        assert_eq!(row("a").await, Some((Some(3), Some(true))));
+
+        // `vu32` is not nullable, it can not be left out
+        assert!(matches!(
+            db.update(
+                "b".to_string(),
+                [(
+                    "vbool",
+                    Arc::new(BooleanArray::from(vec![true])) as ArrayRef
+                )],
+            )
+            .await,
+            Err(CommitError::Database(DbError::InvalidUpdate(_)))
+        ));
+        assert!(matches!(
+            db.update(
+                "a".to_string(),
+                [("missing", Arc::new(UInt32Array::from(vec![2])) as ArrayRef)],
+            )
+            .await,
+            Err(CommitError::Database(DbError::InvalidUpdate(_)))
+        ));
+    }
+}
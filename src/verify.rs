@@ -0,0 +1,285 @@
+use std::{pin::pin, sync::Arc};
+
+use fusio::path::Path;
+use futures_util::StreamExt;
+use parquet_lru::NoCache;
+
+use crate::{
+    encryption,
+    executor::Executor,
+    fs::{parse_file_id, FileType},
+    ondisk::sstable::SsTable,
+    record::{Record, Schema},
+    scope::Scope,
+    wal::{RecoverError, WalFile},
+    DbError, DbOption, ParquetLru, DB,
+};
+
+/// What is wrong with a [`CorruptedFile`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Corruption {
+    /// the file of a table of the manifest can't be opened, e.g. it is missing
+    Open(String),
+    /// the Parquet footer of the table can't be read
+    Footer(String),
+    /// a page of the table can't be read or decoded, or fails its checksum
+    Data(String),
+    /// the keys of the table are out of the range the manifest records for it
+    Range,
+    /// the table overlaps the previous table of its level, whose tables must be disjoint
+    Overlap,
+    /// a log of the WAL segment fails its checksum or can't be decrypted
+    Wal(String),
+}
+
+/// A file found corrupted by [`DB::verify_integrity`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptedFile<K> {
+    pub path: Path,
+    /// level of a table, `None` for a WAL segment
+    pub level: Option<usize>,
+    /// smallest and largest keys the manifest records for a table, `None` for a WAL segment
+    pub range: Option<(K, K)>,
+    pub corruption: Corruption,
+}
+
+/// Result of [`DB::verify_integrity`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityReport<K> {
+    /// number of tables of the manifest verified
+    pub tables: usize,
+    /// number of WAL segments verified
+    pub wal_segments: usize,
+    pub corrupted: Vec<CorruptedFile<K>>,
+}
+
+impl<K> IntegrityReport<K> {
+    pub fn is_ok(&self) -> bool {
+        self.corrupted.is_empty()
+    }
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Verify the files of the current version and the WAL segments while the [`DB`] keeps
+    /// serving reads and writes.
+    ///
+    /// Every table of the manifest is read from its footer to its last page, and its keys are
+    /// checked against the range the manifest records for it and against the other tables of
+    /// its level. Every log of the WAL segments is checked against its checksum, except for the
+    /// segment the [`DB`] is writing, whose last log may be partly written. Corrupted files
+    /// are reported with the key range they hold, so they can be restored or dropped, instead of
+    /// failing a scan that reaches them.
+    ///
+    /// Tables are read from their files, bypassing the cache the [`DB`] reads them with.
+    pub async fn verify_integrity(
+        &self,
+    ) -> Result<IntegrityReport<<R::Schema as Schema>::Key>, DbError<R>> {
+        let option = self.schema.read().await.option.clone();
+        let mut lru_cache: ParquetLru = Arc::new(NoCache::default());
+        if let Some(key_provider) = &option.encryption {
+            lru_cache = encryption::cache(&lru_cache, key_provider.clone())?;
+        }
+        let mut report = IntegrityReport {
+            tables: 0,
+            wal_segments: 0,
+            corrupted: Vec::new(),
+        };
+
+        let version = self.ctx.version_set().current().await;
+        for (level, scopes) in version.level_slice.iter().enumerate() {
+            for (i, scope) in scopes.iter().enumerate() {
+                report.tables += 1;
+                // the tables of the levels below level 0 are sorted runs
+                let corruption = if level > 0 && i > 0 && scopes[i - 1].max >= scope.min {
+                    Some(Corruption::Overlap)
+                } else {
+                    self.verify_table(&option, &lru_cache, level, scope).await
+                };
+                if let Some(corruption) = corruption {
+                    report.corrupted.push(CorruptedFile {
+                        path: option.table_path(scope.gen, level),
+                        level: Some(level),
+                        range: Some((scope.min.clone(), scope.max.clone())),
+                        corruption,
+                    });
+                }
+            }
+        }
+        drop(version);
+
+        // the segment of the mutable memtable is being written, its last log may be partly written
+        let live_wal = self.schema.read().await.mutable.wal_id().await;
+        let fs = self.ctx.manager.wal_fs();
+        let mut wal_paths = Vec::new();
+        let mut wal_stream = fs.list(&option.wal_dir_path()).await?;
+        while let Some(file_meta) = wal_stream.next().await {
+            let file_meta = file_meta?;
+            let is_live = live_wal.is_some()
+                && parse_file_id(&file_meta.path, FileType::Wal).ok().flatten() == live_wal;
+            if file_meta.path.as_ref().ends_with("wal") && !is_live {
+                wal_paths.push(file_meta.path);
+            }
+        }
+        for wal_path in wal_paths {
+            report.wal_segments += 1;
+            let mut stream = pin!(
+                WalFile::<R>::recover(
+                    option.wal_fs().clone(),
+                    wal_path.clone(),
+                    option.encryption.clone()
+                )
+                .await
+            );
+            while let Some(logs) = stream.next().await {
+                // a log torn by a crash at the end of a segment is dropped by the recovery
+                if let Err(RecoverError::Torn) = logs {
+                    break;
+                }
+                if let Err(err) = logs {
+                    report.corrupted.push(CorruptedFile {
+                        path: wal_path,
+                        level: None,
+                        range: None,
+                        corruption: Corruption::Wal(err.to_string()),
+                    });
+                    break;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn verify_table(
+        &self,
+        option: &DbOption,
+        lru_cache: &ParquetLru,
+        level: usize,
+        scope: &Scope<<R::Schema as Schema>::Key>,
+    ) -> Option<Corruption> {
+        let fs = self
+            .ctx
+            .manager
+            .get_fs(option.level_fs_path(level).unwrap_or(&option.base_path));
+        let open = || async {
+            let file = fs
+                .open_options(
+                    &option.table_path(scope.gen, level),
+                    FileType::Parquet.open_options(true),
+                )
+                .await?;
+            SsTable::<R>::open(lru_cache.clone(), scope.gen, file).await
+        };
+
+        match open().await {
+            Ok(table) => {
+                if let Err(err) = table.metadata().await {
+                    return Some(Corruption::Footer(err.to_string()));
+                }
+            }
+            Err(err) => return Some(Corruption::Open(err.to_string())),
+        }
+        match open().await {
+            Ok(table) => match table.key_range().await {
+                Ok(Some((min, max))) if min >= scope.min && max <= scope.max => None,
+                Ok(_) => Some(Corruption::Range),
+                Err(err) => Some(Corruption::Data(err.to_string())),
+            },
+            Err(err) => Some(Corruption::Open(err.to_string())),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{fs, io::Write, path::PathBuf};
+
+    use fusio::path::{path_to_local, Path};
+    use tempfile::TempDir;
+
+    use super::Corruption;
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    fn tables(dir: PathBuf) -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .unwrap()
+            .flat_map(|entry| {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    tables(path)
+                } else if path
+                    .extension()
+                    .is_some_and(|extension| extension == "parquet")
+                {
+                    vec![path]
+                } else {
+                    vec![]
+                }
+            })
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn verify_integrity() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        option.immutable_chunk_num = 1;
+        option.immutable_chunk_max_num = 0;
+        let db: DB<Test, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::current(), TestSchema)
+                .await
+                .unwrap();
+        for i in 0..10 {
+            db.insert(Test {
+                vstring: format!("key{i}"),
+                vu32: i,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+
+        let report = db.verify_integrity().await.unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.tables, 1);
+
+        // a log torn at the end of the segment being written is not a corruption
+        let live_wal = db.schema.read().await.mutable.wal_id().await.unwrap();
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path_to_local(&option.wal_segment_path(live_wal)).unwrap())
+            .unwrap();
+        file.write_all(&[9, 0]).unwrap();
+        drop(file);
+        assert!(db.verify_integrity().await.unwrap().is_ok());
+
+        // cut the footer off the table
+        let tables = tables(temp_dir.path().to_path_buf());
+        assert_eq!(tables.len(), 1);
+        let bytes = fs::read(&tables[0]).unwrap();
+        fs::write(&tables[0], &bytes[..bytes.len() / 2]).unwrap();
+
+        let report = db.verify_integrity().await.unwrap();
+        assert_eq!(report.corrupted.len(), 1);
+        let corrupted = &report.corrupted[0];
+        assert_eq!(corrupted.level, Some(0));
+        assert_eq!(
+            corrupted.range,
+            Some(("key0".to_string(), "key9".to_string()))
+        );
+        assert!(matches!(corrupted.corruption, Corruption::Footer(_)));
+    }
+}
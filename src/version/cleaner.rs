@@ -1,13 +1,23 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, io::ErrorKind, mem, pin::pin, sync::Arc, time::Duration};
 
 use flume::{Receiver, Sender};
+use futures_util::future::{select, Either};
+use tracing::error;
 
 use crate::{
-    fs::{manager::StoreManager, FileId},
+    compaction::rate_limit::RateLimiter,
+    executor,
+    fs::{manager::StoreManager, retry::retry, FileId},
     timestamp::Timestamp,
     DbOption,
 };
 
+/// the tables failing to be removed are retried this often by default, besides every clean
+const PENDING_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+/// at most this many tables are retried, the oldest ones are left to
+/// [`DB::collect_garbage`](crate::DB::collect_garbage) beyond it
+const MAX_PENDING: usize = 1024;
+
 pub enum CleanTag {
     Add {
         ts: Timestamp,
@@ -20,13 +30,25 @@ pub enum CleanTag {
         wal_id: FileId,
         level: usize,
     },
+    /// reply with the tables waiting for the versions reading them to be dropped
+    Retained {
+        tx: Sender<Vec<FileId>>,
+    },
 }
 
 pub(crate) struct Cleaner {
     tag_recv: Receiver<CleanTag>,
     gens_map: BTreeMap<Timestamp, (Vec<(FileId, usize)>, bool)>,
+    /// tables that could not be removed yet, e.g. on Windows, where files can not be removed while
+    /// they are open. They are retried on the next clean or after `retry_interval`, at most
+    /// [`MAX_PENDING`] of them.
+    pending: Vec<(FileId, usize)>,
+    retry_interval: Duration,
     option: Arc<DbOption>,
     manager: Arc<StoreManager>,
+    /// charged a request by every removal, see
+    /// [`DbOption::background_rate_limit`](crate::DbOption::background_rate_limit)
+    rate_limiter: Option<Arc<RateLimiter>>,
 }
 
 impl Cleaner {
@@ -40,15 +62,40 @@ impl Cleaner {
             Cleaner {
                 tag_recv,
                 gens_map: Default::default(),
+                pending: Vec::new(),
+                retry_interval: PENDING_RETRY_INTERVAL,
                 option,
                 manager,
+                rate_limiter: None,
             },
             tag_send,
         )
     }
 
+    pub(crate) fn with_rate_limiter(self, rate_limiter: Option<Arc<RateLimiter>>) -> Self {
+        Self {
+            rate_limiter,
+            ..self
+        }
+    }
+
     pub(crate) async fn listen(&mut self) -> Result<(), fusio::Error> {
-        while let Ok(tag) = self.tag_recv.recv_async().await {
+        loop {
+            let tag = if self.pending.is_empty() || cfg!(target_arch = "wasm32") {
+                self.tag_recv.recv_async().await
+            } else {
+                let recv = pin!(self.tag_recv.recv_async());
+                match select(recv, pin!(executor::sleep(self.retry_interval))).await {
+                    Either::Left((tag, _)) => tag,
+                    Either::Right(_) => {
+                        self.retry_pending().await;
+                        continue;
+                    }
+                }
+            };
+            let Ok(tag) = tag else {
+                break;
+            };
             match tag {
                 CleanTag::Add { ts, gens } => {
                     let _ = self.gens_map.insert(ts, (gens, false));
@@ -57,34 +104,79 @@ impl Cleaner {
                     if let Some((_, dropped)) = self.gens_map.get_mut(&version_num) {
                         *dropped = true;
                     }
+                    self.retry_pending().await;
                     while let Some((first_version, (gens, dropped))) = self.gens_map.pop_first() {
                         if !dropped {
                             let _ = self.gens_map.insert(first_version, (gens, false));
                             break;
                         }
                         for (gen, level) in gens {
-                            let fs = self
-                                .option
-                                .level_fs_path(level)
-                                .map(|path| self.manager.get_fs(path))
-                                .unwrap_or(self.manager.base_fs());
-                            fs.remove(&self.option.table_path(gen, level)).await?;
+                            self.remove(gen, level).await;
                         }
                     }
                 }
                 CleanTag::RecoverClean { wal_id: gen, level } => {
-                    let fs = self
-                        .option
-                        .level_fs_path(level)
-                        .map(|path| self.manager.get_fs(path))
-                        .unwrap_or(self.manager.base_fs());
-                    fs.remove(&self.option.table_path(gen, level)).await?;
+                    self.remove(gen, level).await;
+                }
+                CleanTag::Retained { tx } => {
+                    let gens = self
+                        .gens_map
+                        .values()
+                        .flat_map(|(gens, _)| gens.iter())
+                        .chain(self.pending.iter())
+                        .map(|(gen, _)| *gen)
+                        .collect();
+                    let _ = tx.send_async(gens).await;
                 }
             }
         }
 
         Ok(())
     }
+
+    async fn retry_pending(&mut self) {
+        for (gen, level) in mem::take(&mut self.pending) {
+            self.remove(gen, level).await;
+        }
+    }
+
+    /// remove the table `gen` of `level`, or keep it in the pending tables if it fails so that the
+    /// cleaner keeps running
+    async fn remove(&mut self, gen: FileId, level: usize) {
+        let fs = self
+            .option
+            .level_fs_path(level)
+            .map(|path| self.manager.get_fs(path))
+            .unwrap_or(self.manager.base_fs());
+        if let Some(table_cache) = self.manager.table_cache() {
+            table_cache.evict(gen);
+        }
+        let path = self.option.table_path(gen, level);
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire_requests(0, 1).await;
+        }
+        match retry(self.option.retry_policy.as_ref(), || fs.remove(&path)).await {
+            Ok(()) => {
+                if let Some(event_listener) = &self.option.event_listener {
+                    event_listener.on_table_deleted(gen, level);
+                }
+            }
+            // removed before a restart
+            Err(fusio::Error::Io(err)) if err.kind() == ErrorKind::NotFound => (),
+            Err(err) => {
+                error!("[Cleaner Error]: failed to remove table {}: {}", gen, err);
+                if self.pending.len() >= MAX_PENDING {
+                    let (gen, _) = self.pending.remove(0);
+                    error!(
+                        "[Cleaner Error]: too many tables failed to be removed, table {} is left \
+                         to the garbage collection",
+                        gen
+                    );
+                }
+                self.pending.push((gen, level));
+            }
+        }
+    }
 }
 
 #[cfg(all(test, feature = "tokio"))]
@@ -101,7 +193,7 @@ pub(crate) mod tests {
         executor::{tokio::TokioExecutor, Executor},
         fs::{generate_file_id, manager::StoreManager, FileType},
         inmem::immutable::tests::TestSchema,
-        version::cleaner::{CleanTag, Cleaner},
+        version::cleaner::{CleanTag, Cleaner, MAX_PENDING},
         DbOption,
     };
 
@@ -238,4 +330,115 @@ pub(crate) mod tests {
             .unwrap()
             .exists());
     }
+
+    #[tokio::test]
+    async fn test_cleaner_retries_failed_removals() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(StoreManager::new(FsOptions::Local, vec![]).unwrap());
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        ));
+        let fs = manager.base_fs();
+        let (gen_0, gen_1) = (generate_file_id(), generate_file_id());
+        // a directory can not be removed as a file, like an open file on Windows
+        let blocked = path_to_local(&option.table_path(gen_0, 0)).unwrap();
+        std::fs::create_dir_all(&blocked).unwrap();
+        fs.open_options(
+            &option.table_path(gen_1, 0),
+            FileType::Parquet.open_options(false),
+        )
+        .await
+        .unwrap();
+
+        let (mut cleaner, tx) = Cleaner::new(option.clone(), manager.clone());
+        TokioExecutor::current().spawn(async move {
+            if let Err(err) = cleaner.listen().await {
+                error!("[Cleaner Error]: {}", err)
+            }
+        });
+
+        tx.send_async(CleanTag::Add {
+            ts: 0.into(),
+            gens: vec![(gen_0, 0), (gen_1, 0)],
+        })
+        .await
+        .unwrap();
+        tx.send_async(CleanTag::Clean { ts: 0.into() })
+            .await
+            .unwrap();
+        sleep(Duration::from_millis(10)).await;
+        assert!(blocked.exists());
+        assert!(!path_to_local(&option.table_path(gen_1, 0))
+            .unwrap()
+            .exists());
+
+        std::fs::remove_dir(&blocked).unwrap();
+        std::fs::write(&blocked, b"").unwrap();
+        tx.send_async(CleanTag::Clean { ts: 1.into() })
+            .await
+            .unwrap();
+        sleep(Duration::from_millis(10)).await;
+        assert!(!blocked.exists());
+    }
+
+    #[tokio::test]
+    async fn test_cleaner_retries_pending_on_timer() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(StoreManager::new(FsOptions::Local, vec![]).unwrap());
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        ));
+        let gen = generate_file_id();
+        let blocked = path_to_local(&option.table_path(gen, 0)).unwrap();
+        std::fs::create_dir_all(&blocked).unwrap();
+
+        let (mut cleaner, tx) = Cleaner::new(option.clone(), manager.clone());
+        cleaner.retry_interval = Duration::from_millis(20);
+        TokioExecutor::current().spawn(async move {
+            if let Err(err) = cleaner.listen().await {
+                error!("[Cleaner Error]: {}", err)
+            }
+        });
+        tx.send_async(CleanTag::RecoverClean {
+            wal_id: gen,
+            level: 0,
+        })
+        .await
+        .unwrap();
+        sleep(Duration::from_millis(10)).await;
+        assert!(blocked.exists());
+
+        // retried without any further clean
+        std::fs::remove_dir(&blocked).unwrap();
+        std::fs::write(&blocked, b"").unwrap();
+        sleep(Duration::from_millis(100)).await;
+        assert!(!blocked.exists());
+    }
+
+    #[tokio::test]
+    async fn test_cleaner_caps_pending() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(StoreManager::new(FsOptions::Local, vec![]).unwrap());
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        ));
+        let gen = generate_file_id();
+        std::fs::create_dir_all(path_to_local(&option.table_path(gen, 0)).unwrap()).unwrap();
+
+        let (mut cleaner, _tx) = Cleaner::new(option, manager);
+        let oldest = generate_file_id();
+        cleaner.pending = (0..MAX_PENDING)
+            .map(|i| (if i == 0 { oldest } else { generate_file_id() }, 0))
+            .collect();
+        cleaner.remove(gen, 0).await;
+        assert_eq!(cleaner.pending.len(), MAX_PENDING);
+        assert!(cleaner
+            .pending
+            .iter()
+            .all(|(pending, _)| *pending != oldest));
+        assert_eq!(cleaner.pending.last(), Some(&(gen, 0)));
+    }
 }
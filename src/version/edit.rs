@@ -1,7 +1,7 @@
 use std::mem::size_of;
 
 use fusio::{SeqRead, Write};
-use fusio_log::{Decode, Encode, FsOptions, Options, Path};
+use fusio_log::{error::LogError, Decode, Encode, FsOptions, Options, Path};
 use futures_util::TryStreamExt;
 
 use crate::{fs::FileId, scope::Scope, timestamp::Timestamp};
@@ -12,28 +12,52 @@ pub(crate) enum VersionEdit<K> {
     Remove { level: u8, gen: FileId },
     LatestTimeStamp { ts: Timestamp },
     NewLogLength { len: u32 },
+    // the fencing epoch of the lease the batch is written under, see `LeaseStore::take`
+    Fence { epoch: u64 },
 }
 
 impl<K> VersionEdit<K>
 where
     K: Decode + Send,
 {
+    #[cfg(test)]
     pub(crate) async fn recover(path: Path, fs_option: FsOptions) -> Vec<VersionEdit<K>> {
+        Self::try_recover(path, fs_option).await.unwrap()
+    }
+
+    /// read the edits of the log at `path`, up to the first one that can't be decoded, e.g. while
+    /// it is being written. A batch fenced by an epoch older than the one of a batch before is
+    /// left out, it is written by a holder which lost its lease.
+    pub(crate) async fn try_recover(
+        path: Path,
+        fs_option: FsOptions,
+    ) -> Result<Vec<VersionEdit<K>>, LogError> {
         let mut edits = vec![];
 
         let mut edits_stream = Options::new(path)
             .disable_buf()
             .fs(fs_option)
             .recover::<VersionEdit<K>>()
-            .await
-            .unwrap();
+            .await?;
+        let mut epoch = 0;
         while let Ok(batch) = edits_stream.try_next().await {
             match batch {
-                Some(mut batch) => edits.append(&mut batch),
+                Some(mut batch) => {
+                    if let Some(fence) = batch.iter().find_map(|edit| match edit {
+                        VersionEdit::Fence { epoch } => Some(*epoch),
+                        _ => None,
+                    }) {
+                        if fence < epoch {
+                            continue;
+                        }
+                        epoch = fence;
+                    }
+                    edits.append(&mut batch)
+                }
                 None => break,
             }
         }
-        edits
+        Ok(edits)
     }
 }
 
@@ -67,6 +91,10 @@ where
                 3u8.encode(writer).await?;
                 len.encode(writer).await?;
             }
+            VersionEdit::Fence { epoch } => {
+                4u8.encode(writer).await?;
+                epoch.encode(writer).await?;
+            }
         }
 
         Ok(())
@@ -80,6 +108,7 @@ where
                 VersionEdit::Remove { .. } => 16,
                 VersionEdit::LatestTimeStamp { ts } => ts.size(),
                 VersionEdit::NewLogLength { .. } => size_of::<u32>(),
+                VersionEdit::Fence { .. } => size_of::<u64>(),
             }
     }
 }
@@ -118,6 +147,10 @@ where
                 let len = u32::decode(reader).await?;
                 VersionEdit::NewLogLength { len }
             }
+            4 => {
+                let epoch = u64::decode(reader).await?;
+                VersionEdit::Fence { epoch }
+            }
             _ => unreachable!(),
         })
     }
@@ -130,7 +163,11 @@ mod tests {
     use fusio_log::{Decode, Encode};
     use tokio::io::AsyncSeekExt;
 
-    use crate::{fs::generate_file_id, scope::Scope, version::edit::VersionEdit};
+    use crate::{
+        fs::generate_file_id,
+        scope::{Scope, TableStats},
+        version::edit::VersionEdit,
+    };
 
     #[tokio::test]
     async fn encode_and_decode() {
@@ -142,6 +179,22 @@ mod tests {
                     max: "Max".to_string(),
                     gen: Default::default(),
                     wal_ids: Some(vec![generate_file_id(), generate_file_id()]),
+                    stats: None,
+                },
+            },
+            VersionEdit::Add {
+                level: 1,
+                scope: Scope {
+                    min: "A".to_string(),
+                    max: "B".to_string(),
+                    gen: generate_file_id(),
+                    wal_ids: None,
+                    stats: Some(TableStats {
+                        rows: 10,
+                        tombstones: 4,
+                        size: Some(2048),
+                        is_single_version: true,
+                    }),
                 },
             },
             VersionEdit::Remove {
@@ -150,6 +203,7 @@ mod tests {
             },
             VersionEdit::LatestTimeStamp { ts: 10.into() },
             VersionEdit::NewLogLength { len: 233 },
+            VersionEdit::Fence { epoch: 7 },
         ];
 
         let mut buf = Vec::new();
@@ -11,16 +11,15 @@ use std::{
 };
 
 use flume::{SendError, Sender};
-use fusio::DynFs;
 use fusio_log::{error::LogError, Encode};
-use parquet::arrow::ProjectionMask;
+use parquet::{arrow::ProjectionMask, file::metadata::ParquetMetaData};
 use thiserror::Error;
 use tracing::error;
 
 use crate::{
     context::Context,
-    fs::{manager::StoreManager, FileId, FileType},
-    ondisk::sstable::SsTable,
+    fs::{manager::StoreManager, FileId},
+    ondisk::{readahead::Readahead, scan::SsTableScan, sstable::SsTable},
     record::{Record, Schema},
     scope::Scope,
     stream::{level::LevelStream, record_batch::RecordBatchEntry, ScanStream},
@@ -124,18 +123,13 @@ where
         projection_mask: ProjectionMask,
         parquet_lru: ParquetLru,
     ) -> Result<Option<RecordBatchEntry<R>>, VersionError<R>> {
-        let level_0_path = self
-            .option
-            .level_fs_path(0)
-            .unwrap_or(&self.option.base_path);
-        let level_0_fs = manager.get_fs(level_0_path);
         for scope in self.level_slice[0].iter().rev() {
             if !scope.contains(key.value()) {
                 continue;
             }
             if let Some(entry) = self
                 .table_query(
-                    level_0_fs,
+                    manager,
                     key,
                     0,
                     scope.gen,
@@ -149,11 +143,6 @@ where
         }
         for (i, sort_runs) in self.level_slice[1..MAX_LEVEL].iter().enumerate() {
             let leve = i + 1;
-            let level_path = self
-                .option
-                .level_fs_path(leve)
-                .unwrap_or(&self.option.base_path);
-            let level_fs = manager.get_fs(level_path);
             if sort_runs.is_empty() {
                 continue;
             }
@@ -163,7 +152,7 @@ where
             }
             if let Some(entry) = self
                 .table_query(
-                    level_fs,
+                    manager,
                     key,
                     leve,
                     sort_runs[index].gen,
@@ -179,29 +168,150 @@ where
         Ok(None)
     }
 
+    /// query sorted and deduplicated `keys` at `ts`, each table is opened and scanned at most once
+    /// for all keys it may contain
+    pub(crate) async fn query_many(
+        &self,
+        manager: &StoreManager,
+        keys: &[&<R::Schema as Schema>::Key],
+        ts: Timestamp,
+        projection_mask: ProjectionMask,
+        parquet_lru: ParquetLru,
+    ) -> Result<Vec<Option<RecordBatchEntry<R>>>, VersionError<R>> {
+        let mut entries = keys.iter().map(|_| None).collect::<Vec<_>>();
+
+        for (level, scopes) in self.level_slice.iter().enumerate() {
+            // level 0 tables overlap, the newer ones must be queried first
+            let scopes: Box<dyn Iterator<Item = &Scope<_>>> = if level == 0 {
+                Box::new(scopes.iter().rev())
+            } else {
+                Box::new(scopes.iter())
+            };
+
+            for scope in scopes {
+                let indices = (0..keys.len())
+                    .filter(|i| entries[*i].is_none() && scope.contains(keys[*i]))
+                    .collect::<Vec<_>>();
+                if indices.is_empty() {
+                    continue;
+                }
+                let reader = manager
+                    .open_table(&self.option, level, scope.gen)
+                    .await
+                    .map_err(VersionError::Fusio)?;
+                let table_keys = indices.iter().map(|i| keys[*i]).collect::<Vec<_>>();
+                let table_entries =
+                    SsTable::<R>::from_reader(parquet_lru.clone(), scope.gen, reader)
+                        .await
+                        .get_many(&table_keys, ts, projection_mask.clone())
+                        .await
+                        .map_err(VersionError::Parquet)?;
+
+                for (i, entry) in indices.into_iter().zip(table_entries) {
+                    entries[i] = entry;
+                }
+            }
+            if entries.iter().all(Option::is_some) {
+                break;
+            }
+        }
+        Ok(entries)
+    }
+
+    pub(crate) async fn table_metadata(
+        &self,
+        manager: &StoreManager,
+        level: usize,
+        gen: FileId,
+        parquet_lru: ParquetLru,
+    ) -> Result<Arc<ParquetMetaData>, VersionError<R>> {
+        let reader = manager
+            .open_table(&self.option, level, gen)
+            .await
+            .map_err(VersionError::Fusio)?;
+        SsTable::<R>::from_reader(parquet_lru, gen, reader)
+            .await
+            .metadata()
+            .await
+            .map_err(VersionError::Parquet)
+    }
+
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "tonbo::sstable::get",
+            skip_all,
+            fields(level = level, gen = %gen),
+        )
+    )]
     async fn table_query(
         &self,
-        store: &Arc<dyn DynFs>,
+        manager: &StoreManager,
         key: &TsRef<<R::Schema as Schema>::Key>,
         level: usize,
         gen: FileId,
         projection_mask: ProjectionMask,
         parquet_lru: ParquetLru,
     ) -> Result<Option<RecordBatchEntry<R>>, VersionError<R>> {
-        let file = store
-            .open_options(
-                &self.option.table_path(gen, level),
-                FileType::Parquet.open_options(true),
-            )
+        let reader = manager
+            .open_table(&self.option, level, gen)
             .await
             .map_err(VersionError::Fusio)?;
-        SsTable::<R>::open(parquet_lru, gen, file)
-            .await?
+        SsTable::<R>::from_reader(parquet_lru, gen, reader)
+            .await
             .get(key, projection_mask)
             .await
             .map_err(VersionError::Parquet)
     }
 
+    /// the tables that may hold `key`, from the newest to the oldest: the level 0 tables
+    /// containing it, then the table of every other level containing it
+    pub(crate) fn key_tables(&self, key: &<R::Schema as Schema>::Key) -> Vec<(usize, FileId)> {
+        let mut tables = self.level_slice[0]
+            .iter()
+            .rev()
+            .filter(|scope| scope.contains(key))
+            .map(|scope| (0, scope.gen))
+            .collect::<Vec<_>>();
+        for (level, sort_runs) in self.level_slice.iter().enumerate().skip(1) {
+            if sort_runs.is_empty() {
+                continue;
+            }
+            let scope = &sort_runs[Self::scope_search(key, sort_runs)];
+            if scope.contains(key) {
+                tables.push((level, scope.gen));
+            }
+        }
+        tables
+    }
+
+    /// every version of `key` visible at `ts` in the table `gen` of `level`, from the newest
+    pub(crate) async fn key_versions<'scan>(
+        &self,
+        manager: &StoreManager,
+        key: &'scan <R::Schema as Schema>::Key,
+        ts: Timestamp,
+        level: usize,
+        gen: FileId,
+        projection_mask: ProjectionMask,
+        parquet_lru: ParquetLru,
+    ) -> Result<SsTableScan<'scan, R>, VersionError<R>> {
+        let reader = manager
+            .open_table(&self.option, level, gen)
+            .await
+            .map_err(VersionError::Fusio)?;
+        SsTable::<R>::from_reader(parquet_lru, gen, reader)
+            .await
+            .scan(
+                (Bound::Included(key), Bound::Included(key)),
+                ts,
+                None,
+                projection_mask,
+            )
+            .await
+            .map_err(VersionError::Parquet)
+    }
+
     pub(crate) fn scope_search(
         key: &<R::Schema as Schema>::Key,
         level: &[Scope<<R::Schema as Schema>::Key>],
@@ -216,6 +326,14 @@ where
     }
 
     #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            name = "tonbo::version::streams",
+            skip_all,
+            fields(lower = ?range.0, upper = ?range.1, streams = tracing::field::Empty),
+        )
+    )]
     pub(crate) async fn streams<'streams>(
         &self,
         ctx: &Context<R>,
@@ -227,30 +345,45 @@ where
         ts: Timestamp,
         limit: Option<usize>,
         projection_mask: ProjectionMask,
+        parquet_lru: ParquetLru,
+        borrow_values: bool,
     ) -> Result<(), VersionError<R>> {
-        let level_0_path = self
-            .option
-            .level_fs_path(0)
-            .unwrap_or(&self.option.base_path);
-        let level_0_fs = ctx.manager.get_fs(level_0_path);
+        #[cfg(feature = "trace")]
+        let streams_len = streams.len();
         for scope in self.level_slice[0].iter() {
             if !scope.meets_range(range) {
                 continue;
             }
-            let file = level_0_fs
-                .open_options(
-                    &self.option.table_path(scope.gen, 0),
-                    FileType::Parquet.open_options(true),
-                )
+            let reader = ctx
+                .manager
+                .open_table(&self.option, 0, scope.gen)
                 .await
                 .map_err(VersionError::Fusio)?;
-            let table = SsTable::open(ctx.parquet_lru.clone(), scope.gen, file).await?;
+            let readahead = Readahead::new(
+                &self.option,
+                ctx.manager.table_cache().cloned(),
+                ctx.manager
+                    .get_fs(
+                        self.option
+                            .level_fs_path(0)
+                            .unwrap_or(&self.option.base_path),
+                    )
+                    .clone(),
+                self.option.table_path(scope.gen, 0),
+                scope.gen,
+                parquet_lru.clone(),
+            );
+            let table = SsTable::from_reader(parquet_lru.clone(), scope.gen, reader)
+                .await
+                .with_readahead(readahead);
 
             streams.push(ScanStream::SsTable {
                 inner: table
                     .scan(range, ts, limit, projection_mask.clone())
                     .await
-                    .map_err(VersionError::Parquet)?,
+                    .map_err(VersionError::Parquet)?
+                    .with_memory(ctx.scan_memory().clone())
+                    .borrow_values(borrow_values),
             })
         }
         for (i, scopes) in self.level_slice[1..].iter().enumerate() {
@@ -289,11 +422,17 @@ where
                     limit,
                     projection_mask.clone(),
                     level_fs.clone(),
-                    ctx.parquet_lru.clone(),
+                    parquet_lru.clone(),
                 )
-                .unwrap(),
+                .unwrap()
+                .with_memory(ctx.scan_memory().clone())
+                .with_table_cache(ctx.manager.table_cache().cloned())
+                .borrow_values(borrow_values),
             });
         }
+        // a stream of every level 0 table and of every other level in the range
+        #[cfg(feature = "trace")]
+        tracing::Span::current().record("streams", streams.len() - streams_len);
         Ok(())
     }
 
@@ -344,4 +483,10 @@ where
     Send(#[from] SendError<CleanTag>),
     #[error("log error: {0}")]
     Logger(#[from] LogError),
+    #[error("version set of a read-only DB can't be edited")]
+    ReadOnly,
+    #[error("version cleaner is closed")]
+    CleanerClosed,
+    #[error("the lease of the DB is lost, its version log is written by another process")]
+    LeaseLost,
 }
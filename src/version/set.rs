@@ -1,49 +1,33 @@
 use std::{
-    collections::BinaryHeap,
+    collections::HashSet,
     mem,
     sync::{
-        atomic::{AtomicU32, Ordering},
-        Arc,
+        atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
     },
+    time::{Duration, Instant},
 };
 
 use async_lock::RwLock;
 use flume::Sender;
-use fusio::{fs::FileMeta, DynFs};
+use fusio::DynFs;
 use fusio_log::{Logger, Options};
 use futures_util::StreamExt;
+use tracing::error;
 
 use super::{TransactionTs, MAX_LEVEL};
 use crate::{
-    fs::{generate_file_id, manager::StoreManager, parse_file_id, FileId, FileType},
+    fs::{
+        generate_file_id, manager::StoreManager, parse_file_id, retry::retry_write, FileId,
+        FileType,
+    },
+    lease::Lease,
     record::{Record, Schema},
     timestamp::Timestamp,
     version::{cleaner::CleanTag, edit::VersionEdit, Version, VersionError, VersionRef},
     DbOption,
 };
 
-struct CmpMeta(FileMeta);
-
-impl Eq for CmpMeta {}
-
-impl PartialEq<Self> for CmpMeta {
-    fn eq(&self, other: &Self) -> bool {
-        self.0.path.eq(&other.0.path)
-    }
-}
-
-impl PartialOrd<Self> for CmpMeta {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl Ord for CmpMeta {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.0.path.cmp(&other.0.path)
-    }
-}
-
 pub(crate) struct VersionSetInner<R>
 where
     R: Record,
@@ -54,6 +38,27 @@ where
     deleted_sst: Vec<(FileId, usize)>,
 }
 
+/// a version handed out to reads that tolerate staleness, see [`VersionSet::pinned`]
+struct PinnedVersion<R>
+where
+    R: Record,
+{
+    epoch: u64,
+    pinned_at: Instant,
+    version: VersionRef<R>,
+    ts: Timestamp,
+}
+
+/// keeps the WAL segments of flushed memtables on the fs until it is dropped, see
+/// [`VersionSet::hold_wal`]
+pub(crate) struct WalHold(Arc<AtomicUsize>);
+
+impl Drop for WalHold {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
 pub(crate) struct VersionSet<R>
 where
     R: Record,
@@ -63,6 +68,13 @@ where
     timestamp: Arc<AtomicU32>,
     option: Arc<DbOption>,
     manager: Arc<StoreManager>,
+    /// increased every time `current` is replaced
+    epoch: Arc<AtomicU64>,
+    pinned: Arc<Mutex<Option<PinnedVersion<R>>>>,
+    /// the number of [`WalHold`]s, the segments of `deleted_wal` are kept while there are any
+    wal_holds: Arc<AtomicUsize>,
+    /// the lease of [`DbOption::lease`], the version log is only written while it is held
+    lease: Option<Weak<Lease>>,
 }
 
 impl<R> Clone for VersionSet<R>
@@ -76,6 +88,10 @@ where
             timestamp: self.timestamp.clone(),
             option: self.option.clone(),
             manager: self.manager.clone(),
+            epoch: self.epoch.clone(),
+            pinned: self.pinned.clone(),
+            wal_holds: self.wal_holds.clone(),
+            lease: self.lease.clone(),
         }
     }
 }
@@ -97,57 +113,41 @@ impl<R> VersionSet<R>
 where
     R: Record,
 {
+    /// move the clock to `ts` if it is newer than every timestamp handed out
+    pub(crate) fn advance_ts(&self, ts: Timestamp) -> bool {
+        let ts = u32::from(ts);
+        self.timestamp
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                (current < ts).then_some(ts)
+            })
+            .is_ok()
+    }
+
+    /// the timestamp of a write recovered from the WAL, the logged `ts` is kept when it is newer
+    /// than the clock so that the timestamps given to `DB::insert_at` survive restarts
+    pub(crate) fn recover_ts(&self, ts: Timestamp) -> Timestamp {
+        if self.advance_ts(ts) {
+            ts
+        } else {
+            self.increase_ts()
+        }
+    }
+
     pub(crate) async fn new(
         clean_sender: Sender<CleanTag>,
         option: Arc<DbOption>,
         manager: Arc<StoreManager>,
     ) -> Result<Self, VersionError<R>> {
         let fs = manager.base_fs();
-        let version_dir = option.version_log_dir_path();
-        let mut log_stream = fs.list(&version_dir).await?;
-        let mut log_binary_heap = BinaryHeap::with_capacity(3);
-
-        // when there are multiple logs, this means that a downtime occurred during the
-        // `version_log_snap_shot` process, the second newest file has the highest data
-        // integrity, so it is used as the version log, and the older log is deleted first
-        // to avoid midway downtime, which will cause the second newest file to become the
-        // first newest after restart.
-        while let Some(result) = log_stream.next().await {
-            let file_meta = result?;
-
-            log_binary_heap.push(CmpMeta(file_meta));
-
-            if log_binary_heap.len() > 2 {
-                if let Some(old_meta) = log_binary_heap.pop() {
-                    fs.remove(&old_meta.0.path).await?;
-                }
-            }
-        }
-
-        let second_log_id = log_binary_heap.pop();
-        let latest_log_id = log_binary_heap.pop();
-
-        if let (Some(log_id), Some(_)) = (&latest_log_id, &second_log_id) {
-            fs.remove(&log_id.0.path).await?;
-        }
-
+        let recovered = Self::recover_log(fs, &option, !option.read_only).await?;
         let mut edits = vec![];
 
-        let log_id = match second_log_id
-            .or(latest_log_id)
-            .map(|file_meta| parse_file_id(&file_meta.0.path, FileType::Log))
-            .transpose()?
-            .flatten()
-        {
-            Some(log_id) => {
-                let recover_edits = VersionEdit::<<R::Schema as Schema>::Key>::recover(
-                    option.version_log_path(log_id),
-                    option.base_fs.clone(),
-                )
-                .await;
-                edits = recover_edits;
+        let log_id = match recovered {
+            Some((log_id, recovered_edits)) => {
+                edits = recovered_edits;
                 log_id
             }
+            None if option.read_only => generate_file_id(),
             None => {
                 let log_id = generate_file_id();
                 let base_fs = manager.base_fs();
@@ -158,7 +158,6 @@ where
         };
 
         let timestamp = Arc::new(AtomicU32::default());
-        drop(log_stream);
         let set = VersionSet::<R> {
             inner: Arc::new(RwLock::new(VersionSetInner {
                 current: Arc::new(Version::<R> {
@@ -177,16 +176,227 @@ where
             timestamp,
             option,
             manager,
+            epoch: Arc::new(AtomicU64::new(0)),
+            pinned: Arc::new(Mutex::new(None)),
+            wal_holds: Arc::new(AtomicUsize::new(0)),
+            lease: None,
         };
         set.apply_edits(edits, None, true).await?;
 
         Ok(set)
     }
 
+    /// fence the edits of the version log by `lease`, see [`DbOption::lease`]
+    pub(crate) fn with_lease(self, lease: Option<Weak<Lease>>) -> Self {
+        Self { lease, ..self }
+    }
+
+    /// Keep the WAL segments of flushed memtables from being removed while the returned hold
+    /// lives, e.g. while the segments of a cut are copied. They are removed by the first clean
+    /// after the last hold is dropped.
+    pub(crate) async fn hold_wal(&self) -> WalHold {
+        // a running clean has removed its segments once the lock is released
+        let _guard = self.inner.read().await;
+        self.wal_holds.fetch_add(1, Ordering::AcqRel);
+        WalHold(self.wal_holds.clone())
+    }
+
+    /// The version log to recover from and its edits. Every log starts with a snapshot of the
+    /// version (see [`VersionSet::rewrite`]) and every batch of edits, the snapshot included,
+    /// ends with a [`VersionEdit::NewLogLength`]. The newest log with a whole snapshot is
+    /// recovered from, up to its last whole batch: a newer log is a snapshot torn by a crash. Of
+    /// the logs fenced by a lease, the one of the largest [`VersionEdit::Fence`] is recovered
+    /// from, the newer ones are written by a holder which lost its lease.
+    /// With `remove_stale`, the other logs are removed, e.g. the log a crash kept from being
+    /// removed once its snapshot was written.
+    #[allow(clippy::type_complexity)]
+    async fn recover_log(
+        fs: &Arc<dyn DynFs>,
+        option: &DbOption,
+        remove_stale: bool,
+    ) -> Result<Option<(FileId, Vec<VersionEdit<<R::Schema as Schema>::Key>>)>, VersionError<R>>
+    {
+        let version_dir = option.version_log_dir_path();
+        let mut paths = Vec::new();
+        let mut log_stream = fs.list(&version_dir).await?;
+        while let Some(file_meta) = log_stream.next().await {
+            paths.push(file_meta?.path);
+        }
+        drop(log_stream);
+        // logs are named by their time ordered ids, the newest is read first
+        paths.sort();
+        paths.reverse();
+
+        let mut recovered = None;
+        for (i, path) in paths.iter().enumerate() {
+            let mut edits = VersionEdit::<<R::Schema as Schema>::Key>::try_recover(
+                path.clone(),
+                option.base_fs.clone(),
+            )
+            .await?;
+            let end = edits
+                .iter()
+                .rposition(|edit| matches!(edit, VersionEdit::NewLogLength { .. }));
+            // the edits of a torn batch are left out
+            edits.truncate(end.map_or(0, |end| end + 1));
+            let epoch = edits.iter().rev().find_map(|edit| match edit {
+                VersionEdit::Fence { epoch } => Some(*epoch),
+                _ => None,
+            });
+            // a new DB starts with an empty log, the only one
+            if (end.is_some() || (i + 1 == paths.len() && recovered.is_none()))
+                && recovered.as_ref().is_none_or(|(_, recovered_epoch, _)| {
+                    matches!((epoch, recovered_epoch), (Some(epoch), Some(recovered)) if epoch > *recovered)
+                })
+            {
+                recovered = Some((i, epoch, edits));
+            }
+        }
+        let Some((index, _, edits)) = recovered else {
+            return Ok(None);
+        };
+        if remove_stale {
+            for (i, path) in paths.iter().enumerate() {
+                if i != index {
+                    fs.remove(path).await?;
+                }
+            }
+        }
+
+        Ok(parse_file_id(&paths[index], FileType::Log)?.map(|log_id| (log_id, edits)))
+    }
+
+    /// Replace the current version of a read-only set with the one recovered from the version
+    /// log, which another process writes.
+    pub(crate) async fn refresh(&self) -> Result<(), VersionError<R>> {
+        let Some((log_id, edits)) =
+            Self::recover_log(self.manager.base_fs(), &self.option, false).await?
+        else {
+            return Ok(());
+        };
+
+        let mut version = Version::<R> {
+            ts: Timestamp::from(0),
+            level_slice: [const { Vec::new() }; MAX_LEVEL],
+            clean_sender: self.clean_sender.clone(),
+            option: self.option.clone(),
+            timestamp: self.timestamp.clone(),
+            log_length: 0,
+        };
+        for edit in edits {
+            match edit {
+                VersionEdit::Add { mut scope, level } => {
+                    scope.wal_ids = None;
+                    let sort_runs = &mut version.level_slice[level as usize];
+                    if level == 0 {
+                        sort_runs.push(scope);
+                    } else {
+                        let pos = sort_runs
+                            .binary_search_by(|s| s.min.cmp(&scope.min))
+                            .unwrap_or_else(|index| index);
+                        sort_runs.insert(pos, scope);
+                    }
+                }
+                VersionEdit::Remove { gen, level } => {
+                    version.level_slice[level as usize].retain(|scope| scope.gen != gen);
+                }
+                VersionEdit::LatestTimeStamp { ts } => version.ts = ts,
+                VersionEdit::NewLogLength { len } => version.log_length = len,
+                VersionEdit::Fence { .. } => {}
+            }
+        }
+
+        let mut guard = self.inner.write().await;
+        self.advance_ts(version.ts);
+        guard.log_id = log_id;
+        guard.current = Arc::new(version);
+        self.unpin();
+        Ok(())
+    }
+
+    /// The tables and the WAL segments the set refers to: the tables of the current version and
+    /// the ones removed from it that older versions still read, the WAL segments waiting to be
+    /// archived or removed.
+    pub(crate) async fn referenced(
+        &self,
+    ) -> Result<(HashSet<FileId>, HashSet<FileId>), VersionError<R>> {
+        let guard = self.inner.read().await;
+        let mut tables = guard
+            .current
+            .level_slice
+            .iter()
+            .flatten()
+            .map(|scope| scope.gen)
+            .chain(guard.deleted_sst.iter().map(|(gen, _)| *gen))
+            .collect::<HashSet<_>>();
+        // the cleaner is asked while the set is locked, every table removed from a version is
+        // either in `deleted_sst` or known to the cleaner
+        let (tx, rx) = flume::bounded(1);
+        self.clean_sender
+            .send_async(CleanTag::Retained { tx })
+            .await
+            .map_err(VersionError::Send)?;
+        tables.extend(
+            rx.recv_async()
+                .await
+                .map_err(|_| VersionError::CleanerClosed)?,
+        );
+        Ok((tables, guard.deleted_wal.iter().copied().collect()))
+    }
+
+    /// ask the cleaner to remove the table `gen` of `level`, which is in no version, without
+    /// waiting. Returns whether it was asked, it is not once its channel is full
+    pub(crate) fn try_clean(&self, gen: FileId, level: usize) -> bool {
+        self.clean_sender
+            .try_send(CleanTag::RecoverClean { wal_id: gen, level })
+            .is_ok()
+    }
+
     pub(crate) async fn current(&self) -> VersionRef<R> {
         self.inner.read().await.current.clone()
     }
 
+    /// Returns the pinned version and the read timestamp taken with it, as long as it was pinned
+    /// no longer than `max_staleness` ago and is still the current version. Otherwise the current
+    /// version is pinned at the latest timestamp.
+    pub(crate) async fn pinned(&self, max_staleness: Duration) -> (VersionRef<R>, Timestamp) {
+        // `Instant` is not available on wasm32-unknown-unknown
+        if cfg!(target_arch = "wasm32") {
+            let version = self.current().await;
+            let ts = version.load_ts();
+            return (version, ts);
+        }
+        {
+            let pinned = self.pinned.lock().unwrap();
+            if let Some(pinned) = pinned.as_ref() {
+                if pinned.epoch == self.epoch.load(Ordering::Acquire)
+                    && pinned.pinned_at.elapsed() <= max_staleness
+                {
+                    return (pinned.version.clone(), pinned.ts);
+                }
+            }
+        }
+        // load the epoch first, a version replaced in between only leaves an outdated pin behind
+        let epoch = self.epoch.load(Ordering::Acquire);
+        let version = self.current().await;
+        let ts = version.load_ts();
+        *self.pinned.lock().unwrap() = Some(PinnedVersion {
+            epoch,
+            pinned_at: Instant::now(),
+            version: version.clone(),
+            ts,
+        });
+
+        (version, ts)
+    }
+
+    /// must be called with the write guard of `inner` held, right after `current` is replaced
+    fn unpin(&self) {
+        self.epoch.fetch_add(1, Ordering::Release);
+        // release the outdated version so its files can be cleaned
+        let _ = self.pinned.lock().unwrap().take();
+    }
+
     pub(crate) async fn apply_edits(
         &self,
         mut version_edits: Vec<VersionEdit<<R::Schema as Schema>::Key>>,
@@ -195,16 +405,34 @@ where
     ) -> Result<(), VersionError<R>> {
         let timestamp = &self.timestamp;
         let option = &self.option;
+        if option.read_only && !is_recover {
+            return Err(VersionError::ReadOnly);
+        }
         let mut guard = self.inner.write().await;
+        // a writer which lost its lease must not clobber the version history of the new owner:
+        // the lease is checked under the lock, and the batch is fenced by its epoch for a lease
+        // lost before the batch is written
+        if !is_recover {
+            version_edits.extend(self.fence()?);
+        }
         let mut new_version = Version::clone(&guard.current);
         let log_id = &mut guard.log_id;
         let edit_len = new_version.log_length + version_edits.len() as u32;
 
-        let mut log =
-            Self::open_version_log(&self.option, self.manager.local_fs().clone(), *log_id).await?;
+        // the edits recovered by a read-only set are only applied in memory
+        let mut log = if option.read_only {
+            None
+        } else {
+            Some(
+                Self::open_version_log(&self.option, self.manager.local_fs().clone(), *log_id)
+                    .await?,
+            )
+        };
 
-        if !is_recover {
+        if let (false, Some(log)) = (is_recover, log.as_mut()) {
             version_edits.push(VersionEdit::NewLogLength { len: edit_len });
+            // not retried: the batch is appended to the log and a torn attempt would hide the
+            // batches after it from recovery
             log.write_batch(version_edits.iter())
                 .await
                 .map_err(VersionError::Logger)?;
@@ -249,42 +477,68 @@ where
                 VersionEdit::NewLogLength { len } => {
                     new_version.log_length = len;
                 }
+                VersionEdit::Fence { .. } => {}
             }
         }
         if let Some(delete_gens) = delete_gens {
             guard.deleted_sst.extend(delete_gens);
         }
-        log.close().await?;
+        if let Some(log) = log {
+            log.close().await?;
+        }
 
         guard.current = Arc::new(new_version);
+        self.unpin();
 
         drop(guard);
-        if edit_len >= option.version_log_snapshot_threshold {
+        if !option.read_only && edit_len >= option.version_log_snapshot_threshold {
             self.rewrite().await?;
             self.clean().await?;
         }
         Ok(())
     }
 
+    /// the [`VersionEdit::Fence`] of the lease the edits are written under, if the set has one.
+    /// Fails once the lease is lost.
+    fn fence(&self) -> Result<Option<VersionEdit<<R::Schema as Schema>::Key>>, VersionError<R>> {
+        let Some(lease) = &self.lease else {
+            return Ok(None);
+        };
+        match lease.upgrade() {
+            Some(lease) if lease.is_held() => Ok(Some(VersionEdit::Fence {
+                epoch: lease.epoch(),
+            })),
+            _ => Err(VersionError::LeaseLost),
+        }
+    }
+
     pub(crate) async fn rewrite(&self) -> Result<(), VersionError<R>> {
         let mut guard = self.inner.write().await;
         let mut new_version = Version::clone(&guard.current);
         let fs = self.manager.local_fs();
-        let log_id = &mut guard.log_id;
-        let old_log_id = mem::replace(log_id, generate_file_id());
+        let log_id = generate_file_id();
 
         new_version.log_length = 0;
-        let edits = new_version.to_edits();
-        let mut log = Self::open_version_log(&self.option, fs.clone(), *log_id).await?;
-        log.write_batch(edits.iter())
-            .await
-            .map_err(VersionError::Logger)?;
-        log.close().await?;
-
-        fs.remove(&self.option.version_log_path(old_log_id)).await?;
-        self.sync(*log_id, old_log_id, edits.iter()).await?;
-
+        let mut edits = new_version.to_edits();
+        // the snapshot ends with its length
+        if let Some(fence) = self.fence()? {
+            edits.insert(edits.len() - 1, fence);
+        }
+        // the edits keep going to the old log until the snapshot is written whole, a crash in
+        // between recovers from the old log, see `recover_log`
+        Self::write_version_log(&self.option, fs.clone(), log_id, &edits).await?;
+        let old_log_id = mem::replace(&mut guard.log_id, log_id);
         guard.current = Arc::new(new_version);
+        self.unpin();
+
+        // a stale log left behind is removed once the DB is opened again
+        if let Err(err) = fs.remove(&self.option.version_log_path(old_log_id)).await {
+            error!(
+                "[Version Log Error]: failed to remove log {}: {}",
+                old_log_id, err
+            );
+        }
+        self.sync(log_id, old_log_id, &edits).await?;
 
         Ok(())
     }
@@ -292,16 +546,22 @@ where
     async fn clean(&self) -> Result<(), VersionError<R>> {
         let mut guard = self.inner.write().await;
         let version = Version::clone(&guard.current);
-        if !guard.deleted_wal.is_empty() {
-            for wal_id in guard.deleted_wal.iter() {
+        if !guard.deleted_wal.is_empty() && self.wal_holds.load(Ordering::Acquire) == 0 {
+            let fs = self.manager.wal_fs();
+            let mut unarchived = Vec::new();
+            for wal_id in guard.deleted_wal.drain(..) {
+                let wal_path = self.option.wal_segment_path(wal_id);
+                if let Some(archiver) = &self.option.wal_archiver {
+                    if let Err(err) = archiver.archive(wal_id, &wal_path, fs).await {
+                        error!("[WAL Archive Error]: {}", err);
+                        unarchived.push(wal_id);
+                        continue;
+                    }
+                }
                 // may have been removed after multiple starts
-                let _ = self
-                    .manager
-                    .base_fs()
-                    .remove(&self.option.wal_path(*wal_id))
-                    .await;
+                let _ = fs.remove(&wal_path).await;
             }
-            guard.deleted_wal.clear();
+            guard.deleted_wal = unarchived;
         }
         if !guard.deleted_sst.is_empty() {
             version
@@ -316,22 +576,20 @@ where
         }
 
         guard.current = Arc::new(version);
+        self.unpin();
         Ok(())
     }
 
-    async fn sync<'r>(
+    async fn sync(
         &self,
         log_id: FileId,
         old_log_id: FileId,
-        edits: impl ExactSizeIterator<Item = &'r VersionEdit<<R::Schema as Schema>::Key>>,
+        edits: &[VersionEdit<<R::Schema as Schema>::Key>],
     ) -> Result<(), VersionError<R>> {
         if self.manager.base_fs().file_system() != self.manager.local_fs().file_system() {
             // push local manifest to base file system
             let base_fs = self.manager.base_fs();
-            let mut log = Self::open_version_log(&self.option, base_fs.clone(), log_id).await?;
-
-            log.write_batch(edits).await?;
-            log.close().await?;
+            Self::write_version_log(&self.option, base_fs.clone(), log_id, edits).await?;
             base_fs
                 .remove(&self.option.version_log_path(old_log_id))
                 .await?;
@@ -340,16 +598,83 @@ where
         Ok(())
     }
 
+    /// Replace the version logs of the DB of `option` with a log of `edits`, when they are lost
+    /// or corrupted, see [`DB::repair`](crate::DB::repair).
+    pub(crate) async fn reset(
+        option: &DbOption,
+        manager: &StoreManager,
+        edits: &[VersionEdit<<R::Schema as Schema>::Key>],
+    ) -> Result<(), VersionError<R>> {
+        let version_dir = option.version_log_dir_path();
+        let mut fss = vec![manager.base_fs()];
+        if manager.base_fs().file_system() != manager.local_fs().file_system() {
+            fss.push(manager.local_fs());
+        }
+        let log_id = generate_file_id();
+        for fs in fss {
+            fs.create_dir_all(&version_dir).await?;
+            // the stale logs are removed first, a crash in between leaves no log to recover from
+            // rather than the stale one
+            let mut stale = Vec::new();
+            let mut log_stream = fs.list(&version_dir).await?;
+            while let Some(file_meta) = log_stream.next().await {
+                stale.push(file_meta?.path);
+            }
+            drop(log_stream);
+            for path in stale {
+                fs.remove(&path).await?;
+            }
+
+            Self::write_version_log(option, fs.clone(), log_id, edits).await?;
+        }
+        Ok(())
+    }
+
     async fn open_version_log(
         option: &DbOption,
         fs: Arc<dyn DynFs>,
         gen: FileId,
     ) -> Result<Logger<VersionEdit<<R::Schema as Schema>::Key>>, VersionError<R>> {
-        Options::new(option.version_log_path(gen))
-            .truncate(true)
-            .build_with_fs(fs)
-            .await
-            .map_err(VersionError::Logger)
+        retry_write(option.retry_policy.as_ref(), || {
+            Options::new(option.version_log_path(gen))
+                .truncate(true)
+                .build_with_fs(fs.clone())
+        })
+        .await
+        .map_err(VersionError::Logger)
+    }
+
+    /// Write the new log `gen` of `edits` whole, again from the start on transient errors as
+    /// [`DbOption::retry_policy`] tells.
+    async fn write_version_log(
+        option: &DbOption,
+        fs: Arc<dyn DynFs>,
+        gen: FileId,
+        edits: &[VersionEdit<<R::Schema as Schema>::Key>],
+    ) -> Result<(), VersionError<R>> {
+        let path = option.version_log_path(gen);
+        let mut is_retried = false;
+        retry_write(option.retry_policy.as_ref(), || {
+            let is_retry = mem::replace(&mut is_retried, true);
+            let (path, fs) = (path.clone(), fs.clone());
+            async move {
+                if is_retry {
+                    // a failed attempt may have left a part of the log
+                    let _ = fs.remove(&path).await;
+                }
+                let mut log = Options::new(path)
+                    .truncate(true)
+                    .build_with_fs(fs)
+                    .await
+                    .map_err(VersionError::Logger)?;
+                log.write_batch(edits.iter())
+                    .await
+                    .map_err(VersionError::Logger)?;
+                log.close().await?;
+                Ok(())
+            }
+        })
+        .await
     }
 
     pub(crate) async fn destroy(self) -> Result<(), VersionError<R>> {
@@ -372,12 +697,17 @@ where
             let mut stream = fs.list(level_path).await?;
             while let Ok(meta) = stream.next().await.transpose() {
                 match meta {
-                    Some(meta) => {
-                        let path = std::path::Path::new(meta.path.as_ref());
-                        if path.is_file() {
-                            log_fs.remove(&meta.path).await?;
-                        }
+                    // the directories of the WAL and of the version log may share the path of a
+                    // level. Tables are told apart by their name, fusio paths can not be checked
+                    // with `std::path`, e.g. on Windows.
+                    Some(meta)
+                        if meta.path.filename().is_some_and(|name| {
+                            name.ends_with(&format!(".{}", FileType::Parquet))
+                        }) =>
+                    {
+                        fs.remove(&meta.path).await?
                     }
+                    Some(_) => (),
                     None => break,
                 }
             }
@@ -389,11 +719,14 @@ where
 
 #[cfg(all(test, feature = "tokio"))]
 pub(crate) mod tests {
-    use std::sync::Arc;
+    use std::sync::{
+        atomic::{AtomicU64, AtomicUsize},
+        Arc, Mutex,
+    };
 
     use async_lock::RwLock;
     use flume::{bounded, Sender};
-    use fusio::path::Path;
+    use fusio::path::{path_to_local, Path};
     use fusio_dispatch::FsOptions;
     use futures_util::StreamExt;
     use tempfile::TempDir;
@@ -435,6 +768,10 @@ pub(crate) mod tests {
             timestamp,
             option,
             manager,
+            epoch: Arc::new(AtomicU64::new(0)),
+            pinned: Arc::new(Mutex::new(None)),
+            wal_holds: Arc::new(AtomicUsize::new(0)),
+            lease: None,
         })
     }
 
@@ -512,6 +849,7 @@ pub(crate) mod tests {
                             max: "1".to_string(),
                             gen: gen_0,
                             wal_ids: None,
+                            stats: None,
                         },
                     },
                     VersionEdit::Add {
@@ -521,6 +859,7 @@ pub(crate) mod tests {
                             max: "3".to_string(),
                             gen: gen_1,
                             wal_ids: None,
+                            stats: None,
                         },
                     },
                     VersionEdit::Add {
@@ -530,6 +869,7 @@ pub(crate) mod tests {
                             max: "5".to_string(),
                             gen: gen_2,
                             wal_ids: None,
+                            stats: None,
                         },
                     },
                     VersionEdit::Remove {
@@ -566,6 +906,7 @@ pub(crate) mod tests {
                         max: "3".to_string(),
                         gen: gen_1,
                         wal_ids: None,
+                        stats: None,
                     },
                 },
                 VersionEdit::LatestTimeStamp { ts: 0.into() },
@@ -598,6 +939,7 @@ pub(crate) mod tests {
                         max: "3".to_string(),
                         gen: gen_1,
                         wal_ids: None,
+                        stats: None,
                     },
                 },
                 VersionEdit::LatestTimeStamp { ts: 0.into() },
@@ -657,6 +999,223 @@ pub(crate) mod tests {
         .await;
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn recover_newest_complete_log() {
+        async fn write_log(
+            option: &DbOption,
+            manager: &StoreManager,
+            edits: &[VersionEdit<String>],
+        ) -> Path {
+            let log_id = generate_file_id();
+            let mut log =
+                VersionSet::<String>::open_version_log(option, manager.base_fs().clone(), log_id)
+                    .await
+                    .unwrap();
+            log.write_batch(edits.iter()).await.unwrap();
+            log.close().await.unwrap();
+            option.version_log_path(log_id)
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(StoreManager::new(FsOptions::Local, vec![]).unwrap());
+        let (sender, _) = bounded(1);
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &StringSchema,
+        ));
+        manager
+            .base_fs()
+            .create_dir_all(&option.version_log_dir_path())
+            .await
+            .unwrap();
+        let add = |key: &str| VersionEdit::Add {
+            level: 0,
+            scope: Scope {
+                min: key.to_string(),
+                max: key.to_string(),
+                gen: generate_file_id(),
+                wal_ids: None,
+                stats: None,
+            },
+        };
+
+        // a crash kept the old log from being removed once the snapshot was written, then tore
+        // the next snapshot
+        let old_log = write_log(&option, &manager, &[add("a")]).await;
+        let snapshot = write_log(
+            &option,
+            &manager,
+            &[
+                add("a"),
+                add("b"),
+                VersionEdit::LatestTimeStamp { ts: 0.into() },
+                VersionEdit::NewLogLength { len: 0 },
+            ],
+        )
+        .await;
+        let torn_log = option.version_log_path(generate_file_id());
+        std::fs::write(path_to_local(&torn_log).unwrap(), b"torn").unwrap();
+
+        let version_set: VersionSet<String> =
+            VersionSet::new(sender, option.clone(), manager.clone())
+                .await
+                .unwrap();
+        assert_eq!(version_set.current().await.level_slice[0].len(), 2);
+
+        let mut stream = manager
+            .base_fs()
+            .list(&option.version_log_dir_path())
+            .await
+            .unwrap();
+        let mut logs = Vec::new();
+        while let Some(log) = stream.next().await {
+            logs.push(log.unwrap().path);
+        }
+        assert_eq!(logs, vec![snapshot]);
+        assert!(!path_to_local(&old_log).unwrap().exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn recover_skips_fenced_batches() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(StoreManager::new(FsOptions::Local, vec![]).unwrap());
+        let (sender, _) = bounded(1);
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &StringSchema,
+        ));
+        manager
+            .base_fs()
+            .create_dir_all(&option.version_log_dir_path())
+            .await
+            .unwrap();
+        let add = |key: &str| VersionEdit::Add {
+            level: 0,
+            scope: Scope {
+                min: key.to_string(),
+                max: key.to_string(),
+                gen: generate_file_id(),
+                wal_ids: None,
+                stats: None,
+            },
+        };
+
+        let mut log = VersionSet::<String>::open_version_log(
+            &option,
+            manager.base_fs().clone(),
+            generate_file_id(),
+        )
+        .await
+        .unwrap();
+        for (key, epoch) in [("a", 1), ("b", 2), ("c", 1), ("d", 2)] {
+            log.write_batch(
+                [
+                    add(key),
+                    VersionEdit::Fence { epoch },
+                    VersionEdit::NewLogLength { len: 0 },
+                ]
+                .iter(),
+            )
+            .await
+            .unwrap();
+        }
+        log.close().await.unwrap();
+        // the snapshot of the holder of the epoch 1, written after it lost the lease
+        let stale_id = generate_file_id();
+        let mut log =
+            VersionSet::<String>::open_version_log(&option, manager.base_fs().clone(), stale_id)
+                .await
+                .unwrap();
+        log.write_batch(
+            [
+                add("a"),
+                add("c"),
+                VersionEdit::LatestTimeStamp { ts: 0.into() },
+                VersionEdit::Fence { epoch: 1 },
+                VersionEdit::NewLogLength { len: 0 },
+            ]
+            .iter(),
+        )
+        .await
+        .unwrap();
+        log.close().await.unwrap();
+
+        let version_set: VersionSet<String> =
+            VersionSet::new(sender, option.clone(), manager.clone())
+                .await
+                .unwrap();
+        let keys = version_set.current().await.level_slice[0]
+            .iter()
+            .map(|scope| scope.min.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["a", "b", "d"]);
+        assert!(!path_to_local(&option.version_log_path(stale_id))
+            .unwrap()
+            .exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn recover_skips_torn_snapshot_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(StoreManager::new(FsOptions::Local, vec![]).unwrap());
+        let (sender, _) = bounded(1);
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &StringSchema,
+        ));
+        manager
+            .base_fs()
+            .create_dir_all(&option.version_log_dir_path())
+            .await
+            .unwrap();
+        let add = |key: &str| VersionEdit::Add {
+            level: 0,
+            scope: Scope {
+                min: key.to_string(),
+                max: key.to_string(),
+                gen: generate_file_id(),
+                wal_ids: None,
+                stats: None,
+            },
+        };
+
+        let mut log = VersionSet::<String>::open_version_log(
+            &option,
+            manager.base_fs().clone(),
+            generate_file_id(),
+        )
+        .await
+        .unwrap();
+        log.write_batch(
+            [
+                add("a"),
+                VersionEdit::LatestTimeStamp { ts: 0.into() },
+                VersionEdit::NewLogLength { len: 0 },
+            ]
+            .iter(),
+        )
+        .await
+        .unwrap();
+        log.close().await.unwrap();
+        // the next snapshot is torn after its first edits, which are read whole
+        let torn_id = generate_file_id();
+        let mut log =
+            VersionSet::<String>::open_version_log(&option, manager.base_fs().clone(), torn_id)
+                .await
+                .unwrap();
+        log.write_batch([add("a"), add("b")].iter()).await.unwrap();
+        log.close().await.unwrap();
+
+        let version_set: VersionSet<String> =
+            VersionSet::new(sender, option.clone(), manager.clone())
+                .await
+                .unwrap();
+        assert_eq!(version_set.current().await.level_slice[0].len(), 1);
+        assert!(!path_to_local(&option.version_log_path(torn_id))
+            .unwrap()
+            .exists());
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn version_level_sort() {
         let temp_dir = TempDir::new().unwrap();
@@ -690,6 +1249,7 @@ pub(crate) mod tests {
                         max: "6".to_string(),
                         gen: gen_0,
                         wal_ids: None,
+                        stats: None,
                     },
                 }],
                 None,
@@ -707,6 +1267,7 @@ pub(crate) mod tests {
                             max: "3".to_string(),
                             gen: gen_1,
                             wal_ids: None,
+                            stats: None,
                         },
                     },
                     VersionEdit::Add {
@@ -716,6 +1277,7 @@ pub(crate) mod tests {
                             max: "9".to_string(),
                             gen: gen_2,
                             wal_ids: None,
+                            stats: None,
                         },
                     },
                     VersionEdit::Add {
@@ -725,6 +1287,7 @@ pub(crate) mod tests {
                             max: "0".to_string(),
                             gen: gen_3,
                             wal_ids: None,
+                            stats: None,
                         },
                     },
                 ],
@@ -0,0 +1,118 @@
+use std::io;
+
+use crate::WalCompression;
+
+/// tags starting the compressed encodings of logs, after the tags of the
+/// [`LogType`](crate::wal::log::LogType)s starting the encodings of plain logs
+const LZ4: u8 = 16;
+const ZSTD: u8 = 17;
+
+/// compress the encoding of a log, which is kept as it is when it does not shrink
+pub(crate) fn compress(compression: WalCompression, bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+    let (tag, compressed) = match compression {
+        WalCompression::Lz4 => (LZ4, lz4_flex::compress_prepend_size(&bytes)),
+        WalCompression::Zstd(level) => (ZSTD, zstd::stream::encode_all(&bytes[..], level)?),
+    };
+    if compressed.len() + 1 >= bytes.len() {
+        return Ok(bytes);
+    }
+    let mut tagged = Vec::with_capacity(compressed.len() + 1);
+    tagged.push(tag);
+    tagged.extend_from_slice(&compressed);
+    Ok(tagged)
+}
+
+/// the encoding of a log from [`compress`], whatever the compression of the WAL
+pub(crate) fn decompress(bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+    match bytes.first() {
+        Some(&LZ4) => lz4_flex::decompress_size_prepended(&bytes[1..])
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+        Some(&ZSTD) => zstd::stream::decode_all(&bytes[1..]),
+        _ => Ok(bytes),
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{io::Cursor, pin::pin, sync::Arc};
+
+    use fusio::disk::LocalFs;
+    use fusio_log::{Encode, FsOptions, Path};
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+
+    use super::{compress, decompress};
+    use crate::{
+        fs::{generate_file_id, FileType},
+        timestamp::Ts,
+        wal::{
+            log::{Log, LogType},
+            WalFile,
+        },
+        WalCompression,
+    };
+
+    #[tokio::test]
+    async fn compress_logs() {
+        let log = Log::<String>::new(
+            Ts::new("key".into(), 0.into()),
+            Some("value".repeat(100)),
+            Some(LogType::Full),
+        );
+        let mut bytes = Vec::new();
+        log.encode(&mut Cursor::new(&mut bytes)).await.unwrap();
+
+        for compression in [WalCompression::Lz4, WalCompression::Zstd(3)] {
+            let compressed = compress(compression, bytes.clone()).unwrap();
+            assert!(compressed.len() < bytes.len());
+            assert_eq!(decompress(compressed).unwrap(), bytes);
+        }
+        // logs that don't shrink are kept as they are
+        let short = vec![LogType::Full as u8, 1, 2];
+        assert_eq!(compress(WalCompression::Lz4, short.clone()).unwrap(), short);
+        assert_eq!(decompress(short.clone()).unwrap(), short);
+    }
+
+    #[tokio::test]
+    async fn recover_compressed() {
+        let temp_dir = TempDir::new().unwrap();
+        let wal_id = generate_file_id();
+        let wal_path = Path::from_filesystem_path(temp_dir.path())
+            .unwrap()
+            .child(format!("{}.{}", wal_id, FileType::Wal));
+        let mut wal =
+            WalFile::<String>::new(Arc::new(LocalFs {}), wal_path.clone(), 0, wal_id, None)
+                .await
+                .with_compression(Some(WalCompression::Zstd(3)));
+        for (i, value) in ["value".repeat(100), "short".to_string()]
+            .into_iter()
+            .enumerate()
+        {
+            wal.write(&Log::new(
+                Ts::new(i.to_string(), (i as u32).into()),
+                Some(value),
+                Some(LogType::Full),
+            ))
+            .await
+            .unwrap();
+        }
+        wal.flush().await.unwrap();
+        assert!(
+            std::fs::metadata(
+                temp_dir
+                    .path()
+                    .join(format!("{}.{}", wal_id, FileType::Wal))
+            )
+            .unwrap()
+            .len()
+                < 500
+        );
+
+        let mut stream = pin!(WalFile::<String>::recover(FsOptions::Local, wal_path, None).await);
+        let mut values = Vec::new();
+        while let Some(logs) = stream.next().await {
+            values.extend(logs.unwrap().into_iter().map(|log| log.value.unwrap()));
+        }
+        assert_eq!(values, vec!["value".repeat(100), "short".to_string()]);
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use async_lock::Mutex;
+use fusio_log::error::LogError;
+use futures_channel::oneshot;
+
+use crate::{executor::sleep, record::Record, wal::WalFile};
+
+type Waiter = oneshot::Sender<Result<(), Arc<LogError>>>;
+
+/// Commits waiting for their logs to be written to the WAL segment, see
+/// [`DbOption::wal_group_commit`](crate::DbOption::wal_group_commit).
+///
+/// The first commit of a group leads it: it waits up to the delay for the next commits to join,
+/// then writes the logs of the whole group to the segment at once and wakes the group up.
+pub(crate) struct GroupCommit {
+    max_delay: Duration,
+    group: StdMutex<Option<Vec<Waiter>>>,
+}
+
+impl GroupCommit {
+    pub(crate) fn new(max_delay: Duration) -> Self {
+        Self {
+            max_delay,
+            group: StdMutex::new(None),
+        }
+    }
+
+    /// wait for the logs written to `wal` before the call to be written to its segment
+    pub(crate) async fn commit<R: Record>(
+        &self,
+        wal: &Mutex<WalFile<R>>,
+    ) -> Result<(), Arc<LogError>> {
+        loop {
+            let (tx, rx) = oneshot::channel();
+            let leader = {
+                let mut group = self.group.lock().unwrap();
+                match group.as_mut() {
+                    Some(waiters) => {
+                        waiters.push(tx);
+                        None
+                    }
+                    None => {
+                        *group = Some(vec![tx]);
+                        Some(Disband(Some(&self.group)))
+                    }
+                }
+            };
+            if let Some(leader) = leader {
+                sleep(self.max_delay).await;
+                let waiters = leader.close();
+                let result = wal.lock().await.sync().await.map_err(Arc::new);
+                for waiter in waiters {
+                    let _ = waiter.send(result.clone());
+                }
+            }
+            match rx.await {
+                Ok(result) => return result,
+                // the leader was dropped before writing the logs of the group
+                Err(_) => continue,
+            }
+        }
+    }
+}
+
+/// disbands the group of a leader dropped while it waits for the group to fill up
+struct Disband<'g>(Option<&'g StdMutex<Option<Vec<Waiter>>>>);
+
+impl Disband<'_> {
+    fn close(mut self) -> Vec<Waiter> {
+        self.0
+            .take()
+            .and_then(|group| group.lock().unwrap().take())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for Disband<'_> {
+    fn drop(&mut self) {
+        if let Some(group) = self.0 {
+            group.lock().unwrap().take();
+        }
+    }
+}
@@ -4,6 +4,7 @@ use fusio_log::{Decode, Encode};
 use crate::{
     record::{Record, Schema},
     timestamp::Ts,
+    wal::NONCE_LEN,
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -15,14 +16,18 @@ pub enum LogType {
     Last,
 }
 
-impl From<u8> for LogType {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for LogType {
+    type Error = fusio::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            0 => Self::Full,
-            1 => Self::First,
-            2 => Self::Middle,
-            3 => Self::Last,
-            _ => unreachable!(),
+            0 => Ok(Self::Full),
+            1 => Ok(Self::First),
+            2 => Ok(Self::Middle),
+            3 => Ok(Self::Last),
+            tag => Err(fusio::Error::Other(
+                format!("invalid tag {tag} of wal log").into(),
+            )),
         }
     }
 }
@@ -83,6 +88,26 @@ where
     }
 }
 
+impl<Re> Log<Re>
+where
+    Re: Record,
+{
+    /// decode the log after its type
+    async fn decode_body<R>(log_type: LogType, reader: &mut R) -> Result<Self, fusio::Error>
+    where
+        R: SeqRead,
+    {
+        let key = Ts::<<Re::Schema as Schema>::Key>::decode(reader)
+            .await
+            .map_err(|err| fusio::Error::Other(Box::new(err)))?;
+        let record = Option::<Re>::decode(reader)
+            .await
+            .map_err(|err| fusio::Error::Other(Box::new(err)))?;
+
+        Ok(Log::new(key, record, Some(log_type)))
+    }
+}
+
 impl<Re> Decode for Log<Re>
 where
     Re: Record,
@@ -93,13 +118,176 @@ where
     where
         R: SeqRead,
     {
-        let log_type = LogType::from(u8::decode(reader).await?);
-        let key = Ts::<<Re::Schema as Schema>::Key>::decode(reader)
-            .await
-            .unwrap();
-        let record = Option::<Re>::decode(reader).await.unwrap();
+        let log_type = LogType::try_from(u8::decode(reader).await?)?;
+        Self::decode_body(log_type, reader).await
+    }
+}
 
-        Ok(Log::new(key, record, Some(log_type)))
+/// tag of [`SealedLog`]s, after the tags of the [`LogType`]s starting plain [`Log`]s
+const SEALED: u8 = 4;
+
+/// A [`Log`] encrypted with the key `key_id` of the
+/// [`KeyProvider`](crate::encryption::KeyProvider) of the WAL.
+pub(crate) struct SealedLog {
+    pub(crate) key_id: Vec<u8>,
+    pub(crate) nonce: [u8; NONCE_LEN],
+    pub(crate) ciphertext: Vec<u8>,
+}
+
+impl Encode for SealedLog {
+    type Error = fusio::Error;
+
+    async fn encode<W>(&self, writer: &mut W) -> Result<(), Self::Error>
+    where
+        W: Write,
+    {
+        SEALED.encode(writer).await?;
+        for bytes in [&self.key_id[..], &self.nonce[..], &self.ciphertext[..]] {
+            write_bytes(writer, bytes).await?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        size_of::<u8>()
+            + 3 * size_of::<u32>()
+            + self.key_id.len()
+            + self.nonce.len()
+            + self.ciphertext.len()
+    }
+}
+
+/// tag of [`CheckedLog`]s
+const CHECKED: u8 = 5;
+
+/// The encoding of a [`Log`] with its CRC32 checksum, which is verified when the WAL is
+/// recovered. Sealed logs are authenticated by their encryption instead.
+pub(crate) struct CheckedLog {
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) checksum: u32,
+}
+
+impl CheckedLog {
+    pub(crate) fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            checksum: crc32fast::hash(&bytes),
+            bytes,
+        }
+    }
+
+    /// the encoding of the log, `None` when it does not match its checksum
+    pub(crate) fn verify(self) -> Option<Vec<u8>> {
+        (crc32fast::hash(&self.bytes) == self.checksum).then_some(self.bytes)
+    }
+}
+
+impl Encode for CheckedLog {
+    type Error = fusio::Error;
+
+    async fn encode<W>(&self, writer: &mut W) -> Result<(), Self::Error>
+    where
+        W: Write,
+    {
+        CHECKED.encode(writer).await?;
+        write_bytes(writer, &self.bytes).await?;
+        self.checksum.encode(writer).await
+    }
+
+    fn size(&self) -> usize {
+        size_of::<u8>() + 2 * size_of::<u32>() + self.bytes.len()
+    }
+}
+
+async fn write_bytes<W>(writer: &mut W, bytes: &[u8]) -> Result<(), fusio::Error>
+where
+    W: Write,
+{
+    (bytes.len() as u32).encode(writer).await?;
+    let (result, _) = writer.write_all(bytes).await;
+    result
+}
+
+async fn read_bytes<R>(reader: &mut R) -> Result<Vec<u8>, fusio::Error>
+where
+    R: SeqRead,
+{
+    let mut bytes = vec![0; u32::decode(reader).await? as usize];
+    let (result, _) = reader.read_exact(bytes.as_mut_slice()).await;
+    result?;
+    Ok(bytes)
+}
+
+/// A record of a WAL segment: a checked log, or a sealed one when the WAL is encrypted. Plain
+/// logs are the records of the segments written before logs were checked.
+pub(crate) enum WalRecord<R>
+where
+    R: Record,
+{
+    Plain(Log<R>),
+    Checked(CheckedLog),
+    Sealed(SealedLog),
+}
+
+impl<R> Encode for WalRecord<R>
+where
+    R: Record,
+{
+    type Error = fusio::Error;
+
+    async fn encode<W>(&self, writer: &mut W) -> Result<(), Self::Error>
+    where
+        W: Write,
+    {
+        match self {
+            WalRecord::Plain(log) => log.encode(writer).await,
+            WalRecord::Checked(checked) => checked.encode(writer).await,
+            WalRecord::Sealed(sealed) => sealed.encode(writer).await,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            WalRecord::Plain(log) => log.size(),
+            WalRecord::Checked(checked) => checked.size(),
+            WalRecord::Sealed(sealed) => sealed.size(),
+        }
+    }
+}
+
+impl<Re> Decode for WalRecord<Re>
+where
+    Re: Record,
+{
+    type Error = fusio::Error;
+
+    async fn decode<R>(reader: &mut R) -> Result<Self, Self::Error>
+    where
+        R: SeqRead,
+    {
+        match u8::decode(reader).await? {
+            CHECKED => {
+                let bytes = read_bytes(reader).await?;
+                let checksum = u32::decode(reader).await?;
+                Ok(WalRecord::Checked(CheckedLog { bytes, checksum }))
+            }
+            SEALED => {
+                let key_id = read_bytes(reader).await?;
+                let nonce = read_bytes(reader)
+                    .await?
+                    .try_into()
+                    .map_err(|_| fusio::Error::Other("invalid nonce of sealed log".into()))?;
+                let ciphertext = read_bytes(reader).await?;
+
+                Ok(WalRecord::Sealed(SealedLog {
+                    key_id,
+                    nonce,
+                    ciphertext,
+                }))
+            }
+            tag => Ok(WalRecord::Plain(
+                Log::decode_body(LogType::try_from(tag)?, reader).await?,
+            )),
+        }
     }
 }
 
@@ -112,7 +300,7 @@ mod tests {
 
     use crate::{
         timestamp::Ts,
-        wal::log::{Log, LogType},
+        wal::log::{CheckedLog, Log, LogType, WalRecord},
     };
 
     #[tokio::test]
@@ -134,4 +322,62 @@ mod tests {
         assert_eq!(entry.value, decode_entry.value);
         assert_eq!(entry.key, entry.key);
     }
+
+    #[tokio::test]
+    async fn checked_log() {
+        let entry: Log<String> = Log::new(
+            Ts::new("hello".into(), 1.into()),
+            Some("world".into()),
+            Some(LogType::Full),
+        );
+        let mut log_bytes = Vec::new();
+        entry
+            .encode(&mut Cursor::new(&mut log_bytes))
+            .await
+            .unwrap();
+
+        let mut bytes = Vec::new();
+        CheckedLog::new(log_bytes.clone())
+            .encode(&mut Cursor::new(&mut bytes))
+            .await
+            .unwrap();
+        let decode = |mut bytes: Vec<u8>| async move {
+            match WalRecord::<String>::decode(&mut Cursor::new(&mut bytes))
+                .await
+                .unwrap()
+            {
+                WalRecord::Checked(checked) => checked.verify(),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(decode(bytes.clone()).await, Some(log_bytes));
+
+        // flip a bit of the value
+        let len = bytes.len();
+        bytes[len - 6] ^= 1;
+        assert_eq!(decode(bytes).await, None);
+    }
+
+    #[tokio::test]
+    async fn decode_invalid_log() {
+        let entry: Log<String> = Log::new(
+            Ts::new("hello".into(), 1.into()),
+            Some("world".into()),
+            Some(LogType::Full),
+        );
+        let mut bytes = Vec::new();
+        entry.encode(&mut Cursor::new(&mut bytes)).await.unwrap();
+
+        // a tag no log starts with
+        let mut invalid = bytes.clone();
+        invalid[0] = 9;
+        assert!(WalRecord::<String>::decode(&mut Cursor::new(&mut invalid))
+            .await
+            .is_err());
+        // a log cut in its value
+        let mut torn = bytes[..bytes.len() - 2].to_vec();
+        assert!(Log::<String>::decode(&mut Cursor::new(&mut torn))
+            .await
+            .is_err());
+    }
 }
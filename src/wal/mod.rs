@@ -1,26 +1,69 @@
+pub(crate) mod compression;
+pub(crate) mod group;
 pub(crate) mod log;
 
-use std::{pin::pin, sync::Arc};
+use std::{
+    io::Cursor,
+    marker::PhantomData,
+    pin::pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use async_stream::stream;
+use async_stream::{stream, try_stream};
 use fusio::{disk::LocalFs, DynFs};
-use fusio_log::{error::LogError, Decode, FsOptions, Logger, Options, Path};
+use fusio_log::{error::LogError, Decode, Encode, FsOptions, Logger, Options, Path};
 use futures_core::Stream;
 use futures_util::{StreamExt, TryStreamExt};
 use thiserror::Error;
 
-use crate::{fs::FileId, record::Record, wal::log::Log};
+use crate::{
+    encryption::{self, EncryptionError, KeyProvider},
+    fs::FileId,
+    record::Record,
+    wal::log::{CheckedLog, Log, SealedLog, WalRecord},
+    WalCompression,
+};
+
+/// length of the nonces of [`SealedLog`]s
+pub(crate) const NONCE_LEN: usize = 12;
+
+/// the logger of a [`WalFile`], which seals its logs when the WAL is encrypted
+enum WalLogger {
+    Checked(Logger<CheckedLog>),
+    Sealed(Logger<SealedLog>),
+}
+
+impl WalLogger {
+    async fn close(&mut self) -> Result<(), LogError> {
+        match self {
+            WalLogger::Checked(file) => file.close().await,
+            WalLogger::Sealed(file) => file.close().await,
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), LogError> {
+        match self {
+            WalLogger::Checked(file) => file.flush().await,
+            WalLogger::Sealed(file) => file.flush().await,
+        }
+    }
+}
 
 pub(crate) struct WalFile<R>
 where
     R: Record,
 {
-    file: Option<Logger<Log<R>>>,
+    file: Option<WalLogger>,
+    _marker: PhantomData<R>,
     file_id: FileId,
     path: Path,
     wal_buffer_size: usize,
     fs: Arc<dyn DynFs>,
     local_fs: Arc<dyn DynFs>,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+    synced_at: Option<Instant>,
+    compression: Option<WalCompression>,
 }
 
 impl<R> WalFile<R>
@@ -32,28 +75,53 @@ where
         path: Path,
         wal_buffer_size: usize,
         file_id: FileId,
+        key_provider: Option<Arc<dyn KeyProvider>>,
     ) -> Self {
-        let local_fs = Arc::new(LocalFs {});
-        let file = Options::new(path.clone())
-            .buf_size(wal_buffer_size)
-            .truncate(true)
-            .build_with_fs::<Log<R>>(local_fs.clone())
-            .await
-            .unwrap();
-
-        Self {
-            file: Some(file),
+        let mut wal = Self {
+            file: None,
             file_id,
             path,
             wal_buffer_size,
             fs,
-            local_fs,
+            local_fs: Arc::new(LocalFs {}),
+            key_provider,
+            synced_at: None,
+            compression: None,
+            _marker: PhantomData,
+        };
+        wal.file = Some(wal.open(true).await.unwrap());
+        wal
+    }
+
+    /// compress the logs written next with `compression`
+    pub(crate) fn with_compression(self, compression: Option<WalCompression>) -> Self {
+        Self {
+            compression,
+            ..self
         }
     }
 
     pub(crate) fn file_id(&self) -> FileId {
         self.file_id
     }
+
+    async fn open(&self, truncate: bool) -> Result<WalLogger, LogError> {
+        let options = Options::new(self.path.clone())
+            .buf_size(self.wal_buffer_size)
+            .truncate(truncate);
+        Ok(match self.key_provider {
+            None => WalLogger::Checked(
+                options
+                    .build_with_fs::<CheckedLog>(self.local_fs.clone())
+                    .await?,
+            ),
+            Some(_) => WalLogger::Sealed(
+                options
+                    .build_with_fs::<SealedLog>(self.local_fs.clone())
+                    .await?,
+            ),
+        })
+    }
 }
 
 impl<R> WalFile<R>
@@ -62,15 +130,42 @@ where
 {
     pub(crate) async fn write<'r>(&mut self, data: &Log<R>) -> Result<(), LogError> {
         if self.file.is_none() {
-            self.file = Some(
-                Options::new(self.path.clone())
-                    .buf_size(self.wal_buffer_size)
-                    .build_with_fs::<Log<R>>(self.local_fs.clone())
-                    .await?,
-            );
+            self.file = Some(self.open(false).await?);
         }
 
-        self.file.as_mut().unwrap().write(data).await
+        let mut bytes = Vec::with_capacity(data.size());
+        data.encode(&mut Cursor::new(&mut bytes)).await?;
+        if let Some(compression) = self.compression {
+            bytes = compression::compress(compression, bytes).map_err(fusio::Error::from)?;
+        }
+        match (self.file.as_mut().unwrap(), &self.key_provider) {
+            (WalLogger::Sealed(file), Some(key_provider)) => {
+                file.write(&seal(key_provider.as_ref(), &bytes)?).await
+            }
+            (WalLogger::Checked(file), _) => file.write(&CheckedLog::new(bytes)).await,
+            (WalLogger::Sealed(_), None) => unreachable!("sealed WAL without key provider"),
+        }
+    }
+
+    /// write the buffered logs to the WAL segment, which stays open for the next logs
+    pub(crate) async fn sync(&mut self) -> Result<(), LogError> {
+        match self.file.as_mut() {
+            Some(file) => file.flush().await,
+            None => Ok(()),
+        }
+    }
+
+    /// [`WalFile::sync`] if the last sync of [`WalFile::sync_every`] is older than `interval`
+    pub(crate) async fn sync_every(&mut self, interval: Duration) -> Result<(), LogError> {
+        if self
+            .synced_at
+            .is_some_and(|synced_at| synced_at.elapsed() < interval)
+        {
+            return Ok(());
+        }
+        self.sync().await?;
+        self.synced_at = Some(Instant::now());
+        Ok(())
     }
 
     pub(crate) async fn flush(&mut self) -> Result<(), LogError> {
@@ -81,14 +176,14 @@ where
                     let mut log = Options::new(self.path.clone())
                         .buf_size(self.wal_buffer_size)
                         .truncate(true)
-                        .build_with_fs::<Log<R>>(self.fs.clone())
+                        .build_with_fs::<WalRecord<R>>(self.fs.clone())
                         .await
                         .unwrap();
 
+                    // the records are copied as written, sealed records stay sealed
                     let mut log_stream =
-                        pin!(Self::recover(FsOptions::Local, self.path.clone()).await);
-                    while let Some(record) = log_stream.next().await {
-                        let record_batch = record.unwrap();
+                        pin!(Self::recover_records(FsOptions::Local, self.path.clone()).await);
+                    while let Some(Ok(record_batch)) = log_stream.next().await {
                         log.write_batch(record_batch.iter()).await?;
                     }
 
@@ -113,38 +208,148 @@ impl<R> WalFile<R>
 where
     R: Record,
 {
+    /// recover the logs of the WAL segment at `path`, sealed logs are opened with the keys of
+    /// `key_provider`. Fails with [`RecoverError::Checksum`] on the first log that does not match
+    /// its checksum.
     pub(crate) async fn recover(
         fs_option: FsOptions,
         path: Path,
+        key_provider: Option<Arc<dyn KeyProvider>>,
     ) -> impl Stream<Item = Result<Vec<Log<R>>, RecoverError<<R as Decode>::Error>>> {
+        try_stream! {
+            let mut stream = pin!(Self::recover_records(fs_option, path).await);
+            while let Some(batch) = stream.next().await {
+                let records = batch?;
+                let len = records.len();
+                let mut logs = Vec::new();
+                for (i, record) in records.into_iter().enumerate() {
+                    logs.push(match record {
+                        WalRecord::Plain(log) => log,
+                        WalRecord::Checked(checked) => {
+                            let bytes = match checked.verify() {
+                                Some(bytes) => bytes,
+                                // only the last log of the segment may be torn by a crash
+                                None => {
+                                    let is_last = i + 1 == len
+                                        && matches!(
+                                            stream.next().await,
+                                            None | Some(Err(RecoverError::Torn))
+                                        );
+                                    Err(if is_last {
+                                        RecoverError::Torn
+                                    } else {
+                                        RecoverError::Checksum
+                                    })?
+                                }
+                            };
+                            let mut bytes = compression::decompress(bytes)?;
+                            Log::decode(&mut Cursor::new(&mut bytes)).await?
+                        }
+                        WalRecord::Sealed(sealed) => {
+                            let key_provider =
+                                key_provider.as_deref().ok_or(RecoverError::Sealed)?;
+                            open(key_provider, sealed).await?
+                        }
+                    });
+                }
+                yield logs;
+            }
+        }
+    }
+
+    pub(crate) async fn recover_records(
+        fs_option: FsOptions,
+        path: Path,
+    ) -> impl Stream<Item = Result<Vec<WalRecord<R>>, RecoverError<<R as Decode>::Error>>> {
         stream! {
             let mut stream = Options::new(path)
                 .fs(fs_option)
-                .recover::<Log<R>>()
+                .recover::<WalRecord<R>>()
                 .await
                 .unwrap();
-                while let Ok(batch) = stream.try_next().await {
-                    match batch {
-                        Some(batch) => yield Ok(batch),
-                        None => break,
+                loop {
+                    match stream.try_next().await {
+                        Ok(Some(batch)) => yield Ok(batch),
+                        Ok(None) => break,
+                        // e.g. a log torn by a crash while it was written
+                        Err(err) => {
+                            yield Err(if is_truncated(&err) {
+                                RecoverError::Torn
+                            } else {
+                                RecoverError::from(err)
+                            });
+                            break;
+                        }
                     }
                 }
         }
     }
 }
 
+/// whether `err` reads past the end of the segment, as the last log of a segment cut by a crash
+fn is_truncated(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if err
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|err| err.kind() == std::io::ErrorKind::UnexpectedEof)
+        {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// encrypt the encoding of a log with the current key of `key_provider`
+fn seal(key_provider: &dyn KeyProvider, bytes: &[u8]) -> Result<SealedLog, fusio::Error> {
+    let (key_id, nonce, ciphertext) =
+        encryption::seal(key_provider, bytes).map_err(|err| fusio::Error::Other(Box::new(err)))?;
+
+    Ok(SealedLog {
+        key_id,
+        nonce,
+        ciphertext,
+    })
+}
+
+async fn open<R>(
+    key_provider: &dyn KeyProvider,
+    sealed: SealedLog,
+) -> Result<Log<R>, RecoverError<<R as Decode>::Error>>
+where
+    R: Record,
+{
+    let bytes = encryption::open(
+        key_provider,
+        &sealed.key_id,
+        &sealed.nonce,
+        &sealed.ciphertext,
+    )?;
+    let mut bytes = compression::decompress(bytes)?;
+    Ok(Log::decode(&mut Cursor::new(&mut bytes)).await?)
+}
+
 #[derive(Debug, Error)]
 pub enum RecoverError<E: std::error::Error> {
     #[error("wal recover decode error: {0}")]
     Decode(E),
     #[error("wal recover checksum error")]
     Checksum,
+    /// the last log of the segment is cut short or fails its checksum, it was torn by a crash
+    /// while it was written and its commit did not return
+    #[error("wal recover torn log at the end of the segment")]
+    Torn,
     #[error("wal recover io error")]
     Io(#[from] std::io::Error),
     #[error("wal recover fusio error")]
     Fusio(#[from] fusio::Error),
     #[error("wal recover log error")]
     Logger(#[from] LogError),
+    #[error("wal recover encryption error: {0}")]
+    Encryption(#[from] EncryptionError),
+    #[error("wal recover sealed log without encryption key provider")]
+    Sealed,
 }
 
 #[cfg(all(test, feature = "tokio"))]
@@ -170,7 +375,7 @@ mod tests {
         let wal_path = Path::from_filesystem_path(temp_dir.path())
             .unwrap()
             .child(format!("{}.{}", wal_id, FileType::Wal));
-        let mut wal = WalFile::<String>::new(fs.clone(), wal_path.clone(), 0, wal_id).await;
+        let mut wal = WalFile::<String>::new(fs.clone(), wal_path.clone(), 0, wal_id, None).await;
 
         {
             wal.write(&Log::new(
@@ -184,8 +389,9 @@ mod tests {
         }
         {
             {
-                let mut stream =
-                    pin!(WalFile::<String>::recover(fs_option.clone(), wal_path.clone()).await);
+                let mut stream = pin!(
+                    WalFile::<String>::recover(fs_option.clone(), wal_path.clone(), None).await
+                );
                 for log in stream.next().await.unwrap().unwrap() {
                     assert_eq!(log.key.ts, 0.into());
                     assert_eq!(log.value, Some("hello".to_string()));
@@ -209,7 +415,7 @@ mod tests {
                 let file_number = file_stream.count().await;
                 assert_eq!(file_number, 1);
 
-                let mut stream = pin!(WalFile::<String>::recover(fs_option, wal_path).await);
+                let mut stream = pin!(WalFile::<String>::recover(fs_option, wal_path, None).await);
                 for log in stream.next().await.unwrap().unwrap() {
                     assert_eq!(log.key.ts, 0.into());
                     assert_eq!(log.value, Some("hello".to_string()));
@@ -0,0 +1,552 @@
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_lock::{Mutex, RwLock};
+use fusio_log::Encode;
+use tracing::{error, info_span, warn, Instrument};
+
+use crate::{
+    compaction::CompactTask,
+    context::Context,
+    executor::{self, task, Executor},
+    fs::retry::is_transient,
+    record::{KeyRef, Record, Schema},
+    stall,
+    transaction::CommitError,
+    wal::log::LogType,
+    DbError, DbStorage, RetryPolicy, DB,
+};
+
+/// Inserts and removals applied atomically by [`DB::apply`].
+///
+/// Unlike a [`Transaction`](crate::transaction::Transaction), a batch reads nothing and is not
+/// checked for conflicts: it is written as a single WAL record and applied to the memtable with a
+/// single timestamp, so readers observe all of its writes or none. A later write of a key in the
+/// batch replaces the earlier one.
+pub struct WriteBatch<R>
+where
+    R: Record,
+{
+    entries: BTreeMap<<R::Schema as Schema>::Key, Option<R>>,
+}
+
+impl<R> Default for WriteBatch<R>
+where
+    R: Record,
+{
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl<R> WriteBatch<R>
+where
+    R: Record,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// insert `record` when the batch is applied
+    pub fn insert(&mut self, record: R) {
+        self.entries.insert(record.key().to_key(), Some(record));
+    }
+
+    /// remove the record with the primary key as the `key` when the batch is applied
+    pub fn remove(&mut self, key: <R::Schema as Schema>::Key) {
+        self.entries.insert(key, None);
+    }
+
+    /// number of keys written by the batch
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<R, E> DB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// apply the writes of `batch` atomically with one commit timestamp, see [`WriteBatch`]
+    pub async fn apply(&self, batch: WriteBatch<R>) -> Result<(), CommitError<R>> {
+        apply(&self.schema, &self.ctx, batch).await
+    }
+
+    /// Open a [`BufferedWriter`] committing its writes as [`WriteBatch`]es.
+    pub fn buffered_writer(&self) -> BufferedWriter<'_, R, E>
+    where
+        R: Clone,
+    {
+        BufferedWriter {
+            db: self,
+            buffer: Arc::new(Mutex::new(Buffer::default())),
+            max_batch_len: 1024,
+            max_batch_size: 4 * 1024 * 1024,
+            max_delay: Duration::from_millis(100),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+}
+
+async fn apply<R>(
+    schema: &RwLock<DbStorage<R>>,
+    ctx: &Context<R>,
+    batch: WriteBatch<R>,
+) -> Result<(), CommitError<R>>
+where
+    R: Record + Send + Sync,
+{
+    let len = batch.len();
+    if len == 0 {
+        return Ok(());
+    }
+    stall(schema, ctx).await?;
+    let schema = schema.read().await;
+    let ts = ctx.increase_ts();
+
+    let mut is_excess = false;
+    for (i, (key, record)) in batch.entries.into_iter().enumerate() {
+        let log_ty = match i {
+            _ if len == 1 => LogType::Full,
+            0 => LogType::First,
+            i if i == len - 1 => LogType::Last,
+            _ => LogType::Middle,
+        };
+        is_excess = match record {
+            Some(record) => schema.write(log_ty, record, ts).await?,
+            None => schema.remove(log_ty, key, ts).await?,
+        };
+    }
+    if is_excess {
+        let _ = schema.compaction_tx.try_send(CompactTask::Freeze);
+    }
+
+    Ok(())
+}
+
+/// Accumulates writes and commits them with [`DB::apply`] once a threshold is reached.
+///
+/// A batch is committed when it holds [`BufferedWriter::max_batch_len`] keys, when its records
+/// reach [`BufferedWriter::max_batch_size`] bytes or when its oldest write is older than
+/// [`BufferedWriter::max_delay`]. Thresholds are checked on every write and the write that
+/// reaches one waits for the commit, which applies backpressure to the producer. The delay is
+/// also waited for in the background, so the writes of an idle writer are committed too, except
+/// on wasm32, which has no timer. Call [`BufferedWriter::close`] on shutdown, writes still
+/// buffered when the writer is dropped are lost.
+///
+/// A commit failing with [`DbError::Busy`] or a transient storage error is retried as told by
+/// [`BufferedWriter::retry_policy`]. The writes of a failed commit stay buffered and are committed
+/// with the next batch.
+pub struct BufferedWriter<'db, R, E>
+where
+    R: Record,
+    E: Executor,
+{
+    db: &'db DB<R, E>,
+    buffer: Arc<Mutex<Buffer<R>>>,
+    max_batch_len: usize,
+    max_batch_size: usize,
+    max_delay: Duration,
+    retry_policy: RetryPolicy,
+}
+
+/// the writes of a [`BufferedWriter`], shared with the task committing them after the delay
+struct Buffer<R>
+where
+    R: Record,
+{
+    batch: WriteBatch<R>,
+    size: usize,
+    first_write_at: Option<Instant>,
+    /// whether a task waits for the delay of the batch
+    is_timed: bool,
+}
+
+impl<R> Default for Buffer<R>
+where
+    R: Record,
+{
+    fn default() -> Self {
+        Self {
+            batch: WriteBatch::new(),
+            size: 0,
+            first_write_at: None,
+            is_timed: false,
+        }
+    }
+}
+
+impl<R> Buffer<R>
+where
+    R: Record + Clone + Send + Sync,
+{
+    /// commit the batch, which is kept unless the commit succeeds
+    async fn commit(
+        &mut self,
+        schema: &RwLock<DbStorage<R>>,
+        ctx: &Context<R>,
+        retry_policy: &RetryPolicy,
+    ) -> Result<(), CommitError<R>> {
+        let mut backoff = retry_policy.backoff;
+        let mut attempt = 1;
+        loop {
+            let batch = WriteBatch {
+                entries: self.batch.entries.clone(),
+            };
+            match apply(schema, ctx, batch).await {
+                Ok(()) => break,
+                Err(err) if attempt < retry_policy.max_attempts && is_retriable(&err) => {}
+                Err(err) => return Err(err),
+            }
+            attempt += 1;
+            executor::sleep(backoff).await;
+            backoff = backoff.saturating_mul(2).min(retry_policy.max_backoff);
+        }
+        self.batch = WriteBatch::new();
+        self.size = 0;
+        self.first_write_at = None;
+
+        Ok(())
+    }
+}
+
+/// whether a commit failing with `err` may succeed when retried
+fn is_retriable<R>(err: &CommitError<R>) -> bool
+where
+    R: Record,
+{
+    matches!(err, CommitError::Database(DbError::Busy)) || is_transient(err)
+}
+
+impl<R, E> BufferedWriter<'_, R, E>
+where
+    R: Record + Clone + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// commit once the batch holds `max_batch_len` keys, 1024 by default
+    pub fn max_batch_len(self, max_batch_len: usize) -> Self {
+        Self {
+            max_batch_len,
+            ..self
+        }
+    }
+
+    /// commit once the buffered records reach `max_batch_size` bytes, 4 MiB by default
+    pub fn max_batch_size(self, max_batch_size: usize) -> Self {
+        Self {
+            max_batch_size,
+            ..self
+        }
+    }
+
+    /// commit once the oldest buffered write is older than `max_delay`, 100 ms by default
+    pub fn max_delay(self, max_delay: Duration) -> Self {
+        Self { max_delay, ..self }
+    }
+
+    /// retry the failed commits with `retry_policy`, [`RetryPolicy::default`] by default. Its
+    /// timeout does not apply.
+    pub fn retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Self {
+            retry_policy,
+            ..self
+        }
+    }
+
+    /// buffer the insertion of `record`
+    pub async fn insert(&mut self, record: R) -> Result<(), CommitError<R>> {
+        let mut buffer = self.buffer.lock().await;
+        buffer.size += record.size();
+        buffer.batch.insert(record);
+        self.written(&mut buffer).await
+    }
+
+    /// buffer the removal of the record with the primary key as the `key`
+    pub async fn remove(&mut self, key: <R::Schema as Schema>::Key) -> Result<(), CommitError<R>> {
+        let mut buffer = self.buffer.lock().await;
+        buffer.size += key.size();
+        buffer.batch.remove(key);
+        self.written(&mut buffer).await
+    }
+
+    /// number of keys waiting to be committed
+    pub async fn pending(&self) -> usize {
+        self.buffer.lock().await.batch.len()
+    }
+
+    /// commit the buffered writes now
+    pub async fn flush(&mut self) -> Result<(), CommitError<R>> {
+        self.buffer
+            .lock()
+            .await
+            .commit(&self.db.schema, &self.db.ctx, &self.retry_policy)
+            .await
+    }
+
+    /// commit the buffered writes and close the writer
+    pub async fn close(mut self) -> Result<(), CommitError<R>> {
+        self.flush().await
+    }
+
+    async fn written(&self, buffer: &mut Buffer<R>) -> Result<(), CommitError<R>> {
+        let first_write_at = *buffer.first_write_at.get_or_insert_with(Instant::now);
+        if buffer.batch.len() >= self.max_batch_len
+            || buffer.size >= self.max_batch_size
+            || first_write_at.elapsed() >= self.max_delay
+        {
+            return buffer
+                .commit(&self.db.schema, &self.db.ctx, &self.retry_policy)
+                .await;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if !buffer.is_timed {
+            buffer.is_timed = true;
+            self.spawn_timer(self.max_delay.saturating_sub(first_write_at.elapsed()));
+        }
+        Ok(())
+    }
+
+    /// commit the buffered writes once the oldest one is older than the delay, starting in `wait`
+    #[cfg(not(target_arch = "wasm32"))]
+    fn spawn_timer(&self, mut wait: Duration) {
+        let buffer = Arc::downgrade(&self.buffer);
+        let schema = Arc::downgrade(&self.db.schema);
+        let ctx = Arc::downgrade(&self.db.ctx);
+        let max_delay = self.max_delay;
+        let retry_policy = self.retry_policy;
+        self.db.executor.spawn_named(
+            task::BUFFERED_WRITER,
+            async move {
+                loop {
+                    executor::sleep(wait).await;
+                    let (Some(buffer), Some(schema), Some(ctx)) =
+                        (buffer.upgrade(), schema.upgrade(), ctx.upgrade())
+                    else {
+                        break;
+                    };
+                    let mut buffer = buffer.lock().await;
+                    let Some(first_write_at) = buffer.first_write_at else {
+                        buffer.is_timed = false;
+                        break;
+                    };
+                    let age = first_write_at.elapsed();
+                    if age < max_delay {
+                        wait = max_delay - age;
+                        continue;
+                    }
+                    match buffer.commit(&schema, &ctx, &retry_policy).await {
+                        Ok(()) => {
+                            buffer.is_timed = false;
+                            break;
+                        }
+                        // the writes stay buffered, they are committed again after another delay
+                        Err(err) => {
+                            error!("[BufferedWriter Error]: {}", err);
+                            wait = max_delay;
+                        }
+                    }
+                }
+            }
+            .instrument(info_span!("tonbo_task", name = task::BUFFERED_WRITER)),
+        );
+    }
+}
+
+impl<R, E> Drop for BufferedWriter<'_, R, E>
+where
+    R: Record,
+    E: Executor,
+{
+    fn drop(&mut self) {
+        let pending = self
+            .buffer
+            .try_lock()
+            .map_or(0, |buffer| buffer.batch.len());
+        if pending > 0 {
+            warn!(
+                "buffered writer dropped with {} uncommitted writes",
+                pending
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::WriteBatch;
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test,
+        transaction::CommitError, DbError, DbOption, RetryPolicy, StallPolicy, WriteStall, DB,
+    };
+
+    fn record(key: &str, vu32: u32) -> Test {
+        Test {
+            vstring: key.to_string(),
+            vu32,
+            vbool: None,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn apply_write_batch() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        db.insert(record("c", 0)).await.unwrap();
+
+        let before = db.snapshot().await.ts();
+        let mut batch = WriteBatch::new();
+        batch.insert(record("a", 1));
+        batch.insert(record("b", 1));
+        batch.insert(record("b", 2));
+        batch.remove("c".to_string());
+        assert_eq!(batch.len(), 3);
+        db.apply(batch).await.unwrap();
+
+        // the whole batch is committed with a single timestamp
+        let snapshot = db.snapshot().await;
+        assert_eq!(u32::from(snapshot.ts()), u32::from(before) + 1);
+        drop(snapshot);
+
+        let get = |key: &'static str| {
+            let db = &db;
+            async move {
+                db.get(&key.to_string(), |entry| entry.get().vu32)
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(get("a").await, Some(1));
+        assert_eq!(get("b").await, Some(2));
+        assert_eq!(get("c").await, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn buffered_writer() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        let get = |key: String| {
+            let db = &db;
+            async move { db.get(&key, |entry| entry.get().vu32).await.unwrap() }
+        };
+
+        let mut writer = db
+            .buffered_writer()
+            .max_batch_len(10)
+            .max_delay(Duration::from_secs(3600));
+        for i in 0..25 {
+            writer.insert(record(&format!("k{i:02}"), i)).await.unwrap();
+        }
+        // two batches of 10 keys are committed, the rest is buffered
+        assert_eq!(writer.pending().await, 5);
+        assert_eq!(get("k19".to_string()).await, Some(19));
+        assert_eq!(get("k20".to_string()).await, None);
+
+        writer.remove("k00".to_string()).await.unwrap();
+        writer.close().await.unwrap();
+        assert_eq!(get("k24".to_string()).await, Some(24));
+        assert_eq!(get("k00".to_string()).await, None);
+
+        let mut writer = db.buffered_writer().max_delay(Duration::ZERO);
+        writer.insert(record("a", 1)).await.unwrap();
+        // the write is older than the delay as soon as it is buffered
+        assert_eq!(writer.pending().await, 0);
+        assert_eq!(get("a".to_string()).await, Some(1));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn buffered_writer_commits_when_idle() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+
+        let mut writer = db.buffered_writer().max_delay(Duration::from_millis(50));
+        writer.insert(record("a", 1)).await.unwrap();
+        assert_eq!(writer.pending().await, 1);
+        // no other write comes, the delay commits the batch
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(writer.pending().await, 0);
+        assert_eq!(
+            db.get(&"a".to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap(),
+            Some(1)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn buffered_writer_keeps_failed_batch() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .write_stall(WriteStall {
+            max_immutables: usize::MAX,
+            max_level0_tables: 1,
+            policy: StallPolicy::Reject,
+        });
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::current(), TestSchema)
+            .await
+            .unwrap();
+        for key in ["a", "b"] {
+            db.insert(record(key, 0)).await.unwrap();
+            db.flush().await.unwrap();
+        }
+
+        let mut writer = db
+            .buffered_writer()
+            .max_batch_len(2)
+            .max_delay(Duration::from_secs(3600))
+            .retry_policy(RetryPolicy {
+                max_attempts: 3,
+                backoff: Duration::from_millis(1),
+                ..Default::default()
+            });
+        writer.insert(record("c", 1)).await.unwrap();
+        // the stalled commit is retried, then fails and keeps its writes
+        assert!(matches!(
+            writer.insert(record("d", 1)).await,
+            Err(CommitError::Database(DbError::Busy))
+        ));
+        assert_eq!(writer.pending().await, 2);
+        assert!(matches!(
+            writer.flush().await,
+            Err(CommitError::Database(DbError::Busy))
+        ));
+        assert_eq!(writer.pending().await, 2);
+    }
+}
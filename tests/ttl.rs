@@ -0,0 +1,114 @@
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{
+        ops::Bound,
+        time::{Duration, SystemTime},
+    };
+
+    use fusio::path::Path;
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+    use tonbo::{executor::tokio::TokioExecutor, ttl, DbOption, Projection, Record, DB};
+
+    #[derive(Record, Debug)]
+    pub struct Session {
+        #[record(primary_key)]
+        pub id: String,
+        pub user: u32,
+        #[record(expire_at)]
+        pub expire_at: Option<u64>,
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_expired_records_are_invisible() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &SessionSchema,
+        );
+        let db: DB<Session, TokioExecutor> =
+            DB::new(option, TokioExecutor::current(), SessionSchema)
+                .await
+                .unwrap();
+
+        let expired = ttl::expire_at(SystemTime::now() - Duration::from_secs(1));
+        let alive = ttl::expire_after(Duration::from_secs(3600));
+        for (id, expire_at) in [
+            ("a", None),
+            ("b", Some(expired)),
+            ("c", Some(alive)),
+            ("d", None),
+        ] {
+            db.insert(Session {
+                id: id.to_string(),
+                user: 0,
+                expire_at,
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+        // an expired version hides the older ones
+        db.insert(Session {
+            id: "d".to_string(),
+            user: 1,
+            expire_at: Some(expired),
+        })
+        .await
+        .unwrap();
+
+        for flush in [false, true] {
+            if flush {
+                db.flush().await.unwrap();
+            }
+            let visible = |id: &'static str| {
+                let db = &db;
+                async move {
+                    db.get(&id.to_string(), |entry| Some(entry.get().user))
+                        .await
+                        .unwrap()
+                        .is_some()
+                }
+            };
+            assert!(visible("a").await);
+            assert!(!visible("b").await);
+            assert!(visible("c").await);
+            assert!(!visible("d").await);
+
+            let txn = db.transaction().await;
+            let mut ids = Vec::new();
+            let mut scan = Box::pin(
+                txn.scan((Bound::Unbounded, Bound::Unbounded))
+                    .projection(&["user"])
+                    .take()
+                    .await
+                    .unwrap(),
+            );
+            while let Some(entry) = scan.next().await.transpose().unwrap() {
+                let record = entry.value().unwrap();
+                ids.push(record.id.to_string());
+                // the expiry is read even if it is not projected
+                assert!(record.expire_at.is_none() || record.id == "c");
+            }
+            assert_eq!(ids, vec!["a", "c"]);
+
+            let entry = txn
+                .get(&"c".to_string(), Projection::Parts(vec!["user"]))
+                .await
+                .unwrap();
+            assert_eq!(entry.unwrap().get().expire_at, Some(alive));
+        }
+
+        let mut txn = db.transaction().await;
+        txn.insert(Session {
+            id: "a".to_string(),
+            user: 2,
+            expire_at: Some(expired),
+        });
+        assert!(txn
+            .get(&"a".to_string(), Projection::All)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
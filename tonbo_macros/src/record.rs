@@ -19,6 +19,8 @@ struct RecordStructFieldOpt {
     ty: Type,
     #[darling(default)]
     primary_key: Option<bool>,
+    #[darling(default)]
+    expire_at: Option<bool>,
 }
 
 impl RecordStructFieldOpt {
@@ -111,6 +113,34 @@ pub(crate) fn handle(ast: DeriveInput) -> Result<TokenStream, Error> {
 
     let builder_append_primary_key = &primary_key_definitions.builder_append_value;
 
+    let mut expire_at_fields = data_struct
+        .fields
+        .iter()
+        .enumerate()
+        .filter(|field| field.1.expire_at == Some(true));
+    let expire_at = expire_at_fields.next();
+    if expire_at_fields.next().is_some() {
+        return Err(syn::Error::new_spanned(
+            struct_name,
+            "only one field can be defined with #[record(expire_at)]",
+        ));
+    }
+    let expire_at = match expire_at {
+        Some((index, field)) => {
+            let ident = field.ident.as_ref().expect("expect named struct field");
+            let is_u64 = matches!(field.to_data_type(), Some((DataType::UInt64, _)));
+            if field.primary_key == Some(true) || !is_u64 {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "#[record(expire_at)] field must be a u64 or Option<u64> that is not the \
+                     primary key",
+                ));
+            }
+            Some((ident.clone(), index + 2))
+        }
+        None => None,
+    };
+
     let record_codegen =
         trait_record_codegen(&data_struct.fields, struct_name, &primary_key_definitions);
 
@@ -118,11 +148,19 @@ pub(crate) fn handle(ast: DeriveInput) -> Result<TokenStream, Error> {
 
     let struct_ref_codegen = struct_ref_codegen(struct_name, &data_struct.fields);
 
-    let struct_schema_codegen =
-        struct_schema_codegen(struct_name, &data_struct.fields, &primary_key_definitions);
+    let struct_schema_codegen = struct_schema_codegen(
+        struct_name,
+        &data_struct.fields,
+        &primary_key_definitions,
+        expire_at.as_ref(),
+    );
 
-    let decode_ref_codegen =
-        trait_decode_ref_codegen(&struct_name, primary_key_ident, &data_struct.fields);
+    let decode_ref_codegen = trait_decode_ref_codegen(
+        &struct_name,
+        primary_key_ident,
+        &data_struct.fields,
+        expire_at.as_ref(),
+    );
 
     let encode_codegen = trait_encode_codegen(struct_name, &data_struct.fields);
 
@@ -359,6 +397,7 @@ fn struct_schema_codegen(
     struct_name: &Ident,
     fields: &[RecordStructFieldOpt],
     primary_key: &PrimaryKey,
+    expire_at: Option<&(Ident, usize)>,
 ) -> TokenStream {
     let struct_schema_name = struct_name.to_schema_ident();
     let struct_arrays_name = struct_name.to_immutable_array_ident();
@@ -382,6 +421,13 @@ fn struct_schema_codegen(
                     ::tonbo::arrow::datatypes::Field::new(stringify!(#field_name), #mapped_type, #is_nullable),
                 });
     }
+    let expire_at_index = expire_at.map(|(_, index)| {
+        quote! {
+            fn expire_at_index(&self) -> Option<usize> {
+                Some(#index)
+            }
+        }
+    });
 
     quote! {
         #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -416,6 +462,8 @@ fn struct_schema_codegen(
 
                 &SCHEMA
             }
+
+            #expire_at_index
         }
     }
 }
@@ -424,6 +472,7 @@ fn trait_decode_ref_codegen(
     struct_name: &&Ident,
     primary_key_name: &Ident,
     fields: &[RecordStructFieldOpt],
+    expire_at: Option<&(Ident, usize)>,
 ) -> TokenStream {
     let mut ref_projection_fields: Vec<TokenStream> = Vec::new();
 
@@ -498,6 +547,13 @@ fn trait_decode_ref_codegen(
     }
 
     let struct_ref_name = struct_name.to_ref_ident();
+    let fn_expire_at = expire_at.map(|(field_name, _)| {
+        quote! {
+            fn expire_at(&self) -> Option<u64> {
+                self.#field_name
+            }
+        }
+    });
 
     let struct_ref_type = if has_ref {
         quote! {
@@ -521,6 +577,8 @@ fn trait_decode_ref_codegen(
                 #(#ref_projection_fields)*
             }
 
+            #fn_expire_at
+
             fn from_record_batch(
                 record_batch: &'r ::tonbo::arrow::record_batch::RecordBatch,
                 offset: usize,